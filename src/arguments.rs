@@ -1,11 +1,178 @@
-use std::path::PathBuf;
+use std::{ops::Range, path::PathBuf};
 
 use clap::{Parser, ValueHint};
 
+use crate::{
+    breakpoint::Breakpoint, coverage::CoverageFormat, instruction::Syntax, trace::TraceFormat,
+};
+
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
 pub struct Arguments {
-    /// Assembly file to be executed.
-    #[arg(value_hint = ValueHint::FilePath)]
-    pub file_path: PathBuf,
+    /// Assembly files to be executed, which are assembled as separate modules and linked together.
+    /// The program begins with the first instruction of the first file.
+    #[arg(required = true, value_hint = ValueHint::FilePath)]
+    pub file_paths: Vec<PathBuf>,
+    /// Syntax in which the instructions are written.
+    #[arg(long, value_enum, default_value_t)]
+    pub syntax: Syntax,
+    /// Maximum number of instructions to execute before the program is stopped, such that it
+    /// cannot run forever.
+    #[arg(long, value_name = "COUNT")]
+    pub max_instructions: Option<u64>,
+    /// Maximum number of seconds which the program may run for before it is stopped.
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: Option<f64>,
+    /// Lowest address which the stack may grow down to, which is decimal or hexadecimal (prefixed
+    /// with `0x`). A push below it stops the program with a stack overflow error.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_stack_limit)]
+    pub stack_limit: Option<u32>,
+    /// Initial value of ESP, i.e. the address which the stack grows down from, which is decimal or
+    /// hexadecimal (prefixed with `0x`). By default, it is 0x100000, or the end of memory if
+    /// `--memory-size` is smaller. In real mode, it is 0, such that SP wraps to the top of the
+    /// stack segment.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_stack_limit)]
+    pub stack: Option<u32>,
+    /// Size of memory in bytes, which is decimal or hexadecimal (prefixed with `0x`). Accessing
+    /// memory at or above it raises a #GP exception. By default, the whole 4 GiB address space can
+    /// be accessed.
+    #[arg(long, value_name = "BYTES", value_parser = parse_memory_size)]
+    pub memory_size: Option<u32>,
+    /// Start in real mode with 16-bit, 64 KiB segments, as boot code does, rather than with flat
+    /// 32-bit segments. The program can then enter protected mode by setting CR0.PE.
+    #[arg(long)]
+    pub real_mode: bool,
+    /// Emulate the DOS services of `int 0x21` (console I/O and exiting), using standard input and
+    /// output as the console. The process exits with the program's return code.
+    #[arg(long)]
+    pub dos: bool,
+    /// Attach a 16550 UART to the COM1 ports (0x3f8 to 0x3ff), using standard input and output as
+    /// the other end of the serial line.
+    #[arg(long)]
+    pub serial: bool,
+    /// Attach a PS/2 keyboard to its ports (0x60 and 0x64) and IRQ 1, which types the characters
+    /// read from standard input.
+    #[arg(long)]
+    pub keyboard: bool,
+    /// Protect the program's sections, such that writing to `.text` or executing `.data` or `.bss`
+    /// raises a #GP exception.
+    #[arg(long)]
+    pub protect: bool,
+    /// Stop the program before it executes the instruction at the given address (decimal or
+    /// hexadecimal, prefixed with `0x`) or label, or the first instruction with the given
+    /// mnemonic (e.g. `call` or `int`). May be repeated.
+    #[arg(long = "break", value_name = "LOCATION", value_parser = parse_breakpoint)]
+    pub breakpoints: Vec<Breakpoint>,
+    /// Write a trace of the executed instructions (and what they changed) to standard error.
+    #[arg(long)]
+    pub trace: bool,
+    /// Format of the trace written by `--trace`. The structured formats give each instruction's
+    /// address, machine code, mnemonic, and the registers and memory which it changed.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    pub trace_format: TraceFormat,
+    /// Once the program has stopped, write statistics about the run to standard error: the
+    /// number of instructions executed and the cycles they took, the memory traffic, how many
+    /// conditional branches were taken, and how often each mnemonic was executed.
+    #[arg(long)]
+    pub stats: bool,
+    /// Once the program has stopped, write which lines of the source files were executed (and how
+    /// many times) to the given file.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub coverage: Option<PathBuf>,
+    /// Format of the report written by `--coverage`.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    pub coverage_format: CoverageFormat,
+    /// Once the program has stopped, write the instructions which took the most cycles to standard
+    /// error, along with how often they were executed and the labels they are at.
+    #[arg(long)]
+    pub profile: bool,
+    /// Once the program has stopped, write the cycles spent in each stack of called functions to
+    /// the given file, in the folded format taken by `flamegraph.pl` and `inferno-flamegraph`.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub profile_folded: Option<PathBuf>,
+    /// Load the contents of a file (e.g. a ROM, a boot sector, or data) into memory at the given
+    /// address, which is decimal or hexadecimal (prefixed with `0x`), before the program is run.
+    /// Code in the file may be executed as the program's is. May be repeated, with later files
+    /// overwriting earlier ones where they overlap.
+    #[arg(long, value_name = "FILE@ADDRESS", value_parser = parse_image)]
+    pub load: Vec<Image>,
+    /// Once the program has stopped, write a hexdump of the given range of memory to standard
+    /// error, like GDB's `x/LENGTHxb ADDRESS`. The length defaults to 64 bytes. May be repeated.
+    #[arg(long, value_name = "ADDRESS[/LENGTH]", value_parser = parse_dump)]
+    pub dump: Vec<Range<u32>>,
+    /// Once the program has stopped, write the registers (the general-purpose and segment
+    /// registers, EIP, and the flags which are set in EFLAGS) to standard error.
+    #[arg(long)]
+    pub dump_state: bool,
+}
+
+/// A file to be loaded into memory, as given to `--load`.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub path: PathBuf,
+    pub address: u32,
+}
+
+/// Parses a `FILE@ADDRESS` argument. The path is everything before the last `@`, so that it may
+/// contain `@` itself.
+fn parse_image(argument: &str) -> Result<Image, String> {
+    let (path, address) = argument
+        .rsplit_once('@')
+        .ok_or_else(|| format!("expected FILE@ADDRESS, but there is no `@` in `{argument}`"))?;
+    if path.is_empty() {
+        return Err("the file is missing before the `@`".into());
+    }
+    Ok(Image {
+        path: path.into(),
+        address: parse_number(address, "address")?,
+    })
+}
+
+/// Parses the address given to `--stack-limit` or `--stack`.
+fn parse_stack_limit(argument: &str) -> Result<u32, String> {
+    parse_number(argument, "address")
+}
+
+/// Parses the size given to `--memory-size`.
+fn parse_memory_size(argument: &str) -> Result<u32, String> {
+    parse_number(argument, "size")
+}
+
+/// Parses the location given to `--break`, which is an address if it begins with a digit, and
+/// otherwise a mnemonic if any instruction has it, or else a label.
+fn parse_breakpoint(argument: &str) -> Result<Breakpoint, String> {
+    if argument.starts_with(|c: char| c.is_ascii_digit()) {
+        return parse_number(argument, "address").map(Breakpoint::Address);
+    }
+    Ok(Breakpoint::mnemonic(argument).unwrap_or_else(|| Breakpoint::Label(argument.into())))
+}
+
+/// The number of bytes which `--dump` writes when no length is given.
+const DEFAULT_DUMP_LENGTH: u32 = 64;
+
+/// Parses an `ADDRESS[/LENGTH]` argument into the range of memory which it covers, which must not
+/// go past the end of the address space.
+fn parse_dump(argument: &str) -> Result<Range<u32>, String> {
+    let (address, length) = match argument.split_once('/') {
+        Some((address, length)) => (address, parse_number(length, "length")?),
+        None => (argument, DEFAULT_DUMP_LENGTH),
+    };
+    let address = parse_number(address, "address")?;
+    let end = address
+        .checked_add(length)
+        .ok_or_else(|| format!("{length} bytes at {address:#x} go past the end of memory"))?;
+    Ok(address..end)
+}
+
+/// Parses a number which is decimal or hexadecimal (prefixed with `0x`), describing it as `what`
+/// when it is invalid.
+fn parse_number(number: &str, what: &str) -> Result<u32, String> {
+    match number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        Some(hexadecimal) => u32::from_str_radix(hexadecimal, 16),
+        None => number.parse(),
+    }
+    .map_err(|error| format!("invalid {what} `{number}`: {error}"))
 }
@@ -0,0 +1,312 @@
+use alloc::{format, string::String};
+use core::fmt;
+
+use crate::{
+    cpu::Cpu,
+    error::Error,
+    expression,
+    instruction::{NasmStr, Size},
+    register::Register,
+};
+
+/// What an assertion checks the value of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Subject {
+    /// A general-purpose (or segment) register, e.g. `eax`, `cx`, or `dl`.
+    Register(Register),
+    /// The value of the given size in memory at a physical address, e.g. `mem dword [buffer]`.
+    Memory { size: Size, address: u32 },
+}
+
+impl Subject {
+    /// The size of the value, in bits.
+    fn size(&self) -> Size {
+        match self {
+            Subject::Register(register) => register.size(),
+            Subject::Memory { size, .. } => *size,
+        }
+    }
+
+    /// The current value, zero-extended to 32 bits. This is read as a debugger would, so reading
+    /// memory is neither recorded nor restricted by its permissions.
+    fn value(&self, cpu: &Cpu) -> Result<u32, Error> {
+        Ok(match self {
+            Subject::Register(Register::Register32(register)) => cpu.registers.read32(register),
+            Subject::Register(Register::Register16(register)) => {
+                cpu.registers.read16(register) as u32
+            }
+            Subject::Register(Register::Register8(register)) => {
+                cpu.registers.read8(register) as u32
+            }
+            Subject::Register(register) => {
+                unreachable!("{register} cannot be the subject of an assertion")
+            }
+            Subject::Memory {
+                size: Size::Byte,
+                address,
+            } => cpu.memory.read::<u8>(*address)? as u32,
+            Subject::Memory {
+                size: Size::Word,
+                address,
+            } => cpu.memory.read::<u16>(*address)? as u32,
+            Subject::Memory { address, .. } => cpu.memory.read::<u32>(*address)?,
+        })
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Register(register) => write!(f, "{register}"),
+            Subject::Memory { size, address } => write!(f, "{size} [{address:#x}]"),
+        }
+    }
+}
+
+/// How the value of an assertion's subject is compared with the expected value. Values are
+/// compared as unsigned integers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The comparison operators, with the longest first such that e.g. `<=` is never mistaken for
+    /// `<`.
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn holds(&self, actual: u32, expected: u32) -> bool {
+        match self {
+            Comparison::Equal => actual == expected,
+            Comparison::NotEqual => actual != expected,
+            Comparison::Less => actual < expected,
+            Comparison::LessOrEqual => actual <= expected,
+            Comparison::Greater => actual > expected,
+            Comparison::GreaterOrEqual => actual >= expected,
+        }
+    }
+}
+
+/// A condition which a program asserts about the state of the processor at a point in its
+/// execution, which is written as `%pragma peanut assert condition` before the instruction which
+/// it applies to. A [`Machine`](crate::machine::Machine) checks it whenever that instruction is
+/// about to be executed, and stops with an `Error::AssertionFailed` if it does not hold, such that
+/// a program can test itself. For example:
+///
+/// ```text
+/// %pragma peanut assert eax == 5
+/// %pragma peanut assert mem dword [buffer] == 0x1234
+/// %pragma peanut assert cl < SIZE * 2
+/// ```
+///
+/// The subject is either a general-purpose or segment register, or a `BYTE`, `WORD`, or `DWORD` in
+/// memory at an address given by a constant expression. It is compared with the value of a
+/// constant expression (as in `equ`), which is truncated to the size of the
+/// subject, using one of `==`, `!=`, `<`, `<=`, `>`, or `>=`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assertion {
+    pub subject: Subject,
+    pub comparison: Comparison,
+    pub expected: u32,
+    /// The condition as it was written, which is reported when it does not hold.
+    pub condition: String,
+}
+
+impl Assertion {
+    /// Parses the condition of an assertion, whose symbols and location counter have already been
+    /// replaced with their values in `substituted`. `condition` is the condition as written.
+    pub(crate) fn parse(condition: &str, substituted: &str) -> Result<Self, Error> {
+        let (position, operator, comparison) = find_comparison(substituted).ok_or_else(|| {
+            Error::CannotParseInstruction(format!(
+                "the assertion \"{condition}\" must compare a register or memory with a value, \
+                 using ==, !=, <, <=, >, or >="
+            ))
+        })?;
+        let subject = parse_subject(substituted[..position].trim())?;
+        let expected = expression::evaluate(substituted[position + operator.len()..].trim())?;
+        let expected = match subject.size() {
+            Size::Byte => expected & 0xff,
+            Size::Word => expected & 0xffff,
+            _ => expected,
+        };
+        Ok(Self {
+            subject,
+            comparison,
+            expected,
+            condition: condition.into(),
+        })
+    }
+
+    /// Checks that the condition holds, returning a description of why it does not otherwise.
+    pub(crate) fn check(&self, cpu: &Cpu) -> Result<(), String> {
+        let actual = self
+            .subject
+            .value(cpu)
+            .map_err(|error| format!("\"{}\" could not be checked: {error}", self.condition))?;
+        if self.comparison.holds(actual, self.expected) {
+            return Ok(());
+        }
+        Err(format!(
+            "\"{}\" does not hold, as {} is {actual:#x}",
+            self.condition, self.subject
+        ))
+    }
+}
+
+/// Finds the first comparison operator within a condition, returning its position, the operator,
+/// and the comparison which it makes. The shift operators of expressions (`<<` and `>>`) are
+/// skipped.
+fn find_comparison(condition: &str) -> Option<(usize, &'static str, Comparison)> {
+    let mut position = 0;
+    while position < condition.len() {
+        let remainder = &condition[position..];
+        if remainder.starts_with("<<") || remainder.starts_with(">>") {
+            position += 2;
+            continue;
+        }
+        if let Some((operator, comparison)) = Comparison::OPERATORS
+            .iter()
+            .find(|(operator, _)| remainder.starts_with(operator))
+        {
+            return Some((position, operator, *comparison));
+        }
+        position += remainder.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// Parses the subject of an assertion, i.e. a register or `mem SIZE [address]`.
+fn parse_subject(subject: &str) -> Result<Subject, Error> {
+    let (keyword, memory) = subject
+        .split_once(char::is_whitespace)
+        .unwrap_or((subject, ""));
+    if !keyword.eq_ignore_ascii_case("mem") {
+        return match Register::try_from(&NasmStr(subject)) {
+            Ok(
+                register @ (Register::Register32(_)
+                | Register::Register16(_)
+                | Register::Register8(_)),
+            ) => Ok(Subject::Register(register)),
+            _ => Err(Error::CannotParseInstruction(format!(
+                "\"{subject}\" cannot be the subject of an assertion, which must be a \
+                 general-purpose or segment register, or memory (e.g. \"mem dword [address]\")"
+            ))),
+        };
+    }
+
+    let memory = memory.trim();
+    let (size, address) = memory
+        .split_once(char::is_whitespace)
+        .unwrap_or((memory, ""));
+    let size = match Size::try_from(&NasmStr(size)) {
+        Ok(size @ (Size::Byte | Size::Word | Size::Dword)) => size,
+        _ => {
+            return Err(Error::CannotParseInstruction(format!(
+                "\"{size}\" is not the size of memory which an assertion can check, which must be \
+                 byte, word, or dword"
+            )))
+        }
+    };
+    let address = address
+        .trim()
+        .strip_prefix('[')
+        .and_then(|address| address.strip_suffix(']'))
+        .ok_or_else(|| {
+            Error::CannotParseInstruction(format!(
+                "expected an address within brackets after \"mem {size}\""
+            ))
+        })?;
+    Ok(Subject::Memory {
+        size,
+        address: expression::evaluate(address)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        machine::{tests::load, StopReason},
+        program::Layout,
+        register::{Register32, Register8},
+    };
+
+    #[test]
+    fn assertion_parse() {
+        assert_eq!(
+            Assertion::parse("eax == SIZE", "eax == 4").unwrap(),
+            Assertion {
+                subject: Subject::Register(Register32::Eax.into()),
+                comparison: Comparison::Equal,
+                expected: 4,
+                condition: "eax == SIZE".into(),
+            }
+        );
+        let assertion = Assertion::parse("cl >= 1 << 8 | -1", "cl >= 1 << 8 | -1").unwrap();
+        assert_eq!(assertion.subject, Subject::Register(Register8::Cl.into()));
+        assert_eq!(assertion.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(assertion.expected, 0xff);
+        assert_eq!(
+            Assertion::parse("mem word [0x10 + 2] != 0", "mem word [0x10 + 2] != 0")
+                .unwrap()
+                .subject,
+            Subject::Memory {
+                size: Size::Word,
+                address: 0x12
+            }
+        );
+
+        for condition in [
+            "eax",
+            "eax = 1",
+            "1 == eax",
+            "cr0 == 1",
+            "mem qword [0] == 1",
+            "mem dword 0 == 1",
+            "eax == ebx",
+        ] {
+            assert!(Assertion::parse(condition, condition).is_err());
+        }
+    }
+
+    #[test]
+    fn assertion_check() {
+        let source = "%define COUNT 3\n\
+                      mov [value], ecx\n\
+                      l: sub ecx, 1\n\
+                      %pragma peanut assert ecx >= -COUNT ; checked on every iteration\n\
+                      cmp ecx, 0xfffffffd\n\
+                      jne l\n\
+                      %pragma peanut assert mem dword [value] == 0\n\
+                      %pragma peanut assert cx == 0xfffd\n\
+                      hlt\n\
+                      section .data\n\
+                      value: dd 1";
+        let mut machine = load(source);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+
+        let failing = source.replace("cx == 0xfffd", "cx == 0xfffe");
+        let mut machine = load(&failing);
+        assert_eq!(
+            machine.run().unwrap_err().to_string(),
+            "assertion failed: \"cx == 0xfffe\" does not hold, as CX is 0xfffd, on line 8"
+        );
+        // The machine stops before the instruction which the assertion applies to.
+        assert_eq!(
+            machine.cpu().registers.get_eip(),
+            Layout::default().text + 0x18
+        );
+    }
+}
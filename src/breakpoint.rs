@@ -0,0 +1,141 @@
+use alloc::{collections::BTreeSet, format, string::String};
+
+use crate::{
+    error::Error,
+    instruction::{canonical_mnemonic, lookup_instructions_by_mnemonic, Instruction},
+    program::SymbolTable,
+};
+
+/// Where a breakpoint stops the machine, before the instruction there is executed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The instruction at an address.
+    Address(u32),
+    /// The instruction at a label which the program defines, e.g. `main`.
+    Label(String),
+    /// Every instruction with a mnemonic, e.g. `CALL` or `INT`, such that the machine stops at the
+    /// first one it reaches. Aliases (e.g. `JZ` for `JE`) and case are ignored.
+    Mnemonic(String),
+}
+
+impl Breakpoint {
+    /// A breakpoint on the instructions with `mnemonic`, or `None` if no instruction has it.
+    pub fn mnemonic(mnemonic: &str) -> Option<Self> {
+        if lookup_instructions_by_mnemonic(mnemonic).is_empty() {
+            return None;
+        }
+        Some(Self::Mnemonic(canonical_mnemonic(mnemonic)))
+    }
+}
+
+/// The breakpoints which are set on a [`Machine`](crate::machine::Machine), which its run loop
+/// consults before each instruction. Labels are resolved to addresses when they are added, so
+/// only addresses and mnemonics are kept.
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    addresses: BTreeSet<u32>,
+    mnemonics: BTreeSet<String>,
+}
+
+impl Breakpoints {
+    /// Sets a breakpoint, returning `false` if it was already set. Returns an
+    /// `Error::InvalidSymbol` if it is on a label which is not defined in `symbols`.
+    pub fn add(&mut self, breakpoint: &Breakpoint, symbols: &SymbolTable) -> Result<bool, Error> {
+        Ok(match breakpoint {
+            Breakpoint::Address(address) => self.addresses.insert(*address),
+            Breakpoint::Label(label) => self.addresses.insert(resolve(label, symbols)?),
+            Breakpoint::Mnemonic(mnemonic) => self.mnemonics.insert(canonical_mnemonic(mnemonic)),
+        })
+    }
+
+    /// Removes a breakpoint, returning `false` if it was not set. Returns an
+    /// `Error::InvalidSymbol` if it is on a label which is not defined in `symbols`.
+    pub fn remove(
+        &mut self,
+        breakpoint: &Breakpoint,
+        symbols: &SymbolTable,
+    ) -> Result<bool, Error> {
+        Ok(match breakpoint {
+            Breakpoint::Address(address) => self.addresses.remove(address),
+            Breakpoint::Label(label) => self.addresses.remove(&resolve(label, symbols)?),
+            Breakpoint::Mnemonic(mnemonic) => self.mnemonics.remove(&canonical_mnemonic(mnemonic)),
+        })
+    }
+
+    /// Sets a breakpoint at `address`, returning `false` if there already was one.
+    pub fn add_address(&mut self, address: u32) -> bool {
+        self.addresses.insert(address)
+    }
+
+    /// Removes the breakpoint at `address`, returning `false` if there was none.
+    pub fn remove_address(&mut self, address: u32) -> bool {
+        self.addresses.remove(&address)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.mnemonics.is_empty()
+    }
+
+    /// Whether a breakpoint stops the machine at `instruction`, which is at `address`.
+    pub fn hit(&self, address: u32, instruction: Option<&Instruction>) -> bool {
+        self.addresses.contains(&address)
+            || !self.mnemonics.is_empty()
+                && instruction.is_some_and(|instruction| {
+                    self.mnemonics
+                        .contains(&canonical_mnemonic(&instruction.mnemonic))
+                })
+    }
+}
+
+/// The address of `label`.
+fn resolve(label: &str, symbols: &SymbolTable) -> Result<u32, Error> {
+    symbols.get(label).ok_or_else(|| {
+        Error::InvalidSymbol(format!(
+            "cannot set a breakpoint at \"{label}\", which has not been defined"
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::NasmStr;
+
+    #[test]
+    fn breakpoints() {
+        let mut symbols = SymbolTable::default();
+        symbols.define("main", 4).unwrap();
+        let mut breakpoints = Breakpoints::default();
+        assert!(breakpoints.is_empty());
+
+        assert!(breakpoints.add(&Breakpoint::Address(2), &symbols).unwrap());
+        assert!(breakpoints
+            .add(&Breakpoint::Label("main".into()), &symbols)
+            .unwrap());
+        assert!(!breakpoints.add_address(4));
+        assert!(matches!(
+            breakpoints.add(&Breakpoint::Label("start".into()), &symbols),
+            Err(Error::InvalidSymbol(_))
+        ));
+        assert!(breakpoints.hit(2, None));
+        assert!(breakpoints.hit(4, None));
+        assert!(!breakpoints.hit(3, None));
+
+        let call = Instruction::try_from(&NasmStr("call 0x10")).unwrap();
+        assert!(!breakpoints.hit(3, Some(&call)));
+        let mnemonic = Breakpoint::mnemonic("call").unwrap();
+        assert_eq!(mnemonic, Breakpoint::Mnemonic("CALL".into()));
+        assert!(breakpoints.add(&mnemonic, &symbols).unwrap());
+        assert!(breakpoints.hit(3, Some(&call)));
+        assert!(breakpoints.remove(&mnemonic, &symbols).unwrap());
+        assert!(!breakpoints.hit(3, Some(&call)));
+
+        // Aliases of a mnemonic hit the same breakpoint.
+        let jz = Instruction::try_from(&NasmStr("jz 0x10")).unwrap();
+        breakpoints
+            .add(&Breakpoint::Mnemonic("je".into()), &symbols)
+            .unwrap();
+        assert!(breakpoints.hit(3, Some(&jz)));
+        assert_eq!(Breakpoint::mnemonic("main"), None);
+    }
+}
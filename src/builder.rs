@@ -0,0 +1,270 @@
+#[cfg(feature = "std")]
+use alloc::rc::Rc;
+use alloc::{boxed::Box, vec::Vec};
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+use core::ops::RangeInclusive;
+
+#[cfg(feature = "std")]
+use crate::dos::Dos;
+use crate::{
+    cpu::Cpu,
+    devices::{pic::Pic, PortDevice},
+    error::Error,
+    machine::Machine,
+    memory::Permissions,
+    program::Program,
+    random::EntropySource,
+    register::Register32,
+};
+
+/// The operating system which a program runs under, which services its system calls.
+#[derive(Default)]
+pub enum Personality {
+    /// None, such that the program only has the hardware (e.g. the I/O ports) to talk to.
+    #[default]
+    BareMetal,
+    /// DOS, which services `int 0x21` (see [`Dos`]). The personality is shared with its handler,
+    /// such that the exit code can be read once the program has run.
+    #[cfg(feature = "std")]
+    Dos(Rc<RefCell<Dos>>),
+}
+
+/// Configures the state which a [`Machine`] starts in: the size of memory, the initial values of
+/// the registers, where the stack is, the images loaded into memory alongside the program, the
+/// devices attached to the I/O ports, the source of `RDRAND`'s random numbers, and the operating
+/// system personality. Anything which is not configured is as [`Machine::new`] leaves it. For
+/// example:
+///
+/// ```
+/// use peanut::{
+///     builder::MachineBuilder, instruction::Syntax, machine::StopReason, program::Layout,
+///     register::Register32, Program,
+/// };
+///
+/// let source = "sub ecx, 1\ncall f\nf: hlt";
+/// let program = Program::assemble(source, Layout::default(), Syntax::Nasm)?;
+/// let mut machine = MachineBuilder::new(program)
+///     .register(Register32::Ecx, 0x1234)
+///     .stack(0x8000)
+///     .build()?;
+/// assert_eq!(machine.run()?, StopReason::Halted);
+/// let cpu = machine.cpu();
+/// assert_eq!(cpu.registers().read32(&Register32::Ecx), 0x1233);
+/// // CALL pushed the address of the instruction after it.
+/// assert_eq!(cpu.memory().read::<u32>(0x7ffc)?, 0x100b);
+/// # Ok::<(), peanut::Error>(())
+/// ```
+pub struct MachineBuilder {
+    program: Program,
+    cpu: Cpu,
+    memory_size: Option<u32>,
+    images: Vec<(u32, Vec<u8>)>,
+    protect_sections: bool,
+    pic: Option<Pic>,
+    devices: Vec<(RangeInclusive<u16>, Box<dyn PortDevice>)>,
+    personality: Personality,
+}
+
+impl MachineBuilder {
+    pub fn new(program: Program) -> Self {
+        Self {
+            program,
+            cpu: Cpu::default(),
+            memory_size: None,
+            images: Vec::new(),
+            protect_sections: false,
+            pic: None,
+            devices: Vec::new(),
+            personality: Personality::default(),
+        }
+    }
+
+    /// Starts from the given processor, rather than one in its default state.
+    pub fn cpu(mut self, cpu: Cpu) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// Limits memory to its first `size` bytes, such that any access at or above `size` fails as
+    /// an access to protected memory does (see [`Memory::protect`]). By default, the whole 4 GiB
+    /// address space can be accessed.
+    ///
+    /// [`Memory::protect`]: crate::memory::Memory::protect
+    pub fn memory_size(mut self, size: u32) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    /// Sets the initial value of a general-purpose register.
+    pub fn register(mut self, register: Register32, value: u32) -> Self {
+        self.cpu.registers.write32(&register, value);
+        self
+    }
+
+    /// Places the stack, which grows down from `top` (the initial value of ESP).
+    pub fn stack(mut self, top: u32) -> Self {
+        self.cpu.registers.esp = top;
+        self
+    }
+
+    /// Limits how far down the stack may grow (see [`Cpu::set_stack_limit`]).
+    pub fn stack_limit(mut self, limit: u32) -> Self {
+        self.cpu.set_stack_limit(Some(limit));
+        self
+    }
+
+    /// Starts the processor in real mode (see [`Cpu::enter_real_mode`]).
+    pub fn real_mode(mut self) -> Self {
+        self.cpu.enter_real_mode();
+        self
+    }
+
+    /// Loads an image (e.g. a ROM, a boot sector, or data) into memory at `address`, once the
+    /// program has been loaded (see [`Machine::load_image`]). Images are loaded in the order they
+    /// are given, with later ones overwriting earlier ones (and the program) where they overlap.
+    pub fn image(mut self, address: u32, bytes: Vec<u8>) -> Self {
+        self.images.push((address, bytes));
+        self
+    }
+
+    /// Protects the program's sections once it has been loaded (see
+    /// [`Machine::protect_sections`]).
+    pub fn protect_sections(mut self) -> Self {
+        self.protect_sections = true;
+        self
+    }
+
+    /// Attaches a PIC, which devices may already be connected to (see [`Machine::install_pic`]).
+    pub fn pic(mut self, pic: Pic) -> Self {
+        self.pic = Some(pic);
+        self
+    }
+
+    /// Attaches a device to the given range of I/O ports (see [`Machine::attach_device`]).
+    pub fn device(mut self, ports: RangeInclusive<u16>, device: impl PortDevice + 'static) -> Self {
+        self.devices.push((ports, Box::new(device)));
+        self
+    }
+
+    /// Draws the random numbers returned by `RDRAND` from `source` (see [`Cpu::set_entropy_source`]),
+    /// e.g. such that a run can be reproduced.
+    pub fn entropy_source(mut self, source: impl EntropySource + 'static) -> Self {
+        self.cpu.set_entropy_source(Box::new(source));
+        self
+    }
+
+    /// Runs the program under an operating system personality. By default, there is none.
+    pub fn personality(mut self, personality: Personality) -> Self {
+        self.personality = personality;
+        self
+    }
+
+    /// Creates the machine, with the program loaded and ready to be run. Returns an `Err` if the
+    /// program or an image does not fit in memory, or if a device uses ports which are already in
+    /// use.
+    pub fn build(self) -> Result<Machine, Error> {
+        let mut machine = Machine::new(self.cpu, self.program)?;
+        for (address, bytes) in &self.images {
+            machine.load_image(*address, bytes)?;
+        }
+        if let Some(size) = self.memory_size {
+            machine
+                .cpu_mut()
+                .memory
+                .protect(size..=u32::MAX, Permissions::NONE);
+        }
+        if self.protect_sections {
+            machine.protect_sections();
+        }
+        if let Some(pic) = self.pic {
+            machine.install_pic(pic)?;
+        }
+        for (ports, device) in self.devices {
+            machine.cpu_mut().attach_io_device(ports, device)?;
+        }
+        match &self.personality {
+            Personality::BareMetal => (),
+            #[cfg(feature = "std")]
+            Personality::Dos(dos) => Dos::install_shared(dos, &mut machine),
+        }
+        Ok(machine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::Syntax, interrupt::CpuException, machine::StopReason, program::Layout,
+    };
+
+    #[derive(Debug)]
+    struct ConstantEntropySource(u32);
+
+    impl EntropySource for ConstantEntropySource {
+        fn next_u32(&mut self) -> Option<u32> {
+            Some(self.0)
+        }
+    }
+
+    fn assemble(source: &str) -> Program {
+        Program::assemble(source, Layout::default(), Syntax::Nasm).unwrap()
+    }
+
+    #[test]
+    fn machine_builder() {
+        let mut machine = MachineBuilder::new(assemble("call f\nf: hlt"))
+            .register(Register32::Ecx, 0x1234)
+            .stack(0x2000)
+            .stack_limit(0x1000)
+            .image(0x500, vec![1, 2, 3, 4])
+            .build()
+            .unwrap();
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        let cpu = machine.cpu();
+        assert_eq!(cpu.registers().esp, 0x1ffc);
+        assert_eq!(cpu.memory().read::<u32>(0x1ffc).unwrap(), 0x1005);
+        assert_eq!(cpu.registers().read32(&Register32::Ecx), 0x1234);
+        assert_eq!(cpu.memory().read::<u32>(0x500).unwrap(), 0x0403_0201);
+
+        // Memory beyond its size cannot be accessed.
+        let mut machine = MachineBuilder::new(assemble("mov [0x1800], ecx\nhlt"))
+            .memory_size(0x1000)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::GeneralProtection)
+        );
+
+        let mut machine = MachineBuilder::new(assemble("rdrand eax\nhlt"))
+            .entropy_source(ConstantEntropySource(0x1234_5678))
+            .build()
+            .unwrap();
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(
+            machine.cpu().registers().read32(&Register32::Eax),
+            0x1234_5678
+        );
+
+        let dos = Rc::new(RefCell::new(Dos::new(&b""[..], Vec::new())));
+        let mut machine = MachineBuilder::new(assemble("int 0x21"))
+            .register(Register32::Eax, 0x4c07)
+            .personality(Personality::Dos(Rc::clone(&dos)))
+            .build()
+            .unwrap();
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(dos.borrow().exit_code(), Some(7));
+
+        // A device cannot use the ports of the PIC.
+        let (master, _) = Pic::default().ports();
+        assert!(matches!(
+            MachineBuilder::new(assemble("hlt"))
+                .pic(Pic::default())
+                .device(0x20..=0x21, master)
+                .build(),
+            Err(Error::PortConflict(_))
+        ));
+    }
+}
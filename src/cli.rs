@@ -0,0 +1,215 @@
+use std::{
+    cell::RefCell,
+    fs,
+    io::{stderr, stdin, stdout},
+    process,
+    rc::Rc,
+    time::Duration,
+};
+
+use clap::Parser;
+
+use crate::{
+    arguments,
+    builder::{MachineBuilder, Personality},
+    coverage::CoverageFormat,
+    devices::{
+        keyboard::{Keyboard, DATA_PORT, KEYBOARD_IRQ, STATUS_PORT},
+        pic::Pic,
+        uart::{Uart16550, COM1_IRQ, COM1_PORTS},
+    },
+    dos::Dos,
+    error::Error,
+    machine::StopReason,
+    program::{Layout, Program},
+};
+
+/// The number of hotspots which `--profile` reports.
+const PROFILE_HOTSPOTS: usize = 20;
+
+/// Where the stack grows down from when `--stack` is not given, which is above the sections of a
+/// program with the default layout. If memory is smaller than this, the stack is at its end instead.
+const DEFAULT_STACK: u32 = 0x10_0000;
+
+/// The entry point of the `peanut` command, which assembles and runs the programs given by the
+/// command-line arguments.
+pub fn run() {
+    let arguments = arguments::Arguments::parse();
+    let paths: Vec<_> = arguments
+        .file_paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let contents: Vec<_> = arguments
+        .file_paths
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path).unwrap_or_else(|error| {
+                eprintln!("error: failed to read {}: {error}", path.display());
+                process::exit(1);
+            })
+        })
+        .collect();
+    let sources: Vec<_> = contents.iter().map(String::as_str).collect();
+    let program = match Program::assemble_modules(&sources, Layout::default(), arguments.syntax) {
+        Ok(program) => program,
+        Err(Error::Diagnostic(diagnostic)) => {
+            let module = diagnostic.module;
+            eprint!("{}", diagnostic.render(&paths[module], &contents[module]));
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("error: {error}");
+            process::exit(1);
+        }
+    };
+    for warning in program.warnings() {
+        let module = warning.module;
+        eprint!("{}", warning.render(&paths[module], &contents[module]));
+    }
+
+    let pic = Pic::default();
+    let mut builder = MachineBuilder::new(program).pic(pic.clone());
+    if let Some(size) = arguments.memory_size {
+        builder = builder.memory_size(size);
+    }
+    // A 32-bit stack cannot grow down from an ESP of 0, as the first push would wrap it past zero.
+    // SP wraps around its 64 KiB segment instead, so real mode keeps the top of it as the default.
+    let stack = arguments.stack.or_else(|| {
+        (!arguments.real_mode).then(|| {
+            arguments
+                .memory_size
+                .map_or(DEFAULT_STACK, |size| size.min(DEFAULT_STACK))
+        })
+    });
+    if let Some(top) = stack {
+        builder = builder.stack(top);
+    }
+    if let Some(limit) = arguments.stack_limit {
+        builder = builder.stack_limit(limit);
+    }
+    if arguments.real_mode {
+        builder = builder.real_mode();
+    }
+    for image in &arguments.load {
+        match fs::read(&image.path) {
+            Ok(bytes) => builder = builder.image(image.address, bytes),
+            Err(error) => {
+                eprintln!(
+                    "error: failed to load {} at {:#x}: {error}",
+                    image.path.display(),
+                    image.address
+                );
+                process::exit(1);
+            }
+        }
+    }
+    if arguments.protect {
+        builder = builder.protect_sections();
+    }
+    if arguments.serial {
+        let mut uart = Uart16550::default();
+        uart.connect_irq(pic.irq_line(COM1_IRQ));
+        builder = builder.device(COM1_PORTS, uart);
+    }
+    if arguments.keyboard {
+        let keyboard = Keyboard::from_stdin();
+        keyboard.connect_irq(pic.irq_line(KEYBOARD_IRQ));
+        for port in [DATA_PORT, STATUS_PORT] {
+            builder = builder.device(port..=port, keyboard.port());
+        }
+    }
+    let dos = arguments
+        .dos
+        .then(|| Rc::new(RefCell::new(Dos::new(stdin(), stdout()))));
+    if let Some(dos) = &dos {
+        builder = builder.personality(Personality::Dos(Rc::clone(dos)));
+    }
+    let mut machine = builder.build().unwrap_or_else(|error| {
+        eprintln!("error: {error}");
+        process::exit(1);
+    });
+
+    machine.set_instruction_limit(arguments.max_instructions);
+    machine.set_timeout(arguments.timeout.map(Duration::from_secs_f64));
+    for breakpoint in &arguments.breakpoints {
+        if let Err(error) = machine.set_breakpoint(breakpoint) {
+            eprintln!("error: {error}");
+            process::exit(1);
+        }
+    }
+    if arguments.trace {
+        machine.set_tracer(Some(arguments.trace_format.tracer(stderr())));
+    }
+    machine.collect_stats(arguments.stats);
+    machine.collect_coverage(arguments.coverage.is_some());
+    machine.collect_profile(arguments.profile || arguments.profile_folded.is_some());
+    let stop_reason = machine.run();
+    if let Some(profile) = machine.profile() {
+        if arguments.profile {
+            eprint!("{}", profile.report(machine.program(), PROFILE_HOTSPOTS));
+        }
+        if let Some(path) = &arguments.profile_folded {
+            if let Err(error) = fs::write(path, profile.folded(machine.program())) {
+                eprintln!(
+                    "error: failed to write the profile to {}: {error}",
+                    path.display()
+                );
+            }
+        }
+    }
+    if let (Some(path), Some(coverage)) = (&arguments.coverage, machine.coverage()) {
+        let program = machine.program();
+        let report = match arguments.coverage_format {
+            CoverageFormat::Lcov => coverage.lcov(program, &paths),
+            CoverageFormat::Annotated => paths
+                .iter()
+                .zip(&contents)
+                .enumerate()
+                .map(|(module, (file, source))| coverage.annotate(program, module, file, source))
+                .collect(),
+        };
+        if let Err(error) = fs::write(path, report) {
+            eprintln!(
+                "error: failed to write the coverage to {}: {error}",
+                path.display()
+            );
+        }
+    }
+    if let Some(stats) = machine.stats() {
+        eprint!("{stats}");
+    }
+    if arguments.dump_state {
+        eprintln!("{}", machine.cpu().registers);
+    }
+    for range in &arguments.dump {
+        eprint!("{}", machine.cpu().memory.hexdump(range.clone()));
+    }
+    match stop_reason {
+        Ok(StopReason::Exception(exception)) => {
+            eprintln!("error: unhandled exception: {exception}");
+            process::exit(1);
+        }
+        Ok(StopReason::HardwareBreakpoint(index)) => {
+            eprintln!("error: unhandled exception: #DB, raised by the breakpoint in DR{index}");
+            process::exit(1);
+        }
+        Ok(StopReason::InstructionLimit) => {
+            let limit = arguments.max_instructions.unwrap_or_default();
+            let error = Error::Timeout(format!("{limit} instructions were executed"));
+            eprintln!("error: {error}");
+            process::exit(1);
+        }
+        Ok(StopReason::Breakpoint(address)) => {
+            eprintln!("stopped at the breakpoint at {address:#x}");
+        }
+        Ok(_) => (),
+        Err(error) => {
+            eprintln!("error: {error}");
+            process::exit(1);
+        }
+    }
+    if let Some(exit_code) = dos.and_then(|dos| dos.borrow().exit_code()) {
+        process::exit(exit_code.into());
+    }
+}
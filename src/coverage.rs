@@ -0,0 +1,159 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+};
+use core::fmt::Write;
+
+use crate::program::Program;
+
+/// The formats in which `--coverage` can write which lines of source were executed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum CoverageFormat {
+    /// An LCOV tracefile, which tools such as `genhtml` can render.
+    #[default]
+    Lcov,
+    /// The source, with the number of times each line was executed.
+    Annotated,
+}
+
+/// How many times each instruction of a [`Program`] has been executed, which a
+/// [`Machine`](crate::machine::Machine) collects once it is enabled with
+/// [`Machine::collect_coverage`](crate::machine::Machine::collect_coverage).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// The number of times that the instruction at each address was executed, which is only
+    /// present once it has been.
+    hits: BTreeMap<u32, u64>,
+}
+
+impl Coverage {
+    /// Counts an execution of the instruction at `address`.
+    pub(crate) fn record(&mut self, address: u32) {
+        *self.hits.entry(address).or_default() += 1;
+    }
+
+    /// The number of times that the instruction at `address` has been executed.
+    pub fn hits(&self, address: u32) -> u64 {
+        self.hits.get(&address).copied().unwrap_or_default()
+    }
+
+    /// The number of times that each line of the module with the given index (see
+    /// [`Program::assemble_modules`]) was executed, by line number. Only lines which have
+    /// instructions are included. A line with several instructions (e.g. from `TIMES` or a macro)
+    /// counts as executed as many times as the most executed of them.
+    pub fn lines(&self, program: &Program, module: usize) -> BTreeMap<usize, u64> {
+        let mut lines = BTreeMap::new();
+        for (address, source_line) in program.source_lines() {
+            if source_line.module == module {
+                let hits: &mut u64 = lines.entry(source_line.line).or_default();
+                *hits = (*hits).max(self.hits(address));
+            }
+        }
+        lines
+    }
+
+    /// An LCOV tracefile, with a record for each module, whose source file is given by `paths`
+    /// (indexed by module). For example:
+    ///
+    /// ```text
+    /// TN:
+    /// SF:loop.asm
+    /// DA:1,3
+    /// DA:4,0
+    /// LF:2
+    /// LH:1
+    /// end_of_record
+    /// ```
+    pub fn lcov(&self, program: &Program, paths: &[String]) -> String {
+        let mut lcov = String::new();
+        for (module, path) in paths.iter().enumerate() {
+            let lines = self.lines(program, module);
+            let _ = writeln!(lcov, "TN:\nSF:{path}");
+            for (line, hits) in &lines {
+                let _ = writeln!(lcov, "DA:{line},{hits}");
+            }
+            let hit = lines.values().filter(|hits| **hits > 0).count();
+            let _ = writeln!(lcov, "LF:{}\nLH:{hit}\nend_of_record", lines.len());
+        }
+        lcov
+    }
+
+    /// The source of a module, as `gcov` annotates it: each line is prefixed with the number of
+    /// times it was executed, `#####` if it has instructions which were never executed, or `-` if
+    /// it has none, and then with its line number. For example:
+    ///
+    /// ```text
+    ///         -:    0:Source:loop.asm
+    ///         3:    1:l: sub ecx, 1
+    ///         -:    2:; no instructions
+    ///     #####:    3:hlt
+    /// ```
+    pub fn annotate(&self, program: &Program, module: usize, path: &str, source: &str) -> String {
+        let lines = self.lines(program, module);
+        let mut annotated = format!("{:>9}:{:>5}:Source:{path}\n", "-", 0);
+        for (index, text) in source.lines().enumerate() {
+            let number = index + 1;
+            let hits = match lines.get(&number) {
+                Some(0) => "#####".into(),
+                Some(hits) => hits.to_string(),
+                None => "-".into(),
+            };
+            let _ = writeln!(annotated, "{hits:>9}:{number:>5}:{text}");
+        }
+        annotated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu::Cpu,
+        instruction::Syntax,
+        machine::{Machine, StopReason},
+        program::{Layout, SourceLine},
+    };
+
+    #[test]
+    fn coverage() {
+        let sources = [
+            "extern f\nl: sub ecx, 1\n; count down\ncmp ecx, 0xfffffffd\njne l\ncall f\nhlt",
+            "global f\nf: ret\nhlt",
+        ];
+        let program = Program::assemble_modules(&sources, Layout::default(), Syntax::Nasm).unwrap();
+        assert_eq!(
+            program.source_line(Layout::default().text + 0x18),
+            Some(SourceLine { module: 1, line: 2 })
+        );
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 0x1000;
+        let mut machine = Machine::new(cpu, program).unwrap();
+        assert_eq!(machine.coverage(), None);
+        machine.collect_coverage(true);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+
+        let coverage = machine.coverage().unwrap();
+        let program = machine.program();
+        assert_eq!(coverage.hits(Layout::default().text), 3);
+        assert_eq!(
+            coverage.lines(program, 0).into_iter().collect::<Vec<_>>(),
+            [(2, 3), (4, 3), (5, 3), (6, 1), (7, 1)]
+        );
+
+        let paths = ["main.asm".to_string(), "f.asm".to_string()];
+        assert_eq!(
+            coverage.lcov(program, &paths),
+            "TN:\nSF:main.asm\nDA:2,3\nDA:4,3\nDA:5,3\nDA:6,1\nDA:7,1\nLF:5\nLH:5\nend_of_record\n\
+             TN:\nSF:f.asm\nDA:2,1\nDA:3,0\nLF:2\nLH:1\nend_of_record\n"
+        );
+        assert_eq!(
+            coverage.annotate(program, 1, "f.asm", sources[1]),
+            "        -:    0:Source:f.asm\n        \
+             -:    1:global f\n        \
+             1:    2:f: ret\n    \
+             #####:    3:hlt\n"
+        );
+    }
+}
@@ -1,30 +1,676 @@
-use std::ops::{BitAnd, BitOr};
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use core::{
+    cell::{Cell, RefCell},
+    fmt,
+    ops::{BitAnd, BitOr, BitXor, RangeInclusive},
+};
 
 use num_traits::{FromPrimitive, PrimInt, WrappingAdd, WrappingSub};
 
 use crate::{
+    descriptor::{
+        AccessRights, DescriptorKind, GateDescriptor, SegmentDescriptor, Selector, TaskState,
+        AVAILABLE_TSS_TYPE, BUSY_FLAG, BUSY_TSS_TYPE, DESCRIPTOR_SIZE, INTERRUPT_GATE_16_TYPE,
+        INTERRUPT_GATE_TYPE, LDT_TYPE, TASK_GATE_TYPE, TASK_STATE_SIZE, TRAP_GATE_16_TYPE,
+        TRAP_GATE_TYPE,
+    },
+    devices::{IoBus, PortDevice},
+    error::Error,
+    fpu::Fpu,
     instruction::{
-        unwrap_operands, EffectiveAddress, Immediate, Operands, RegisterOrMemory16,
-        RegisterOrMemory32, RegisterOrMemory8, Size,
+        unwrap_operands, EffectiveAddress, FarPointer, Immediate, Immediate16, Immediate32,
+        Immediate8, Instruction, MmxRegisterOrMemory64, Operands, RegisterOrMemory16,
+        RegisterOrMemory32, RegisterOrMemory8, RepeatPrefix, Size, XmmRegisterOrMemory128,
+        XmmRegisterOrMemory64,
+    },
+    interrupt::{
+        CpuException, InterruptHandler, InterruptHandlers, InterruptKind, InterruptVector,
+    },
+    memory::{AccessKind, Memory, PhysicalAddress},
+    msr::{self, ModelSpecificRegisters},
+    paging::{self, PageFault, Tlb},
+    random::{EntropySource, RandomNumberGenerator},
+    register::{
+        ControlRegister, CurrentPrivilegeLevel, DebugRegister, DescriptorTableRegister,
+        MmxRegister, OperatingMode, Register16, Register32, Register8, Registers, Segment,
+        SegmentRegister, WithCarry, XmmRegister,
     },
-    memory::Memory,
-    register::{Register16, Register32, Register8, Registers, WithCarry},
+    sse::{ArithmeticOperation, SimdFloat, Sse},
     traits::{AsUnsigned, RegisterReadWrite},
 };
 
+/// The size in bytes of the pseudo-descriptor operand of `LGDT`, `LIDT`, `SGDT` and `SIDT`.
+const PSEUDO_DESCRIPTOR_LENGTH: u32 = 6;
+
+/// The most bytes which an instruction may be encoded in.
+const MAX_INSTRUCTION_LENGTH: u32 = 15;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Operation {
     Add,
     Subtract,
 }
 
+/// Intel manual section 7.3 "TASK SWITCHING", table 7-2.
+/// What initiated a task switch, which determines how the busy flags of the TSS descriptors, the
+/// NT flag, and the previous task link are updated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TaskSwitch {
+    /// A far `CALL`, which nests the new task within the running one, so that it can return to it
+    /// with `IRET`.
+    Call,
+    /// A far `JMP`, which leaves the running task.
+    Jump,
+    /// An `IRET` while the NT flag is set, which returns to the task that the running one is
+    /// nested within.
+    Iret,
+}
+
+/// The time-stamp counter, which counts the number of cycles elapsed since reset. Unlike the
+/// processor's cycle counter (see `Cpu::cycles`), it can be written (using `WRMSR`).
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeStampCounter {
+    value: u64,
+}
+
+impl TimeStampCounter {
+    pub fn get(&self) -> u64 {
+        self.value
+    }
+
+    pub fn set(&mut self, value: u64) {
+        self.value = value;
+    }
+
+    /// Advances the counter by the given number of cycles.
+    pub fn tick(&mut self, cycles: u64) {
+        self.value = self.value.wrapping_add(cycles);
+    }
+}
+
+/// The architectural state of the processor and the contents of its memory, which can be saved
+/// and later restored (see [`Cpu::save_state`]), e.g. to rewind it (see
+/// [`Machine::reverse_step`]). The state of the devices, host handlers and hooks, and the entropy
+/// source is not included, as it cannot be copied. Neither are the protected regions of memory.
+///
+/// With the `serde` feature, the state can be serialized (e.g. to compare it with a golden file in
+/// a test, or to hand it to another tool), and deserialized to be restored later.
+///
+/// [`Machine::reverse_step`]: crate::machine::Machine::reverse_step
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    registers: Registers,
+    memory: Memory,
+    fpu: Fpu,
+    sse: Sse,
+    model_specific_registers: ModelSpecificRegisters,
+    time_stamp_counter: TimeStampCounter,
+    cycles: u64,
+    halted: bool,
+    interrupt_shadow: bool,
+}
+
+impl CpuState {
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// The number of cycles which the processor had executed for.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+/// A host (Rust) closure which observes the locked read-modify-write cycles performed by
+/// instructions with the `LOCK` prefix. It is called once the instruction has completed, with the
+/// linear address of the memory operand which was locked.
+pub type LockedCycleObserver = Box<dyn FnMut(&mut Cpu, u32)>;
+
+/// The observer of locked cycles (if any). Closures are not `Debug`, so only whether there is one
+/// is shown.
+#[derive(Default)]
+pub(crate) struct LockedCycleObserverSlot(Option<LockedCycleObserver>);
+
+impl fmt::Debug for LockedCycleObserverSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LockedCycleObserverSlot")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+/// A 32-bit x86 processor, along with the memory and I/O devices which it is connected to. Its
+/// registers and memory may be inspected and changed by an embedder (see [`Cpu::registers`] and
+/// [`Cpu::memory`]), while a [`Machine`](crate::machine::Machine) runs a program on it.
+#[derive(Debug, Default)]
 pub struct Cpu {
     pub(crate) registers: Registers,
     pub(crate) memory: Memory,
+    pub(crate) interrupt_handlers: InterruptHandlers,
+    pub(crate) io: IoBus,
+    pub(crate) repeat_prefix: Option<RepeatPrefix>,
+    /// The segment which overrides the default segment of the memory operand of the instruction
+    /// being executed.
+    pub(crate) segment_override: Option<SegmentRegister>,
+    /// The segment override which has been set by a prefix, and which will be applied to the next
+    /// instruction.
+    pub(crate) pending_segment_override: Option<SegmentRegister>,
+    pub(crate) locked_cycle_observer: LockedCycleObserverSlot,
+    pub(crate) undocumented_instructions: bool,
+    pub(crate) time_stamp_counter: TimeStampCounter,
+    /// The number of cycles which have elapsed since reset, as estimated by the cost of each
+    /// executed instruction. This clocks the time-stamp counter and the devices.
+    cycles: u64,
+    /// The number of cycles which each executed instruction takes, overriding the estimated cost
+    /// of the instruction, if set.
+    cycles_per_instruction: Option<u64>,
+    pub(crate) model_specific_registers: ModelSpecificRegisters,
+    pub(crate) fpu: Fpu,
+    pub(crate) sse: Sse,
+    pub(crate) random_number_generator: RandomNumberGenerator,
+    /// Whether `HLT` has been executed, such that no more instructions should be executed until
+    /// the processor is resumed.
+    pub(crate) halted: bool,
+    /// Whether hardware interrupts are inhibited until the next instruction has been executed, as
+    /// they are after `STI` enables them (so that `STI` followed by `HLT` cannot miss one).
+    pub(crate) interrupt_shadow: bool,
+    /// The most recent exception which could not be delivered, as there was neither a host handler
+    /// nor an interrupt service routine for it, and which is yet to be reported to the embedder.
+    pub(crate) unreported_exception: Option<CpuException>,
+    /// The first fault raised by an access made by the instruction being executed.
+    /// This is held in a `Cell`, as accesses (e.g. memory reads) may only borrow the CPU
+    /// immutably.
+    pub(crate) fault: Cell<Option<CpuException>>,
+    /// The details of the latched fault, if it is a #PF exception.
+    pub(crate) page_fault: Cell<Option<PageFault>>,
+    /// The address (EIP) of the instruction being executed, which EIP is returned to when it
+    /// raises a fault (see `CpuException::is_trap`), or `None` between instructions.
+    pub(crate) instruction_eip: Option<u32>,
+    pub(crate) tlb: RefCell<Tlb>,
+    /// The accessed and dirty flags which are yet to be set in paging-structure entries, as
+    /// `(entry address, flags)` pairs (see `Cpu::update_paging_entries`).
+    paging_entry_updates: RefCell<Vec<(u32, u32)>>,
+    /// The lowest address which the stack may grow down to (see `Cpu::set_stack_limit`).
+    stack_limit: Option<u32>,
+    /// The stack pointer from before the push which overflowed the stack, if one did, which is yet
+    /// to be reported to the embedder.
+    pub(crate) stack_overflow: Option<u32>,
+    /// The data breakpoints (as a mask of DR0-DR3) which have been hit by the accesses made by the
+    /// instruction being executed. This is held in a `Cell`, as accesses may only borrow the CPU
+    /// immutably.
+    pub(crate) data_breakpoint_hits: Cell<u8>,
+    /// The hardware breakpoint (0-3) which raised the most recent #DB exception, if it could not be
+    /// delivered, which is yet to be reported to the embedder.
+    pub(crate) unreported_breakpoint: Option<usize>,
 }
 
 impl Cpu {
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// The x87 FPU, whose data registers are also the MMX registers.
+    pub fn fpu(&self) -> &Fpu {
+        &self.fpu
+    }
+
+    pub fn fpu_mut(&mut self) -> &mut Fpu {
+        &mut self.fpu
+    }
+
+    /// Whether `HLT` has been executed, such that the processor is waiting to be resumed.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Sets the number of cycles which each executed instruction takes, in place of the estimated
+    /// cost of the instruction (see `Instruction::cycles`). For example, setting it to 1 makes the
+    /// cycle and time-stamp counters count executed instructions.
+    pub fn set_cycles_per_instruction(&mut self, cycles: u64) {
+        self.cycles_per_instruction = Some(cycles);
+    }
+
+    /// Guards the stack, such that a push which would move ESP below `limit` (or wrap it past zero)
+    /// is not performed, and instead stops the machine with an `Error::StackOverflow`. This catches
+    /// runaway recursion before it corrupts the memory below the stack. The limit is removed if
+    /// `None`, in which case only a push which would wrap ESP past zero is an overflow.
+    pub fn set_stack_limit(&mut self, limit: Option<u32>) {
+        self.stack_limit = limit;
+    }
+
+    /// Puts the processor into real mode, with the 16-bit segments which it has after a reset:
+    /// CR0.PE and CR0.PG are cleared, and each segment register is reloaded with a 64 KiB segment
+    /// based at 16 times its selector, with 16-bit operands, addresses and stack pointer (see
+    /// `Segment::real_mode`). This is how boot code starts, whereas programs otherwise start with
+    /// flat 32-bit segments (see `Segment::default`). Setting CR0.PE then enters protected mode,
+    /// with each segment keeping its size until it is reloaded from a descriptor.
+    pub fn enter_real_mode(&mut self) {
+        let control_registers = &mut self.registers.control_registers;
+        control_registers.set_protection_enable(false);
+        control_registers.set_paging(false);
+        self.registers.eflags.set_virtual_8086_mode(false);
+        for segment in SegmentRegister::ALL {
+            let selector = self.registers.get_selector(segment);
+            self.registers
+                .set_segment(segment, Segment::real_mode(selector));
+        }
+        self.tlb.borrow_mut().flush();
+    }
+
+    /// The number of cycles which have elapsed since reset.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Advances the cycle and time-stamp counters past an executed instruction, which is estimated
+    /// to have taken the given number of cycles.
+    pub(crate) fn elapse(&mut self, cycles: u64) {
+        let cycles = self.cycles_per_instruction.unwrap_or(cycles);
+        self.cycles = self.cycles.wrapping_add(cycles);
+        self.time_stamp_counter.tick(cycles);
+    }
+
+    /// Saves the processor's state, such that it can be restored by [`Cpu::restore_state`].
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            registers: self.registers.clone(),
+            memory: self.memory.snapshot(),
+            fpu: self.fpu.clone(),
+            sse: self.sse.clone(),
+            model_specific_registers: self.model_specific_registers.clone(),
+            time_stamp_counter: self.time_stamp_counter.clone(),
+            cycles: self.cycles,
+            halted: self.halted,
+            interrupt_shadow: self.interrupt_shadow,
+        }
+    }
+
+    /// Restores a state which was saved by [`Cpu::save_state`]. The memory's contents are replaced,
+    /// but its hooks and protected regions are kept. Any exception or overflow which is yet to be
+    /// reported is discarded, and the TLB is flushed.
+    pub fn restore_state(&mut self, state: &CpuState) {
+        self.registers = state.registers.clone();
+        self.memory.restore(&state.memory);
+        self.fpu = state.fpu.clone();
+        self.sse = state.sse.clone();
+        self.model_specific_registers = state.model_specific_registers.clone();
+        self.time_stamp_counter = state.time_stamp_counter.clone();
+        self.cycles = state.cycles;
+        self.halted = state.halted;
+        self.interrupt_shadow = state.interrupt_shadow;
+        self.unreported_exception = None;
+        self.stack_overflow = None;
+        self.unreported_breakpoint = None;
+        self.tlb.borrow_mut().flush();
+    }
+
+    /// Defines a model-specific register at `address` with the given initial value, such that it
+    /// can be accessed by `RDMSR` and `WRMSR`.
+    pub fn define_msr(&mut self, address: u32, value: u64) {
+        self.model_specific_registers.define(address, value);
+    }
+
+    /// Sets whether `RDMSR` and `WRMSR` may access undefined model-specific registers, which then
+    /// read as 0 and ignore writes. Otherwise, accessing them raises a #GP exception.
+    pub fn set_permissive_msrs(&mut self, permissive: bool) {
+        self.model_specific_registers.set_permissive(permissive);
+    }
+
+    /// Sets whether undocumented instructions (e.g. `SALC`), and undocumented forms of documented
+    /// instructions (e.g. `AAM` with a base other than 10), may be executed. These work on real
+    /// processors and are relied upon by some older code, but are disabled by default, in which
+    /// case they raise a #UD exception.
+    pub fn set_undocumented_instructions(&mut self, enabled: bool) {
+        self.undocumented_instructions = enabled;
+    }
+
+    /// Registers a host handler which will service the interrupt `vector` whenever it is raised,
+    /// returning the handler it replaced (if any).
+    pub fn register_interrupt_handler(
+        &mut self,
+        vector: u8,
+        handler: impl FnMut(&mut Cpu) + 'static,
+    ) -> Option<InterruptHandler> {
+        self.interrupt_handlers.register(vector, Box::new(handler))
+    }
+
+    /// Removes the host handler for the interrupt `vector`, returning it (if any).
+    pub fn unregister_interrupt_handler(&mut self, vector: u8) -> Option<InterruptHandler> {
+        self.interrupt_handlers.unregister(vector)
+    }
+
+    /// Points the interrupt `vector` at the interrupt service routine at `segment:offset`, by
+    /// writing its entry in the interrupt vector table, or in protected mode, its gate in the IDT
+    /// (a 32-bit interrupt gate which `INT` may use at any privilege level). Any host handler for
+    /// the vector is removed, as it would otherwise take precedence. Returns an `Err` if the entry
+    /// is not in memory, if it is beyond the limit of the IDT, or if the offset does not fit in an
+    /// entry of the interrupt vector table.
+    pub fn set_interrupt_vector(
+        &mut self,
+        vector: u8,
+        segment: u16,
+        offset: u32,
+    ) -> Result<(), Error> {
+        if self.registers.control_registers.get_protection_enable() {
+            let gate = GateDescriptor {
+                selector: segment,
+                offset,
+                rights: AccessRights {
+                    kind: DescriptorKind::System(INTERRUPT_GATE_TYPE),
+                    dpl: 3,
+                },
+                present: true,
+            };
+            let entry = self.host_access(|cpu| cpu.gate_entry(vector, AccessKind::Write))?;
+            let Some(entry) = entry else {
+                return Err(Error::InaccessibleAddress(format!(
+                    "the gate for interrupt {vector:#04x} is beyond the limit of the IDT"
+                )));
+            };
+            self.memory.write64(entry, gate.into())?;
+        } else {
+            let offset = u16::try_from(offset).map_err(|_| {
+                Error::InaccessibleAddress(format!(
+                    "interrupt {vector:#04x} cannot be serviced at offset {offset:#x}, which does \
+                     not fit in 16 bits"
+                ))
+            })?;
+            let entry =
+                self.host_access(|cpu| cpu.interrupt_vector_entry(vector, AccessKind::Write))?;
+            self.memory.write16(entry, offset)?;
+            self.memory.write16(entry.offset(2), segment)?;
+        }
+        self.interrupt_handlers.unregister(vector);
+        Ok(())
+    }
+
+    /// What services the interrupt `vector`.
+    pub fn interrupt_vector(&self, vector: u8) -> InterruptVector {
+        self.host_access(|cpu| cpu.read_interrupt_vector(vector))
+    }
+
+    /// What services the interrupt `vector`, as it is delivered. If its entry in the interrupt
+    /// vector table (or its gate in the IDT) cannot be read, then the fault is latched and it is
+    /// treated as unset.
+    fn read_interrupt_vector(&self, vector: u8) -> InterruptVector {
+        if self.interrupt_handlers.contains(vector) {
+            return InterruptVector::Host;
+        }
+        if self.registers.control_registers.get_protection_enable() {
+            return match self.read_gate(vector) {
+                Ok(Some(gate)) => InterruptVector::Guest {
+                    segment: gate.selector,
+                    offset: gate.offset,
+                },
+                _ => InterruptVector::Unset,
+            };
+        }
+
+        let entry = match self.interrupt_vector_entry(vector, AccessKind::Read) {
+            Ok(entry) => entry,
+            Err(_) => return InterruptVector::Unset,
+        };
+        match (
+            self.memory.read16(entry),
+            self.memory.read16(entry.offset(2)),
+        ) {
+            (Ok(0), Ok(0)) | (Err(_), _) | (_, Err(_)) => InterruptVector::Unset,
+            (Ok(offset), Ok(segment)) => InterruptVector::Guest {
+                segment,
+                offset: offset.into(),
+            },
+        }
+    }
+
+    /// Intel manual section 6.10 "INTERRUPT DESCRIPTOR TABLE (IDT)".
+    /// Reads the gate for the interrupt `vector` from the IDT. `None` is returned if the gate is
+    /// beyond the limit of the IDT, or if it is null.
+    fn read_gate(&self, vector: u8) -> Result<Option<GateDescriptor>, Error> {
+        let Some(entry) = self.gate_entry(vector, AccessKind::Read)? else {
+            return Ok(None);
+        };
+        let raw = self.memory.read64(entry)?;
+        Ok((raw != 0).then(|| GateDescriptor::from(raw)))
+    }
+
+    /// The physical address of the gate for the interrupt `vector` in the IDT, which is translated
+    /// through the page tables as any other access. `None` is returned if the gate is beyond the
+    /// limit of the IDT.
+    fn gate_entry(&self, vector: u8, kind: AccessKind) -> Result<Option<PhysicalAddress>, Error> {
+        let offset = vector as u32 * DESCRIPTOR_SIZE;
+        if offset + DESCRIPTOR_SIZE - 1 > self.registers.idtr.limit as u32 {
+            return Ok(None);
+        }
+        let linear = self.registers.idtr.base.wrapping_add(offset);
+        self.physical_address(linear, DESCRIPTOR_SIZE, kind)
+            .map(Some)
+    }
+
+    /// The physical address of the entry for the interrupt `vector` in the interrupt vector table,
+    /// which is translated through the page tables as any other access.
+    fn interrupt_vector_entry(
+        &self,
+        vector: u8,
+        kind: AccessKind,
+    ) -> Result<PhysicalAddress, Error> {
+        let linear = self.registers.idtr.base.wrapping_add(vector as u32 * 4);
+        self.physical_address(linear, 4, kind)
+    }
+
+    /// Makes an access on behalf of the host, rather than of an instruction, such that a fault
+    /// which it latches is not raised by the next instruction to be executed.
+    fn host_access<T>(&self, access: impl FnOnce(&Self) -> T) -> T {
+        let (fault, page_fault) = (self.fault.get(), self.page_fault.get());
+        let result = access(self);
+        self.fault.set(fault);
+        self.page_fault.set(page_fault);
+        result
+    }
+
+    /// Sets the host closure which observes locked read-modify-write cycles, returning the
+    /// observer it replaced (if any).
+    pub fn set_locked_cycle_observer(
+        &mut self,
+        observer: impl FnMut(&mut Cpu, u32) + 'static,
+    ) -> Option<LockedCycleObserver> {
+        self.locked_cycle_observer.0.replace(Box::new(observer))
+    }
+
+    /// Removes the observer of locked read-modify-write cycles, returning it (if any).
+    pub fn remove_locked_cycle_observer(&mut self) -> Option<LockedCycleObserver> {
+        self.locked_cycle_observer.0.take()
+    }
+
+    /// Notifies the observer (if any) that a locked read-modify-write cycle has been performed on
+    /// the given address. The observer is removed while it is called, so that it can be given the
+    /// CPU, and is then put back unless it has been replaced in the meantime.
+    pub(crate) fn observe_locked_cycle(&mut self, address: u32) {
+        let Some(mut observer) = self.locked_cycle_observer.0.take() else {
+            return;
+        };
+        observer(self, address);
+        self.locked_cycle_observer.0.get_or_insert(observer);
+    }
+
+    /// Attaches a port-mapped device which will service `IN` and `OUT` instructions targeting the
+    /// given range of ports. Returns an `Err` if any of the ports are already in use.
+    pub fn attach_io_device(
+        &mut self,
+        ports: RangeInclusive<u16>,
+        device: Box<dyn PortDevice>,
+    ) -> Result<(), Error> {
+        self.io.attach(ports, device)
+    }
+
+    /// Replaces the source of the random numbers returned by `RDRAND`. By default, the numbers are
+    /// seeded differently on each run, so a deterministic source can be used for reproducibility.
+    pub fn set_entropy_source(&mut self, source: Box<dyn EntropySource>) {
+        self.random_number_generator.set_source(source);
+    }
+
+    /// Performs a single iteration of a string instruction, or repeats it as directed by the repeat
+    /// prefix applied to the current instruction. With any repeat prefix, the operation is repeated
+    /// until ECX (see `Cpu::string_count`) reaches 0, with it being decremented after each
+    /// iteration.
+    fn repeat_string_operation(
+        &mut self,
+        operation: fn(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.repeat_prefix.is_none() {
+            return operation(self);
+        }
+
+        while self.string_count() != 0 {
+            operation(self)?;
+            self.set_string_count(self.string_count().wrapping_sub(1));
+        }
+        Ok(())
+    }
+
+    /// Performs a repeated `MOVSB` or `STOSB` as a single bulk write to ES:EDI of the bytes which
+    /// `bytes` returns, given the number of bytes. This is only done when it is equivalent to
+    /// writing the bytes one at a time: DF is clear, paging is disabled, the destination is within
+    /// the limit of ES and does not run past the end of the address space, and `bytes` returns
+    /// them. Returns whether it was done, such that the instruction can otherwise be repeated
+    /// element by element, or an `Err` if the write faults.
+    fn repeat_string_block(
+        &mut self,
+        bytes: impl FnOnce(&Self, u32) -> Option<Vec<u8>>,
+    ) -> Result<bool, Error> {
+        let count = self.registers.get_ecx();
+        if self.repeat_prefix.is_none()
+            || self.registers.get_default_size() == Size::Word
+            || self.registers.debug_registers.get_dr7() & 0xff != 0
+            || self.registers.eflags.get_direction_flag()
+            || self.registers.control_registers.get_paging()
+            || count == 0
+        {
+            return Ok(false);
+        }
+        let Some(destination) =
+            self.checked_linear_address(SegmentRegister::Es, self.registers.edi, count)
+        else {
+            return Ok(false);
+        };
+        if destination.checked_add(count - 1).is_none() {
+            return Ok(false);
+        }
+        let Some(bytes) = bytes(self, count) else {
+            return Ok(false);
+        };
+
+        self.memory.write_bytes(destination, &bytes)?;
+        self.registers.edi = self.registers.edi.wrapping_add(count);
+        self.registers.set_ecx(0);
+        Ok(true)
+    }
+
+    /// Performs a single iteration of a string comparison (`CMPS` or `SCAS`), or repeats it as
+    /// directed by the repeat prefix applied to the current instruction. In addition to stopping
+    /// once ECX reaches 0, `REP`/`REPE` stop once ZF is clear (i.e. the elements differ), and
+    /// `REPNE` stops once ZF is set (i.e. the elements are equal).
+    fn repeat_string_comparison(
+        &mut self,
+        comparison: fn(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let Some(repeat_prefix) = self.repeat_prefix else {
+            return comparison(self);
+        };
+
+        let repeat_while_equal = repeat_prefix != RepeatPrefix::Repne;
+        while self.string_count() != 0 {
+            comparison(self)?;
+            self.set_string_count(self.string_count().wrapping_sub(1));
+            if self.registers.eflags.get_zero_flag() != repeat_while_equal {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The physical address of the source element of a string instruction, at DS:ESI, which is
+    /// always read. The segment may be overridden.
+    fn string_source(&self, size: Size) -> Result<PhysicalAddress, Error> {
+        let segment = self.segment_override.unwrap_or(SegmentRegister::Ds);
+        self.memory_address(
+            segment,
+            self.string_offset(self.registers.esi),
+            size as u32 / 8,
+            AccessKind::Read,
+        )
+    }
+
+    /// The physical address of the destination element of a string instruction, at ES:EDI. Unlike
+    /// the source, the segment cannot be overridden.
+    fn string_destination(&self, size: Size, kind: AccessKind) -> Result<PhysicalAddress, Error> {
+        self.memory_address(
+            SegmentRegister::Es,
+            self.string_offset(self.registers.edi),
+            size as u32 / 8,
+            kind,
+        )
+    }
+
+    /// The offset of an element of a string instruction within its segment, given the index
+    /// register (ESI or EDI) which refers to it. Only SI or DI is used when the default address
+    /// size is 16 bits.
+    fn string_offset(&self, index: u32) -> u32 {
+        match self.registers.get_default_size() {
+            Size::Word => index & 0xffff,
+            _ => index,
+        }
+    }
+
+    /// Steps the index register (ESI or EDI) of a string instruction on to the next element, which
+    /// is forwards when the DF flag is clear, and backwards when it is set. When the default
+    /// address size is 16 bits, only SI or DI is stepped, wrapping around within the segment.
+    fn next_string_index(&self, index: u32, size: Size) -> u32 {
+        let step = size as u32 / 8;
+        let next = if self.registers.eflags.get_direction_flag() {
+            index.wrapping_sub(step)
+        } else {
+            index.wrapping_add(step)
+        };
+        match self.registers.get_default_size() {
+            Size::Word => index & 0xffff_0000 | next & 0xffff,
+            _ => next,
+        }
+    }
+
+    /// The number of iterations which remain of a repeated string instruction, which is held in
+    /// ECX, or in CX when the default address size is 16 bits.
+    fn string_count(&self) -> u32 {
+        match self.registers.get_default_size() {
+            Size::Word => self.registers.get_cx() as u32,
+            _ => self.registers.get_ecx(),
+        }
+    }
+
+    fn set_string_count(&mut self, count: u32) {
+        match self.registers.get_default_size() {
+            Size::Word => self.registers.set_cx(count as u16),
+            _ => self.registers.set_ecx(count),
+        }
+    }
+
     /// Performs wrapping addition, adding the carry if required.
     fn wrapping_add<T>(&mut self, lhs: T, rhs: T, with_carry: WithCarry) -> T
     where
@@ -53,6 +699,109 @@ impl Cpu {
         result
     }
 
+    /// ASCII adjust after addition. Adjusts the sum of two unpacked BCD values in AL to create an
+    /// unpacked BCD result, carrying into AH if required. The AF and CF flags are set if there was
+    /// a decimal carry, and cleared otherwise. The OF, SF, ZF, and PF flags are undefined.
+    pub(crate) fn aaa(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let decimal_carry =
+            self.registers.get_al() & 0x0f > 9 || self.registers.eflags.get_auxiliary_carry_flag();
+        if decimal_carry {
+            self.registers
+                .set_ax(self.registers.get_ax().wrapping_add(0x106));
+        }
+        self.registers
+            .eflags
+            .set_auxiliary_carry_flag(decimal_carry);
+        self.registers.eflags.set_carry_flag(decimal_carry);
+        self.registers.set_al(self.registers.get_al() & 0x0f);
+        Ok(())
+    }
+
+    /// ASCII adjust before division. Converts the unpacked BCD value in AH:AL into a binary value
+    /// in AL, in preparation for a division, using `imm8` as the number base. AH is cleared. The
+    /// SF, ZF, and PF flags are set according to the result. The OF, AF, and CF flags are
+    /// undefined.
+    fn aad(&mut self, imm8: u8) {
+        let result = self
+            .registers
+            .get_al()
+            .wrapping_add(self.registers.get_ah().wrapping_mul(imm8));
+        self.registers.set_ax(result as u16);
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers.eflags.compute_parity_flag(result);
+    }
+
+    pub(crate) fn aad_base10(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.aad(10);
+        Ok(())
+    }
+
+    /// Only base 10 is documented, and other bases require undocumented instructions to be enabled.
+    pub(crate) fn aad_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let imm8 = unwrap_operands!(operands, Immediate8);
+        if imm8.0 != 10 && !self.undocumented() {
+            return Ok(());
+        }
+        self.aad(imm8.0);
+        Ok(())
+    }
+
+    /// ASCII adjust after multiplication. Splits the binary value in AL into two unpacked BCD
+    /// digits, using `imm8` as the number base, with the quotient stored in AH and the remainder
+    /// in AL. The SF, ZF, and PF flags are set according to the result. The OF, AF, and CF flags
+    /// are undefined. A base of 0 raises a #DE exception.
+    fn aam(&mut self, imm8: u8) {
+        if imm8 == 0 {
+            self.raise_exception(CpuException::DivideError);
+            return;
+        }
+
+        let al = self.registers.get_al();
+        self.registers.set_ah(al / imm8);
+        let result = al % imm8;
+        self.registers.set_al(result);
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers.eflags.compute_parity_flag(result);
+    }
+
+    pub(crate) fn aam_base10(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.aam(10);
+        Ok(())
+    }
+
+    /// Only base 10 is documented, and other bases require undocumented instructions to be enabled.
+    pub(crate) fn aam_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let imm8 = unwrap_operands!(operands, Immediate8);
+        if imm8.0 != 10 && !self.undocumented() {
+            return Ok(());
+        }
+        self.aam(imm8.0);
+        Ok(())
+    }
+
+    /// ASCII adjust after subtraction. Adjusts the difference of two unpacked BCD values in AL to
+    /// create an unpacked BCD result, borrowing from AH if required. The AF and CF flags are set
+    /// if there was a decimal borrow, and cleared otherwise. The OF, SF, ZF, and PF flags are
+    /// undefined.
+    pub(crate) fn aas(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let decimal_borrow =
+            self.registers.get_al() & 0x0f > 9 || self.registers.eflags.get_auxiliary_carry_flag();
+        if decimal_borrow {
+            self.registers
+                .set_ax(self.registers.get_ax().wrapping_sub(6));
+            self.registers
+                .set_ah(self.registers.get_ah().wrapping_sub(1));
+        }
+        self.registers
+            .eflags
+            .set_auxiliary_carry_flag(decimal_borrow);
+        self.registers.eflags.set_carry_flag(decimal_borrow);
+        self.registers.set_al(self.registers.get_al() & 0x0f);
+        Ok(())
+    }
+
     /// Add the two operands and carry together, wrapping if an overflow occurs, and set the
     /// OF, SF, ZF, AF, CF, and PF flags according to the result.
     fn adc<T>(&mut self, lhs: T, rhs: T) -> T
@@ -75,58 +824,102 @@ impl Cpu {
         result
     }
 
-    pub(crate) fn adc_al_imm8(&mut self, operands: &Operands) {
-        let (_al, imm8) = unwrap_operands!(operands, &Register8, &Immediate);
-        let result = self.adc(self.registers.get_al(), imm8.0 as u8);
+    pub(crate) fn adc_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let result = self.adc(self.registers.get_al(), imm8.0);
         self.registers.set_al(result);
+        Ok(())
     }
 
-    pub(crate) fn adc_ax_imm16(&mut self, operands: &Operands) {
-        let (_ax, imm16) = unwrap_operands!(operands, &Register16, &Immediate);
-        let result = self.adc(self.registers.get_ax(), imm16.0 as u16);
+    pub(crate) fn adc_ax_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, imm16) = unwrap_operands!(operands, &Register16, Immediate16);
+        let result = self.adc(self.registers.get_ax(), imm16.0);
         self.registers.set_ax(result);
+        Ok(())
     }
 
-    pub(crate) fn adc_eax_imm32(&mut self, operands: &Operands) {
-        let (_eax, imm32) = unwrap_operands!(operands, &Register32, &Immediate);
-        let result = self.adc(self.registers.get_eax(), imm32.0 as u32);
+    pub(crate) fn adc_eax_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, imm32) = unwrap_operands!(operands, &Register32, Immediate32);
+        let result = self.adc(self.registers.get_eax(), imm32.0);
         self.registers.set_eax(result);
+        Ok(())
     }
 
-    pub(crate) fn adc_reg8_rm8(&mut self, operands: &Operands) {
+    pub(crate) fn adc_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        let result = self.adc(reg8.read(&self.registers), rm8.read(self).unwrap());
+        let result = self.adc(reg8.read(&self.registers), rm8.read(self)?);
         self.registers.write8(&reg8, result);
+        Ok(())
     }
 
-    pub(crate) fn adc_reg16_rm16(&mut self, operands: &Operands) {
+    pub(crate) fn adc_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        let result = self.adc(reg16.read(&self.registers), rm16.read(self).unwrap());
+        let result = self.adc(reg16.read(&self.registers), rm16.read(self)?);
         self.registers.write16(&reg16, result);
+        Ok(())
     }
 
-    pub(crate) fn adc_reg32_rm32(&mut self, operands: &Operands) {
+    pub(crate) fn adc_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        let result = self.adc(self.registers.read32(reg32), rm32.read(self).unwrap());
+        let result = self.adc(self.registers.read32(reg32), rm32.read(self)?);
         self.registers.write32(&reg32, result);
+        Ok(())
+    }
+
+    pub(crate) fn adc_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.adc(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn adc_rm8_reg8(&mut self, operands: &Operands) {
+    pub(crate) fn adc_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        let result = self.adc(rm8.read(self).unwrap(), reg8.read(&self.registers));
-        rm8.write(self, result).unwrap();
+        let result = self.adc(rm8.read(self)?, reg8.read(&self.registers));
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn adc_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.adc(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn adc_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.adc(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn adc_rm16_reg16(&mut self, operands: &Operands) {
+    pub(crate) fn adc_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        let result = self.adc(rm16.read(self).unwrap(), reg16.read(&self.registers));
-        rm16.write(self, result).unwrap();
+        let result = self.adc(rm16.read(self)?, reg16.read(&self.registers));
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn adc_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.adc(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn adc_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.adc(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn adc_rm32_reg32(&mut self, operands: &Operands) {
+    pub(crate) fn adc_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        let result = self.adc(rm32.read(self).unwrap(), self.registers.read32(reg32));
-        rm32.write(self, result).unwrap();
+        let result = self.adc(rm32.read(self)?, self.registers.read32(reg32));
+        rm32.write(self, result)?;
+        Ok(())
     }
 
     /// Add the two operands together, wrapping if an overflow occurs, and set the OF, SF, ZF, AF,
@@ -151,64 +944,117 @@ impl Cpu {
         result
     }
 
-    pub(crate) fn add_al_imm8(&mut self, operands: &Operands) {
-        let (_al, imm8) = unwrap_operands!(operands, &Register8, &Immediate);
-        let result = self.add(self.registers.get_al(), imm8.0 as u8);
+    pub(crate) fn add_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let result = self.add(self.registers.get_al(), imm8.0);
         self.registers.set_al(result);
+        Ok(())
     }
 
-    pub(crate) fn add_ax_imm16(&mut self, operands: &Operands) {
-        let (_ax, imm16) = unwrap_operands!(operands, &Register16, &Immediate);
-        let result = self.add(self.registers.get_ax(), imm16.0 as u16);
+    pub(crate) fn add_ax_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, imm16) = unwrap_operands!(operands, &Register16, Immediate16);
+        let result = self.add(self.registers.get_ax(), imm16.0);
         self.registers.set_ax(result);
+        Ok(())
     }
 
-    pub(crate) fn add_eax_imm32(&mut self, operands: &Operands) {
-        let (_eax, imm32) = unwrap_operands!(operands, &Register32, &Immediate);
-        let result = self.add(self.registers.get_eax(), imm32.0 as u32);
+    pub(crate) fn add_eax_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, imm32) = unwrap_operands!(operands, &Register32, Immediate32);
+        let result = self.add(self.registers.get_eax(), imm32.0);
         self.registers.set_eax(result);
+        Ok(())
     }
 
-    pub(crate) fn add_reg8_rm8(&mut self, operands: &Operands) {
+    pub(crate) fn add_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        let result = self.add(reg8.read(&self.registers), rm8.read(self).unwrap());
+        let result = self.add(reg8.read(&self.registers), rm8.read(self)?);
         self.registers.write8(&reg8, result);
+        Ok(())
     }
 
-    pub(crate) fn add_reg16_rm16(&mut self, operands: &Operands) {
+    pub(crate) fn add_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        let result = self.add(reg16.read(&self.registers), rm16.read(self).unwrap());
+        let result = self.add(reg16.read(&self.registers), rm16.read(self)?);
         self.registers.write16(&reg16, result);
+        Ok(())
     }
 
-    pub(crate) fn add_reg32_rm32(&mut self, operands: &Operands) {
+    pub(crate) fn add_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        let result = self.add(self.registers.read32(reg32), rm32.read(self).unwrap());
+        let result = self.add(self.registers.read32(reg32), rm32.read(self)?);
         self.registers.write32(&reg32, result);
+        Ok(())
     }
 
-    pub(crate) fn add_rm8_imm8(&mut self, operands: &Operands) {
-        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, &Immediate);
-        let result = self.add(rm8.read(&self).unwrap(), imm8.0 as u8);
-        rm8.write(self, result).unwrap();
+    pub(crate) fn add_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.add(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn add_rm8_reg8(&mut self, operands: &Operands) {
+    pub(crate) fn add_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        let result = self.add(rm8.read(self).unwrap(), reg8.read(&self.registers));
-        rm8.write(self, result).unwrap();
+        let result = self.add(rm8.read(self)?, reg8.read(&self.registers));
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn add_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.add(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn add_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.add(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn add_rm16_reg16(&mut self, operands: &Operands) {
+    pub(crate) fn add_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        let result = self.add(rm16.read(self).unwrap(), reg16.read(&self.registers));
-        rm16.write(self, result).unwrap();
+        let result = self.add(rm16.read(self)?, reg16.read(&self.registers));
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn add_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.add(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn add_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.add(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn add_rm32_reg32(&mut self, operands: &Operands) {
+    pub(crate) fn add_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        let result = self.add(rm32.read(self).unwrap(), self.registers.read32(reg32));
-        rm32.write(self, result).unwrap();
+        let result = self.add(rm32.read(self)?, self.registers.read32(reg32));
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    /// Adds packed double-precision floating-point values.
+    pub(crate) fn addpd_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f64>(operands, ArithmeticOperation::Add)
+    }
+
+    /// Adds packed single-precision floating-point values.
+    pub(crate) fn addps_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f32>(operands, ArithmeticOperation::Add)
+    }
+
+    /// Adds the low double-precision floating-point values.
+    pub(crate) fn addsd_xmm_xmm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.scalar_double_arithmetic(operands, ArithmeticOperation::Add)
     }
 
     /// Performs a bitwise AND operation. Clears the OF and CF flags, and sets the SF, ZF, and PF
@@ -226,718 +1072,5684 @@ impl Cpu {
         result
     }
 
-    pub(crate) fn and_al_imm8(&mut self, operands: &Operands) {
-        let (_al, imm8) = unwrap_operands!(operands, &Register8, &Immediate);
-        let result = self.and(self.registers.get_al(), imm8.0 as u8);
+    pub(crate) fn and_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let result = self.and(self.registers.get_al(), imm8.0);
         self.registers.set_al(result);
+        Ok(())
     }
 
-    pub(crate) fn and_ax_imm16(&mut self, operands: &Operands) {
-        let (_ax, imm16) = unwrap_operands!(operands, &Register16, &Immediate);
-        let result = self.and(self.registers.get_ax(), imm16.0 as u16);
+    pub(crate) fn and_ax_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, imm16) = unwrap_operands!(operands, &Register16, Immediate16);
+        let result = self.and(self.registers.get_ax(), imm16.0);
         self.registers.set_ax(result);
+        Ok(())
     }
 
-    pub(crate) fn and_eax_imm32(&mut self, operands: &Operands) {
-        let (_eax, imm32) = unwrap_operands!(operands, &Register32, &Immediate);
-        let result = self.and(self.registers.get_eax(), imm32.0 as u32);
+    pub(crate) fn and_eax_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, imm32) = unwrap_operands!(operands, &Register32, Immediate32);
+        let result = self.and(self.registers.get_eax(), imm32.0);
         self.registers.set_eax(result);
+        Ok(())
     }
 
-    pub(crate) fn and_reg8_rm8(&mut self, operands: &Operands) {
+    pub(crate) fn and_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        let result = self.and(reg8.read(&self.registers), rm8.read(self).unwrap());
+        let result = self.and(reg8.read(&self.registers), rm8.read(self)?);
         self.registers.write8(reg8, result);
+        Ok(())
     }
 
-    pub(crate) fn and_reg16_rm16(&mut self, operands: &Operands) {
+    pub(crate) fn and_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        let result = self.and(reg16.read(&self.registers), rm16.read(self).unwrap());
+        let result = self.and(reg16.read(&self.registers), rm16.read(self)?);
         self.registers.write16(reg16, result);
+        Ok(())
     }
 
-    pub(crate) fn and_reg32_rm32(&mut self, operands: &Operands) {
+    pub(crate) fn and_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        let result = self.and(self.registers.read32(reg32), rm32.read(self).unwrap());
+        let result = self.and(self.registers.read32(reg32), rm32.read(self)?);
         self.registers.write32(reg32, result);
-        todo!()
+        Ok(())
+    }
+
+    pub(crate) fn and_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.and(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn and_rm8_reg8(&mut self, operands: &Operands) {
+    pub(crate) fn and_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        let result = self.and(rm8.read(self).unwrap(), reg8.read(&self.registers));
-        rm8.write(self, result).unwrap();
+        let result = self.and(rm8.read(self)?, reg8.read(&self.registers));
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn and_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.and(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn and_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.and(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn and_rm16_reg16(&mut self, operands: &Operands) {
+    pub(crate) fn and_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
         let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        let result = self.and(rm16.read(self).unwrap(), reg16.read(&self.registers));
-        rm16.write(self, result).unwrap();
+        let result = self.and(rm16.read(self)?, reg16.read(&self.registers));
+        rm16.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn and_rm32_reg32(&mut self, operands: &Operands) {
-        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        let result = self.and(rm32.read(self).unwrap(), reg32.read(&self.registers));
-        rm32.write(self, result).unwrap();
+    pub(crate) fn and_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.and(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn es(&mut self, operands: &Operands) {
-        todo!()
+    pub(crate) fn and_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.and(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn daa(&mut self, operands: &Operands) {
-        todo!()
+    pub(crate) fn and_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
+        let result = self.and(rm32.read(self)?, reg32.read(&self.registers));
+        rm32.write(self, result)?;
+        Ok(())
     }
 
-    pub(crate) fn lea_reg16_mem(&mut self, operands: &Operands) {
+    /// Checks that the signed index in the first operand is within the bounds held in memory by the
+    /// second operand, which are a lower bound followed by an upper bound (both inclusive). A #BR
+    /// exception is raised if the index is out of bounds.
+    pub(crate) fn bound_reg16_mem(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg16, mem) = unwrap_operands!(operands, &Register16, &EffectiveAddress);
-        self.registers.write16(reg16, mem.resolve(self) as u16);
+        let address = mem.translate(self, Size::Dword, AccessKind::Read)?;
+        let index = self.registers.read16(reg16) as i16;
+        let lower = self.memory.read16(address)? as i16;
+        let upper = self.memory.read16(address.offset(2))? as i16;
+        if index < lower || index > upper {
+            self.raise_exception(CpuException::BoundRangeExceeded);
+        }
+        Ok(())
     }
 
-    pub(crate) fn lea_reg32_mem(&mut self, operands: &Operands) {
+    pub(crate) fn bound_reg32_mem(&mut self, operands: &Operands) -> Result<(), Error> {
         let (reg32, mem) = unwrap_operands!(operands, &Register32, &EffectiveAddress);
-        self.registers.write32(reg32, mem.resolve(self));
+        let address = mem.translate(self, Size::Qword, AccessKind::Read)?;
+        let index = self.registers.read32(reg32) as i32;
+        let lower = self.memory.read32(address)? as i32;
+        let upper = self.memory.read32(address.offset(4))? as i32;
+        if index < lower || index > upper {
+            self.raise_exception(CpuException::BoundRangeExceeded);
+        }
+        Ok(())
     }
 
-    pub(crate) fn mov_rm8_reg8(&mut self, operands: &Operands) {
-        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        rm8.write(self, reg8.read(&self.registers)).unwrap();
-    }
-    pub(crate) fn mov_rm16_reg16(&mut self, operands: &Operands) {
-        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        rm16.write(self, reg16.read(&self.registers)).unwrap();
-    }
-    pub(crate) fn mov_rm32_reg32(&mut self, operands: &Operands) {
-        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        rm32.write(self, reg32.read(&self.registers)).unwrap();
-    }
-    pub(crate) fn mov_reg8_rm8(&mut self, operands: &Operands) {
-        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        self.registers.write8(reg8, rm8.read(self).unwrap());
+    /// Calls the procedure at the target address, pushing the address of the next instruction (as
+    /// held in EIP) onto the stack as the return address. As in assembly source, the operand is
+    /// the target address itself, rather than the displacement to it from the next instruction.
+    /// With a 16-bit default operand size, only IP is pushed.
+    pub(crate) fn call_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rel32 = unwrap_operands!(operands, &Immediate);
+        match self.registers.get_default_size() {
+            Size::Word => self.push16(self.registers.get_eip() as u16)?,
+            _ => self.push32(self.registers.get_eip())?,
+        }
+        self.branch(rel32.0);
+        Ok(())
     }
-    pub(crate) fn mov_reg16_rm16(&mut self, operands: &Operands) {
-        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        self.registers.write16(reg16, rm16.read(self).unwrap());
+
+    /// Calls a procedure in another code segment by pushing CS and EIP, or switches to another task
+    /// and nests it within the running one (see `Cpu::far_transfer`).
+    pub(crate) fn call_far32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let far_pointer = unwrap_operands!(operands, &FarPointer);
+        self.far_transfer(far_pointer, TaskSwitch::Call)
     }
-    pub(crate) fn mov_reg32_rm32(&mut self, operands: &Operands) {
-        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        self.registers.write32(reg32, rm32.read(self).unwrap());
+
+    /// Continues execution at the target of a near branch. With a 16-bit default operand size, the
+    /// target is truncated to IP, i.e. the high WORD of EIP is cleared.
+    fn branch(&mut self, target: u32) {
+        match self.registers.get_default_size() {
+            Size::Word => self.registers.set_eip(target & 0xffff),
+            _ => self.registers.set_eip(target),
+        }
     }
 
-    /// Performs a bitwise inclusive OR operation. The OF and CF flags are cleared, and the SF, ZF,
-    /// and PF flags are set according to the result. The AF flag is undefined.
-    fn or<T>(&mut self, lhs: T, rhs: T) -> T
+    /// Compares the operands by subtracting the second operand from the first, and setting the OF,
+    /// SF, ZF, AF, PF, and CF flags as `SUB` would. The result is discarded.
+    fn cmp<T>(&mut self, lhs: T, rhs: T)
     where
-        T: PrimInt + BitOr<T> + AsUnsigned + FromPrimitive,
+        T: PrimInt + WrappingSub + AsUnsigned + FromPrimitive,
     {
-        let result = lhs | rhs;
-        self.registers.eflags.set_overflow_flag(false);
-        self.registers.eflags.set_carry_flag(false);
-        self.registers.eflags.compute_sign_flag(result);
-        self.registers.eflags.compute_zero_flag(result);
-        self.registers.eflags.compute_parity_flag(result);
-        result
-    }
-    pub(crate) fn or_al_imm8(&mut self, operands: &Operands) {
-        let (_al, imm8) = unwrap_operands!(operands, &Register8, &Immediate);
-        let result = self.or(self.registers.get_al(), imm8.0 as u8);
-        self.registers.set_al(result);
+        self.sub(lhs, rhs);
     }
 
-    pub(crate) fn or_ax_imm16(&mut self, operands: &Operands) {
-        let (_ax, imm16) = unwrap_operands!(operands, &Register16, &Immediate);
-        let result = self.or(self.registers.get_ax(), imm16.0 as u16);
-        self.registers.set_ax(result);
+    pub(crate) fn cmp_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        self.cmp(rm8.read(self)?, imm8.0);
+        Ok(())
     }
 
-    pub(crate) fn or_eax_imm32(&mut self, operands: &Operands) {
-        let (_eax, imm32) = unwrap_operands!(operands, &Register32, &Immediate);
-        let result = self.or(self.registers.get_eax(), imm32.0 as u32);
-        self.registers.set_eax(result);
+    pub(crate) fn cmp_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        self.cmp(rm16.read(self)?, imm8.0 as i8 as u16);
+        Ok(())
     }
 
-    pub(crate) fn or_reg8_rm8(&mut self, operands: &Operands) {
-        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        let result = self.or(reg8.read(&self.registers), rm8.read(self).unwrap());
-        self.registers.write8(reg8, result);
+    pub(crate) fn cmp_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        self.cmp(rm16.read(self)?, imm16.0);
+        Ok(())
     }
 
-    pub(crate) fn or_reg16_rm16(&mut self, operands: &Operands) {
-        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        let result = self.or(reg16.read(&self.registers), rm16.read(self).unwrap());
-        self.registers.write16(reg16, result);
+    pub(crate) fn cmp_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        self.cmp(rm32.read(self)?, imm8.0 as i8 as u32);
+        Ok(())
     }
 
-    pub(crate) fn or_reg32_rm32(&mut self, operands: &Operands) {
-        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        let result = self.or(self.registers.read32(reg32), rm32.read(self).unwrap());
-        self.registers.write32(reg32, result);
+    pub(crate) fn cmp_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        self.cmp(rm32.read(self)?, imm32.0);
+        Ok(())
     }
 
-    pub(crate) fn or_rm8_reg8(&mut self, operands: &Operands) {
-        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        let result = self.or(rm8.read(self).unwrap(), reg8.read(&self.registers));
-        rm8.write(self, result).unwrap();
+    /// Compares the element at [ESI] with the element at [EDI] by subtracting the latter from the
+    /// former, setting the flags as `CMP` would.
+    pub(crate) fn cmpsb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_comparison(|cpu| {
+            let lhs = cpu
+                .string_source(Size::Byte)
+                .and_then(|address| cpu.memory.read8(address))?;
+            let rhs = cpu
+                .string_destination(Size::Byte, AccessKind::Read)
+                .and_then(|address| cpu.memory.read8(address))?;
+            cpu.cmp(lhs, rhs);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Byte);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Byte);
+            Ok(())
+        })
     }
 
-    pub(crate) fn or_rm16_reg16(&mut self, operands: &Operands) {
-        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        let result = self.or(rm16.read(self).unwrap(), reg16.read(&self.registers));
-        rm16.write(self, result).unwrap();
+    pub(crate) fn cmpsw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_comparison(|cpu| {
+            let lhs = cpu
+                .string_source(Size::Word)
+                .and_then(|address| cpu.memory.read16(address))?;
+            let rhs = cpu
+                .string_destination(Size::Word, AccessKind::Read)
+                .and_then(|address| cpu.memory.read16(address))?;
+            cpu.cmp(lhs, rhs);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Word);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Word);
+            Ok(())
+        })
     }
 
-    pub(crate) fn or_rm32_reg32(&mut self, operands: &Operands) {
-        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        let result = self.or(rm32.read(self).unwrap(), self.registers.read32(reg32));
-        rm32.write(self, result).unwrap();
+    pub(crate) fn cmpsd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_comparison(|cpu| {
+            let lhs = cpu
+                .string_source(Size::Dword)
+                .and_then(|address| cpu.memory.read32(address))?;
+            let rhs = cpu
+                .string_destination(Size::Dword, AccessKind::Read)
+                .and_then(|address| cpu.memory.read32(address))?;
+            cpu.cmp(lhs, rhs);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Dword);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Dword);
+            Ok(())
+        })
     }
 
-    /// Pops a 16-bit (WORD) value off the stack, adjusting the stack pointer as required. Panics
-    /// if 16-bit value cannot be read from the location in memory pointed to by ESP.
-    fn pop16(&mut self) -> u16 {
-        self.registers.shrink_stack(&Size::Word);
-        self.memory.read16(self.registers.esp).unwrap()
+    /// Compares packed single-precision floating-point values using the predicate in the immediate
+    /// operand (see `Sse::compare`). Each result is written as a mask of all 1s (true) or all 0s
+    /// (false).
+    pub(crate) fn cmpps_xmm_xmm128_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_, _, imm8) =
+            unwrap_operands!(operands, &XmmRegister, XmmRegisterOrMemory128, Immediate8);
+        let predicate = imm8.0;
+        self.packed(operands, |sse, lhs: f32, rhs| {
+            let (result, exceptions) = sse.compare(predicate, lhs, rhs);
+            let mask = if result { u128::MAX } else { 0 };
+            (mask, exceptions)
+        })
     }
 
-    /// Pops a 32-bit (DWORD) value off the stack, adjusting the stack pointer as required. Panics
-    /// if 32-bit value cannot be read from the location in memory pointed to by ESP.
-    fn pop32(&mut self) -> u32 {
-        self.registers.shrink_stack(&Size::Dword);
-        self.memory.read32(self.registers.esp).unwrap()
-    }
+    /// Clears the task-switched (TS) flag in CR0. This is a privileged instruction.
+    pub(crate) fn clts(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if !self.privileged() {
+            return Ok(());
+        }
 
-    pub(crate) fn pop_ds(&mut self, _operands: &Operands) {
-        self.registers.ds = self.pop16();
+        self.registers.control_registers.set_task_switched(false);
+        Ok(())
     }
 
-    pub(crate) fn pop_es(&mut self, _operands: &Operands) {
-        self.registers.es = self.pop16();
+    /// Overrides the segment used by the memory operand of the next instruction. This is the effect
+    /// of the segment-override prefixes, which are written as separate instructions.
+    fn override_segment(&mut self, segment: SegmentRegister) {
+        self.pending_segment_override = Some(segment);
     }
 
-    pub(crate) fn pop_ss(&mut self, _operands: &Operands) {
-        self.registers.ss = self.pop16();
+    pub(crate) fn cs(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.override_segment(SegmentRegister::Cs);
+        Ok(())
     }
 
-    pub(crate) fn pop_reg16(&mut self, operands: &Operands) {
-        let reg16 = unwrap_operands!(operands, &Register16);
-        let popped = self.pop16();
-        reg16.write(&mut self.registers, popped);
+    pub(crate) fn ds(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.override_segment(SegmentRegister::Ds);
+        Ok(())
     }
 
-    pub(crate) fn pop_reg32(&mut self, operands: &Operands) {
-        let reg32 = unwrap_operands!(operands, &Register32);
-        let popped = self.pop32();
-        reg32.write(&mut self.registers, popped);
+    pub(crate) fn es(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.override_segment(SegmentRegister::Es);
+        Ok(())
     }
 
-    /// Pushes a 16-bit (WORD) value onto the stack, adjusting the stack pointer as required. Panics
-    /// if a 16-bit value cannot be written into memory at the index pointed to by ESP.
-    fn push16(&mut self, value: u16) {
-        self.registers.grow_stack(&Size::Word);
-        self.memory.write16(self.registers.esp, value).unwrap();
+    pub(crate) fn fs(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.override_segment(SegmentRegister::Fs);
+        Ok(())
     }
 
-    /// Pushes a 32-bit (DWORD) value onto the stack, adjusting the stack pointer as required.
-    /// Panics if a 32-bit value cannot be written into memory at the index pointed to by ESP.
-    fn push32(&mut self, value: u32) {
-        self.registers.grow_stack(&Size::Dword);
-        self.memory.write32(self.registers.esp, value).unwrap();
+    pub(crate) fn gs(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.override_segment(SegmentRegister::Gs);
+        Ok(())
     }
 
-    pub(crate) fn push_cs(&mut self, _operands: &Operands) {
-        self.push16(self.registers.cs);
+    pub(crate) fn ss(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.override_segment(SegmentRegister::Ss);
+        Ok(())
     }
 
-    pub(crate) fn push_ds(&mut self, _operands: &Operands) {
-        self.push16(self.registers.ds);
-    }
+    /// Decimal adjust after addition. Adjusts the sum of two packed BCD values in AL to create a
+    /// packed BCD result. The AF and CF flags are set if there was a decimal carry out of the low
+    /// and high digits respectively. The SF, ZF, and PF flags are set according to the result. The
+    /// OF flag is undefined.
+    pub(crate) fn daa(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let old_al = self.registers.get_al();
+        let old_carry_flag = self.registers.eflags.get_carry_flag();
+        self.registers.eflags.set_carry_flag(false);
 
-    pub(crate) fn push_es(&mut self, _operands: &Operands) {
-        self.push16(self.registers.es);
-    }
+        if old_al & 0x0f > 9 || self.registers.eflags.get_auxiliary_carry_flag() {
+            let (al, carry) = old_al.overflowing_add(6);
+            self.registers.set_al(al);
+            self.registers
+                .eflags
+                .set_carry_flag(old_carry_flag || carry);
+            self.registers.eflags.set_auxiliary_carry_flag(true);
+        } else {
+            self.registers.eflags.set_auxiliary_carry_flag(false);
+        }
 
-    pub(crate) fn push_ss(&mut self, _operands: &Operands) {
-        self.push16(self.registers.ss);
-    }
+        if old_al > 0x99 || old_carry_flag {
+            self.registers
+                .set_al(self.registers.get_al().wrapping_add(0x60));
+            self.registers.eflags.set_carry_flag(true);
+        } else {
+            self.registers.eflags.set_carry_flag(false);
+        }
 
-    pub(crate) fn push_reg16(&mut self, operands: &Operands) {
-        let reg16 = unwrap_operands!(operands, &Register16);
-        self.push16(reg16.read(&self.registers));
+        let result = self.registers.get_al();
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers.eflags.compute_parity_flag(result);
+        Ok(())
     }
 
-    pub(crate) fn push_reg32(&mut self, operands: &Operands) {
-        let reg32 = unwrap_operands!(operands, &Register32);
-        self.push32(reg32.read(&self.registers));
-    }
+    /// Decimal adjust after subtraction. Adjusts the difference of two packed BCD values in AL to
+    /// create a packed BCD result. The AF and CF flags are set if there was a decimal borrow into
+    /// the low and high digits respectively. The SF, ZF, and PF flags are set according to the
+    /// result. The OF flag is undefined.
+    pub(crate) fn das(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let old_al = self.registers.get_al();
+        let old_carry_flag = self.registers.eflags.get_carry_flag();
+        self.registers.eflags.set_carry_flag(false);
 
-    /// Integer subtraction with borrow. Adds the source and the carry flag, and subtracts the
-    /// result from the destination. Sets the OF, SF, ZF, AF, PF, and CF flags according to the
-    /// result.
-    // TODO: Test
-    fn sbb<T>(&mut self, lhs: T, rhs: T) -> T
-    where
-        T: PrimInt + WrappingSub + AsUnsigned + FromPrimitive,
-    {
-        let result = self.wrapping_sub(lhs, rhs, WithCarry::True);
-        self.registers
-            .eflags
-            .compute_overflow_flag(lhs, rhs, result, Operation::Subtract);
+        if old_al & 0x0f > 9 || self.registers.eflags.get_auxiliary_carry_flag() {
+            let (al, borrow) = old_al.overflowing_sub(6);
+            self.registers.set_al(al);
+            self.registers
+                .eflags
+                .set_carry_flag(old_carry_flag || borrow);
+            self.registers.eflags.set_auxiliary_carry_flag(true);
+        } else {
+            self.registers.eflags.set_auxiliary_carry_flag(false);
+        }
+
+        if old_al > 0x99 || old_carry_flag {
+            self.registers
+                .set_al(self.registers.get_al().wrapping_sub(0x60));
+            self.registers.eflags.set_carry_flag(true);
+        }
+
+        let result = self.registers.get_al();
         self.registers.eflags.compute_sign_flag(result);
         self.registers.eflags.compute_zero_flag(result);
-        self.registers
-            .eflags
-            .compute_auxiliary_carry_flag(lhs, rhs, Operation::Subtract);
         self.registers.eflags.compute_parity_flag(result);
-        self.registers
-            .eflags
-            .compute_carry_flag(lhs, rhs, result, Operation::Subtract);
-        result
+        Ok(())
     }
 
-    pub(crate) fn sbb_al_imm8(&mut self, operands: &Operands) {
-        let (_al, imm8) = unwrap_operands!(operands, &Register8, &Immediate);
-        let result = self.sbb(self.registers.get_al(), imm8.0 as u8);
-        self.registers.set_al(result);
+    /// Divides packed double-precision floating-point values.
+    pub(crate) fn divpd_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f64>(operands, ArithmeticOperation::Divide)
     }
 
-    pub(crate) fn sbb_ax_imm16(&mut self, operands: &Operands) {
-        let (_ax, imm16) = unwrap_operands!(operands, &Register16, &Immediate);
-        let result = self.sbb(self.registers.get_ax(), imm16.0 as u16);
-        self.registers.set_ax(result);
+    /// Divides packed single-precision floating-point values.
+    pub(crate) fn divps_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f32>(operands, ArithmeticOperation::Divide)
     }
 
-    pub(crate) fn sbb_eax_imm32(&mut self, operands: &Operands) {
-        let (_eax, imm32) = unwrap_operands!(operands, &Register32, &Immediate);
-        let result = self.sbb(self.registers.get_eax(), imm32.0 as u32);
-        self.registers.set_eax(result);
+    /// Divides the low double-precision floating-point values.
+    pub(crate) fn divsd_xmm_xmm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.scalar_double_arithmetic(operands, ArithmeticOperation::Divide)
     }
 
-    pub(crate) fn sbb_reg8_rm8(&mut self, operands: &Operands) {
-        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        let result = self.sbb(reg8.read(&self.registers), rm8.read(self).unwrap());
-        self.registers.write8(reg8, result);
+    /// Empties the MMX state by marking every x87 FPU data register as empty. This must be executed
+    /// at the end of MMX code, before any x87 FPU instructions are used.
+    pub(crate) fn emms(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.fpu.empty_mmx_state();
+        Ok(())
     }
 
-    pub(crate) fn sbb_reg16_rm16(&mut self, operands: &Operands) {
-        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        let result = self.sbb(reg16.read(&self.registers), rm16.read(self).unwrap());
-        self.registers.write16(reg16, result);
+    /// Loads the x87 FPU control word from memory, which changes the exception masks, precision
+    /// control, and rounding mode.
+    pub(crate) fn fldcw_mem16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem16 = unwrap_operands!(operands, &EffectiveAddress);
+        let control_word = mem16
+            .translate(self, Size::Word, AccessKind::Read)
+            .and_then(|address| self.memory.read16(address))?;
+        self.fpu.set_control_word(control_word);
+        Ok(())
     }
 
-    pub(crate) fn sbb_reg32_rm32(&mut self, operands: &Operands) {
-        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        let result = self.sbb(self.registers.read32(reg32), rm32.read(self).unwrap());
-        self.registers.write32(reg32, result);
+    /// Initializes the x87 FPU to its default state. See `Fpu::initialize`.
+    pub(crate) fn fninit(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.fpu.initialize();
+        Ok(())
     }
 
-    pub(crate) fn sbb_rm8_reg8(&mut self, operands: &Operands) {
-        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        let result = self.sbb(rm8.read(self).unwrap(), reg8.read(&self.registers));
-        rm8.write(self, result).unwrap();
+    /// Stores the x87 FPU control word to memory.
+    pub(crate) fn fnstcw_mem16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem16 = unwrap_operands!(operands, &EffectiveAddress);
+        mem16
+            .translate(self, Size::Word, AccessKind::Write)
+            .and_then(|address| self.memory.write16(address, self.fpu.get_control_word()))?;
+        Ok(())
     }
 
-    pub(crate) fn sbb_rm16_reg16(&mut self, operands: &Operands) {
-        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        let result = self.sbb(rm16.read(self).unwrap(), reg16.read(&self.registers));
-        rm16.write(self, result).unwrap();
+    /// Stores the x87 FPU status word in AX. This is typically followed by `SAHF`, so that the
+    /// condition codes (C0, C2, and C3) can be tested with conditional jumps (as CF, PF, and ZF).
+    pub(crate) fn fnstsw_ax(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.registers.set_ax(self.fpu.get_status_word());
+        Ok(())
     }
 
-    pub(crate) fn sbb_rm32_reg32(&mut self, operands: &Operands) {
-        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        let result = self.sbb(rm32.read(self).unwrap(), self.registers.read32(reg32));
-        rm32.write(self, result).unwrap();
+    /// Stores the x87 FPU status word to memory.
+    pub(crate) fn fnstsw_mem16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem16 = unwrap_operands!(operands, &EffectiveAddress);
+        mem16
+            .translate(self, Size::Word, AccessKind::Write)
+            .and_then(|address| self.memory.write16(address, self.fpu.get_status_word()))?;
+        Ok(())
     }
 
-    /// Integer subtraction. Adds the source and the carry flag, and subtracts the result from the
-    /// destination. Sets the OF, SF, ZF, AF, PF, and CF flags according to the result.
-    fn sub<T>(&mut self, lhs: T, rhs: T) -> T
-    where
-        T: PrimInt + WrappingSub + AsUnsigned + FromPrimitive,
-    {
-        let result = self.wrapping_sub(lhs, rhs, WithCarry::False);
-        self.registers
-            .eflags
-            .compute_overflow_flag(lhs, rhs, result, Operation::Subtract);
-        self.registers.eflags.compute_sign_flag(result);
-        self.registers.eflags.compute_zero_flag(result);
-        self.registers
-            .eflags
-            .compute_auxiliary_carry_flag(lhs, rhs, Operation::Subtract);
-        self.registers.eflags.compute_parity_flag(result);
-        self.registers
-            .eflags
-            .compute_carry_flag(lhs, rhs, result, Operation::Subtract);
-        result
+    pub(crate) fn in_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let value = self.io.read8(imm8.0 as u16);
+        self.registers.set_al(value);
+        Ok(())
     }
 
-    pub(crate) fn sub_al_imm8(&mut self, operands: &Operands) {
-        let (_al, imm8) = unwrap_operands!(operands, &Register8, &Immediate);
-        let result = self.sub(self.registers.get_al(), imm8.0 as u8);
-        self.registers.set_al(result);
+    pub(crate) fn in_ax_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_, imm8) = unwrap_operands!(operands, &Register16, Immediate8);
+        let value = self.io.read16(imm8.0 as u16);
+        self.registers.set_ax(value);
+        Ok(())
     }
 
-    pub(crate) fn sub_ax_imm16(&mut self, operands: &Operands) {
-        let (_ax, imm16) = unwrap_operands!(operands, &Register16, &Immediate);
-        let result = self.sub(self.registers.get_ax(), imm16.0 as u16);
-        self.registers.set_ax(result);
+    pub(crate) fn in_eax_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_, imm8) = unwrap_operands!(operands, &Register32, Immediate8);
+        let value = self.io.read32(imm8.0 as u16);
+        self.registers.set_eax(value);
+        Ok(())
     }
 
-    pub(crate) fn sub_eax_imm32(&mut self, operands: &Operands) {
-        let (_eax, imm32) = unwrap_operands!(operands, &Register32, &Immediate);
-        let result = self.sub(self.registers.get_eax(), imm32.0 as u32);
-        self.registers.set_eax(result);
+    pub(crate) fn in_al_dx(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let value = self.io.read8(self.registers.get_dx());
+        self.registers.set_al(value);
+        Ok(())
     }
 
-    pub(crate) fn sub_reg8_rm8(&mut self, operands: &Operands) {
-        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
-        let result = self.sub(reg8.read(&self.registers), rm8.read(self).unwrap());
-        self.registers.write8(reg8, result);
+    pub(crate) fn in_ax_dx(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let value = self.io.read16(self.registers.get_dx());
+        self.registers.set_ax(value);
+        Ok(())
     }
 
-    pub(crate) fn sub_reg16_rm16(&mut self, operands: &Operands) {
-        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
-        let result = self.sub(reg16.read(&self.registers), rm16.read(self).unwrap());
-        self.registers.write16(reg16, result);
+    pub(crate) fn in_eax_dx(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let value = self.io.read32(self.registers.get_dx());
+        self.registers.set_eax(value);
+        Ok(())
     }
 
-    pub(crate) fn sub_reg32_rm32(&mut self, operands: &Operands) {
-        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
-        let result = self.sub(self.registers.read32(reg32), rm32.read(self).unwrap());
-        self.registers.write32(reg32, result);
+    pub(crate) fn insb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu.io.read8(cpu.registers.get_dx());
+            cpu.string_destination(Size::Byte, AccessKind::Write)
+                .and_then(|address| cpu.memory.write8(address, value))?;
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Byte);
+            Ok(())
+        })
     }
 
-    pub(crate) fn sub_rm8_reg8(&mut self, operands: &Operands) {
-        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
-        let result = self.sub(rm8.read(self).unwrap(), reg8.read(&self.registers));
-        rm8.write(self, result).unwrap();
+    pub(crate) fn insw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu.io.read16(cpu.registers.get_dx());
+            cpu.string_destination(Size::Word, AccessKind::Write)
+                .and_then(|address| cpu.memory.write16(address, value))?;
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Word);
+            Ok(())
+        })
     }
 
-    pub(crate) fn sub_rm16_reg16(&mut self, operands: &Operands) {
-        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
-        let result = self.sub(rm16.read(self).unwrap(), reg16.read(&self.registers));
-        rm16.write(self, result).unwrap();
+    pub(crate) fn insd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu.io.read32(cpu.registers.get_dx());
+            cpu.string_destination(Size::Dword, AccessKind::Write)
+                .and_then(|address| cpu.memory.write32(address, value))?;
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Dword);
+            Ok(())
+        })
     }
 
-    pub(crate) fn sub_rm32_reg32(&mut self, operands: &Operands) {
-        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
-        let result = self.sub(rm32.read(self).unwrap(), reg32.read(&self.registers));
-        rm32.write(self, result).unwrap();
-    }
-}
+    /// Raises the interrupt `vector`. If a host handler has been registered for the vector, then it
+    /// services the interrupt. Otherwise, in protected mode, the interrupt is delivered through its
+    /// gate in the IDT (see `Cpu::interrupt_through_gate`). In real-address mode, FLAGS, CS, and
+    /// IP are pushed onto the stack, the IF, TF, and AC flags are cleared, and execution continues
+    /// at the far pointer read from the vector's entry in the interrupt vector table (see
+    /// `Cpu::interrupt_vector`), even if it is null.
+    pub(crate) fn interrupt(&mut self, vector: u8, kind: InterruptKind) -> Result<(), Error> {
+        let (segment, offset) = match self.read_interrupt_vector(vector) {
+            InterruptVector::Host => {
+                InterruptHandlers::call(self, vector);
+                return Ok(());
+            }
+            _ if self.registers.control_registers.get_protection_enable() => {
+                return self.interrupt_through_gate(vector, kind);
+            }
+            InterruptVector::Guest { segment, offset } => (segment, offset),
+            InterruptVector::Unset => (0, 0),
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::instruction::{NasmStr, Operand};
+        self.push16(self.registers.eflags.get_value() as u16)?;
+        self.push16(self.registers.cs)?;
+        self.push16(self.registers.get_eip() as u16)?;
+        self.registers.eflags.set_interrupt_enable_flag(false);
+        self.registers.eflags.set_trap_flag(false);
+        self.registers.eflags.set_alignment_check(false);
+        self.registers.set_eip(offset);
+        self.registers.load_segment(SegmentRegister::Cs, segment);
+        Ok(())
+    }
 
-    macro_rules! assert_eflags {
-        (@ $cpu:ident, CF=$expected:literal) => {
-            assert_eq!($cpu.registers.eflags.get_carry_flag(), $expected, "CF is incorrect")
-        };
-        (@ $cpu:ident, PF=$expected:literal) => {
-            assert_eq!($cpu.registers.eflags.get_parity_flag(), $expected, "PF is incorrect")
-        };
-        (@ $cpu:ident, AF=$expected:literal) => {
-            assert_eq!(
-                $cpu.registers.eflags.get_auxiliary_carry_flag(),
-                $expected,
-                "AF is incorrect"
-            )
+    /// Intel manual section 6.12 "EXCEPTION AND INTERRUPT HANDLING".
+    /// Delivers the interrupt `vector` through its gate in the IDT. A task gate switches to the
+    /// task of its TSS, nesting it within the running task (as a far `CALL` would). An interrupt or
+    /// trap gate calls the handler in the gate's code segment, at the same or an inner privilege
+    /// level. Moving to an inner level switches to the handler's stack, as read from the TSS, and
+    /// pushes the interrupted SS and ESP onto it. EFLAGS, CS, EIP, and the error code (if any) are
+    /// then pushed, as DWORDs through a 32-bit gate or as WORDs through a 16-bit gate, such that
+    /// `IRETD` (or `IRET`) returns to the interrupted code. The TF, NT, RF, and VM flags are
+    /// cleared, as is IF through an interrupt gate. If the gate or its code segment cannot be used,
+    /// then a #GP exception is latched (or #NP if it is not present, or #TS if the TSS does not
+    /// hold a usable stack), and nothing is delivered.
+    fn interrupt_through_gate(&mut self, vector: u8, kind: InterruptKind) -> Result<(), Error> {
+        let cpl = self.registers.get_cpl() as u8;
+        let Some(gate) = self.read_gate(vector)? else {
+            self.latch_fault(CpuException::GeneralProtection);
+            return Ok(());
         };
-        (@ $cpu:ident, ZF=$expected:literal) => {
-            assert_eq!($cpu.registers.eflags.get_zero_flag(), $expected, "ZF is incorrect")
+        let DescriptorKind::System(
+            gate_type @ (TASK_GATE_TYPE
+            | INTERRUPT_GATE_16_TYPE
+            | TRAP_GATE_16_TYPE
+            | INTERRUPT_GATE_TYPE
+            | TRAP_GATE_TYPE),
+        ) = gate.rights.kind
+        else {
+            self.latch_fault(CpuException::GeneralProtection);
+            return Ok(());
         };
-        (@ $cpu:ident, SF=$expected:literal) => {
-            assert_eq!($cpu.registers.eflags.get_sign_flag(), $expected, "SF is incorrect")
+        if kind == InterruptKind::Software && gate.rights.dpl < cpl {
+            self.latch_fault(CpuException::GeneralProtection);
+            return Ok(());
+        }
+        if !gate.present {
+            self.latch_fault(CpuException::SegmentNotPresent);
+            return Ok(());
+        }
+        if gate_type == TASK_GATE_TYPE {
+            self.switch_task(Selector(gate.selector), TaskSwitch::Call)?;
+            if let (None, InterruptKind::Exception(Some(error_code))) = (self.fault.get(), kind) {
+                self.push32(error_code)?;
+            }
+            return Ok(());
+        }
+
+        let selector = Selector(gate.selector);
+        let descriptor = (!selector.is_null())
+            .then(|| self.read_descriptor(selector))
+            .flatten();
+        let (descriptor, conforming) = match descriptor {
+            Some(
+                descriptor @ SegmentDescriptor {
+                    rights:
+                        AccessRights {
+                            kind: DescriptorKind::Code { conforming, .. },
+                            dpl,
+                        },
+                    ..
+                },
+            ) if dpl <= cpl => (descriptor, conforming),
+            _ => {
+                self.latch_fault(CpuException::GeneralProtection);
+                return Ok(());
+            }
         };
-        (@ $cpu:ident, OF=$expected:literal) => {
-            assert_eq!($cpu.registers.eflags.get_overflow_flag(), $expected, "OF is incorrect")
+        if !descriptor.present {
+            self.latch_fault(CpuException::SegmentNotPresent);
+            return Ok(());
+        }
+
+        let new_cpl = if conforming {
+            cpl
+        } else {
+            descriptor.rights.dpl
         };
-        ($cpu:ident, $($flag:ident=$expected:literal),+) => {
-            $(assert_eflags!(@ $cpu, $flag=$expected));+
+        let eflags = self.registers.eflags.get_value();
+        let (cs, eip) = (self.registers.cs, self.registers.get_eip());
+        let (ss, esp) = (self.registers.ss, self.registers.esp);
+        if new_cpl < cpl {
+            let Some((inner_ss, inner_esp)) = self.inner_stack(new_cpl)? else {
+                return Ok(());
+            };
+            self.registers.eflags.set_virtual_8086_mode(false);
+            // The CPL is that of the handler once the stack has been switched, as its stack segment
+            // is checked against it.
+            self.registers.cs = cs & !0b11 | new_cpl as u16;
+            match self.protected_mode_segment(SegmentRegister::Ss, Selector(inner_ss)) {
+                Ok(cached) => {
+                    self.registers.load_segment(SegmentRegister::Ss, inner_ss);
+                    self.registers.set_segment(SegmentRegister::Ss, cached);
+                    self.registers.esp = inner_esp;
+                }
+                Err(CpuException::GeneralProtection) => {
+                    self.latch_fault(CpuException::InvalidTss);
+                    return Ok(());
+                }
+                Err(exception) => {
+                    self.latch_fault(exception);
+                    return Ok(());
+                }
+            }
+        }
+
+        let push = |cpu: &mut Self, value: u32| match gate_type {
+            INTERRUPT_GATE_16_TYPE | TRAP_GATE_16_TYPE => cpu.push16(value as u16),
+            _ => cpu.push32(value),
         };
+        if new_cpl < cpl {
+            push(self, ss as u32)?;
+            push(self, esp)?;
+        }
+        push(self, eflags)?;
+        push(self, cs as u32)?;
+        push(self, eip)?;
+        if let InterruptKind::Exception(Some(error_code)) = kind {
+            push(self, error_code)?;
+        }
+
+        let flags = &mut self.registers.eflags;
+        flags.set_trap_flag(false);
+        flags.set_nested_task(false);
+        flags.set_resume_flag(false);
+        flags.set_virtual_8086_mode(false);
+        if matches!(gate_type, INTERRUPT_GATE_16_TYPE | INTERRUPT_GATE_TYPE) {
+            flags.set_interrupt_enable_flag(false);
+        }
+        self.load_segment(SegmentRegister::Cs, selector.0 & !0b11 | new_cpl as u16);
+        self.registers.set_eip(match gate_type {
+            INTERRUPT_GATE_16_TYPE | TRAP_GATE_16_TYPE => gate.offset & 0xffff,
+            _ => gate.offset,
+        });
+        Ok(())
     }
 
-    macro_rules! operands {
-        () => { Operands(vec![]) };
-        ($operand:literal) => { Operands(vec![Operand::try_from(&NasmStr($operand)).unwrap()])};
-        ($operand_a:literal, $operand_b:literal) => {
-            {
-                let mut operands = operands!($operand_a);
-                operands.0.append(&mut operands!($operand_b).0);
-                operands
-            }
+    /// The stack (SS and ESP) of the privilege level `cpl`, as read from the TSS of the running
+    /// task. If there is no TSS, or it is too small to hold the stack, then a #TS exception is
+    /// latched and `None` is returned.
+    fn inner_stack(&self, cpl: u8) -> Result<Option<(u16, u32)>, Error> {
+        let offset = 4 + cpl as u32 * 8;
+        if Selector(self.registers.tr).is_null() || self.registers.tss.limit < offset + 7 {
+            self.latch_fault(CpuException::InvalidTss);
+            return Ok(None);
+        }
+        let address = self.physical_address(
+            self.registers.tss.base.wrapping_add(offset),
+            8,
+            AccessKind::Read,
+        )?;
+        let esp = self.memory.read32(address)?;
+        let ss = self.memory.read16(address.offset(4))?;
+        Ok(Some((ss, esp)))
+    }
+
+    /// Latches a fault raised by an access made by the current instruction, unless an earlier
+    /// access has already faulted.
+    pub(crate) fn latch_fault(&self, exception: CpuException) {
+        if self.fault.get().is_none() {
+            self.fault.set(Some(exception));
+        }
+    }
+
+    /// Raises the given exception. If there is a host handler or an interrupt service routine for
+    /// its vector (i.e. its entry in the interrupt vector table, or its gate in the IDT, is not
+    /// null), then it is delivered in the same way as any other interrupt, along with its error
+    /// code in protected mode. Otherwise, it is left for the embedder to report (see
+    /// `Machine::run`). An exception which faults while it is being delivered (e.g. because the
+    /// stack is inaccessible) is reported as a double fault instead.
+    pub(crate) fn raise_exception(&mut self, exception: CpuException) {
+        if let Some(eip) = self.instruction_eip.filter(|_| !exception.is_trap()) {
+            self.registers.set_eip(eip);
+        }
+        let vector = exception.vector();
+        let page_fault = match exception {
+            CpuException::PageFault => self.page_fault.take(),
+            _ => None,
         };
-        ($operand:literal, $($tail:tt)*) => {
-            {
-                operands!($operand).0.append(&mut operands!($($tail)*).0)
-            }
+        if let Some(page_fault) = page_fault {
+            self.registers.control_registers.set_cr2(page_fault.address);
+        }
+        let kind = if exception.is_trap() {
+            InterruptKind::Software
+        } else {
+            let error_code = page_fault.map_or(0, |page_fault| page_fault.error_code);
+            InterruptKind::Exception(exception.has_error_code().then_some(error_code))
+        };
+        let interrupt_vector = self.read_interrupt_vector(vector);
+        let delivered = match interrupt_vector {
+            InterruptVector::Unset => Ok(()),
+            _ => self.interrupt(vector, kind),
         };
+        if self.fault.take().is_some() || delivered.is_err() {
+            self.page_fault.take();
+            self.unreported_exception = Some(CpuException::DoubleFault);
+        } else if interrupt_vector == InterruptVector::Unset {
+            self.unreported_exception = Some(exception);
+        }
     }
 
-    // https://stackoverflow.com/questions/8965923/carry-overflow-subtraction-in-x86#8982549
-    //       A                   B                   A + B              Flags
-    // ---------------     ----------------    ---------------      -----------------
-    // h  |  ud  |   d   | h  |  ud  |   d   | h  |  ud  |   d   | OF | SF | ZF | CF
-    // ---+------+-------+----+------+-------+----+------+-------+----+----+----+---
-    // 7F | 127  |  127  | 0  |  0   |   0   | 7F | 127  |  127  | 0  | 0  | 0  | 0
-    // FF | 255  |  -1   | 7F | 127  |  127  | 7E | 126  |  126  | 0  | 0  | 0  | 1
-    // 0  |  0   |   0   | 0  |  0   |   0   | 0  |  0   |   0   | 0  | 0  | 1  | 0
-    // FF | 255  |  -1   | 1  |  1   |   1   | 0  |  0   |   0   | 0  | 0  | 1  | 1
-    // FF | 255  |  -1   | 0  |  0   |   0   | FF | 255  |  -1   | 0  | 1  | 0  | 0
-    // FF | 255  |  -1   | FF | 255  |  -1   | FE | 254  |  -2   | 0  | 1  | 0  | 1
-    // FF | 255  |  -1   | 80 | 128  | -128  | 7F | 127  |  127  | 1  | 0  | 0  | 1
-    // 80 | 128  | -128  | 80 | 128  | -128  | 0  |  0   |   0   | 1  | 0  | 1  | 1
-    // 7F | 127  |  127  | 7F | 127  |  127  | FE | 254  |  -2   | 1  | 1  | 0  | 0
-    // TODO: Test for AF and PF.
-    #[test]
-    fn add() {
-        let mut cpu = Cpu::default();
+    /// Checks that the processor is running at CPL 0, as is required to execute privileged
+    /// instructions. Otherwise, a #GP exception is raised and `false` is returned, in which case
+    /// the instruction must not be performed.
+    fn privileged(&mut self) -> bool {
+        if self.registers.get_cpl() == CurrentPrivilegeLevel::CPL0 {
+            return true;
+        }
 
-        // Decimal
-        assert_eq!(cpu.add(127_i8, 0_i8), 127_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+        self.raise_exception(CpuException::GeneralProtection);
+        false
+    }
 
-        assert_eq!(cpu.add(-1_i8, 127_i8), 126_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+    /// Checks that the processor is in protected mode, for the instructions which are not
+    /// recognized in real-address or virtual-8086 mode. Otherwise, a #UD exception is raised and
+    /// `false` is returned, in which case the instruction must not be performed.
+    fn protected_mode(&mut self) -> bool {
+        if self.registers.get_operating_mode() == OperatingMode::Protected {
+            return true;
+        }
 
-        assert_eq!(cpu.add(0_i8, 0_i8), 0_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+        self.raise_exception(CpuException::InvalidOpcode);
+        false
+    }
 
-        assert_eq!(cpu.add(-1_i8, 1_i8), 0_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = true);
+    /// Checks that the current privilege level is sufficient for the I/O-sensitive instructions
+    /// (i.e. that CPL <= IOPL). Otherwise, a #GP exception is raised and `false` is returned, in
+    /// which case the instruction must not be performed.
+    fn io_privileged(&mut self) -> bool {
+        if self.registers.get_cpl() as u8 <= self.registers.eflags.get_iopl() as u8 {
+            return true;
+        }
 
-        assert_eq!(cpu.add(-1_i8, 0_i8), -1_i8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+        self.raise_exception(CpuException::GeneralProtection);
+        false
+    }
 
-        assert_eq!(cpu.add(-1_i8, -1_i8), -2_i8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+    /// Checks that undocumented instructions have been enabled. Otherwise, a #UD exception is raised
+    /// and `false` is returned, in which case the instruction must not be performed.
+    fn undocumented(&mut self) -> bool {
+        if self.undocumented_instructions {
+            return true;
+        }
 
-        assert_eq!(cpu.add(-1_i8, -128_i8), 127_i8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = true);
+        self.raise_exception(CpuException::InvalidOpcode);
+        false
+    }
 
-        assert_eq!(cpu.add(-128_i8, -128_i8), 0_i8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = true, CF = true);
+    /// Checks that a debug register may be accessed, which is only permitted at CPL 0. DR4 and DR5
+    /// are only accessible as aliases of DR6 and DR7 while debugging extensions (CR4.DE) are
+    /// disabled, and otherwise raise a #UD exception. If the general detect flag (DR7.GD) is set,
+    /// then a #DB exception is raised instead, allowing a debugger to guard its debug registers.
+    /// In this case, GD is cleared so that the handler may access them, and BD is set in DR6 to
+    /// report the cause.
+    fn debug_register_access_permitted(&mut self, register: &DebugRegister) -> bool {
+        if !self.privileged() {
+            return false;
+        }
 
-        assert_eq!(cpu.add(127_i8, 127_i8), -2_i8);
-        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = false);
+        if matches!(register, DebugRegister::Dr4 | DebugRegister::Dr5)
+            && self.registers.control_registers.get_debugging_extensions()
+        {
+            self.raise_exception(CpuException::InvalidOpcode);
+            return false;
+        }
 
-        // Unsigned decimal
-        assert_eq!(cpu.add(127_u8, 0_u8), 127_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+        let debug_registers = &mut self.registers.debug_registers;
+        if debug_registers.get_general_detect_enable() {
+            debug_registers.set_general_detect_enable(false);
+            debug_registers.set_debug_register_access_detected(true);
+            self.raise_exception(CpuException::Debug);
+            return false;
+        }
 
-        assert_eq!(cpu.add(255_u8, 127_u8), 126_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+        true
+    }
 
-        assert_eq!(cpu.add(0_u8, 0_u8), 0_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+    /// Checks that SSE instructions may be executed, which requires the operating system to have
+    /// enabled them (CR4.OSFXSR), and the x87 FPU not to be emulated (CR0.EM). Otherwise, a #UD
+    /// exception is raised. If a task switch has occurred (CR0.TS), then a #NM exception is raised
+    /// instead, so that the SIMD state can be saved and restored lazily.
+    fn sse_available(&mut self) -> bool {
+        let control_registers = &self.registers.control_registers;
+        if control_registers.get_emulation() || !control_registers.get_osfxsr() {
+            self.raise_exception(CpuException::InvalidOpcode);
+            return false;
+        }
 
-        assert_eq!(cpu.add(255_u8, 1_u8), 0_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = true);
+        if control_registers.get_task_switched() {
+            self.raise_exception(CpuException::DeviceNotAvailable);
+            return false;
+        }
 
-        assert_eq!(cpu.add(255_u8, 0_u8), 255_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+        true
+    }
 
-        assert_eq!(cpu.add(255_u8, 255_u8), 254_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+    /// Checks that a 128-bit SSE operand is aligned to 16 bytes, raising a #GP exception if not.
+    fn sse_aligned(&mut self, xmm128: &XmmRegisterOrMemory128) -> bool {
+        if xmm128.is_aligned(self) {
+            return true;
+        }
 
-        assert_eq!(cpu.add(255_u8, 128_u8), 127_u8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = true);
+        self.raise_exception(CpuException::GeneralProtection);
+        false
+    }
 
-        assert_eq!(cpu.add(128_u8, 128_u8), 0_u8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = true, CF = true);
+    /// Records the SIMD floating-point exceptions detected by an SSE instruction in MXCSR. If any
+    /// of them are unmasked, then a #XM exception is raised (or #UD, if CR4.OSXMMEXCPT is clear)
+    /// and `false` is returned, in which case the instruction must not write its result.
+    fn simd_exceptions_masked(&mut self, exceptions: u32) -> bool {
+        if !self.sse.signal_exceptions(exceptions) {
+            return true;
+        }
 
-        assert_eq!(cpu.add(127_u8, 127_u8), 254_u8);
-        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = false);
+        if self.registers.control_registers.get_osxmmexcpt() {
+            self.raise_exception(CpuException::SimdFloatingPoint);
+        } else {
+            self.raise_exception(CpuException::InvalidOpcode);
+        }
+        false
+    }
 
-        // Hexadecimal
-        assert_eq!(cpu.add(0x7F_u8, 0x0_u8), 0x7F_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+    /// Performs a packed operation on an XMM register and an XMM register or aligned 128-bit memory
+    /// operand, storing the result in the XMM register. `operation` is applied to each pair of
+    /// corresponding floating-point values, and returns the raw result along with the SIMD
+    /// floating-point exceptions which it detected.
+    fn packed<T, F>(&mut self, operands: &Operands, operation: F) -> Result<(), Error>
+    where
+        T: SimdFloat,
+        F: Fn(&Sse, T, T) -> (u128, u32),
+    {
+        let (xmm, xmm128) = unwrap_operands!(operands, &XmmRegister, XmmRegisterOrMemory128);
+        if !self.sse_available() || !self.sse_aligned(&xmm128) {
+            return Ok(());
+        }
 
-        assert_eq!(cpu.add(0xFF_u8, 0x7F_u8), 0x7E_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+        let lhs = self.sse.read_xmm(xmm.index());
+        let rhs = xmm128.read(self)?;
+        let mask = u128::MAX >> (128 - T::BITS);
+        let mut exceptions = 0;
+        let result = (0..128).step_by(T::BITS).fold(0, |result, shift| {
+            let lhs = T::from_lane(lhs >> shift);
+            let rhs = T::from_lane(rhs >> shift);
+            let (lane, lane_exceptions) = operation(&self.sse, lhs, rhs);
+            exceptions |= lane_exceptions;
+            result | (lane & mask) << shift
+        });
+
+        if self.simd_exceptions_masked(exceptions) {
+            self.sse.write_xmm(xmm.index(), result);
+        }
+        Ok(())
+    }
 
-        assert_eq!(cpu.add(0x0_u8, 0x0_u8), 0x0_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+    fn packed_arithmetic<T: SimdFloat>(
+        &mut self,
+        operands: &Operands,
+        operation: ArithmeticOperation,
+    ) -> Result<(), Error> {
+        self.packed(operands, |sse, lhs: T, rhs: T| {
+            let (result, exceptions) = sse.arithmetic(operation, lhs, rhs);
+            (result.to_lane(), exceptions)
+        })
+    }
 
-        assert_eq!(cpu.add(0xFF_u8, 0x1_u8), 0x0_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = true);
+    /// Performs a scalar operation on the low double-precision values of an XMM register and an XMM
+    /// register or 64-bit memory operand, storing the result in the low QWORD of the XMM register.
+    /// The high QWORD is left unchanged.
+    fn scalar_double_arithmetic(
+        &mut self,
+        operands: &Operands,
+        operation: ArithmeticOperation,
+    ) -> Result<(), Error> {
+        let (xmm, xmm64) = unwrap_operands!(operands, &XmmRegister, XmmRegisterOrMemory64);
+        if !self.sse_available() {
+            return Ok(());
+        }
 
-        assert_eq!(cpu.add(0xFF_u8, 0x0_u8), 0xFF_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+        let destination = self.sse.read_xmm(xmm.index());
+        let lhs = f64::from_bits(destination as u64);
+        let rhs = f64::from_bits(xmm64.read(self)?);
+        let (result, exceptions) = self.sse.arithmetic(operation, lhs, rhs);
+        if self.simd_exceptions_masked(exceptions) {
+            let value = destination & !(u64::MAX as u128) | result.to_bits() as u128;
+            self.sse.write_xmm(xmm.index(), value);
+        }
+        Ok(())
+    }
 
-        assert_eq!(cpu.add(0xFF_u8, 0xFF_u8), 0xFE_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+    /// Halts the processor, such that no more instructions are executed until it is resumed. This
+    /// is a privileged instruction.
+    pub(crate) fn hlt(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self.privileged() {
+            self.halted = true;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn cli(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self.io_privileged() {
+            self.registers.eflags.set_interrupt_enable_flag(false);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sti(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self.io_privileged() {
+            self.interrupt_shadow = !self.registers.eflags.get_interrupt_enable_flag();
+            self.registers.eflags.set_interrupt_enable_flag(true);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn int_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let imm8 = unwrap_operands!(operands, Immediate8);
+        self.interrupt(imm8.0, InterruptKind::Software)
+    }
+
+    pub(crate) fn int3(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.raise_exception(CpuException::Breakpoint);
+        Ok(())
+    }
+
+    /// Raises the overflow interrupt if the OF flag is set, otherwise does nothing.
+    pub(crate) fn interrupt_on_overflow(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self.registers.eflags.get_overflow_flag() {
+            self.raise_exception(CpuException::Overflow);
+        }
+        Ok(())
+    }
+
+    /// Returns from an interrupt service routine by popping IP, CS, and FLAGS off the stack, in
+    /// that order. This is the inverse of an interrupt delivered to guest code in real mode, or
+    /// through a 16-bit gate in protected mode, where returning to an outer privilege level also
+    /// pops SP and SS (see `Cpu::pop_outer_stack`).
+    pub(crate) fn iret(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self.return_from_nested_task()? {
+            return Ok(());
+        }
+
+        let ip = self.pop16()?;
+        let selector = self.pop16()?;
+        let flags = self.pop16()?;
+        let outer_stack = self.pop_outer_stack(selector, Size::Word)?;
+        self.load_segment(SegmentRegister::Cs, selector);
+        self.load_outer_stack(outer_stack);
+        self.registers.set_eip(ip as u32);
+        let eflags = self.registers.eflags.get_value() & 0xffff0000 | flags as u32;
+        self.registers.eflags.set_value(eflags);
+        Ok(())
+    }
+
+    /// Returns from an interrupt service routine by popping EIP, CS, and EFLAGS off the stack, in
+    /// that order. Each value occupies a DWORD on the stack, with the upper WORD of CS discarded.
+    /// The VM flag cannot be changed and the RF flag is always cleared. In protected mode, returning
+    /// to an outer privilege level also pops ESP and SS (see `Cpu::pop_outer_stack`).
+    pub(crate) fn iretd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self.return_from_nested_task()? {
+            return Ok(());
+        }
+
+        let eip = self.pop32()?;
+        let selector = self.pop32()? as u16;
+        let eflags = self.pop32()?;
+        let outer_stack = self.pop_outer_stack(selector, Size::Dword)?;
+        self.load_segment(SegmentRegister::Cs, selector);
+        self.load_outer_stack(outer_stack);
+        self.registers.set_eip(eip);
+        let virtual_8086_mode = self.registers.eflags.get_virtual_8086_mode();
+        self.registers.eflags.set_value(eflags);
+        self.registers
+            .eflags
+            .set_virtual_8086_mode(virtual_8086_mode);
+        self.registers.eflags.set_resume_flag(false);
+        Ok(())
+    }
+
+    /// Returns from a task which was nested within another by a far `CALL` (i.e. whose NT flag is
+    /// set), by switching back to the task in the previous task link of its TSS. This is only done
+    /// by `IRET` in protected mode, and `false` is returned otherwise.
+    fn return_from_nested_task(&mut self) -> Result<bool, Error> {
+        if self.registers.get_operating_mode() != OperatingMode::Protected
+            || !self.registers.eflags.get_nested_task()
+        {
+            return Ok(false);
+        }
+
+        let link = self
+            .physical_address(self.registers.tss.base, 2, AccessKind::Read)
+            .and_then(|address| self.memory.read16(address))?;
+        self.switch_task(Selector(link), TaskSwitch::Iret)?;
+        Ok(true)
+    }
+
+    /// Jumps to the target address if the condition is met, and otherwise continues with the next
+    /// instruction. As with `CALL`, the operand is the target address itself.
+    fn jump_if(&mut self, operands: &Operands, condition: bool) {
+        let rel32 = unwrap_operands!(operands, &Immediate);
+        if condition {
+            self.branch(rel32.0);
+        }
+    }
+
+    /// Jumps if above (CF and ZF are clear).
+    pub(crate) fn ja_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let eflags = &self.registers.eflags;
+        let condition = !eflags.get_carry_flag() && !eflags.get_zero_flag();
+        self.jump_if(operands, condition);
+        Ok(())
+    }
+
+    /// Jumps if above or equal (CF is clear).
+    pub(crate) fn jae_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, !self.registers.eflags.get_carry_flag());
+        Ok(())
+    }
+
+    /// Jumps if below (CF is set).
+    pub(crate) fn jb_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, self.registers.eflags.get_carry_flag());
+        Ok(())
+    }
+
+    /// Jumps if below or equal (CF or ZF is set).
+    pub(crate) fn jbe_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let eflags = &self.registers.eflags;
+        let condition = eflags.get_carry_flag() || eflags.get_zero_flag();
+        self.jump_if(operands, condition);
+        Ok(())
+    }
+
+    /// Jumps if equal (ZF is set).
+    pub(crate) fn je_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, self.registers.eflags.get_zero_flag());
+        Ok(())
+    }
+
+    /// Jumps if greater (ZF is clear and SF equals OF).
+    pub(crate) fn jg_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let eflags = &self.registers.eflags;
+        let condition =
+            !eflags.get_zero_flag() && eflags.get_sign_flag() == eflags.get_overflow_flag();
+        self.jump_if(operands, condition);
+        Ok(())
+    }
+
+    /// Jumps if greater or equal (SF equals OF).
+    pub(crate) fn jge_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let eflags = &self.registers.eflags;
+        let condition = eflags.get_sign_flag() == eflags.get_overflow_flag();
+        self.jump_if(operands, condition);
+        Ok(())
+    }
+
+    /// Jumps if less (SF does not equal OF).
+    pub(crate) fn jl_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let eflags = &self.registers.eflags;
+        let condition = eflags.get_sign_flag() != eflags.get_overflow_flag();
+        self.jump_if(operands, condition);
+        Ok(())
+    }
+
+    /// Jumps if less or equal (ZF is set or SF does not equal OF).
+    pub(crate) fn jle_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let eflags = &self.registers.eflags;
+        let condition =
+            eflags.get_zero_flag() || eflags.get_sign_flag() != eflags.get_overflow_flag();
+        self.jump_if(operands, condition);
+        Ok(())
+    }
+
+    pub(crate) fn jmp_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, true);
+        Ok(())
+    }
+
+    /// Jumps to another code segment, or switches to another task (see `Cpu::far_transfer`).
+    pub(crate) fn jmp_far32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let far_pointer = unwrap_operands!(operands, &FarPointer);
+        self.far_transfer(far_pointer, TaskSwitch::Jump)
+    }
+
+    /// Transfers control to the target of a far `CALL` or `JMP`, which are told apart by the task
+    /// switch that they would make. In real-address and virtual-8086 mode, CS is simply loaded with
+    /// the selector. In protected mode, the selector may refer to a code segment, which must be at
+    /// the current privilege level (as there are no call gates to change it), or to a TSS or a
+    /// task gate, in which case the offset is ignored and the processor switches to that task.
+    fn far_transfer(&mut self, target: &FarPointer, switch: TaskSwitch) -> Result<(), Error> {
+        let mut selector = Selector(target.selector);
+        if self.registers.get_operating_mode() == OperatingMode::Protected {
+            let Some(descriptor) = self.read_descriptor(selector) else {
+                self.latch_fault(CpuException::GeneralProtection);
+                return Ok(());
+            };
+            let cpl = self.registers.get_cpl() as u8;
+            let AccessRights { kind, dpl } = descriptor.rights;
+            match kind {
+                DescriptorKind::System(AVAILABLE_TSS_TYPE | BUSY_TSS_TYPE | TASK_GATE_TYPE)
+                    if dpl < cpl.max(selector.rpl()) =>
+                {
+                    self.latch_fault(CpuException::GeneralProtection);
+                    return Ok(());
+                }
+                DescriptorKind::System(TASK_GATE_TYPE) if !descriptor.present => {
+                    self.latch_fault(CpuException::SegmentNotPresent);
+                    return Ok(());
+                }
+                DescriptorKind::System(TASK_GATE_TYPE) => {
+                    return self.switch_task(Selector(descriptor.base as u16), switch);
+                }
+                DescriptorKind::System(AVAILABLE_TSS_TYPE | BUSY_TSS_TYPE) => {
+                    return self.switch_task(selector, switch);
+                }
+                DescriptorKind::Code {
+                    conforming: false, ..
+                } if selector.rpl() > cpl => {
+                    self.latch_fault(CpuException::GeneralProtection);
+                    return Ok(());
+                }
+                DescriptorKind::Code { .. } => selector = Selector(selector.0 & !0b11 | cpl as u16),
+                _ => {
+                    self.latch_fault(CpuException::GeneralProtection);
+                    return Ok(());
+                }
+            }
+        }
+
+        if switch == TaskSwitch::Call {
+            let eip = self.registers.get_eip();
+            match self.registers.get_default_size() {
+                Size::Word => {
+                    self.push16(self.registers.cs)?;
+                    self.push16(eip as u16)?;
+                }
+                _ => {
+                    self.push32(self.registers.cs as u32)?;
+                    self.push32(eip)?;
+                }
+            }
+        }
+        self.load_segment(SegmentRegister::Cs, selector.0);
+        self.branch(target.offset);
+        Ok(())
+    }
+
+    /// Jumps if not equal (ZF is clear).
+    pub(crate) fn jne_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, !self.registers.eflags.get_zero_flag());
+        Ok(())
+    }
+
+    /// Jumps if not overflow (OF is clear).
+    pub(crate) fn jno_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, !self.registers.eflags.get_overflow_flag());
+        Ok(())
+    }
+
+    /// Jumps if not parity (PF is clear).
+    pub(crate) fn jnp_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, !self.registers.eflags.get_parity_flag());
+        Ok(())
+    }
+
+    /// Jumps if not sign (SF is clear).
+    pub(crate) fn jns_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, !self.registers.eflags.get_sign_flag());
+        Ok(())
+    }
+
+    /// Jumps if overflow (OF is set).
+    pub(crate) fn jo_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, self.registers.eflags.get_overflow_flag());
+        Ok(())
+    }
+
+    /// Jumps if parity (PF is set).
+    pub(crate) fn jp_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, self.registers.eflags.get_parity_flag());
+        Ok(())
+    }
+
+    /// Jumps if sign (SF is set).
+    pub(crate) fn js_rel32(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.jump_if(operands, self.registers.eflags.get_sign_flag());
+        Ok(())
+    }
+
+    /// Loads a pseudo-descriptor (a 16-bit limit followed by a 32-bit base) from the given
+    /// address.
+    fn read_pseudo_descriptor(
+        &self,
+        address: PhysicalAddress,
+    ) -> Result<DescriptorTableRegister, Error> {
+        Ok(DescriptorTableRegister {
+            base: self.memory.read32(address.offset(2))?,
+            limit: self.memory.read16(address)?,
+        })
+    }
+
+    /// Stores a pseudo-descriptor (a 16-bit limit followed by a 32-bit base) to the given address.
+    fn write_pseudo_descriptor(
+        &mut self,
+        address: PhysicalAddress,
+        register: DescriptorTableRegister,
+    ) -> Result<(), Error> {
+        self.memory.write16(address, register.limit)?;
+        self.memory.write32(address.offset(2), register.base)?;
+        Ok(())
+    }
+
+    /// Serializes all prior loads. As every memory access completes before the next instruction
+    /// executes, there is nothing to order and this is a no-op.
+    pub(crate) fn lfence(&mut self, _operands: &Operands) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Loads the GDTR from the pseudo-descriptor in memory. This is a privileged instruction.
+    pub(crate) fn lgdt_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem = unwrap_operands!(operands, &EffectiveAddress);
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let address = mem.translate_bytes(self, PSEUDO_DESCRIPTOR_LENGTH, AccessKind::Read)?;
+        self.registers.gdtr = self.read_pseudo_descriptor(address)?;
+        Ok(())
+    }
+
+    /// Loads the IDTR from the pseudo-descriptor in memory. This is a privileged instruction.
+    pub(crate) fn lidt_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem = unwrap_operands!(operands, &EffectiveAddress);
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let address = mem.translate_bytes(self, PSEUDO_DESCRIPTOR_LENGTH, AccessKind::Read)?;
+        self.registers.idtr = self.read_pseudo_descriptor(address)?;
+        Ok(())
+    }
+
+    /// Invalidates the TLB's translation of the page containing the memory operand, if it has one.
+    /// This is a privileged instruction.
+    pub(crate) fn invlpg_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem = unwrap_operands!(operands, &EffectiveAddress);
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let address = mem.resolve(self);
+        self.tlb.get_mut().invalidate(address);
+        Ok(())
+    }
+
+    pub(crate) fn lea_reg16_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg16, mem) = unwrap_operands!(operands, &Register16, &EffectiveAddress);
+        self.registers.write16(reg16, mem.offset(self) as u16);
+        Ok(())
+    }
+
+    pub(crate) fn lea_reg32_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, mem) = unwrap_operands!(operands, &Register32, &EffectiveAddress);
+        self.registers.write32(reg32, mem.offset(self));
+        Ok(())
+    }
+
+    /// Loads the LDTR with a selector of an LDT descriptor in the GDT, caching the base and limit
+    /// of the LDT. The null selector leaves the LDT unusable. This is a privileged instruction,
+    /// which is only recognized in protected mode.
+    pub(crate) fn lldt_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rm16 = unwrap_operands!(operands, RegisterOrMemory16);
+        if !self.protected_mode() || !self.privileged() {
+            return Ok(());
+        }
+
+        let selector = Selector(rm16.read(self)?);
+        if let Err(exception) = self.load_ldt(selector) {
+            self.latch_fault(exception);
+        }
+        Ok(())
+    }
+
+    /// Loads the LDTR with `selector`, which must either be null or refer to a present LDT
+    /// descriptor in the GDT. Otherwise, the LDTR is left as it was and the exception to raise is
+    /// returned.
+    fn load_ldt(&mut self, selector: Selector) -> Result<(), CpuException> {
+        if selector.is_null() {
+            self.registers.ldtr = selector.0;
+            return Ok(());
+        }
+        let descriptor = (!selector.local())
+            .then(|| self.read_descriptor(selector))
+            .flatten()
+            .filter(|descriptor| descriptor.rights.kind == DescriptorKind::System(LDT_TYPE));
+        match descriptor {
+            None => Err(CpuException::GeneralProtection),
+            Some(descriptor) if !descriptor.present => Err(CpuException::SegmentNotPresent),
+            Some(descriptor) => {
+                self.registers.ldtr = selector.0;
+                self.registers.ldt = Segment {
+                    base: descriptor.base,
+                    limit: descriptor.limit,
+                    rights: Some(descriptor.rights),
+                    usable: true,
+                    big: descriptor.big,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Loads the machine status word, i.e. the low 4 bits (PE, MP, EM, and TS) of CR0. The
+    /// remaining bits of the operand are ignored. This can be used to enter protected mode, but not
+    /// to leave it, as PE cannot be cleared. This is a privileged instruction.
+    pub(crate) fn lmsw_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rm16 = unwrap_operands!(operands, RegisterOrMemory16);
+        let value = rm16.read(self)?;
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let control_registers = &mut self.registers.control_registers;
+        let protection_enable = control_registers.get_protection_enable();
+        control_registers.set_protection_enable(protection_enable || value & 1 != 0);
+        control_registers.set_monitor_coprocessor(value & (1 << 1) != 0);
+        control_registers.set_emulation(value & (1 << 2) != 0);
+        control_registers.set_task_switched(value & (1 << 3) != 0);
+        Ok(())
+    }
+
+    /// Loads the task register with a selector of an available TSS descriptor in the GDT, caching
+    /// the base and limit of the TSS, and marks the TSS as busy. Unlike a task switch, nothing is
+    /// saved or loaded, so this is how the first task is set up. This is a privileged instruction,
+    /// which is only recognized in protected mode.
+    pub(crate) fn ltr_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rm16 = unwrap_operands!(operands, RegisterOrMemory16);
+        if !self.protected_mode() || !self.privileged() {
+            return Ok(());
+        }
+
+        let selector = Selector(rm16.read(self)?);
+        let descriptor = (!selector.local())
+            .then(|| self.read_descriptor(selector))
+            .flatten()
+            .filter(|descriptor| {
+                descriptor.rights.kind == DescriptorKind::System(AVAILABLE_TSS_TYPE)
+            });
+        match descriptor {
+            None => self.latch_fault(CpuException::GeneralProtection),
+            Some(descriptor) if !descriptor.present => {
+                self.latch_fault(CpuException::SegmentNotPresent)
+            }
+            Some(descriptor) => {
+                self.load_task_register(selector, descriptor);
+                self.set_task_busy(selector, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn lodsb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Byte)
+                .and_then(|address| cpu.memory.read8(address))?;
+            cpu.registers.set_al(value);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Byte);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn lodsw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Word)
+                .and_then(|address| cpu.memory.read16(address))?;
+            cpu.registers.set_ax(value);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Word);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn lodsd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Dword)
+                .and_then(|address| cpu.memory.read32(address))?;
+            cpu.registers.set_eax(value);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Dword);
+            Ok(())
+        })
+    }
+
+    /// Serializes all prior loads and stores. As every memory access completes before the next
+    /// instruction executes, there is nothing to order and this is a no-op.
+    pub(crate) fn mfence(&mut self, _operands: &Operands) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub(crate) fn mov_al_moffs8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, moffs8) = unwrap_operands!(operands, &Register8, &EffectiveAddress);
+        let value = moffs8
+            .translate(self, Size::Byte, AccessKind::Read)
+            .and_then(|address| self.memory.read8(address))?;
+        self.registers.set_al(value);
+        Ok(())
+    }
+
+    pub(crate) fn mov_ax_moffs16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, moffs16) = unwrap_operands!(operands, &Register16, &EffectiveAddress);
+        let value = moffs16
+            .translate(self, Size::Word, AccessKind::Read)
+            .and_then(|address| self.memory.read16(address))?;
+        self.registers.set_ax(value);
+        Ok(())
+    }
+
+    pub(crate) fn mov_eax_moffs32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, moffs32) = unwrap_operands!(operands, &Register32, &EffectiveAddress);
+        let value = moffs32
+            .translate(self, Size::Dword, AccessKind::Read)
+            .and_then(|address| self.memory.read32(address))?;
+        self.registers.set_eax(value);
+        Ok(())
+    }
+
+    /// Loads a control register from a general-purpose register. This is a privileged instruction.
+    /// Enabling paging without also enabling protected mode raises a #GP exception.
+    pub(crate) fn mov_cr_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (cr, reg32) = unwrap_operands!(operands, &ControlRegister, &Register32);
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let value = self.registers.read32(reg32);
+        const PROTECTION_ENABLE: u32 = 1 << 0;
+        const PAGING: u32 = 1 << 31;
+        if *cr == ControlRegister::Cr0 && value & PAGING != 0 && value & PROTECTION_ENABLE == 0 {
+            self.raise_exception(CpuException::GeneralProtection);
+            return Ok(());
+        }
+
+        self.registers.control_registers.write(cr, value);
+        self.tlb.get_mut().flush();
+        Ok(())
+    }
+
+    /// Loads a debug register from a general-purpose register. This is a privileged instruction.
+    pub(crate) fn mov_dr_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (dr, reg32) = unwrap_operands!(operands, &DebugRegister, &Register32);
+        if !self.debug_register_access_permitted(dr) {
+            return Ok(());
+        }
+
+        let value = self.registers.read32(reg32);
+        self.registers.debug_registers.write(dr, value);
+        Ok(())
+    }
+
+    pub(crate) fn mov_moffs8_al(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (moffs8, _al) = unwrap_operands!(operands, &EffectiveAddress, &Register8);
+        moffs8
+            .translate(self, Size::Byte, AccessKind::Write)
+            .and_then(|address| self.memory.write8(address, self.registers.get_al()))?;
+        Ok(())
+    }
+
+    pub(crate) fn mov_moffs16_ax(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (moffs16, _ax) = unwrap_operands!(operands, &EffectiveAddress, &Register16);
+        moffs16
+            .translate(self, Size::Word, AccessKind::Write)
+            .and_then(|address| self.memory.write16(address, self.registers.get_ax()))?;
+        Ok(())
+    }
+
+    pub(crate) fn mov_moffs32_eax(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (moffs32, _eax) = unwrap_operands!(operands, &EffectiveAddress, &Register32);
+        moffs32
+            .translate(self, Size::Dword, AccessKind::Write)
+            .and_then(|address| self.memory.write32(address, self.registers.get_eax()))?;
+        Ok(())
+    }
+
+    pub(crate) fn mov_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
+        rm8.write(self, reg8.read(&self.registers))?;
+        Ok(())
+    }
+    pub(crate) fn mov_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
+        rm16.write(self, reg16.read(&self.registers))?;
+        Ok(())
+    }
+    pub(crate) fn mov_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
+        rm32.write(self, reg32.read(&self.registers))?;
+        Ok(())
+    }
+    pub(crate) fn mov_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
+        self.registers.write8(reg8, rm8.read(self)?);
+        Ok(())
+    }
+    pub(crate) fn mov_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
+        self.registers.write16(reg16, rm16.read(self)?);
+        Ok(())
+    }
+    /// Stores a control register into a general-purpose register. This is a privileged
+    /// instruction.
+    pub(crate) fn mov_reg32_cr(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, cr) = unwrap_operands!(operands, &Register32, &ControlRegister);
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let value = self.registers.control_registers.read(cr);
+        self.registers.write32(reg32, value);
+        Ok(())
+    }
+
+    /// Stores a debug register into a general-purpose register. This is a privileged instruction.
+    pub(crate) fn mov_reg32_dr(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, dr) = unwrap_operands!(operands, &Register32, &DebugRegister);
+        if !self.debug_register_access_permitted(dr) {
+            return Ok(());
+        }
+
+        let value = self.registers.debug_registers.read(dr);
+        self.registers.write32(reg32, value);
+        Ok(())
+    }
+    pub(crate) fn mov_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
+        self.registers.write32(reg32, rm32.read(self)?);
+        Ok(())
+    }
+
+    /// Moves packed double-precision floating-point values. This is no different from `MOVAPS`, as
+    /// the values are only copied.
+    pub(crate) fn movapd_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.movaps_xmm_xmm128(operands)
+    }
+
+    pub(crate) fn movapd_xmm128_xmm(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.movaps_xmm128_xmm(operands)
+    }
+
+    /// Moves packed single-precision floating-point values. A memory operand must be aligned to 16
+    /// bytes, otherwise a #GP exception is raised.
+    pub(crate) fn movaps_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (xmm, xmm128) = unwrap_operands!(operands, &XmmRegister, XmmRegisterOrMemory128);
+        if !self.sse_available() || !self.sse_aligned(&xmm128) {
+            return Ok(());
+        }
+
+        let value = xmm128.read(self)?;
+        self.sse.write_xmm(xmm.index(), value);
+        Ok(())
+    }
+
+    pub(crate) fn movaps_xmm128_xmm(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (xmm128, xmm) = unwrap_operands!(operands, XmmRegisterOrMemory128, &XmmRegister);
+        if !self.sse_available() || !self.sse_aligned(&xmm128) {
+            return Ok(());
+        }
+
+        xmm128.write(self, self.sse.read_xmm(xmm.index()))?;
+        Ok(())
+    }
+
+    /// Moves aligned packed integers. This is no different from `MOVAPS`, as the values are only
+    /// copied.
+    pub(crate) fn movdqa_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.movaps_xmm_xmm128(operands)
+    }
+
+    pub(crate) fn movdqa_xmm128_xmm(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.movaps_xmm128_xmm(operands)
+    }
+
+    /// Moves unaligned packed integers. This is no different from `MOVUPS`, as the values are only
+    /// copied.
+    pub(crate) fn movdqu_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.movups_xmm_xmm128(operands)
+    }
+
+    pub(crate) fn movdqu_xmm128_xmm(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.movups_xmm128_xmm(operands)
+    }
+
+    /// Moves a DWORD into the low half of an MMX register, zeroing the high half.
+    pub(crate) fn movd_mm_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (mm, rm32) = unwrap_operands!(operands, &MmxRegister, RegisterOrMemory32);
+        let value = rm32.read(self)?;
+        self.fpu.write_mmx(mm.index(), value as u64);
+        Ok(())
+    }
+
+    /// Moves the low half of an MMX register into a DWORD.
+    pub(crate) fn movd_rm32_mm(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, mm) = unwrap_operands!(operands, RegisterOrMemory32, &MmxRegister);
+        let value = self.fpu.read_mmx(mm.index());
+        rm32.write(self, value as u32)?;
+        Ok(())
+    }
+
+    pub(crate) fn movq_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (mm, mm64) = unwrap_operands!(operands, &MmxRegister, MmxRegisterOrMemory64);
+        let value = mm64.read(self)?;
+        self.fpu.write_mmx(mm.index(), value);
+        Ok(())
+    }
+
+    pub(crate) fn movq_mm64_mm(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (mm64, mm) = unwrap_operands!(operands, MmxRegisterOrMemory64, &MmxRegister);
+        let value = self.fpu.read_mmx(mm.index());
+        mm64.write(self, value)?;
+        Ok(())
+    }
+
+    pub(crate) fn movsb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        // A destination which overlaps the source ahead of it replicates the bytes as they are
+        // moved, so it is moved byte by byte.
+        let count = self.registers.get_ecx();
+        let moved_as_block = self.repeat_string_block(|cpu, count| {
+            let segment = cpu.segment_override.unwrap_or(SegmentRegister::Ds);
+            let source = cpu.checked_linear_address(segment, cpu.registers.esi, count)?;
+            // Paging is disabled, so the destination is not split.
+            let destination = cpu
+                .string_destination(Size::Byte, AccessKind::Write)
+                .ok()?
+                .start;
+            if destination > source && destination - source < count {
+                return None;
+            }
+            cpu.memory.read_bytes(source, count).ok()
+        })?;
+        if moved_as_block {
+            self.registers.esi = self.registers.esi.wrapping_add(count);
+            return Ok(());
+        }
+
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Byte)
+                .and_then(|address| cpu.memory.read8(address))?;
+            cpu.string_destination(Size::Byte, AccessKind::Write)
+                .and_then(|address| cpu.memory.write8(address, value))?;
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Byte);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Byte);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn movsw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Word)
+                .and_then(|address| cpu.memory.read16(address))?;
+            cpu.string_destination(Size::Word, AccessKind::Write)
+                .and_then(|address| cpu.memory.write16(address, value))?;
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Word);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Word);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn movsd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Dword)
+                .and_then(|address| cpu.memory.read32(address))?;
+            cpu.string_destination(Size::Dword, AccessKind::Write)
+                .and_then(|address| cpu.memory.write32(address, value))?;
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Dword);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Dword);
+            Ok(())
+        })
+    }
+
+    /// Moves a scalar double-precision floating-point value into the low QWORD of an XMM register.
+    /// When the source is a register, the high QWORD is left unchanged. When the source is memory,
+    /// it is cleared.
+    pub(crate) fn movsd_xmm_xmm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (xmm, xmm64) = unwrap_operands!(operands, &XmmRegister, XmmRegisterOrMemory64);
+        if !self.sse_available() {
+            return Ok(());
+        }
+
+        let value = xmm64.read(self)?;
+        match xmm64 {
+            XmmRegisterOrMemory64::Register(_) => {
+                XmmRegisterOrMemory64::Register(xmm).write(self, value)?
+            }
+            XmmRegisterOrMemory64::Memory(_) => self.sse.write_xmm(xmm.index(), value as u128),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn movsd_xmm64_xmm(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (xmm64, xmm) = unwrap_operands!(operands, XmmRegisterOrMemory64, &XmmRegister);
+        if !self.sse_available() {
+            return Ok(());
+        }
+
+        let value = self.sse.read_xmm(xmm.index()) as u64;
+        xmm64.write(self, value)?;
+        Ok(())
+    }
+
+    /// Moves packed single-precision floating-point values, without any alignment requirement.
+    pub(crate) fn movups_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (xmm, xmm128) = unwrap_operands!(operands, &XmmRegister, XmmRegisterOrMemory128);
+        if !self.sse_available() {
+            return Ok(());
+        }
+
+        let value = xmm128.read(self)?;
+        self.sse.write_xmm(xmm.index(), value);
+        Ok(())
+    }
+
+    pub(crate) fn movups_xmm128_xmm(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (xmm128, xmm) = unwrap_operands!(operands, XmmRegisterOrMemory128, &XmmRegister);
+        if !self.sse_available() {
+            return Ok(());
+        }
+
+        xmm128.write(self, self.sse.read_xmm(xmm.index()))?;
+        Ok(())
+    }
+
+    /// Multiplies packed double-precision floating-point values.
+    pub(crate) fn mulpd_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f64>(operands, ArithmeticOperation::Multiply)
+    }
+
+    /// Multiplies packed single-precision floating-point values.
+    pub(crate) fn mulps_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f32>(operands, ArithmeticOperation::Multiply)
+    }
+
+    /// Multiplies the low double-precision floating-point values.
+    pub(crate) fn mulsd_xmm_xmm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.scalar_double_arithmetic(operands, ArithmeticOperation::Multiply)
+    }
+
+    /// Performs a bitwise inclusive OR operation. The OF and CF flags are cleared, and the SF, ZF,
+    /// and PF flags are set according to the result. The AF flag is undefined.
+    fn or<T>(&mut self, lhs: T, rhs: T) -> T
+    where
+        T: PrimInt + BitOr<T> + AsUnsigned + FromPrimitive,
+    {
+        let result = lhs | rhs;
+        self.registers.eflags.set_overflow_flag(false);
+        self.registers.eflags.set_carry_flag(false);
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers.eflags.compute_parity_flag(result);
+        result
+    }
+    pub(crate) fn or_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let result = self.or(self.registers.get_al(), imm8.0);
+        self.registers.set_al(result);
+        Ok(())
+    }
+
+    pub(crate) fn or_ax_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, imm16) = unwrap_operands!(operands, &Register16, Immediate16);
+        let result = self.or(self.registers.get_ax(), imm16.0);
+        self.registers.set_ax(result);
+        Ok(())
+    }
+
+    pub(crate) fn or_eax_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, imm32) = unwrap_operands!(operands, &Register32, Immediate32);
+        let result = self.or(self.registers.get_eax(), imm32.0);
+        self.registers.set_eax(result);
+        Ok(())
+    }
+
+    pub(crate) fn or_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
+        let result = self.or(reg8.read(&self.registers), rm8.read(self)?);
+        self.registers.write8(reg8, result);
+        Ok(())
+    }
+
+    pub(crate) fn or_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
+        let result = self.or(reg16.read(&self.registers), rm16.read(self)?);
+        self.registers.write16(reg16, result);
+        Ok(())
+    }
+
+    pub(crate) fn or_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
+        let result = self.or(self.registers.read32(reg32), rm32.read(self)?);
+        self.registers.write32(reg32, result);
+        Ok(())
+    }
+
+    pub(crate) fn or_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.or(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
+        let result = self.or(rm8.read(self)?, reg8.read(&self.registers));
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.or(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.or(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
+        let result = self.or(rm16.read(self)?, reg16.read(&self.registers));
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.or(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.or(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn or_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
+        let result = self.or(rm32.read(self)?, self.registers.read32(reg32));
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn out_imm8_al(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (imm8, _) = unwrap_operands!(operands, Immediate8, &Register8);
+        self.io.write8(imm8.0 as u16, self.registers.get_al());
+        Ok(())
+    }
+
+    pub(crate) fn out_imm8_ax(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (imm8, _) = unwrap_operands!(operands, Immediate8, &Register16);
+        self.io.write16(imm8.0 as u16, self.registers.get_ax());
+        Ok(())
+    }
+
+    pub(crate) fn out_imm8_eax(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (imm8, _) = unwrap_operands!(operands, Immediate8, &Register32);
+        self.io.write32(imm8.0 as u16, self.registers.get_eax());
+        Ok(())
+    }
+
+    pub(crate) fn out_dx_al(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.io
+            .write8(self.registers.get_dx(), self.registers.get_al());
+        Ok(())
+    }
+
+    pub(crate) fn out_dx_ax(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.io
+            .write16(self.registers.get_dx(), self.registers.get_ax());
+        Ok(())
+    }
+
+    pub(crate) fn out_dx_eax(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.io
+            .write32(self.registers.get_dx(), self.registers.get_eax());
+        Ok(())
+    }
+
+    pub(crate) fn outsb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Byte)
+                .and_then(|address| cpu.memory.read8(address))?;
+            cpu.io.write8(cpu.registers.get_dx(), value);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Byte);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn outsw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Word)
+                .and_then(|address| cpu.memory.read16(address))?;
+            cpu.io.write16(cpu.registers.get_dx(), value);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Word);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn outsd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            let value = cpu
+                .string_source(Size::Dword)
+                .and_then(|address| cpu.memory.read32(address))?;
+            cpu.io.write32(cpu.registers.get_dx(), value);
+            cpu.registers.esi = cpu.next_string_index(cpu.registers.esi, Size::Dword);
+            Ok(())
+        })
+    }
+
+    /// Performs a packed operation on an MMX register and an MMX register or 64-bit memory operand,
+    /// storing the result in the MMX register. `operation` is applied to each pair of
+    /// corresponding `lane_bits`-wide lanes, which are passed zero-extended. Its result is
+    /// truncated to the width of the lane.
+    fn packed_mm_mm64(
+        &mut self,
+        operands: &Operands,
+        lane_bits: u32,
+        operation: fn(u64, u64) -> u64,
+    ) -> Result<(), Error> {
+        let (mm, mm64) = unwrap_operands!(operands, &MmxRegister, MmxRegisterOrMemory64);
+        let rhs = mm64.read(self)?;
+        let lhs = self.fpu.read_mmx(mm.index());
+
+        let mask = u64::MAX >> (64 - lane_bits);
+        let result = (0..64)
+            .step_by(lane_bits as usize)
+            .fold(0, |result, shift| {
+                let lane = operation(lhs >> shift & mask, rhs >> shift & mask);
+                result | (lane & mask) << shift
+            });
+        self.fpu.write_mmx(mm.index(), result);
+        Ok(())
+    }
+
+    /// Adds packed integers with wraparound.
+    pub(crate) fn paddb_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 8, u64::wrapping_add)
+    }
+
+    pub(crate) fn paddw_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 16, u64::wrapping_add)
+    }
+
+    pub(crate) fn paddd_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 32, u64::wrapping_add)
+    }
+
+    pub(crate) fn paddq_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 64, u64::wrapping_add)
+    }
+
+    /// Adds packed signed integers, saturating each result to the range of the lane.
+    pub(crate) fn paddsb_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 8, |lhs, rhs| {
+            (lhs as i8).saturating_add(rhs as i8) as u8 as u64
+        })
+    }
+
+    pub(crate) fn paddsw_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 16, |lhs, rhs| {
+            (lhs as i16).saturating_add(rhs as i16) as u16 as u64
+        })
+    }
+
+    /// Adds packed unsigned integers, saturating each result to the range of the lane.
+    pub(crate) fn paddusb_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 8, |lhs, rhs| {
+            (lhs as u8).saturating_add(rhs as u8) as u64
+        })
+    }
+
+    pub(crate) fn paddusw_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 16, |lhs, rhs| {
+            (lhs as u16).saturating_add(rhs as u16) as u64
+        })
+    }
+
+    pub(crate) fn pand_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 64, |lhs, rhs| lhs & rhs)
+    }
+
+    /// Inverts the destination, then performs a bitwise AND with the source.
+    pub(crate) fn pandn_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 64, |lhs, rhs| !lhs & rhs)
+    }
+
+    /// Hints that the processor is in a spin-wait loop. There is no pipeline to relax or power to
+    /// save, so beyond being counted as an executed instruction this is a no-op.
+    pub(crate) fn pause(&mut self, _operands: &Operands) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub(crate) fn por_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 64, |lhs, rhs| lhs | rhs)
+    }
+
+    /// Subtracts packed integers with wraparound.
+    pub(crate) fn psubb_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 8, u64::wrapping_sub)
+    }
+
+    pub(crate) fn psubw_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 16, u64::wrapping_sub)
+    }
+
+    pub(crate) fn psubd_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 32, u64::wrapping_sub)
+    }
+
+    pub(crate) fn psubq_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 64, u64::wrapping_sub)
+    }
+
+    /// Subtracts packed signed integers, saturating each result to the range of the lane.
+    pub(crate) fn psubsb_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 8, |lhs, rhs| {
+            (lhs as i8).saturating_sub(rhs as i8) as u8 as u64
+        })
+    }
+
+    pub(crate) fn psubsw_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 16, |lhs, rhs| {
+            (lhs as i16).saturating_sub(rhs as i16) as u16 as u64
+        })
+    }
+
+    /// Subtracts packed unsigned integers, saturating each result to the range of the lane.
+    pub(crate) fn psubusb_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 8, |lhs, rhs| {
+            (lhs as u8).saturating_sub(rhs as u8) as u64
+        })
+    }
+
+    pub(crate) fn psubusw_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 16, |lhs, rhs| {
+            (lhs as u16).saturating_sub(rhs as u16) as u64
+        })
+    }
+
+    pub(crate) fn pxor_mm_mm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_mm_mm64(operands, 64, |lhs, rhs| lhs ^ rhs)
+    }
+
+    /// The physical address of the top of the stack, at SS:ESP (or SS:SP), for an access of the
+    /// given size.
+    fn stack_top(&self, size: Size, kind: AccessKind) -> Result<PhysicalAddress, Error> {
+        self.memory_address(
+            SegmentRegister::Ss,
+            self.registers.get_stack_pointer(),
+            size as u32 / 8,
+            kind,
+        )
+    }
+
+    /// The linear address of the next instruction, i.e. EIP within the code segment.
+    pub fn instruction_address(&self) -> u32 {
+        self.registers
+            .get_segment_base(SegmentRegister::Cs)
+            .wrapping_add(self.registers.get_eip())
+    }
+
+    /// Executes the instruction at CS:EIP, which is fetched from memory (see `Cpu::fetch`). If it
+    /// cannot be fetched, then the fault which that raised is raised instead. EIP is advanced
+    /// past the instruction before it is executed, such that branches may replace it, and `CALL`
+    /// pushes the address of the instruction after it. An instruction breakpoint raises a #DB
+    /// exception before the instruction is executed (see `Cpu::instruction_breakpoint`), and the
+    /// RF flag which suppresses it is cleared once the instruction has been executed.
+    pub(crate) fn step(&mut self) {
+        let fetched = self.fetch();
+        self.update_paging_entries();
+        let Ok((instruction, bytes)) = fetched else {
+            let fault = self.fault.take().unwrap_or(CpuException::GeneralProtection);
+            self.raise_exception(fault);
+            return;
+        };
+        if self.instruction_breakpoint() {
+            return;
+        }
+        let eip = self.registers.get_eip();
+        self.instruction_eip = Some(eip);
+        self.registers.set_eip(eip.wrapping_add(bytes.len() as u32));
+        instruction.execute(self);
+        self.registers.eflags.set_resume_flag(false);
+    }
+
+    /// Fetches the instruction at CS:EIP and decodes it, returning it along with its machine code.
+    /// Instructions are fetched as any other read is made (though the fetch is neither recorded
+    /// nor a data access), so bytes beyond the limit of the code segment latch a #GP exception,
+    /// and bytes in a page which cannot be accessed latch a #PF exception. The page after EIP's
+    /// is only fetched from if the instruction does not end within EIP's. Bytes which are not a
+    /// valid instruction latch a #UD exception.
+    pub(crate) fn fetch(&self) -> Result<(Instruction, Vec<u8>), Error> {
+        let eip = self.registers.get_eip();
+        let linear = self.linear_address(SegmentRegister::Cs, eip, 1)?;
+        let limit = self.registers.get_segment(SegmentRegister::Cs).limit;
+        let length = MAX_INSTRUCTION_LENGTH
+            .min((limit - eip).saturating_add(1))
+            .min((!linear).saturating_add(1));
+        let within_page = length.min(paging::PAGE_SIZE - linear % paging::PAGE_SIZE);
+
+        let mut bytes = self.fetch_bytes(linear, within_page)?;
+        let mut decoded = Instruction::decode(&bytes, eip);
+        if decoded.is_err() && length > within_page {
+            bytes = self.fetch_bytes(linear, length)?;
+            decoded = Instruction::decode(&bytes, eip);
+        }
+        match decoded {
+            Ok((instruction, length)) => {
+                bytes.truncate(length);
+                Ok((instruction, bytes))
+            }
+            Err(error) => {
+                // The bytes may only be cut short by the limit of the code segment, in which case
+                // fetching the rest of them faults.
+                let mut padded = bytes;
+                padded.resize(MAX_INSTRUCTION_LENGTH as usize, 0);
+                if let Ok((_, length)) = Instruction::decode(&padded, eip) {
+                    self.linear_address(SegmentRegister::Cs, eip, length as u32)?;
+                }
+                self.latch_fault(CpuException::InvalidOpcode);
+                Err(error)
+            }
+        }
+    }
+
+    /// Reads `length` bytes of code at the linear address, translating it through the page tables.
+    fn fetch_bytes(&self, linear: u32, length: u32) -> Result<Vec<u8>, Error> {
+        let physical = self.physical_address(linear, length, AccessKind::Read)?;
+        Ok(self.memory.fetch(physical, length))
+    }
+
+    /// The instruction at CS:EIP along with its machine code, or `None` if it cannot be fetched
+    /// (see `Cpu::fetch`). This is on behalf of the host (e.g. to report the instruction before it
+    /// is executed), so neither a fault nor the paging-structure entries which were walked are
+    /// left to be raised or updated.
+    pub(crate) fn peek_instruction(&self) -> Option<(Instruction, Vec<u8>)> {
+        let updates = self.paging_entry_updates.borrow().len();
+        let fetched = self.host_access(|cpu| cpu.fetch().ok());
+        self.paging_entry_updates.borrow_mut().truncate(updates);
+        fetched
+    }
+
+    /// Translates an offset within a segment into the physical address of an access of `length`
+    /// bytes, first through the segment (see `Cpu::linear_address`), and then through the page
+    /// tables (see `Cpu::physical_address`).
+    pub(crate) fn memory_address(
+        &self,
+        segment: SegmentRegister,
+        offset: u32,
+        length: u32,
+        kind: AccessKind,
+    ) -> Result<PhysicalAddress, Error> {
+        let linear = self.linear_address(segment, offset, length)?;
+        let hits = self
+            .registers
+            .debug_registers
+            .data_breakpoints(linear, length, kind);
+        self.data_breakpoint_hits
+            .set(self.data_breakpoint_hits.get() | hits);
+        self.physical_address(linear, length, kind)
+    }
+
+    /// Checks whether the instruction at EIP is at an enabled instruction breakpoint (see
+    /// `DebugRegisters::instruction_breakpoints`), unless the RF flag is set. If it is, then the
+    /// breakpoint is reported in DR6, and a #DB exception is raised before the instruction is
+    /// executed, in which case `true` is returned. When the exception cannot be delivered, RF is
+    /// set, such that the instruction is executed once the machine is resumed.
+    pub(crate) fn instruction_breakpoint(&mut self) -> bool {
+        if self.registers.eflags.get_resume_flag() {
+            return false;
+        }
+        let hits = self
+            .registers
+            .debug_registers
+            .instruction_breakpoints(self.instruction_address());
+        if hits == 0 {
+            return false;
+        }
+
+        self.raise_breakpoint(hits);
+        if self.unreported_breakpoint.is_some() {
+            self.registers.eflags.set_resume_flag(true);
+        }
+        true
+    }
+
+    /// Intel manual section 18.2.3 "Debug Status Register (DR6)".
+    /// Raises a #DB exception for the hardware breakpoints which were hit, given as a mask of
+    /// DR0-DR3, setting their B0-B3 flags in DR6.
+    pub(crate) fn raise_breakpoint(&mut self, hits: u8) {
+        let debug_registers = &mut self.registers.debug_registers;
+        debug_registers.set_dr6(debug_registers.get_dr6() | hits as u32);
+        self.raise_exception(CpuException::Debug);
+        if self.unreported_exception == Some(CpuException::Debug) {
+            self.unreported_breakpoint = Some(hits.trailing_zeros() as usize);
+        }
+    }
+
+    /// Translates a linear address into the physical address of an access of `length` bytes. When
+    /// paging is enabled (CR0.PG), this walks the page tables (or uses the TLB), and if the access
+    /// is not permitted, a #PF exception is latched and an `Err` is returned. The accessed and
+    /// dirty flags are set once the instruction completes (see `Cpu::update_paging_entries`). An
+    /// access which spans two pages is split between their frames, which need not be consecutive.
+    pub(crate) fn physical_address(
+        &self,
+        linear: u32,
+        length: u32,
+        kind: AccessKind,
+    ) -> Result<PhysicalAddress, Error> {
+        let control_registers = &self.registers.control_registers;
+        if !control_registers.get_paging() {
+            return Ok(linear.into());
+        }
+
+        let access = paging::Access {
+            directory: control_registers.get_cr3(),
+            kind,
+            user: self.registers.get_cpl() == CurrentPrivilegeLevel::CPL3,
+            write_protect: control_registers.get_write_protect(),
+            large_pages: control_registers.get_page_size_extensions(),
+        };
+        let start = self.translate_page(linear, access)?;
+        let last = linear.wrapping_add(length.saturating_sub(1));
+        if last / paging::PAGE_SIZE == linear / paging::PAGE_SIZE {
+            return Ok(start.into());
+        }
+
+        let frame = self.translate_page(last & !(paging::PAGE_SIZE - 1), access)?;
+        Ok(PhysicalAddress {
+            start,
+            split: Some((paging::PAGE_SIZE - linear % paging::PAGE_SIZE, frame)),
+        })
+    }
+
+    /// Translates the linear address of an access within a single page.
+    fn translate_page(&self, linear: u32, access: paging::Access) -> Result<u32, Error> {
+        let translation = self
+            .tlb
+            .borrow_mut()
+            .translate(&self.memory, linear, access);
+        match translation {
+            Ok((physical, updates)) => {
+                self.paging_entry_updates.borrow_mut().extend(updates);
+                Ok(physical)
+            }
+            Err(page_fault) => {
+                if self.fault.get().is_none() {
+                    self.page_fault.set(Some(page_fault));
+                }
+                self.latch_fault(CpuException::PageFault);
+                Err(Error::ProtectionViolation(format!(
+                    "accessing {linear:#x} raised a page fault with error code {:#x}",
+                    page_fault.error_code
+                )))
+            }
+        }
+    }
+
+    /// Sets the accessed and dirty flags in the paging-structure entries which have been walked
+    /// by the translations made since this was last called. This cannot be done by the walk
+    /// itself, as translation only borrows the CPU (and so memory) immutably.
+    pub(crate) fn update_paging_entries(&mut self) {
+        for (address, flags) in self.paging_entry_updates.take() {
+            if let Ok(entry) = self.memory.read32(address) {
+                let _ = self.memory.write32(address, entry | flags);
+            }
+        }
+    }
+
+    /// Translates an offset within a segment into the linear address of an access of `length`
+    /// bytes, by adding the base of the segment. If any of the bytes are beyond the limit of the
+    /// segment, then a #GP exception is latched (or #SS, if it is the stack segment), and an `Err`
+    /// is returned.
+    pub(crate) fn linear_address(
+        &self,
+        segment: SegmentRegister,
+        offset: u32,
+        length: u32,
+    ) -> Result<u32, Error> {
+        self.checked_linear_address(segment, offset, length)
+            .ok_or_else(|| {
+                self.latch_fault(match segment {
+                    SegmentRegister::Ss => CpuException::StackFault,
+                    _ => CpuException::GeneralProtection,
+                });
+                Error::ProtectionViolation(format!(
+                    "accessing {length} bytes at {segment}:{offset:#x} exceeds the segment limit \
+                     of {:#x}",
+                    self.registers.get_segment(segment).limit
+                ))
+            })
+    }
+
+    /// As `Cpu::linear_address`, but returns `None` rather than faulting if any of the bytes are
+    /// beyond the limit of the segment.
+    fn checked_linear_address(
+        &self,
+        segment: SegmentRegister,
+        offset: u32,
+        length: u32,
+    ) -> Option<u32> {
+        let Segment {
+            base,
+            limit,
+            usable,
+            ..
+        } = self.registers.get_segment(segment);
+        if !usable {
+            return None;
+        }
+        let last = offset.checked_add(length.saturating_sub(1))?;
+        (last <= limit).then(|| base.wrapping_add(offset))
+    }
+
+    /// Loads a selector into a segment register. In real mode, only the base changes (see
+    /// `Registers::load_segment`). In protected mode, the descriptor which the selector refers to is
+    /// read from the GDT or LDT, and its base, limit, and access rights are cached in the segment
+    /// register. If the descriptor cannot be loaded into the register, then the register is left
+    /// as it was, and a #GP exception is latched (or #NP, or #SS for SS, if the segment is not
+    /// present).
+    pub(crate) fn load_segment(&mut self, segment: SegmentRegister, selector: u16) {
+        if self.registers.get_operating_mode() != OperatingMode::Protected {
+            self.registers.load_segment(segment, selector);
+            return;
+        }
+
+        match self.protected_mode_segment(segment, Selector(selector)) {
+            Ok(cached) => {
+                self.registers.load_segment(segment, selector);
+                self.registers.set_segment(segment, cached);
+            }
+            Err(exception) => self.latch_fault(exception),
+        }
+    }
+
+    /// Reads the descriptor which `selector` refers to, and checks that it may be loaded into
+    /// `segment`, returning what is cached in the segment register. Intel manual, volume 2, the
+    /// "Protected Mode Exceptions" of `MOV` and `POP`.
+    fn protected_mode_segment(
+        &self,
+        segment: SegmentRegister,
+        selector: Selector,
+    ) -> Result<Segment, CpuException> {
+        use SegmentRegister::*;
+        if selector.is_null() {
+            return match segment {
+                Cs | Ss => Err(CpuException::GeneralProtection),
+                _ => Ok(Segment {
+                    base: 0,
+                    limit: 0,
+                    rights: None,
+                    usable: false,
+                    big: true,
+                }),
+            };
+        }
+
+        let descriptor = self
+            .read_descriptor(selector)
+            .ok_or(CpuException::GeneralProtection)?;
+        let cpl = self.registers.get_cpl() as u8;
+        let rpl = selector.rpl();
+        let AccessRights { kind, dpl } = descriptor.rights;
+        let permitted = match (segment, kind) {
+            (Ss, DescriptorKind::Data { writable, .. }) => writable && rpl == cpl && dpl == cpl,
+            (Cs, DescriptorKind::Code { conforming, .. }) => {
+                // A far return may only return to the same or an outer privilege level.
+                rpl >= cpl && if conforming { dpl <= rpl } else { dpl == rpl }
+            }
+            (Ss | Cs, _) => false,
+            (_, DescriptorKind::Data { .. }) => dpl >= cpl.max(rpl),
+            (
+                _,
+                DescriptorKind::Code {
+                    readable,
+                    conforming,
+                },
+            ) => readable && (conforming || dpl >= cpl.max(rpl)),
+            (_, DescriptorKind::System(_)) => false,
+        };
+        if !permitted {
+            return Err(CpuException::GeneralProtection);
+        }
+        if !descriptor.present {
+            return Err(match segment {
+                Ss => CpuException::StackFault,
+                _ => CpuException::SegmentNotPresent,
+            });
+        }
+
+        Ok(Segment {
+            base: descriptor.base,
+            limit: descriptor.limit,
+            rights: Some(descriptor.rights),
+            usable: true,
+            big: descriptor.big,
+        })
+    }
+
+    /// Intel manual section 7.3 "TASK SWITCHING".
+    /// Switches from the running task to the task whose TSS descriptor `selector` refers to. The
+    /// state of the running task is saved to its TSS, and the state of the new task (see
+    /// `TaskState`) is loaded from its own, including CR3, the LDT, and the segment registers, so
+    /// it resumes on whichever stack it was using when it was last switched away from. CR0.TS is
+    /// set, so that the new task's first x87 or SIMD instruction can save the old task's state.
+    /// The new TSS is checked before anything is saved, but a segment of the new task which cannot
+    /// be loaded raises #TS once the switch has been made.
+    pub(crate) fn switch_task(
+        &mut self,
+        selector: Selector,
+        switch: TaskSwitch,
+    ) -> Result<(), Error> {
+        let expected = match switch {
+            TaskSwitch::Iret => BUSY_TSS_TYPE,
+            TaskSwitch::Call | TaskSwitch::Jump => AVAILABLE_TSS_TYPE,
+        };
+        let descriptor = (!selector.local())
+            .then(|| self.read_descriptor(selector))
+            .flatten()
+            .filter(|descriptor| descriptor.rights.kind == DescriptorKind::System(expected));
+        let descriptor = match descriptor {
+            None if switch == TaskSwitch::Iret => {
+                self.latch_fault(CpuException::InvalidTss);
+                return Ok(());
+            }
+            None => {
+                self.latch_fault(CpuException::GeneralProtection);
+                return Ok(());
+            }
+            Some(descriptor) if !descriptor.present => {
+                self.latch_fault(CpuException::SegmentNotPresent);
+                return Ok(());
+            }
+            Some(descriptor)
+                if descriptor.limit < TASK_STATE_SIZE as u32 - 1
+                    || Selector(self.registers.tr).is_null() =>
+            {
+                self.latch_fault(CpuException::InvalidTss);
+                return Ok(());
+            }
+            Some(descriptor) => descriptor,
+        };
+        let mut outgoing = self.read_task_state(self.registers.tss.base)?;
+        let mut incoming = self.read_task_state(descriptor.base)?;
+        if self.fault.get().is_some() {
+            return Ok(());
+        }
+
+        let registers = &self.registers;
+        let mut eflags = registers.eflags.clone();
+        if switch == TaskSwitch::Iret {
+            eflags.set_nested_task(false);
+        }
+        outgoing.eip = registers.get_eip();
+        outgoing.eflags = eflags.get_value();
+        outgoing.general_purpose = [
+            registers.eax,
+            registers.ecx,
+            registers.edx,
+            registers.ebx,
+            registers.esp,
+            registers.ebp,
+            registers.esi,
+            registers.edi,
+        ];
+        outgoing.segments = SegmentRegister::ALL.map(|segment| registers.get_selector(segment));
+        self.write_task_state(self.registers.tss.base, &outgoing)?;
+        if switch != TaskSwitch::Call {
+            self.set_task_busy(Selector(self.registers.tr), false)?;
+        }
+        if switch == TaskSwitch::Call {
+            incoming.link = self.registers.tr;
+            let address = self.physical_address(descriptor.base, 2, AccessKind::Write)?;
+            self.memory.write16(address, incoming.link)?;
+        }
+        if switch != TaskSwitch::Iret {
+            self.set_task_busy(selector, true)?;
+        }
+
+        self.load_task_register(selector, descriptor);
+        self.registers.control_registers.set_task_switched(true);
+        self.load_task_state(&incoming);
+        if switch == TaskSwitch::Call {
+            self.registers.eflags.set_nested_task(true);
+        }
+        Ok(())
+    }
+
+    /// Loads the state of the task which is being switched to. Selectors which cannot be loaded
+    /// raise #TS rather than #GP.
+    fn load_task_state(&mut self, state: &TaskState) {
+        if self.registers.control_registers.get_paging() {
+            self.registers.control_registers.set_cr3(state.cr3);
+        }
+        self.registers.eflags.set_value(state.eflags);
+        self.registers.set_eip(state.eip);
+        let [eax, ecx, edx, ebx, esp, ebp, esi, edi] = state.general_purpose;
+        let registers = &mut self.registers;
+        (registers.eax, registers.ecx, registers.edx, registers.ebx) = (eax, ecx, edx, ebx);
+        (registers.esp, registers.ebp, registers.esi, registers.edi) = (esp, ebp, esi, edi);
+
+        let as_invalid_tss = |exception| match exception {
+            CpuException::GeneralProtection => CpuException::InvalidTss,
+            exception => exception,
+        };
+        if let Err(exception) = self.load_ldt(Selector(state.ldt)) {
+            self.latch_fault(as_invalid_tss(exception));
+        }
+
+        // The CPL of the new task is the RPL of its CS selector, which the other segments are
+        // checked against.
+        let segments = state.segments;
+        self.registers
+            .load_segment(SegmentRegister::Cs, segments[SegmentRegister::Cs.index()]);
+        for segment in SegmentRegister::ALL {
+            let selector = segments[segment.index()];
+            if self.registers.get_operating_mode() != OperatingMode::Protected {
+                self.registers.load_segment(segment, selector);
+                continue;
+            }
+            match self.protected_mode_segment(segment, Selector(selector)) {
+                Ok(cached) => {
+                    self.registers.load_segment(segment, selector);
+                    self.registers.set_segment(segment, cached);
+                }
+                Err(exception) => self.latch_fault(as_invalid_tss(exception)),
+            }
+        }
+    }
+
+    /// Loads the task register with a selector of a TSS descriptor, caching the base and limit of
+    /// the TSS.
+    fn load_task_register(&mut self, selector: Selector, descriptor: SegmentDescriptor) {
+        self.registers.tr = selector.0;
+        self.registers.tss = Segment {
+            base: descriptor.base,
+            limit: descriptor.limit,
+            rights: Some(descriptor.rights),
+            usable: true,
+            big: descriptor.big,
+        };
+    }
+
+    /// Sets or clears the busy flag of the TSS descriptor which `selector` refers to in the GDT.
+    fn set_task_busy(&mut self, selector: Selector, busy: bool) -> Result<(), Error> {
+        let linear = self.registers.gdtr.base.wrapping_add(selector.offset());
+        let address = self.physical_address(linear, DESCRIPTOR_SIZE, AccessKind::Write)?;
+        let descriptor = self.memory.read64(address)?;
+        let descriptor = if busy {
+            descriptor | BUSY_FLAG
+        } else {
+            descriptor & !BUSY_FLAG
+        };
+        self.memory.write64(address, descriptor)
+    }
+
+    fn read_task_state(&self, base: u32) -> Result<TaskState, Error> {
+        let address = self.physical_address(base, TASK_STATE_SIZE as u32, AccessKind::Read)?;
+        let bytes = self.memory.read_bytes(address, TASK_STATE_SIZE as u32)?;
+        let bytes: [u8; TASK_STATE_SIZE] = bytes.try_into().unwrap();
+        Ok(TaskState::from(&bytes))
+    }
+
+    fn write_task_state(&mut self, base: u32, state: &TaskState) -> Result<(), Error> {
+        let address = self.physical_address(base, TASK_STATE_SIZE as u32, AccessKind::Write)?;
+        self.memory.write_bytes(address, &state.to_bytes())
+    }
+
+    /// Reads the descriptor which `selector` refers to from the GDT or the LDT. `None` is returned
+    /// if the descriptor is beyond the limit of its table, or if it is in the LDT and none has
+    /// been loaded.
+    fn read_descriptor(&self, selector: Selector) -> Option<SegmentDescriptor> {
+        let (base, limit) = if selector.local() {
+            if Selector(self.registers.ldtr).is_null() {
+                return None;
+            }
+            (self.registers.ldt.base, self.registers.ldt.limit)
+        } else {
+            (self.registers.gdtr.base, self.registers.gdtr.limit as u32)
+        };
+        let last = selector.offset().checked_add(DESCRIPTOR_SIZE - 1)?;
+        if last > limit {
+            return None;
+        }
+        let linear = base.wrapping_add(selector.offset());
+        self.physical_address(linear, DESCRIPTOR_SIZE, AccessKind::Read)
+            .and_then(|address| self.memory.read64(address))
+            .ok()
+            .map(SegmentDescriptor::from)
+    }
+
+    /// Pops a 16-bit (WORD) value off the stack, adjusting the stack pointer as required.
+    /// If the value cannot be read from the top of the stack, then an `Err` is returned, and the
+    /// stack pointer is left as it is.
+    fn pop16(&mut self) -> Result<u16, Error> {
+        let address = self.stack_top(Size::Word, AccessKind::Read)?;
+        let value = self.memory.read16(address)?;
+        self.registers.shrink_stack(&Size::Word);
+        Ok(value)
+    }
+
+    /// Pops a 32-bit (DWORD) value off the stack, adjusting the stack pointer as required.
+    /// If the value cannot be read from the top of the stack, then an `Err` is returned, and the
+    /// stack pointer is left as it is.
+    fn pop32(&mut self) -> Result<u32, Error> {
+        let address = self.stack_top(Size::Dword, AccessKind::Read)?;
+        let value = self.memory.read32(address)?;
+        self.registers.shrink_stack(&Size::Dword);
+        Ok(value)
+    }
+
+    pub(crate) fn pop_ds(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let selector = self.pop16()?;
+        self.load_segment(SegmentRegister::Ds, selector);
+        Ok(())
+    }
+
+    pub(crate) fn pop_es(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let selector = self.pop16()?;
+        self.load_segment(SegmentRegister::Es, selector);
+        Ok(())
+    }
+
+    pub(crate) fn pop_ss(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let selector = self.pop16()?;
+        self.load_segment(SegmentRegister::Ss, selector);
+        Ok(())
+    }
+
+    pub(crate) fn pop_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg16 = unwrap_operands!(operands, &Register16);
+        let popped = self.pop16()?;
+        reg16.write(&mut self.registers, popped);
+        Ok(())
+    }
+
+    pub(crate) fn pop_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg32 = unwrap_operands!(operands, &Register32);
+        let popped = self.pop32()?;
+        reg32.write(&mut self.registers, popped);
+        Ok(())
+    }
+
+    /// Moves the stack pointer down to make room for a value of the given size. If this would
+    /// overflow the stack (i.e. wrap ESP past zero, or move the stack pointer below the stack
+    /// limit), then it is left as it is, the overflow is recorded and a #SS exception is latched
+    /// (such that the instruction is abandoned), and an `Err` is returned. SP wrapping past zero
+    /// is not an overflow, as that is how a 16-bit stack uses the whole of its segment.
+    fn grow_stack(&mut self, size: Size) -> Result<(), Error> {
+        let esp = self.registers.esp;
+        let stack_pointer = self.registers.get_stack_pointer();
+        let (grown, wrapped) = stack_pointer.overflowing_sub(size as u32 / 8);
+        let big = self.registers.get_segment(SegmentRegister::Ss).big;
+        let grown = if big { grown } else { grown & 0xffff };
+        if wrapped && big || self.stack_limit.is_some_and(|limit| grown < limit) {
+            self.stack_overflow.get_or_insert(esp);
+            self.latch_fault(CpuException::StackFault);
+            return Err(Error::StackOverflow(format!(
+                "pushing {} bytes would move ESP past the end of the stack, from {esp:#x}",
+                size as u32 / 8
+            )));
+        }
+        self.registers.grow_stack(&size);
+        Ok(())
+    }
+
+    /// Pushes a 16-bit (WORD) value onto the stack, adjusting the stack pointer as required.
+    /// If the stack would overflow, or the value cannot be written to the new top of the stack,
+    /// then an `Err` is returned, and the fault is latched if it was raised by the access.
+    fn push16(&mut self, value: u16) -> Result<(), Error> {
+        self.grow_stack(Size::Word)?;
+        let address = self.stack_top(Size::Word, AccessKind::Write)?;
+        self.memory.write16(address, value)
+    }
+
+    /// Pushes a 32-bit (DWORD) value onto the stack, adjusting the stack pointer as required.
+    /// If the stack would overflow, or the value cannot be written to the new top of the stack,
+    /// then an `Err` is returned, and the fault is latched if it was raised by the access.
+    fn push32(&mut self, value: u32) -> Result<(), Error> {
+        self.grow_stack(Size::Dword)?;
+        let address = self.stack_top(Size::Dword, AccessKind::Write)?;
+        self.memory.write32(address, value)
+    }
+
+    pub(crate) fn push_cs(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.push16(self.registers.cs)?;
+        Ok(())
+    }
+
+    pub(crate) fn push_ds(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.push16(self.registers.ds)?;
+        Ok(())
+    }
+
+    pub(crate) fn push_es(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.push16(self.registers.es)?;
+        Ok(())
+    }
+
+    pub(crate) fn push_ss(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.push16(self.registers.ss)?;
+        Ok(())
+    }
+
+    pub(crate) fn push_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg16 = unwrap_operands!(operands, &Register16);
+        self.push16(reg16.read(&self.registers))?;
+        Ok(())
+    }
+
+    pub(crate) fn push_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg32 = unwrap_operands!(operands, &Register32);
+        self.push32(reg32.read(&self.registers))?;
+        Ok(())
+    }
+
+    /// Draws a random number from the entropy source. If one was available, then it is written to
+    /// the destination and the CF flag is set. Otherwise, the destination is cleared along with the
+    /// CF flag. The OF, SF, ZF, AF, and PF flags are always cleared.
+    fn rdrand(&mut self) -> Option<u32> {
+        let value = self.random_number_generator.next_u32();
+        self.registers.eflags.set_overflow_flag(false);
+        self.registers.eflags.set_sign_flag(false);
+        self.registers.eflags.set_zero_flag(false);
+        self.registers.eflags.set_auxiliary_carry_flag(false);
+        self.registers.eflags.set_parity_flag(false);
+        self.registers.eflags.set_carry_flag(value.is_some());
+        value
+    }
+
+    pub(crate) fn rdrand_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg16 = unwrap_operands!(operands, &Register16);
+        let value = self.rdrand().unwrap_or(0);
+        self.registers.write16(reg16, value as u16);
+        Ok(())
+    }
+
+    pub(crate) fn rdrand_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg32 = unwrap_operands!(operands, &Register32);
+        let value = self.rdrand().unwrap_or(0);
+        self.registers.write32(reg32, value);
+        Ok(())
+    }
+
+    /// Reads the model-specific register addressed by ECX into EDX:EAX, with the high-order 32
+    /// bits in EDX and the low-order 32 bits in EAX. This is a privileged instruction, and a #GP
+    /// exception is raised if the MSR does not exist.
+    pub(crate) fn rdmsr(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let address = self.registers.get_ecx();
+        let value = match address {
+            msr::IA32_TIME_STAMP_COUNTER => Some(self.time_stamp_counter.get()),
+            _ => self.model_specific_registers.read(address),
+        };
+
+        let Some(value) = value else {
+            self.raise_exception(CpuException::GeneralProtection);
+            return Ok(());
+        };
+        self.registers.set_edx((value >> 32) as u32);
+        self.registers.set_eax(value as u32);
+        Ok(())
+    }
+
+    /// Reads the time-stamp counter into EDX:EAX, with the high-order 32 bits in EDX and the
+    /// low-order 32 bits in EAX.
+    pub(crate) fn rdtsc(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let value = self.time_stamp_counter.get();
+        self.registers.set_edx((value >> 32) as u32);
+        self.registers.set_eax(value as u32);
+        Ok(())
+    }
+
+    /// Returns from a procedure by popping the return address off the stack into EIP (or into IP,
+    /// with a 16-bit default operand size).
+    pub(crate) fn ret(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let eip = self.pop_return_address()?;
+        self.branch(eip);
+        Ok(())
+    }
+
+    /// Returns from a procedure as `RET` does, and then releases `imm16` bytes of parameters from
+    /// the stack.
+    pub(crate) fn ret_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let imm16 = unwrap_operands!(operands, Immediate16);
+        let eip = self.pop_return_address()?;
+        self.branch(eip);
+        let stack_pointer = self.registers.get_stack_pointer();
+        self.registers
+            .set_stack_pointer(stack_pointer.wrapping_add(imm16.0 as u32));
+        Ok(())
+    }
+
+    /// Pops the return address of a near call, which is a WORD with a 16-bit default operand
+    /// size, and a DWORD otherwise.
+    fn pop_return_address(&mut self) -> Result<u32, Error> {
+        match self.registers.get_default_size() {
+            Size::Word => self.pop16().map(u32::from),
+            _ => self.pop32(),
+        }
+    }
+
+    /// Returns from a procedure which was called with a far `CALL`, by popping EIP and then CS
+    /// (each of which is a WORD with a 16-bit default operand size). In protected mode, returning
+    /// to an outer privilege level also pops ESP and SS (see `Cpu::pop_outer_stack`).
+    pub(crate) fn retf(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let eip = self.pop_return_address()?;
+        let selector = self.pop_return_address()? as u16;
+        let outer_stack = self.pop_outer_stack(selector, self.registers.get_default_size())?;
+        self.load_segment(SegmentRegister::Cs, selector);
+        self.load_outer_stack(outer_stack);
+        self.branch(eip);
+        Ok(())
+    }
+
+    /// Pops the stack of the caller (ESP and then SS) if a far return to `selector` is to an outer
+    /// privilege level, i.e. if its RPL is greater than the CPL in protected mode. Each is popped
+    /// as `size`, with the upper WORD of SS discarded.
+    fn pop_outer_stack(&mut self, selector: u16, size: Size) -> Result<Option<(u32, u16)>, Error> {
+        if self.registers.get_operating_mode() != OperatingMode::Protected
+            || Selector(selector).rpl() <= self.registers.get_cpl() as u8
+        {
+            return Ok(None);
+        }
+
+        let pop = |cpu: &mut Self| match size {
+            Size::Word => cpu.pop16().map(u32::from),
+            _ => cpu.pop32(),
+        };
+        let esp = pop(self)?;
+        let ss = pop(self)? as u16;
+        Ok(Some((esp, ss)))
+    }
+
+    /// Switches to the stack which was popped by `Cpu::pop_outer_stack`, once CS has been loaded
+    /// for the outer privilege level. The data segment registers which refer to segments that are
+    /// more privileged than the new CPL (other than conforming code segments) are loaded with the
+    /// null selector, so that they cannot be used to access those segments.
+    fn load_outer_stack(&mut self, stack: Option<(u32, u16)>) {
+        let Some((esp, ss)) = stack else {
+            return;
+        };
+        self.load_segment(SegmentRegister::Ss, ss);
+        self.registers.esp = esp;
+
+        let cpl = self.registers.get_cpl() as u8;
+        for segment in [
+            SegmentRegister::Es,
+            SegmentRegister::Ds,
+            SegmentRegister::Fs,
+            SegmentRegister::Gs,
+        ] {
+            let inner = match self.registers.get_segment(segment).rights {
+                Some(AccessRights {
+                    kind:
+                        DescriptorKind::Code {
+                            conforming: true, ..
+                        },
+                    ..
+                })
+                | None => false,
+                Some(rights) => rights.dpl < cpl,
+            };
+            if inner {
+                self.load_segment(segment, 0);
+            }
+        }
+    }
+
+    /// Set AL from carry. AL is set to 0xff if the CF flag is set, and cleared otherwise. No flags
+    /// are affected. This instruction is undocumented.
+    pub(crate) fn salc(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if !self.undocumented() {
+            return Ok(());
+        }
+
+        let al = if self.registers.eflags.get_carry_flag() {
+            0xff
+        } else {
+            0
+        };
+        self.registers.set_al(al);
+        Ok(())
+    }
+
+    /// Integer subtraction with borrow. Adds the source and the carry flag, and subtracts the
+    /// result from the destination. Sets the OF, SF, ZF, AF, PF, and CF flags according to the
+    /// result.
+    // TODO: Test
+    fn sbb<T>(&mut self, lhs: T, rhs: T) -> T
+    where
+        T: PrimInt + WrappingSub + AsUnsigned + FromPrimitive,
+    {
+        let result = self.wrapping_sub(lhs, rhs, WithCarry::True);
+        self.registers
+            .eflags
+            .compute_overflow_flag(lhs, rhs, result, Operation::Subtract);
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers
+            .eflags
+            .compute_auxiliary_carry_flag(lhs, rhs, Operation::Subtract);
+        self.registers.eflags.compute_parity_flag(result);
+        self.registers
+            .eflags
+            .compute_carry_flag(lhs, rhs, result, Operation::Subtract);
+        result
+    }
+
+    pub(crate) fn sbb_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let result = self.sbb(self.registers.get_al(), imm8.0);
+        self.registers.set_al(result);
+        Ok(())
+    }
+
+    pub(crate) fn sbb_ax_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, imm16) = unwrap_operands!(operands, &Register16, Immediate16);
+        let result = self.sbb(self.registers.get_ax(), imm16.0);
+        self.registers.set_ax(result);
+        Ok(())
+    }
+
+    pub(crate) fn sbb_eax_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, imm32) = unwrap_operands!(operands, &Register32, Immediate32);
+        let result = self.sbb(self.registers.get_eax(), imm32.0);
+        self.registers.set_eax(result);
+        Ok(())
+    }
+
+    pub(crate) fn sbb_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
+        let result = self.sbb(reg8.read(&self.registers), rm8.read(self)?);
+        self.registers.write8(reg8, result);
+        Ok(())
+    }
+
+    pub(crate) fn sbb_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
+        let result = self.sbb(reg16.read(&self.registers), rm16.read(self)?);
+        self.registers.write16(reg16, result);
+        Ok(())
+    }
+
+    pub(crate) fn sbb_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
+        let result = self.sbb(self.registers.read32(reg32), rm32.read(self)?);
+        self.registers.write32(reg32, result);
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.sbb(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
+        let result = self.sbb(rm8.read(self)?, reg8.read(&self.registers));
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.sbb(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.sbb(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
+        let result = self.sbb(rm16.read(self)?, reg16.read(&self.registers));
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.sbb(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.sbb(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sbb_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
+        let result = self.sbb(rm32.read(self)?, self.registers.read32(reg32));
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    /// Compares the accumulator with the element at [EDI] by subtracting the latter from the
+    /// former, setting the flags as `CMP` would.
+    pub(crate) fn scasb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_comparison(|cpu| {
+            let rhs = cpu
+                .string_destination(Size::Byte, AccessKind::Read)
+                .and_then(|address| cpu.memory.read8(address))?;
+            cpu.cmp(cpu.registers.get_al(), rhs);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Byte);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn scasw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_comparison(|cpu| {
+            let rhs = cpu
+                .string_destination(Size::Word, AccessKind::Read)
+                .and_then(|address| cpu.memory.read16(address))?;
+            cpu.cmp(cpu.registers.get_ax(), rhs);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Word);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn scasd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_comparison(|cpu| {
+            let rhs = cpu
+                .string_destination(Size::Dword, AccessKind::Read)
+                .and_then(|address| cpu.memory.read32(address))?;
+            cpu.cmp(cpu.registers.get_eax(), rhs);
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Dword);
+            Ok(())
+        })
+    }
+
+    /// Serializes all prior stores. As every memory access completes before the next instruction
+    /// executes, there is nothing to order and this is a no-op.
+    pub(crate) fn sfence(&mut self, _operands: &Operands) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Stores the GDTR to memory as a pseudo-descriptor. Unlike `LGDT`, this is not privileged.
+    pub(crate) fn sgdt_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem = unwrap_operands!(operands, &EffectiveAddress);
+        let address = mem.translate_bytes(self, PSEUDO_DESCRIPTOR_LENGTH, AccessKind::Write)?;
+        self.write_pseudo_descriptor(address, self.registers.gdtr.clone())
+    }
+
+    /// Stores the IDTR to memory as a pseudo-descriptor. Unlike `LIDT`, this is not privileged.
+    pub(crate) fn sidt_mem(&mut self, operands: &Operands) -> Result<(), Error> {
+        let mem = unwrap_operands!(operands, &EffectiveAddress);
+        let address = mem.translate_bytes(self, PSEUDO_DESCRIPTOR_LENGTH, AccessKind::Write)?;
+        self.write_pseudo_descriptor(address, self.registers.idtr.clone())
+    }
+
+    /// Stores the LDTR's selector. This is not privileged, but is only recognized in protected
+    /// mode.
+    pub(crate) fn sldt_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rm16 = unwrap_operands!(operands, RegisterOrMemory16);
+        if !self.protected_mode() {
+            return Ok(());
+        }
+
+        rm16.write(self, self.registers.ldtr)?;
+        Ok(())
+    }
+
+    /// Stores the machine status word, i.e. the low 16 bits of CR0. Unlike `LMSW`, this is not
+    /// privileged.
+    pub(crate) fn smsw_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rm16 = unwrap_operands!(operands, RegisterOrMemory16);
+        let value = self.registers.control_registers.get_cr0() as u16;
+        rm16.write(self, value)?;
+        Ok(())
+    }
+
+    /// Stores the machine status word into a 32-bit register. The entire CR0 register is stored.
+    pub(crate) fn smsw_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let reg32 = unwrap_operands!(operands, &Register32);
+        let value = self.registers.control_registers.get_cr0();
+        self.registers.write32(reg32, value);
+        Ok(())
+    }
+
+    pub(crate) fn stosb(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if self
+            .repeat_string_block(|cpu, count| Some(vec![cpu.registers.get_al(); count as usize]))?
+        {
+            return Ok(());
+        }
+
+        self.repeat_string_operation(|cpu| {
+            cpu.string_destination(Size::Byte, AccessKind::Write)
+                .and_then(|address| cpu.memory.write8(address, cpu.registers.get_al()))?;
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Byte);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn stosw(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            cpu.string_destination(Size::Word, AccessKind::Write)
+                .and_then(|address| cpu.memory.write16(address, cpu.registers.get_ax()))?;
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Word);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn stosd(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.repeat_string_operation(|cpu| {
+            cpu.string_destination(Size::Dword, AccessKind::Write)
+                .and_then(|address| cpu.memory.write32(address, cpu.registers.get_eax()))?;
+            cpu.registers.edi = cpu.next_string_index(cpu.registers.edi, Size::Dword);
+            Ok(())
+        })
+    }
+
+    /// Stores the task register's selector. This is not privileged, but is only recognized in
+    /// protected mode.
+    pub(crate) fn str_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let rm16 = unwrap_operands!(operands, RegisterOrMemory16);
+        if !self.protected_mode() {
+            return Ok(());
+        }
+
+        rm16.write(self, self.registers.tr)?;
+        Ok(())
+    }
+
+    /// Integer subtraction. Adds the source and the carry flag, and subtracts the result from the
+    /// destination. Sets the OF, SF, ZF, AF, PF, and CF flags according to the result.
+    fn sub<T>(&mut self, lhs: T, rhs: T) -> T
+    where
+        T: PrimInt + WrappingSub + AsUnsigned + FromPrimitive,
+    {
+        let result = self.wrapping_sub(lhs, rhs, WithCarry::False);
+        self.registers
+            .eflags
+            .compute_overflow_flag(lhs, rhs, result, Operation::Subtract);
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers
+            .eflags
+            .compute_auxiliary_carry_flag(lhs, rhs, Operation::Subtract);
+        self.registers.eflags.compute_parity_flag(result);
+        self.registers
+            .eflags
+            .compute_carry_flag(lhs, rhs, result, Operation::Subtract);
+        result
+    }
+
+    pub(crate) fn sub_al_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_al, imm8) = unwrap_operands!(operands, &Register8, Immediate8);
+        let result = self.sub(self.registers.get_al(), imm8.0);
+        self.registers.set_al(result);
+        Ok(())
+    }
+
+    pub(crate) fn sub_ax_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_ax, imm16) = unwrap_operands!(operands, &Register16, Immediate16);
+        let result = self.sub(self.registers.get_ax(), imm16.0);
+        self.registers.set_ax(result);
+        Ok(())
+    }
+
+    pub(crate) fn sub_eax_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (_eax, imm32) = unwrap_operands!(operands, &Register32, Immediate32);
+        let result = self.sub(self.registers.get_eax(), imm32.0);
+        self.registers.set_eax(result);
+        Ok(())
+    }
+
+    pub(crate) fn sub_reg8_rm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg8, rm8) = unwrap_operands!(operands, &Register8, RegisterOrMemory8);
+        let result = self.sub(reg8.read(&self.registers), rm8.read(self)?);
+        self.registers.write8(reg8, result);
+        Ok(())
+    }
+
+    pub(crate) fn sub_reg16_rm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg16, rm16) = unwrap_operands!(operands, &Register16, RegisterOrMemory16);
+        let result = self.sub(reg16.read(&self.registers), rm16.read(self)?);
+        self.registers.write16(reg16, result);
+        Ok(())
+    }
+
+    pub(crate) fn sub_reg32_rm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (reg32, rm32) = unwrap_operands!(operands, &Register32, RegisterOrMemory32);
+        let result = self.sub(self.registers.read32(reg32), rm32.read(self)?);
+        self.registers.write32(reg32, result);
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.sub(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
+        let result = self.sub(rm8.read(self)?, reg8.read(&self.registers));
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.sub(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.sub(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
+        let result = self.sub(rm16.read(self)?, reg16.read(&self.registers));
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.sub(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.sub(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn sub_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
+        let result = self.sub(rm32.read(self)?, reg32.read(&self.registers));
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    /// Subtracts packed double-precision floating-point values.
+    pub(crate) fn subpd_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f64>(operands, ArithmeticOperation::Subtract)
+    }
+
+    /// Subtracts packed single-precision floating-point values.
+    pub(crate) fn subps_xmm_xmm128(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.packed_arithmetic::<f32>(operands, ArithmeticOperation::Subtract)
+    }
+
+    /// Subtracts the low double-precision floating-point values.
+    pub(crate) fn subsd_xmm_xmm64(&mut self, operands: &Operands) -> Result<(), Error> {
+        self.scalar_double_arithmetic(operands, ArithmeticOperation::Subtract)
+    }
+
+    /// Gets the code segment selector for CPL 0 which is configured in the SYSENTER_CS MSR. If it
+    /// is null, or the processor is not in protected mode, then a #GP exception is raised and
+    /// `None` is returned.
+    fn sysenter_cs(&mut self) -> Option<u16> {
+        let cs = self
+            .model_specific_registers
+            .read(msr::IA32_SYSENTER_CS)
+            .unwrap() as u16;
+        if !self.registers.control_registers.get_protection_enable() || cs & 0xfffc == 0 {
+            self.raise_exception(CpuException::GeneralProtection);
+            return None;
+        }
+
+        Some(cs)
+    }
+
+    /// Fast system call, which transitions to CPL 0. CS is loaded from the SYSENTER_CS MSR and SS
+    /// is the selector after it, while ESP and EIP are loaded from the SYSENTER_ESP and
+    /// SYSENTER_EIP MSRs. The VM, IF, and RF flags are cleared. Nothing is saved for the return,
+    /// which is the responsibility of the caller (conventionally with EDX and ECX for `SYSEXIT`).
+    pub(crate) fn sysenter(&mut self, _operands: &Operands) -> Result<(), Error> {
+        let Some(cs) = self.sysenter_cs() else {
+            return Ok(());
+        };
+
+        let eflags = &mut self.registers.eflags;
+        eflags.set_virtual_8086_mode(false);
+        eflags.set_interrupt_enable_flag(false);
+        eflags.set_resume_flag(false);
+
+        self.load_flat_segments(cs & 0xfffc, (cs & 0xfffc).wrapping_add(8));
+        self.registers.esp = self
+            .model_specific_registers
+            .read(msr::IA32_SYSENTER_ESP)
+            .unwrap() as u32;
+        let eip = self
+            .model_specific_registers
+            .read(msr::IA32_SYSENTER_EIP)
+            .unwrap() as u32;
+        self.registers.set_eip(eip);
+        Ok(())
+    }
+
+    /// Fast return from a system call to CPL 3. CS and SS are the selectors 16 and 24 bytes after
+    /// the one in the SYSENTER_CS MSR, with an RPL of 3. ESP is loaded from ECX and EIP from EDX.
+    /// This is a privileged instruction.
+    pub(crate) fn sysexit(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let Some(cs) = self.sysenter_cs() else {
+            return Ok(());
+        };
+
+        self.load_flat_segments(cs.wrapping_add(16) | 0b11, cs.wrapping_add(24) | 0b11);
+        self.registers.esp = self.registers.get_ecx();
+        let eip = self.registers.get_edx();
+        self.registers.set_eip(eip);
+        Ok(())
+    }
+
+    /// Loads CS and SS for `SYSENTER` and `SYSEXIT`. Rather than being read from the GDT, their
+    /// descriptors are fixed: a flat code segment and a flat data segment, both at the RPL of the
+    /// CS selector.
+    fn load_flat_segments(&mut self, cs: u16, ss: u16) {
+        let dpl = Selector(cs).rpl();
+        for (segment, selector, kind) in [
+            (
+                SegmentRegister::Cs,
+                cs,
+                DescriptorKind::Code {
+                    readable: true,
+                    conforming: false,
+                },
+            ),
+            (
+                SegmentRegister::Ss,
+                ss,
+                DescriptorKind::Data {
+                    writable: true,
+                    expand_down: false,
+                },
+            ),
+        ] {
+            self.registers.load_segment(segment, selector);
+            self.registers.set_segment(
+                segment,
+                Segment {
+                    rights: Some(AccessRights { kind, dpl }),
+                    ..Segment::default()
+                },
+            );
+        }
+    }
+
+    /// Raises an invalid opcode (#UD) exception. This is intended for testing, and is
+    /// guaranteed to be an undefined instruction.
+    pub(crate) fn ud2(&mut self, _operands: &Operands) -> Result<(), Error> {
+        self.raise_exception(CpuException::InvalidOpcode);
+        Ok(())
+    }
+
+    /// Writes EDX:EAX into the model-specific register addressed by ECX, with the high-order 32
+    /// bits in EDX and the low-order 32 bits in EAX. This is a privileged instruction, and a #GP
+    /// exception is raised if the MSR does not exist.
+    pub(crate) fn wrmsr(&mut self, _operands: &Operands) -> Result<(), Error> {
+        if !self.privileged() {
+            return Ok(());
+        }
+
+        let address = self.registers.get_ecx();
+        let value = (self.registers.get_edx() as u64) << 32 | self.registers.get_eax() as u64;
+        if address == msr::IA32_TIME_STAMP_COUNTER {
+            self.time_stamp_counter.set(value);
+        } else if !self.model_specific_registers.write(address, value) {
+            self.raise_exception(CpuException::GeneralProtection);
+        }
+        Ok(())
+    }
+
+    /// Exchanges the operands, then stores their sum in the destination operand. Sets the OF, SF,
+    /// ZF, AF, PF, and CF flags as `ADD` would.
+    pub(crate) fn xadd_rm8_reg8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, reg8) = unwrap_operands!(operands, RegisterOrMemory8, &Register8);
+        let destination = rm8.read(self)?;
+        let result = self.add(destination, self.registers.read8(reg8));
+        self.registers.write8(reg8, destination);
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn xadd_rm16_reg16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, reg16) = unwrap_operands!(operands, RegisterOrMemory16, &Register16);
+        let destination = rm16.read(self)?;
+        let result = self.add(destination, self.registers.read16(reg16));
+        self.registers.write16(reg16, destination);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn xadd_rm32_reg32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, reg32) = unwrap_operands!(operands, RegisterOrMemory32, &Register32);
+        let destination = rm32.read(self)?;
+        let result = self.add(destination, self.registers.read32(reg32));
+        self.registers.write32(reg32, destination);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    /// Performs a bitwise exclusive OR operation. The OF and CF flags are cleared, and the SF, ZF,
+    /// and PF flags are set according to the result. The AF flag is undefined.
+    fn xor<T>(&mut self, lhs: T, rhs: T) -> T
+    where
+        T: PrimInt + BitXor<Output = T> + AsUnsigned + FromPrimitive,
+    {
+        let result = lhs ^ rhs;
+        self.registers.eflags.set_overflow_flag(false);
+        self.registers.eflags.set_carry_flag(false);
+        self.registers.eflags.compute_sign_flag(result);
+        self.registers.eflags.compute_zero_flag(result);
+        self.registers.eflags.compute_parity_flag(result);
+        result
+    }
+
+    pub(crate) fn xor_rm8_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm8, imm8) = unwrap_operands!(operands, RegisterOrMemory8, Immediate8);
+        let result = self.xor(rm8.read(self)?, imm8.0);
+        rm8.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn xor_rm16_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm8) = unwrap_operands!(operands, RegisterOrMemory16, Immediate8);
+        let result = self.xor(rm16.read(self)?, imm8.0 as i8 as u16);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn xor_rm16_imm16(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm16, imm16) = unwrap_operands!(operands, RegisterOrMemory16, Immediate16);
+        let result = self.xor(rm16.read(self)?, imm16.0);
+        rm16.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn xor_rm32_imm8(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm8) = unwrap_operands!(operands, RegisterOrMemory32, Immediate8);
+        let result = self.xor(rm32.read(self)?, imm8.0 as i8 as u32);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+
+    pub(crate) fn xor_rm32_imm32(&mut self, operands: &Operands) -> Result<(), Error> {
+        let (rm32, imm32) = unwrap_operands!(operands, RegisterOrMemory32, Immediate32);
+        let result = self.xor(rm32.read(self)?, imm32.0);
+        rm32.write(self, result)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        devices::tests::Latches,
+        fpu::RoundingMode,
+        instruction::{NasmStr, Operand},
+        sse,
+    };
+
+    macro_rules! assert_eflags {
+        (@ $cpu:ident, CF=$expected:literal) => {
+            assert_eq!($cpu.registers.eflags.get_carry_flag(), $expected, "CF is incorrect")
+        };
+        (@ $cpu:ident, PF=$expected:literal) => {
+            assert_eq!($cpu.registers.eflags.get_parity_flag(), $expected, "PF is incorrect")
+        };
+        (@ $cpu:ident, AF=$expected:literal) => {
+            assert_eq!(
+                $cpu.registers.eflags.get_auxiliary_carry_flag(),
+                $expected,
+                "AF is incorrect"
+            )
+        };
+        (@ $cpu:ident, ZF=$expected:literal) => {
+            assert_eq!($cpu.registers.eflags.get_zero_flag(), $expected, "ZF is incorrect")
+        };
+        (@ $cpu:ident, SF=$expected:literal) => {
+            assert_eq!($cpu.registers.eflags.get_sign_flag(), $expected, "SF is incorrect")
+        };
+        (@ $cpu:ident, OF=$expected:literal) => {
+            assert_eq!($cpu.registers.eflags.get_overflow_flag(), $expected, "OF is incorrect")
+        };
+        ($cpu:ident, $($flag:ident=$expected:literal),+) => {
+            $(assert_eflags!(@ $cpu, $flag=$expected));+
+        };
+    }
+
+    macro_rules! operands {
+        () => { Operands(vec![]) };
+        ($operand:literal) => { Operands(vec![Operand::try_from(&NasmStr($operand)).unwrap()])};
+        ($operand_a:literal, $operand_b:literal) => {
+            {
+                let mut operands = operands!($operand_a);
+                operands.0.append(&mut operands!($operand_b).0);
+                operands
+            }
+        };
+        ($operand:literal, $($tail:tt)*) => {
+            {
+                let mut operands = operands!($operand);
+                operands.0.append(&mut operands!($($tail)*).0);
+                operands
+            }
+        };
+    }
+
+    // https://stackoverflow.com/questions/8965923/carry-overflow-subtraction-in-x86#8982549
+    //       A                   B                   A + B              Flags
+    // ---------------     ----------------    ---------------      -----------------
+    // h  |  ud  |   d   | h  |  ud  |   d   | h  |  ud  |   d   | OF | SF | ZF | CF
+    // ---+------+-------+----+------+-------+----+------+-------+----+----+----+---
+    // 7F | 127  |  127  | 0  |  0   |   0   | 7F | 127  |  127  | 0  | 0  | 0  | 0
+    // FF | 255  |  -1   | 7F | 127  |  127  | 7E | 126  |  126  | 0  | 0  | 0  | 1
+    // 0  |  0   |   0   | 0  |  0   |   0   | 0  |  0   |   0   | 0  | 0  | 1  | 0
+    // FF | 255  |  -1   | 1  |  1   |   1   | 0  |  0   |   0   | 0  | 0  | 1  | 1
+    // FF | 255  |  -1   | 0  |  0   |   0   | FF | 255  |  -1   | 0  | 1  | 0  | 0
+    // FF | 255  |  -1   | FF | 255  |  -1   | FE | 254  |  -2   | 0  | 1  | 0  | 1
+    // FF | 255  |  -1   | 80 | 128  | -128  | 7F | 127  |  127  | 1  | 0  | 0  | 1
+    // 80 | 128  | -128  | 80 | 128  | -128  | 0  |  0   |   0   | 1  | 0  | 1  | 1
+    // 7F | 127  |  127  | 7F | 127  |  127  | FE | 254  |  -2   | 1  | 1  | 0  | 0
+    // TODO: Test for AF and PF.
+    #[test]
+    fn aaa() {
+        let mut cpu = Cpu::default();
+        // 8 + 6 = 14
+        cpu.registers.set_ax(0x000e);
+        cpu.aaa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0104);
+        assert_eflags!(cpu, AF = true, CF = true);
+
+        // 9 + 9 = 18, which carries out of the low nibble.
+        cpu.registers.set_ax(0x0012);
+        cpu.registers.eflags.set_auxiliary_carry_flag(true);
+        cpu.aaa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0108);
+        assert_eflags!(cpu, AF = true, CF = true);
+
+        // 3 + 4 = 7
+        cpu.registers.set_ax(0x0537);
+        cpu.registers.eflags.set_auxiliary_carry_flag(false);
+        cpu.aaa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0507);
+        assert_eflags!(cpu, AF = false, CF = false);
+    }
+
+    #[test]
+    fn aad() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ax(0x0603);
+        cpu.aad_base10(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 63);
+        assert_eflags!(cpu, ZF = false, SF = false, PF = true);
+
+        cpu.registers.set_ax(0x0f0f);
+        cpu.set_undocumented_instructions(true);
+        cpu.aad_imm8(&operands!("16")).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0xff);
+        assert_eflags!(cpu, ZF = false, SF = true, PF = true);
+
+        cpu.registers.set_ax(0);
+        cpu.aad_base10(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = true, SF = false);
+    }
+
+    fn set_eax_to_0x0(cpu: &mut Cpu) {
+        cpu.registers.set_eax(0x0);
+    }
+
+    #[test]
+    fn aam() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ax(0x003f);
+        cpu.aam_base10(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0603);
+        assert_eflags!(cpu, ZF = false, SF = false, PF = true);
+
+        cpu.registers.set_ax(0x00ff);
+        cpu.set_undocumented_instructions(true);
+        cpu.aam_imm8(&operands!("16")).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0f0f);
+
+        cpu.registers.set_ax(0x0050);
+        cpu.aam_base10(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0800);
+        assert_eflags!(cpu, ZF = true, PF = true);
+
+        // Dividing by 0 raises #DE, leaving AX untouched.
+        cpu.register_interrupt_handler(CpuException::DivideError.vector(), set_eax_to_0x0);
+        cpu.registers.set_eax(0x1234);
+        cpu.aam_imm8(&operands!("0")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
+    }
+
+    #[test]
+    fn aam_aad_undocumented_base() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_eax_to_0x5);
+        cpu.registers.set_ax(0x003f);
+        cpu.aam_imm8(&operands!("10")).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0603);
+        cpu.aad_imm8(&operands!("10")).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 63);
+
+        cpu.aam_imm8(&operands!("16")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        cpu.registers.set_ax(0x0f0f);
+        cpu.aad_imm8(&operands!("16")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+    }
+
+    #[test]
+    fn aas() {
+        let mut cpu = Cpu::default();
+        // 2 - 8, borrowing from AH.
+        cpu.registers.set_ax(0x02fa);
+        cpu.registers.eflags.set_auxiliary_carry_flag(true);
+        cpu.aas(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0104);
+        assert_eflags!(cpu, AF = true, CF = true);
+
+        // 8 - 3 = 5
+        cpu.registers.set_ax(0x0505);
+        cpu.registers.eflags.set_auxiliary_carry_flag(false);
+        cpu.aas(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0x0505);
+        assert_eflags!(cpu, AF = false, CF = false);
+    }
+
+    #[test]
+    fn daa() {
+        let mut cpu = Cpu::default();
+        // 79 + 35 = 114
+        cpu.registers.set_al(0xae);
+        cpu.daa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x14);
+        assert_eflags!(cpu, AF = true, CF = true, ZF = false, SF = false, PF = true);
+
+        // 38 + 29 = 67, where the low digit carried (0x38 + 0x29 = 0x61 with AF set).
+        cpu.registers.set_al(0x61);
+        cpu.registers.eflags.set_auxiliary_carry_flag(true);
+        cpu.registers.eflags.set_carry_flag(false);
+        cpu.daa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x67);
+        assert_eflags!(cpu, AF = true, CF = false);
+
+        // 99 + 1 = 100
+        cpu.registers.set_al(0x9a);
+        cpu.registers.eflags.set_auxiliary_carry_flag(false);
+        cpu.daa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x00);
+        assert_eflags!(cpu, AF = true, CF = true, ZF = true);
+
+        // Already a valid BCD value.
+        cpu.registers.set_al(0x42);
+        cpu.registers.eflags.set_auxiliary_carry_flag(false);
+        cpu.registers.eflags.set_carry_flag(false);
+        cpu.daa(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x42);
+        assert_eflags!(cpu, AF = false, CF = false);
+    }
+
+    #[test]
+    fn das() {
+        let mut cpu = Cpu::default();
+        // 35 - 47 = -12, i.e. 88 with a borrow.
+        cpu.registers.set_al(0xee);
+        cpu.registers.eflags.set_auxiliary_carry_flag(true);
+        cpu.registers.eflags.set_carry_flag(true);
+        cpu.das(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x88);
+        assert_eflags!(cpu, AF = true, CF = true, SF = true, ZF = false);
+
+        // 47 - 35 = 12
+        cpu.registers.set_al(0x12);
+        cpu.registers.eflags.set_auxiliary_carry_flag(false);
+        cpu.registers.eflags.set_carry_flag(false);
+        cpu.das(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x12);
+        assert_eflags!(cpu, AF = false, CF = false);
+
+        // 10 - 1 = 9, which borrows from the low digit.
+        cpu.registers.set_al(0x0f);
+        cpu.registers.eflags.set_auxiliary_carry_flag(true);
+        cpu.das(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_al(), 0x09);
+        assert_eflags!(cpu, AF = true, CF = false);
+    }
+
+    #[test]
+    fn add() {
+        let mut cpu = Cpu::default();
+
+        // Decimal
+        assert_eq!(cpu.add(127_i8, 0_i8), 127_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.add(-1_i8, 127_i8), 126_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(0_i8, 0_i8), 0_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+
+        assert_eq!(cpu.add(-1_i8, 1_i8), 0_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = true);
+
+        assert_eq!(cpu.add(-1_i8, 0_i8), -1_i8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+
+        assert_eq!(cpu.add(-1_i8, -1_i8), -2_i8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(-1_i8, -128_i8), 127_i8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(-128_i8, -128_i8), 0_i8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = true, CF = true);
+
+        assert_eq!(cpu.add(127_i8, 127_i8), -2_i8);
+        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = false);
+
+        // Unsigned decimal
+        assert_eq!(cpu.add(127_u8, 0_u8), 127_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.add(255_u8, 127_u8), 126_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(0_u8, 0_u8), 0_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+
+        assert_eq!(cpu.add(255_u8, 1_u8), 0_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = true);
+
+        assert_eq!(cpu.add(255_u8, 0_u8), 255_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+
+        assert_eq!(cpu.add(255_u8, 255_u8), 254_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(255_u8, 128_u8), 127_u8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(128_u8, 128_u8), 0_u8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = true, CF = true);
+
+        assert_eq!(cpu.add(127_u8, 127_u8), 254_u8);
+        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = false);
+
+        // Hexadecimal
+        assert_eq!(cpu.add(0x7F_u8, 0x0_u8), 0x7F_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.add(0xFF_u8, 0x7F_u8), 0x7E_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(0x0_u8, 0x0_u8), 0x0_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+
+        assert_eq!(cpu.add(0xFF_u8, 0x1_u8), 0x0_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = true);
+
+        assert_eq!(cpu.add(0xFF_u8, 0x0_u8), 0xFF_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+
+        assert_eq!(cpu.add(0xFF_u8, 0xFF_u8), 0xFE_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(0xFF_u8, 0x80_u8), 0x7F_u8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.add(0x80_u8, 0x80_u8), 0x0_u8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = true, CF = true);
+
+        assert_eq!(cpu.add(0x7F_u8, 0x7F_u8), 0xFE_u8);
+        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = false);
+    }
+
+    // https://stackoverflow.com/questions/8965923/carry-overflow-subtraction-in-x86#8982549
+    //       A                   B                   A - B              Flags
+    // ---------------     ----------------    ---------------      -----------------
+    // h  |  ud  |   d   | h  |  ud  |   d   | h  |  ud  |   d   || OF | SF | ZF | CF
+    // ---+------+-------+----+------+-------+----+------+-------++----+----+----+----
+    // FF | 255  |  -1   | FE | 254  |  -2   | 1  |  1   |   1   || 0  | 0  | 0  | 0
+    // 7E | 126  |  126  | FF | 255  |  -1   | 7F | 127  |  127  || 0  | 0  | 0  | 1
+    // FF | 255  |  -1   | FF | 255  |  -1   | 0  |  0   |   0   || 0  | 0  | 1  | 0
+    // FF | 255  |  -1   | 7F | 127  |  127  | 80 | 128  | -128  || 0  | 1  | 0  | 0
+    // FE | 254  |  -2   | FF | 255  |  -1   | FF | 255  |  -1   || 0  | 1  | 0  | 1
+    // FE | 254  |  -2   | 7F | 127  |  127  | 7F | 127  |  127  || 1  | 0  | 0  | 0
+    // 7F | 127  |  127  | FF | 255  |  -1   | 80 | 128  | -128  || 1  | 1  | 0  | 1
+    // TODO: Why can't you have the other flag combinations e.g. OF + ZF?
+    // TODO: Test for other 2 flags which are set.
+    #[test]
+    fn sub() {
+        let mut cpu = Cpu::default();
+
+        // Decimal
+        assert_eq!(cpu.sub(-1_i8, -2_i8), 1_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(126_i8, -1_i8), 127_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.sub(-1_i8, -1_i8), 0_i8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+
+        assert_eq!(cpu.sub(-1_i8, 127_i8), -128_i8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(-2_i8, -1_i8), -1_i8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+
+        assert_eq!(cpu.sub(-2_i8, 127_i8), 127_i8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(127_i8, -1_i8), -128_i8);
+        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = true);
+
+        // Unsigned decimal
+        assert_eq!(cpu.sub(255_u8, 254_u8), 1_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(126_u8, 255_u8), 127_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.sub(255_u8, 255_u8), 0_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+
+        assert_eq!(cpu.sub(255_u8, 127_u8), 128_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(254_u8, 255_u8), 255_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+
+        assert_eq!(cpu.sub(254_u8, 127_u8), 127_u8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(127_u8, 255_u8), 128_u8);
+        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = true);
+
+        // Hexadecimal
+        assert_eq!(cpu.sub(0xFF_u8, 0xFE_u8), 0x1_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(0x7E_u8, 0xFF_u8), 0x7F_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+
+        assert_eq!(cpu.sub(0xFF_u8, 0xFF_u8), 0x0_u8);
+        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+
+        assert_eq!(cpu.sub(0xFF_u8, 0x7F_u8), 0x80_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(0xFE_u8, 0xFF_u8), 0xFF_u8);
+        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+
+        assert_eq!(cpu.sub(0xFE_u8, 0x7F_u8), 0x7F_u8);
+        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = false);
+
+        assert_eq!(cpu.sub(0x7F_u8, 0xFF_u8), 0x80_u8);
+        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = true);
+    }
+
+    #[test]
+    fn and() {
+        let mut cpu = Cpu::default();
+
+        cpu.registers.eflags.set_overflow_flag(true);
+        cpu.registers.eflags.set_carry_flag(true);
+
+        assert_eq!(
+            cpu.and(0b0000_0001_u8, 0b1111_1111_u8),
+            0b0000_0001_u8 & 0b1111_1111_u8
+        );
+        assert_eflags!(
+            cpu,
+            OF = false,
+            CF = false,
+            SF = false,
+            ZF = false,
+            PF = false
+        );
+
+        assert_eq!(
+            cpu.and(0b0000_0011_u8, 0b1111_1111_u8),
+            0b0000_0011_u8 & 0b1111_1111_u8
+        );
+        assert_eflags!(
+            cpu,
+            OF = false,
+            CF = false,
+            SF = false,
+            ZF = false,
+            PF = true
+        );
+
+        assert_eq!(
+            cpu.and(0b0000_0000_u8, 0b1111_1111_u8),
+            0b0000_0000_u8 & 0b1111_1111_u8
+        );
+        assert_eflags!(
+            cpu,
+            OF = false,
+            CF = false,
+            SF = false,
+            ZF = true,
+            PF = true
+        );
+
+        assert_eq!(
+            cpu.and(0b1000_0000_u8, 0b1111_1111_u8),
+            0b1000_0000_u8 & 0b1111_1111_u8
+        );
+        assert_eflags!(
+            cpu,
+            OF = false,
+            CF = false,
+            SF = true,
+            ZF = false,
+            PF = false
+        );
+    }
+
+    fn set_eax_to_0x5(cpu: &mut Cpu) {
+        cpu.registers.set_eax(0x5);
+    }
+
+    #[test]
+    fn bound() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::BoundRangeExceeded.vector(), set_eax_to_0x5);
+        cpu.memory.write16(0x100, -2i16 as u16).unwrap();
+        cpu.memory.write16(0x102, 10).unwrap();
+        cpu.memory.write32(0x200, -200i32 as u32).unwrap();
+        cpu.memory.write32(0x204, 100).unwrap();
+
+        for (index, in_bounds) in [(-3, false), (-2, true), (0, true), (10, true), (11, false)] {
+            cpu.registers.set_eax(0);
+            cpu.registers.set_ecx(index as u32);
+            cpu.bound_reg16_mem(&operands!("cx", "[0x100]")).unwrap();
+            assert_eq!(cpu.registers.get_eax() == 0, in_bounds, "index {index}");
+        }
+
+        for (index, in_bounds) in [(-201, false), (-200, true), (100, true), (101, false)] {
+            cpu.registers.set_eax(0);
+            cpu.registers.set_ecx(index as u32);
+            cpu.bound_reg32_mem(&operands!("ecx", "[0x200]")).unwrap();
+            assert_eq!(cpu.registers.get_eax() == 0, in_bounds, "index {index}");
+        }
+    }
+
+    #[test]
+    fn clts() {
+        let mut cpu = Cpu::default();
+        cpu.registers.control_registers.set_task_switched(true);
+        cpu.clts(&operands!()).unwrap();
+        assert!(!cpu.registers.control_registers.get_task_switched());
+
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.control_registers.set_task_switched(true);
+        cpu.registers.cs = 0x1b;
+        cpu.clts(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert!(cpu.registers.control_registers.get_task_switched());
+    }
+
+    #[test]
+    fn cmps() {
+        let mut cpu = Cpu::default();
+        cpu.memory.write32(0x100, 0x44332211).unwrap();
+        cpu.memory.write32(0x200, 0x44332212).unwrap();
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0x200;
+        cpu.cmpsb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = true, SF = true);
+        assert_eq!(cpu.registers.esi, 0x101);
+        assert_eq!(cpu.registers.edi, 0x201);
+        cpu.cmpsw(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = true, CF = false, SF = false);
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0x200;
+        cpu.cmpsd(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = true);
+        assert_eq!(cpu.registers.esi, 0x104);
+        assert_eq!(cpu.registers.edi, 0x204);
+
+        // REPE stops at the first mismatch, which is the last byte compared backwards.
+        cpu.registers.eflags.set_direction_flag(true);
+        cpu.repeat_prefix = Some(RepeatPrefix::Repe);
+        cpu.registers.set_ecx(8);
+        cpu.registers.esi = 0x103;
+        cpu.registers.edi = 0x203;
+        cpu.cmpsb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false);
+        assert_eq!(cpu.registers.get_ecx(), 4);
+        assert_eq!(cpu.registers.esi, 0xff);
+        assert_eq!(cpu.registers.edi, 0x1ff);
+
+        // REPNE stops at the first match.
+        cpu.registers.eflags.set_direction_flag(false);
+        cpu.repeat_prefix = Some(RepeatPrefix::Repne);
+        cpu.registers.set_ecx(8);
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0x200;
+        cpu.cmpsb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = true);
+        assert_eq!(cpu.registers.get_ecx(), 6);
+        assert_eq!(cpu.registers.esi, 0x102);
+
+        // Both stop once ECX reaches 0.
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.registers.set_ecx(2);
+        cpu.registers.esi = 0x101;
+        cpu.registers.edi = 0x201;
+        cpu.cmpsb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = true);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.esi, 0x103);
+    }
+
+    fn cpu_with_latches() -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.attach_io_device(
+            0x60..=0x63,
+            Box::new(Latches {
+                base: 0x60,
+                values: vec![0x11, 0x22, 0x33, 0x44],
+            }),
+        )
+        .unwrap();
+        cpu
+    }
+
+    #[test]
+    fn fpu_control() {
+        let mut cpu = Cpu::default();
+        cpu.fninit(&operands!()).unwrap();
+        cpu.fnstcw_mem16(&operands!("[0x100]")).unwrap();
+        assert_eq!(cpu.memory.read16(0x100).unwrap(), 0x037f);
+
+        // Round toward zero, with double precision.
+        cpu.memory.write16(0x102, 0x0e7f).unwrap();
+        cpu.fldcw_mem16(&operands!("word [0x102]")).unwrap();
+        assert_eq!(cpu.fpu.get_rounding_mode(), RoundingMode::TowardZero);
+        assert_eq!(cpu.fpu.get_control_word(), 0x0e7f);
+
+        // C3 (bit 14) and C0 (bit 8) are set.
+        cpu.fpu.set_status_word(0x4100);
+        cpu.registers.set_eax(0xffff_ffff);
+        cpu.fnstsw_ax(&operands!("ax")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xffff_4100);
+        cpu.fnstsw_mem16(&operands!("[0x104]")).unwrap();
+        assert_eq!(cpu.memory.read16(0x104).unwrap(), 0x4100);
+
+        cpu.fninit(&operands!()).unwrap();
+        cpu.fnstsw_ax(&operands!("ax")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xffff_0000);
+        assert_eq!(cpu.fpu.get_rounding_mode(), RoundingMode::Nearest);
+    }
+
+    #[test]
+    fn mmx_moves() {
+        let mut cpu = Cpu::default();
+        cpu.fpu.set_top(4);
+        cpu.registers.set_eax(0x8765_4321);
+        cpu.movd_mm_rm32(&operands!("mm1", "eax")).unwrap();
+        assert_eq!(cpu.fpu.read_mmx(1), 0x8765_4321);
+        assert_eq!(cpu.fpu.get_top(), 0);
+        assert_eq!(cpu.fpu.get_tag_word(), 0);
+
+        cpu.memory.write32(0x100, 0x89ab_cdef).unwrap();
+        cpu.memory.write32(0x104, 0x0123_4567).unwrap();
+        cpu.movq_mm_mm64(&operands!("mm2", "qword [0x100]"))
+            .unwrap();
+        assert_eq!(cpu.fpu.read_mmx(2), 0x0123_4567_89ab_cdef);
+        cpu.movq_mm_mm64(&operands!("mm3", "mm2")).unwrap();
+        cpu.movq_mm64_mm(&operands!("[0x108]", "mm3")).unwrap();
+        assert_eq!(cpu.memory.read32(0x108).unwrap(), 0x89ab_cdef);
+        assert_eq!(cpu.memory.read32(0x10c).unwrap(), 0x0123_4567);
+
+        cpu.movd_rm32_mm(&operands!("ebx", "mm3")).unwrap();
+        assert_eq!(cpu.registers.get_ebx(), 0x89ab_cdef);
+
+        cpu.emms(&operands!()).unwrap();
+        assert_eq!(cpu.fpu.get_tag_word(), 0xffff);
+    }
+
+    #[test]
+    fn mmx_packed_arithmetic() {
+        let mut cpu = Cpu::default();
+        let lhs = 0x7fff_8000_00ff_f001;
+        let rhs = 0x0001_8000_0002_1002;
+        for (function, expected) in [
+            (
+                Cpu::paddb_mm_mm64 as fn(&mut Cpu, &Operands) -> Result<(), Error>,
+                0x7f00_0000_0001_0003,
+            ),
+            (Cpu::paddw_mm_mm64, 0x8000_0000_0101_0003),
+            (Cpu::paddd_mm_mm64, 0x8001_0000_0102_0003),
+            (Cpu::paddq_mm_mm64, 0x8001_0000_0102_0003),
+            (Cpu::paddsb_mm_mm64, 0x7f00_8000_0001_0003),
+            (Cpu::paddsw_mm_mm64, 0x7fff_8000_0101_0003),
+            (Cpu::paddusb_mm_mm64, 0x7fff_ff00_00ff_ff03),
+            (Cpu::paddusw_mm_mm64, 0x8000_ffff_0101_ffff),
+            (Cpu::psubb_mm_mm64, 0x7ffe_0000_00fd_e0ff),
+            (Cpu::psubw_mm_mm64, 0x7ffe_0000_00fd_dfff),
+            (Cpu::psubd_mm_mm64, 0x7ffe_0000_00fd_dfff),
+            (Cpu::psubq_mm_mm64, 0x7ffe_0000_00fd_dfff),
+            (Cpu::psubsb_mm_mm64, 0x7ffe_0000_00fd_e0ff),
+            (Cpu::psubsw_mm_mm64, 0x7ffe_0000_00fd_dfff),
+            (Cpu::psubusb_mm_mm64, 0x7ffe_0000_00fd_e000),
+            (Cpu::psubusw_mm_mm64, 0x7ffe_0000_00fd_dfff),
+        ] {
+            cpu.fpu.write_mmx(0, lhs);
+            cpu.fpu.write_mmx(1, rhs);
+            function(&mut cpu, &operands!("mm0", "mm1")).unwrap();
+            assert_eq!(cpu.fpu.read_mmx(0), expected, "{expected:#018x}");
+        }
+    }
+
+    #[test]
+    fn mmx_logic() {
+        let mut cpu = Cpu::default();
+        cpu.memory.write32(0x100, 0x0ff0_0ff0).unwrap();
+        cpu.memory.write32(0x104, 0xffff_0000).unwrap();
+        for (function, expected) in [
+            (
+                Cpu::pand_mm_mm64 as fn(&mut Cpu, &Operands) -> Result<(), Error>,
+                0x00ff_0000_0ff0_0f00,
+            ),
+            (Cpu::pandn_mm_mm64, 0xff00_0000_0000_00f0),
+            (Cpu::por_mm_mm64, 0xffff_ff00_0ff0_ffff),
+            (Cpu::pxor_mm_mm64, 0xff00_ff00_0000_f0ff),
+        ] {
+            cpu.fpu.write_mmx(4, 0x00ff_ff00_0ff0_ff0f);
+            function(&mut cpu, &operands!("mm4", "[0x100]")).unwrap();
+            assert_eq!(cpu.fpu.read_mmx(4), expected, "{expected:#018x}");
+        }
+    }
+
+    #[test]
+    fn in_port() {
+        let mut cpu = cpu_with_latches();
+        cpu.in_al_imm8(&operands!("al", "0x61")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x22);
+        cpu.in_ax_imm8(&operands!("ax", "0x60")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x2211);
+        cpu.in_eax_imm8(&operands!("eax", "0x60")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x44332211);
+
+        cpu.registers.set_edx(0x63);
+        cpu.in_al_dx(&operands!("al", "dx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x44332244);
+        cpu.registers.set_edx(0x62);
+        cpu.in_ax_dx(&operands!("ax", "dx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x44334433);
+
+        // Ports without a device attached read as 0xff.
+        cpu.registers.set_edx(0x62);
+        cpu.in_eax_dx(&operands!("eax", "dx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xffff4433);
+    }
+
+    #[test]
+    fn out_port() {
+        let mut cpu = cpu_with_latches();
+        cpu.registers.set_eax(0xaabbccdd);
+        cpu.out_imm8_al(&operands!("0x60", "al")).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0x443322dd);
+        cpu.out_imm8_ax(&operands!("0x62", "ax")).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0xccdd22dd);
+        cpu.out_imm8_eax(&operands!("0x60", "eax")).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0xaabbccdd);
+
+        cpu.registers.set_eax(0x12345678);
+        cpu.registers.set_edx(0x61);
+        cpu.out_dx_al(&operands!("dx", "al")).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0xaabb78dd);
+        cpu.out_dx_ax(&operands!("dx", "ax")).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0xaa5678dd);
+        cpu.registers.set_edx(0x60);
+        cpu.out_dx_eax(&operands!("dx", "eax")).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0x12345678);
+    }
+
+    #[test]
+    fn ins() {
+        let mut cpu = cpu_with_latches();
+        cpu.registers.set_edx(0x60);
+        cpu.registers.edi = 0x100;
+        cpu.insb(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read8(0x100).unwrap(), 0x11);
+        assert_eq!(cpu.registers.edi, 0x101);
+        cpu.insw(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read16(0x101).unwrap(), 0x2211);
+        assert_eq!(cpu.registers.edi, 0x103);
+        cpu.insd(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x103).unwrap(), 0x44332211);
+        assert_eq!(cpu.registers.edi, 0x107);
+
+        // Backwards, repeated ECX times.
+        cpu.registers.eflags.set_direction_flag(true);
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.registers.set_ecx(3);
+        cpu.registers.set_edx(0x63);
+        cpu.registers.edi = 0x200;
+        cpu.insw(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.edi, 0x200 - 6);
+        assert_eq!(cpu.memory.read16(0x1fc).unwrap(), 0xff44);
+        assert_eq!(cpu.memory.read16(0x1fe).unwrap(), 0xff44);
+        assert_eq!(cpu.memory.read16(0x200).unwrap(), 0xff44);
+        assert_eq!(cpu.memory.read16(0x1fa).unwrap(), 0);
+
+        // Nothing happens when ECX is 0.
+        cpu.insb(&operands!()).unwrap();
+        assert_eq!(cpu.registers.edi, 0x200 - 6);
+    }
+
+    #[test]
+    fn lods() {
+        let mut cpu = Cpu::default();
+        cpu.memory.write32(0x100, 0x44332211).unwrap();
+        cpu.memory.write32(0x104, 0x88776655).unwrap();
+        cpu.registers.set_eax(0xaabbccdd);
+        cpu.registers.esi = 0x100;
+        cpu.lodsb(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xaabbcc11);
+        assert_eq!(cpu.registers.esi, 0x101);
+        cpu.lodsw(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xaabb3322);
+        assert_eq!(cpu.registers.esi, 0x103);
+        cpu.lodsd(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x77665544);
+        assert_eq!(cpu.registers.esi, 0x107);
+
+        cpu.registers.eflags.set_direction_flag(true);
+        cpu.lodsb(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x77665588);
+        assert_eq!(cpu.registers.esi, 0x106);
+
+        // Each iteration overwrites the accumulator, leaving the last element loaded.
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.registers.set_ecx(3);
+        cpu.lodsw(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x77664433);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.esi, 0x100);
+    }
+
+    #[test]
+    fn movs() {
+        let mut cpu = Cpu::default();
+        cpu.memory.write32(0x100, 0x44332211).unwrap();
+        cpu.memory.write32(0x104, 0x88776655).unwrap();
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0x200;
+        cpu.movsb(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read8(0x200).unwrap(), 0x11);
+        cpu.movsw(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read16(0x201).unwrap(), 0x3322);
+        cpu.movsd(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x203).unwrap(), 0x77665544);
+        assert_eq!(cpu.registers.esi, 0x107);
+        assert_eq!(cpu.registers.edi, 0x207);
+
+        // Copy the 8 bytes again, 2 bytes at a time and starting from the end.
+        cpu.registers.eflags.set_direction_flag(true);
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.registers.set_ecx(4);
+        cpu.registers.esi = 0x106;
+        cpu.registers.edi = 0x306;
+        cpu.movsw(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x300).unwrap(), 0x44332211);
+        assert_eq!(cpu.memory.read32(0x304).unwrap(), 0x88776655);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.esi, 0xfe);
+        assert_eq!(cpu.registers.edi, 0x2fe);
+
+        // Copy the 8 bytes forwards, one byte at a time, which moves them as a block.
+        cpu.registers.eflags.set_direction_flag(false);
+        cpu.registers.set_ecx(8);
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0xffc;
+        cpu.movsb(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read64(0xffc).unwrap(), 0x8877665544332211);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.esi, 0x108);
+        assert_eq!(cpu.registers.edi, 0x1004);
+
+        // A destination which overlaps the source ahead of it repeats the first byte.
+        cpu.registers.set_ecx(7);
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0x101;
+        cpu.movsb(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read64(0x100).unwrap(), 0x1111111111111111);
+        assert_eq!(cpu.registers.esi, 0x107);
+        assert_eq!(cpu.registers.edi, 0x108);
+    }
+
+    #[test]
+    fn movs_segment_override() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_segment_base(SegmentRegister::Es, 0x1000);
+        cpu.registers.set_segment_base(SegmentRegister::Gs, 0x2000);
+        cpu.memory.write32(0x100, 0x44332211).unwrap();
+        cpu.memory.write32(0x2100, 0x88776655).unwrap();
+        cpu.registers.esi = 0x100;
+        cpu.registers.edi = 0x200;
+        cpu.movsd(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x1200).unwrap(), 0x44332211);
+
+        // Only the source segment can be overridden.
+        cpu.segment_override = Some(SegmentRegister::Gs);
+        cpu.registers.esi = 0x100;
+        cpu.movsd(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x1204).unwrap(), 0x88776655);
+    }
+
+    #[test]
+    fn outs() {
+        let mut cpu = cpu_with_latches();
+        cpu.registers.set_edx(0x60);
+        cpu.registers.esi = 0x100;
+        cpu.memory.write32(0x100, 0xaabbccdd).unwrap();
+        cpu.memory.write32(0x104, 0x01020304).unwrap();
+        cpu.outsb(&operands!()).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0x443322dd);
+        assert_eq!(cpu.registers.esi, 0x101);
+        cpu.outsw(&operands!()).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0x4433bbcc);
+        assert_eq!(cpu.registers.esi, 0x103);
+        cpu.outsd(&operands!()).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0x020304aa);
+        assert_eq!(cpu.registers.esi, 0x107);
+
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.registers.set_ecx(4);
+        cpu.registers.set_edx(0x61);
+        cpu.registers.esi = 0x100;
+        cpu.outsb(&operands!()).unwrap();
+        assert_eq!(cpu.io.read32(0x60), 0x0203aaaa);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.esi, 0x104);
+    }
+
+    #[test]
+    fn rdmsr_and_wrmsr() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
+
+        cpu.registers.set_ecx(msr::IA32_SYSENTER_EIP);
+        cpu.registers.set_edx(0x1122_3344);
+        cpu.registers.set_eax(0x5566_7788);
+        cpu.wrmsr(&operands!()).unwrap();
+        cpu.registers.set_edx(0);
+        cpu.registers.set_eax(0);
+        cpu.rdmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0x1122_3344);
+        assert_eq!(cpu.registers.get_eax(), 0x5566_7788);
+
+        // The time-stamp counter is also accessible as an MSR.
+        cpu.registers.set_ecx(msr::IA32_TIME_STAMP_COUNTER);
+        cpu.registers.set_edx(1);
+        cpu.registers.set_eax(2);
+        cpu.wrmsr(&operands!()).unwrap();
+        assert_eq!(cpu.time_stamp_counter.get(), 0x1_0000_0002);
+        cpu.elapse(1);
+        cpu.rdmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 1);
+        assert_eq!(cpu.registers.get_eax(), 3);
+
+        // Undefined MSRs raise #GP, unless permissive.
+        cpu.registers.set_ecx(0x1234);
+        cpu.registers.set_edx(0xffff_ffff);
+        cpu.rdmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.get_edx(), 0xffff_ffff);
+        cpu.registers.set_eax(0);
+        cpu.wrmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+
+        cpu.set_permissive_msrs(true);
+        cpu.registers.set_eax(1);
+        cpu.wrmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 1);
+        cpu.rdmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0);
+        assert_eq!(cpu.registers.get_eax(), 0);
+
+        cpu.define_msr(0x1234, 0x42);
+        cpu.rdmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x42);
+
+        // Both are privileged.
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.cs = 0x1b;
+        cpu.rdmsr(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+    }
+
+    fn cpu_with_sse() -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.registers.control_registers.set_osfxsr(true);
+        cpu
+    }
+
+    fn packed_single(values: [f32; 4]) -> u128 {
+        values
+            .iter()
+            .rev()
+            .fold(0, |packed, value| packed << 32 | value.to_bits() as u128)
+    }
+
+    #[test]
+    fn sse_moves() {
+        let mut cpu = cpu_with_sse();
+        let value = packed_single([1.0, -2.0, 0.5, 8.0]);
+        for (index, dword) in [0x3f80_0000, 0xc000_0000, 0x3f00_0000, 0x4100_0000]
+            .into_iter()
+            .enumerate()
+        {
+            cpu.memory.write32(0x100 + index as u32 * 4, dword).unwrap();
+        }
+
+        cpu.movaps_xmm_xmm128(&operands!("xmm1", "[0x100]"))
+            .unwrap();
+        assert_eq!(cpu.sse.read_xmm(1), value);
+        cpu.movaps_xmm_xmm128(&operands!("xmm2", "xmm1")).unwrap();
+        cpu.movups_xmm128_xmm(&operands!("[0x204]", "xmm2"))
+            .unwrap();
+        cpu.movups_xmm_xmm128(&operands!("xmm3", "oword [0x204]"))
+            .unwrap();
+        assert_eq!(cpu.sse.read_xmm(3), value);
+
+        // MOVAPS requires its memory operand to be aligned to 16 bytes.
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
+        cpu.movaps_xmm128_xmm(&operands!("[0x208]", "xmm1"))
+            .unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.memory.read32(0x208).unwrap(), 0xc000_0000);
+        cpu.movaps_xmm128_xmm(&operands!("[0x300]", "xmm1"))
+            .unwrap();
+        assert_eq!(cpu.memory.read32(0x30c).unwrap(), 0x4100_0000);
+
+        // SSE instructions are unavailable until enabled through CR4.OSFXSR, and raise #NM while
+        // CR0.TS is set.
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_eax_to_3);
+        cpu.register_interrupt_handler(CpuException::DeviceNotAvailable.vector(), set_eax_to_4);
+        cpu.registers.control_registers.set_task_switched(true);
+        cpu.movups_xmm_xmm128(&operands!("xmm0", "xmm1")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 4);
+        cpu.registers.control_registers.set_osfxsr(false);
+        cpu.movups_xmm_xmm128(&operands!("xmm0", "xmm1")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 3);
+        assert_eq!(cpu.sse.read_xmm(0), 0);
+    }
+
+    #[test]
+    fn sse_packed_single() {
+        let mut cpu = cpu_with_sse();
+        let lhs = packed_single([1.5, -2.0, 10.0, 3.0]);
+        let rhs = packed_single([0.5, 4.0, -4.0, 3.0]);
+        for (function, expected) in [
+            (
+                Cpu::addps_xmm_xmm128 as fn(&mut Cpu, &Operands) -> Result<(), Error>,
+                [2.0, 2.0, 6.0, 6.0],
+            ),
+            (Cpu::subps_xmm_xmm128, [1.0, -6.0, 14.0, 0.0]),
+            (Cpu::mulps_xmm_xmm128, [0.75, -8.0, -40.0, 9.0]),
+            (Cpu::divps_xmm_xmm128, [3.0, -0.5, -2.5, 1.0]),
+        ] {
+            cpu.sse.write_xmm(0, lhs);
+            cpu.sse.write_xmm(1, rhs);
+            function(&mut cpu, &operands!("xmm0", "xmm1")).unwrap();
+            assert_eq!(cpu.sse.read_xmm(0), packed_single(expected));
+        }
+        assert_eq!(cpu.sse.get_mxcsr(), 0x1f80);
+
+        cpu.sse.write_xmm(0, lhs);
+        cpu.cmpps_xmm_xmm128_imm8(&operands!("xmm0", "xmm1", "2"))
+            .unwrap();
+        assert_eq!(
+            cpu.sse.read_xmm(0),
+            0xffff_ffff_0000_0000_ffff_ffff_0000_0000
+        );
+    }
+
+    #[test]
+    fn sse_exceptions() {
+        let mut cpu = cpu_with_sse();
+        let lhs = packed_single([1.0, 1.0, 1.0, 1.0]);
+        cpu.sse.write_xmm(0, lhs);
+        cpu.sse.write_xmm(1, packed_single([3.0, 0.0, 1.0, 1.0]));
+        cpu.divps_xmm_xmm128(&operands!("xmm0", "xmm1")).unwrap();
+        assert_eq!(
+            cpu.sse.read_xmm(0),
+            packed_single([1.0 / 3.0, f32::INFINITY, 1.0, 1.0])
+        );
+        assert_eq!(cpu.sse.get_mxcsr(), 0x1f80 | 0x24);
+
+        // An unmasked exception discards the result, and raises #UD unless CR4.OSXMMEXCPT is set.
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_eax_to_3);
+        cpu.register_interrupt_handler(CpuException::SimdFloatingPoint.vector(), set_eax_to_4);
+        cpu.sse.write_xmm(0, lhs);
+        cpu.sse
+            .set_mxcsr(cpu.sse.get_mxcsr() & !(sse::DIVIDE_BY_ZERO << 7));
+        cpu.divps_xmm_xmm128(&operands!("xmm0", "xmm1")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 3);
+        assert_eq!(cpu.sse.read_xmm(0), lhs);
+
+        cpu.registers.control_registers.set_osxmmexcpt(true);
+        cpu.divps_xmm_xmm128(&operands!("xmm0", "xmm1")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 4);
+        assert_eq!(cpu.sse.read_xmm(0), lhs);
+    }
+
+    #[test]
+    fn sse2_moves() {
+        let mut cpu = cpu_with_sse();
+        let value = 0x0011_2233_4455_6677_8899_aabb_ccdd_eeff;
+        cpu.sse.write_xmm(0, value);
+        cpu.movdqa_xmm128_xmm(&operands!("[0x100]", "xmm0"))
+            .unwrap();
+        cpu.movdqu_xmm_xmm128(&operands!("xmm1", "[0x104]"))
+            .unwrap();
+        assert_eq!(cpu.sse.read_xmm(1), value >> 32);
+        cpu.movapd_xmm_xmm128(&operands!("xmm2", "xmm0")).unwrap();
+        assert_eq!(cpu.sse.read_xmm(2), value);
+
+        // Moving from a register only replaces the low QWORD, whereas moving from memory also
+        // clears the high QWORD.
+        cpu.movsd_xmm_xmm64(&operands!("xmm2", "xmm1")).unwrap();
+        assert_eq!(
+            cpu.sse.read_xmm(2),
+            0x0011_2233_4455_6677_4455_6677_8899_aabb
+        );
+        cpu.movsd_xmm_xmm64(&operands!("xmm2", "qword [0x108]"))
+            .unwrap();
+        assert_eq!(cpu.sse.read_xmm(2), 0x0011_2233_4455_6677);
+        cpu.movsd_xmm64_xmm(&operands!("[0x200]", "xmm0")).unwrap();
+        assert_eq!(cpu.memory.read32(0x200).unwrap(), 0xccdd_eeff);
+        assert_eq!(cpu.memory.read32(0x204).unwrap(), 0x8899_aabb);
+        assert_eq!(cpu.memory.read32(0x208).unwrap(), 0);
+    }
+
+    #[test]
+    fn sse2_arithmetic() {
+        let mut cpu = cpu_with_sse();
+        let packed_double =
+            |low: f64, high: f64| (high.to_bits() as u128) << 64 | low.to_bits() as u128;
+        let lhs = packed_double(1.5, -8.0);
+        let rhs = packed_double(0.5, 2.0);
+        for (function, expected) in [
+            (
+                Cpu::addpd_xmm_xmm128 as fn(&mut Cpu, &Operands) -> Result<(), Error>,
+                packed_double(2.0, -6.0),
+            ),
+            (Cpu::subpd_xmm_xmm128, packed_double(1.0, -10.0)),
+            (Cpu::mulpd_xmm_xmm128, packed_double(0.75, -16.0)),
+            (Cpu::divpd_xmm_xmm128, packed_double(3.0, -4.0)),
+            (Cpu::addsd_xmm_xmm64, packed_double(2.0, -8.0)),
+            (Cpu::subsd_xmm_xmm64, packed_double(1.0, -8.0)),
+            (Cpu::mulsd_xmm_xmm64, packed_double(0.75, -8.0)),
+            (Cpu::divsd_xmm_xmm64, packed_double(3.0, -8.0)),
+        ] {
+            cpu.sse.write_xmm(0, lhs);
+            cpu.sse.write_xmm(1, rhs);
+            function(&mut cpu, &operands!("xmm0", "xmm1")).unwrap();
+            assert_eq!(cpu.sse.read_xmm(0), expected);
+        }
+
+        cpu.memory.write32(0x100, 0).unwrap();
+        cpu.memory.write32(0x104, 0).unwrap();
+        cpu.addsd_xmm_xmm64(&operands!("xmm0", "[0x100]")).unwrap();
+        cpu.divsd_xmm_xmm64(&operands!("xmm0", "qword [0x100]"))
+            .unwrap();
+        assert_eq!(cpu.sse.read_xmm(0), packed_double(f64::INFINITY, -8.0));
+        assert_ne!(cpu.sse.get_mxcsr() & sse::DIVIDE_BY_ZERO, 0);
+    }
+
+    #[test]
+    fn sysenter_and_sysexit() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
+
+        // #GP in real mode.
+        cpu.sysenter(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+
+        // #GP with a null SYSENTER_CS.
+        cpu.registers.set_eax(0);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.cs = 0x1b;
+        cpu.sysenter(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+
+        cpu.registers.set_eax(0);
+        cpu.define_msr(msr::IA32_SYSENTER_CS, 0x08);
+        cpu.define_msr(msr::IA32_SYSENTER_ESP, 0x8000);
+        cpu.define_msr(msr::IA32_SYSENTER_EIP, 0x1000);
+        cpu.registers.eflags.set_interrupt_enable_flag(true);
+        cpu.sysenter(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
+        assert_eq!(cpu.registers.cs, 0x08);
+        assert_eq!(cpu.registers.ss, 0x10);
+        assert_eq!(cpu.registers.esp, 0x8000);
+        assert_eq!(cpu.registers.get_eip(), 0x1000);
+        assert!(!cpu.registers.eflags.get_interrupt_enable_flag());
+        assert_eq!(cpu.registers.get_cpl(), CurrentPrivilegeLevel::CPL0);
+
+        cpu.registers.set_ecx(0x4000);
+        cpu.registers.set_edx(0x2000);
+        cpu.sysexit(&operands!()).unwrap();
+        assert_eq!(cpu.registers.cs, 0x1b);
+        assert_eq!(cpu.registers.ss, 0x23);
+        assert_eq!(cpu.registers.esp, 0x4000);
+        assert_eq!(cpu.registers.get_eip(), 0x2000);
+        assert_eq!(cpu.registers.get_cpl(), CurrentPrivilegeLevel::CPL3);
+
+        // SYSEXIT is privileged.
+        cpu.sysexit(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+    }
+
+    #[test]
+    fn ud2() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_eax_to_0x5);
+        cpu.ud2(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+    }
+
+    /// An entropy source which returns a fixed sequence of results, and then runs dry.
+    #[derive(Debug)]
+    struct FixedEntropySource(Vec<Option<u32>>);
+
+    impl EntropySource for FixedEntropySource {
+        fn next_u32(&mut self) -> Option<u32> {
+            if self.0.is_empty() {
+                None
+            } else {
+                self.0.remove(0)
+            }
+        }
+    }
+
+    #[test]
+    fn rdrand() {
+        let mut cpu = Cpu::default();
+        cpu.set_entropy_source(Box::new(FixedEntropySource(vec![
+            Some(0x1234_5678),
+            Some(0x8765_4321),
+        ])));
+        cpu.registers.eflags.set_zero_flag(true);
+        cpu.rdrand_reg32(&operands!("ecx")).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0x1234_5678);
+        assert_eflags!(cpu, CF = true, ZF = false, SF = false, OF = false);
+
+        cpu.registers.set_eax(0xffff_ffff);
+        cpu.rdrand_reg16(&operands!("ax")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xffff_4321);
+        assert_eflags!(cpu, CF = true);
+
+        cpu.rdrand_reg32(&operands!("ecx")).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eflags!(cpu, CF = false);
+    }
+
+    #[test]
+    fn rdtsc() {
+        let mut cpu = Cpu::default();
+        cpu.rdtsc(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0);
+        assert_eq!(cpu.registers.get_eax(), 0);
+
+        cpu.elapse(1);
+        cpu.elapse(1);
+        cpu.rdtsc(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0);
+        assert_eq!(cpu.registers.get_eax(), 2);
+
+        cpu.set_cycles_per_instruction(0x8000_0000);
+        cpu.elapse(1);
+        cpu.elapse(1);
+        cpu.elapse(1);
+        cpu.rdtsc(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 1);
+        assert_eq!(cpu.registers.get_eax(), 0x8000_0002);
+    }
+
+    #[test]
+    fn salc() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_eax_to_0x5);
+        cpu.registers.eflags.set_carry_flag(true);
+        cpu.salc(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+
+        cpu.set_undocumented_instructions(true);
+        cpu.salc(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xff);
+        assert_eflags!(cpu, CF = true);
+        cpu.registers.eflags.set_carry_flag(false);
+        cpu.salc(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
+    }
+
+    #[test]
+    fn scas() {
+        let mut cpu = Cpu::default();
+        cpu.memory.write32(0x100, 0x44332211).unwrap();
+        cpu.registers.set_eax(0x4433);
+        cpu.registers.edi = 0x100;
+        cpu.scasb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = false);
+        assert_eq!(cpu.registers.edi, 0x101);
+        cpu.scasw(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = false);
+        assert_eq!(cpu.registers.edi, 0x103);
+        cpu.registers.edi = 0x102;
+        cpu.scasw(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = true);
+        cpu.registers.edi = 0x100;
+        cpu.scasd(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = true);
+        assert_eq!(cpu.registers.edi, 0x104);
+
+        // strlen: scan for the NUL terminator, starting with ECX at its maximum value.
+        for (i, c) in b"hello\0".iter().enumerate() {
+            cpu.memory.write8(0x200 + i as u32, *c).unwrap();
+        }
+        cpu.repeat_prefix = Some(RepeatPrefix::Repne);
+        cpu.registers.set_eax(0);
+        cpu.registers.set_ecx(u32::MAX);
+        cpu.registers.edi = 0x200;
+        cpu.scasb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = true);
+        assert_eq!(!cpu.registers.get_ecx() - 1, 5);
+        assert_eq!(cpu.registers.edi, 0x206);
+
+        // Skip over leading spaces.
+        for (i, c) in b"   x".iter().enumerate() {
+            cpu.memory.write8(0x300 + i as u32, *c).unwrap();
+        }
+        cpu.repeat_prefix = Some(RepeatPrefix::Repe);
+        cpu.registers.set_eax(b' ' as u32);
+        cpu.registers.set_ecx(10);
+        cpu.registers.edi = 0x300;
+        cpu.scasb(&operands!()).unwrap();
+        assert_eflags!(cpu, ZF = false);
+        assert_eq!(cpu.registers.get_ecx(), 6);
+        assert_eq!(cpu.registers.edi, 0x304);
+    }
+
+    #[test]
+    fn stos() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(0x44332211);
+        cpu.registers.edi = 0x100;
+        cpu.stosb(&operands!()).unwrap();
+        cpu.stosw(&operands!()).unwrap();
+        cpu.stosd(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 0x11221111);
+        assert_eq!(cpu.memory.read32(0x103).unwrap(), 0x44332211);
+        assert_eq!(cpu.registers.edi, 0x107);
+
+        // Fill a large block of memory.
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.registers.set_ecx(0x4000);
+        cpu.registers.edi = 0x10000;
+        cpu.stosd(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.edi, 0x20000);
+        assert_eq!(cpu.memory.read32(0xfffc).unwrap(), 0);
+        assert!((0x10000..0x20000)
+            .step_by(4)
+            .all(|address| cpu.memory.read32(address).unwrap() == 0x44332211));
+        assert_eq!(cpu.memory.read32(0x20000).unwrap(), 0);
+
+        cpu.registers.eflags.set_direction_flag(true);
+        cpu.registers.set_ecx(2);
+        cpu.stosb(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read16(0x1ffff).unwrap(), 0x1111);
+        assert_eq!(cpu.registers.edi, 0x1fffe);
+
+        // Storing bytes forwards stores them as a block.
+        cpu.registers.eflags.set_direction_flag(false);
+        cpu.registers.set_ecx(3);
+        cpu.registers.esi = 0x10;
+        cpu.registers.edi = 0x1fff;
+        cpu.stosb(&operands!()).unwrap();
+        assert_eq!(cpu.memory.read32(0x1fff).unwrap(), 0x111111);
+        assert_eq!(cpu.registers.esi, 0x10);
+        assert_eq!(cpu.registers.edi, 0x2002);
+    }
+
+    fn set_eax_to_0x21(cpu: &mut Cpu) {
+        cpu.registers.set_eax(0x21);
+    }
+
+    #[test]
+    fn int_imm8() {
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 128;
+        cpu.register_interrupt_handler(0x21, set_eax_to_0x21);
+
+        // Serviced by the host handler, so nothing is pushed onto the stack.
+        cpu.int_imm8(&operands!("0x21")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x21);
+        assert_eq!(cpu.registers.esp, 128);
+
+        // Delivered to guest code through the interrupt vector table.
+        cpu.memory.write16(0x22 * 4, 0x1234).unwrap();
+        cpu.memory.write16(0x22 * 4 + 2, 0x10).unwrap();
+        cpu.registers.cs = 0x20;
+        cpu.registers.set_eip(0x100);
+        cpu.registers.eflags.set_interrupt_enable_flag(true);
+        cpu.registers.eflags.set_trap_flag(true);
+        let flags = cpu.registers.eflags.get_value() as u16;
+        cpu.int_imm8(&operands!("0x22")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x1234);
+        assert_eq!(cpu.registers.cs, 0x10);
+        assert!(!cpu.registers.eflags.get_interrupt_enable_flag());
+        assert!(!cpu.registers.eflags.get_trap_flag());
+        assert_eq!(cpu.registers.esp, 122);
+        assert_eq!(cpu.memory.read16(122).unwrap(), 0x100);
+        assert_eq!(cpu.memory.read16(124).unwrap(), 0x20);
+        assert_eq!(cpu.memory.read16(126).unwrap(), flags);
+    }
 
-        assert_eq!(cpu.add(0xFF_u8, 0x80_u8), 0x7F_u8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = true);
+    #[test]
+    fn hlt() {
+        let mut cpu = Cpu::default();
+        cpu.hlt(&operands!()).unwrap();
+        assert!(cpu.halted);
 
-        assert_eq!(cpu.add(0x80_u8, 0x80_u8), 0x0_u8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = true, CF = true);
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_3);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.cs = 0x1b;
+        cpu.hlt(&operands!()).unwrap();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.registers.get_eax(), 3);
+    }
 
-        assert_eq!(cpu.add(0x7F_u8, 0x7F_u8), 0xFE_u8);
-        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = false);
+    #[test]
+    fn cli_and_sti() {
+        let mut cpu = Cpu::default();
+        cpu.sti(&operands!()).unwrap();
+        assert!(cpu.registers.eflags.get_interrupt_enable_flag());
+        assert!(cpu.interrupt_shadow);
+        cpu.cli(&operands!()).unwrap();
+        assert!(!cpu.registers.eflags.get_interrupt_enable_flag());
+
+        // At CPL 3, IOPL must also be 3.
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_3);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.cs = 0x1b;
+        cpu.sti(&operands!()).unwrap();
+        assert!(!cpu.registers.eflags.get_interrupt_enable_flag());
+        assert_eq!(cpu.registers.get_eax(), 3);
+        cpu.registers.eflags.set_iopl(CurrentPrivilegeLevel::CPL3);
+        cpu.sti(&operands!()).unwrap();
+        assert!(cpu.registers.eflags.get_interrupt_enable_flag());
+    }
+
+    fn set_eax_to_3(cpu: &mut Cpu) {
+        cpu.registers.set_eax(3);
     }
 
-    // https://stackoverflow.com/questions/8965923/carry-overflow-subtraction-in-x86#8982549
-    //       A                   B                   A - B              Flags
-    // ---------------     ----------------    ---------------      -----------------
-    // h  |  ud  |   d   | h  |  ud  |   d   | h  |  ud  |   d   || OF | SF | ZF | CF
-    // ---+------+-------+----+------+-------+----+------+-------++----+----+----+----
-    // FF | 255  |  -1   | FE | 254  |  -2   | 1  |  1   |   1   || 0  | 0  | 0  | 0
-    // 7E | 126  |  126  | FF | 255  |  -1   | 7F | 127  |  127  || 0  | 0  | 0  | 1
-    // FF | 255  |  -1   | FF | 255  |  -1   | 0  |  0   |   0   || 0  | 0  | 1  | 0
-    // FF | 255  |  -1   | 7F | 127  |  127  | 80 | 128  | -128  || 0  | 1  | 0  | 0
-    // FE | 254  |  -2   | FF | 255  |  -1   | FF | 255  |  -1   || 0  | 1  | 0  | 1
-    // FE | 254  |  -2   | 7F | 127  |  127  | 7F | 127  |  127  || 1  | 0  | 0  | 0
-    // 7F | 127  |  127  | FF | 255  |  -1   | 80 | 128  | -128  || 1  | 1  | 0  | 1
-    // TODO: Why can't you have the other flag combinations e.g. OF + ZF?
-    // TODO: Test for other 2 flags which are set.
     #[test]
-    fn sub() {
+    fn int3() {
         let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(3, set_eax_to_3);
+        cpu.int3(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 3);
+    }
 
-        // Decimal
-        assert_eq!(cpu.sub(-1_i8, -2_i8), 1_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+    fn set_eax_to_4(cpu: &mut Cpu) {
+        cpu.registers.set_eax(4);
+    }
 
-        assert_eq!(cpu.sub(126_i8, -1_i8), 127_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+    #[test]
+    fn interrupt_on_overflow() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(4, set_eax_to_4);
 
-        assert_eq!(cpu.sub(-1_i8, -1_i8), 0_i8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+        cpu.interrupt_on_overflow(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
 
-        assert_eq!(cpu.sub(-1_i8, 127_i8), -128_i8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+        cpu.registers.eflags.set_overflow_flag(true);
+        cpu.interrupt_on_overflow(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 4);
+    }
 
-        assert_eq!(cpu.sub(-2_i8, -1_i8), -1_i8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+    #[test]
+    fn iret() {
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 128;
+        cpu.memory.write16(0x22 * 4, 0x1234).unwrap();
+        cpu.memory.write16(0x22 * 4 + 2, 0x10).unwrap();
+        cpu.registers.cs = 0x20;
+        cpu.registers.set_eip(0x100);
+        cpu.registers.eflags.set_interrupt_enable_flag(true);
+        cpu.registers.eflags.set_carry_flag(true);
+        let eflags = cpu.registers.eflags.get_value();
+
+        cpu.int_imm8(&operands!("0x22")).unwrap();
+        assert!(!cpu.registers.eflags.get_interrupt_enable_flag());
+        cpu.iret(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x100);
+        assert_eq!(cpu.registers.cs, 0x20);
+        assert_eq!(cpu.registers.eflags.get_value(), eflags);
+        assert_eq!(cpu.registers.esp, 128);
+    }
 
-        assert_eq!(cpu.sub(-2_i8, 127_i8), 127_i8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = false);
+    #[test]
+    fn iretd() {
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 128;
+        cpu.push32(0x0000_0a83).unwrap();
+        cpu.push32(0xffff_0020).unwrap();
+        cpu.push32(0x1234_5678).unwrap();
+
+        cpu.registers.eflags.set_resume_flag(true);
+        cpu.iretd(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x1234_5678);
+        assert_eq!(cpu.registers.cs, 0x20);
+        assert!(cpu.registers.eflags.get_carry_flag());
+        assert!(cpu.registers.eflags.get_sign_flag());
+        assert!(cpu.registers.eflags.get_interrupt_enable_flag());
+        assert!(cpu.registers.eflags.get_overflow_flag());
+        assert!(!cpu.registers.eflags.get_resume_flag());
+        assert_eq!(cpu.registers.esp, 128);
+    }
 
-        assert_eq!(cpu.sub(127_i8, -1_i8), -128_i8);
-        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = true);
+    #[test]
+    fn call_and_ret() {
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 0x100;
+        cpu.registers.set_eip(0x10);
+        cpu.call_rel32(&operands!("0x40")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x40);
+        assert_eq!(cpu.registers.esp, 0xfc);
+        assert_eq!(cpu.memory.read32(0xfc).unwrap(), 0x10);
+        cpu.ret(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x10);
+        assert_eq!(cpu.registers.esp, 0x100);
+
+        cpu.call_rel32(&operands!("0x40")).unwrap();
+        cpu.ret_imm16(&operands!("8")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x10);
+        assert_eq!(cpu.registers.esp, 0x108);
+    }
 
-        // Unsigned decimal
-        assert_eq!(cpu.sub(255_u8, 254_u8), 1_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+    #[test]
+    fn jcc() {
+        let mut cpu = Cpu::default();
+        // 1 - 2 sets CF and SF, and clears ZF and OF.
+        cpu.cmp(1_u32, 2_u32);
+        for (function, taken) in [
+            (
+                Cpu::ja_rel32 as fn(&mut Cpu, &Operands) -> Result<(), Error>,
+                false,
+            ),
+            (Cpu::jae_rel32, false),
+            (Cpu::jb_rel32, true),
+            (Cpu::jbe_rel32, true),
+            (Cpu::je_rel32, false),
+            (Cpu::jne_rel32, true),
+            (Cpu::jg_rel32, false),
+            (Cpu::jge_rel32, false),
+            (Cpu::jl_rel32, true),
+            (Cpu::jle_rel32, true),
+            (Cpu::jo_rel32, false),
+            (Cpu::jno_rel32, true),
+            (Cpu::js_rel32, true),
+            (Cpu::jns_rel32, false),
+            (Cpu::jmp_rel32, true),
+        ] {
+            cpu.registers.set_eip(0x10);
+            function(&mut cpu, &operands!("0x20")).unwrap();
+            let expected = if taken { 0x20 } else { 0x10 };
+            assert_eq!(cpu.registers.get_eip(), expected);
+        }
 
-        assert_eq!(cpu.sub(126_u8, 255_u8), 127_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+        cpu.registers.eflags.set_parity_flag(true);
+        cpu.registers.set_eip(0x10);
+        cpu.jnp_rel32(&operands!("0x20")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x10);
+        cpu.jp_rel32(&operands!("0x20")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x20);
+    }
 
-        assert_eq!(cpu.sub(255_u8, 255_u8), 0_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+    #[test]
+    fn lea_reg16_mem() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ebx(10);
+        cpu.lea_reg16_mem(&operands!("ax", "[ebx]")).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 10);
+    }
 
-        assert_eq!(cpu.sub(255_u8, 127_u8), 128_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+    #[test]
+    fn descriptor_tables() {
+        let mut cpu = Cpu::default();
+        assert_eq!(cpu.registers.gdtr, DescriptorTableRegister::default());
+
+        cpu.memory.write16(0x100, 0x17).unwrap();
+        cpu.memory.write32(0x102, 0x0001_0000).unwrap();
+        cpu.memory.write16(0x108, 0x7ff).unwrap();
+        cpu.memory.write32(0x10a, 0x0002_0000).unwrap();
+        cpu.lgdt_mem(&operands!("[0x100]")).unwrap();
+        cpu.lidt_mem(&operands!("[0x108]")).unwrap();
+        assert_eq!(cpu.registers.gdtr.base, 0x0001_0000);
+        assert_eq!(cpu.registers.gdtr.limit, 0x17);
+        assert_eq!(cpu.registers.idtr.base, 0x0002_0000);
+        assert_eq!(cpu.registers.idtr.limit, 0x7ff);
+
+        cpu.sgdt_mem(&operands!("[0x200]")).unwrap();
+        cpu.sidt_mem(&operands!("[0x208]")).unwrap();
+        assert_eq!(cpu.memory.read16(0x200).unwrap(), 0x17);
+        assert_eq!(cpu.memory.read32(0x202).unwrap(), 0x0001_0000);
+        assert_eq!(cpu.memory.read16(0x208).unwrap(), 0x7ff);
+        assert_eq!(cpu.memory.read32(0x20a).unwrap(), 0x0002_0000);
+
+        // In real mode, the interrupt vector table is located through the IDTR.
+        cpu.registers.esp = 0x400;
+        cpu.memory.write16(0x0002_0000 + 0x21 * 4, 0x1234).unwrap();
+        cpu.memory
+            .write16(0x0002_0000 + 0x21 * 4 + 2, 0x10)
+            .unwrap();
+        cpu.int_imm8(&operands!("0x21")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x1234);
+        assert_eq!(cpu.registers.cs, 0x10);
+
+        // Outside of CPL 0, loading raises #GP, but storing is still permitted.
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.cs = 0x1b;
+        cpu.lgdt_mem(&operands!("[0x108]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.gdtr.base, 0x0001_0000);
+
+        cpu.registers.set_eax(0);
+        cpu.sgdt_mem(&operands!("[0x300]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
+        assert_eq!(cpu.memory.read32(0x302).unwrap(), 0x0001_0000);
+    }
 
-        assert_eq!(cpu.sub(254_u8, 255_u8), 255_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+    #[test]
+    fn segment_descriptors() {
+        let mut cpu = Cpu::default();
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.cs = 0x08;
+        cpu.registers.gdtr = DescriptorTableRegister {
+            base: 0x1000,
+            limit: 0x2f,
+        };
+        for (index, descriptor) in [
+            0,
+            // A flat ring 0 code segment.
+            0x00cf_9a00_0000_ffff,
+            // A ring 0 data segment at 0x5000, with a limit of 0xfff.
+            0x0040_9200_5000_0fff,
+            // A flat ring 3 data segment.
+            0x00cf_f200_0000_ffff,
+            // A ring 0 data segment which is not present.
+            0x00cf_1200_0000_ffff,
+            // An LDT at 0x2000, with 2 entries.
+            0x0000_8200_2000_000f,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            cpu.memory
+                .write64(0x1000 + index as u32 * 8, descriptor)
+                .unwrap();
+        }
+        cpu.memory.write64(0x2008, 0x0000_9200_6000_00ff).unwrap();
+
+        cpu.load_segment(SegmentRegister::Ds, 0x10);
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.ds, 0x10);
+        assert_eq!(cpu.registers.get_segment_base(SegmentRegister::Ds), 0x5000);
+        assert_eq!(cpu.registers.get_segment(SegmentRegister::Ds).limit, 0xfff);
+
+        // SS must be a writable data segment at the CPL.
+        cpu.load_segment(SegmentRegister::Ss, 0x18);
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        assert_eq!(cpu.registers.ss, 0);
+        cpu.load_segment(SegmentRegister::Ss, 0x10);
+        assert_eq!(cpu.fault.take(), None);
+
+        // Descriptors which are not present, beyond the limit of the GDT, or not code or data
+        // segments cannot be loaded.
+        cpu.load_segment(SegmentRegister::Es, 0x20);
+        assert_eq!(cpu.fault.take(), Some(CpuException::SegmentNotPresent));
+        cpu.load_segment(SegmentRegister::Es, 0x30);
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        cpu.load_segment(SegmentRegister::Es, 0x28);
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+
+        // The null selector can be loaded, but the segment cannot then be used.
+        cpu.load_segment(SegmentRegister::Fs, 0);
+        assert_eq!(cpu.fault.take(), None);
+        assert!(cpu.linear_address(SegmentRegister::Fs, 0, 1).is_err());
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+
+        // Selectors in the LDT refer to nothing until it has been loaded.
+        cpu.load_segment(SegmentRegister::Gs, 0x0c);
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        cpu.registers.set_eax(0x28);
+        cpu.lldt_rm16(&operands!("ax")).unwrap();
+        cpu.load_segment(SegmentRegister::Gs, 0x0c);
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_segment_base(SegmentRegister::Gs), 0x6000);
+        cpu.sldt_rm16(&operands!("bx")).unwrap();
+        assert_eq!(cpu.registers.get_bx(), 0x28);
+
+        // Only a selector of an LDT descriptor can be loaded into the LDTR.
+        cpu.registers.set_eax(0x10);
+        cpu.lldt_rm16(&operands!("ax")).unwrap();
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        assert_eq!(cpu.registers.ldtr, 0x28);
+    }
 
-        assert_eq!(cpu.sub(254_u8, 127_u8), 127_u8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = false);
+    #[test]
+    fn operating_modes() {
+        let mut cpu = Cpu::default();
+        cpu.registers.ds = 0x100;
+        cpu.registers.ss = 0x200;
+        cpu.enter_real_mode();
+        assert_eq!(cpu.registers.get_operating_mode(), OperatingMode::Real);
+        assert_eq!(cpu.registers.get_default_size(), Size::Word);
+
+        // A near call pushes IP, and SP wraps around within the stack segment.
+        cpu.registers.esp = 0x0001_0000;
+        cpu.registers.set_eip(0x1234);
+        cpu.call_rel32(&operands!("0x5678")).unwrap();
+        assert_eq!(cpu.registers.esp, 0x0001_fffe);
+        assert_eq!(cpu.memory.read16(0x11ffe).unwrap(), 0x1234);
+        cpu.ret(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x1234);
+        assert_eq!(cpu.registers.esp, 0x0001_0000);
+
+        // String instructions use SI, DI, and CX, as do addresses without registers.
+        cpu.memory.write16(0x10ffe, 0xbeef).unwrap();
+        cpu.registers.esi = 0x0001_fffe;
+        cpu.lodsw(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0xbeef);
+        assert_eq!(cpu.registers.esi, 0x0001_0000);
+        cpu.registers.set_ecx(0x0001_0002);
+        cpu.repeat_prefix = Some(RepeatPrefix::Rep);
+        cpu.stosb(&operands!()).unwrap();
+        cpu.repeat_prefix = None;
+        assert_eq!(cpu.registers.get_ecx(), 0x0001_0000);
+        assert_eq!(cpu.memory.read16(0).unwrap(), 0xefef);
+        cpu.mov_ax_moffs16(&operands!("ax", "[0xfffe]")).unwrap();
+        assert_eq!(cpu.registers.get_ax(), 0xbeef);
+
+        // Instructions which are only recognized in protected mode raise #UD.
+        cpu.lldt_rm16(&operands!("ax")).unwrap();
+        assert_eq!(
+            cpu.unreported_exception.take(),
+            Some(CpuException::InvalidOpcode)
+        );
 
-        assert_eq!(cpu.sub(127_u8, 255_u8), 128_u8);
-        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = true);
+        // Setting CR0.PE enters protected mode, but the segments keep their size until they are
+        // reloaded from descriptors.
+        cpu.registers.control_registers.set_protection_enable(true);
+        assert_eq!(cpu.registers.get_operating_mode(), OperatingMode::Protected);
+        assert_eq!(cpu.registers.get_default_size(), Size::Word);
+        cpu.registers.gdtr = DescriptorTableRegister {
+            base: 0x1000,
+            limit: 0x17,
+        };
+        // A flat 32-bit code segment, and a 16-bit data segment.
+        cpu.memory.write64(0x1008, 0x00cf_9a00_0000_ffff).unwrap();
+        cpu.memory.write64(0x1010, 0x0000_9200_0000_ffff).unwrap();
+        cpu.registers.cs = 0;
+        cpu.load_segment(SegmentRegister::Cs, 0x08);
+        cpu.load_segment(SegmentRegister::Ss, 0x10);
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_default_size(), Size::Dword);
+        assert_eq!(cpu.registers.get_stack_pointer(), 0);
+
+        // Clearing CR0.PE returns to real mode, with the segments as they were.
+        cpu.registers.control_registers.set_protection_enable(false);
+        assert_eq!(cpu.registers.get_operating_mode(), OperatingMode::Real);
+        assert_eq!(cpu.registers.get_default_size(), Size::Dword);
+    }
 
-        // Hexadecimal
-        assert_eq!(cpu.sub(0xFF_u8, 0xFE_u8), 0x1_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = false);
+    #[test]
+    fn far_call_and_return() {
+        let mut cpu = Cpu::default();
+        cpu.enter_real_mode();
+        cpu.registers.esp = 0x100;
+        cpu.registers.set_eip(0x10);
+        cpu.call_far32(&operands!("0x1000:0x20")).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x20);
+        assert_eq!(cpu.registers.cs, 0x1000);
+        assert_eq!(
+            cpu.registers.get_segment_base(SegmentRegister::Cs),
+            0x0001_0000
+        );
+        assert_eq!(cpu.registers.esp, 0xfc);
+        assert_eq!(cpu.memory.read16(0xfc).unwrap(), 0x10);
+        cpu.retf(&operands!()).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x10);
+        assert_eq!(cpu.registers.cs, 0);
+        assert_eq!(cpu.registers.esp, 0x100);
+
+        // In protected mode, a far call to a code segment stays at the same privilege level, and
+        // returning to an outer privilege level switches back to the caller's stack.
+        let mut cpu = Cpu::default();
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.gdtr = DescriptorTableRegister {
+            base: 0x1000,
+            limit: 0x27,
+        };
+        // Flat code and data segments for CPL 0 and CPL 3.
+        cpu.memory.write64(0x1008, 0x00cf_9a00_0000_ffff).unwrap();
+        cpu.memory.write64(0x1010, 0x00cf_9200_0000_ffff).unwrap();
+        cpu.memory.write64(0x1018, 0x00cf_fa00_0000_ffff).unwrap();
+        cpu.memory.write64(0x1020, 0x00cf_f200_0000_ffff).unwrap();
+        cpu.registers.cs = 0;
+        cpu.load_segment(SegmentRegister::Cs, 0x08);
+        cpu.load_segment(SegmentRegister::Ss, 0x10);
+        cpu.load_segment(SegmentRegister::Ds, 0x10);
+        cpu.registers.esp = 0x8000;
+        cpu.jmp_far32(&operands!("0x08:0x300")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_eip(), 0x300);
+        cpu.jmp_far32(&operands!("0x0b:0x300")).unwrap();
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        cpu.jmp_far32(&operands!("0x1b:0x300")).unwrap();
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+
+        cpu.push32(0x23).unwrap();
+        cpu.push32(0x7000).unwrap();
+        cpu.push32(0x202).unwrap();
+        cpu.push32(0x1b).unwrap();
+        cpu.push32(0x400).unwrap();
+        cpu.iretd(&operands!()).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_cpl(), CurrentPrivilegeLevel::CPL3);
+        assert_eq!(cpu.registers.get_eip(), 0x400);
+        assert_eq!(cpu.registers.ss, 0x23);
+        assert_eq!(cpu.registers.esp, 0x7000);
+        // DS referred to a segment which CPL 3 cannot access, so it was nulled.
+        assert_eq!(cpu.registers.ds, 0);
+        assert!(!cpu.registers.get_segment(SegmentRegister::Ds).usable);
+    }
 
-        assert_eq!(cpu.sub(0x7E_u8, 0xFF_u8), 0x7F_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = false, CF = true);
+    #[test]
+    fn task_switches() {
+        let mut cpu = Cpu::default();
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.gdtr = DescriptorTableRegister {
+            base: 0x1000,
+            limit: 0x2f,
+        };
+        // Flat code and data segments, TSSs at 0x2000 and 0x3000, and a task gate for the second.
+        cpu.memory.write64(0x1008, 0x00cf_9a00_0000_ffff).unwrap();
+        cpu.memory.write64(0x1010, 0x00cf_9200_0000_ffff).unwrap();
+        cpu.memory.write64(0x1018, 0x0000_8900_2000_0067).unwrap();
+        cpu.memory.write64(0x1020, 0x0000_8900_3000_0067).unwrap();
+        cpu.memory.write64(0x1028, 0x0000_8500_0020_0000).unwrap();
+        cpu.registers.cs = 0;
+        cpu.load_segment(SegmentRegister::Cs, 0x08);
+        for segment in [
+            SegmentRegister::Ss,
+            SegmentRegister::Ds,
+            SegmentRegister::Es,
+        ] {
+            cpu.load_segment(segment, 0x10);
+        }
 
-        assert_eq!(cpu.sub(0xFF_u8, 0xFF_u8), 0x0_u8);
-        assert_eflags!(cpu, OF = false, SF = false, ZF = true, CF = false);
+        // The first task is set up with LTR, which marks its TSS as busy.
+        cpu.registers.set_eax(0x18);
+        cpu.ltr_rm16(&operands!("ax")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        cpu.str_rm16(&operands!("bx")).unwrap();
+        assert_eq!(cpu.registers.get_bx(), 0x18);
+        assert_eq!(cpu.memory.read64(0x1018).unwrap(), 0x0000_8b00_2000_0067);
+
+        let second = TaskState {
+            eip: 0x500,
+            eflags: 0x2,
+            general_purpose: [0, 0, 0, 0, 0x9000, 0, 0, 0],
+            segments: [0x10, 0x08, 0x10, 0x10, 0x10, 0x10],
+            ..TaskState::default()
+        };
+        cpu.memory.write_bytes(0x3000, &second.to_bytes()).unwrap();
+
+        // Calling through the task gate nests the second task within the first.
+        cpu.registers.set_eip(0x100);
+        cpu.registers.esp = 0x8000;
+        cpu.registers.set_ecx(0x1234);
+        cpu.call_far32(&operands!("0x28:0")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.tr, 0x20);
+        assert_eq!(cpu.registers.get_eip(), 0x500);
+        assert_eq!(cpu.registers.esp, 0x9000);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert!(cpu.registers.eflags.get_nested_task());
+        assert!(cpu.registers.control_registers.get_task_switched());
+        assert_eq!(cpu.memory.read16(0x3000).unwrap(), 0x18);
+        assert_eq!(cpu.memory.read64(0x1020).unwrap(), 0x0000_8b00_3000_0067);
+        let first = cpu.read_task_state(0x2000).unwrap();
+        assert_eq!(first.eip, 0x100);
+        assert_eq!(first.general_purpose[1], 0x1234);
+        assert_eq!(first.general_purpose[4], 0x8000);
+        assert_eq!(first.segments[SegmentRegister::Cs.index()], 0x08);
+
+        // A busy task cannot be called.
+        cpu.call_far32(&operands!("0x18:0")).unwrap();
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+
+        // IRET returns to the first task, as NT is set.
+        cpu.iretd(&operands!()).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.tr, 0x18);
+        assert_eq!(cpu.registers.get_eip(), 0x100);
+        assert_eq!(cpu.registers.esp, 0x8000);
+        assert_eq!(cpu.registers.get_ecx(), 0x1234);
+        assert!(!cpu.registers.eflags.get_nested_task());
+        assert_eq!(cpu.memory.read64(0x1020).unwrap(), 0x0000_8900_3000_0067);
+
+        // Jumping to the second task leaves the first, which is no longer busy.
+        cpu.jmp_far32(&operands!("0x20:0")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.tr, 0x20);
+        assert!(!cpu.registers.eflags.get_nested_task());
+        assert_eq!(cpu.memory.read64(0x1018).unwrap(), 0x0000_8900_2000_0067);
+    }
 
-        assert_eq!(cpu.sub(0xFF_u8, 0x7F_u8), 0x80_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = false);
+    #[test]
+    fn interrupt_gates() {
+        let mut cpu = Cpu::default();
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.gdtr = DescriptorTableRegister {
+            base: 0x1000,
+            limit: 0x2f,
+        };
+        cpu.registers.idtr = DescriptorTableRegister {
+            base: 0x3000,
+            limit: 0xff,
+        };
+        // Flat code and data segments for CPL 0 and CPL 3, and a TSS at 0x2000.
+        cpu.memory.write64(0x1008, 0x00cf_9a00_0000_ffff).unwrap();
+        cpu.memory.write64(0x1010, 0x00cf_9200_0000_ffff).unwrap();
+        cpu.memory.write64(0x1018, 0x00cf_fa00_0000_ffff).unwrap();
+        cpu.memory.write64(0x1020, 0x00cf_f200_0000_ffff).unwrap();
+        cpu.memory.write64(0x1028, 0x0000_8900_2000_0067).unwrap();
+        let state = TaskState {
+            stacks: [(0x10, 0x9000), (0, 0), (0, 0)],
+            ..TaskState::default()
+        };
+        cpu.memory.write_bytes(0x2000, &state.to_bytes()).unwrap();
+        cpu.registers.cs = 0;
+        cpu.load_segment(SegmentRegister::Cs, 0x08);
+        cpu.load_segment(SegmentRegister::Ss, 0x10);
+        cpu.registers.set_eax(0x28);
+        cpu.ltr_rm16(&operands!("ax")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+
+        // An interrupt gate pushes a 32-bit frame which IRETD returns from, and clears IF.
+        cpu.set_interrupt_vector(0x10, 0x08, 0x500).unwrap();
+        assert_eq!(cpu.memory.read64(0x3080).unwrap(), 0x0000_ee00_0008_0500);
+        assert_eq!(
+            cpu.interrupt_vector(0x10),
+            InterruptVector::Guest {
+                segment: 0x08,
+                offset: 0x500
+            }
+        );
+        cpu.registers.eflags.set_value(0x202);
+        cpu.registers.set_eip(0x100);
+        cpu.registers.esp = 0x8000;
+        cpu.int_imm8(&operands!("0x10")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_eip(), 0x500);
+        assert_eq!(cpu.registers.esp, 0x8000 - 12);
+        assert_eq!(cpu.memory.read32(0x8000 - 12).unwrap(), 0x100);
+        assert_eq!(cpu.memory.read32(0x8000 - 8).unwrap(), 0x08);
+        assert_eq!(cpu.memory.read32(0x8000 - 4).unwrap(), 0x202);
+        assert!(!cpu.registers.eflags.get_interrupt_enable_flag());
+        cpu.iretd(&operands!()).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_eip(), 0x100);
+        assert_eq!(cpu.registers.esp, 0x8000);
+        assert!(cpu.registers.eflags.get_interrupt_enable_flag());
+
+        // Gates which are null, not present, or of the wrong type cannot be used.
+        cpu.memory.write64(0x3090, 0x0000_0e00_0008_0500).unwrap();
+        cpu.memory.write64(0x3098, 0x0000_8c00_0008_0500).unwrap();
+        for (vector, exception) in [
+            (0x11, CpuException::GeneralProtection),
+            (0x12, CpuException::SegmentNotPresent),
+            (0x13, CpuException::GeneralProtection),
+            (0x20, CpuException::GeneralProtection),
+        ] {
+            cpu.interrupt(vector, InterruptKind::Software).unwrap();
+            assert_eq!(cpu.fault.take(), Some(exception));
+            assert_eq!(cpu.registers.get_eip(), 0x100);
+        }
 
-        assert_eq!(cpu.sub(0xFE_u8, 0xFF_u8), 0xFF_u8);
-        assert_eflags!(cpu, OF = false, SF = true, ZF = false, CF = true);
+        // Interrupting CPL 3 switches to the CPL 0 stack from the TSS, onto which the interrupted
+        // stack is pushed.
+        cpu.push32(0x23).unwrap();
+        cpu.push32(0x7000).unwrap();
+        cpu.push32(0x202).unwrap();
+        cpu.push32(0x1b).unwrap();
+        cpu.push32(0x400).unwrap();
+        cpu.iretd(&operands!()).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_cpl(), CurrentPrivilegeLevel::CPL3);
+        cpu.int_imm8(&operands!("0x10")).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_cpl(), CurrentPrivilegeLevel::CPL0);
+        assert_eq!(cpu.registers.ss, 0x10);
+        assert_eq!(cpu.registers.esp, 0x9000 - 20);
+        assert_eq!(cpu.memory.read32(0x9000 - 20).unwrap(), 0x400);
+        assert_eq!(cpu.memory.read32(0x9000 - 16).unwrap(), 0x1b);
+        assert_eq!(cpu.memory.read32(0x9000 - 8).unwrap(), 0x7000);
+        assert_eq!(cpu.memory.read32(0x9000 - 4).unwrap(), 0x23);
+        cpu.iretd(&operands!()).unwrap();
+        assert_eq!(cpu.fault.take(), None);
+        assert_eq!(cpu.registers.get_cpl(), CurrentPrivilegeLevel::CPL3);
+        assert_eq!(cpu.registers.get_eip(), 0x400);
+        assert_eq!((cpu.registers.ss, cpu.registers.esp), (0x23, 0x7000));
+
+        // INT may only use a gate whose DPL is at least the CPL, but exceptions may use any gate,
+        // and push their error code.
+        cpu.memory.write64(0x3068, 0x0000_8e00_0008_0600).unwrap();
+        cpu.int_imm8(&operands!("0x0d")).unwrap();
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        cpu.raise_exception(CpuException::GeneralProtection);
+        assert_eq!(cpu.unreported_exception, None);
+        assert_eq!(cpu.registers.get_eip(), 0x600);
+        assert_eq!(cpu.registers.esp, 0x9000 - 24);
+        assert_eq!(cpu.memory.read32(0x9000 - 24).unwrap(), 0);
+        assert_eq!(cpu.memory.read32(0x9000 - 20).unwrap(), 0x400);
+    }
 
-        assert_eq!(cpu.sub(0xFE_u8, 0x7F_u8), 0x7F_u8);
-        assert_eflags!(cpu, OF = true, SF = false, ZF = false, CF = false);
+    #[test]
+    fn lea_reg32_mem() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ebx(10);
+        cpu.lea_reg32_mem(&operands!("eax", "[ebx]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 10);
+    }
 
-        assert_eq!(cpu.sub(0x7F_u8, 0xFF_u8), 0x80_u8);
-        assert_eflags!(cpu, OF = true, SF = true, ZF = false, CF = true);
+    #[test]
+    fn machine_status_word() {
+        let mut cpu = Cpu::default();
+        cpu.smsw_rm16(&operands!("ax")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x0010);
+
+        // Only PE, MP, EM, and TS are loaded.
+        cpu.registers.set_ecx(0xfffe);
+        cpu.lmsw_rm16(&operands!("cx")).unwrap();
+        assert_eq!(cpu.registers.control_registers.get_cr0(), 0x6000_001e);
+
+        cpu.memory.write16(0x100, 0x0009).unwrap();
+        cpu.lmsw_rm16(&operands!("[0x100]")).unwrap();
+        assert_eq!(cpu.registers.control_registers.get_cr0(), 0x6000_0019);
+        cpu.smsw_reg32(&operands!("edx")).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0x6000_0019);
+        cpu.smsw_rm16(&operands!("[0x200]")).unwrap();
+        assert_eq!(cpu.memory.read32(0x200).unwrap(), 0x0019);
+
+        // PE cannot be cleared.
+        cpu.lmsw_rm16(&operands!("[0x300]")).unwrap();
+        assert_eq!(cpu.registers.control_registers.get_cr0(), 0x6000_0011);
+
+        // Outside of CPL 0, LMSW raises #GP, but SMSW is still permitted.
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
+        cpu.registers.cs = 0x1b;
+        cpu.lmsw_rm16(&operands!("[0x100]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.control_registers.get_cr0(), 0x6000_0011);
+        cpu.smsw_rm16(&operands!("bx")).unwrap();
+        assert_eq!(cpu.registers.get_ebx(), 0x0011);
     }
 
     #[test]
-    fn and() {
+    fn mov_cr() {
         let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::GeneralProtection.vector(), set_eax_to_0x5);
 
-        cpu.registers.eflags.set_overflow_flag(true);
-        cpu.registers.eflags.set_carry_flag(true);
+        cpu.mov_reg32_cr(&operands!("ebx", "cr0")).unwrap();
+        assert_eq!(cpu.registers.get_ebx(), 0x6000_0010);
 
-        assert_eq!(
-            cpu.and(0b0000_0001_u8, 0b1111_1111_u8),
-            0b0000_0001_u8 & 0b1111_1111_u8
-        );
-        assert_eflags!(
-            cpu,
-            OF = false,
-            CF = false,
-            SF = false,
-            ZF = false,
-            PF = false
-        );
+        cpu.registers.set_ecx(0x0010_0000);
+        cpu.mov_cr_reg32(&operands!("cr3", "ecx")).unwrap();
+        cpu.mov_reg32_cr(&operands!("edx", "cr3")).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0x0010_0000);
 
-        assert_eq!(
-            cpu.and(0b0000_0011_u8, 0b1111_1111_u8),
-            0b0000_0011_u8 & 0b1111_1111_u8
-        );
-        assert_eflags!(
-            cpu,
-            OF = false,
-            CF = false,
-            SF = false,
-            ZF = false,
-            PF = true
-        );
+        // Enabling paging without protected mode raises #GP, leaving CR0 unchanged.
+        cpu.registers.set_ecx(0x8000_0000);
+        cpu.mov_cr_reg32(&operands!("cr0", "ecx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.control_registers.get_cr0(), 0x6000_0010);
 
+        cpu.registers.set_eax(0);
+        cpu.registers.set_ecx(0x8000_0001);
+        cpu.mov_cr_reg32(&operands!("cr0", "ecx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
+        assert!(cpu.registers.control_registers.get_protection_enable());
+        assert!(cpu.registers.control_registers.get_paging());
+
+        // Outside of CPL 0, both directions raise #GP.
+        cpu.registers.cs = 0x1b;
+        cpu.registers.set_ebx(0);
+        cpu.mov_reg32_cr(&operands!("ebx", "cr0")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.get_ebx(), 0);
+
+        cpu.registers.set_eax(0);
+        cpu.mov_cr_reg32(&operands!("cr3", "ebx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.control_registers.get_cr3(), 0x0010_0000);
+    }
+
+    #[test]
+    fn mov_dr() {
+        let mut cpu = Cpu::default();
+        cpu.register_interrupt_handler(CpuException::Debug.vector(), set_eax_to_0x5);
+
+        cpu.registers.set_ecx(0x1234);
+        cpu.mov_dr_reg32(&operands!("dr2", "ecx")).unwrap();
+        cpu.mov_reg32_dr(&operands!("edx", "dr2")).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0x1234);
         assert_eq!(
-            cpu.and(0b0000_0000_u8, 0b1111_1111_u8),
-            0b0000_0000_u8 & 0b1111_1111_u8
-        );
-        assert_eflags!(
-            cpu,
-            OF = false,
-            CF = false,
-            SF = false,
-            ZF = true,
-            PF = true
+            cpu.registers.debug_registers.get_breakpoint_address(2),
+            0x1234
         );
 
+        // DR4 and DR5 alias DR6 and DR7.
+        cpu.mov_reg32_dr(&operands!("edx", "dr4")).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0xffff_0ff0);
+        cpu.registers.set_ecx(0x0003_0010);
+        cpu.mov_dr_reg32(&operands!("dr5", "ecx")).unwrap();
+        cpu.mov_reg32_dr(&operands!("edx", "dr7")).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0x0003_0410);
+        assert!(cpu.registers.debug_registers.get_local_breakpoint_enable(2));
         assert_eq!(
-            cpu.and(0b1000_0000_u8, 0b1111_1111_u8),
-            0b1000_0000_u8 & 0b1111_1111_u8
-        );
-        assert_eflags!(
-            cpu,
-            OF = false,
-            CF = false,
-            SF = true,
-            ZF = false,
-            PF = false
+            cpu.registers.debug_registers.get_breakpoint_condition(0),
+            0b11
         );
-    }
 
-    #[test]
-    fn lea_reg16_mem() {
-        let mut cpu = Cpu::default();
-        cpu.registers.set_ebx(10);
-        cpu.lea_reg16_mem(&operands!("ax", "[ebx]"));
-        assert_eq!(cpu.registers.get_ax(), 10);
+        // With general detect enabled, the next access raises #DB instead.
+        cpu.registers.set_ecx(0x2000);
+        cpu.mov_dr_reg32(&operands!("dr7", "ecx")).unwrap();
+        cpu.registers.set_edx(0);
+        cpu.mov_reg32_dr(&operands!("edx", "dr0")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.get_edx(), 0);
+        assert!(!cpu.registers.debug_registers.get_general_detect_enable());
+        assert!(cpu
+            .registers
+            .debug_registers
+            .get_debug_register_access_detected());
+
+        cpu.registers.set_eax(0);
+        cpu.mov_reg32_dr(&operands!("edx", "dr6")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
+        assert_eq!(cpu.registers.get_edx(), 0xffff_2ff0);
+
+        // With debugging extensions enabled, DR4 and DR5 raise #UD.
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_eax_to_3);
+        cpu.registers
+            .control_registers
+            .set_debugging_extensions(true);
+        cpu.mov_reg32_dr(&operands!("edx", "dr4")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 3);
+        cpu.registers.set_eax(0);
+        cpu.mov_dr_reg32(&operands!("dr5", "ecx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 3);
+        cpu.registers.set_eax(0);
+        cpu.mov_reg32_dr(&operands!("edx", "dr6")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0);
     }
 
     #[test]
-    fn lea_reg32_mem() {
+    fn mov_moffs() {
         let mut cpu = Cpu::default();
-        cpu.registers.set_ebx(10);
-        cpu.lea_reg32_mem(&operands!("eax", "[ebx]"));
-        assert_eq!(cpu.registers.get_eax(), 10);
+        cpu.memory.write32(0x1234, 0x44332211).unwrap();
+        cpu.mov_al_moffs8(&operands!("al", "[0x1234]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x11);
+        cpu.mov_ax_moffs16(&operands!("ax", "[0x1235]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x3322);
+        cpu.mov_eax_moffs32(&operands!("eax", "[0x1234]")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x44332211);
+
+        cpu.registers.set_eax(0xaabbccdd);
+        cpu.mov_moffs8_al(&operands!("[0x2000]", "al")).unwrap();
+        assert_eq!(cpu.memory.read32(0x2000).unwrap(), 0xdd);
+        cpu.mov_moffs16_ax(&operands!("[0x2001]", "ax")).unwrap();
+        assert_eq!(cpu.memory.read32(0x2000).unwrap(), 0xccdddd);
+        cpu.mov_moffs32_eax(&operands!("[0x2004]", "eax")).unwrap();
+        assert_eq!(cpu.memory.read32(0x2004).unwrap(), 0xaabbccdd);
     }
 
     #[test]
@@ -945,10 +6757,10 @@ mod tests {
         let mut cpu = Cpu::default();
 
         cpu.registers.set_bh(1);
-        cpu.mov_rm8_reg8(&operands!("ah", "bh"));
+        cpu.mov_rm8_reg8(&operands!("ah", "bh")).unwrap();
         assert_eq!(cpu.registers.get_ah(), 1);
 
-        cpu.mov_rm8_reg8(&operands!("BYTE [0]", "bh"));
+        cpu.mov_rm8_reg8(&operands!("BYTE [0]", "bh")).unwrap();
         assert_eq!(cpu.memory.read8(0).unwrap(), 1);
     }
 
@@ -957,10 +6769,10 @@ mod tests {
         let mut cpu = Cpu::default();
 
         cpu.registers.set_bx(1);
-        cpu.mov_rm16_reg16(&operands!("ax", "bx"));
+        cpu.mov_rm16_reg16(&operands!("ax", "bx")).unwrap();
         assert_eq!(cpu.registers.get_ax(), 1);
 
-        cpu.mov_rm16_reg16(&operands!("WORD [0]", "bx"));
+        cpu.mov_rm16_reg16(&operands!("WORD [0]", "bx")).unwrap();
         assert_eq!(cpu.memory.read16(0).unwrap(), 1);
     }
 
@@ -969,10 +6781,10 @@ mod tests {
         let mut cpu = Cpu::default();
 
         cpu.registers.set_ebx(1);
-        cpu.mov_rm32_reg32(&operands!("eax", "ebx"));
+        cpu.mov_rm32_reg32(&operands!("eax", "ebx")).unwrap();
         assert_eq!(cpu.registers.get_eax(), 1);
 
-        cpu.mov_rm32_reg32(&operands!("BYTE [0]", "ebx"));
+        cpu.mov_rm32_reg32(&operands!("BYTE [0]", "ebx")).unwrap();
         assert_eq!(cpu.memory.read32(0).unwrap(), 1);
     }
 
@@ -983,10 +6795,10 @@ mod tests {
         cpu.registers.set_al(1);
         cpu.registers.set_bl(2);
 
-        cpu.mov_reg8_rm8(&operands!("al", "[0]"));
+        cpu.mov_reg8_rm8(&operands!("al", "[0]")).unwrap();
         assert_eq!(cpu.registers.get_al(), 0);
 
-        cpu.mov_reg8_rm8(&operands!("al", "bl"));
+        cpu.mov_reg8_rm8(&operands!("al", "bl")).unwrap();
         assert_eq!(cpu.registers.get_al(), 2);
     }
 
@@ -997,10 +6809,10 @@ mod tests {
         cpu.registers.set_ax(1);
         cpu.registers.set_bx(2);
 
-        cpu.mov_reg16_rm16(&operands!("ax", "[0]"));
+        cpu.mov_reg16_rm16(&operands!("ax", "[0]")).unwrap();
         assert_eq!(cpu.registers.get_ax(), 0);
 
-        cpu.mov_reg16_rm16(&operands!("ax", "bx"));
+        cpu.mov_reg16_rm16(&operands!("ax", "bx")).unwrap();
         assert_eq!(cpu.registers.get_ax(), 2);
     }
 
@@ -1011,10 +6823,10 @@ mod tests {
         cpu.registers.set_eax(1);
         cpu.registers.set_ebx(2);
 
-        cpu.mov_reg32_rm32(&operands!("eax", "[0]"));
+        cpu.mov_reg32_rm32(&operands!("eax", "[0]")).unwrap();
         assert_eq!(cpu.registers.get_eax(), 0);
 
-        cpu.mov_reg32_rm32(&operands!("eax", "ebx"));
+        cpu.mov_reg32_rm32(&operands!("eax", "ebx")).unwrap();
         assert_eq!(cpu.registers.get_eax(), 2);
     }
 
@@ -1083,12 +6895,12 @@ mod tests {
         let mut cpu = Cpu::default();
         cpu.registers.esp = 128;
 
-        cpu.memory.write16(130, u16::MAX).unwrap();
-        assert_eq!(cpu.pop16(), u16::MAX);
+        cpu.memory.write16(128, u16::MAX).unwrap();
+        assert_eq!(cpu.pop16().unwrap(), u16::MAX);
         assert_eq!(cpu.registers.esp, 130);
 
-        cpu.memory.write32(134, u32::MAX).unwrap();
-        assert_eq!(cpu.pop32(), u32::MAX);
+        cpu.memory.write32(130, u32::MAX).unwrap();
+        assert_eq!(cpu.pop32().unwrap(), u32::MAX);
         assert_eq!(cpu.registers.esp, 134);
     }
 
@@ -1097,12 +6909,183 @@ mod tests {
         let mut cpu = Cpu::default();
         cpu.registers.esp = 128;
 
-        cpu.push16(u16::MAX);
+        cpu.push16(u16::MAX).unwrap();
         assert_eq!(cpu.registers.esp, 126);
         assert_eq!(cpu.memory.read16(126).unwrap(), u16::MAX);
 
-        cpu.push32(u32::MAX);
+        cpu.push32(u32::MAX).unwrap();
         assert_eq!(cpu.registers.esp, 122);
         assert_eq!(cpu.memory.read32(122).unwrap(), u32::MAX);
     }
+
+    #[test]
+    fn xadd() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(0x12345678);
+        cpu.registers.set_ebx(0x100);
+        cpu.registers.set_ecx(0x11111111);
+        cpu.memory.write32(0x100, 0xffffffff).unwrap();
+
+        cpu.xadd_rm8_reg8(&operands!("byte [ebx]", "cl")).unwrap();
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 0xffffff10);
+        assert_eq!(cpu.registers.get_ecx(), 0x111111ff);
+        assert_eflags!(cpu, CF = true, ZF = false, SF = false, OF = false);
+
+        cpu.xadd_rm16_reg16(&operands!("ax", "cx")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0x12346877);
+        assert_eq!(cpu.registers.get_ecx(), 0x11115678);
+        assert_eflags!(cpu, CF = false, ZF = false, SF = false);
+
+        cpu.xadd_rm32_reg32(&operands!("[ebx]", "eax")).unwrap();
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 0x12346787);
+        assert_eq!(cpu.registers.get_eax(), 0xffffff10);
+        assert_eflags!(cpu, CF = true, ZF = false, SF = false);
+
+        // When both operands are the same register, it ends up holding the sum.
+        cpu.registers.set_edx(0x40000000);
+        cpu.xadd_rm32_reg32(&operands!("edx", "edx")).unwrap();
+        assert_eq!(cpu.registers.get_edx(), 0x80000000);
+        assert_eflags!(cpu, CF = false, SF = true, OF = true);
+    }
+
+    #[test]
+    fn xor() {
+        let mut cpu = Cpu::default();
+        cpu.registers.eflags.set_overflow_flag(true);
+        cpu.registers.eflags.set_carry_flag(true);
+        assert_eq!(cpu.xor(0b1100_u8, 0b1010_u8), 0b0110_u8);
+        assert_eflags!(
+            cpu,
+            OF = false,
+            CF = false,
+            SF = false,
+            ZF = false,
+            PF = true
+        );
+
+        assert_eq!(cpu.xor(0x1234_u16, 0x1234_u16), 0);
+        assert_eflags!(cpu, SF = false, ZF = true, PF = true);
+
+        assert_eq!(cpu.xor(0x7fffffff_u32, 0xffffffff_u32), 0x80000000);
+        assert_eflags!(cpu, SF = true, ZF = false, PF = true);
+
+        assert_eq!(cpu.xor(0x01_u8, 0x00_u8), 0x01);
+        assert_eflags!(cpu, PF = false);
+    }
+
+    #[test]
+    fn rm_imm() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ebx(0x100);
+
+        cpu.add_rm32_imm32(&operands!("dword [ebx]", "4")).unwrap();
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 4);
+        cpu.add_rm16_imm16(&operands!("word [ebx]", "0xfffe"))
+            .unwrap();
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 2);
+        assert_eflags!(cpu, CF = true, ZF = false);
+
+        // The imm8 forms of the 16 and 32-bit instructions are sign-extended.
+        cpu.registers.set_eax(0);
+        cpu.sub_rm32_imm8(&operands!("eax", "byte 2")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xfffffffe);
+        assert_eflags!(cpu, CF = true, SF = true);
+        cpu.add_rm32_imm8(&operands!("eax", "byte 0xff")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xfffffffd);
+        cpu.and_rm16_imm8(&operands!("ax", "byte 0x80")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xffffff80);
+        cpu.or_rm16_imm8(&operands!("ax", "byte 0x7f")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xffffffff);
+        cpu.xor_rm32_imm8(&operands!("eax", "byte 0x0f")).unwrap();
+        assert_eq!(cpu.registers.get_eax(), 0xfffffff0);
+        assert_eflags!(cpu, CF = false, OF = false);
+
+        cpu.registers.set_ecx(0x11);
+        cpu.registers.eflags.set_carry_flag(false);
+        cpu.adc_rm8_imm8(&operands!("cl", "0x0f")).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0x20);
+        cpu.sbb_rm16_imm16(&operands!("cx", "0x11")).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0x0f);
+        cpu.sub_rm8_imm8(&operands!("cl", "0x0f")).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eflags!(cpu, ZF = true);
+        cpu.xor_rm8_imm8(&operands!("cl", "0xff")).unwrap();
+        cpu.or_rm32_imm32(&operands!("ecx", "0x12340000")).unwrap();
+        cpu.and_rm32_imm32(&operands!("ecx", "0xffff00f0")).unwrap();
+        assert_eq!(cpu.registers.get_ecx(), 0x123400f0);
+    }
+
+    #[test]
+    fn cmp() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(5);
+        cpu.cmp_rm32_imm32(&operands!("eax", "5")).unwrap();
+        assert_eflags!(cpu, ZF = true, CF = false, SF = false);
+        assert_eq!(cpu.registers.get_eax(), 5);
+
+        cpu.cmp_rm8_imm8(&operands!("al", "6")).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = true, SF = true);
+
+        // Compared against -1, which is greater than 5 when treated as an unsigned value.
+        cpu.cmp_rm16_imm8(&operands!("ax", "byte 0xff")).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = true, OF = false, SF = false);
+
+        cpu.memory.write16(0x10, 0x8000).unwrap();
+        cpu.cmp_rm16_imm16(&operands!("word [0x10]", "1")).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = false, OF = true, SF = false);
+        assert_eq!(cpu.memory.read16(0x10).unwrap(), 0x8000);
+
+        cpu.cmp_rm32_imm8(&operands!("eax", "byte 4")).unwrap();
+        assert_eflags!(cpu, ZF = false, CF = false, SF = false);
+    }
+
+    #[test]
+    fn fetch() {
+        let mut cpu = Cpu::default();
+        // The first 4 MiB are identity mapped by the page table at 0x2000, except for the page at
+        // 0x5000, which is not present.
+        cpu.memory.write32(0x1000, 0x2000 | 0b111).unwrap();
+        for page in 0..1024 {
+            if page != 5 {
+                cpu.memory
+                    .write32(0x2000 + page * 4, page << 12 | 0b111)
+                    .unwrap();
+            }
+        }
+        cpu.registers.control_registers.set_cr3(0x1000);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.control_registers.set_paging(true);
+
+        // add eax, 1, which ends at the end of its page, so the page after it is not fetched from.
+        cpu.memory.load(0x4ffb, &[0x05, 1, 0, 0, 0]).unwrap();
+        cpu.registers.set_eip(0x4ffb);
+        let (instruction, bytes) = cpu.fetch().unwrap();
+        assert_eq!(instruction.mnemonic, "ADD");
+        assert_eq!(bytes, [0x05, 1, 0, 0, 0]);
+        cpu.step();
+        assert_eq!(cpu.registers.get_eax(), 1);
+        assert_eq!(cpu.registers.get_eip(), 0x5000);
+
+        // The same instruction, but continuing into the page which is not present.
+        cpu.registers.set_eip(0x4ffe);
+        cpu.memory.load(0x4ffe, &[0x05, 1]).unwrap();
+        assert!(cpu.peek_instruction().is_none());
+        assert_eq!(cpu.fault.get(), None);
+        cpu.step();
+        assert_eq!(cpu.unreported_exception, Some(CpuException::PageFault));
+        assert_eq!(cpu.registers.control_registers.get_cr2(), 0x5000);
+        assert_eq!(cpu.registers.get_eax(), 1);
+
+        // An instruction which runs past the limit of the code segment raises a #GP exception,
+        // whereas bytes which are not an instruction raise a #UD exception.
+        let mut cpu = Cpu::default();
+        cpu.enter_real_mode();
+        cpu.memory.load(0xfffe, &[0x05, 1, 0, 0, 0]).unwrap();
+        cpu.registers.set_eip(0xfffe);
+        assert!(cpu.fetch().is_err());
+        assert_eq!(cpu.fault.take(), Some(CpuException::GeneralProtection));
+        cpu.memory.load(0xfffe, &[0x0f, 0x04]).unwrap();
+        assert!(cpu.fetch().is_err());
+        assert_eq!(cpu.fault.take(), Some(CpuException::InvalidOpcode));
+    }
 }
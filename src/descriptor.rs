@@ -0,0 +1,347 @@
+/// Intel manual section 3.4.2 "Segment Selectors".
+/// A 16-bit segment selector, which identifies a descriptor by its index within either the GDT or
+/// the LDT, along with the privilege level of the request (the RPL).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selector(pub u16);
+
+impl Selector {
+    /// The index of the descriptor within its table.
+    pub fn index(&self) -> u16 {
+        self.0 >> 3
+    }
+
+    /// Whether the selector refers to the LDT (the TI flag is set), rather than the GDT.
+    pub fn local(&self) -> bool {
+        self.0 & 0b100 != 0
+    }
+
+    /// The requested privilege level.
+    pub fn rpl(&self) -> u8 {
+        (self.0 & 0b11) as u8
+    }
+
+    /// Whether this is the null selector, i.e. the first entry of the GDT, which may be loaded into
+    /// the data segment registers, but faults when it is used to access memory.
+    pub fn is_null(&self) -> bool {
+        self.index() == 0 && !self.local()
+    }
+
+    /// The offset of the selector's descriptor within its table.
+    pub fn offset(&self) -> u32 {
+        self.index() as u32 * DESCRIPTOR_SIZE
+    }
+}
+
+/// The size in bytes of a segment descriptor.
+pub const DESCRIPTOR_SIZE: u32 = 8;
+
+/// Intel manual section 3.4.5.1 "Code- and Data-Segment Descriptor Types", and section 3.5 "SYSTEM
+/// DESCRIPTOR TYPES". What a descriptor describes, as given by its S flag and type field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DescriptorKind {
+    Data {
+        writable: bool,
+        expand_down: bool,
+    },
+    Code {
+        readable: bool,
+        conforming: bool,
+    },
+    /// A system descriptor (e.g. an LDT, TSS or gate), with its 4-bit type.
+    System(u8),
+}
+
+/// The access rights of a segment, which are cached in the hidden part of a segment register when
+/// it is loaded from a descriptor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccessRights {
+    pub kind: DescriptorKind,
+    /// The descriptor privilege level.
+    pub dpl: u8,
+}
+
+/// The type field of an LDT system descriptor.
+pub const LDT_TYPE: u8 = 0x2;
+
+/// Intel manual section 7.2.2 "TSS Descriptor" and section 7.2.5 "Task-Gate Descriptor".
+/// The type fields of the descriptor of a 32-bit TSS, which is busy while its task is running or
+/// is nested in the running task, and of a task gate. A task gate holds the selector of a TSS
+/// descriptor where the low WORD of a segment's base would be.
+pub const AVAILABLE_TSS_TYPE: u8 = 0x9;
+pub const BUSY_TSS_TYPE: u8 = 0xb;
+pub const TASK_GATE_TYPE: u8 = 0x5;
+
+/// Intel manual section 6.11 "IDT DESCRIPTORS".
+/// The type fields of the 16-bit and 32-bit interrupt and trap gates. They differ only in that an
+/// interrupt gate clears IF, and the size of the gate determines that of the values which are
+/// pushed onto the stack.
+pub const INTERRUPT_GATE_16_TYPE: u8 = 0x6;
+pub const TRAP_GATE_16_TYPE: u8 = 0x7;
+pub const INTERRUPT_GATE_TYPE: u8 = 0xe;
+pub const TRAP_GATE_TYPE: u8 = 0xf;
+
+/// The bit of a raw TSS descriptor which is set while the TSS is busy.
+pub const BUSY_FLAG: u64 = 1 << 41;
+
+/// Intel manual section 3.4.5 "Segment Descriptors".
+/// A segment descriptor, as read from the GDT or LDT. The limit is in bytes, i.e. it has already
+/// been scaled when the granularity flag is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentDescriptor {
+    pub base: u32,
+    pub limit: u32,
+    pub rights: AccessRights,
+    pub present: bool,
+    /// The D/B flag, which selects 32-bit rather than 16-bit operands and addresses for a code
+    /// segment, and ESP rather than SP for a stack segment.
+    pub big: bool,
+}
+
+impl From<u64> for SegmentDescriptor {
+    fn from(raw: u64) -> Self {
+        let base = ((raw >> 16) & 0xff_ffff) as u32 | (((raw >> 56) as u32) << 24);
+        let limit = (raw & 0xffff) as u32 | ((raw >> 32) as u32 & 0xf_0000);
+        let granular = raw & (1 << 55) != 0;
+        let access = (raw >> 40) as u8;
+        let kind = match (access & 0x10 != 0, access & 0x08 != 0) {
+            (false, _) => DescriptorKind::System(access & 0xf),
+            (true, false) => DescriptorKind::Data {
+                writable: access & 0x02 != 0,
+                expand_down: access & 0x04 != 0,
+            },
+            (true, true) => DescriptorKind::Code {
+                readable: access & 0x02 != 0,
+                conforming: access & 0x04 != 0,
+            },
+        };
+        Self {
+            base,
+            limit: if granular { limit << 12 | 0xfff } else { limit },
+            rights: AccessRights {
+                kind,
+                dpl: (access >> 5) & 0b11,
+            },
+            present: access & 0x80 != 0,
+            big: raw & (1 << 54) != 0,
+        }
+    }
+}
+
+/// Intel manual section 6.11 "IDT DESCRIPTORS".
+/// A gate, as read from the IDT. An interrupt or trap gate holds the far pointer to the handler,
+/// and a task gate holds the selector of a TSS descriptor (its offset is unused).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GateDescriptor {
+    pub selector: u16,
+    pub offset: u32,
+    pub rights: AccessRights,
+    pub present: bool,
+}
+
+impl From<u64> for GateDescriptor {
+    fn from(raw: u64) -> Self {
+        let SegmentDescriptor {
+            rights, present, ..
+        } = SegmentDescriptor::from(raw);
+        Self {
+            selector: (raw >> 16) as u16,
+            offset: (raw & 0xffff) as u32 | ((raw >> 48) as u32) << 16,
+            rights,
+            present,
+        }
+    }
+}
+
+impl From<GateDescriptor> for u64 {
+    fn from(gate: GateDescriptor) -> Self {
+        let kind = match gate.rights.kind {
+            DescriptorKind::System(kind) => kind,
+            _ => panic!("a gate must be a system descriptor"),
+        };
+        let access =
+            (gate.present as u64) << 7 | (gate.rights.dpl as u64 & 0b11) << 5 | kind as u64;
+        (gate.offset & 0xffff) as u64
+            | (gate.selector as u64) << 16
+            | access << 40
+            | ((gate.offset >> 16) as u64) << 48
+    }
+}
+
+/// The size in bytes of a 32-bit TSS, so a TSS descriptor must have a limit of at least 0x67.
+pub const TASK_STATE_SIZE: usize = 104;
+
+/// Intel manual section 7.2.1 "Task-State Segment (TSS)".
+/// The state of a task which is saved to its 32-bit TSS when the processor switches away from it,
+/// and restored when it switches back, along with the stacks which are used when the task's
+/// privilege level is raised.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskState {
+    /// The selector of the TSS of the task which called (or was interrupted by) this one.
+    pub link: u16,
+    /// SS and ESP for CPL 0, 1, and 2, in that order.
+    pub stacks: [(u16, u32); 3],
+    pub cr3: u32,
+    pub eip: u32,
+    pub eflags: u32,
+    /// EAX, ECX, EDX, EBX, ESP, EBP, ESI, and EDI, in that order.
+    pub general_purpose: [u32; 8],
+    /// The selectors of ES, CS, SS, DS, FS, and GS, in that order (see `SegmentRegister::index`).
+    pub segments: [u16; 6],
+    /// The selector of the task's LDT.
+    pub ldt: u16,
+    /// The T flag, which raises a debug exception when the task is switched to.
+    pub trap: bool,
+    /// The offset of the I/O permission bit map from the base of the TSS.
+    pub io_map_base: u16,
+}
+
+impl From<&[u8; TASK_STATE_SIZE]> for TaskState {
+    fn from(bytes: &[u8; TASK_STATE_SIZE]) -> Self {
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let dword =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        Self {
+            link: word(0),
+            stacks: [0, 1, 2].map(|i| (word(8 + i * 8), dword(4 + i * 8))),
+            cr3: dword(28),
+            eip: dword(32),
+            eflags: dword(36),
+            general_purpose: [0, 1, 2, 3, 4, 5, 6, 7].map(|i| dword(40 + i * 4)),
+            segments: [0, 1, 2, 3, 4, 5].map(|i| word(72 + i * 4)),
+            ldt: word(96),
+            trap: bytes[100] & 1 != 0,
+            io_map_base: word(102),
+        }
+    }
+}
+
+impl TaskState {
+    /// The TSS in memory, with any reserved bits clear.
+    pub fn to_bytes(&self) -> [u8; TASK_STATE_SIZE] {
+        let mut bytes = [0; TASK_STATE_SIZE];
+        let mut put = |offset: usize, value: &[u8]| {
+            bytes[offset..offset + value.len()].copy_from_slice(value)
+        };
+        put(0, &self.link.to_le_bytes());
+        for (i, (ss, esp)) in self.stacks.iter().enumerate() {
+            put(4 + i * 8, &esp.to_le_bytes());
+            put(8 + i * 8, &ss.to_le_bytes());
+        }
+        put(28, &self.cr3.to_le_bytes());
+        put(32, &self.eip.to_le_bytes());
+        put(36, &self.eflags.to_le_bytes());
+        for (i, value) in self.general_purpose.iter().enumerate() {
+            put(40 + i * 4, &value.to_le_bytes());
+        }
+        for (i, selector) in self.segments.iter().enumerate() {
+            put(72 + i * 4, &selector.to_le_bytes());
+        }
+        put(96, &self.ldt.to_le_bytes());
+        put(100, &[self.trap as u8]);
+        put(102, &self.io_map_base.to_le_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selector() {
+        let selector = Selector(0x2b);
+        assert_eq!(selector.index(), 5);
+        assert!(!selector.local());
+        assert_eq!(selector.rpl(), 3);
+        assert_eq!(selector.offset(), 40);
+        assert!(Selector(0x3).is_null());
+        assert!(!Selector(0x4).is_null());
+    }
+
+    #[test]
+    fn segment_descriptor_from_u64() {
+        // A flat, ring 0 code segment, as found in most GDTs.
+        assert_eq!(
+            SegmentDescriptor::from(0x00cf_9a00_0000_ffff),
+            SegmentDescriptor {
+                base: 0,
+                limit: u32::MAX,
+                rights: AccessRights {
+                    kind: DescriptorKind::Code {
+                        readable: true,
+                        conforming: false,
+                    },
+                    dpl: 0,
+                },
+                present: true,
+                big: true,
+            }
+        );
+        // A byte-granular, 16-bit ring 3 data segment, based at 0x12345678.
+        assert_eq!(
+            SegmentDescriptor::from(0x1200_f234_5678_0fff),
+            SegmentDescriptor {
+                base: 0x1234_5678,
+                limit: 0xfff,
+                rights: AccessRights {
+                    kind: DescriptorKind::Data {
+                        writable: true,
+                        expand_down: false,
+                    },
+                    dpl: 3,
+                },
+                present: true,
+                big: false,
+            }
+        );
+        assert_eq!(
+            SegmentDescriptor::from(0x0000_0200_0000_0000).rights.kind,
+            DescriptorKind::System(LDT_TYPE)
+        );
+    }
+
+    #[test]
+    fn gate_descriptor() {
+        // A 32-bit ring 3 interrupt gate to 0x0008:0x12345678.
+        let gate = GateDescriptor {
+            selector: 0x8,
+            offset: 0x1234_5678,
+            rights: AccessRights {
+                kind: DescriptorKind::System(INTERRUPT_GATE_TYPE),
+                dpl: 3,
+            },
+            present: true,
+        };
+        assert_eq!(u64::from(gate), 0x1234_ee00_0008_5678);
+        assert_eq!(GateDescriptor::from(0x1234_ee00_0008_5678), gate);
+        assert_eq!(
+            GateDescriptor::from(0x0000_0500_0028_0000).rights.kind,
+            DescriptorKind::System(TASK_GATE_TYPE)
+        );
+        assert!(!GateDescriptor::from(0x0000_0f00_0008_0000).present);
+    }
+
+    #[test]
+    fn task_state() {
+        let state = TaskState {
+            link: 0x28,
+            stacks: [(0x10, 0x9000), (0, 0), (0, 0)],
+            cr3: 0x0010_0000,
+            eip: 0x1234,
+            eflags: 0x202,
+            general_purpose: [1, 2, 3, 4, 0x8000, 6, 7, 8],
+            segments: [0x23, 0x1b, 0x23, 0x23, 0x23, 0x23],
+            ldt: 0,
+            trap: true,
+            io_map_base: TASK_STATE_SIZE as u16,
+        };
+        let bytes = state.to_bytes();
+        assert_eq!(&bytes[4..10], &[0x00, 0x90, 0, 0, 0x10, 0]);
+        assert_eq!(&bytes[56..60], &[0x00, 0x80, 0, 0]);
+        assert_eq!(&bytes[76..78], &[0x1b, 0]);
+        assert_eq!(bytes[100], 1);
+        assert_eq!(TaskState::from(&bytes), state);
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{stdin, Read},
+    rc::Rc,
+    sync::mpsc::Receiver,
+};
+
+use super::{pic::IrqLine, spawn_reader, PortDevice};
+
+/// The port through which scancodes (and the responses to commands) are read, and through which
+/// commands are sent to the keyboard itself.
+pub const DATA_PORT: u16 = 0x60;
+/// The port through which the controller's status is read, and commands are sent to it.
+pub const STATUS_PORT: u16 = 0x64;
+/// The IRQ which the controller raises when a byte is ready to be read.
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// The bits of the status register.
+const OUTPUT_BUFFER_FULL: u8 = 1 << 0;
+const SYSTEM_FLAG: u8 = 1 << 2;
+/// Whether the last write was to the command port (rather than the data port).
+const COMMAND: u8 = 1 << 3;
+const KEYBOARD_UNLOCKED: u8 = 1 << 4;
+
+/// The bits of the controller's command byte.
+const INTERRUPT_ENABLE: u8 = 1 << 0;
+const KEYBOARD_DISABLED: u8 = 1 << 4;
+/// The command byte as the BIOS leaves it: interrupts and translation to scancode set 1 enabled.
+const DEFAULT_COMMAND_BYTE: u8 = 0b0100_0101;
+
+/// The responses of the keyboard to the commands sent to it.
+const ACKNOWLEDGE: u8 = 0xfa;
+const SELF_TEST_PASSED: u8 = 0xaa;
+const KEYBOARD_ID: [u8; 2] = [0xab, 0x83];
+
+/// The scancode (in set 1) of the left shift key, and the bit which turns a key's make code into
+/// its break code.
+const LEFT_SHIFT: u8 = 0x2a;
+const BREAK: u8 = 0x80;
+
+/// The keys which are typed for ASCII characters: the character without shift, the character with
+/// shift, and the key's make code in scancode set 1.
+#[rustfmt::skip]
+const KEYS: [(u8, u8, u8); 52] = [
+    (0x1b, 0x1b, 0x01), (b'1', b'!', 0x02), (b'2', b'@', 0x03), (b'3', b'#', 0x04),
+    (b'4', b'$', 0x05), (b'5', b'%', 0x06), (b'6', b'^', 0x07), (b'7', b'&', 0x08),
+    (b'8', b'*', 0x09), (b'9', b'(', 0x0a), (b'0', b')', 0x0b), (b'-', b'_', 0x0c),
+    (b'=', b'+', 0x0d), (0x08, 0x08, 0x0e), (b'\t', b'\t', 0x0f), (b'q', b'Q', 0x10),
+    (b'w', b'W', 0x11), (b'e', b'E', 0x12), (b'r', b'R', 0x13), (b't', b'T', 0x14),
+    (b'y', b'Y', 0x15), (b'u', b'U', 0x16), (b'i', b'I', 0x17), (b'o', b'O', 0x18),
+    (b'p', b'P', 0x19), (b'[', b'{', 0x1a), (b']', b'}', 0x1b), (b'\n', b'\n', 0x1c),
+    (b'a', b'A', 0x1e), (b's', b'S', 0x1f), (b'd', b'D', 0x20), (b'f', b'F', 0x21),
+    (b'g', b'G', 0x22), (b'h', b'H', 0x23), (b'j', b'J', 0x24), (b'k', b'K', 0x25),
+    (b'l', b'L', 0x26), (b';', b':', 0x27), (b'\'', b'"', 0x28), (b'`', b'~', 0x29),
+    (b'\\', b'|', 0x2b), (b'z', b'Z', 0x2c), (b'x', b'X', 0x2d), (b'c', b'C', 0x2e),
+    (b'v', b'V', 0x2f), (b'b', b'B', 0x30), (b'n', b'N', 0x31), (b'm', b'M', 0x32),
+    (b',', b'<', 0x33), (b'.', b'>', 0x34), (b'/', b'?', 0x35), (b' ', b' ', 0x39),
+];
+
+/// The scancodes (in set 1) of pressing and releasing the key(s) which type an ASCII character, or
+/// `None` if no key types it. A carriage return is typed with the enter key, like a line feed.
+pub fn scancodes(character: u8) -> Option<Vec<u8>> {
+    let character = if character == b'\r' { b'\n' } else { character };
+    let (unshifted, _, code) = KEYS
+        .iter()
+        .find(|(unshifted, shifted, _)| *unshifted == character || *shifted == character)?;
+    Some(if *unshifted == character {
+        vec![*code, code | BREAK]
+    } else {
+        vec![LEFT_SHIFT, *code, code | BREAK, LEFT_SHIFT | BREAK]
+    })
+}
+
+/// A command which is waiting for the byte written to the data port after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PendingCommand {
+    /// The controller's 0x60 command, which writes the command byte.
+    WriteCommandByte,
+    /// The controller's 0xd1 command, which writes the output port (whose A20 gate is ignored).
+    WriteOutputPort,
+    /// The keyboard's 0xed command, which sets the LEDs.
+    SetLeds,
+}
+
+#[derive(Debug)]
+struct Controller {
+    /// The byte which can be read from the data port, if there is one.
+    output_buffer: Option<u8>,
+    /// The byte which was last read from the data port, which is read again while the output
+    /// buffer is empty.
+    last_output: u8,
+    /// The responses to commands, which are read before any scancodes.
+    responses: VecDeque<u8>,
+    /// The scancodes of keys which have been pressed and released, but not yet read.
+    scancodes: VecDeque<u8>,
+    input: Option<Receiver<u8>>,
+    command_byte: u8,
+    status: u8,
+    pending_command: Option<PendingCommand>,
+    /// Whether the keyboard sends scancodes (which is disabled with its 0xf5 command).
+    scanning: bool,
+    irq: Option<IrqLine>,
+}
+
+impl Controller {
+    /// Moves the next byte into the output buffer once it is empty, raising the IRQ (if enabled).
+    fn update(&mut self) {
+        if let Some(input) = &self.input {
+            for character in input.try_iter() {
+                self.scancodes
+                    .extend(scancodes(character).into_iter().flatten());
+            }
+        }
+
+        if self.output_buffer.is_none() {
+            let keyboard_enabled = self.scanning && self.command_byte & KEYBOARD_DISABLED == 0;
+            self.output_buffer = match self.responses.pop_front() {
+                Some(response) => Some(response),
+                None if keyboard_enabled => self.scancodes.pop_front(),
+                None => None,
+            };
+        }
+
+        if let Some(irq) = &self.irq {
+            irq.set_level(
+                self.output_buffer.is_some() && self.command_byte & INTERRUPT_ENABLE != 0,
+            );
+        }
+    }
+
+    /// Reads the output buffer. The IRQ is lowered, such that it is raised again (and so requests
+    /// another interrupt) for the next byte.
+    fn read_data(&mut self) -> u8 {
+        if let Some(value) = self.output_buffer.take() {
+            self.last_output = value;
+        }
+        if let Some(irq) = &self.irq {
+            irq.lower();
+        }
+        self.update();
+        self.last_output
+    }
+
+    fn read_status(&self) -> u8 {
+        let full = if self.output_buffer.is_some() {
+            OUTPUT_BUFFER_FULL
+        } else {
+            0
+        };
+        self.status | full
+    }
+
+    fn write_data(&mut self, value: u8) {
+        self.status &= !COMMAND;
+        match self.pending_command.take() {
+            Some(PendingCommand::WriteCommandByte) => self.command_byte = value,
+            Some(PendingCommand::WriteOutputPort) => (),
+            Some(PendingCommand::SetLeds) => self.responses.push_back(ACKNOWLEDGE),
+            None => self.keyboard_command(value),
+        }
+        self.update();
+    }
+
+    fn keyboard_command(&mut self, command: u8) {
+        self.responses.push_back(ACKNOWLEDGE);
+        match command {
+            0xed => self.pending_command = Some(PendingCommand::SetLeds),
+            0xf2 => self.responses.extend(KEYBOARD_ID),
+            0xf4 => self.scanning = true,
+            0xf5 => self.scanning = false,
+            0xff => {
+                self.scancodes.clear();
+                self.scanning = true;
+                self.responses.push_back(SELF_TEST_PASSED);
+            }
+            // The other commands (e.g. setting the typematic rate) are acknowledged and ignored.
+            _ => (),
+        }
+    }
+
+    fn write_command(&mut self, command: u8) {
+        self.status |= COMMAND;
+        match command {
+            0x20 => self.responses.push_back(self.command_byte),
+            0x60 => self.pending_command = Some(PendingCommand::WriteCommandByte),
+            // The controller's self-test, which passes.
+            0xaa => self.responses.push_back(0x55),
+            // The keyboard interface test, which passes.
+            0xab => self.responses.push_back(0x00),
+            0xad => self.command_byte |= KEYBOARD_DISABLED,
+            0xae => self.command_byte &= !KEYBOARD_DISABLED,
+            0xd1 => self.pending_command = Some(PendingCommand::WriteOutputPort),
+            // The other commands (e.g. those of the auxiliary device) are ignored.
+            _ => (),
+        }
+        self.update();
+    }
+}
+
+/// A PS/2 keyboard and its (8042) controller, which are accessed through [`DATA_PORT`] and
+/// [`STATUS_PORT`]. The keyboard sends scancodes in set 1, as the controller translates them by
+/// default.
+///
+/// This is a handle, so it can be cloned to keep access to the keyboard once its ports have been
+/// attached (see [`Keyboard::port`]), e.g. to inject scancodes while the guest runs.
+#[derive(Clone, Debug)]
+pub struct Keyboard(Rc<RefCell<Controller>>);
+
+impl Default for Keyboard {
+    /// A keyboard with no input other than the scancodes which are injected into it.
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new(Controller {
+            output_buffer: None,
+            last_output: 0,
+            responses: VecDeque::new(),
+            scancodes: VecDeque::new(),
+            input: None,
+            command_byte: DEFAULT_COMMAND_BYTE,
+            status: SYSTEM_FLAG | KEYBOARD_UNLOCKED,
+            pending_command: None,
+            scanning: true,
+            irq: None,
+        })))
+    }
+}
+
+impl Keyboard {
+    /// A keyboard which types the ASCII characters read from `input` (see [`scancodes`]). The
+    /// characters which no key types are ignored.
+    pub fn from_reader(input: impl Read + Send + 'static) -> Self {
+        let keyboard = Self::default();
+        keyboard.0.borrow_mut().input = Some(spawn_reader(input));
+        keyboard
+    }
+
+    /// A keyboard which types the characters read from the host's standard input.
+    pub fn from_stdin() -> Self {
+        Self::from_reader(stdin())
+    }
+
+    /// A device which responds to [`DATA_PORT`] and [`STATUS_PORT`], and which must be attached to
+    /// both (each on its own, as the ports in between belong to other devices).
+    pub fn port(&self) -> KeyboardPort {
+        KeyboardPort(self.clone())
+    }
+
+    /// Connects the controller's interrupt output to an IRQ line (normally [`KEYBOARD_IRQ`]).
+    pub fn connect_irq(&self, line: IrqLine) {
+        self.0.borrow_mut().irq = Some(line);
+    }
+
+    /// Queues scancodes to be sent by the keyboard, as if keys had been pressed and released.
+    pub fn inject_scancodes(&self, scancodes: &[u8]) {
+        let mut controller = self.0.borrow_mut();
+        controller.scancodes.extend(scancodes);
+        controller.update();
+    }
+
+    /// Queues the scancodes which type the ASCII characters of `text` (see [`scancodes`]). The
+    /// characters which no key types are ignored.
+    pub fn type_text(&self, text: &str) {
+        let codes: Vec<_> = text.bytes().filter_map(scancodes).flatten().collect();
+        self.inject_scancodes(&codes);
+    }
+}
+
+/// The ports of a [`Keyboard`].
+#[derive(Debug)]
+pub struct KeyboardPort(Keyboard);
+
+impl PortDevice for KeyboardPort {
+    fn read8(&mut self, port: u16) -> u8 {
+        let mut controller = self.0 .0.borrow_mut();
+        match port {
+            STATUS_PORT => controller.read_status(),
+            _ => controller.read_data(),
+        }
+    }
+
+    fn write8(&mut self, port: u16, value: u8) {
+        let mut controller = self.0 .0.borrow_mut();
+        match port {
+            STATUS_PORT => controller.write_command(value),
+            _ => controller.write_data(value),
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        self.0 .0.borrow_mut().update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::devices::pic::Pic;
+
+    /// Reads every byte which is ready to be read from the data port.
+    fn read_all(port: &mut KeyboardPort) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while port.read8(STATUS_PORT) & OUTPUT_BUFFER_FULL != 0 {
+            bytes.push(port.read8(DATA_PORT));
+        }
+        bytes
+    }
+
+    #[test]
+    fn scancodes_of_characters() {
+        assert_eq!(scancodes(b'a'), Some(vec![0x1e, 0x9e]));
+        assert_eq!(scancodes(b'A'), Some(vec![0x2a, 0x1e, 0x9e, 0xaa]));
+        assert_eq!(scancodes(b'\r'), scancodes(b'\n'));
+        assert_eq!(scancodes(b' '), Some(vec![0x39, 0xb9]));
+        assert_eq!(scancodes(0x80), None);
+    }
+
+    #[test]
+    fn injected_scancodes() {
+        let keyboard = Keyboard::default();
+        let mut port = keyboard.port();
+        assert_eq!(port.read8(STATUS_PORT) & OUTPUT_BUFFER_FULL, 0);
+
+        keyboard.type_text("Hi");
+        assert_eq!(read_all(&mut port), [0x2a, 0x23, 0xa3, 0xaa, 0x17, 0x97]);
+
+        // Scancodes are held back while the keyboard is disabled.
+        port.write8(STATUS_PORT, 0xad);
+        keyboard.inject_scancodes(&[0x01, 0x81]);
+        assert!(read_all(&mut port).is_empty());
+        port.write8(STATUS_PORT, 0xae);
+        assert_eq!(read_all(&mut port), [0x01, 0x81]);
+    }
+
+    #[test]
+    fn commands() {
+        let keyboard = Keyboard::default();
+        let mut port = keyboard.port();
+        port.write8(STATUS_PORT, 0xaa);
+        assert_eq!(port.read8(STATUS_PORT) & COMMAND, COMMAND);
+        assert_eq!(read_all(&mut port), [0x55]);
+
+        port.write8(STATUS_PORT, 0x20);
+        assert_eq!(read_all(&mut port), [DEFAULT_COMMAND_BYTE]);
+        port.write8(STATUS_PORT, 0x60);
+        port.write8(DATA_PORT, 0x44);
+        port.write8(STATUS_PORT, 0x20);
+        assert_eq!(read_all(&mut port), [0x44]);
+
+        // Responses are read before the scancodes which are waiting.
+        keyboard.type_text("a");
+        port.read8(DATA_PORT);
+        port.write8(DATA_PORT, 0xf2);
+        port.write8(DATA_PORT, 0xed);
+        port.write8(DATA_PORT, 0b111);
+        assert_eq!(
+            read_all(&mut port),
+            [0x9e, ACKNOWLEDGE, 0xab, 0x83, ACKNOWLEDGE, ACKNOWLEDGE]
+        );
+
+        port.write8(DATA_PORT, 0xff);
+        assert_eq!(read_all(&mut port), [ACKNOWLEDGE, SELF_TEST_PASSED]);
+    }
+
+    #[test]
+    fn irq() {
+        let pic = Pic::default();
+        let keyboard = Keyboard::default();
+        keyboard.connect_irq(pic.irq_line(KEYBOARD_IRQ));
+        let mut port = keyboard.port();
+
+        keyboard.inject_scancodes(&[0x1e, 0x9e]);
+        assert_eq!(pic.acknowledge(), Some(0x08 + KEYBOARD_IRQ));
+        assert_eq!(port.read8(DATA_PORT), 0x1e);
+        // Reading the data port lets the next scancode raise the IRQ again.
+        pic.ports().0.write8(0x20, 0x20);
+        assert_eq!(pic.acknowledge(), Some(0x08 + KEYBOARD_IRQ));
+        assert_eq!(port.read8(DATA_PORT), 0x9e);
+        assert_eq!(port.read8(DATA_PORT), 0x9e);
+        assert_eq!(port.read8(STATUS_PORT) & OUTPUT_BUFFER_FULL, 0);
+    }
+
+    #[test]
+    fn host_input() {
+        let keyboard = Keyboard::from_reader(&b"ok"[..]);
+        let mut port = keyboard.port();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut scancodes = Vec::new();
+        while scancodes.len() < 4 {
+            assert!(Instant::now() < deadline, "no input was received");
+            port.tick(0);
+            scancodes.extend(read_all(&mut port));
+            thread::yield_now();
+        }
+        assert_eq!(scancodes, [0x18, 0x98, 0x25, 0xa5]);
+    }
+}
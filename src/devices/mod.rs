@@ -0,0 +1,300 @@
+use alloc::{boxed::Box, format, vec::Vec};
+use core::{fmt::Debug, ops::RangeInclusive};
+#[cfg(feature = "std")]
+use std::{
+    io::{BufReader, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::error::Error;
+
+#[cfg(feature = "std")]
+pub mod keyboard;
+pub mod pic;
+#[cfg(feature = "std")]
+pub mod uart;
+
+/// The value read from a port which no device responds to. With nothing driving the data bus, it
+/// is pulled high.
+const UNMAPPED_PORT_VALUE: u8 = 0xff;
+
+/// A device which is accessed through the I/O address space (i.e. using the `IN` and `OUT` family
+/// of instructions), rather than being mapped into memory. Each port is 8 bits wide. By default,
+/// wider accesses are performed on consecutive ports, lowest port first, although a device whose
+/// registers are wider than a byte may service them itself.
+pub trait PortDevice: Debug {
+    /// Reads a byte from the given port.
+    fn read8(&mut self, port: u16) -> u8;
+
+    /// Writes a byte to the given port.
+    fn write8(&mut self, port: u16, value: u8);
+
+    /// Called between instructions, such that the device can act on events which do not coincide
+    /// with an access to its ports (e.g. by raising an IRQ when input arrives). `cycles` is the
+    /// number of cycles which the processor has executed since reset (see
+    /// [`Cpu::cycles`](crate::cpu::Cpu::cycles)), by which timers can be clocked.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Reads 2 bytes from the given port and the port after it, in little-endian format.
+    fn read16(&mut self, port: u16) -> u16 {
+        u16::from_le_bytes([self.read8(port), self.read8(port.wrapping_add(1))])
+    }
+
+    /// Reads 4 bytes from the given port and the 3 ports after it, in little-endian format.
+    fn read32(&mut self, port: u16) -> u32 {
+        u32::from_le_bytes([
+            self.read8(port),
+            self.read8(port.wrapping_add(1)),
+            self.read8(port.wrapping_add(2)),
+            self.read8(port.wrapping_add(3)),
+        ])
+    }
+
+    /// Writes 2 bytes to the given port and the port after it, in little-endian format.
+    fn write16(&mut self, port: u16, value: u16) {
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.write8(port.wrapping_add(i as u16), byte);
+        }
+    }
+
+    /// Writes 4 bytes to the given port and the 3 ports after it, in little-endian format.
+    fn write32(&mut self, port: u16, value: u32) {
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.write8(port.wrapping_add(i as u16), byte);
+        }
+    }
+}
+
+/// Reads the bytes of `input` on a thread of its own, such that a device can check for input
+/// without blocking while none is available. The bytes are sent as they are read, until the end of
+/// the input (or an error).
+#[cfg(feature = "std")]
+fn spawn_reader(input: impl Read + Send + 'static) -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in BufReader::new(input).bytes() {
+            let Ok(byte) = byte else { break };
+            if sender.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// The I/O port bus, which routes accesses to the device that has been attached to the port. A
+/// wide access which falls entirely within the ports of a single device is made to the device as
+/// a whole, whereas one which straddles devices (or unmapped ports) is split into bytes.
+#[derive(Debug, Default)]
+pub struct IoBus(Vec<(RangeInclusive<u16>, Box<dyn PortDevice>)>);
+
+impl IoBus {
+    /// Attaches a device which will respond to the given range of ports. If any of the ports are
+    /// already used by another device, then an `Err` is returned.
+    pub fn attach(
+        &mut self,
+        ports: RangeInclusive<u16>,
+        device: Box<dyn PortDevice>,
+    ) -> Result<(), Error> {
+        if let Some((existing, _)) = self.0.iter().find(|(existing, _)| {
+            existing.start() <= ports.end() && ports.start() <= existing.end()
+        }) {
+            return Err(Error::PortConflict(format!(
+                "ports {:#x}..={:#x} overlap with the already attached ports {:#x}..={:#x}",
+                ports.start(),
+                ports.end(),
+                existing.start(),
+                existing.end()
+            )));
+        }
+
+        self.0.push((ports, device));
+        Ok(())
+    }
+
+    fn device_mut(&mut self, port: u16) -> Option<&mut Box<dyn PortDevice>> {
+        self.0
+            .iter_mut()
+            .find(|(ports, _)| ports.contains(&port))
+            .map(|(_, device)| device)
+    }
+
+    /// The device which all of the `width` ports starting at `port` belong to, if there is one.
+    fn whole_device_mut(&mut self, port: u16, width: u16) -> Option<&mut Box<dyn PortDevice>> {
+        let last = port.checked_add(width - 1)?;
+        self.0
+            .iter_mut()
+            .find(|(ports, _)| ports.contains(&port) && ports.contains(&last))
+            .map(|(_, device)| device)
+    }
+
+    /// Ticks each device (see [`PortDevice::tick`]).
+    pub fn tick(&mut self, cycles: u64) {
+        for (_, device) in &mut self.0 {
+            device.tick(cycles);
+        }
+    }
+
+    /// Reads a byte from the given port. Ports without a device read as `0xff`.
+    pub fn read8(&mut self, port: u16) -> u8 {
+        match self.device_mut(port) {
+            Some(device) => device.read8(port),
+            None => UNMAPPED_PORT_VALUE,
+        }
+    }
+
+    /// Reads 2 bytes from the given port and the port after it, in little-endian format.
+    pub fn read16(&mut self, port: u16) -> u16 {
+        if let Some(device) = self.whole_device_mut(port, 2) {
+            return device.read16(port);
+        }
+        u16::from_le_bytes([self.read8(port), self.read8(port.wrapping_add(1))])
+    }
+
+    /// Reads 4 bytes from the given port and the 3 ports after it, in little-endian format.
+    pub fn read32(&mut self, port: u16) -> u32 {
+        if let Some(device) = self.whole_device_mut(port, 4) {
+            return device.read32(port);
+        }
+        let mut result = 0;
+        for i in 0..4 {
+            result |= (self.read8(port.wrapping_add(i)) as u32) << (8 * i);
+        }
+        result
+    }
+
+    /// Writes a byte to the given port. Writes to ports without a device are discarded.
+    pub fn write8(&mut self, port: u16, value: u8) {
+        if let Some(device) = self.device_mut(port) {
+            device.write8(port, value);
+        }
+    }
+
+    /// Writes 2 bytes to the given port and the port after it, in little-endian format.
+    pub fn write16(&mut self, port: u16, value: u16) {
+        if let Some(device) = self.whole_device_mut(port, 2) {
+            return device.write16(port, value);
+        }
+        for i in 0..2 {
+            self.write8(port.wrapping_add(i), (value >> (8 * i)) as u8);
+        }
+    }
+
+    /// Writes 4 bytes to the given port and the 3 ports after it, in little-endian format.
+    pub fn write32(&mut self, port: u16, value: u32) {
+        if let Some(device) = self.whole_device_mut(port, 4) {
+            return device.write32(port, value);
+        }
+        for i in 0..4 {
+            self.write8(port.wrapping_add(i), (value >> (8 * i)) as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A device with a byte of storage per port, starting at `base`.
+    #[derive(Debug)]
+    pub(crate) struct Latches {
+        pub(crate) base: u16,
+        pub(crate) values: Vec<u8>,
+    }
+
+    impl PortDevice for Latches {
+        fn read8(&mut self, port: u16) -> u8 {
+            self.values[(port - self.base) as usize]
+        }
+
+        fn write8(&mut self, port: u16, value: u8) {
+            self.values[(port - self.base) as usize] = value;
+        }
+    }
+
+    fn latches(base: u16, count: usize) -> Box<Latches> {
+        Box::new(Latches {
+            base,
+            values: vec![0; count],
+        })
+    }
+
+    #[test]
+    fn attach() {
+        let mut io = IoBus::default();
+        assert!(io.attach(0x10..=0x13, latches(0x10, 4)).is_ok());
+        assert!(io.attach(0x14..=0x17, latches(0x14, 4)).is_ok());
+        assert!(io.attach(0x0c..=0x10, latches(0x0c, 5)).is_err());
+        assert!(io.attach(0x13..=0x13, latches(0x13, 1)).is_err());
+        assert!(io.attach(0x00..=0xff, latches(0x00, 256)).is_err());
+    }
+
+    #[test]
+    fn read_and_write() {
+        let mut io = IoBus::default();
+        io.attach(0x10..=0x13, latches(0x10, 4)).unwrap();
+
+        io.write8(0x10, 0x01);
+        assert_eq!(io.read8(0x10), 0x01);
+
+        io.write16(0x11, 0x0302);
+        assert_eq!(io.read8(0x11), 0x02);
+        assert_eq!(io.read8(0x12), 0x03);
+        assert_eq!(io.read16(0x10), 0x0201);
+
+        io.write32(0x10, 0x0403_0201);
+        assert_eq!(io.read32(0x10), 0x0403_0201);
+
+        // Accesses partially outside of the device read the unmapped ports as 0xff.
+        assert_eq!(io.read16(0x13), 0xff04);
+        assert_eq!(io.read8(0x20), 0xff);
+        io.write8(0x20, 0);
+        assert_eq!(io.read8(0x20), 0xff);
+    }
+
+    /// A device with a single 16-bit register, which counts the accesses made to it as a whole.
+    #[derive(Debug, Default)]
+    struct Counter {
+        value: u16,
+        wide_accesses: usize,
+    }
+
+    impl PortDevice for Counter {
+        fn read8(&mut self, port: u16) -> u8 {
+            self.value.to_le_bytes()[port as usize & 1]
+        }
+
+        fn write8(&mut self, port: u16, value: u8) {
+            let mut bytes = self.value.to_le_bytes();
+            bytes[port as usize & 1] = value;
+            self.value = u16::from_le_bytes(bytes);
+        }
+
+        fn read16(&mut self, _: u16) -> u16 {
+            self.wide_accesses += 1;
+            self.value
+        }
+
+        fn write16(&mut self, _: u16, value: u16) {
+            self.wide_accesses += 1;
+            self.value = value;
+        }
+    }
+
+    #[test]
+    fn wide_accesses() {
+        let mut counter = Counter::default();
+        counter.write32(0, 0xdead_1234);
+        assert_eq!(counter.read32(0), 0xdead_dead);
+
+        let mut io = IoBus::default();
+        io.attach(0x20..=0x21, Box::<Counter>::default()).unwrap();
+        io.write16(0x20, 0xbeef);
+        assert_eq!(io.read16(0x20), 0xbeef);
+        // An access which only partly overlaps the device is split into bytes.
+        assert_eq!(io.read16(0x21), 0xffbe);
+        assert_eq!(io.read32(0x20), 0xffff_beef);
+        assert_eq!(io.read8(0x20), 0xef);
+    }
+}
@@ -0,0 +1,360 @@
+use alloc::rc::Rc;
+use core::{cell::RefCell, ops::RangeInclusive};
+
+use super::PortDevice;
+
+/// The ports of the master PIC, which services IRQs 0 to 7.
+pub const MASTER_PORTS: RangeInclusive<u16> = 0x20..=0x21;
+/// The ports of the slave PIC, which services IRQs 8 to 15.
+pub const SLAVE_PORTS: RangeInclusive<u16> = 0xa0..=0xa1;
+/// The IRQ of the master which the slave's output is connected to.
+const CASCADE_IRQ: u8 = 2;
+
+/// The bits of the first initialisation command word, which is written to the command port.
+const ICW1: u8 = 1 << 4;
+const ICW1_NEEDS_ICW4: u8 = 1 << 0;
+const ICW1_SINGLE: u8 = 1 << 1;
+/// The bit of the fourth initialisation command word which enables automatic end of interrupt.
+const ICW4_AUTO_EOI: u8 = 1 << 1;
+/// The bit of a command which marks it as OCW3, rather than OCW2.
+const OCW3: u8 = 1 << 3;
+/// The bits of OCW3 which select the register that the command port reads.
+const OCW3_READ_REGISTER: u8 = 1 << 1;
+const OCW3_READ_IN_SERVICE: u8 = 1 << 0;
+/// The OCW2 commands (in its top 3 bits) which end an interrupt.
+const NON_SPECIFIC_EOI: u8 = 0b001;
+const SPECIFIC_EOI: u8 = 0b011;
+
+/// How far through its initialisation sequence a chip is, i.e. which initialisation command word
+/// the next write to its data port is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Initialisation {
+    Icw2,
+    Icw3,
+    Icw4,
+    Done,
+}
+
+/// A single 8259 chip.
+#[derive(Debug)]
+struct Chip {
+    /// The vector of IRQ 0 of the chip, which the other IRQs follow on from.
+    vector_base: u8,
+    /// The interrupt request register, i.e. the IRQs which have been raised but not acknowledged.
+    requests: u8,
+    /// The in-service register, i.e. the IRQs which have been acknowledged but not ended.
+    in_service: u8,
+    /// The interrupt mask register, i.e. the IRQs which are ignored.
+    mask: u8,
+    /// The current level of each IRQ line. As the lines are edge-triggered, a request is only made
+    /// when a line goes from low to high.
+    levels: u8,
+    initialisation: Initialisation,
+    single: bool,
+    needs_icw4: bool,
+    auto_eoi: bool,
+    read_in_service: bool,
+}
+
+impl Chip {
+    fn new(vector_base: u8) -> Self {
+        Self {
+            vector_base,
+            requests: 0,
+            in_service: 0,
+            mask: 0,
+            levels: 0,
+            initialisation: Initialisation::Done,
+            single: false,
+            needs_icw4: false,
+            auto_eoi: false,
+            read_in_service: false,
+        }
+    }
+
+    /// The highest priority IRQ which is requested, unmasked, and not blocked by an IRQ of equal or
+    /// higher priority that is in service. IRQ 0 has the highest priority. `extra_requests` are
+    /// requests from other chips which are cascaded into this one.
+    fn pending(&self, extra_requests: u8) -> Option<u8> {
+        let requests = (self.requests | extra_requests) & !self.mask;
+        (0..8)
+            .take_while(|irq| self.in_service & (1 << irq) == 0)
+            .find(|irq| requests & (1 << irq) != 0)
+    }
+
+    /// Acknowledges the IRQ, moving it from being requested to being in service (unless automatic
+    /// end of interrupt is enabled).
+    fn acknowledge(&mut self, irq: u8) {
+        self.requests &= !(1 << irq);
+        if !self.auto_eoi {
+            self.in_service |= 1 << irq;
+        }
+    }
+
+    fn set_level(&mut self, irq: u8, high: bool) {
+        let bit = 1 << irq;
+        if high && self.levels & bit == 0 {
+            self.requests |= bit;
+        }
+        if high {
+            self.levels |= bit;
+        } else {
+            self.levels &= !bit;
+        }
+    }
+
+    fn read(&self, port: u16) -> u8 {
+        match port & 1 {
+            0 if self.read_in_service => self.in_service,
+            0 => self.requests,
+            _ => self.mask,
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u8) {
+        if port & 1 == 0 {
+            self.write_command(value);
+            return;
+        }
+
+        match self.initialisation {
+            Initialisation::Icw2 => {
+                self.vector_base = value & 0xf8;
+                self.initialisation = match (self.single, self.needs_icw4) {
+                    (false, _) => Initialisation::Icw3,
+                    (true, true) => Initialisation::Icw4,
+                    (true, false) => Initialisation::Done,
+                };
+            }
+            // The chips are always wired to each other as they are in the PC/AT, so how they are
+            // cascaded is ignored.
+            Initialisation::Icw3 if self.needs_icw4 => self.initialisation = Initialisation::Icw4,
+            Initialisation::Icw3 => self.initialisation = Initialisation::Done,
+            Initialisation::Icw4 => {
+                self.auto_eoi = value & ICW4_AUTO_EOI != 0;
+                self.initialisation = Initialisation::Done;
+            }
+            Initialisation::Done => self.mask = value,
+        }
+    }
+
+    fn write_command(&mut self, value: u8) {
+        if value & ICW1 != 0 {
+            *self = Self {
+                levels: self.levels,
+                initialisation: Initialisation::Icw2,
+                single: value & ICW1_SINGLE != 0,
+                needs_icw4: value & ICW1_NEEDS_ICW4 != 0,
+                ..Self::new(self.vector_base)
+            };
+        } else if value & OCW3 != 0 {
+            if value & OCW3_READ_REGISTER != 0 {
+                self.read_in_service = value & OCW3_READ_IN_SERVICE != 0;
+            }
+        } else {
+            // Priority rotation and the other OCW2 commands are not supported.
+            match value >> 5 {
+                NON_SPECIFIC_EOI => {
+                    if let Some(irq) = (0..8).find(|irq| self.in_service & (1 << irq) != 0) {
+                        self.in_service &= !(1 << irq);
+                    }
+                }
+                SPECIFIC_EOI => self.in_service &= !(1 << (value & 0b111)),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// The pair of cascaded 8259 programmable interrupt controllers of the PC/AT, which prioritise the
+/// IRQs raised by devices and deliver them to the processor as interrupts.
+///
+/// This is a handle, so it can be cloned to share the controllers between the processor, the
+/// ports that program them (see [`Pic::ports`]), and the devices that raise IRQs (see
+/// [`Pic::irq_line`]). The controllers start as the BIOS leaves them: IRQs 0 to 7 are delivered
+/// to vectors 0x08 to 0x0f, IRQs 8 to 15 to vectors 0x70 to 0x77, and no IRQs are masked.
+#[derive(Clone, Debug)]
+pub struct Pic(Rc<RefCell<[Chip; 2]>>);
+
+impl Default for Pic {
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new([Chip::new(0x08), Chip::new(0x70)])))
+    }
+}
+
+impl Pic {
+    /// The devices which respond to the master's and slave's ports (see [`MASTER_PORTS`] and
+    /// [`SLAVE_PORTS`]) respectively.
+    pub fn ports(&self) -> (PicPorts, PicPorts) {
+        (
+            PicPorts {
+                pic: self.clone(),
+                chip: 0,
+            },
+            PicPorts {
+                pic: self.clone(),
+                chip: 1,
+            },
+        )
+    }
+
+    /// The line of the given IRQ (0 to 15), which a device raises to request an interrupt.
+    pub fn irq_line(&self, irq: u8) -> IrqLine {
+        assert!(irq < 16, "there are only 16 IRQs (tried to use IRQ {irq})");
+        IrqLine {
+            pic: self.clone(),
+            irq,
+        }
+    }
+
+    /// Whether an IRQ has been requested which would be delivered if interrupts were enabled.
+    pub fn pending(&self) -> bool {
+        let [master, slave] = &*self.0.borrow();
+        master.pending(Self::cascaded_requests(slave)).is_some()
+    }
+
+    /// Acknowledges the highest priority IRQ which is pending (if any), as the processor does
+    /// when it begins to service it, and returns its vector.
+    pub fn acknowledge(&self) -> Option<u8> {
+        let [master, slave] = &mut *self.0.borrow_mut();
+        let irq = master.pending(Self::cascaded_requests(slave))?;
+        master.acknowledge(irq);
+        if irq != CASCADE_IRQ {
+            return Some(master.vector_base + irq);
+        }
+
+        let irq = slave.pending(0)?;
+        slave.acknowledge(irq);
+        Some(slave.vector_base + irq)
+    }
+
+    /// The request which the slave makes of the master's cascade IRQ, as its output is connected
+    /// to it.
+    fn cascaded_requests(slave: &Chip) -> u8 {
+        match slave.pending(0) {
+            Some(_) => 1 << CASCADE_IRQ,
+            None => 0,
+        }
+    }
+}
+
+/// The ports of one of the chips of a [`Pic`]: the command port, and the data port after it.
+#[derive(Debug)]
+pub struct PicPorts {
+    pic: Pic,
+    chip: usize,
+}
+
+impl PortDevice for PicPorts {
+    fn read8(&mut self, port: u16) -> u8 {
+        self.pic.0.borrow()[self.chip].read(port)
+    }
+
+    fn write8(&mut self, port: u16, value: u8) {
+        self.pic.0.borrow_mut()[self.chip].write(port, value);
+    }
+}
+
+/// An IRQ line of a [`Pic`]. The lines are edge-triggered, so an interrupt is requested when the
+/// line is raised while it is low.
+#[derive(Clone, Debug)]
+pub struct IrqLine {
+    pic: Pic,
+    irq: u8,
+}
+
+impl IrqLine {
+    pub fn raise(&self) {
+        self.set_level(true);
+    }
+
+    pub fn lower(&self) {
+        self.set_level(false);
+    }
+
+    pub fn set_level(&self, high: bool) {
+        let chip = (self.irq / 8) as usize;
+        self.pic.0.borrow_mut()[chip].set_level(self.irq % 8, high);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Programs the controllers as an operating system would, with IRQs 0 to 15 delivered to
+    /// vectors 0x20 to 0x2f.
+    fn initialise(master: &mut PicPorts, slave: &mut PicPorts) {
+        for (chip, base, cascade) in [(&mut *master, 0x20, 0x04), (&mut *slave, 0x28, 0x02)] {
+            chip.write8(0, ICW1 | ICW1_NEEDS_ICW4);
+            chip.write8(1, base);
+            chip.write8(1, cascade);
+            chip.write8(1, 0x01);
+        }
+    }
+
+    #[test]
+    fn bios_defaults() {
+        let pic = Pic::default();
+        pic.irq_line(0).raise();
+        pic.irq_line(8).raise();
+        assert!(pic.pending());
+        assert_eq!(pic.acknowledge(), Some(0x08));
+        // IRQ 8 is blocked by IRQ 0, which is in service, as IRQ 2 has a lower priority.
+        assert_eq!(pic.acknowledge(), None);
+    }
+
+    #[test]
+    fn priority_and_end_of_interrupt() {
+        let pic = Pic::default();
+        let (mut master, mut slave) = pic.ports();
+        initialise(&mut master, &mut slave);
+
+        pic.irq_line(4).raise();
+        pic.irq_line(1).raise();
+        assert_eq!(master.read8(0x20), 0b0001_0010);
+        assert_eq!(pic.acknowledge(), Some(0x21));
+        // IRQ 4 has a lower priority than IRQ 1, which is in service.
+        assert!(!pic.pending());
+        master.write8(0x20, 0x0b);
+        assert_eq!(master.read8(0x20), 0b0000_0010);
+
+        master.write8(0x20, NON_SPECIFIC_EOI << 5);
+        assert_eq!(master.read8(0x20), 0);
+        assert_eq!(pic.acknowledge(), Some(0x24));
+        master.write8(0x20, SPECIFIC_EOI << 5 | 4);
+        assert_eq!(pic.acknowledge(), None);
+    }
+
+    #[test]
+    fn edge_triggered() {
+        let pic = Pic::default();
+        let line = pic.irq_line(3);
+        line.raise();
+        line.raise();
+        assert_eq!(pic.acknowledge(), Some(0x0b));
+        let (mut master, _) = pic.ports();
+        master.write8(0x20, NON_SPECIFIC_EOI << 5);
+        assert_eq!(pic.acknowledge(), None);
+
+        line.lower();
+        line.raise();
+        assert_eq!(pic.acknowledge(), Some(0x0b));
+    }
+
+    #[test]
+    fn masking_and_cascade() {
+        let pic = Pic::default();
+        let (mut master, mut slave) = pic.ports();
+        initialise(&mut master, &mut slave);
+
+        master.write8(0x21, 0xff);
+        assert_eq!(master.read8(0x21), 0xff);
+        pic.irq_line(12).raise();
+        assert!(!pic.pending());
+
+        master.write8(0x21, !(1 << CASCADE_IRQ));
+        assert_eq!(pic.acknowledge(), Some(0x2c));
+        assert_eq!(pic.acknowledge(), None);
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{stdin, stdout, Read, Write},
+    ops::RangeInclusive,
+    sync::mpsc::Receiver,
+};
+
+use super::{pic::IrqLine, spawn_reader, PortDevice};
+
+/// The ports of the first serial port, COM1.
+pub const COM1_PORTS: RangeInclusive<u16> = 0x3f8..=0x3ff;
+/// The IRQ of the first serial port, COM1.
+pub const COM1_IRQ: u8 = 4;
+
+/// The registers of the UART, by their offset from its base port. Some offsets refer to different
+/// registers depending on whether the access is a read or a write, and on the DLAB bit of the line
+/// control register.
+mod offset {
+    /// The receiver buffer (read) and transmitter holding (write) registers, or the low byte of the
+    /// divisor latch when DLAB is set.
+    pub const DATA: u16 = 0;
+    /// The interrupt enable register, or the high byte of the divisor latch when DLAB is set.
+    pub const INTERRUPT_ENABLE: u16 = 1;
+    /// The interrupt identification (read) and FIFO control (write) registers.
+    pub const INTERRUPT_IDENTIFICATION: u16 = 2;
+    pub const LINE_CONTROL: u16 = 3;
+    pub const MODEM_CONTROL: u16 = 4;
+    pub const LINE_STATUS: u16 = 5;
+    pub const MODEM_STATUS: u16 = 6;
+    pub const SCRATCH: u16 = 7;
+}
+
+/// The divisor latch access bit of the line control register.
+const DLAB: u8 = 1 << 7;
+/// The OUT2 bit of the modem control register, which the PC uses to connect the interrupt output
+/// to the IRQ line.
+const OUT2: u8 = 1 << 3;
+/// The loopback bit of the modem control register, which connects the transmitter to the receiver.
+const LOOPBACK: u8 = 1 << 4;
+/// The bit of the FIFO control register (and the bits of the interrupt identification register)
+/// which enable the FIFOs.
+const FIFO_ENABLE: u8 = 1 << 0;
+const FIFOS_ENABLED: u8 = 0b1100_0000;
+/// The bits of the FIFO control register which clear the receive FIFO.
+const CLEAR_RECEIVE_FIFO: u8 = 1 << 1;
+
+/// The bits of the line status register.
+const DATA_READY: u8 = 1 << 0;
+const TRANSMITTER_HOLDING_REGISTER_EMPTY: u8 = 1 << 5;
+const TRANSMITTER_EMPTY: u8 = 1 << 6;
+
+/// The bits of the interrupt enable register, and the interrupt identification register's value
+/// for the corresponding interrupt.
+const RECEIVED_DATA_INTERRUPT: u8 = 1 << 0;
+const TRANSMITTER_EMPTY_INTERRUPT: u8 = 1 << 1;
+const NO_INTERRUPT_PENDING: u8 = 0b0001;
+const RECEIVED_DATA_PENDING: u8 = 0b0100;
+const TRANSMITTER_EMPTY_PENDING: u8 = 0b0010;
+
+/// The modem status register with the modem's control lines (CTS, DSR, and DCD) asserted, such
+/// that a guest which waits for them does not wait forever.
+const MODEM_READY: u8 = 0b1011_0000;
+
+/// A 16550 UART, as used for the PC's serial ports. Bytes which are transmitted by the guest are
+/// written to the host's writer, and bytes which are read from the host's reader are received by
+/// the guest. The line's speed and framing (set through the divisor latch and line control
+/// register) are stored, but have no effect, as the bytes are transferred instantly.
+///
+/// The host's reader is read by a thread of its own, such that the guest can poll the line status
+/// register without blocking while no input is available.
+pub struct Uart16550 {
+    output: Box<dyn Write>,
+    input: Receiver<u8>,
+    received: VecDeque<u8>,
+    divisor: u16,
+    interrupt_enable: u8,
+    /// Whether the transmitter empty interrupt is pending. Unlike the received data interrupt, it
+    /// is cleared by reading the interrupt identification register (when it is reported there).
+    transmitter_empty_pending: bool,
+    fifo_control: u8,
+    line_control: u8,
+    modem_control: u8,
+    scratch: u8,
+    irq: Option<IrqLine>,
+}
+
+impl fmt::Debug for Uart16550 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Uart16550")
+            .field("received", &self.received)
+            .field("divisor", &self.divisor)
+            .field("interrupt_enable", &self.interrupt_enable)
+            .field("line_control", &self.line_control)
+            .field("modem_control", &self.modem_control)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Uart16550 {
+    /// A UART which is connected to the host's standard input and output.
+    fn default() -> Self {
+        Self::new(stdin(), stdout())
+    }
+}
+
+impl Uart16550 {
+    /// Creates a UART which receives the bytes read from `input`, and transmits to `output`.
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            input: spawn_reader(input),
+            received: VecDeque::new(),
+            // 115200 baud, which is the fastest rate (a divisor of 1) that a 1.8432 MHz clock gives.
+            divisor: 1,
+            interrupt_enable: 0,
+            transmitter_empty_pending: false,
+            fifo_control: 0,
+            line_control: 0,
+            modem_control: 0,
+            scratch: 0,
+            irq: None,
+        }
+    }
+
+    /// Connects the UART's interrupt output to an IRQ line. As on the PC, the line is only raised
+    /// while OUT2 of the modem control register is set.
+    pub fn connect_irq(&mut self, line: IrqLine) {
+        self.irq = Some(line);
+    }
+
+    /// Whether an interrupt is pending, i.e. that the interrupt output is raised.
+    pub fn interrupt_pending(&mut self) -> bool {
+        self.pending_interrupt() != NO_INTERRUPT_PENDING
+    }
+
+    /// Sets the level of the IRQ line (if it is connected) to that of the interrupt output.
+    fn update_irq(&mut self) {
+        let pending = self.modem_control & OUT2 != 0 && self.interrupt_pending();
+        if let Some(irq) = &self.irq {
+            irq.set_level(pending);
+        }
+    }
+
+    /// Moves the bytes which have arrived from the host's reader into the receive buffer.
+    fn poll_input(&mut self) {
+        self.received.extend(self.input.try_iter());
+    }
+
+    /// The interrupt identification of the highest priority interrupt that is pending.
+    fn pending_interrupt(&mut self) -> u8 {
+        self.poll_input();
+        if self.interrupt_enable & RECEIVED_DATA_INTERRUPT != 0 && !self.received.is_empty() {
+            RECEIVED_DATA_PENDING
+        } else if self.interrupt_enable & TRANSMITTER_EMPTY_INTERRUPT != 0
+            && self.transmitter_empty_pending
+        {
+            TRANSMITTER_EMPTY_PENDING
+        } else {
+            NO_INTERRUPT_PENDING
+        }
+    }
+
+    /// Transmits a byte. The transmitter is empty again straight away, as bytes are transferred
+    /// instantly.
+    fn transmit(&mut self, byte: u8) {
+        self.transmitter_empty_pending = true;
+        if self.modem_control & LOOPBACK != 0 {
+            self.received.push_back(byte);
+        } else {
+            // A serial line has no way to report that the other end failed to receive a byte.
+            let _ = self
+                .output
+                .write_all(&[byte])
+                .and_then(|_| self.output.flush());
+        }
+    }
+
+    fn read_register(&mut self, port: u16) -> u8 {
+        let dlab = self.line_control & DLAB != 0;
+        match port & 0b111 {
+            offset::DATA if dlab => self.divisor as u8,
+            offset::DATA => {
+                self.poll_input();
+                self.received.pop_front().unwrap_or(0)
+            }
+            offset::INTERRUPT_ENABLE if dlab => (self.divisor >> 8) as u8,
+            offset::INTERRUPT_ENABLE => self.interrupt_enable,
+            offset::INTERRUPT_IDENTIFICATION => {
+                let fifos = if self.fifo_control & FIFO_ENABLE != 0 {
+                    FIFOS_ENABLED
+                } else {
+                    0
+                };
+                let pending = self.pending_interrupt();
+                if pending == TRANSMITTER_EMPTY_PENDING {
+                    self.transmitter_empty_pending = false;
+                }
+                fifos | pending
+            }
+            offset::LINE_CONTROL => self.line_control,
+            offset::MODEM_CONTROL => self.modem_control,
+            offset::LINE_STATUS => {
+                self.poll_input();
+                let data_ready = if self.received.is_empty() {
+                    0
+                } else {
+                    DATA_READY
+                };
+                data_ready | TRANSMITTER_HOLDING_REGISTER_EMPTY | TRANSMITTER_EMPTY
+            }
+            offset::MODEM_STATUS => MODEM_READY,
+            offset::SCRATCH => self.scratch,
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_register(&mut self, port: u16, value: u8) {
+        let dlab = self.line_control & DLAB != 0;
+        match port & 0b111 {
+            offset::DATA if dlab => self.divisor = self.divisor & 0xff00 | value as u16,
+            offset::DATA => self.transmit(value),
+            offset::INTERRUPT_ENABLE if dlab => {
+                self.divisor = self.divisor & 0x00ff | (value as u16) << 8;
+            }
+            offset::INTERRUPT_ENABLE => {
+                // Enabling the transmitter empty interrupt raises it, as the transmitter is empty.
+                if value & !self.interrupt_enable & TRANSMITTER_EMPTY_INTERRUPT != 0 {
+                    self.transmitter_empty_pending = true;
+                }
+                self.interrupt_enable = value & 0x0f;
+            }
+            offset::INTERRUPT_IDENTIFICATION => {
+                if value & CLEAR_RECEIVE_FIFO != 0 {
+                    self.poll_input();
+                    self.received.clear();
+                }
+                self.fifo_control = value;
+            }
+            offset::LINE_CONTROL => self.line_control = value,
+            offset::MODEM_CONTROL => self.modem_control = value & 0x1f,
+            // The line and modem status registers are read-only.
+            offset::LINE_STATUS | offset::MODEM_STATUS => (),
+            offset::SCRATCH => self.scratch = value,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl PortDevice for Uart16550 {
+    fn read8(&mut self, port: u16) -> u8 {
+        let value = self.read_register(port);
+        self.update_irq();
+        value
+    }
+
+    fn write8(&mut self, port: u16, value: u8) {
+        self.write_register(port, value);
+        self.update_irq();
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        if self.irq.is_some() {
+            self.update_irq();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::{empty, sink},
+        rc::Rc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::devices::pic::Pic;
+
+    const BASE: u16 = *COM1_PORTS.start();
+
+    /// An output which can still be read once it has been given to the UART.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transmit() {
+        let output = SharedOutput::default();
+        let mut uart = Uart16550::new(empty(), output.clone());
+        let status = uart.read8(BASE + offset::LINE_STATUS);
+        assert_eq!(
+            status & TRANSMITTER_HOLDING_REGISTER_EMPTY,
+            TRANSMITTER_HOLDING_REGISTER_EMPTY
+        );
+        assert_eq!(status & DATA_READY, 0);
+
+        for byte in b"hi\n" {
+            uart.write8(BASE + offset::DATA, *byte);
+        }
+        assert_eq!(*output.0.borrow(), b"hi\n");
+    }
+
+    #[test]
+    fn receive() {
+        let mut uart = Uart16550::new(&b"ok"[..], sink());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while uart.read8(BASE + offset::LINE_STATUS) & DATA_READY == 0 {
+            assert!(Instant::now() < deadline, "no input was received");
+            thread::yield_now();
+        }
+        assert_eq!(uart.read8(BASE + offset::DATA), b'o');
+
+        // Clearing the receive FIFO discards the input which has not been read.
+        while uart.received.is_empty() {
+            assert!(Instant::now() < deadline, "no input was received");
+            uart.poll_input();
+        }
+        uart.write8(
+            BASE + offset::INTERRUPT_IDENTIFICATION,
+            FIFO_ENABLE | CLEAR_RECEIVE_FIFO,
+        );
+        assert_eq!(uart.read8(BASE + offset::LINE_STATUS) & DATA_READY, 0);
+        assert_eq!(
+            uart.read8(BASE + offset::INTERRUPT_IDENTIFICATION),
+            FIFOS_ENABLED | NO_INTERRUPT_PENDING
+        );
+    }
+
+    #[test]
+    fn loopback_and_interrupts() {
+        let output = SharedOutput::default();
+        let mut uart = Uart16550::new(empty(), output.clone());
+        uart.write8(BASE + offset::MODEM_CONTROL, LOOPBACK);
+        uart.write8(BASE + offset::INTERRUPT_ENABLE, RECEIVED_DATA_INTERRUPT);
+        assert!(!uart.interrupt_pending());
+
+        uart.write8(BASE + offset::DATA, b'x');
+        assert!(output.0.borrow().is_empty());
+        assert!(uart.interrupt_pending());
+        assert_eq!(
+            uart.read8(BASE + offset::INTERRUPT_IDENTIFICATION),
+            RECEIVED_DATA_PENDING
+        );
+        assert_eq!(uart.read8(BASE + offset::DATA), b'x');
+        assert!(!uart.interrupt_pending());
+
+        // The transmitter empty interrupt is cleared once it has been identified.
+        uart.write8(
+            BASE + offset::INTERRUPT_ENABLE,
+            RECEIVED_DATA_INTERRUPT | TRANSMITTER_EMPTY_INTERRUPT,
+        );
+        assert!(uart.interrupt_pending());
+        assert_eq!(
+            uart.read8(BASE + offset::INTERRUPT_IDENTIFICATION),
+            TRANSMITTER_EMPTY_PENDING
+        );
+        assert!(!uart.interrupt_pending());
+    }
+
+    #[test]
+    fn irq() {
+        let pic = Pic::default();
+        let mut uart = Uart16550::new(empty(), sink());
+        uart.connect_irq(pic.irq_line(COM1_IRQ));
+        uart.write8(BASE + offset::MODEM_CONTROL, LOOPBACK);
+        uart.write8(BASE + offset::INTERRUPT_ENABLE, RECEIVED_DATA_INTERRUPT);
+        uart.write8(BASE + offset::DATA, b'x');
+        uart.tick(0);
+        // The IRQ line is only raised once OUT2 is set.
+        assert!(!pic.pending());
+        uart.write8(BASE + offset::MODEM_CONTROL, LOOPBACK | OUT2);
+        assert_eq!(pic.acknowledge(), Some(0x08 + COM1_IRQ));
+    }
+
+    #[test]
+    fn divisor_latch() {
+        let mut uart = Uart16550::new(empty(), sink());
+        uart.write8(BASE + offset::INTERRUPT_ENABLE, 0x03);
+        uart.write8(BASE + offset::LINE_CONTROL, DLAB | 0x03);
+        uart.write16(BASE + offset::DATA, 0x000c);
+        assert_eq!(uart.divisor, 12);
+        assert_eq!(uart.read16(BASE + offset::DATA), 0x000c);
+
+        uart.write8(BASE + offset::LINE_CONTROL, 0x03);
+        assert_eq!(uart.read8(BASE + offset::INTERRUPT_ENABLE), 0x03);
+        uart.write8(BASE + offset::SCRATCH, 0x5a);
+        assert_eq!(uart.read8(BASE + offset::SCRATCH), 0x5a);
+    }
+}
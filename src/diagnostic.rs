@@ -0,0 +1,222 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+};
+use core::{fmt, ops::Range};
+
+use crate::{
+    error::{Error, Warning},
+    program::is_symbol_char,
+};
+
+/// Whether a diagnostic prevents a program from being assembled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or warning at a particular place in the source of a program, which can be reported
+/// alongside the line that caused it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The index of the module (e.g. the source file) which the line belongs to, when a program is
+    /// assembled from several (see `Program::assemble_modules`).
+    pub module: usize,
+    /// The (1-based) number of the line.
+    pub line: usize,
+    /// The (0-based) range of the characters within the line which caused the error.
+    pub columns: Range<usize>,
+    /// The text which caused the error.
+    pub token: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic for an error caused by `token`, on the line with the given (0-based)
+    /// index whose text is `line`. If the token is empty or cannot be found within the line (e.g.
+    /// because it came from the expansion of a macro), the whole line is referred to instead.
+    pub fn new(index: usize, line: &str, token: &str, error: &Error) -> Self {
+        Self::locate(index, line, token, Severity::Error, error.to_string())
+    }
+
+    /// Creates a diagnostic for a warning caused by `token`. See [`Diagnostic::new`].
+    pub fn warning(index: usize, line: &str, token: &str, warning: &Warning) -> Self {
+        Self::locate(index, line, token, Severity::Warning, warning.to_string())
+    }
+
+    fn locate(index: usize, line: &str, token: &str, severity: Severity, message: String) -> Self {
+        let code = line.trim_end();
+        // An occurrence of the token which is a whole word is preferred, such that e.g. the `g` of
+        // `global g` refers to the symbol rather than to the start of the directive.
+        let is_word_boundary = |c: Option<char>| !c.is_some_and(is_symbol_char);
+        let occurrence = code
+            .match_indices(token)
+            .map(|(start, _)| start)
+            .find(|&start| {
+                is_word_boundary(code[..start].chars().next_back())
+                    && is_word_boundary(code[start + token.len()..].chars().next())
+            })
+            .or_else(|| code.find(token));
+        let (start, token) = match occurrence {
+            Some(start) if !token.is_empty() => (start, token),
+            _ => {
+                let start = code.len() - code.trim_start().len();
+                (start, &code[start..])
+            }
+        };
+        let start_column = code[..start].chars().count();
+        Self {
+            severity,
+            module: 0,
+            line: index + 1,
+            columns: start_column..start_column + token.chars().count(),
+            token: token.into(),
+            message,
+        }
+    }
+
+    /// Renders the diagnostic in the style of rustc, such that the line which caused the error is
+    /// shown with the offending token underlined. `source` must be the whole source of the program
+    /// which was read from `path`.
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let line = source.lines().nth(self.line - 1).unwrap_or_default();
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Tabs are kept in the padding so that the underline lines up however they are shown.
+        let padding: String = line
+            .chars()
+            .take(self.columns.start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline = "^".repeat(self.columns.len().max(1));
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        format!(
+            "{severity}: {message}\n\
+             {gutter}--> {path}:{number}:{column}\n\
+             {gutter} |\n\
+             {number} | {line}\n\
+             {gutter} | {padding}{underline}\n",
+            message = self.message,
+            column = self.columns.start + 1,
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line,
+            self.columns.start + 1,
+            self.message
+        )
+    }
+}
+
+/// Attaches the location of `token` to an error encountered while processing the line of `source`
+/// with the given (0-based) index. An error which already has a location keeps it, as it is the
+/// more precise of the two.
+pub(crate) fn on_line(source: &[&str], index: usize, token: &str, error: Error) -> Error {
+    match error {
+        Error::Diagnostic(_) => error,
+        _ => {
+            let line = source.get(index).copied().unwrap_or_default();
+            Error::Diagnostic(Box::new(Diagnostic::new(index, line, token, &error)))
+        }
+    }
+}
+
+/// Attaches the index of the module that an error was encountered in to its location, if it has
+/// one.
+pub(crate) fn in_module(module: usize, error: Error) -> Error {
+    match error {
+        Error::Diagnostic(mut diagnostic) => {
+            diagnostic.module = module;
+            Error::Diagnostic(diagnostic)
+        }
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_new() {
+        let error = Error::InvalidSymbol("\"nowhere\" is not defined".into());
+        let diagnostic = Diagnostic::new(2, "    jmp nowhere ; comment", "nowhere", &error);
+        assert_eq!(diagnostic.line, 3);
+        assert_eq!(diagnostic.columns, 8..15);
+        assert_eq!(diagnostic.token, "nowhere");
+        assert_eq!(
+            diagnostic.to_string(),
+            "line 3, column 9: invalid symbol: \"nowhere\" is not defined"
+        );
+
+        let diagnostic = Diagnostic::new(0, "  COUNT_DOWN eax  ", "sub eax, 1", &error);
+        assert_eq!(diagnostic.columns, 2..16);
+        assert_eq!(diagnostic.token, "COUNT_DOWN eax");
+        let diagnostic = Diagnostic::new(0, "é: jmp é", "é", &error);
+        assert_eq!(diagnostic.columns, 0..1);
+        let diagnostic = Diagnostic::new(0, "extern exit, x", "x", &error);
+        assert_eq!(diagnostic.columns, 13..14);
+    }
+
+    #[test]
+    fn diagnostic_render() {
+        let error = Error::CannotParseInstruction("ORG can only be used once".into());
+        let source = "org 0\n\torg 0x100\n";
+        let diagnostic = Diagnostic::new(1, "\torg 0x100", "org", &error);
+        assert_eq!(
+            diagnostic.render("boot.asm", source),
+            "error: instruction could not be parsed: ORG can only be used once\n \
+             --> boot.asm:2:2\n  \
+             |\n\
+             2 | \torg 0x100\n  \
+             | \t^^^\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_render_warning() {
+        let warning = Warning::IgnoredSizeDirective {
+            size: crate::instruction::Size::Byte,
+            register: crate::register::Register32::Eax.into(),
+        };
+        let diagnostic = Diagnostic::warning(0, "inc byte eax", "eax", &warning);
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.render("a.asm", "inc byte eax"),
+            "warning: the byte size directive is ignored, as EAX is a dword register\n \
+             --> a.asm:1:10\n  \
+             |\n\
+             1 | inc byte eax\n  \
+             |          ^^^\n"
+        );
+    }
+
+    #[test]
+    fn on_line_keeps_location() {
+        let source = ["nop", "M eax"];
+        let error = on_line(
+            &source,
+            1,
+            "eax",
+            Error::CannotParseInstruction("bad".into()),
+        );
+        let error = on_line(&source, 0, "nop", error);
+        let Error::Diagnostic(diagnostic) = error else {
+            panic!("expected a diagnostic");
+        };
+        assert_eq!(diagnostic.line, 2);
+        assert_eq!(diagnostic.token, "eax");
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use crate::{cpu::Cpu, interrupt::CpuException, machine::Machine, register::SegmentRegister};
+
+/// The interrupt through which programs request DOS services, with the function in AH.
+pub const DOS_SERVICES_VECTOR: u8 = 0x21;
+
+/// The character which terminates a string that is written by function 09h.
+const STRING_TERMINATOR: u8 = b'$';
+
+/// The character which is read by function 01h once the input has been exhausted (Ctrl-Z), as DOS
+/// uses it to mark the end of a file.
+const END_OF_FILE: u8 = 0x1a;
+
+/// A minimal DOS personality, which services the `int 0x21` functions that classic DOS programs use
+/// for console I/O and to exit:
+///
+/// - 01h: reads a character into AL, and echoes it to the output.
+/// - 02h: writes the character in DL to the output.
+/// - 09h: writes the string at DS:DX, which is terminated by `$`, to the output.
+/// - 4Ch: exits with the return code in AL, which halts the processor.
+///
+/// As DOS does, any other function returns 0 in AL and does nothing else.
+pub struct Dos {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    exit_code: Option<u8>,
+}
+
+impl Dos {
+    /// Creates a DOS personality whose console reads from `input` and writes to `output`.
+    pub fn new(input: impl Read + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+            exit_code: None,
+        }
+    }
+
+    /// The return code which the program exited with (using function 4Ch), if it has exited.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    /// Installs the personality as the host handler for `int 0x21` on the machine. The returned
+    /// handle is shared with the handler, such that the exit code can be read after a run.
+    pub fn install(self, machine: &mut Machine) -> Rc<RefCell<Self>> {
+        let dos = Rc::new(RefCell::new(self));
+        Self::install_shared(&dos, machine);
+        dos
+    }
+
+    /// Installs a personality which is already shared (see [`Dos::install`]).
+    pub fn install_shared(dos: &Rc<RefCell<Self>>, machine: &mut Machine) {
+        let handler = Rc::clone(dos);
+        machine.set_interrupt_handler(DOS_SERVICES_VECTOR, move |cpu| {
+            handler.borrow_mut().service(cpu);
+        });
+    }
+
+    /// Services the function in AH. Errors writing to the output are ignored, as DOS has no way to
+    /// report them to the program.
+    fn service(&mut self, cpu: &mut Cpu) {
+        match cpu.registers.get_ah() {
+            0x01 => {
+                let mut byte = [END_OF_FILE];
+                if self.input.read_exact(&mut byte).is_err() {
+                    byte = [END_OF_FILE];
+                }
+                self.write(&byte);
+                cpu.registers.set_al(byte[0]);
+            }
+            0x02 => {
+                let character = cpu.registers.get_dl();
+                self.write(&[character]);
+                cpu.registers.set_al(character);
+            }
+            0x09 => {
+                let Some(string) = read_string(cpu) else {
+                    cpu.latch_fault(CpuException::GeneralProtection);
+                    return;
+                };
+                self.write(&string);
+                cpu.registers.set_al(STRING_TERMINATOR);
+            }
+            0x4c => {
+                self.exit_code = Some(cpu.registers.get_al());
+                cpu.halted = true;
+            }
+            _ => cpu.registers.set_al(0),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let _ = self
+            .output
+            .write_all(bytes)
+            .and_then(|_| self.output.flush());
+    }
+}
+
+/// Reads the `$`-terminated string at DS:DX (without its terminator), or returns `None` if it runs
+/// past the end of the address space.
+fn read_string(cpu: &Cpu) -> Option<Vec<u8>> {
+    let start = cpu
+        .registers
+        .get_segment_base(SegmentRegister::Ds)
+        .wrapping_add(cpu.registers.get_dx() as u32);
+    let mut string = Vec::new();
+    for offset in 0.. {
+        match cpu.memory.read8(start.checked_add(offset)?).ok()? {
+            STRING_TERMINATOR => break,
+            byte => string.push(byte),
+        }
+    }
+    Some(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::Syntax,
+        machine::StopReason,
+        program::{Layout, Program},
+    };
+
+    /// An output which can still be read once it has been given to the personality.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn load(source: &str, input: &'static [u8]) -> (Machine, Rc<RefCell<Dos>>, SharedOutput) {
+        let layout = Layout {
+            data: 0x200,
+            ..Layout::default()
+        };
+        let program = Program::assemble_modules(&[source], layout, Syntax::Nasm).unwrap();
+        let mut machine = Machine::new(Cpu::default(), program).unwrap();
+        let output = SharedOutput::default();
+        let dos = Dos::new(input, output.clone()).install(&mut machine);
+        (machine, dos, output)
+    }
+
+    #[test]
+    fn print_string_and_exit() {
+        let (mut machine, dos, output) = load(
+            "section .data\n\
+             message: db \"Hello, world!\", 13, 10, \"$\"\n\
+             section .text\n\
+             int 0x21\n\
+             int 0x21\n\
+             sub ecx, 1",
+            b"",
+        );
+        let message = machine.program().symbols().get("message").unwrap();
+        machine.cpu_mut().registers.set_dx(message as u16);
+        machine.cpu_mut().registers.set_ah(0x09);
+        assert_eq!(machine.step().stop_reason, None);
+        assert_eq!(*output.0.borrow(), b"Hello, world!\r\n");
+        assert_eq!(dos.borrow().exit_code(), None);
+
+        machine.cpu_mut().registers.set_ax(0x4c03);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(dos.borrow().exit_code(), Some(3));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0);
+    }
+
+    #[test]
+    fn character_io() {
+        let (mut machine, _, output) = load("int 0x21\nint 0x21\nint 0x21\nint 0x21", b"x");
+        machine.cpu_mut().registers.set_ah(0x01);
+        machine.step();
+        assert_eq!(machine.cpu().registers.get_al(), b'x');
+
+        // Once the input is exhausted, Ctrl-Z is read.
+        machine.step();
+        assert_eq!(machine.cpu().registers.get_al(), END_OF_FILE);
+
+        machine.cpu_mut().registers.set_ah(0x02);
+        machine.cpu_mut().registers.set_dl(b'!');
+        machine.step();
+        assert_eq!(*output.0.borrow(), b"x\x1a!");
+
+        // Unsupported functions return 0 in AL.
+        machine.cpu_mut().registers.set_ah(0x30);
+        machine.step();
+        assert_eq!(machine.cpu().registers.get_al(), 0);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        // The string runs up to the end of the address space without being terminated.
+        let (mut machine, _, output) = load("int 0x21", b"");
+        machine.cpu_mut().memory.write8(u32::MAX, b'a').unwrap();
+        machine.cpu_mut().registers.set_ah(0x09);
+        machine.cpu_mut().registers.set_dx(0xffff);
+        machine
+            .cpu_mut()
+            .registers
+            .set_segment_base(SegmentRegister::Ds, 0xffff_0000);
+        assert_eq!(
+            machine.step().stop_reason,
+            Some(StopReason::Exception(CpuException::GeneralProtection))
+        );
+        assert!(output.0.borrow().is_empty());
+    }
+}
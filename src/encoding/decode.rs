@@ -0,0 +1,464 @@
+use crate::{
+    error::Error,
+    instruction::{
+        lookup_instructions_by_opcode, EffectiveAddress, EffectiveAddressOperand,
+        EffectiveAddressOperator, FarPointer, Immediate, Instruction, Operand, OperandFunctionMap,
+        OperandType, Operands, RepeatPrefix, Size,
+    },
+    register::{ControlRegister, DebugRegister, Register, Register16},
+};
+use alloc::{format, string::ToString, vec::Vec};
+
+use super::{
+    modrm::ModRM,
+    operand_encodings,
+    sib::{Base, SIB},
+    OperandEncoding, Prefix, RegisterKind,
+};
+
+/// Reads the bytes of an instruction in turn.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn read(&mut self, count: usize) -> Result<&[u8], Error> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| Error::CannotDecodeInstruction("the instruction is truncated".into()))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn read8(&mut self) -> Result<u8, Error> {
+        Ok(self.read(1)?[0])
+    }
+
+    /// Reads a little-endian value of the given size, which is zero-extended to 32 bits unless
+    /// `sign_extended` is set.
+    fn read_sized(&mut self, size: Size, sign_extended: bool) -> Result<u32, Error> {
+        let value = match size {
+            Size::Byte if sign_extended => self.read8()? as i8 as u32,
+            Size::Byte => self.read8()?.into(),
+            Size::Word => {
+                let value = u16::from_le_bytes(self.read(2)?.try_into().unwrap());
+                if sign_extended {
+                    value as i16 as u32
+                } else {
+                    value.into()
+                }
+            }
+            _ => u32::from_le_bytes(self.read(4)?.try_into().unwrap()),
+        };
+        Ok(value)
+    }
+}
+
+impl Instruction {
+    /// Decodes the machine code at the start of `bytes`, which is placed at `address` (which the
+    /// target of a relative branch is relative to). Returns the instruction along with the number
+    /// of bytes that it was encoded in. This is the inverse of `Instruction::encode`, and so a
+    /// segment-override prefix is decoded as an instruction of its own.
+    pub fn decode(bytes: &[u8], address: u32) -> Result<(Self, usize), Error> {
+        let mut reader = Reader { bytes, position: 0 };
+        let mut lock_prefix = false;
+        let mut repeat_prefix = None;
+        let mut operand_size_prefix = false;
+
+        // A mandatory prefix (e.g. the 0x66 of `MOVAPD`) is part of the opcode, so the bytes are
+        // only treated as prefixes if they do not begin an opcode.
+        let (descriptors, opcode_length) = loop {
+            let (descriptors, length) = lookup_instructions_by_opcode(&bytes[reader.position..]);
+            if !descriptors.is_empty() {
+                break (descriptors, length);
+            }
+            let byte = reader.read8()?;
+            match Prefix::try_from(byte) {
+                Ok(Prefix::Lock) if !lock_prefix => lock_prefix = true,
+                Ok(Prefix::Repne) if repeat_prefix.is_none() => {
+                    repeat_prefix = Some(RepeatPrefix::Repne)
+                }
+                Ok(Prefix::Rep) if repeat_prefix.is_none() => {
+                    repeat_prefix = Some(RepeatPrefix::Rep)
+                }
+                Ok(Prefix::OperandSizeOverride) if !operand_size_prefix => {
+                    operand_size_prefix = true
+                }
+                _ => {
+                    return Err(Error::CannotDecodeInstruction(format!(
+                        "{byte:#04x} is not a valid opcode"
+                    )))
+                }
+            }
+        };
+        let opcode = reader.read(opcode_length)?.to_vec();
+
+        // The operand-size prefix selects between the 16-bit and 32-bit forms of an opcode. The
+        // same opcode may also be listed both with and without its operands (e.g. `AAM` and
+        // `AAM imm8`), in which case the operands are always encoded.
+        let candidates: Vec<_> = descriptors
+            .iter()
+            .flat_map(|descriptor| descriptor.maps().map(move |map| (*descriptor, map)))
+            .collect();
+        let sized: Vec<_> = candidates
+            .iter()
+            .filter(|(descriptor, map)| {
+                descriptor.needs_operand_size_prefix(map) == operand_size_prefix
+            })
+            .copied()
+            .collect();
+        let mut candidates = if sized.is_empty() { candidates } else { sized };
+        let operand_count = |map: &OperandFunctionMap| {
+            operand_encodings(&map.instruction_operand_format).map_or(0, <[_]>::len)
+        };
+        let most_operands = candidates
+            .iter()
+            .map(|(_, map)| operand_count(map))
+            .max()
+            .unwrap_or(0);
+        candidates.retain(|(_, map)| operand_count(map) == most_operands);
+        let [(descriptor, map)] = candidates[..] else {
+            return Err(Error::AmbiguousInstruction(format!(
+                "the opcode {opcode:02x?} does not uniquely match a single instruction"
+            )));
+        };
+
+        let format = &map.instruction_operand_format;
+        let encodings = operand_encodings(format)?;
+        let has_modrm = descriptor.opcode_extension.is_some()
+            || encodings.iter().any(|encoding| {
+                matches!(
+                    encoding,
+                    OperandEncoding::Reg(_)
+                        | OperandEncoding::Rm(..)
+                        | OperandEncoding::Memory(_)
+                        | OperandEncoding::RmRegister(_)
+                )
+            });
+        let modrm = if has_modrm {
+            Some(ModRM::from(reader.read8()?))
+        } else {
+            None
+        };
+
+        // The SIB byte and displacement come before any immediates.
+        let memory = match &modrm {
+            Some(modrm) if modrm.mode() != 0b11 => Some(decode_memory(&mut reader, modrm)?),
+            _ => None,
+        };
+
+        let mut operands = Vec::new();
+        for (index, encoding) in encodings.iter().enumerate() {
+            let rm = || {
+                let modrm = modrm.as_ref().expect("the format has a ModR/M byte");
+                ModRM::new(0b11, modrm.rm(), 0)
+            };
+            let operand = match encoding {
+                OperandEncoding::Implied(register) => {
+                    Operand::new(OperandType::Register(register.clone()), None)
+                }
+                OperandEncoding::Constant(value) => {
+                    Operand::new(OperandType::Immediate(Immediate(*value)), None)
+                }
+                OperandEncoding::Reg(kind) => {
+                    let modrm = modrm.as_ref().expect("the format has a ModR/M byte");
+                    Operand::new(OperandType::Register(register(*kind, modrm)?), None)
+                }
+                OperandEncoding::Rm(kind, size) => match &memory {
+                    Some(memory) => Operand::new(OperandType::Memory(memory.clone()), Some(*size)),
+                    None => Operand::new(OperandType::Register(register(*kind, &rm())?), None),
+                },
+                OperandEncoding::Memory(size) => match &memory {
+                    Some(memory) => Operand::new(OperandType::Memory(memory.clone()), *size),
+                    None => {
+                        return Err(Error::CannotDecodeInstruction(format!(
+                            "{} requires a memory operand",
+                            descriptor.mnemonic
+                        )))
+                    }
+                },
+                // The mod field is ignored, and is always treated as referring to a register.
+                OperandEncoding::RmRegister(kind) => {
+                    Operand::new(OperandType::Register(register(*kind, &rm())?), None)
+                }
+                OperandEncoding::OpcodeRegister(kind) => {
+                    let base = descriptor.opcode_bytes().last().copied().unwrap_or(0);
+                    let code = opcode.last().unwrap().wrapping_sub(base);
+                    let modrm = ModRM::new(0b11, code, 0);
+                    Operand::new(OperandType::Register(register(*kind, &modrm)?), None)
+                }
+                // An immediate which is sign-extended is given its size, such that the same form
+                // of the instruction is used if it is encoded again.
+                OperandEncoding::Immediate(size) => {
+                    let sign_extended = format
+                        .immediates()
+                        .iter()
+                        .any(|&(operand, _, sign_extended)| operand == index && sign_extended);
+                    let value = reader.read_sized(*size, sign_extended)?;
+                    Operand::new(
+                        OperandType::Immediate(Immediate(value)),
+                        sign_extended.then_some(*size),
+                    )
+                }
+                OperandEncoding::Offset(size) => {
+                    let offset = reader.read_sized(Size::Dword, false)?;
+                    let memory = EffectiveAddress::try_from_iter([(
+                        EffectiveAddressOperator::Add,
+                        EffectiveAddressOperand::Immediate(Immediate(offset)),
+                    )])?;
+                    Operand::new(OperandType::Memory(memory), Some(*size))
+                }
+                OperandEncoding::FarPointer => {
+                    let offset = reader.read_sized(Size::Dword, false)?;
+                    let selector = reader.read_sized(Size::Word, false)? as u16;
+                    Operand::new(
+                        OperandType::FarPointer(FarPointer { selector, offset }),
+                        None,
+                    )
+                }
+                // The branch is the last part of the instruction, so its end is the address of the
+                // next instruction.
+                OperandEncoding::Relative => {
+                    let relative = reader.read_sized(Size::Dword, false)?;
+                    let next = address.wrapping_add(reader.position as u32);
+                    Operand::new(
+                        OperandType::Immediate(Immediate(next.wrapping_add(relative))),
+                        None,
+                    )
+                }
+            };
+            operands.push(operand);
+        }
+
+        // `REP` shares its encoding with `REPE`, which is how it behaves for `CMPS` and `SCAS`.
+        let mnemonic = descriptor.mnemonic.to_string();
+        if repeat_prefix == Some(RepeatPrefix::Rep)
+            && (mnemonic.starts_with("CMPS") || mnemonic.starts_with("SCAS"))
+        {
+            repeat_prefix = Some(RepeatPrefix::Repe);
+        }
+
+        let instruction = Self::new(
+            mnemonic,
+            descriptor,
+            map,
+            Operands(operands),
+            repeat_prefix,
+            lock_prefix,
+        );
+        Ok((instruction, reader.position))
+    }
+}
+
+/// Decodes the memory operand given by the r/m field of the ModR/M byte (which must not be in
+/// register mode), along with any SIB byte and displacement which follow it.
+fn decode_memory(reader: &mut Reader, modrm: &ModRM) -> Result<EffectiveAddress, Error> {
+    let register32 = |code: u8| register(RegisterKind::Sized(Size::Dword), &ModRM::new(0, code, 0));
+
+    let mut base = None;
+    let mut index = None;
+    let mut displacement_size = match modrm.mode() {
+        0b00 => None,
+        0b01 => Some(Size::Byte),
+        _ => Some(Size::Dword),
+    };
+    match modrm.rm() {
+        // An r/m of 100 signifies that a SIB byte follows, in which an index of 100 signifies that
+        // there is no index, and a base of 101 with a mod of 00 that there is no base.
+        0b100 => {
+            let sib = SIB::from(reader.read8()?);
+            if let Some(register) = sib.get_index().register() {
+                index = Some((register.into(), sib.get_scale().factor()));
+            }
+            match sib.get_base() {
+                Base::DisplacementOnlyOrEbp if modrm.mode() == 0b00 => {
+                    displacement_size = Some(Size::Dword)
+                }
+                sib_base => base = Some(sib_base.register().into()),
+            }
+        }
+        // An r/m of 101 with a mod of 00 signifies that there is only a displacement.
+        0b101 if modrm.mode() == 0b00 => displacement_size = Some(Size::Dword),
+        rm => base = Some(register32(rm)?),
+    }
+    let displacement = displacement_size
+        .map(|size| reader.read_sized(size, true))
+        .transpose()?;
+
+    let mut raw = Vec::new();
+    if let Some(base) = base {
+        raw.push((
+            EffectiveAddressOperator::Add,
+            EffectiveAddressOperand::Register(base),
+        ));
+    }
+    if let Some((index, scale)) = index {
+        raw.push((
+            EffectiveAddressOperator::Add,
+            EffectiveAddressOperand::Register(index),
+        ));
+        if scale != 1 {
+            raw.push((
+                EffectiveAddressOperator::Multiply,
+                EffectiveAddressOperand::Immediate(Immediate(scale)),
+            ));
+        }
+    }
+    // A displacement of 0 is only needed to encode some addresses (e.g. `[ebp]`).
+    match displacement {
+        Some(0) if !raw.is_empty() => (),
+        Some(displacement) => raw.push((
+            EffectiveAddressOperator::Add,
+            EffectiveAddressOperand::Immediate(Immediate(displacement)),
+        )),
+        None => (),
+    }
+    EffectiveAddress::try_from_iter(raw)
+}
+
+/// The register of the given kind which is identified by the reg field of the ModR/M byte.
+fn register(kind: RegisterKind, modrm: &ModRM) -> Result<Register, Error> {
+    let register = match kind {
+        RegisterKind::Sized(size) => modrm.resolve_register(&size),
+        RegisterKind::Segment => match modrm.reg() {
+            0 => Register16::Es.into(),
+            1 => Register16::Cs.into(),
+            2 => Register16::Ss.into(),
+            3 => Register16::Ds.into(),
+            4 => Register16::Fs.into(),
+            5 => Register16::Gs.into(),
+            code => {
+                return Err(Error::CannotDecodeInstruction(format!(
+                    "{code} does not identify a segment register"
+                )))
+            }
+        },
+        RegisterKind::Control => match modrm.reg() {
+            0 => ControlRegister::Cr0.into(),
+            2 => ControlRegister::Cr2.into(),
+            3 => ControlRegister::Cr3.into(),
+            4 => ControlRegister::Cr4.into(),
+            code => {
+                return Err(Error::CannotDecodeInstruction(format!(
+                    "{code} does not identify a control register"
+                )))
+            }
+        },
+        RegisterKind::Debug => [
+            DebugRegister::Dr0,
+            DebugRegister::Dr1,
+            DebugRegister::Dr2,
+            DebugRegister::Dr3,
+            DebugRegister::Dr4,
+            DebugRegister::Dr5,
+            DebugRegister::Dr6,
+            DebugRegister::Dr7,
+        ][modrm.reg() as usize]
+            .clone()
+            .into(),
+    };
+    Ok(register)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::NasmStr;
+
+    fn decode(bytes: &[u8], address: u32) -> (Instruction, usize) {
+        Instruction::decode(bytes, address).unwrap()
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let instructions = [
+            "ret",
+            "ret 8",
+            "push es",
+            "int 0x80",
+            "int3",
+            "aam 10",
+            "add [eax], ebx",
+            "add [eax], bx",
+            "add al, 4",
+            "add ecx, 0x12345678",
+            "add ecx, byte -4",
+            "add dword [ebx], 0x12345678",
+            "sub ecx, [ebp-8]",
+            "mov eax, [esp]",
+            "mov [ebp], al",
+            "mov eax, [ebx+ecx*4+0x100]",
+            "mov eax, [ecx*8]",
+            "mov ecx, [0x1234]",
+            "mov al, [0x1234]",
+            "lock add [eax], ecx",
+            "rep movsb",
+            "movsw",
+            "mov cr0, eax",
+            "addpd xmm1, [eax]",
+            "movdqu xmm0, xmm7",
+            "fstcw [eax]",
+            "jmp 0x10",
+            "je 0x100",
+            "call 0x28:0x1000",
+        ];
+        for text in instructions {
+            let instruction = Instruction::try_from(&NasmStr(text)).unwrap();
+            let bytes = instruction.encode(0x100).unwrap().to_bytes();
+            let (decoded, length) = decode(&bytes, 0x100);
+            assert_eq!(length, bytes.len(), "{text}");
+            assert_eq!(
+                decoded.mnemonic,
+                instruction.mnemonic.to_uppercase(),
+                "{text}"
+            );
+            assert_eq!(decoded.repeat_prefix, instruction.repeat_prefix, "{text}");
+            assert_eq!(decoded.lock_prefix, instruction.lock_prefix, "{text}");
+            assert_eq!(
+                decoded.encode(0x100).unwrap().to_bytes(),
+                bytes,
+                "{text} was decoded as {:?}",
+                decoded.operands.0
+            );
+        }
+    }
+
+    #[test]
+    fn decode_operands() {
+        let (instruction, length) = decode(&[0x8b, 0x44, 0x8b, 0xfc, 0x90], 0);
+        assert_eq!(length, 4);
+        assert_eq!(instruction.mnemonic, "MOV");
+        assert_eq!(
+            instruction.operands.0[0],
+            Operand::try_from(&NasmStr("eax")).unwrap()
+        );
+        assert_eq!(
+            instruction.operands.0[1],
+            Operand::try_from(&NasmStr("dword [ebx+ecx*4-4]")).unwrap()
+        );
+
+        let (instruction, _) = decode(&[0xe8, 0xfb, 0xff, 0xff, 0xff], 0x10);
+        assert_eq!(
+            instruction.operands.0[0].operand_type,
+            OperandType::Immediate(Immediate(0x10))
+        );
+        let (instruction, _) = decode(&[0xf3, 0xa6], 0);
+        assert_eq!(instruction.repeat_prefix, Some(RepeatPrefix::Repe));
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(matches!(
+            Instruction::decode(&[0x81, 0xc1, 0x04], 0),
+            Err(Error::CannotDecodeInstruction(_))
+        ));
+        assert!(matches!(
+            Instruction::decode(&[0x0f, 0x01, 0xd0], 0),
+            Err(Error::CannotDecodeInstruction(_))
+        ));
+        assert!(Instruction::decode(&[0xf0], 0).is_err());
+        assert!(Instruction::decode(&[], 0).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{
+    error::Error,
+    instruction::{self, InstructionDescriptor, OperandType, Size},
+    register::Register,
+};
+use alloc::{format, vec::Vec};
+
+use super::{
+    modrm::ModRM,
+    operand_encodings,
+    sib::{Base, Index, Scale, SIB},
+    Displacement, EncodedInstruction, Immediate, OperandEncoding, Prefix,
+};
+impl instruction::Instruction {
+    /// Encodes the instruction into machine code, as it would be if it were placed at `address`
+    /// (which a relative branch is encoded relative to). A segment-override prefix is encoded on
+    /// its own, as it is parsed as an instruction of its own.
+    pub fn encode(&self, address: u32) -> Result<EncodedInstruction, Error> {
+        let (descriptor, map) = InstructionDescriptor::lookup(&self.mnemonic, &self.operands)?;
+
+        let mut prefixes = Vec::new();
+        if self.lock_prefix {
+            prefixes.push(Prefix::Lock);
+        }
+        if let Some(repeat_prefix) = self.repeat_prefix {
+            prefixes.push(repeat_prefix.into());
+        }
+        if descriptor.needs_operand_size_prefix(map) {
+            prefixes.push(Prefix::OperandSizeOverride);
+        }
+        let mut encoded = EncodedInstruction {
+            prefixes,
+            opcode: descriptor.opcode_bytes(),
+            modrm: None,
+            sib: None,
+            displacement: None,
+            immediates: Vec::new(),
+        };
+
+        // The reg field holds the opcode extension, unless a register is encoded in it.
+        let mut reg = descriptor.opcode_extension.unwrap_or(0);
+        let mut rm = None;
+        let mut relative_target = None;
+        let encodings = operand_encodings(&map.instruction_operand_format)?;
+        for (operand, encoding) in self.operands.0.iter().zip(encodings) {
+            let operand = &operand.operand_type;
+            match encoding {
+                OperandEncoding::Implied(_) | OperandEncoding::Constant(_) => (),
+                OperandEncoding::Reg(_) => reg = operand.unwrap_register().code(),
+                OperandEncoding::Rm(..)
+                | OperandEncoding::Memory(_)
+                | OperandEncoding::RmRegister(_) => rm = Some(operand),
+                OperandEncoding::OpcodeRegister(_) => {
+                    *encoded.opcode.last_mut().unwrap() += operand.unwrap_register().code();
+                }
+                OperandEncoding::Immediate(size) => {
+                    let value = operand.unwrap_immediate().0;
+                    encoded.immediates.push(match size {
+                        Size::Byte => Immediate::One(value as u8),
+                        Size::Word => Immediate::Two(value as u16),
+                        _ => Immediate::Four(value),
+                    });
+                }
+                OperandEncoding::Offset(_) => {
+                    let (_, _, offset) = operand.unwrap_effective_address().components();
+                    encoded.displacement = Some(Displacement::Four(offset.unwrap_or(0)));
+                }
+                OperandEncoding::Relative => relative_target = Some(operand.unwrap_immediate().0),
+                OperandEncoding::FarPointer => {
+                    let far_pointer = <&instruction::FarPointer>::try_from(operand)?;
+                    encoded.immediates.push(Immediate::Four(far_pointer.offset));
+                    encoded
+                        .immediates
+                        .push(Immediate::Two(far_pointer.selector));
+                }
+            }
+        }
+        if let Some(rm) = rm {
+            encode_rm(&mut encoded, reg, rm)?;
+        }
+
+        // A branch is relative to the end of the instruction, so its length must be known first.
+        if let Some(target) = relative_target {
+            encoded.immediates.push(Immediate::Four(0));
+            let next = address.wrapping_add(encoded.len() as u32);
+            *encoded.immediates.last_mut().unwrap() = Immediate::Four(target.wrapping_sub(next));
+        }
+        Ok(encoded)
+    }
+}
+
+/// Encodes a register or memory operand into the r/m field of the ModR/M byte (along with any SIB
+/// byte and displacement), with the given value in the reg field.
+fn encode_rm(encoded: &mut EncodedInstruction, reg: u8, rm: &OperandType) -> Result<(), Error> {
+    let effective_address = match rm {
+        OperandType::Register(register) => {
+            encoded.modrm = Some(ModRM::new(0b11, reg, register.code()));
+            return Ok(());
+        }
+        OperandType::Memory(effective_address) => effective_address,
+        OperandType::Immediate(_) | OperandType::FarPointer(_) => {
+            return Err(Error::CannotEncodeInstruction(
+                "an immediate cannot be encoded as a register or memory operand".into(),
+            ))
+        }
+    };
+
+    let as_32_bit = |register: &Register| match register {
+        Register::Register32(register) => Ok(register.clone()),
+        _ => Err(Error::CannotEncodeInstruction(format!(
+            "only 32-bit registers can be used to address memory (tried to use {register})"
+        ))),
+    };
+    let (base, index, displacement) = effective_address.components();
+    let base = base.map(as_32_bit).transpose()?;
+    let index = match index {
+        Some((register, scale)) => Some((as_32_bit(register)?, Scale::try_from(scale)?)),
+        None => None,
+    };
+    let displacement = displacement.unwrap_or(0);
+
+    // Without a base, the address is encoded with a 32-bit displacement, as mod 00 with a base of
+    // EBP is used for this purpose.
+    let Some(base) = base else {
+        encoded.displacement = Some(Displacement::Four(displacement));
+        match index {
+            Some((index, scale)) => {
+                encoded.modrm = Some(ModRM::new(0b00, reg, 0b100));
+                encoded.sib = Some(SIB::new(
+                    &scale,
+                    &Index::try_from(&index)?,
+                    &Base::DisplacementOnlyOrEbp,
+                ));
+            }
+            None => encoded.modrm = Some(ModRM::new(0b00, reg, 0b101)),
+        }
+        return Ok(());
+    };
+
+    // The shortest displacement is used, although EBP as a base always needs one.
+    let base_code = Register::from(base.clone()).code();
+    let (mode, encoded_displacement) = if displacement == 0 && base_code != 0b101 {
+        (0b00, None)
+    } else if displacement as i32 == displacement as i8 as i32 {
+        (0b01, Some(Displacement::One(displacement as u8)))
+    } else {
+        (0b10, Some(Displacement::Four(displacement)))
+    };
+    encoded.displacement = encoded_displacement;
+
+    // ESP as a base can only be encoded with a SIB byte, as r/m 100 signifies that one follows.
+    match index {
+        None if base_code != 0b100 => encoded.modrm = Some(ModRM::new(mode, reg, base_code)),
+        index => {
+            let (index, scale) = match index {
+                Some((index, scale)) => (Index::try_from(&index)?, scale),
+                None => (Index::None, Scale::One),
+            };
+            encoded.modrm = Some(ModRM::new(mode, reg, 0b100));
+            encoded.sib = Some(SIB::new(&scale, &index, &Base::from(&base)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{Instruction, NasmStr};
+
+    fn encode(instruction: &str, address: u32) -> Vec<u8> {
+        Instruction::try_from(&NasmStr(instruction))
+            .unwrap()
+            .encode(address)
+            .unwrap()
+            .to_bytes()
+    }
+
+    #[test]
+    fn encode_instructions() {
+        let cases: [(&str, &[u8]); 22] = [
+            ("ret", &[0xc3]),
+            ("ret 8", &[0xc2, 0x08, 0x00]),
+            ("push es", &[0x06]),
+            ("int 0x80", &[0xcd, 0x80]),
+            ("add [eax], ebx", &[0x01, 0x18]),
+            ("add [eax], bx", &[0x66, 0x01, 0x18]),
+            ("add al, 4", &[0x04, 0x04]),
+            ("add ecx, 4", &[0x81, 0xc1, 0x04, 0x00, 0x00, 0x00]),
+            ("add ecx, byte 4", &[0x83, 0xc1, 0x04]),
+            (
+                "add dword [ebx], 0x12345678",
+                &[0x81, 0x03, 0x78, 0x56, 0x34, 0x12],
+            ),
+            ("sub ecx, [ebp-8]", &[0x2b, 0x4d, 0xf8]),
+            ("mov eax, [esp]", &[0x8b, 0x04, 0x24]),
+            ("mov [ebp], al", &[0x88, 0x45, 0x00]),
+            (
+                "mov eax, [ebx+ecx*4+0x100]",
+                &[0x8b, 0x84, 0x8b, 0x00, 0x01, 0x00, 0x00],
+            ),
+            (
+                "mov eax, [ecx*8]",
+                &[0x8b, 0x04, 0xcd, 0x00, 0x00, 0x00, 0x00],
+            ),
+            ("mov ecx, [0x1234]", &[0x8b, 0x0d, 0x34, 0x12, 0x00, 0x00]),
+            ("mov al, [0x1234]", &[0xa0, 0x34, 0x12, 0x00, 0x00]),
+            ("lock add [eax], ecx", &[0xf0, 0x01, 0x08]),
+            ("rep movsb", &[0xf3, 0xa4]),
+            ("movsw", &[0x66, 0xa5]),
+            ("mov cr0, eax", &[0x0f, 0x22, 0xc0]),
+            ("addpd xmm1, [eax]", &[0x66, 0x0f, 0x58, 0x08]),
+        ];
+        for (instruction, bytes) in cases {
+            assert_eq!(encode(instruction, 0), bytes, "{instruction}");
+        }
+    }
+
+    #[test]
+    fn encode_relative_branches() {
+        assert_eq!(encode("jmp 0x10", 0), [0xe9, 0x0b, 0x00, 0x00, 0x00]);
+        assert_eq!(encode("call 0", 0x10), [0xe8, 0xeb, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            encode("jz 0x100", 0x100),
+            [0x0f, 0x84, 0xfa, 0xff, 0xff, 0xff]
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{
+    error::Error,
+    instruction::{InstructionOperandFormat, RepeatPrefix, Size},
+    register::{Register, Register16, Register32, Register8},
+};
+use alloc::{format, vec::Vec};
+
+use self::{modrm::ModRM, sib::SIB};
+
+mod decode;
+mod encode;
+pub mod modrm;
+pub mod sib;
+
+/// A legacy prefix, which is shared by the encoder and the decoder. Several prefixes share the same
+/// byte (e.g. `REP` and `REPE`), in which case what they mean depends on the instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prefix {
+    // Group 1: lock and repeat prefixes.
+    Lock,
+    Repne,
+    Repnz,
+    Bnd,
+    Rep,
+    Repe,
+    Repz,
+
+    // Group 2: segment override prefixes and branch hints.
+    CsSegmentOverride,
+    SsSegmentOverride,
+    DsSegmentOverride,
+    EsSegmentOverride,
+    FsSegmentOverride,
+    GsSegmentOverride,
+    BranchTaken,
+    BranchNotTaken,
+
+    // Group 3: operand-size override prefix.
+    OperandSizeOverride,
+
+    // Group 4: address-size override prefix.
+    AddressSizeOverride,
+
+    // 9B may be the wait prefix, but unsure.
+    Other(u8),
+}
+
+impl Prefix {
+    pub fn as_u8(&self) -> u8 {
+        use Prefix::*;
+        match self {
+            Lock => 0xF0,
+            Repne => 0xF2,
+            Repnz => 0xF2,
+            Bnd => 0xF2,
+            Rep => 0xF3,
+            Repe => 0xF3,
+            Repz => 0xF3,
+            CsSegmentOverride => 0x2E,
+            SsSegmentOverride => 0x36,
+            DsSegmentOverride => 0x3E,
+            EsSegmentOverride => 0x26,
+            FsSegmentOverride => 0x64,
+            GsSegmentOverride => 0x65,
+            BranchTaken => 0x2E,
+            BranchNotTaken => 0x3E,
+            OperandSizeOverride => 0x66,
+            AddressSizeOverride => 0x67,
+            Other(n) => *n,
+        }
+    }
+}
+
+impl TryFrom<u8> for Prefix {
+    type Error = Error;
+
+    /// Converts a byte into the prefix that it is most commonly used as, e.g. 0xF3 into `REP`
+    /// rather than `REPE`.
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        use Prefix::*;
+        let prefix = match byte {
+            0xF0 => Lock,
+            0xF2 => Repne,
+            0xF3 => Rep,
+            0x2E => CsSegmentOverride,
+            0x36 => SsSegmentOverride,
+            0x3E => DsSegmentOverride,
+            0x26 => EsSegmentOverride,
+            0x64 => FsSegmentOverride,
+            0x65 => GsSegmentOverride,
+            0x66 => OperandSizeOverride,
+            0x67 => AddressSizeOverride,
+            _ => {
+                return Err(Error::CannotDecodeInstruction(format!(
+                    "{byte:#04x} is not a prefix"
+                )))
+            }
+        };
+        Ok(prefix)
+    }
+}
+
+impl From<RepeatPrefix> for Prefix {
+    fn from(prefix: RepeatPrefix) -> Self {
+        match prefix {
+            RepeatPrefix::Rep => Self::Rep,
+            RepeatPrefix::Repe => Self::Repe,
+            RepeatPrefix::Repne => Self::Repne,
+        }
+    }
+}
+
+/// May be either 1, 2, or 4 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Displacement {
+    One(u8),
+    Two(u16),
+    Four(u32),
+}
+
+/// May be either 1, 2, or 4 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Immediate {
+    One(u8),
+    Two(u16),
+    Four(u32),
+}
+
+/// Implements writing a displacement or immediate in little-endian byte order.
+macro_rules! little_endian {
+    ($name:ident) => {
+        impl $name {
+            fn write(&self, bytes: &mut Vec<u8>) {
+                match self {
+                    Self::One(value) => bytes.push(*value),
+                    Self::Two(value) => bytes.extend(value.to_le_bytes()),
+                    Self::Four(value) => bytes.extend(value.to_le_bytes()),
+                }
+            }
+        }
+    };
+}
+
+little_endian!(Displacement);
+little_endian!(Immediate);
+
+/// An instruction in a format as similar to machine code as possible. Primarily useful for
+/// assembling or disassembling.
+#[derive(Debug)]
+pub struct EncodedInstruction {
+    pub prefixes: Vec<Prefix>,
+    /// The opcode, including any escape bytes (e.g. 0x0F) and mandatory prefixes.
+    pub opcode: Vec<u8>,
+    pub modrm: Option<ModRM>,
+    pub sib: Option<SIB>,
+    pub displacement: Option<Displacement>,
+    pub immediates: Vec<Immediate>,
+}
+
+impl EncodedInstruction {
+    /// The machine code of the instruction.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<_> = self.prefixes.iter().map(Prefix::as_u8).collect();
+        bytes.extend(&self.opcode);
+        bytes.extend(self.modrm.iter().map(ModRM::as_u8));
+        bytes.extend(self.sib.iter().map(SIB::as_u8));
+        if let Some(displacement) = &self.displacement {
+            displacement.write(&mut bytes);
+        }
+        for immediate in &self.immediates {
+            immediate.write(&mut bytes);
+        }
+        bytes
+    }
+
+    /// The number of bytes that the instruction is encoded in.
+    pub fn len(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The kind of register which an operand encoded in a ModR/M byte (or in an opcode) refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RegisterKind {
+    /// A general-purpose register of the given size, or an MMX (`Qword`) or XMM (`Oword`) register,
+    /// as they are numbered in the same way.
+    Sized(Size),
+    Segment,
+    Control,
+    Debug,
+}
+
+/// How an operand of an `InstructionOperandFormat` is encoded, which is shared by the encoder and
+/// the decoder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum OperandEncoding {
+    /// A register which is implied by the opcode, e.g. the `AL` of `ADD AL, imm8`.
+    Implied(Register),
+    /// A constant which is implied by the opcode, e.g. the 1 of `SHL r/m8, 1`.
+    Constant(u32),
+    /// A register in the reg field of the ModR/M byte.
+    Reg(RegisterKind),
+    /// A register or a memory operand of the given size in the r/m field of the ModR/M byte.
+    Rm(RegisterKind, Size),
+    /// A memory operand in the r/m field of the ModR/M byte, which may have a size.
+    Memory(Option<Size>),
+    /// A register in the r/m field of the ModR/M byte, where memory operands are not allowed.
+    RmRegister(RegisterKind),
+    /// A register which is added to the last byte of the opcode, e.g. in `MOV r32, imm32`.
+    OpcodeRegister(RegisterKind),
+    Immediate(Size),
+    /// A memory operand of the given size which is encoded as its 32-bit offset, e.g. in
+    /// `MOV AL, moffs8`.
+    Offset(Size),
+    /// The target of a branch, which is encoded relative to the address of the next instruction.
+    Relative,
+    /// A far pointer, which is encoded as its offset followed by its selector.
+    FarPointer,
+}
+
+/// The encoding of each of the operands of the format, in order. Formats which cannot be encoded
+/// (e.g. 8-bit relative branches) result in an `Err`.
+pub(crate) fn operand_encodings(
+    format: &InstructionOperandFormat,
+) -> Result<&'static [OperandEncoding], Error> {
+    use InstructionOperandFormat as F;
+    use OperandEncoding::*;
+    use RegisterKind::*;
+    use Size::*;
+
+    const AL: OperandEncoding = Implied(Register::Register8(Register8::Al));
+    const CL: OperandEncoding = Implied(Register::Register8(Register8::Cl));
+    const AX: OperandEncoding = Implied(Register::Register16(Register16::Ax));
+    const DX: OperandEncoding = Implied(Register::Register16(Register16::Dx));
+    const EAX: OperandEncoding = Implied(Register::Register32(Register32::Eax));
+    macro_rules! implied {
+        ($register:ident::$name:ident) => {
+            &[Implied(Register::$register($register::$name))]
+        };
+    }
+
+    let encodings: &'static [OperandEncoding] = match format {
+        F::None => &[],
+        F::Eax => &[EAX],
+        F::Ecx => implied!(Register32::Ecx),
+        F::Edx => implied!(Register32::Edx),
+        F::Ebx => implied!(Register32::Ebx),
+        F::Esp => implied!(Register32::Esp),
+        F::Ebp => implied!(Register32::Ebp),
+        F::Esi => implied!(Register32::Esi),
+        F::Edi => implied!(Register32::Edi),
+        F::Ax => &[AX],
+        F::Cx => implied!(Register16::Cx),
+        F::Dx => &[DX],
+        F::Bx => implied!(Register16::Bx),
+        F::Sp => implied!(Register16::Sp),
+        F::Bp => implied!(Register16::Bp),
+        F::Si => implied!(Register16::Si),
+        F::Di => implied!(Register16::Di),
+        F::Cs => implied!(Register16::Cs),
+        F::Ds => implied!(Register16::Ds),
+        F::Es => implied!(Register16::Es),
+        F::Fs => implied!(Register16::Fs),
+        F::Gs => implied!(Register16::Gs),
+        F::Ss => implied!(Register16::Ss),
+        F::Const3 => &[Constant(3)],
+        F::Imm8 => &[Immediate(Byte)],
+        F::Imm16 => &[Immediate(Word)],
+        F::Imm32 => &[Immediate(Dword)],
+        F::Reg16 => &[RmRegister(Sized(Word))],
+        F::Reg32 => &[RmRegister(Sized(Dword))],
+        F::Reg8Imm8 => &[OpcodeRegister(Sized(Byte)), Immediate(Byte)],
+        F::Reg16Imm16 => &[OpcodeRegister(Sized(Word)), Immediate(Word)],
+        F::Reg32Imm32 => &[OpcodeRegister(Sized(Dword)), Immediate(Dword)],
+        F::Rel32 => &[Relative],
+        F::Rm8 => &[Rm(Sized(Byte), Byte)],
+        F::Rm16 => &[Rm(Sized(Word), Word)],
+        F::Rm32 => &[Rm(Sized(Dword), Dword)],
+        F::Reg8Rm8 => &[Reg(Sized(Byte)), Rm(Sized(Byte), Byte)],
+        F::Reg16Rm16 => &[Reg(Sized(Word)), Rm(Sized(Word), Word)],
+        F::Reg32Rm32 => &[Reg(Sized(Dword)), Rm(Sized(Dword), Dword)],
+        F::Rm8Reg8 => &[Rm(Sized(Byte), Byte), Reg(Sized(Byte))],
+        F::Rm16Reg16 => &[Rm(Sized(Word), Word), Reg(Sized(Word))],
+        F::Rm32Reg32 => &[Rm(Sized(Dword), Dword), Reg(Sized(Dword))],
+        F::Rm16Sreg => &[Rm(Sized(Word), Word), Reg(Segment)],
+        F::Rm32Sreg => &[Rm(Sized(Dword), Word), Reg(Segment)],
+        F::Rm8Imm8 => &[Rm(Sized(Byte), Byte), Immediate(Byte)],
+        F::Rm16Imm16 => &[Rm(Sized(Word), Word), Immediate(Word)],
+        F::Rm16Imm8 => &[Rm(Sized(Word), Word), Immediate(Byte)],
+        F::Rm32Imm8 => &[Rm(Sized(Dword), Dword), Immediate(Byte)],
+        F::Rm32Imm32 => &[Rm(Sized(Dword), Dword), Immediate(Dword)],
+        F::Reg16Rm16Imm8 => &[Reg(Sized(Word)), Rm(Sized(Word), Word), Immediate(Byte)],
+        F::Reg16Rm16Imm16 => &[Reg(Sized(Word)), Rm(Sized(Word), Word), Immediate(Word)],
+        F::Reg32Rm32Imm8 => &[Reg(Sized(Dword)), Rm(Sized(Dword), Dword), Immediate(Byte)],
+        F::Reg32Rm32Imm32 => &[Reg(Sized(Dword)), Rm(Sized(Dword), Dword), Immediate(Dword)],
+        F::Mem => &[Memory(None)],
+        F::Mem16 => &[Memory(Some(Word))],
+        F::Reg16Mem => &[Reg(Sized(Word)), Memory(None)],
+        F::Reg32Mem => &[Reg(Sized(Dword)), Memory(None)],
+        F::SregRm16 => &[Reg(Segment), Rm(Sized(Word), Word)],
+        F::SregRm32 => &[Reg(Segment), Rm(Sized(Dword), Word)],
+        F::Rm8Const1 => &[Rm(Sized(Byte), Byte), Constant(1)],
+        F::Rm16Const1 => &[Rm(Sized(Word), Word), Constant(1)],
+        F::Rm32Const1 => &[Rm(Sized(Dword), Dword), Constant(1)],
+        F::Rm8Cl => &[Rm(Sized(Byte), Byte), CL],
+        F::Rm16Cl => &[Rm(Sized(Word), Word), CL],
+        F::Rm32Cl => &[Rm(Sized(Dword), Dword), CL],
+        F::Reg32Cr => &[RmRegister(Sized(Dword)), Reg(Control)],
+        F::Reg32Dr => &[RmRegister(Sized(Dword)), Reg(Debug)],
+        F::CrReg32 => &[Reg(Control), RmRegister(Sized(Dword))],
+        F::DrReg32 => &[Reg(Debug), RmRegister(Sized(Dword))],
+        F::MmRm32 => &[Reg(Sized(Qword)), Rm(Sized(Dword), Dword)],
+        F::Rm32Mm => &[Rm(Sized(Dword), Dword), Reg(Sized(Qword))],
+        F::MmMm64 => &[Reg(Sized(Qword)), Rm(Sized(Qword), Qword)],
+        F::Mm64Mm => &[Rm(Sized(Qword), Qword), Reg(Sized(Qword))],
+        F::XmmXmm128 => &[Reg(Sized(Oword)), Rm(Sized(Oword), Oword)],
+        F::Xmm128Xmm => &[Rm(Sized(Oword), Oword), Reg(Sized(Oword))],
+        F::XmmXmm128Imm8 => &[Reg(Sized(Oword)), Rm(Sized(Oword), Oword), Immediate(Byte)],
+        F::XmmXmm64 => &[Reg(Sized(Oword)), Rm(Sized(Oword), Qword)],
+        F::Xmm64Xmm => &[Rm(Sized(Oword), Qword), Reg(Sized(Oword))],
+        F::Reg16Rm8 => &[Reg(Sized(Word)), Rm(Sized(Byte), Byte)],
+        F::Reg32Rm8 => &[Reg(Sized(Dword)), Rm(Sized(Byte), Byte)],
+        F::Reg32Rm16 => &[Reg(Sized(Dword)), Rm(Sized(Word), Word)],
+        F::Rm16Reg16Imm8 => &[Rm(Sized(Word), Word), Reg(Sized(Word)), Immediate(Byte)],
+        F::Rm32Reg32Imm8 => &[Rm(Sized(Dword), Dword), Reg(Sized(Dword)), Immediate(Byte)],
+        F::Rm16Reg16Cl => &[Rm(Sized(Word), Word), Reg(Sized(Word)), CL],
+        F::Rm32Reg32Cl => &[Rm(Sized(Dword), Dword), Reg(Sized(Dword)), CL],
+        F::AlImm8 => &[AL, Immediate(Byte)],
+        F::AxImm16 => &[AX, Immediate(Word)],
+        F::EaxImm32 => &[EAX, Immediate(Dword)],
+        F::Imm16Imm16 => &[Immediate(Word), Immediate(Word)],
+        F::Imm16Imm32 => &[Immediate(Word), Immediate(Dword)],
+        F::AxReg16 => &[AX, OpcodeRegister(Sized(Word))],
+        F::EaxReg32 => &[EAX, OpcodeRegister(Sized(Dword))],
+        F::AxImm8 => &[AX, Immediate(Byte)],
+        F::EaxImm8 => &[EAX, Immediate(Byte)],
+        F::AlMoffs8 => &[AL, Offset(Byte)],
+        F::AxMoffs16 => &[AX, Offset(Word)],
+        F::EaxMoffs32 => &[EAX, Offset(Dword)],
+        F::Moffs8Al => &[Offset(Byte), AL],
+        F::Moffs16Ax => &[Offset(Word), AX],
+        F::Moffs32Eax => &[Offset(Dword), EAX],
+        F::AlDx => &[AL, DX],
+        F::AxDx => &[AX, DX],
+        F::EaxDx => &[EAX, DX],
+        F::DxAl => &[DX, AL],
+        F::DxAx => &[DX, AX],
+        F::DxEax => &[DX, EAX],
+        F::Imm8Al => &[Immediate(Byte), AL],
+        F::Imm8Ax => &[Immediate(Byte), AX],
+        F::Imm8Eax => &[Immediate(Byte), EAX],
+        F::Imm8Imm16 => &[Immediate(Byte), Immediate(Word)],
+        F::Far32 => &[FarPointer],
+        F::Rel8 | F::Rel16 | F::Far16 | F::Reg8Cl => {
+            return Err(Error::CannotEncodeInstruction(format!(
+                "operands of the form {format:?} cannot be encoded"
+            )))
+        }
+    };
+    Ok(encodings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_from_u8() {
+        for byte in [
+            0xF0, 0xF2, 0xF3, 0x2E, 0x36, 0x3E, 0x26, 0x64, 0x65, 0x66, 0x67,
+        ] {
+            assert_eq!(Prefix::try_from(byte).unwrap().as_u8(), byte);
+        }
+        assert_eq!(Prefix::try_from(0xF3).unwrap(), Prefix::Rep);
+        assert_eq!(Prefix::from(RepeatPrefix::Repe).as_u8(), 0xF3);
+        assert!(Prefix::try_from(0x90).is_err());
+    }
+
+    #[test]
+    fn encoded_instruction_to_bytes() {
+        let encoded = EncodedInstruction {
+            prefixes: vec![Prefix::Lock],
+            opcode: vec![0x81],
+            modrm: Some(ModRM::new(0b01, 0, 0b100)),
+            sib: Some(SIB::from(0b10_001_000)),
+            displacement: Some(Displacement::One(0xfc)),
+            immediates: vec![Immediate::Four(0x12345678)],
+        };
+        assert_eq!(
+            encoded.to_bytes(),
+            [0xf0, 0x81, 0x44, 0x88, 0xfc, 0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(encoded.len(), 9);
+    }
+}
@@ -2,7 +2,7 @@ use bitmaps::Bitmap;
 
 use crate::{
     instruction::Size,
-    register::{Register, Register16, Register32, Register8},
+    register::{MmxRegister, Register, Register16, Register32, Register8, XmmRegister},
 };
 
 ///  Intel manual section 2.1.
@@ -18,19 +18,42 @@ use crate::{
 ///   - 10: Four-byte signed displacement follows addressing mode bytes(s).
 ///   - 11: Register addressing mode.
 ///
-/// REG     8-bit       16-bit      32-bit
-/// 000     al          ax          eax
-/// 001     cl          cx          ecx
-/// 010     dl          dx          edx
-/// 011     bl          bx          ebx
-/// 100     ah          sp          esp
-/// 101     ch          bp          ebp
-/// 110     dh          si          esi
-/// 111     bh          di          edi
+/// REG     8-bit       16-bit      32-bit      64-bit (MMX)    128-bit (SSE)
+/// 000     al          ax          eax         mm0             xmm0
+/// 001     cl          cx          ecx         mm1             xmm1
+/// 010     dl          dx          edx         mm2             xmm2
+/// 011     bl          bx          ebx         mm3             xmm3
+/// 100     ah          sp          esp         mm4             xmm4
+/// 101     ch          bp          ebp         mm5             xmm5
+/// 110     dh          si          esi         mm6             xmm6
+/// 111     bh          di          edi         mm7             xmm7
 #[derive(Debug, Default)]
 pub struct ModRM(Bitmap<8>);
 
 impl ModRM {
+    /// Creates a ModR/M byte from its fields, of which only the low bits are used.
+    pub fn new(mode: u8, reg: u8, rm: u8) -> Self {
+        Self(Bitmap::from_value(
+            (mode & 0b11) << 6 | (reg & 0b111) << 3 | rm & 0b111,
+        ))
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.0.into_value()
+    }
+
+    pub fn mode(&self) -> u8 {
+        self.as_u8() >> 6
+    }
+
+    pub fn reg(&self) -> u8 {
+        self.as_u8() >> 3 & 0b111
+    }
+
+    pub fn rm(&self) -> u8 {
+        self.as_u8() & 0b111
+    }
+
     pub fn resolve_register(&self, size: &Size) -> Register {
         use Size::*;
         match (self.0.get(5), self.0.get(4), self.0.get(3)) {
@@ -38,46 +61,68 @@ impl ModRM {
                 Byte => Register8::Al.into(),
                 Word => Register16::Ax.into(),
                 Dword => Register32::Eax.into(),
+                Qword => MmxRegister::Mm0.into(),
+                Oword => XmmRegister::Xmm0.into(),
             },
             (false, false, true) => match size {
                 Byte => Register8::Cl.into(),
                 Word => Register16::Cx.into(),
                 Dword => Register32::Ecx.into(),
+                Qword => MmxRegister::Mm1.into(),
+                Oword => XmmRegister::Xmm1.into(),
             },
             (false, true, false) => match size {
                 Byte => Register8::Dl.into(),
                 Word => Register16::Dx.into(),
                 Dword => Register32::Edx.into(),
+                Qword => MmxRegister::Mm2.into(),
+                Oword => XmmRegister::Xmm2.into(),
             },
             (false, true, true) => match size {
                 Byte => Register8::Bl.into(),
                 Word => Register16::Bx.into(),
                 Dword => Register32::Ebx.into(),
+                Qword => MmxRegister::Mm3.into(),
+                Oword => XmmRegister::Xmm3.into(),
             },
             (true, false, false) => match size {
                 Byte => Register8::Ah.into(),
                 Word => Register16::Sp.into(),
                 Dword => Register32::Esp.into(),
+                Qword => MmxRegister::Mm4.into(),
+                Oword => XmmRegister::Xmm4.into(),
             },
             (true, false, true) => match size {
                 Byte => Register8::Ch.into(),
                 Word => Register16::Bp.into(),
                 Dword => Register32::Ebp.into(),
+                Qword => MmxRegister::Mm5.into(),
+                Oword => XmmRegister::Xmm5.into(),
             },
             (true, true, false) => match size {
                 Byte => Register8::Dh.into(),
                 Word => Register16::Si.into(),
                 Dword => Register32::Esi.into(),
+                Qword => MmxRegister::Mm6.into(),
+                Oword => XmmRegister::Xmm6.into(),
             },
             (true, true, true) => match size {
                 Byte => Register8::Bh.into(),
                 Word => Register16::Di.into(),
                 Dword => Register32::Edi.into(),
+                Qword => MmxRegister::Mm7.into(),
+                Oword => XmmRegister::Xmm7.into(),
             },
         }
     }
 }
 
+impl From<u8> for ModRM {
+    fn from(byte: u8) -> Self {
+        Self(Bitmap::from_value(byte))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,6 +171,13 @@ mod tests {
         reg_111.set(4, true);
         reg_111.set(5, true);
 
+        let modrm = ModRM::new(0b01, 0b011, 0b100);
+        assert_eq!(modrm.as_u8(), 0b01_011_100);
+        assert_eq!(
+            (modrm.mode(), modrm.reg(), modrm.rm()),
+            (0b01, 0b011, 0b100)
+        );
+
         let mut modrm = ModRM::default();
 
         modrm.0 = reg_000;
@@ -1,5 +1,8 @@
+use alloc::format;
 use bitmaps::Bitmap;
 
+use crate::{error::Error, register::Register32};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Scale {
     One = 0b00,
@@ -8,17 +11,78 @@ pub enum Scale {
     Eight = 0b11,
 }
 
+impl Scale {
+    /// The factor which the index is multiplied by.
+    pub fn factor(&self) -> u32 {
+        1 << *self as u32
+    }
+}
+
+impl TryFrom<u32> for Scale {
+    type Error = Error;
+
+    /// Converts the factor which an index is multiplied by into a scale.
+    fn try_from(factor: u32) -> Result<Self, Self::Error> {
+        match factor {
+            1 => Ok(Self::One),
+            2 => Ok(Self::Two),
+            4 => Ok(Self::Four),
+            8 => Ok(Self::Eight),
+            _ => Err(Error::InvalidEffectiveAddress(format!(
+                "the scale of an index must be 1, 2, 4, or 8 (was {factor})"
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Index {
     Eax = 0b000,
     Ecx = 0b001,
     Edx = 0b010,
     Ebx = 0b011,
+    /// There is no index, which is the encoding that would otherwise refer to ESP.
+    None = 0b100,
     Ebp = 0b101,
     Esi = 0b110,
     Edi = 0b111,
 }
 
+impl Index {
+    /// The register which is used as the index, if there is one.
+    pub fn register(&self) -> Option<Register32> {
+        match self {
+            Self::Eax => Some(Register32::Eax),
+            Self::Ecx => Some(Register32::Ecx),
+            Self::Edx => Some(Register32::Edx),
+            Self::Ebx => Some(Register32::Ebx),
+            Self::None => None,
+            Self::Ebp => Some(Register32::Ebp),
+            Self::Esi => Some(Register32::Esi),
+            Self::Edi => Some(Register32::Edi),
+        }
+    }
+}
+
+impl TryFrom<&Register32> for Index {
+    type Error = Error;
+
+    fn try_from(register: &Register32) -> Result<Self, Self::Error> {
+        match register {
+            Register32::Eax => Ok(Self::Eax),
+            Register32::Ecx => Ok(Self::Ecx),
+            Register32::Edx => Ok(Self::Edx),
+            Register32::Ebx => Ok(Self::Ebx),
+            Register32::Esp => Err(Error::InvalidEffectiveAddress(
+                "ESP cannot be used as an index".into(),
+            )),
+            Register32::Ebp => Ok(Self::Ebp),
+            Register32::Esi => Ok(Self::Esi),
+            Register32::Edi => Ok(Self::Edi),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Base {
     Eax = 0b000,
@@ -31,16 +95,53 @@ pub enum Base {
     Edi = 0b111,
 }
 
+impl Base {
+    /// The register which is used as the base. `DisplacementOnlyOrEbp` only refers to EBP when the
+    /// mod field of the ModR/M byte is not 00, which is left for the caller to check.
+    pub fn register(&self) -> Register32 {
+        match self {
+            Self::Eax => Register32::Eax,
+            Self::Ecx => Register32::Ecx,
+            Self::Edx => Register32::Edx,
+            Self::Ebx => Register32::Ebx,
+            Self::Esp => Register32::Esp,
+            Self::DisplacementOnlyOrEbp => Register32::Ebp,
+            Self::Esi => Register32::Esi,
+            Self::Edi => Register32::Edi,
+        }
+    }
+}
+
+impl From<&Register32> for Base {
+    fn from(register: &Register32) -> Self {
+        match register {
+            Register32::Eax => Self::Eax,
+            Register32::Ecx => Self::Ecx,
+            Register32::Edx => Self::Edx,
+            Register32::Ebx => Self::Ebx,
+            Register32::Esp => Self::Esp,
+            Register32::Ebp => Self::DisplacementOnlyOrEbp,
+            Register32::Esi => Self::Esi,
+            Register32::Edi => Self::Edi,
+        }
+    }
+}
+
 ///   7   6   5   4   3   2   1   0
 /// +---+---+---+---+---+---+---+---+
 /// | scale |   index   |    base   |
 /// +---+---+---+---+---+---+---+---+
 /// http://www.c-jump.com/CIS77/CPU/x86/X77_0100_sib_byte_layout.htm
 // TODO: Are the values encoded left-to-right or right-to-left?
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct SIB(Bitmap<8>);
 
-// TODO: Tests
+impl From<u8> for SIB {
+    fn from(byte: u8) -> Self {
+        Self(Bitmap::from_value(byte))
+    }
+}
+
 impl SIB {
     pub fn new(scale: &Scale, index: &Index, base: &Base) -> Self {
         let mut sib = SIB::default();
@@ -50,6 +151,10 @@ impl SIB {
         sib
     }
 
+    pub fn as_u8(&self) -> u8 {
+        self.0.into_value()
+    }
+
     pub fn get_scale(&self) -> Scale {
         match (self.0.get(7), self.0.get(6)) {
             (false, false) => Scale::One,
@@ -86,7 +191,7 @@ impl SIB {
             (false, false, true) => Index::Ecx,
             (false, true, false) => Index::Edx,
             (false, true, true) => Index::Ebx,
-            (true, false, false) => unreachable!(),
+            (true, false, false) => Index::None,
             (true, false, true) => Index::Ebp,
             (true, true, false) => Index::Esi,
             (true, true, true) => Index::Edi,
@@ -99,6 +204,7 @@ impl SIB {
             Index::Ecx => (false, false, true),
             Index::Edx => (false, true, false),
             Index::Ebx => (false, true, true),
+            Index::None => (true, false, false),
             Index::Ebp => (true, false, true),
             Index::Esi => (true, true, false),
             Index::Edi => (true, true, true),
@@ -148,6 +254,25 @@ mod tests {
         assert_eq!(sib.get_scale(), Scale::Two);
         assert_eq!(sib.get_index(), Index::Ecx);
         assert_eq!(sib.get_base(), Base::Edx);
+        assert_eq!(sib.as_u8(), 0b01_001_010);
+    }
+
+    #[test]
+    fn from_u8() {
+        let sib = SIB::from(0b11_100_101);
+        assert_eq!(sib.get_scale(), Scale::Eight);
+        assert_eq!(sib.get_scale().factor(), 8);
+        assert_eq!(sib.get_index(), Index::None);
+        assert_eq!(sib.get_index().register(), None);
+        assert_eq!(sib.get_base(), Base::DisplacementOnlyOrEbp);
+        assert_eq!(sib.get_base().register(), Register32::Ebp);
+        assert_eq!(sib.as_u8(), 0b11_100_101);
+
+        for byte in 0..=u8::MAX {
+            let sib = SIB::from(byte);
+            let copy = SIB::new(&sib.get_scale(), &sib.get_index(), &sib.get_base());
+            assert_eq!(copy.as_u8(), byte);
+        }
     }
 
     #[test]
@@ -163,6 +288,7 @@ mod tests {
         assert_eq!(sib.get_scale(), Scale::One);
     }
 
+    #[test]
     fn index() {
         let mut sib = SIB::default();
         sib.set_index(&Index::Edi);
@@ -179,8 +305,11 @@ mod tests {
         assert_eq!(sib.get_index(), Index::Ecx);
         sib.set_index(&Index::Eax);
         assert_eq!(sib.get_index(), Index::Eax);
+        sib.set_index(&Index::None);
+        assert_eq!(sib.get_index(), Index::None);
     }
 
+    #[test]
     fn base() {
         let mut sib = SIB::default();
         sib.set_base(&Base::Edi);
@@ -1,20 +1,64 @@
+use alloc::{boxed::Box, string::String};
 use thiserror::Error;
 
+use crate::{diagnostic::Diagnostic, instruction::Size, register::Register};
+
 #[non_exhaustive]
 #[derive(Clone, Debug, Error)]
 pub enum Error {
+    #[error("assertion failed: {0}")]
+    AssertionFailed(String),
     #[error("multiple matching instructions were found: {0}")]
     AmbiguousInstruction(String),
     #[error("could not convert type: {0}")]
     CannotCovertType(String),
+    #[error("instruction could not be decoded: {0}")]
+    CannotDecodeInstruction(String),
+    #[error("instruction could not be encoded: {0}")]
+    CannotEncodeInstruction(String),
     #[error("instruction could not be parsed: {0}")]
     CannotParseInstruction(String),
+    #[error("{0}")]
+    Diagnostic(Box<Diagnostic>),
     #[error("invalid effective address: {0}")]
     InvalidEffectiveAddress(String),
     #[error("inaccessible address: {0}")]
     InaccessibleAddress(String),
     #[error("invalid operand type: {0}")]
     InvalidOperandType(String),
+    #[error("invalid symbol: {0}")]
+    InvalidSymbol(String),
     #[error("no matching instruction could be found: {0}")]
     NoMatchingInstruction(String),
+    #[error("I/O port conflict: {0}")]
+    PortConflict(String),
+    #[error("protection violation: {0}")]
+    ProtectionViolation(String),
+    #[error("stack overflow: {0}")]
+    StackOverflow(String),
+    #[error("execution timed out: {0}")]
+    Timeout(String),
+}
+
+/// A problem with a program which does not prevent it from being assembled, but which likely
+/// indicates a mistake.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum Warning {
+    #[error("the {size} size directive is ignored, as {register} is a {} register", .register.size())]
+    IgnoredSizeDirective { size: Size, register: Register },
+    /// The immediate operand at the given index does not fit within the size that the instruction
+    /// uses it as, so only its low bits are used. An immediate which is sign-extended (e.g. in
+    /// `ADD r/m32, imm8`) must fit within the signed range of the size.
+    #[error(
+        "{} does not fit in a {}{size}, and is truncated",
+        *.value as i32,
+        if *.sign_extended { "signed " } else { "" }
+    )]
+    ImmediateOutOfRange {
+        operand: usize,
+        value: u32,
+        size: Size,
+        sign_extended: bool,
+    },
 }
@@ -0,0 +1,279 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::iter::Peekable;
+
+use crate::{
+    error::Error,
+    instruction::Immediate,
+    lexer::{self, Token, TokenKind},
+};
+
+/// The operators which may be used in a constant expression, with the longest first such that
+/// e.g. `<<` is never mistaken for `<`.
+const OPERATORS: [&str; 11] = ["<<", ">>", "|", "^", "&", "+", "-", "*", "/", "%", "~"];
+
+/// Whether the character begins one of the operators which may be used in a constant expression.
+pub fn is_operator(c: char) -> bool {
+    OPERATORS.iter().any(|operator| operator.starts_with(c))
+}
+
+/// The location counter, which is written as `$` for the address of the start of the current line,
+/// and as `$$` for the address of the start of the current section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub here: u32,
+    pub start: u32,
+}
+
+/// Replaces each use of the location counter (`$` and `$$`) in `expression` with its value, such
+/// that the expression can be evaluated. A `$` which is part of a symbol name (e.g. `a$b`) is left
+/// untouched.
+pub fn substitute_location(expression: &str, location: Location) -> String {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(expression.len());
+    let mut chars = expression.chars().peekable();
+    let mut previous = None;
+    while let Some(c) = chars.next() {
+        if c != '$' || previous.is_some_and(is_word_char) {
+            result.push(c);
+            previous = Some(c);
+            continue;
+        }
+
+        let mut run = String::from(c);
+        while let Some(c) = chars.next_if_eq(&'$') {
+            run.push(c);
+        }
+        previous = Some('$');
+
+        match run.as_str() {
+            _ if chars.peek().is_some_and(|&c| is_word_char(c)) => result.push_str(&run),
+            "$" => result.push_str(&location.here.to_string()),
+            "$$" => result.push_str(&location.start.to_string()),
+            _ => result.push_str(&run),
+        }
+    }
+    result
+}
+
+/// Evaluates a constant expression (e.g. `(3+4)*8` or `1 << 12`), which is made up of numeric
+/// literals, parentheses, and operators. From the lowest precedence to the highest, the binary
+/// operators are:
+///
+/// - `|` (bitwise OR)
+/// - `^` (bitwise XOR)
+/// - `&` (bitwise AND)
+/// - `<<` and `>>` (logical shifts)
+/// - `+` and `-`
+/// - `*`, `/`, and `%` (unsigned division and modulo)
+///
+/// Operators of the same precedence are evaluated from left to right. The unary operators `-`,
+/// `+`, and `~` (bitwise NOT) take precedence over all of them. As in NASM, the expression is
+/// evaluated with 64-bit arithmetic which wraps around, and the result is then truncated.
+///
+/// Symbols and the location counter must have already been replaced with their values, as this
+/// only knows about numbers.
+pub fn evaluate(expression: &str) -> Result<u32, Error> {
+    evaluate_tokens(&lexer::tokenize(expression)?)
+}
+
+/// Evaluates a constant expression which has already been split into tokens. See [`evaluate`].
+pub(crate) fn evaluate_tokens(tokens: &[Token]) -> Result<u32, Error> {
+    evaluate_tokens64(tokens).map(|value| value as u32)
+}
+
+/// Evaluates a constant expression like [`evaluate`], but without truncating the result to 32
+/// bits, e.g. for a `dq` data item.
+pub(crate) fn evaluate64(expression: &str) -> Result<u64, Error> {
+    evaluate_tokens64(&lexer::tokenize(expression)?)
+}
+
+fn evaluate_tokens64(tokens: &[Token]) -> Result<u64, Error> {
+    let mut tokens = tokens.iter().map(|token| token.kind).peekable();
+    let value = parse_binary(&mut tokens, 0)?;
+    match tokens.next() {
+        None => Ok(value),
+        Some(token) => Err(Error::CannotParseInstruction(format!(
+            "unexpected {token:?} in a constant expression"
+        ))),
+    }
+}
+
+/// Whether the tokens form an expression, in which symbols may be used in place of numbers. This
+/// only checks the syntax of the expression, as the values of symbols may not yet be known.
+pub(crate) fn is_well_formed(tokens: &[Token]) -> bool {
+    let mut depth = 0usize;
+    // Whether a number (or parenthesised expression) is expected next, rather than an operator.
+    let mut expecting_operand = true;
+    for token in tokens {
+        match (expecting_operand, token.kind) {
+            (true, TokenKind::Number(_) | TokenKind::Identifier(_)) => expecting_operand = false,
+            (true, TokenKind::Punctuation("(")) => depth += 1,
+            (true, TokenKind::Punctuation("-" | "+" | "~")) => (),
+            (false, TokenKind::Punctuation(")")) if depth > 0 => depth -= 1,
+            (false, TokenKind::Punctuation(operator)) if precedence(operator).is_some() => {
+                expecting_operand = true
+            }
+            _ => return false,
+        }
+    }
+    !expecting_operand && depth == 0
+}
+
+/// The precedence of a binary operator, where operators with a higher precedence are evaluated
+/// first. Returns `None` if the operator is only unary.
+fn precedence(operator: &str) -> Option<u8> {
+    match operator {
+        "|" => Some(0),
+        "^" => Some(1),
+        "&" => Some(2),
+        "<<" | ">>" => Some(3),
+        "+" | "-" => Some(4),
+        "*" | "/" | "%" => Some(5),
+        _ => None,
+    }
+}
+
+/// Parses a sequence of unary expressions separated by binary operators, stopping at the first
+/// operator whose precedence is lower than `minimum_precedence`.
+fn parse_binary<'a, I>(tokens: &mut Peekable<I>, minimum_precedence: u8) -> Result<u64, Error>
+where
+    I: Iterator<Item = TokenKind<'a>>,
+{
+    let mut value = parse_unary(tokens)?;
+    while let Some(&TokenKind::Punctuation(operator)) = tokens.peek() {
+        let Some(precedence) = precedence(operator).filter(|p| *p >= minimum_precedence) else {
+            break;
+        };
+        tokens.next();
+        // Operators are left-associative, so the right-hand side may only contain operators which
+        // take precedence over this one.
+        let rhs = parse_binary(tokens, precedence + 1)?;
+        value = match operator {
+            "|" => value | rhs,
+            "^" => value ^ rhs,
+            "&" => value & rhs,
+            "<<" => u32::try_from(rhs)
+                .ok()
+                .and_then(|rhs| value.checked_shl(rhs))
+                .unwrap_or(0),
+            ">>" => u32::try_from(rhs)
+                .ok()
+                .and_then(|rhs| value.checked_shr(rhs))
+                .unwrap_or(0),
+            "+" => value.wrapping_add(rhs),
+            "-" => value.wrapping_sub(rhs),
+            "*" => value.wrapping_mul(rhs),
+            _ if rhs == 0 => {
+                return Err(Error::CannotParseInstruction(
+                    "division by zero in a constant expression".into(),
+                ))
+            }
+            "/" => value / rhs,
+            _ => value % rhs,
+        };
+    }
+    Ok(value)
+}
+
+/// Parses a number or a parenthesised expression, which may be preceded by any number of unary
+/// operators.
+fn parse_unary<'a, I>(tokens: &mut Peekable<I>) -> Result<u64, Error>
+where
+    I: Iterator<Item = TokenKind<'a>>,
+{
+    match tokens.next() {
+        Some(TokenKind::Number(number)) => Ok(Immediate::parse_literal(number)?.0.into()),
+        Some(TokenKind::Punctuation("(")) => {
+            let value = parse_binary(tokens, 0)?;
+            match tokens.next() {
+                Some(TokenKind::Punctuation(")")) => Ok(value),
+                _ => Err(Error::CannotParseInstruction(
+                    "expected \")\" in a constant expression".into(),
+                )),
+            }
+        }
+        Some(TokenKind::Punctuation("-")) => Ok(parse_unary(tokens)?.wrapping_neg()),
+        Some(TokenKind::Punctuation("+")) => parse_unary(tokens),
+        Some(TokenKind::Punctuation("~")) => Ok(!parse_unary(tokens)?),
+        Some(TokenKind::Identifier(name)) => Err(Error::CannotParseInstruction(format!(
+            "\"{name}\" cannot be used in a constant expression, as it is not a number"
+        ))),
+        Some(token) => Err(Error::CannotParseInstruction(format!(
+            "expected a number in a constant expression, found {token:?}"
+        ))),
+        None => Err(Error::CannotParseInstruction(
+            "a constant expression ended unexpectedly".into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_expression() {
+        assert_eq!(evaluate("4*2+1").unwrap(), 9);
+        assert_eq!(evaluate("1 + 2 * 3 - 4").unwrap(), 3);
+        assert_eq!(evaluate("0x10/3").unwrap(), 5);
+        assert_eq!(evaluate("0x10 % 3").unwrap(), 1);
+        assert_eq!(evaluate("-1").unwrap(), 0xffff_ffff);
+        assert_eq!(evaluate("2*-3").unwrap(), (-6i32) as u32);
+        assert_eq!(evaluate("1-2").unwrap(), 0xffff_ffff);
+        assert_eq!(evaluate("10h+0b1").unwrap(), 0x11);
+
+        assert_eq!(evaluate("(3+4)*8").unwrap(), 56);
+        assert_eq!(evaluate("1 << 12").unwrap(), 0x1000);
+        assert_eq!(evaluate("1 << 4 + 1").unwrap(), 0x20);
+        assert_eq!(evaluate("0xf0 >> 4 | 1 << 8").unwrap(), 0x10f);
+        assert_eq!(evaluate("0xff & ~0xf ^ 1").unwrap(), 0xf1);
+        assert_eq!(evaluate("6 & 3 | 8").unwrap(), 0xa);
+        assert_eq!(evaluate("16 - 4 - 2").unwrap(), 10);
+        assert_eq!(evaluate("1 << 32").unwrap(), 0);
+        assert_eq!(evaluate("1 << 64").unwrap(), 0);
+        assert_eq!(evaluate("-4 / 2").unwrap(), 0xffff_fffe);
+        assert_eq!(evaluate("-(1+(2*3))").unwrap(), (-7i32) as u32);
+
+        assert!(evaluate("(1").is_err());
+        assert!(evaluate("1)").is_err());
+        assert!(evaluate("$").is_err());
+        assert!(evaluate("1/0").is_err());
+        assert!(evaluate("1+").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("1 < 2").is_err());
+        assert!(evaluate("1 ~ 2").is_err());
+        assert!(evaluate("eax+1").is_err());
+        assert!(evaluate("[1]").is_err());
+
+        assert_eq!(evaluate64("-1").unwrap(), u64::MAX);
+        assert_eq!(evaluate64("0xffffffff + 1").unwrap(), 1 << 32);
+    }
+
+    #[test]
+    fn well_formed_expression() {
+        let is_well_formed = |text| is_well_formed(&lexer::tokenize(text).unwrap());
+        assert!(is_well_formed("1"));
+        assert!(is_well_formed("SIZE / 2"));
+        assert!(is_well_formed("-(a + ~(b << 2)) * 3"));
+        assert!(!is_well_formed(""));
+        assert!(!is_well_formed("1 +"));
+        assert!(!is_well_formed("(1"));
+        assert!(!is_well_formed("1)"));
+        assert!(!is_well_formed("4 db"));
+        assert!(!is_well_formed("[eax]"));
+    }
+
+    #[test]
+    fn substitute_location_counter() {
+        let location = Location {
+            here: 0x10,
+            start: 4,
+        };
+        assert_eq!(substitute_location("$", location), "16");
+        assert_eq!(substitute_location("510-($-$$)", location), "510-(16-4)");
+        assert_eq!(substitute_location("a$ + $$b", location), "a$ + $$b");
+    }
+}
@@ -0,0 +1,224 @@
+use bitmaps::Bitmap;
+
+/// Intel manual section 8.1.5 "x87 FPU Control Word", field RC (bits 10 and 11). Controls how the
+/// results of x87 FPU floating-point instructions are rounded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Rounded result is the closest to the infinitely precise result. If two values are equally
+    /// close, the result is the even value (that is, the one with the least-significant bit of
+    /// zero).
+    Nearest = 0,
+    /// Rounded result is closest to but no greater than the infinitely precise result.
+    Down = 1,
+    /// Rounded result is closest to but no less than the infinitely precise result.
+    Up = 2,
+    /// Rounded result is closest to but no greater in absolute value than the infinitely precise
+    /// result.
+    TowardZero = 3,
+}
+
+/// Intel manual section 8.1.2 "x87 FPU Data Registers". An 80-bit data register, in double
+/// extended-precision format: a 64-bit significand (including the explicit integer bit), followed
+/// by a 15-bit exponent and the sign bit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataRegister {
+    pub(crate) significand: u64,
+    pub(crate) sign_and_exponent: u16,
+}
+
+/// Intel manual section 8.1 "X87 FPU EXECUTION ENVIRONMENT".
+/// The data registers and the control, status, and tag words of the x87 FPU.
+/// - The eight data registers are addressed as a stack relative to TOP, and are also aliased by
+///   the MMX registers MM0-MM7, which map to the significands of the physical registers R0-R7.
+/// - The control word holds the exception masks (bits 0-5), the precision control field (bits 8
+///   and 9), the rounding control field (bits 10 and 11), and the infinity control flag (bit 12).
+///   Bit 6 is reserved and always reads as 1.
+/// - The status word holds the exception flags (bits 0-5), the stack fault flag (bit 6), the
+///   exception summary status flag (bit 7), the condition codes C0-C3 (bits 8, 9, 10, and 14),
+///   the top-of-stack pointer (bits 11-13), and the busy flag (bit 15).
+/// - The tag word holds 2 bits for each physical data register, describing its contents: valid
+///   (0b00), zero (0b01), special (0b10), or empty (0b11).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fpu {
+    data_registers: [DataRegister; 8],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    control_word: Bitmap<16>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    status_word: Bitmap<16>,
+    tag_word: u16,
+}
+
+impl Fpu {
+    pub fn get_control_word(&self) -> u16 {
+        *self.control_word.as_value()
+    }
+
+    /// Sets the raw value of the control word. Reserved bits are forced to their fixed values
+    /// regardless of `value`.
+    pub fn set_control_word(&mut self, value: u16) {
+        const RESERVED_SET: u16 = 1 << 6;
+        const RESERVED_CLEAR: u16 = 1 << 7 | 0xe000;
+        self.control_word = Bitmap::from_value(value & !RESERVED_CLEAR | RESERVED_SET);
+    }
+
+    pub fn get_status_word(&self) -> u16 {
+        *self.status_word.as_value()
+    }
+
+    pub fn set_status_word(&mut self, value: u16) {
+        self.status_word = Bitmap::from_value(value);
+    }
+
+    pub fn get_tag_word(&self) -> u16 {
+        self.tag_word
+    }
+
+    pub fn set_tag_word(&mut self, value: u16) {
+        self.tag_word = value;
+    }
+
+    pub fn get_rounding_mode(&self) -> RoundingMode {
+        match self.get_control_word() >> 10 & 0b11 {
+            0 => RoundingMode::Nearest,
+            1 => RoundingMode::Down,
+            2 => RoundingMode::Up,
+            _ => RoundingMode::TowardZero,
+        }
+    }
+
+    pub fn set_rounding_mode(&mut self, rounding_mode: RoundingMode) {
+        let control_word = self.get_control_word() & !(0b11 << 10) | (rounding_mode as u16) << 10;
+        self.set_control_word(control_word);
+    }
+
+    /// Gets the index of the physical register which is currently the top of the register stack,
+    /// i.e. ST(0).
+    pub fn get_top(&self) -> u8 {
+        (self.get_status_word() >> 11 & 0b111) as u8
+    }
+
+    pub fn set_top(&mut self, top: u8) {
+        let status_word = self.get_status_word() & !(0b111 << 11) | ((top & 0b111) as u16) << 11;
+        self.set_status_word(status_word);
+    }
+
+    /// Intel manual section 8.1.1 "x87 FPU Initialization". Sets the control word to 0x037f (all
+    /// exceptions masked, round to nearest, and double extended precision), clears the status
+    /// word, and marks every data register as empty.
+    pub fn initialize(&mut self) {
+        self.set_control_word(0x037f);
+        self.set_status_word(0);
+        self.set_tag_word(0xffff);
+    }
+
+    pub fn get_data_register(&self, index: usize) -> DataRegister {
+        self.data_registers[index]
+    }
+
+    /// Intel manual section 9.5.1 "MMX Instructions and the x87 FPU Tag Word". Executing an MMX
+    /// instruction sets TOP to 0 and marks every data register as valid.
+    fn enter_mmx_state(&mut self) {
+        self.set_top(0);
+        self.set_tag_word(0);
+    }
+
+    /// Reads MMn, which is the significand of physical data register Rn.
+    pub fn read_mmx(&mut self, index: usize) -> u64 {
+        self.enter_mmx_state();
+        self.data_registers[index].significand
+    }
+
+    /// Writes MMn, which is the significand of physical data register Rn. The sign and exponent
+    /// bits of the register are all set, so that it reads as a NaN or infinity to x87 instructions.
+    pub fn write_mmx(&mut self, index: usize, value: u64) {
+        self.enter_mmx_state();
+        self.data_registers[index] = DataRegister {
+            significand: value,
+            sign_and_exponent: 0xffff,
+        };
+    }
+
+    /// Intel manual section 9.6.3 "Using the EMMS Instruction". Marks every data register as empty,
+    /// so that the x87 FPU can be used again after MMX code.
+    pub fn empty_mmx_state(&mut self) {
+        self.set_tag_word(0xffff);
+    }
+}
+
+impl Default for Fpu {
+    /// Intel manual section 10.1.1 "Processor State After Reset". The control word is 0x0040, the
+    /// status word is 0, and the tag word is 0x5555.
+    fn default() -> Self {
+        let mut fpu = Self {
+            data_registers: [DataRegister::default(); 8],
+            control_word: Bitmap::new(),
+            status_word: Bitmap::new(),
+            tag_word: 0x5555,
+        };
+        fpu.set_control_word(0x0040);
+        fpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_word() {
+        let mut fpu = Fpu::default();
+        assert_eq!(fpu.get_control_word(), 0x0040);
+        assert_eq!(fpu.get_rounding_mode(), RoundingMode::Nearest);
+
+        fpu.set_control_word(0xffff);
+        assert_eq!(fpu.get_control_word(), 0x1f7f);
+        assert_eq!(fpu.get_rounding_mode(), RoundingMode::TowardZero);
+
+        fpu.set_rounding_mode(RoundingMode::Up);
+        assert_eq!(fpu.get_control_word(), 0x1b7f);
+    }
+
+    #[test]
+    fn top() {
+        let mut fpu = Fpu::default();
+        fpu.set_status_word(0x4000);
+        fpu.set_top(5);
+        assert_eq!(fpu.get_top(), 5);
+        assert_eq!(fpu.get_status_word(), 0x6800);
+        fpu.set_top(9);
+        assert_eq!(fpu.get_top(), 1);
+    }
+
+    #[test]
+    fn initialize() {
+        let mut fpu = Fpu::default();
+        fpu.set_status_word(0x3800);
+        fpu.initialize();
+        assert_eq!(fpu.get_control_word(), 0x037f);
+        assert_eq!(fpu.get_status_word(), 0);
+        assert_eq!(fpu.get_tag_word(), 0xffff);
+    }
+
+    #[test]
+    fn mmx_aliasing() {
+        let mut fpu = Fpu::default();
+        fpu.set_top(3);
+        fpu.write_mmx(2, 0x0123_4567_89ab_cdef);
+        assert_eq!(fpu.get_top(), 0);
+        assert_eq!(fpu.get_tag_word(), 0);
+        assert_eq!(
+            fpu.get_data_register(2),
+            DataRegister {
+                significand: 0x0123_4567_89ab_cdef,
+                sign_and_exponent: 0xffff,
+            }
+        );
+        assert_eq!(fpu.read_mmx(2), 0x0123_4567_89ab_cdef);
+        assert_eq!(fpu.read_mmx(3), 0);
+
+        fpu.empty_mmx_state();
+        assert_eq!(fpu.get_tag_word(), 0xffff);
+    }
+}
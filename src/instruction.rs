@@ -1,12 +1,26 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
 use crate::{
     cpu::Cpu,
-    error::Error,
-    register::{Register, Register16, Register32, Register8},
-    traits::{AsUnsigned, RegisterReadWrite},
+    error::{Error, Warning},
+    expression,
+    interrupt::CpuException,
+    lexer::{self, Token, TokenKind},
+    memory::{AccessKind, PhysicalAddress},
+    program::SymbolTable,
+    register::{
+        MmxRegister, Register, Register16, Register32, Register8, SegmentRegister, XmmRegister,
+    },
+    traits::RegisterReadWrite,
 };
 
 #[derive(Debug)]
-enum InstructionOperandFormat {
+pub(crate) enum InstructionOperandFormat {
     Eax,
     Ecx,
     Edx,
@@ -61,6 +75,8 @@ enum InstructionOperandFormat {
     Reg16Rm16Imm16,
     Reg32Rm32Imm8,
     Reg32Rm32Imm32,
+    Mem,
+    Mem16,
     Reg16Mem,
     Reg32Mem,
     SregRm16,
@@ -77,6 +93,15 @@ enum InstructionOperandFormat {
     Reg32Dr,
     CrReg32,
     DrReg32,
+    MmRm32,
+    Rm32Mm,
+    MmMm64,
+    Mm64Mm,
+    XmmXmm128,
+    Xmm128Xmm,
+    XmmXmm128Imm8,
+    XmmXmm64,
+    Xmm64Xmm,
     Reg16Rm8,
     Reg32Rm8,
     Reg32Rm16,
@@ -114,29 +139,106 @@ enum InstructionOperandFormat {
 }
 
 impl InstructionOperandFormat {
+    /// Whether the format is one of the dedicated accumulator forms (e.g. `ADD AL, imm8` or
+    /// `MOV AL, moffs8`), which overlap with the general r/m forms (e.g. `ADD r/m8, imm8` or
+    /// `MOV r8, r/m8`).
+    fn is_accumulator_form(&self) -> bool {
+        matches!(
+            self,
+            Self::AlImm8
+                | Self::AxImm16
+                | Self::EaxImm32
+                | Self::AlMoffs8
+                | Self::AxMoffs16
+                | Self::EaxMoffs32
+                | Self::Moffs8Al
+                | Self::Moffs16Ax
+                | Self::Moffs32Eax
+        )
+    }
+
+    /// Whether a register operand of the format is added to the last byte of the opcode, e.g. in
+    /// `MOV r32, imm32` (0xb8+r).
+    pub(crate) fn adds_register_to_opcode(&self) -> bool {
+        matches!(
+            self,
+            Self::Reg8Imm8 | Self::Reg16Imm16 | Self::Reg32Imm32 | Self::AxReg16 | Self::EaxReg32
+        )
+    }
+
+    /// The immediate operands of the format, as the index of the operand, the size that it is used
+    /// as, and whether it is sign-extended to the size of the operation (e.g. in `ADD r/m32, imm8`).
+    pub(crate) fn immediates(&self) -> &'static [(usize, Size, bool)] {
+        use InstructionOperandFormat as F;
+        use Size::*;
+        match self {
+            F::Imm8 | F::Imm8Al | F::Imm8Ax | F::Imm8Eax => &[(0, Byte, false)],
+            F::Imm16 => &[(0, Word, false)],
+            F::Imm32 => &[(0, Dword, false)],
+            F::Reg8Imm8 | F::Rm8Imm8 | F::AlImm8 | F::AxImm8 | F::EaxImm8 => &[(1, Byte, false)],
+            F::Reg16Imm16 | F::Rm16Imm16 | F::AxImm16 => &[(1, Word, false)],
+            F::Reg32Imm32 | F::Rm32Imm32 | F::EaxImm32 => &[(1, Dword, false)],
+            F::Rm16Imm8 | F::Rm32Imm8 => &[(1, Byte, true)],
+            F::Reg16Rm16Imm8 | F::Reg32Rm32Imm8 => &[(2, Byte, true)],
+            F::Reg16Rm16Imm16 => &[(2, Word, false)],
+            F::Reg32Rm32Imm32 => &[(2, Dword, false)],
+            F::XmmXmm128Imm8 | F::Rm16Reg16Imm8 | F::Rm32Reg32Imm8 => &[(2, Byte, false)],
+            F::Imm16Imm16 => &[(0, Word, false), (1, Word, false)],
+            F::Imm16Imm32 => &[(0, Word, false), (1, Dword, false)],
+            F::Imm8Imm16 => &[(0, Byte, false), (1, Word, false)],
+            _ => &[],
+        }
+    }
+
     /// Checks whether the `InstructionOperandFormat` is compatible with the operands provided.
     /// I.e. can an instruction with this `InstructionOperandFormat` be executed on the operands
     /// provided.
     pub fn matches(&self, operands: &Operands) -> bool {
-        // Validates that the operand is the correct immediate value.
+        // Validates that the operand is the correct immediate value. A `strict` immediate never
+        // matches, as it has been asked to be encoded with its size rather than implied by the
+        // opcode, e.g. `int strict byte 3` is `INT imm8` rather than `INT3`.
         let validate_const = |operand: &Operand, target: u32| -> bool {
             if let OperandType::Immediate(immediate) = &operand.operand_type {
-                immediate.0 == target
+                !operand.strict && immediate.0 == target
             } else {
                 false
             }
         };
 
+        // As NASM does, an operand without a size directive takes on the size of the register
+        // operand(s) it is used alongside, e.g. `add eax, [ebx]` is a DWORD operation and
+        // `mov [ebx], al` is a BYTE store.
+        let inferred_size = operands.infer_size();
+
         // Validates that the immediate operand's size directive (if given) matches the target
-        // size. If no size directive is provided, then it is accepted regardless of whether it may
-        // be too large. In the case that it is, it will simply be truncated when used. This
-        // tangentially allows negative numbers to work as expected, as inferring the size of -1
-        // would always result in `u32::MAX` due to two's complement encoding, even though it can
-        // equivalently fit in a BYTE (`u8::MAX`), or WORD (`u16::MAX`).
+        // size. If no size directive is provided, then the size inferred from the register
+        // operands is used instead. If no size can be inferred, then it is accepted regardless of
+        // whether it may be too large. In the case that it is, it will simply be truncated when
+        // used. This tangentially allows negative numbers to work as expected, as inferring the
+        // size of -1 from its value would always result in `u32::MAX` due to two's complement
+        // encoding, even though it can equivalently fit in a BYTE (`u8::MAX`), or WORD
+        // (`u16::MAX`). A `strict` immediate only ever matches the size given by its directive.
         let validate_immediate = |operand: &Operand, target_size: Size| -> bool {
             let OperandType::Immediate(_) = &operand.operand_type else {
-                    return false;
-                };
+                return false;
+            };
+
+            if operand.strict {
+                return operand.size_directive == Some(target_size);
+            }
+
+            match operand.size_directive.or(inferred_size) {
+                Some(size) => size == target_size,
+                None => true,
+            }
+        };
+
+        // Validates an immediate whose size is independent of the size of the other operands, such
+        // as an I/O port number or shift count. Only an explicit size directive is checked.
+        let validate_independent_immediate = |operand: &Operand, target_size: Size| -> bool {
+            let OperandType::Immediate(_) = &operand.operand_type else {
+                return false;
+            };
 
             if let Some(size_directive) = &operand.size_directive {
                 return size_directive == &target_size;
@@ -145,18 +247,52 @@ impl InstructionOperandFormat {
             true
         };
 
-        // Validates that the register contained within this operand is of the specified
-        // `target_size`.
+        // Validates that the general-purpose register contained within this operand is of the
+        // specified `target_size`. Other registers (e.g. control registers) are only accepted by
+        // their dedicated validation functions.
         let validate_register = |operand: &Operand, target_size: Size| -> bool {
             let OperandType::Register(register) = &operand.operand_type else {
                 return false;
             };
-            register.size() == target_size
+            register.is_general_purpose() && register.size() == target_size
+        };
+
+        // Validates that the operand is a control register.
+        let validate_control_register = |operand: &Operand| -> bool {
+            matches!(
+                &operand.operand_type,
+                OperandType::Register(Register::ControlRegister(_))
+            )
+        };
+
+        // Validates that the operand is a debug register.
+        let validate_debug_register = |operand: &Operand| -> bool {
+            matches!(
+                &operand.operand_type,
+                OperandType::Register(Register::DebugRegister(_))
+            )
+        };
+
+        // Validates that the operand is an MMX register.
+        let validate_mmx_register = |operand: &Operand| -> bool {
+            matches!(
+                &operand.operand_type,
+                OperandType::Register(Register::MmxRegister(_))
+            )
+        };
+
+        // Validates that the operand is an XMM register.
+        let validate_xmm_register = |operand: &Operand| -> bool {
+            matches!(
+                &operand.operand_type,
+                OperandType::Register(Register::XmmRegister(_))
+            )
         };
 
         // Validates that the operand containing this effective address either does not have a size
-        // directive, or that it has a matching size directive. If `target_size` is `None`, then we
-        // do not perform any size checks.
+        // directive, or that it has a matching size directive. Without a size directive, the size
+        // inferred from the register operands (if any) must match instead. If `target_size` is
+        // `None`, then we do not perform any size checks.
         let validate_memory = |operand: &Operand, target_size: Option<Size>| -> bool {
             let OperandType::Memory(_) = &operand.operand_type else {
                 return false;
@@ -166,24 +302,29 @@ impl InstructionOperandFormat {
                 return true;
             };
 
-            if let Some(size_directive) = &operand.size_directive {
-                return size_directive == &target_size;
+            match operand.size_directive.or(inferred_size) {
+                Some(size) => size == target_size,
+                None => true,
             }
+        };
 
-            true
+        // Validates that the operand is a memory offset (moffs), i.e. an effective address without
+        // any registers, of the specified `target_size`.
+        let validate_memory_offset = |operand: &Operand, target_size: Size| -> bool {
+            let OperandType::Memory(effective_address) = &operand.operand_type else {
+                return false;
+            };
+
+            effective_address.is_offset_only() && validate_memory(operand, Some(target_size))
         };
 
         // Validates that either a register or effective address has been provided. If it is a
-        // register, it should also be of the specified `target_size`.
+        // register, it should also be of the specified `target_size`. If it is an effective
+        // address, it is subject to the same size checks as `validate_memory`.
         let validate_register_or_memory = |operand: &Operand, target_size: Size| -> bool {
             match &operand.operand_type {
-                OperandType::Memory(_) => {
-                    let Some(size_directive) = &operand.size_directive else {
-                        return true;
-                    };
-                    size_directive == &target_size
-                }
-                OperandType::Register(register) => register.size() == target_size,
+                OperandType::Memory(_) => validate_memory(operand, Some(target_size)),
+                OperandType::Register(_) => validate_register(operand, target_size),
                 _ => false,
             }
         };
@@ -195,6 +336,9 @@ impl InstructionOperandFormat {
             operands.0.get(1),
             operands.0.get(2),
         ) {
+            (F::Ax, Some(op), None, None) => {
+                op.operand_type == OperandType::Register(Register16::Ax.into())
+            }
             (F::Cs, Some(op), None, None) => {
                 op.operand_type == OperandType::Register(Register16::Cs.into())
             }
@@ -230,7 +374,7 @@ impl InstructionOperandFormat {
             }
             // (F::Rel8, Some(op), None, None) => {},
             // (F::Rel16, Some(op), None, None) => {},
-            // (F::Rel32, Some(op), None, None) => {},
+            (F::Rel32, Some(op), None, None) => validate_independent_immediate(op, Size::Dword),
             (F::Rm8, Some(op), None, None) => validate_register_or_memory(op, Size::Byte),
             (F::Rm16, Some(op), None, None) => validate_register_or_memory(op, Size::Word),
             (F::Rm32, Some(op), None, None) => validate_register_or_memory(op, Size::Dword),
@@ -290,6 +434,8 @@ impl InstructionOperandFormat {
                     && validate_register_or_memory(op2, Size::Dword)
                     && validate_immediate(op3, Size::Dword)
             }
+            (F::Mem, Some(op), None, None) => validate_memory(op, None),
+            (F::Mem16, Some(op), None, None) => validate_memory(op, Some(Size::Word)),
             (F::Reg16Mem, Some(op1), Some(op2), None) => {
                 validate_register(op1, Size::Word) && validate_memory(op2, None)
             }
@@ -308,7 +454,9 @@ impl InstructionOperandFormat {
                 validate_register_or_memory(op1, Size::Dword) && validate_const(op2, 1)
             }
             // (F::Far16, Some(op), None, None) => {},
-            // (F::Far32, Some(op), None, None) => {},
+            (F::Far32, Some(op), None, None) => {
+                matches!(op.operand_type, OperandType::FarPointer(_))
+            }
             (F::Rm8Cl, Some(op1), Some(op2), None) => {
                 validate_register_or_memory(op1, Size::Byte)
                     && op2.operand_type == OperandType::Register(Register8::Cl.into())
@@ -321,10 +469,54 @@ impl InstructionOperandFormat {
                 validate_register_or_memory(op1, Size::Dword)
                     && op2.operand_type == OperandType::Register(Register8::Cl.into())
             }
-            // (F::Reg32Cr, Some(op1), Some(op2), None) => {},
-            // (F::Reg32Dr, Some(op1), Some(op2), None) => {},
-            // (F::CrReg32, Some(op1), Some(op2), None) => {},
-            // (F::DrReg32, Some(op1), Some(op2), None) => {},
+            (F::Reg32Cr, Some(op1), Some(op2), None) => {
+                validate_register(op1, Size::Dword) && validate_control_register(op2)
+            }
+            (F::Reg32Dr, Some(op1), Some(op2), None) => {
+                validate_register(op1, Size::Dword) && validate_debug_register(op2)
+            }
+            (F::CrReg32, Some(op1), Some(op2), None) => {
+                validate_control_register(op1) && validate_register(op2, Size::Dword)
+            }
+            (F::DrReg32, Some(op1), Some(op2), None) => {
+                validate_debug_register(op1) && validate_register(op2, Size::Dword)
+            }
+            (F::MmRm32, Some(op1), Some(op2), None) => {
+                validate_mmx_register(op1) && validate_register_or_memory(op2, Size::Dword)
+            }
+            (F::Rm32Mm, Some(op1), Some(op2), None) => {
+                validate_register_or_memory(op1, Size::Dword) && validate_mmx_register(op2)
+            }
+            (F::MmMm64, Some(op1), Some(op2), None) => {
+                validate_mmx_register(op1)
+                    && (validate_mmx_register(op2) || validate_memory(op2, Some(Size::Qword)))
+            }
+            // A register to register move is also encodable with this format, but as NASM does,
+            // only the `MmMm64` form is used for it, so that the two do not conflict.
+            (F::Mm64Mm, Some(op1), Some(op2), None) => {
+                validate_memory(op1, Some(Size::Qword)) && validate_mmx_register(op2)
+            }
+            (F::XmmXmm128, Some(op1), Some(op2), None) => {
+                validate_xmm_register(op1)
+                    && (validate_xmm_register(op2) || validate_memory(op2, Some(Size::Oword)))
+            }
+            // As with `Mm64Mm`, a register to register move only uses the `XmmXmm128` form.
+            (F::Xmm128Xmm, Some(op1), Some(op2), None) => {
+                validate_memory(op1, Some(Size::Oword)) && validate_xmm_register(op2)
+            }
+            (F::XmmXmm128Imm8, Some(op1), Some(op2), Some(op3)) => {
+                validate_xmm_register(op1)
+                    && (validate_xmm_register(op2) || validate_memory(op2, Some(Size::Oword)))
+                    && validate_independent_immediate(op3, Size::Byte)
+            }
+            (F::XmmXmm64, Some(op1), Some(op2), None) => {
+                validate_xmm_register(op1)
+                    && (validate_xmm_register(op2) || validate_memory(op2, Some(Size::Qword)))
+            }
+            // As with `Mm64Mm`, a register to register move only uses the `XmmXmm64` form.
+            (F::Xmm64Xmm, Some(op1), Some(op2), None) => {
+                validate_memory(op1, Some(Size::Qword)) && validate_xmm_register(op2)
+            }
             (F::Reg16Rm8, Some(op1), Some(op2), None) => {
                 validate_register(op1, Size::Word) && validate_register_or_memory(op2, Size::Byte)
             }
@@ -337,12 +529,12 @@ impl InstructionOperandFormat {
             (F::Rm16Reg16Imm8, Some(op1), Some(op2), Some(op3)) => {
                 validate_register_or_memory(op1, Size::Word)
                     && validate_register(op2, Size::Word)
-                    && validate_immediate(op3, Size::Byte)
+                    && validate_independent_immediate(op3, Size::Byte)
             }
             (F::Rm32Reg32Imm8, Some(op1), Some(op2), Some(op3)) => {
                 validate_register_or_memory(op1, Size::Dword)
                     && validate_register(op2, Size::Dword)
-                    && validate_immediate(op3, Size::Byte)
+                    && validate_independent_immediate(op3, Size::Byte)
             }
             (F::Rm16Reg16Cl, Some(op1), Some(op2), Some(op3)) => {
                 validate_register_or_memory(op1, Size::Word)
@@ -367,10 +559,12 @@ impl InstructionOperandFormat {
                     && validate_immediate(op2, Size::Dword)
             }
             (F::Imm16Imm16, Some(op1), Some(op2), None) => {
-                validate_immediate(op1, Size::Word) && validate_immediate(op2, Size::Word)
+                validate_independent_immediate(op1, Size::Word)
+                    && validate_independent_immediate(op2, Size::Word)
             }
             (F::Imm16Imm32, Some(op1), Some(op2), None) => {
-                validate_immediate(op1, Size::Word) && validate_immediate(op2, Size::Dword)
+                validate_independent_immediate(op1, Size::Word)
+                    && validate_independent_immediate(op2, Size::Dword)
             }
             (F::AxReg16, Some(op1), Some(op2), None) => {
                 op1.operand_type == OperandType::Register(Register16::Ax.into())
@@ -382,19 +576,36 @@ impl InstructionOperandFormat {
             }
             (F::AxImm8, Some(op1), Some(op2), None) => {
                 op1.operand_type == OperandType::Register(Register16::Ax.into())
-                    && validate_immediate(op2, Size::Byte)
+                    && validate_independent_immediate(op2, Size::Byte)
             }
             (F::EaxImm8, Some(op1), Some(op2), None) => {
                 op1.operand_type == OperandType::Register(Register32::Eax.into())
-                    && validate_immediate(op2, Size::Byte)
+                    && validate_independent_immediate(op2, Size::Byte)
+            }
+            (F::AlMoffs8, Some(op1), Some(op2), None) => {
+                op1.operand_type == OperandType::Register(Register8::Al.into())
+                    && validate_memory_offset(op2, Size::Byte)
+            }
+            (F::AxMoffs16, Some(op1), Some(op2), None) => {
+                op1.operand_type == OperandType::Register(Register16::Ax.into())
+                    && validate_memory_offset(op2, Size::Word)
+            }
+            (F::EaxMoffs32, Some(op1), Some(op2), None) => {
+                op1.operand_type == OperandType::Register(Register32::Eax.into())
+                    && validate_memory_offset(op2, Size::Dword)
+            }
+            (F::Moffs8Al, Some(op1), Some(op2), None) => {
+                validate_memory_offset(op1, Size::Byte)
+                    && op2.operand_type == OperandType::Register(Register8::Al.into())
+            }
+            (F::Moffs16Ax, Some(op1), Some(op2), None) => {
+                validate_memory_offset(op1, Size::Word)
+                    && op2.operand_type == OperandType::Register(Register16::Ax.into())
+            }
+            (F::Moffs32Eax, Some(op1), Some(op2), None) => {
+                validate_memory_offset(op1, Size::Dword)
+                    && op2.operand_type == OperandType::Register(Register32::Eax.into())
             }
-            // TODO: implement below
-            // (F::AlMoffs8, Some(op1), Some(op2), None) => {},
-            // (F::AxMoffs16, Some(op1), Some(op2), None) => {},
-            // (F::EaxMoffs32, Some(op1), Some(op2), None) => {},
-            // (F::Moffs8Al, Some(op1), Some(op2), None) => {},
-            // (F::Moffs16Ax, Some(op1), Some(op2), None) => {},
-            // (F::Moffs32Eax, Some(op1), Some(op2), None) => {},
             (F::AlDx, Some(op1), Some(op2), None) => {
                 op1.operand_type == OperandType::Register(Register8::Al.into())
                     && op2.operand_type == OperandType::Register(Register16::Dx.into())
@@ -420,32 +631,36 @@ impl InstructionOperandFormat {
                     && op2.operand_type == OperandType::Register(Register32::Eax.into())
             }
             (F::Imm8Al, Some(op1), Some(op2), None) => {
-                validate_immediate(op1, Size::Byte)
+                validate_independent_immediate(op1, Size::Byte)
                     && op2.operand_type == OperandType::Register(Register8::Al.into())
             }
             (F::Imm8Ax, Some(op1), Some(op2), None) => {
-                validate_immediate(op1, Size::Byte)
+                validate_independent_immediate(op1, Size::Byte)
                     && op2.operand_type == OperandType::Register(Register16::Ax.into())
             }
             (F::Imm8Eax, Some(op1), Some(op2), None) => {
-                validate_immediate(op1, Size::Byte)
+                validate_independent_immediate(op1, Size::Byte)
                     && op2.operand_type == OperandType::Register(Register32::Eax.into())
             }
             (F::Imm8Imm16, Some(op1), Some(op2), None) => {
-                validate_immediate(op1, Size::Byte) && validate_immediate(op2, Size::Word)
+                validate_independent_immediate(op1, Size::Byte)
+                    && validate_independent_immediate(op2, Size::Word)
             }
             (F::Reg8Cl, Some(op1), Some(op2), None) => {
                 validate_register(op1, Size::Byte)
                     && op2.operand_type == OperandType::Register(Register8::Cl.into())
             }
+            (F::None, None, None, None) => true,
             _ => false,
         }
     }
 }
 
-type CpuFunction = fn(&mut Cpu, &Operands);
+/// Performs an instruction on the CPU, given its operands. An access which faults returns an
+/// `Err`, which stops the instruction there, and is raised by `Instruction::execute`.
+type CpuFunction = fn(&mut Cpu, &Operands) -> Result<(), Error>;
 
-struct OperandFunctionMap {
+pub(crate) struct OperandFunctionMap {
     pub instruction_operand_format: InstructionOperandFormat,
     pub cpu_function: CpuFunction,
 }
@@ -462,82 +677,166 @@ impl From<(InstructionOperandFormat, CpuFunction)> for OperandFunctionMap {
 /// A valid instruction's signature, which may be matched against to determine what x86 instruction
 /// should be performed.
 pub(crate) struct InstructionDescriptor<'a> {
-    opcode: u32,
-    mnemonic: &'a str,
+    /// The opcode, including any mandatory prefix (e.g. 0x660f28 for `MOVAPD`), of which each byte
+    /// is encoded in turn starting from the most significant non-zero byte.
+    pub(crate) opcode: u32,
+    /// The value of the ModR/M reg field (i.e. /digit) which selects this instruction from amongst
+    /// the others sharing the same opcode, if any.
+    pub(crate) opcode_extension: Option<u8>,
+    pub(crate) mnemonic: &'a str,
     operand_function_map_8: Option<OperandFunctionMap>,
     operand_function_map_16: Option<OperandFunctionMap>,
     operand_function_map_32: Option<OperandFunctionMap>,
     // FIXME: Unsure if this should be stored here, perhaps it should just be encoded within the
     //        relevant CPU functions.
     lock_prefix: bool,
+    /// The approximate number of cycles which the instruction takes, with its operands in
+    /// registers (see `cycles!`).
+    pub(crate) cycles: u32,
 }
 
 /// Finds the appropriate `InstructionDescriptor` based on the mnemonic and operands provided.
 /// Multiple instructions may share the same menmonic, but they should be differentiated by their
 /// operand sizes.
 impl<'a> InstructionDescriptor<'a> {
+    /// Finds the `InstructionDescriptor` matching the mnemonic and operands, along with the
+    /// `OperandFunctionMap` within it which matches the operands.
     // FIXME: This will need to be refactored when we want to support more than just NASM, similarly
     //        to how there are different `TryInto` implementations based on what type of instruction
     //        format you are passing.
     // FIXME: Signature could be made more ergonomic by accepting a borrowed iterator in some form.
-    pub fn lookup_using_mnemonic_and_operands(
+    pub(crate) fn lookup(
         mnemonic: &str,
         operands: &Operands,
-    ) -> Result<CpuFunction, Error> {
+    ) -> Result<
+        (
+            &'static InstructionDescriptor<'static>,
+            &'static OperandFunctionMap,
+        ),
+        Error,
+    > {
+        let candidates = lookup_instructions_by_mnemonic(mnemonic);
         let mnemonic = mnemonic.to_uppercase();
-        let candidates: Vec<_> = INSTRUCTION_DESCRIPTORS
-            .iter()
-            .filter(|i| i.mnemonic == mnemonic)
-            .collect();
 
-        let mut matching_cpu_functions = Vec::new();
-        for candidate in &candidates {
-            if let Some(cpu_function) = candidate.resolve_matching_cpu_function(operands)? {
-                matching_cpu_functions.push(cpu_function);
+        let mut matches = Vec::new();
+        for candidate in candidates {
+            if let Some(map) = candidate.resolve_matching_operand_function_map(operands)? {
+                matches.push((candidate, map));
             }
         }
 
-        match matching_cpu_functions.len() {
+        // The accumulator forms behave identically to the r/m forms that they overlap with, so
+        // prefer them as they have the shorter encoding.
+        if matches.len() > 1 {
+            let accumulator_matches: Vec<_> = matches
+                .iter()
+                .filter(|(_, map)| map.instruction_operand_format.is_accumulator_form())
+                .collect();
+            if let [accumulator_match] = accumulator_matches[..] {
+                return Ok(*accumulator_match);
+            }
+        }
+
+        match matches.len() {
             0 => Err(Error::NoMatchingInstruction(format!("an instruction could not be found that matches the mnemonic \"{mnemonic}\" and associated operands"))),
-            1 => Ok(*matching_cpu_functions.get(0).unwrap()),
+            1 => Ok(matches[0]),
             _ => Err(Error::AmbiguousInstruction(format!("the mnemonic \"{mnemonic}\" and associated operands do not uniquely match a single instruction"))),
         }
     }
 
+    /// The bytes of the opcode, starting from its most significant non-zero byte, e.g. 0F 84 for
+    /// 0x0f84.
+    pub(crate) fn opcode_bytes(&self) -> Vec<u8> {
+        let bytes = self.opcode.to_be_bytes();
+        let first = bytes.iter().position(|&byte| byte != 0).unwrap_or(3);
+        bytes[first..].to_vec()
+    }
+
+    /// The `OperandFunctionMap`s of the descriptor, from the smallest operand size to the largest.
+    pub(crate) fn maps(&self) -> impl Iterator<Item = &OperandFunctionMap> {
+        [
+            &self.operand_function_map_8,
+            &self.operand_function_map_16,
+            &self.operand_function_map_32,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Whether the operand-size prefix (0x66) must be used to select the given map of the
+    /// descriptor. This is the case for the 16-bit form of an opcode which also has a 32-bit form,
+    /// whether in the same descriptor (e.g. `ADD r/m16, r16`) or another (e.g. `MOVSW`), but not
+    /// for instructions which only operate on 16 bits (e.g. `RET imm16` or `PUSH ES`).
+    pub(crate) fn needs_operand_size_prefix(&self, map: &OperandFunctionMap) -> bool {
+        let is_16_bit_form = self
+            .operand_function_map_16
+            .as_ref()
+            .is_some_and(|map_16| core::ptr::eq(map_16, map));
+        is_16_bit_form
+            && INSTRUCTION_DESCRIPTORS.iter().any(|descriptor| {
+                descriptor.opcode == self.opcode
+                    && descriptor.opcode_extension == self.opcode_extension
+                    && descriptor.operand_function_map_32.is_some()
+            })
+    }
+
+    /// The approximate number of cycles which the instruction takes with the given operands. An
+    /// operand in memory costs an extra cycle.
+    pub(crate) fn cycles(&self, operands: &Operands) -> u32 {
+        let memory_operand = operands
+            .0
+            .iter()
+            .any(|operand| matches!(operand.operand_type, OperandType::Memory(_)));
+        self.cycles + memory_operand as u32 * MEMORY_OPERAND_CYCLES
+    }
+
+    /// Whether the `LOCK` prefix may be applied to the instruction with the given operands. This is
+    /// only the case for read-modify-write instructions which have a memory destination.
+    pub(crate) fn is_lockable(&self, operands: &Operands) -> bool {
+        self.lock_prefix
+            && matches!(
+                operands.0.first(),
+                Some(Operand {
+                    operand_type: OperandType::Memory(_),
+                    ..
+                })
+            )
+    }
+
     /// An `InstructionDescriptor` may have multiple `CpuFunction`, each for different operands.
-    /// For a given set of operands, this function will find the appropriate `CpuFunction`, if it
-    /// exists.
-    pub fn resolve_matching_cpu_function(
+    /// For a given set of operands, this function will find the `OperandFunctionMap` holding the
+    /// appropriate `CpuFunction`, if it exists.
+    fn resolve_matching_operand_function_map(
         &self,
         operands: &Operands,
-    ) -> Result<Option<CpuFunction>, Error> {
-        let mut cpu_function = None;
+    ) -> Result<Option<&OperandFunctionMap>, Error> {
+        let mut matching_map = None;
 
         if let Some(map) = &self.operand_function_map_8 {
             if map.instruction_operand_format.matches(operands) {
-                cpu_function = Some(map.cpu_function);
+                matching_map = Some(map);
             }
         };
 
         if let Some(map) = &self.operand_function_map_16 {
             if map.instruction_operand_format.matches(operands) {
-                if cpu_function.is_some() {
+                if matching_map.is_some() {
                     return Err(Error::AmbiguousInstruction(format!("ambigious operand(s)")));
                 }
-                cpu_function = Some(map.cpu_function);
+                matching_map = Some(map);
             }
         };
 
         if let Some(map) = &self.operand_function_map_32 {
             if map.instruction_operand_format.matches(operands) {
-                if cpu_function.is_some() {
+                if matching_map.is_some() {
                     return Err(Error::AmbiguousInstruction(format!("ambigious operand(s)")));
                 }
-                cpu_function = Some(map.cpu_function);
+                matching_map = Some(map);
             }
         };
 
-        Ok(cpu_function)
+        Ok(matching_map)
     }
 }
 
@@ -553,6 +852,20 @@ macro_rules! expand_operand_function_mapping {
     };
 }
 
+/// The approximate number of cycles which an instruction takes, with its operands in registers.
+/// An instruction which takes longer than a single cycle gives its cost at the end of its `build!`
+/// entry. These are roughly the timings of the 486, and are only meant to make the cycle counter
+/// (and so the time-stamp counter) advance plausibly, not to model the pipeline. A repeated string
+/// instruction is costed as a single iteration, and a conditional jump as if it is taken.
+macro_rules! cycles {
+    ($cycles:literal) => {
+        $cycles
+    };
+    () => {
+        DEFAULT_CYCLES
+    };
+}
+
 macro_rules! build {
     (
         $opcode:literal,
@@ -561,20 +874,70 @@ macro_rules! build {
         ($($mapping_16:tt)*),
         ($($mapping_32:tt)*),
         $lock_prefix:literal
+        $(, $cycles:literal)?
+    ) => {
+        build!(
+            @ $opcode,
+            None,
+            $mnemonic,
+            ($($mapping_8)*),
+            ($($mapping_16)*),
+            ($($mapping_32)*),
+            $lock_prefix,
+            cycles!($($cycles)?)
+        )
+    };
+    (
+        $opcode:literal / $opcode_extension:literal,
+        $mnemonic:literal,
+        ($($mapping_8:tt)*),
+        ($($mapping_16:tt)*),
+        ($($mapping_32:tt)*),
+        $lock_prefix:literal
+        $(, $cycles:literal)?
+    ) => {
+        build!(
+            @ $opcode,
+            Some($opcode_extension),
+            $mnemonic,
+            ($($mapping_8)*),
+            ($($mapping_16)*),
+            ($($mapping_32)*),
+            $lock_prefix,
+            cycles!($($cycles)?)
+        )
+    };
+    (
+        @ $opcode:literal,
+        $opcode_extension:expr,
+        $mnemonic:literal,
+        ($($mapping_8:tt)*),
+        ($($mapping_16:tt)*),
+        ($($mapping_32:tt)*),
+        $lock_prefix:literal,
+        $cycles:expr
     ) => {
         InstructionDescriptor {
             opcode: $opcode,
+            opcode_extension: $opcode_extension,
             mnemonic: $mnemonic,
             operand_function_map_8: expand_operand_function_mapping!($($mapping_8)*),
             operand_function_map_16: expand_operand_function_mapping!($($mapping_16)*),
             operand_function_map_32: expand_operand_function_mapping!($($mapping_32)*),
             lock_prefix: $lock_prefix,
+            cycles: $cycles,
         }
-    }
+    };
 }
 
+/// The cost of the instructions which do not give one in their `build!` entry.
+const DEFAULT_CYCLES: u32 = 1;
+
+/// The extra cost of an instruction with an operand in memory.
+const MEMORY_OPERAND_CYCLES: u32 = 1;
+
 // TODO: Hash maps for op code and mnemonic look-ups.
-const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
+const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 387] = [
     build!(0x00, "ADD", (Rm8Reg8, add_rm8_reg8), (), (), true),
     build!(
         0x01,
@@ -603,7 +966,7 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
         false
     ),
     build!(0x06, "PUSH", (), (Es, push_es), (), false),
-    build!(0x07, "POP", (), (Es, pop_es), (), false),
+    build!(0x07, "POP", (), (Es, pop_es), (), false, 4),
     build!(0x08, "OR", (Rm8Reg8, or_rm8_reg8), (), (), true),
     build!(
         0x09,
@@ -660,7 +1023,7 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
         false
     ),
     build!(0x16, "PUSH", (), (Ss, push_ss), (), false),
-    build!(0x17, "POP", (), (Ss, pop_ss), (), false),
+    build!(0x17, "POP", (), (Ss, pop_ss), (), false, 4),
     build!(0x18, "SBB", (Rm8Reg8, sbb_rm8_reg8), (), (), true),
     build!(
         0x19,
@@ -689,7 +1052,7 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
         false
     ),
     build!(0x1e, "PUSH", (), (Ds, push_ds), (), false),
-    build!(0x1f, "POP", (), (Ds, pop_ds), (), false),
+    build!(0x1f, "POP", (), (Ds, pop_ds), (), false, 4),
     build!(0x20, "AND", (Rm8Reg8, and_rm8_reg8), (), (), true),
     build!(
         0x21,
@@ -717,8 +1080,8 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
         (EaxImm32, and_eax_imm32),
         false
     ),
-    build!(0x26, "ES", (), (None, es), (), false),
-    build!(0x27, "DAA", (None, daa), (), (), false),
+    build!(0x26, "ES", (None, es), (), (), false),
+    build!(0x27, "DAA", (None, daa), (), (), false, 2),
     build!(0x28, "SUB", (Rm8Reg8, sub_rm8_reg8), (), (), true),
     build!(
         0x29,
@@ -746,24 +1109,24 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
         (EaxImm32, sub_eax_imm32),
         false
     ),
-    build!(0x2e, "CS", (), (), (), false),
-    build!(0x2f, "DAS", (), (), (), false),
+    build!(0x2e, "CS", (None, cs), (), (), false),
+    build!(0x2f, "DAS", (None, das), (), (), false, 2),
     build!(0x30, "XOR", (), (), (), true),
     build!(0x31, "XOR", (), (), (), true),
     build!(0x32, "XOR", (), (), (), false),
     build!(0x33, "XOR", (), (), (), false),
     build!(0x34, "XOR", (), (), (), false),
     build!(0x35, "XOR", (), (), (), false),
-    build!(0x36, "SS", (), (), (), false),
-    build!(0x37, "AAA", (), (), (), false),
+    build!(0x36, "SS", (None, ss), (), (), false),
+    build!(0x37, "AAA", (None, aaa), (), (), false, 3),
     build!(0x38, "CMP", (), (), (), false),
     build!(0x39, "CMP", (), (), (), false),
     build!(0x3a, "CMP", (), (), (), false),
     build!(0x3b, "CMP", (), (), (), false),
     build!(0x3c, "CMP", (), (), (), false),
     build!(0x3d, "CMP", (), (), (), false),
-    build!(0x3e, "DS", (), (), (), false),
-    build!(0x3f, "AAS", (), (), (), false),
+    build!(0x3e, "DS", (None, ds), (), (), false),
+    build!(0x3f, "AAS", (None, aas), (), (), false, 3),
     build!(0x40, "INC", (), (), (), false),
     build!(0x41, "INC", (), (), (), false),
     build!(0x42, "INC", (), (), (), false),
@@ -788,30 +1151,40 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
     build!(0x55, "PUSH", (), (Bp, push_reg16), (Ebp, push_reg32), false),
     build!(0x56, "PUSH", (), (Si, push_reg16), (Esi, push_reg32), false),
     build!(0x57, "PUSH", (), (Di, push_reg16), (Edi, push_reg32), false),
-    build!(0x58, "POP", (), (Ax, pop_reg16), (Eax, pop_reg32), false),
-    build!(0x59, "POP", (), (Cx, pop_reg16), (Ecx, pop_reg32), false),
-    build!(0x5a, "POP", (), (Dx, pop_reg16), (Edx, pop_reg32), false),
-    build!(0x5b, "POP", (), (Bx, pop_reg16), (Ebx, pop_reg32), false),
-    build!(0x5c, "POP", (), (Sp, pop_reg16), (Esp, pop_reg32), false),
-    build!(0x5d, "POP", (), (Bp, pop_reg16), (Ebp, pop_reg32), false),
-    build!(0x5e, "POP", (), (Si, pop_reg16), (Esi, pop_reg32), false),
-    build!(0x5f, "POP", (), (Di, pop_reg16), (Edi, pop_reg32), false),
+    build!(0x58, "POP", (), (Ax, pop_reg16), (Eax, pop_reg32), false, 4),
+    build!(0x59, "POP", (), (Cx, pop_reg16), (Ecx, pop_reg32), false, 4),
+    build!(0x5a, "POP", (), (Dx, pop_reg16), (Edx, pop_reg32), false, 4),
+    build!(0x5b, "POP", (), (Bx, pop_reg16), (Ebx, pop_reg32), false, 4),
+    build!(0x5c, "POP", (), (Sp, pop_reg16), (Esp, pop_reg32), false, 4),
+    build!(0x5d, "POP", (), (Bp, pop_reg16), (Ebp, pop_reg32), false, 4),
+    build!(0x5e, "POP", (), (Si, pop_reg16), (Esi, pop_reg32), false, 4),
+    build!(0x5f, "POP", (), (Di, pop_reg16), (Edi, pop_reg32), false, 4),
     build!(0x60, "", (), (), (), false),
     build!(0x61, "", (), (), (), false),
-    build!(0x62, "", (), (), (), false),
+    build!(
+        0x62,
+        "BOUND",
+        (),
+        (Reg16Mem, bound_reg16_mem),
+        (Reg32Mem, bound_reg32_mem),
+        false,
+        7
+    ),
     build!(0x63, "", (), (), (), false),
-    build!(0x64, "", (), (), (), false),
-    build!(0x65, "", (), (), (), false),
+    build!(0x64, "FS", (None, fs), (), (), false),
+    build!(0x65, "GS", (None, gs), (), (), false),
     build!(0x66, "", (), (), (), false),
     build!(0x67, "", (), (), (), false),
     build!(0x68, "", (), (), (), false),
     build!(0x69, "", (), (), (), false),
     build!(0x6a, "", (), (), (), false),
     build!(0x6b, "", (), (), (), false),
-    build!(0x6c, "", (), (), (), false),
-    build!(0x6d, "", (), (), (), false),
-    build!(0x6e, "", (), (), (), false),
-    build!(0x6f, "", (), (), (), false),
+    build!(0x6c, "INSB", (None, insb), (), (), false, 17),
+    build!(0x6d, "INSW", (), (None, insw), (), false, 17),
+    build!(0x6d, "INSD", (), (), (None, insd), false, 17),
+    build!(0x6e, "OUTSB", (None, outsb), (), (), false, 17),
+    build!(0x6f, "OUTSW", (), (None, outsw), (), false, 17),
+    build!(0x6f, "OUTSD", (), (), (None, outsd), false, 17),
     build!(0x70, "", (), (), (), false),
     build!(0x71, "", (), (), (), false),
     build!(0x72, "", (), (), (), false),
@@ -828,10 +1201,143 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
     build!(0x7d, "", (), (), (), false),
     build!(0x7e, "", (), (), (), false),
     build!(0x7f, "", (), (), (), false),
-    build!(0x80, "", (), (), (), false),
-    build!(0x81, "", (), (), (), false),
+    build!(0x80 / 0, "ADD", (Rm8Imm8, add_rm8_imm8), (), (), true),
+    build!(0x80 / 1, "OR", (Rm8Imm8, or_rm8_imm8), (), (), true),
+    build!(0x80 / 2, "ADC", (Rm8Imm8, adc_rm8_imm8), (), (), true),
+    build!(0x80 / 3, "SBB", (Rm8Imm8, sbb_rm8_imm8), (), (), true),
+    build!(0x80 / 4, "AND", (Rm8Imm8, and_rm8_imm8), (), (), true),
+    build!(0x80 / 5, "SUB", (Rm8Imm8, sub_rm8_imm8), (), (), true),
+    build!(0x80 / 6, "XOR", (Rm8Imm8, xor_rm8_imm8), (), (), true),
+    build!(0x80 / 7, "CMP", (Rm8Imm8, cmp_rm8_imm8), (), (), false),
+    build!(
+        0x81 / 0,
+        "ADD",
+        (),
+        (Rm16Imm16, add_rm16_imm16),
+        (Rm32Imm32, add_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 1,
+        "OR",
+        (),
+        (Rm16Imm16, or_rm16_imm16),
+        (Rm32Imm32, or_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 2,
+        "ADC",
+        (),
+        (Rm16Imm16, adc_rm16_imm16),
+        (Rm32Imm32, adc_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 3,
+        "SBB",
+        (),
+        (Rm16Imm16, sbb_rm16_imm16),
+        (Rm32Imm32, sbb_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 4,
+        "AND",
+        (),
+        (Rm16Imm16, and_rm16_imm16),
+        (Rm32Imm32, and_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 5,
+        "SUB",
+        (),
+        (Rm16Imm16, sub_rm16_imm16),
+        (Rm32Imm32, sub_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 6,
+        "XOR",
+        (),
+        (Rm16Imm16, xor_rm16_imm16),
+        (Rm32Imm32, xor_rm32_imm32),
+        true
+    ),
+    build!(
+        0x81 / 7,
+        "CMP",
+        (),
+        (Rm16Imm16, cmp_rm16_imm16),
+        (Rm32Imm32, cmp_rm32_imm32),
+        false
+    ),
     build!(0x82, "", (), (), (), false),
-    build!(0x83, "", (), (), (), false),
+    build!(
+        0x83 / 0,
+        "ADD",
+        (),
+        (Rm16Imm8, add_rm16_imm8),
+        (Rm32Imm8, add_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 1,
+        "OR",
+        (),
+        (Rm16Imm8, or_rm16_imm8),
+        (Rm32Imm8, or_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 2,
+        "ADC",
+        (),
+        (Rm16Imm8, adc_rm16_imm8),
+        (Rm32Imm8, adc_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 3,
+        "SBB",
+        (),
+        (Rm16Imm8, sbb_rm16_imm8),
+        (Rm32Imm8, sbb_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 4,
+        "AND",
+        (),
+        (Rm16Imm8, and_rm16_imm8),
+        (Rm32Imm8, and_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 5,
+        "SUB",
+        (),
+        (Rm16Imm8, sub_rm16_imm8),
+        (Rm32Imm8, sub_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 6,
+        "XOR",
+        (),
+        (Rm16Imm8, xor_rm16_imm8),
+        (Rm32Imm8, xor_rm32_imm8),
+        true
+    ),
+    build!(
+        0x83 / 7,
+        "CMP",
+        (),
+        (Rm16Imm8, cmp_rm16_imm8),
+        (Rm32Imm8, cmp_rm32_imm8),
+        false
+    ),
     build!(0x84, "", (), (), (), false),
     build!(0x85, "", (), (), (), false),
     build!(0x86, "", (), (), (), false),
@@ -842,7 +1348,7 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
         "MOV",
         (),
         (Rm16Reg16, mov_rm16_reg16),
-        (Reg32Rm32, mov_rm32_reg32),
+        (Rm32Reg32, mov_rm32_reg32),
         false
     ),
     build!(0x8a, "MOV", (Reg8Rm8, mov_reg8_rm8), (), (), false),
@@ -875,28 +1381,47 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
     build!(0x97, "", (), (), (), false),
     build!(0x98, "", (), (), (), false),
     build!(0x99, "", (), (), (), false),
-    build!(0x9a, "", (), (), (), false),
+    build!(0x9a, "CALL", (), (), (Far32, call_far32), false, 3),
     build!(0x9b, "", (), (), (), false),
     build!(0x9c, "", (), (), (), false),
     build!(0x9d, "", (), (), (), false),
     build!(0x9e, "", (), (), (), false),
     build!(0x9f, "", (), (), (), false),
-    build!(0xa0, "", (), (), (), false),
-    build!(0xa1, "", (), (), (), false),
-    build!(0xa2, "", (), (), (), false),
-    build!(0xa3, "", (), (), (), false),
-    build!(0xa4, "", (), (), (), false),
-    build!(0xa5, "", (), (), (), false),
-    build!(0xa6, "", (), (), (), false),
-    build!(0xa7, "", (), (), (), false),
+    build!(0xa0, "MOV", (AlMoffs8, mov_al_moffs8), (), (), false),
+    build!(
+        0xa1,
+        "MOV",
+        (),
+        (AxMoffs16, mov_ax_moffs16),
+        (EaxMoffs32, mov_eax_moffs32),
+        false
+    ),
+    build!(0xa2, "MOV", (Moffs8Al, mov_moffs8_al), (), (), false),
+    build!(
+        0xa3,
+        "MOV",
+        (),
+        (Moffs16Ax, mov_moffs16_ax),
+        (Moffs32Eax, mov_moffs32_eax),
+        false
+    ),
+    build!(0xa4, "MOVSB", (None, movsb), (), (), false, 7),
+    build!(0xa5, "MOVSW", (), (None, movsw), (), false, 7),
+    build!(0xa5, "MOVSD", (), (), (None, movsd), false, 7),
+    build!(0xa6, "CMPSB", (None, cmpsb), (), (), false, 8),
+    build!(0xa7, "CMPSW", (), (None, cmpsw), (), false, 8),
+    build!(0xa7, "CMPSD", (), (), (None, cmpsd), false, 8),
     build!(0xa8, "", (), (), (), false),
     build!(0xa9, "", (), (), (), false),
-    build!(0xaa, "", (), (), (), false),
-    build!(0xab, "", (), (), (), false),
-    build!(0xac, "", (), (), (), false),
-    build!(0xad, "", (), (), (), false),
-    build!(0xae, "", (), (), (), false),
-    build!(0xaf, "", (), (), (), false),
+    build!(0xaa, "STOSB", (None, stosb), (), (), false, 5),
+    build!(0xab, "STOSW", (), (None, stosw), (), false, 5),
+    build!(0xab, "STOSD", (), (), (None, stosd), false, 5),
+    build!(0xac, "LODSB", (None, lodsb), (), (), false, 5),
+    build!(0xad, "LODSW", (), (None, lodsw), (), false, 5),
+    build!(0xad, "LODSD", (), (), (None, lodsd), false, 5),
+    build!(0xae, "SCASB", (None, scasb), (), (), false, 6),
+    build!(0xaf, "SCASW", (), (None, scasw), (), false, 6),
+    build!(0xaf, "SCASD", (), (), (None, scasd), false, 6),
     build!(0xb0, "", (), (), (), false),
     build!(0xb1, "", (), (), (), false),
     build!(0xb2, "", (), (), (), false),
@@ -915,8 +1440,8 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
     build!(0xbf, "", (), (), (), false),
     build!(0xc0, "", (), (), (), false),
     build!(0xc1, "", (), (), (), false),
-    build!(0xc2, "", (), (), (), false),
-    build!(0xc3, "", (), (), (), false),
+    build!(0xc2, "RET", (), (Imm16, ret_imm16), (), false, 5),
+    build!(0xc3, "RET", (None, ret), (), (), false, 5),
     build!(0xc4, "", (), (), (), false),
     build!(0xc5, "", (), (), (), false),
     build!(0xc6, "", (), (), (), false),
@@ -924,89 +1449,539 @@ const INSTRUCTION_DESCRIPTORS: [InstructionDescriptor; 254] = [
     build!(0xc8, "", (), (), (), false),
     build!(0xc9, "", (), (), (), false),
     build!(0xca, "", (), (), (), false),
-    build!(0xcb, "", (), (), (), false),
-    build!(0xcc, "", (), (), (), false),
-    build!(0xcd, "", (), (), (), false),
-    build!(0xce, "", (), (), (), false),
-    build!(0xcf, "", (), (), (), false),
+    build!(0xcb, "RETF", (None, retf), (), (), false, 13),
+    build!(0xcc, "INT3", (None, int3), (), (), false, 26),
+    build!(0xcd, "INT", (Imm8, int_imm8), (), (), false, 30),
+    build!(
+        0xce,
+        "INTO",
+        (None, interrupt_on_overflow),
+        (),
+        (),
+        false,
+        28
+    ),
+    build!(0xcf, "IRET", (), (None, iret), (), false, 15),
+    build!(0xcf, "IRETD", (), (), (None, iretd), false, 15),
     build!(0xd0, "", (), (), (), false),
     build!(0xd1, "", (), (), (), false),
     build!(0xd2, "", (), (), (), false),
     build!(0xd3, "", (), (), (), false),
-    build!(0xd4, "", (), (), (), false),
-    build!(0xd5, "", (), (), (), false),
-    build!(0xd6, "", (), (), (), false),
+    build!(0xd4, "AAM", (None, aam_base10), (), (), false, 15),
+    build!(0xd4, "AAM", (Imm8, aam_imm8), (), (), false, 15),
+    build!(0xd5, "AAD", (None, aad_base10), (), (), false, 14),
+    build!(0xd5, "AAD", (Imm8, aad_imm8), (), (), false, 14),
+    build!(0xd6, "SALC", (None, salc), (), (), false),
     build!(0xd7, "", (), (), (), false),
     build!(0xd8, "", (), (), (), false),
-    build!(0xd9, "", (), (), (), false),
+    build!(0xd9 / 5, "FLDCW", (), (Mem16, fldcw_mem16), (), false, 4),
+    build!(0xd9 / 7, "FNSTCW", (), (Mem16, fnstcw_mem16), (), false, 3),
     build!(0xda, "", (), (), (), false),
     build!(0xdb, "", (), (), (), false),
+    build!(0xdbe3, "FNINIT", (None, fninit), (), (), false, 17),
     build!(0xdc, "", (), (), (), false),
-    build!(0xdd, "", (), (), (), false),
+    build!(0xdd / 7, "FNSTSW", (), (Mem16, fnstsw_mem16), (), false, 3),
     build!(0xde, "", (), (), (), false),
     build!(0xdf, "", (), (), (), false),
+    build!(0xdfe0, "FNSTSW", (), (Ax, fnstsw_ax), (), false, 3),
     build!(0xe0, "", (), (), (), false),
     build!(0xe1, "", (), (), (), false),
     build!(0xe2, "", (), (), (), false),
     build!(0xe3, "", (), (), (), false),
-    build!(0xe4, "", (), (), (), false),
-    build!(0xe5, "", (), (), (), false),
-    build!(0xe6, "", (), (), (), false),
-    build!(0xe7, "", (), (), (), false),
-    build!(0xe8, "", (), (), (), false),
-    build!(0xe9, "", (), (), (), false),
-    build!(0xea, "", (), (), (), false),
+    build!(0xe4, "IN", (AlImm8, in_al_imm8), (), (), false, 14),
+    build!(
+        0xe5,
+        "IN",
+        (),
+        (AxImm8, in_ax_imm8),
+        (EaxImm8, in_eax_imm8),
+        false,
+        14
+    ),
+    build!(0xe6, "OUT", (Imm8Al, out_imm8_al), (), (), false, 16),
+    build!(
+        0xe7,
+        "OUT",
+        (),
+        (Imm8Ax, out_imm8_ax),
+        (Imm8Eax, out_imm8_eax),
+        false,
+        16
+    ),
+    build!(0xe8, "CALL", (), (), (Rel32, call_rel32), false, 3),
+    build!(0xe9, "JMP", (), (), (Rel32, jmp_rel32), false, 3),
+    build!(0xea, "JMP", (), (), (Far32, jmp_far32), false, 3),
     build!(0xeb, "", (), (), (), false),
-    build!(0xec, "", (), (), (), false),
-    build!(0xed, "", (), (), (), false),
-    build!(0xee, "", (), (), (), false),
-    build!(0xef, "", (), (), (), false),
+    build!(0xec, "IN", (AlDx, in_al_dx), (), (), false, 14),
+    build!(
+        0xed,
+        "IN",
+        (),
+        (AxDx, in_ax_dx),
+        (EaxDx, in_eax_dx),
+        false,
+        14
+    ),
+    build!(0xee, "OUT", (DxAl, out_dx_al), (), (), false, 16),
+    build!(
+        0xef,
+        "OUT",
+        (),
+        (DxAx, out_dx_ax),
+        (DxEax, out_dx_eax),
+        false,
+        16
+    ),
     build!(0xf0, "", (), (), (), false),
     build!(0xf1, "", (), (), (), false),
     build!(0xf2, "", (), (), (), false),
     build!(0xf3, "", (), (), (), false),
-    build!(0xf4, "", (), (), (), false),
+    build!(0xf4, "HLT", (None, hlt), (), (), false, 4),
     build!(0xf5, "", (), (), (), false),
     build!(0xf6, "", (), (), (), false),
     build!(0xf7, "", (), (), (), false),
     build!(0xf8, "", (), (), (), false),
     build!(0xf9, "", (), (), (), false),
-    build!(0xfa, "", (), (), (), false),
-    build!(0xfb, "", (), (), (), false),
+    build!(0xfa, "CLI", (None, cli), (), (), false, 5),
+    build!(0xfb, "STI", (None, sti), (), (), false, 5),
     build!(0xfc, "", (), (), (), false),
     build!(0xfd, "", (), (), (), false),
     build!(0xfe, "", (), (), (), false),
-];
-
-// FIXME: create hashtable or some other faster lookup method and use that.
-// FIXME: I don't understand how assemblers choose which opcode to use when multiple would match.
-//        For example ADD r8, rm8 vs ADD rm8, r8. How does ADD al, bl choose which one is correct?
-//        This is already proving to be an issue with instructions such as `MOV`, as we are
-//        returning an `AmbiguousInstruction` error.
-pub(crate) fn lookup_instructions_by_mnemonic(mnemonic: &str) -> Vec<&InstructionDescriptor> {
-    let mnemonic = mnemonic.to_uppercase();
-    INSTRUCTION_DESCRIPTORS
-        .iter()
-        .filter(|i| i.mnemonic == mnemonic)
-        .collect()
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum EffectiveAddressOperator {
-    Add,
-    Subtract,
-    Multiply,
-}
-
-impl TryFrom<char> for EffectiveAddressOperator {
-    type Error = Error;
-
-    fn try_from(value: char) -> Result<Self, Self::Error> {
-        match value {
-            '+' => Ok(Self::Add),
-            '-' => Ok(Self::Subtract),
-            '*' => Ok(Self::Multiply),
-            _ => Err(Error::CannotCovertType(format!(
+    // x87 FPU instructions which are preceded by `FWAIT` (0x9b). As there are never any pending
+    // unmasked exceptions to wait for, they behave identically to their no-wait forms.
+    build!(0x9bd9 / 7, "FSTCW", (), (Mem16, fnstcw_mem16), (), false, 3),
+    build!(0x9bdbe3, "FINIT", (None, fninit), (), (), false, 17),
+    build!(0x9bdd / 7, "FSTSW", (), (Mem16, fnstsw_mem16), (), false, 3),
+    build!(0x9bdfe0, "FSTSW", (), (Ax, fnstsw_ax), (), false, 3),
+    // `PAUSE` is encoded as `REP NOP`, and so is decoded by older processors as a plain `NOP`.
+    build!(0xf390, "PAUSE", (None, pause), (), (), false, 10),
+    // Two-byte opcodes, which are escaped with 0x0f.
+    build!(0x0f00 / 0, "SLDT", (), (Rm16, sldt_rm16), (), false, 2),
+    build!(0x0f00 / 1, "STR", (), (Rm16, str_rm16), (), false, 2),
+    build!(0x0f00 / 2, "LLDT", (), (Rm16, lldt_rm16), (), false, 20),
+    build!(0x0f00 / 3, "LTR", (), (Rm16, ltr_rm16), (), false, 20),
+    build!(0x0f01 / 0, "SGDT", (Mem, sgdt_mem), (), (), false, 10),
+    build!(0x0f01 / 1, "SIDT", (Mem, sidt_mem), (), (), false, 10),
+    build!(0x0f01 / 2, "LGDT", (Mem, lgdt_mem), (), (), false, 11),
+    build!(0x0f01 / 3, "LIDT", (Mem, lidt_mem), (), (), false, 11),
+    build!(
+        0x0f01 / 4,
+        "SMSW",
+        (),
+        (Rm16, smsw_rm16),
+        (Reg32, smsw_reg32),
+        false,
+        2
+    ),
+    build!(0x0f01 / 6, "LMSW", (), (Rm16, lmsw_rm16), (), false, 13),
+    build!(0x0f01 / 7, "INVLPG", (Mem, invlpg_mem), (), (), false, 25),
+    build!(0x0f06, "CLTS", (None, clts), (), (), false, 7),
+    build!(0x0f0b, "UD2", (None, ud2), (), (), false),
+    build!(
+        0x0f10,
+        "MOVUPS",
+        (XmmXmm128, movups_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x0f11,
+        "MOVUPS",
+        (Xmm128Xmm, movups_xmm128_xmm),
+        (),
+        (),
+        false
+    ),
+    build!(0x0f20, "MOV", (), (), (Reg32Cr, mov_reg32_cr), false),
+    build!(0x0f21, "MOV", (), (), (Reg32Dr, mov_reg32_dr), false),
+    build!(0x0f22, "MOV", (), (), (CrReg32, mov_cr_reg32), false),
+    build!(0x0f23, "MOV", (), (), (DrReg32, mov_dr_reg32), false),
+    build!(
+        0x0f28,
+        "MOVAPS",
+        (XmmXmm128, movaps_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x0f29,
+        "MOVAPS",
+        (Xmm128Xmm, movaps_xmm128_xmm),
+        (),
+        (),
+        false
+    ),
+    build!(0x0f30, "WRMSR", (None, wrmsr), (), (), false),
+    build!(0x0f31, "RDTSC", (None, rdtsc), (), (), false, 11),
+    build!(0x0f32, "RDMSR", (None, rdmsr), (), (), false, 20),
+    build!(0x0f34, "SYSENTER", (None, sysenter), (), (), false, 20),
+    build!(0x0f35, "SYSEXIT", (None, sysexit), (), (), false, 20),
+    build!(
+        0x0f58,
+        "ADDPS",
+        (XmmXmm128, addps_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x0f59,
+        "MULPS",
+        (XmmXmm128, mulps_xmm_xmm128),
+        (),
+        (),
+        false,
+        5
+    ),
+    build!(
+        0x0f5c,
+        "SUBPS",
+        (XmmXmm128, subps_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x0f5e,
+        "DIVPS",
+        (XmmXmm128, divps_xmm_xmm128),
+        (),
+        (),
+        false,
+        40
+    ),
+    build!(0x0f6e, "MOVD", (), (), (MmRm32, movd_mm_rm32), false),
+    build!(0x0f6f, "MOVQ", (MmMm64, movq_mm_mm64), (), (), false),
+    build!(0x0f77, "EMMS", (None, emms), (), (), false, 6),
+    build!(0x0f7e, "MOVD", (), (), (Rm32Mm, movd_rm32_mm), false),
+    build!(0x0f7f, "MOVQ", (Mm64Mm, movq_mm64_mm), (), (), false),
+    build!(0x0f80, "JO", (), (), (Rel32, jo_rel32), false, 3),
+    build!(0x0f81, "JNO", (), (), (Rel32, jno_rel32), false, 3),
+    build!(0x0f82, "JB", (), (), (Rel32, jb_rel32), false, 3),
+    build!(0x0f83, "JAE", (), (), (Rel32, jae_rel32), false, 3),
+    build!(0x0f84, "JE", (), (), (Rel32, je_rel32), false, 3),
+    build!(0x0f85, "JNE", (), (), (Rel32, jne_rel32), false, 3),
+    build!(0x0f86, "JBE", (), (), (Rel32, jbe_rel32), false, 3),
+    build!(0x0f87, "JA", (), (), (Rel32, ja_rel32), false, 3),
+    build!(0x0f88, "JS", (), (), (Rel32, js_rel32), false, 3),
+    build!(0x0f89, "JNS", (), (), (Rel32, jns_rel32), false, 3),
+    build!(0x0f8a, "JP", (), (), (Rel32, jp_rel32), false, 3),
+    build!(0x0f8b, "JNP", (), (), (Rel32, jnp_rel32), false, 3),
+    build!(0x0f8c, "JL", (), (), (Rel32, jl_rel32), false, 3),
+    build!(0x0f8d, "JGE", (), (), (Rel32, jge_rel32), false, 3),
+    build!(0x0f8e, "JLE", (), (), (Rel32, jle_rel32), false, 3),
+    build!(0x0f8f, "JG", (), (), (Rel32, jg_rel32), false, 3),
+    build!(0x0faee8, "LFENCE", (None, lfence), (), (), false, 3),
+    build!(0x0faef0, "MFENCE", (None, mfence), (), (), false, 3),
+    build!(0x0faef8, "SFENCE", (None, sfence), (), (), false, 3),
+    build!(0x0fc0, "XADD", (Rm8Reg8, xadd_rm8_reg8), (), (), true),
+    build!(
+        0x0fc1,
+        "XADD",
+        (),
+        (Rm16Reg16, xadd_rm16_reg16),
+        (Rm32Reg32, xadd_rm32_reg32),
+        true
+    ),
+    build!(
+        0x0fc2,
+        "CMPPS",
+        (XmmXmm128Imm8, cmpps_xmm_xmm128_imm8),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x0fc7 / 6,
+        "RDRAND",
+        (),
+        (Reg16, rdrand_reg16),
+        (Reg32, rdrand_reg32),
+        false,
+        100
+    ),
+    build!(0x0fd4, "PADDQ", (MmMm64, paddq_mm_mm64), (), (), false),
+    build!(0x0fd8, "PSUBUSB", (MmMm64, psubusb_mm_mm64), (), (), false),
+    build!(0x0fd9, "PSUBUSW", (MmMm64, psubusw_mm_mm64), (), (), false),
+    build!(0x0fdb, "PAND", (MmMm64, pand_mm_mm64), (), (), false),
+    build!(0x0fdc, "PADDUSB", (MmMm64, paddusb_mm_mm64), (), (), false),
+    build!(0x0fdd, "PADDUSW", (MmMm64, paddusw_mm_mm64), (), (), false),
+    build!(0x0fdf, "PANDN", (MmMm64, pandn_mm_mm64), (), (), false),
+    build!(0x0fe8, "PSUBSB", (MmMm64, psubsb_mm_mm64), (), (), false),
+    build!(0x0fe9, "PSUBSW", (MmMm64, psubsw_mm_mm64), (), (), false),
+    build!(0x0feb, "POR", (MmMm64, por_mm_mm64), (), (), false),
+    build!(0x0fec, "PADDSB", (MmMm64, paddsb_mm_mm64), (), (), false),
+    build!(0x0fed, "PADDSW", (MmMm64, paddsw_mm_mm64), (), (), false),
+    build!(0x0fef, "PXOR", (MmMm64, pxor_mm_mm64), (), (), false),
+    build!(0x0ff8, "PSUBB", (MmMm64, psubb_mm_mm64), (), (), false),
+    build!(0x0ff9, "PSUBW", (MmMm64, psubw_mm_mm64), (), (), false),
+    build!(0x0ffa, "PSUBD", (MmMm64, psubd_mm_mm64), (), (), false),
+    build!(0x0ffb, "PSUBQ", (MmMm64, psubq_mm_mm64), (), (), false),
+    build!(0x0ffc, "PADDB", (MmMm64, paddb_mm_mm64), (), (), false),
+    build!(0x0ffd, "PADDW", (MmMm64, paddw_mm_mm64), (), (), false),
+    build!(0x0ffe, "PADDD", (MmMm64, paddd_mm_mm64), (), (), false),
+    // SSE2 instructions which share their opcode with an SSE instruction, and are distinguished
+    // from it by a mandatory prefix (0x66, 0xf2, or 0xf3) before the 0x0f escape.
+    build!(
+        0x660f28,
+        "MOVAPD",
+        (XmmXmm128, movapd_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x660f29,
+        "MOVAPD",
+        (Xmm128Xmm, movapd_xmm128_xmm),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x660f58,
+        "ADDPD",
+        (XmmXmm128, addpd_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x660f59,
+        "MULPD",
+        (XmmXmm128, mulpd_xmm_xmm128),
+        (),
+        (),
+        false,
+        5
+    ),
+    build!(
+        0x660f5c,
+        "SUBPD",
+        (XmmXmm128, subpd_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x660f5e,
+        "DIVPD",
+        (XmmXmm128, divpd_xmm_xmm128),
+        (),
+        (),
+        false,
+        40
+    ),
+    build!(
+        0x660f6f,
+        "MOVDQA",
+        (XmmXmm128, movdqa_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0x660f7f,
+        "MOVDQA",
+        (Xmm128Xmm, movdqa_xmm128_xmm),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0xf20f10,
+        "MOVSD",
+        (XmmXmm64, movsd_xmm_xmm64),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0xf20f11,
+        "MOVSD",
+        (Xmm64Xmm, movsd_xmm64_xmm),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0xf20f58,
+        "ADDSD",
+        (XmmXmm64, addsd_xmm_xmm64),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0xf20f59,
+        "MULSD",
+        (XmmXmm64, mulsd_xmm_xmm64),
+        (),
+        (),
+        false,
+        5
+    ),
+    build!(
+        0xf20f5c,
+        "SUBSD",
+        (XmmXmm64, subsd_xmm_xmm64),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0xf20f5e,
+        "DIVSD",
+        (XmmXmm64, divsd_xmm_xmm64),
+        (),
+        (),
+        false,
+        20
+    ),
+    build!(
+        0xf30f6f,
+        "MOVDQU",
+        (XmmXmm128, movdqu_xmm_xmm128),
+        (),
+        (),
+        false
+    ),
+    build!(
+        0xf30f7f,
+        "MOVDQU",
+        (Xmm128Xmm, movdqu_xmm128_xmm),
+        (),
+        (),
+        false
+    ),
+];
+
+// FIXME: create hashtable or some other faster lookup method and use that.
+// FIXME: I don't understand how assemblers choose which opcode to use when multiple would match.
+//        For example ADD r8, rm8 vs ADD rm8, r8. How does ADD al, bl choose which one is correct?
+//        This is already proving to be an issue with instructions such as `MOV`, as we are
+//        returning an `AmbiguousInstruction` error.
+pub(crate) fn lookup_instructions_by_mnemonic(
+    mnemonic: &str,
+) -> Vec<&'static InstructionDescriptor<'static>> {
+    let mnemonic = canonical_mnemonic(mnemonic);
+    INSTRUCTION_DESCRIPTORS
+        .iter()
+        .filter(|i| i.mnemonic == mnemonic)
+        .collect()
+}
+
+/// Finds the instructions whose opcode (along with its extension, if any) begins `bytes`, where the
+/// last byte of an opcode may have a register added to it (e.g. 0xb9 is `MOV ECX, imm32`). If
+/// several opcodes do, then only those which are the longest are returned (e.g. `FSTCW`, rather
+/// than the `WAIT` that its opcode begins with), along with the number of bytes in the opcode.
+pub(crate) fn lookup_instructions_by_opcode(
+    bytes: &[u8],
+) -> (Vec<&'static InstructionDescriptor<'static>>, usize) {
+    let mut longest = Vec::new();
+    let mut longest_length = 0;
+    for descriptor in INSTRUCTION_DESCRIPTORS.iter() {
+        let opcode = descriptor.opcode_bytes();
+        let length = opcode.len();
+        let extension_matches = match descriptor.opcode_extension {
+            Some(extension) => bytes
+                .get(length)
+                .is_some_and(|modrm| modrm >> 3 & 0b111 == extension),
+            None => true,
+        };
+        let adds_register = descriptor
+            .maps()
+            .any(|map| map.instruction_operand_format.adds_register_to_opcode());
+        let opcode_matches = match (bytes.get(..length), opcode.split_last()) {
+            (Some([start @ .., last]), Some((opcode_last, opcode_start))) => {
+                start == opcode_start
+                    && (last == opcode_last || adds_register && last.wrapping_sub(*opcode_last) < 8)
+            }
+            _ => false,
+        };
+        if descriptor.maps().next().is_none()
+            || !opcode_matches
+            || !extension_matches
+            || length < longest_length
+        {
+            continue;
+        }
+
+        if length > longest_length {
+            longest.clear();
+            longest_length = length;
+        }
+        longest.push(descriptor);
+    }
+    (longest, longest_length)
+}
+
+/// Mnemonics which are alternative spellings of another, which is the one used by the
+/// `INSTRUCTION_DESCRIPTORS`.
+const MNEMONIC_ALIASES: [(&str, &str); 1] = [("SAL", "SHL")];
+
+/// Condition codes which are alternative spellings of another, as used by the conditional
+/// instructions (`Jcc`, `SETcc`, and `CMOVcc`), e.g. `JZ` is `JE` and `SETNAE` is `SETB`.
+const CONDITION_CODE_ALIASES: [(&str, &str); 14] = [
+    ("C", "B"),
+    ("NAE", "B"),
+    ("NB", "AE"),
+    ("NC", "AE"),
+    ("Z", "E"),
+    ("NZ", "NE"),
+    ("NA", "BE"),
+    ("NBE", "A"),
+    ("PE", "P"),
+    ("PO", "NP"),
+    ("NGE", "L"),
+    ("NL", "GE"),
+    ("NG", "LE"),
+    ("NLE", "G"),
+];
+
+/// Converts a mnemonic into the (uppercase) spelling used by the `INSTRUCTION_DESCRIPTORS`, such
+/// that equivalent mnemonics (e.g. `jz` and `je`) find the same instructions.
+pub(crate) fn canonical_mnemonic(mnemonic: &str) -> String {
+    let mnemonic = mnemonic.to_uppercase();
+    if let Some((_, canonical)) = MNEMONIC_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == mnemonic)
+    {
+        return canonical.to_string();
+    }
+
+    for prefix in ["J", "SET", "CMOV"] {
+        let Some(condition_code) = mnemonic.strip_prefix(prefix) else {
+            continue;
+        };
+        if let Some((_, canonical)) = CONDITION_CODE_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == condition_code)
+        {
+            return format!("{prefix}{canonical}");
+        }
+    }
+    mnemonic
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EffectiveAddressOperator {
+    Add,
+    Subtract,
+    Multiply,
+}
+
+impl TryFrom<char> for EffectiveAddressOperator {
+    type Error = Error;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value {
+            '+' => Ok(Self::Add),
+            '-' => Ok(Self::Subtract),
+            '*' => Ok(Self::Multiply),
+            _ => Err(Error::CannotCovertType(format!(
                 "'{}' does not correspond to a valid operator",
                 value
             ))),
@@ -1024,7 +1999,7 @@ impl TryFrom<&NasmStr<'_>> for EffectiveAddressOperand {
     type Error = Error;
 
     fn try_from(value: &NasmStr<'_>) -> Result<Self, Self::Error> {
-        if let Ok(immediate) = Immediate::try_from(value) {
+        if let Ok(immediate) = Immediate::parse_literal(value.0) {
             return Ok(Self::Immediate(immediate));
         }
 
@@ -1050,18 +2025,20 @@ impl TryFrom<&NasmStr<'_>> for EffectiveAddressOperand {
 /// For example:
 ///
 /// - [EAX] = [(Add, Register(Eax))]
-/// - [EAX+4*EBX] = [(Add, Register(Eax)), (Add, Immediate(4)), (Multiply, Register(Ebx))]
+/// - [EAX+EBX*4+8] = [(Add, Register(Eax)), (Add, Register(Ebx)), (Multiply, Immediate(4)),
+///   (Add, Immediate(8))]
 ///
 /// There cannot be more than two registers used in the formation of a valid effective address,
 /// therefore this is tracked and a push will fail on the third attempt to push a register.
-/// Registers used must be general purpose.
+/// Registers used must be general purpose. Once parsed, the address is normalised into the form
+/// given by the SIB byte, i.e. a base, a scaled index, and a displacement.
 // FIXME: Apparently only general purpose registers should be able to be used as the base, but NASM
 //        appears to also allow si, di, bp, and bx.
 //        https://stackoverflow.com/questions/34058101/referencing-the-contents-of-a-memory-location-x86-addressing-modes/34058400#34058400
 // TODO: Should this just be SIB?
 // TODO: Tests. Also ensure that EIP cannot be used.
 // TODO: Remove num_registers and register_size, which are only used during creation.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct EffectiveAddress {
     raw: Vec<(EffectiveAddressOperator, EffectiveAddressOperand)>,
     num_registers: u8,
@@ -1077,9 +2054,84 @@ impl EffectiveAddress {
         }
     }
 
+    /// Resolves the linear address which is referenced, i.e. the offset added to the base of the
+    /// segment it is in, without checking the segment's limit. This is the segment given by a
+    /// segment-override prefix, or otherwise the default segment for the address. Accesses to
+    /// memory use `EffectiveAddress::translate` instead.
     pub fn resolve(&self, cpu: &Cpu) -> u32 {
-        let mut result = 0;
+        cpu.registers
+            .get_segment_base(self.segment(cpu))
+            .wrapping_add(self.offset(cpu))
+    }
+
+    /// Translates the address into the physical address of an access of the given size, checking
+    /// that the access is within the limit of the segment and is permitted by the page tables (see
+    /// `Cpu::memory_address`).
+    pub fn translate(
+        &self,
+        cpu: &Cpu,
+        size: Size,
+        kind: AccessKind,
+    ) -> Result<PhysicalAddress, Error> {
+        self.translate_bytes(cpu, size as u32 / 8, kind)
+    }
+
+    /// As `EffectiveAddress::translate`, for an access of any number of bytes (e.g. the 6-byte
+    /// pseudo-descriptor of `LGDT`).
+    pub fn translate_bytes(
+        &self,
+        cpu: &Cpu,
+        length: u32,
+        kind: AccessKind,
+    ) -> Result<PhysicalAddress, Error> {
+        cpu.memory_address(self.segment(cpu), self.offset(cpu), length, kind)
+    }
+
+    /// The segment which is referenced, which is the one given by a segment-override prefix, or
+    /// otherwise the default segment.
+    fn segment(&self, cpu: &Cpu) -> SegmentRegister {
+        cpu.segment_override
+            .unwrap_or_else(|| self.default_segment())
+    }
 
+    /// The segment which is referenced when there is no segment-override prefix. This is SS if the
+    /// base register is the stack or frame pointer, and DS otherwise.
+    pub fn default_segment(&self) -> SegmentRegister {
+        let base = self.raw.iter().enumerate().find_map(|(i, (_, operand))| {
+            // Registers can only be scaled by multiplying them by the immediate which follows.
+            let scaled = matches!(
+                self.raw.get(i + 1),
+                Some((EffectiveAddressOperator::Multiply, _))
+            );
+            match operand {
+                EffectiveAddressOperand::Register(register) if !scaled => Some(register),
+                _ => None,
+            }
+        });
+
+        match base {
+            Some(Register::Register32(Register32::Esp | Register32::Ebp))
+            | Some(Register::Register16(Register16::Sp | Register16::Bp)) => SegmentRegister::Ss,
+            _ => SegmentRegister::Ds,
+        }
+    }
+
+    /// Computes the offset of the address within its segment, which is also known as the effective
+    /// address. Multiplication takes precedence over addition and subtraction, such that an index
+    /// is scaled before it is added to the base and displacement (e.g. `[table+eax*4]`). As on
+    /// the processor, the arithmetic wraps around, within 64 KiB for a 16-bit address (see
+    /// `EffectiveAddress::address_size`).
+    pub fn offset(&self, cpu: &Cpu) -> u32 {
+        let accumulate =
+            |result: u32, term: u32, operator: &EffectiveAddressOperator| match operator {
+                EffectiveAddressOperator::Subtract => result.wrapping_sub(term),
+                _ => result.wrapping_add(term),
+            };
+
+        let mut result = 0;
+        // The term which is currently being multiplied, and whether it is added or subtracted.
+        let mut term: u32 = 0;
+        let mut term_operator = &EffectiveAddressOperator::Add;
         for (operator, operand) in &self.raw {
             let operand = match operand {
                 EffectiveAddressOperand::Immediate(immediate) => immediate.0,
@@ -1087,17 +2139,74 @@ impl EffectiveAddress {
                     Register::Register32(r) => r.read(&cpu.registers),
                     Register::Register16(r) => r.read(&cpu.registers).into(),
                     Register::Register8(r) => r.read(&cpu.registers).into(),
+                    _ => unreachable!("{register} cannot be used in an effective address"),
                 },
             };
 
             match operator {
-                EffectiveAddressOperator::Add => result = result + operand,
-                EffectiveAddressOperator::Subtract => result = result - operand,
-                EffectiveAddressOperator::Multiply => result = result * operand,
+                EffectiveAddressOperator::Multiply => term = term.wrapping_mul(operand),
+                _ => {
+                    result = accumulate(result, term, term_operator);
+                    term = operand;
+                    term_operator = operator;
+                }
             }
         }
 
-        result
+        let offset = accumulate(result, term, term_operator);
+        match self.address_size(cpu) {
+            Size::Word => offset & 0xffff,
+            _ => offset,
+        }
+    }
+
+    /// The size of the address, which is that of its registers (e.g. 16 bits for `[bx+si]`), or
+    /// the default address size (see `Registers::get_default_size`) if it has none.
+    pub fn address_size(&self, cpu: &Cpu) -> Size {
+        self.raw
+            .iter()
+            .find_map(|(_, operand)| match operand {
+                EffectiveAddressOperand::Register(Register::Register16(_)) => Some(Size::Word),
+                EffectiveAddressOperand::Register(Register::Register32(_)) => Some(Size::Dword),
+                _ => None,
+            })
+            .unwrap_or_else(|| cpu.registers.get_default_size())
+    }
+
+    /// Whether the effective address is made up of only a displacement, without any registers,
+    /// i.e. it is a memory offset (moffs) such as `[0x1234]`.
+    pub fn is_offset_only(&self) -> bool {
+        self.num_registers == 0
+    }
+
+    /// The base register, the index register and its scale, and the displacement which the address
+    /// is made up of, i.e. the parts which are encoded in the ModR/M and SIB bytes. An unscaled
+    /// register is the base, unless it follows another unscaled register.
+    pub(crate) fn components(&self) -> (Option<&Register>, Option<(&Register, u32)>, Option<u32>) {
+        let (mut base, mut index, mut displacement) = (None, None, None);
+        let mut raw = self.raw.iter().peekable();
+        while let Some((_, operand)) = raw.next() {
+            match operand {
+                EffectiveAddressOperand::Register(register) => {
+                    let scale = match raw.peek() {
+                        Some((
+                            EffectiveAddressOperator::Multiply,
+                            EffectiveAddressOperand::Immediate(scale),
+                        )) => {
+                            raw.next();
+                            Some(scale.0)
+                        }
+                        _ => None,
+                    };
+                    match scale {
+                        None if base.is_none() => base = Some(register),
+                        _ => index = Some((register, scale.unwrap_or(1))),
+                    }
+                }
+                EffectiveAddressOperand::Immediate(immediate) => displacement = Some(immediate.0),
+            }
+        }
+        (base, index, displacement)
     }
 
     // TODO: Tests.
@@ -1129,6 +2238,98 @@ impl EffectiveAddress {
         Ok(())
     }
 
+    /// Validates the effective address against the constraints of the SIB byte, and rewrites it
+    /// into the form that it is encoded in: the base register, then the index register with its
+    /// scale, and then a single displacement into which all of the immediates are folded. As in
+    /// NASM, the registers are swapped if ESP would otherwise be the index.
+    fn normalize(self) -> Result<Self, Error> {
+        use EffectiveAddressOperator::*;
+
+        // Each term is an operand which is added or subtracted, multiplied by any immediates which
+        // follow it.
+        let mut registers: Vec<(Register, u32)> = Vec::new();
+        let mut displacement: Option<u32> = None;
+        let mut raw = self.raw.into_iter().peekable();
+        while let Some((operator, operand)) = raw.next() {
+            let mut multiplier: u32 = 1;
+            while let Some((_, EffectiveAddressOperand::Immediate(immediate))) =
+                raw.next_if(|(operator, _)| operator == &Multiply)
+            {
+                multiplier = multiplier.wrapping_mul(immediate.0);
+            }
+
+            match operand {
+                EffectiveAddressOperand::Immediate(immediate) => {
+                    let term = immediate.0.wrapping_mul(multiplier);
+                    let displacement = displacement.get_or_insert(0);
+                    *displacement = match operator {
+                        Subtract => displacement.wrapping_sub(term),
+                        _ => displacement.wrapping_add(term),
+                    };
+                }
+                EffectiveAddressOperand::Register(register) => {
+                    if !matches!(multiplier, 1 | 2 | 4 | 8) {
+                        return Err(Error::InvalidEffectiveAddress(format!(
+                            "the scale of an index must be 1, 2, 4, or 8 (was {multiplier})"
+                        )));
+                    }
+                    registers.push((register, multiplier));
+                }
+            }
+        }
+
+        if registers.iter().filter(|(_, scale)| *scale != 1).count() > 1 {
+            return Err(Error::InvalidEffectiveAddress(
+                "only one register can be scaled, as there is only one index".into(),
+            ));
+        }
+
+        // The index is the scaled register, or otherwise the second register.
+        if registers.first().is_some_and(|(_, scale)| *scale != 1) {
+            registers.reverse();
+        }
+        let is_stack_pointer =
+            |register: &Register| matches!(register, Register::Register32(Register32::Esp));
+        if let [(base, 1), (index, 1)] = registers.as_slice() {
+            if is_stack_pointer(index) && !is_stack_pointer(base) {
+                registers.swap(0, 1);
+            }
+        }
+        let index = match registers.as_slice() {
+            [_, (index, _)] => Some(index),
+            [(index, scale)] if *scale != 1 => Some(index),
+            _ => None,
+        };
+        if index.is_some_and(is_stack_pointer) {
+            return Err(Error::InvalidEffectiveAddress(
+                "ESP cannot be used as an index".into(),
+            ));
+        }
+
+        let mut normalized = Self {
+            raw: Vec::new(),
+            ..self
+        };
+        for (register, scale) in registers {
+            normalized
+                .raw
+                .push((Add, EffectiveAddressOperand::Register(register)));
+            if scale != 1 {
+                normalized.raw.push((
+                    Multiply,
+                    EffectiveAddressOperand::Immediate(Immediate(scale)),
+                ));
+            }
+        }
+        if let Some(displacement) = displacement {
+            normalized.raw.push((
+                Add,
+                EffectiveAddressOperand::Immediate(Immediate(displacement)),
+            ));
+        }
+        Ok(normalized)
+    }
+
     // FIXME: If this can be implemented under the TryFrom trait that would be great. Am having
     //        issues with it conflicting with the core generic implementation.
     // TODO: Never used. Intuitively this should be used somewhere but I forgot to replace it.
@@ -1146,87 +2347,156 @@ impl EffectiveAddress {
     }
 }
 
-impl TryFrom<&NasmStr<'_>> for EffectiveAddress {
-    type Error = Error;
-
-    fn try_from(value: &NasmStr<'_>) -> Result<Self, Self::Error> {
-        // FIXME: This entire function is far too complex and should be simplified.
-        let remainder = value.0;
-        let mut chars = remainder.chars();
-        if chars.nth(0).unwrap() != '[' {
-            return Err(Error::CannotParseInstruction(
-                "invalid effective address (must start with \"[\")".into(),
-            ));
+impl fmt::Display for EffectiveAddress {
+    /// Writes the address in NASM syntax, e.g. `[ebx+ecx*4-8]`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (base, index, displacement) = self.components();
+        f.write_str("[")?;
+        if let Some(base) = base {
+            write!(f, "{}", base.to_string().to_lowercase())?;
         }
-
-        if chars.last().unwrap() != ']' {
-            return Err(Error::CannotParseInstruction(
-                "invalid effective address (expected \"]\" at end of operand)".into(),
-            ));
+        if let Some((index, scale)) = index {
+            if base.is_some() {
+                f.write_str("+")?;
+            }
+            write!(f, "{}", index.to_string().to_lowercase())?;
+            if scale != 1 {
+                write!(f, "*{scale}")?;
+            }
         }
+        match displacement {
+            // A displacement which is added to a register is written as signed.
+            Some(displacement) if base.is_some() || index.is_some() => {
+                if (displacement as i32) < 0 {
+                    write!(f, "-{}", Number(displacement.wrapping_neg()))?;
+                } else {
+                    write!(f, "+{}", Number(displacement))?;
+                }
+            }
+            Some(displacement) => write!(f, "{}", Number(displacement))?,
+            None => (),
+        }
+        f.write_str("]")
+    }
+}
+
+/// Writes a number as NASM does when disassembling, i.e. in hexadecimal unless it is a single digit.
+struct Number(u32);
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 10 {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "{:#x}", self.0)
+        }
+    }
+}
 
-        if remainder.len() < 3 {
+impl TryFrom<&NasmStr<'_>> for EffectiveAddress {
+    type Error = Error;
+
+    /// The brackets must be the first and last characters of the text.
+    fn try_from(value: &NasmStr<'_>) -> Result<Self, Self::Error> {
+        if value.0.trim() != value.0 {
             return Err(Error::CannotParseInstruction(
-                "invalid effective address (no contents)".into(),
+                "invalid effective address (must not be surrounded by whitespace)".into(),
             ));
         }
+        Self::parse(&lexer::tokenize(value.0)?)
+    }
+}
 
-        let inner = &remainder[1..remainder.len() - 1].trim().to_lowercase();
-        let mut operator = EffectiveAddressOperator::Add;
-        let mut memory_operand_sequence = EffectiveAddress::new();
-        let mut first_iteration = true;
-        for mut token in inner.split_inclusive(&['+', '-', '*']) {
-            let next_operator = if let Ok(next_operator) =
-                EffectiveAddressOperator::try_from(token.chars().last().unwrap())
-            {
-                // Remove the trailing operand and trim since whitespace is irrelevant.
-                token = &token[0..token.len() - 1];
-                next_operator
-            } else {
-                // Irrelevant: this is the final iteration.
+impl EffectiveAddress {
+    /// Parses an effective address (e.g. `[ebx+ecx*4+8]`) from its tokens.
+    pub(crate) fn parse(tokens: &[Token]) -> Result<Self, Error> {
+        let inner = match tokens {
+            [Token {
+                kind: TokenKind::OpenBracket,
+                ..
+            }, inner @ .., Token {
+                kind: TokenKind::CloseBracket,
+                ..
+            }] => inner,
+            [Token {
+                kind: TokenKind::OpenBracket,
+                ..
+            }, ..] => {
+                return Err(Error::CannotParseInstruction(
+                    "invalid effective address (expected \"]\" at end of operand)".into(),
+                ))
+            }
+            _ => {
+                return Err(Error::CannotParseInstruction(
+                    "invalid effective address (must start with \"[\")".into(),
+                ))
+            }
+        };
+
+        let mut inner = inner.iter().peekable();
+        // An effective address may begin with an operator (e.g. `[-4]`), other than multiplication.
+        let mut operator = match inner.peek().map(|token| token.kind) {
+            None => {
+                return Err(Error::CannotParseInstruction(
+                    "invalid effective address (no contents)".into(),
+                ))
+            }
+            Some(TokenKind::Punctuation("*")) => {
+                return Err(Error::CannotParseInstruction(
+                    "an effective address cannot begin with a multiplication operator".into(),
+                ))
+            }
+            Some(TokenKind::Punctuation("-")) => {
+                inner.next();
+                EffectiveAddressOperator::Subtract
+            }
+            Some(TokenKind::Punctuation("+")) => {
+                inner.next();
                 EffectiveAddressOperator::Add
-            };
+            }
+            Some(_) => EffectiveAddressOperator::Add,
+        };
 
-            // Handles the case where there is an operator at the start of the effective address
-            // e.g. [+1]. In this case, the first split will be "+" and we need to keep this for
-            // the next iteration and move on.
-            if token.len() == 0 && first_iteration {
-                if next_operator == EffectiveAddressOperator::Multiply {
+        let mut effective_address = EffectiveAddress::new();
+        loop {
+            let operand = match inner.next() {
+                Some(Token {
+                    kind: TokenKind::Number(word) | TokenKind::Identifier(word),
+                    ..
+                }) => EffectiveAddressOperand::try_from(&NasmStr(&word.to_lowercase()))?,
+                token => {
+                    return Err(Error::CannotParseInstruction(format!(
+                        "invalid effective address (expected a register or a number, found {:?})",
+                        token.map(|token| token.kind)
+                    )))
+                }
+            };
+            if let EffectiveAddressOperand::Register(_) = &operand {
+                if operator == EffectiveAddressOperator::Subtract
+                    || operator == EffectiveAddressOperator::Multiply
+                {
                     return Err(Error::CannotParseInstruction(
-                        "an effective address cannot begin with a multiplication operator".into(),
+                        "invalid effective address (registers can only be added together)".into(),
                     ));
                 }
-                continue;
             }
+            effective_address.try_push(operator, operand)?;
 
-            token = token.trim();
-            let operand = EffectiveAddressOperand::try_from(&NasmStr(token))?;
-            match &operand {
-                EffectiveAddressOperand::Immediate(immediate) => {
-                    if operator == EffectiveAddressOperator::Multiply && immediate.0 > 9 {
-                        return Err(Error::CannotParseInstruction(format!(
-                            "invalid effective address (scale can be at most 9, was {})",
-                            immediate.0
-                        )));
-                    }
-                }
-                EffectiveAddressOperand::Register(_) => {
-                    if operator == EffectiveAddressOperator::Subtract
-                        || operator == EffectiveAddressOperator::Multiply
-                    {
-                        return Err(Error::CannotParseInstruction(
-                            "invalid effective address (registers can only be added together)"
-                                .into(),
-                        ));
-                    }
+            operator = match inner.next().map(|token| token.kind) {
+                None => break,
+                Some(TokenKind::Punctuation("+")) => EffectiveAddressOperator::Add,
+                Some(TokenKind::Punctuation("-")) => EffectiveAddressOperator::Subtract,
+                Some(TokenKind::Punctuation("*")) => EffectiveAddressOperator::Multiply,
+                Some(token) => {
+                    return Err(Error::CannotParseInstruction(format!(
+                        "invalid effective address (expected \"+\", \"-\", or \"*\", found \
+                         {token:?})"
+                    )))
                 }
-            }
-            memory_operand_sequence.try_push(operator, operand)?;
-            operator = next_operator;
-            first_iteration = false;
+            };
         }
 
-        Ok(memory_operand_sequence)
+        effective_address.normalize()
     }
 }
 
@@ -1242,12 +2512,15 @@ impl<'a> TryFrom<&'a OperandType> for &'a EffectiveAddress {
             OperandType::Register(_) => Err(Error::CannotCovertType(
                 "a register was provided when a memory reference was expected".into(),
             )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "a far pointer was provided when a memory reference was expected".into(),
+            )),
         }
     }
 }
 
-// FIXME: Should likely have Immediate8/16/32 variants in order to enforce sizes when executing
-//        instructions.
+/// An immediate as it is written, before the size that it is used as is known. Once it is, it can be
+/// converted into an [`Immediate8`], [`Immediate16`], or [`Immediate32`].
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Immediate(pub u32);
 
@@ -1271,87 +2544,164 @@ impl Immediate {
     }
 }
 
+impl Immediate {
+    /// Whether the immediate can be used as the given size without losing any of its value, which
+    /// it can if it is within either the signed or the unsigned range of the size (e.g. -128 to
+    /// 255 for a byte). If it is sign-extended, then it must be within the signed range, as e.g.
+    /// `add eax, byte 0xff` subtracts 1.
+    pub fn fits(&self, size: Size, sign_extended: bool) -> bool {
+        let bits = size as u32;
+        if bits >= 32 {
+            return true;
+        }
+
+        let signed = self.0 as i32 as i64;
+        let min = -(1 << (bits - 1));
+        let max = if sign_extended {
+            (1 << (bits - 1)) - 1
+        } else {
+            (1 << bits) - 1
+        };
+        (min..=max).contains(&signed)
+    }
+}
+
 impl TryFrom<&NasmStr<'_>> for Immediate {
     type Error = Error;
 
+    /// Parses a numeric literal, or otherwise a constant expression made up of numeric literals
+    /// (e.g. `4*2+1`).
     fn try_from(value: &NasmStr) -> Result<Self, Self::Error> {
+        match Self::parse_literal(value.0) {
+            Ok(immediate) => Ok(immediate),
+            Err(_) if value.0.contains(|c| expression::is_operator(c) || c == '(') => {
+                expression::evaluate(value.0).map(Immediate)
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl Immediate {
+    /// Parses a single numeric literal, in any of the formats supported by NASM.
+    pub(crate) fn parse_literal(value: &str) -> Result<Self, Error> {
         // 200          ; decimal
         // 0200         ; still decimal - the leading 0 does not make it octal
         // 0000000200   ; valid
         // 0200d        ; explicitly decimal - d suffix
+        // 0200t        ; also decimal - t suffix
         // 0d200        ; also decimal - 0d prefex
+        // 0t200        ; also decimal - 0t prefix
         // 00d200       ; invalid
         // 0c8h         ; hex - h suffix, but leading 0 is required because c8h looks like a var
         // 0xc8         ; hex - the classic 0x prefix
         // 0hc8         ; hex - for some reason NASM likes 0h
         // 310q         ; octal - q suffix
+        // 310o         ; octal - o suffix
         // 0q310        ; octal - 0q prefix
+        // 0o310        ; octal - 0o prefix
         // 11001000b    ; binary - b suffix
-        // 0b1100_1000
-        // 0d prefix/d suffix = decimal
-        // 0q prefix/q suffix = octal
-        // 0..h               = hex
-        // ..h (where first char is numberic) = hex
-        // 0x...              = hex
-        // 0h...              = hex
-        let parse = |trimmed_value: &str, radix: u32, radix_name: &str| {
-            let parsed = u32::from_str_radix(trimmed_value, radix).map_err(|_| {
-                Error::CannotParseInstruction(format!(
-                    "could not parse {} as {}",
-                    trimmed_value, radix_name
-                ))
-            })?;
-            return Ok(Immediate(parsed));
+        // 11001000y    ; binary - y suffix
+        // 0b1100_1000  ; binary - 0b prefix
+        // 0y1100_1000  ; binary - 0y prefix
+        // 0d200h       ; hex - when both a prefix and a suffix are present, the larger radix wins
+        //                (as `0d200` is a valid hex number), which is what NASM does
+        // 0x200h       ; invalid - the radix is given twice
+        let malformed = |reason: String| {
+            Error::CannotParseInstruction(format!("\"{value}\" is not a valid number, as {reason}"))
         };
 
-        let to_parse = value.0.replace('_', "");
-
-        if to_parse.len() > 1 {
-            let value_without_suffix = &to_parse[..to_parse.len() - 1];
-            if to_parse.ends_with("b") {
-                return parse(value_without_suffix, 2, "binary");
-            }
+        let (negative, to_parse) = match value.strip_prefix('-') {
+            Some(magnitude) => (true, magnitude),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let to_parse = to_parse.replace('_', "");
+        if !to_parse.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(malformed("it does not begin with a digit".into()));
+        }
 
-            if to_parse.ends_with("q") {
-                return parse(value_without_suffix, 8, "octal");
-            }
+        let radix_of = |letter: u8, is_prefix: bool| match letter.to_ascii_lowercase() {
+            b'b' | b'y' => Some(2),
+            b'o' | b'q' => Some(8),
+            b'd' | b't' => Some(10),
+            b'h' => Some(16),
+            b'x' if is_prefix => Some(16),
+            _ => None,
+        };
+        let bytes = to_parse.as_bytes();
+        let prefix_radix = match bytes {
+            [b'0', letter, _, ..] => radix_of(*letter, true),
+            _ => None,
+        };
+        let suffix_radix = match bytes {
+            [_, .., letter] => radix_of(*letter, false),
+            _ => None,
+        };
 
-            if to_parse.ends_with("d") {
-                return parse(value_without_suffix, 10, "decimal");
+        let (radix, digits) = match (prefix_radix, suffix_radix) {
+            (Some(prefix), Some(suffix)) if prefix == suffix => {
+                return Err(malformed(
+                    "its radix is given by both a prefix and a suffix".into(),
+                ));
             }
+            (Some(prefix), suffix) if Some(prefix) > suffix => (prefix, &to_parse[2..]),
+            (_, Some(suffix)) => (suffix, &to_parse[..to_parse.len() - 1]),
+            _ => (10, to_parse.as_str()),
+        };
 
-            if to_parse.chars().nth(0).unwrap().is_numeric() && to_parse.ends_with("h") {
-                return parse(value_without_suffix, 16, "hexadecimal");
-            }
+        let radix_name = match radix {
+            2 => "a binary",
+            8 => "an octal",
+            10 => "a decimal",
+            _ => "a hexadecimal",
+        };
+        if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+            return Err(malformed(format!("'{c}' is not {radix_name} digit")));
         }
 
-        if to_parse.len() > 2 {
-            let prefix = to_parse[0..=1].to_lowercase();
-            let value_without_prefix = &to_parse[2..];
-            match prefix.as_str() {
-                "0b" => return parse(value_without_prefix, 2, "binary"),
-                "0q" => return parse(value_without_prefix, 8, "octal"),
-                "0d" => return parse(value_without_prefix, 10, "decimal"),
-                "0h" | "0x" => return parse(value_without_prefix, 16, "hexadecimal"),
-                _ => (),
+        // Negative numbers are stored in two's complement, e.g. an input of -1 results in the
+        // maximum unsigned value.
+        let magnitude = u32::from_str_radix(digits, radix)
+            .ok()
+            .filter(|&magnitude| !negative || magnitude <= 1 << 31)
+            .ok_or_else(|| malformed("it does not fit in 32 bits".into()))?;
+        Ok(Immediate(if negative {
+            magnitude.wrapping_neg()
+        } else {
+            magnitude
+        }))
+    }
+}
+
+macro_rules! sized_immediate {
+    ($name:ident, $type:ty, $size:ident) => {
+        #[doc = concat!(
+                                    "An immediate which is used as a ",
+                                    stringify!($size),
+                                    ", e.g. by an instruction whose operand format takes an `imm",
+                                    stringify!($type),
+                                    "`.",
+                                )]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct $name(pub $type);
+
+        impl TryFrom<&OperandType> for $name {
+            type Error = Error;
+
+            /// Values which do not fit are truncated, as they would be if they were encoded. They
+            /// are warned about when the instruction is parsed (see
+            /// `InstructionOperandFormat::immediates`).
+            fn try_from(operand_type: &OperandType) -> Result<Self, Self::Error> {
+                <&Immediate>::try_from(operand_type).map(|immediate| Self(immediate.0 as $type))
             }
         }
-
-        // We don't directly parse into `u32` because that would cause negative values to be
-        // invalid. We therefore need to first parse into an `i64` to preserve the full range of
-        // values possible, and then convert it to be unsigned, before then finally cast it to
-        // `u32`. I.e. an input of -1 should result in the maximum unsigned value.
-        // FIXME: Avoid going via `i64`.
-        let parsed = to_parse.parse::<i64>().map_err(|_| {
-            Error::CannotParseInstruction(format!("cannot parse {} as i64", to_parse))
-        })?;
-
-        let parsed = parsed.as_unsigned() as u32;
-
-        Ok(Immediate(parsed))
-    }
+    };
 }
 
+sized_immediate!(Immediate8, u8, byte);
+sized_immediate!(Immediate16, u16, word);
+sized_immediate!(Immediate32, u32, dword);
+
 impl<'a> TryFrom<&'a OperandType> for &'a Immediate {
     type Error = Error;
 
@@ -1364,6 +2714,47 @@ impl<'a> TryFrom<&'a OperandType> for &'a Immediate {
             OperandType::Register(_) => Err(Error::CannotCovertType(
                 "a register was provided when an immediate value was expected".into(),
             )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "a far pointer was provided when an immediate value was expected".into(),
+            )),
+        }
+    }
+}
+
+/// A far pointer (`ptr16:32`) which is given directly as the operand of a far `JMP` or `CALL`,
+/// written as a selector and an offset separated by a colon, e.g. `jmp 0x08:0x1000`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FarPointer {
+    pub selector: u16,
+    pub offset: u32,
+}
+
+impl fmt::Display for FarPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            Number(self.selector as u32),
+            Number(self.offset)
+        )
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for &'a FarPointer {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        match operand_type {
+            OperandType::Immediate(_) => Err(Error::CannotCovertType(
+                "an immediate was provided when a far pointer was expected".into(),
+            )),
+            OperandType::Memory(_) => Err(Error::CannotCovertType(
+                "a memory reference was provided when a far pointer was expected".into(),
+            )),
+            OperandType::Register(_) => Err(Error::CannotCovertType(
+                "a register was provided when a far pointer was expected".into(),
+            )),
+            OperandType::FarPointer(far_pointer) => Ok(far_pointer),
         }
     }
 }
@@ -1373,6 +2764,7 @@ pub enum OperandType {
     Immediate(Immediate),
     Memory(EffectiveAddress),
     Register(Register),
+    FarPointer(FarPointer),
 }
 
 impl OperandType {
@@ -1402,22 +2794,55 @@ impl TryFrom<&NasmStr<'_>> for OperandType {
     type Error = Error;
 
     fn try_from(nasm_str: &NasmStr<'_>) -> Result<Self, Self::Error> {
-        if let Ok(immediate) = Immediate::try_from(nasm_str) {
-            return Ok(Self::Immediate(immediate));
-        }
-
-        if let Ok(effective_address) = EffectiveAddress::try_from(nasm_str) {
-            return Ok(Self::Memory(effective_address));
-        }
+        Self::parse(&lexer::tokenize(nasm_str.0)?, nasm_str.0)
+    }
+}
 
-        if let Ok(register) = Register::try_from(nasm_str) {
-            return Ok(Self::Register(register));
+impl OperandType {
+    /// Parses a register, memory reference, or immediate from its tokens, which were read from
+    /// `text` (which is only used to describe an error).
+    pub(crate) fn parse(tokens: &[Token], text: &str) -> Result<Self, Error> {
+        match tokens {
+            [Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }] if Register::try_from(&NasmStr(name)).is_ok() => {
+                Register::try_from(&NasmStr(name)).map(Self::Register)
+            }
+            [Token {
+                kind: TokenKind::OpenBracket,
+                ..
+            }, ..] => EffectiveAddress::parse(tokens).map(Self::Memory),
+            _ => {
+                let error = || {
+                    Error::CannotParseInstruction(format!(
+                        "cannot convert \"{text}\" (NASM format) into a valid operand type"
+                    ))
+                };
+                // A malformed number is reported as such, rather than as an invalid operand.
+                for token in tokens {
+                    if let TokenKind::Number(number) = token.kind {
+                        Immediate::parse_literal(number)?;
+                    }
+                }
+                let colon = tokens
+                    .iter()
+                    .position(|token| token.kind == TokenKind::Punctuation(":"));
+                let Some(colon) = colon else {
+                    return expression::evaluate_tokens(tokens)
+                        .map(|value| Self::Immediate(Immediate(value)))
+                        .map_err(|_| error());
+                };
+                let selector =
+                    expression::evaluate_tokens(&tokens[..colon]).map_err(|_| error())?;
+                let offset =
+                    expression::evaluate_tokens(&tokens[colon + 1..]).map_err(|_| error())?;
+                Ok(Self::FarPointer(FarPointer {
+                    selector: u16::try_from(selector).map_err(|_| error())?,
+                    offset,
+                }))
+            }
         }
-
-        Err(Error::CannotParseInstruction(format!(
-            "cannot convert \"{}\" (NASM format) into a valid operand type",
-            nasm_str.0
-        )))
     }
 }
 
@@ -1426,6 +2851,8 @@ pub enum Size {
     Byte = 8,
     Word = 16,
     Dword = 32,
+    Qword = 64,
+    Oword = 128,
 }
 
 impl TryFrom<&NasmStr<'_>> for Size {
@@ -1437,6 +2864,8 @@ impl TryFrom<&NasmStr<'_>> for Size {
             "BYTE" => Ok(Byte),
             "WORD" => Ok(Word),
             "DWORD" => Ok(Dword),
+            "QWORD" => Ok(Qword),
+            "OWORD" => Ok(Oword),
             value @ _ => Err(Error::CannotParseInstruction(format!(
                 "cannot convert {} into a valid size",
                 value
@@ -1445,10 +2874,27 @@ impl TryFrom<&NasmStr<'_>> for Size {
     }
 }
 
+impl fmt::Display for Size {
+    /// Writes the keyword for the size, as used in a size directive (e.g. `dword`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyword = match self {
+            Size::Byte => "byte",
+            Size::Word => "word",
+            Size::Dword => "dword",
+            Size::Qword => "qword",
+            Size::Oword => "oword",
+        };
+        f.write_str(keyword)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Operand {
     pub(crate) operand_type: OperandType,
     pub(crate) size_directive: Option<Size>,
+    /// Whether the size directive was qualified with `strict` (e.g. `strict byte 3`), in which case
+    /// only an operand format of exactly that size is matched.
+    pub(crate) strict: bool,
 }
 
 impl Operand {
@@ -1456,6 +2902,33 @@ impl Operand {
         Self {
             operand_type,
             size_directive,
+            strict: false,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    /// Writes the operand in NASM syntax, along with its size directive. An immediate which has a
+    /// size directive smaller than a dword is sign-extended, and so is written as signed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.strict {
+            f.write_str("strict ")?;
+        }
+        if let Some(size) = self.size_directive {
+            write!(f, "{size} ")?;
+        }
+        match &self.operand_type {
+            OperandType::Immediate(immediate) => match self.size_directive {
+                Some(size) if size < Size::Dword && (immediate.0 as i32) < 0 => {
+                    write!(f, "-{}", Number(immediate.0.wrapping_neg()))
+                }
+                _ => write!(f, "{}", Number(immediate.0)),
+            },
+            OperandType::Memory(effective_address) => write!(f, "{effective_address}"),
+            OperandType::Register(register) => {
+                write!(f, "{}", register.to_string().to_lowercase())
+            }
+            OperandType::FarPointer(far_pointer) => write!(f, "{far_pointer}"),
         }
     }
 }
@@ -1464,31 +2937,55 @@ impl TryFrom<&NasmStr<'_>> for Operand {
     type Error = Error;
 
     fn try_from(value: &NasmStr<'_>) -> Result<Self, Self::Error> {
-        let mut index = if let Some(index) = value.0.find('[') {
-            index
-        } else if let Some(index) = value.0.find(' ') {
-            index
-        } else {
-            0
-        };
+        Self::parse_nasm(value, &mut Vec::new())
+    }
+}
 
-        let minimum_size_directive_length = 4;
-        let mut size_directive = if index >= minimum_size_directive_length {
-            Size::try_from(&NasmStr(&value.0[..index].trim())).ok()
-        } else {
-            None
+impl Operand {
+    /// Parses an operand in NASM syntax, adding a warning to `warnings` for anything which is
+    /// valid but likely to be a mistake.
+    pub(crate) fn parse_nasm(
+        value: &NasmStr<'_>,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Self, Error> {
+        let tokens = lexer::tokenize(value.0)?;
+        let (strict, tokens) = match tokens.split_first() {
+            Some((
+                Token {
+                    kind: TokenKind::Identifier(keyword),
+                    ..
+                },
+                rest,
+            )) if keyword.eq_ignore_ascii_case("strict") => (true, rest),
+            _ => (false, tokens.as_slice()),
+        };
+        let (mut size_directive, tokens) = match tokens.split_first() {
+            Some((
+                Token {
+                    kind: TokenKind::Size(size),
+                    ..
+                },
+                rest,
+            )) if !rest.is_empty() => (Some(*size), rest),
+            _ if strict => {
+                return Err(Error::CannotParseInstruction(format!(
+                    "STRICT must be followed by a size directive and an operand in \"{}\"",
+                    value.0
+                )))
+            }
+            _ => (None, tokens),
         };
 
-        if size_directive.is_none() {
-            index = 0;
-        }
-
-        let operand_type = OperandType::try_from(&NasmStr(&value.0[index..].trim()))?;
+        let operand_type = OperandType::parse(tokens, value.0)?;
         if let Some(size) = &size_directive {
             if let OperandType::Register(register) = &operand_type {
                 if size != &register.size() {
                     // Size directive does not match register size. NASM ignores the size directive
                     // in this case.
+                    warnings.push(Warning::IgnoredSizeDirective {
+                        size: *size,
+                        register: register.clone(),
+                    });
                     size_directive = None;
                 }
             }
@@ -1497,6 +2994,7 @@ impl TryFrom<&NasmStr<'_>> for Operand {
         Ok(Self {
             operand_type,
             size_directive,
+            strict: strict && size_directive.is_some(),
         })
     }
 }
@@ -1504,15 +3002,366 @@ impl TryFrom<&NasmStr<'_>> for Operand {
 #[derive(Debug)]
 pub struct NasmStr<'a>(pub &'a str);
 
+/// Assembly in AT&T syntax, as used by GAS (e.g. `movl %eax, 4(%ebx,%ecx,2)`). Registers are
+/// prefixed with `%` and immediates with `$`, memory operands are written as
+/// `displacement(base, index, scale)`, the destination is the last operand, and the size of the
+/// operands may be given as a suffix on the mnemonic (`b`, `w`, or `l`).
+#[derive(Debug)]
+pub struct AttStr<'a>(pub &'a str);
+
+/// Assembly in the Intel syntax used by MASM, which differs from NASM in that the size of a
+/// memory operand is followed by `PTR` (e.g. `mov eax, dword ptr [ebx]`), and in that the suffixes
+/// of numbers are case-insensitive (e.g. `0C8H`), with `o` also denoting octal.
+#[derive(Debug)]
+pub struct MasmStr<'a>(pub &'a str);
+
+/// The syntaxes in which assembly can be written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum Syntax {
+    #[default]
+    Nasm,
+    Att,
+    Masm,
+}
+
+impl TryFrom<&MasmStr<'_>> for Operand {
+    type Error = Error;
+
+    fn try_from(value: &MasmStr<'_>) -> Result<Self, Self::Error> {
+        Self::parse_masm(value, &mut Vec::new())
+    }
+}
+
+impl Operand {
+    /// Parses an operand in MASM syntax by translating it into NASM syntax. See
+    /// [`Operand::parse_nasm`].
+    pub(crate) fn parse_masm(
+        value: &MasmStr<'_>,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Self, Error> {
+        let tokens = lexer::tokenize(value.0)?;
+        let mut words = Vec::with_capacity(tokens.len());
+        for (i, token) in tokens.iter().enumerate() {
+            match token.kind {
+                // `PTR` only ever follows a size, and is otherwise invalid.
+                TokenKind::Identifier(word) if word.eq_ignore_ascii_case("ptr") => {
+                    if !matches!(
+                        i.checked_sub(1).map(|i| tokens[i].kind),
+                        Some(TokenKind::Size(_))
+                    ) {
+                        return Err(Error::CannotParseInstruction(format!(
+                            "PTR must follow the size of an operand in \"{}\"",
+                            value.0
+                        )));
+                    }
+                }
+                _ => words.push(&value.0[token.span.clone()]),
+            }
+        }
+
+        Operand::parse_nasm(&NasmStr(&words.join(" ")), warnings)
+    }
+}
+
+impl TryFrom<&AttStr<'_>> for Register {
+    type Error = Error;
+
+    fn try_from(value: &AttStr<'_>) -> Result<Self, Self::Error> {
+        match value.0.strip_prefix('%') {
+            Some(register) => Register::try_from(&NasmStr(register)),
+            None => Err(Error::CannotParseInstruction(format!(
+                "\"{}\" is not a register (registers must be prefixed with %)",
+                value.0
+            ))),
+        }
+    }
+}
+
+impl TryFrom<&AttStr<'_>> for Immediate {
+    type Error = Error;
+
+    fn try_from(value: &AttStr<'_>) -> Result<Self, Self::Error> {
+        match value.0.strip_prefix('$') {
+            Some(immediate) => Immediate::try_from(&NasmStr(immediate)),
+            None => Err(Error::CannotParseInstruction(format!(
+                "\"{}\" is not an immediate (immediates must be prefixed with $)",
+                value.0
+            ))),
+        }
+    }
+}
+
+impl TryFrom<&AttStr<'_>> for EffectiveAddress {
+    type Error = Error;
+
+    /// Parses a memory operand, which is written as `displacement(base, index, scale)`, where
+    /// everything is optional except for either the displacement or the base (e.g. `(%eax)`,
+    /// `-4(%ebp)`, `(,%ecx,4)`, or `0x100`).
+    fn try_from(value: &AttStr<'_>) -> Result<Self, Self::Error> {
+        let (displacement, registers) = match value.0.split_once('(') {
+            Some((displacement, registers)) => {
+                let registers = registers.strip_suffix(')').ok_or_else(|| {
+                    Error::CannotParseInstruction(format!(
+                        "invalid effective address \"{}\" (expected \")\" at end of operand)",
+                        value.0
+                    ))
+                })?;
+                (displacement.trim(), registers)
+            }
+            None => (value.0.trim(), ""),
+        };
+
+        // The parts are translated into an effective address in NASM syntax, such that they are
+        // validated in the same way.
+        let mut parts = Vec::new();
+        if !displacement.is_empty() {
+            parts.push(Immediate::try_from(&NasmStr(displacement))?.0.to_string());
+        }
+
+        let mut registers = registers.split(',').map(str::trim);
+        let base = registers.next().filter(|base| !base.is_empty());
+        let index = registers.next();
+        let scale = registers.next();
+        if registers.next().is_some() {
+            return Err(Error::InvalidEffectiveAddress(format!(
+                "\"{}\" has more than a base, index, and scale",
+                value.0
+            )));
+        }
+
+        if let Some(base) = base {
+            parts.push(Register::try_from(&AttStr(base))?.to_string());
+        }
+        match (index, scale) {
+            (Some(index), scale) if !index.is_empty() => parts.push(format!(
+                "{}*{}",
+                Register::try_from(&AttStr(index))?,
+                scale.unwrap_or("1")
+            )),
+            (_, None) => (),
+            _ => {
+                return Err(Error::InvalidEffectiveAddress(format!(
+                    "\"{}\" has a scale without an index",
+                    value.0
+                )))
+            }
+        }
+
+        if parts.is_empty() {
+            return Err(Error::InvalidEffectiveAddress(format!(
+                "\"{}\" has neither a displacement nor a base",
+                value.0
+            )));
+        }
+        EffectiveAddress::try_from(&NasmStr(&format!("[{}]", parts.join("+"))))
+    }
+}
+
+impl TryFrom<&AttStr<'_>> for OperandType {
+    type Error = Error;
+
+    fn try_from(value: &AttStr<'_>) -> Result<Self, Self::Error> {
+        match value.0.chars().next() {
+            Some('%') => Register::try_from(value).map(Self::Register),
+            Some('$') => Immediate::try_from(value).map(Self::Immediate),
+            Some('*') => Err(Error::CannotParseInstruction(format!(
+                "indirect branches (\"{}\") are not supported",
+                value.0
+            ))),
+            _ => EffectiveAddress::try_from(value).map(Self::Memory),
+        }
+    }
+}
+
+impl TryFrom<&AttStr<'_>> for Operand {
+    type Error = Error;
+
+    /// Parses an operand, which never has a size directive, as the size is instead given by the
+    /// suffix of the mnemonic.
+    fn try_from(value: &AttStr<'_>) -> Result<Self, Self::Error> {
+        Ok(Self::new(OperandType::try_from(value)?, None))
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Instruction {
     pub mnemonic: String,
     pub operands: Operands,
     pub cpu_function: CpuFunction,
+    pub repeat_prefix: Option<RepeatPrefix>,
+    pub lock_prefix: bool,
+    /// Whether the `LOCK` prefix is permitted on this instruction. If it is used anyway, then a #UD
+    /// exception is raised when the instruction is executed.
+    lockable: bool,
+    /// The approximate number of cycles which the instruction takes.
+    cycles: u32,
+}
+
+impl Instruction {
+    /// Executes the instruction, with its prefixes applied for the duration of the instruction. If
+    /// an access made by the instruction faults, then the instruction is abandoned: its changes to
+    /// the registers are undone (including to EIP, if it was advanced past the instruction by
+    /// `Cpu::step`), and the fault is raised, unless it is a stack overflow, which is left for
+    /// `Machine::run` to report. Otherwise, if an access hit a data breakpoint, then a #DB
+    /// exception is raised once the instruction has completed.
+    pub fn execute(&self, cpu: &mut Cpu) {
+        let eip = *cpu.instruction_eip.get_or_insert(cpu.registers.get_eip());
+        cpu.data_breakpoint_hits.set(0);
+        cpu.repeat_prefix = self.repeat_prefix;
+        cpu.segment_override = cpu.pending_segment_override.take();
+        let registers = cpu.registers.clone();
+        // A locked instruction must have a memory destination.
+        let destination = self
+            .operands
+            .0
+            .first()
+            .and_then(|operand| <&EffectiveAddress>::try_from(&operand.operand_type).ok());
+        let result = match (self.lock_prefix, destination) {
+            (false, _) => (self.cpu_function)(cpu, &self.operands),
+            (true, Some(effective_address)) if self.lockable => {
+                // The address is resolved up front, as the instruction may modify the registers
+                // which it is computed from (e.g. `lock xadd [eax], eax`).
+                let address = effective_address.resolve(cpu);
+                let result = (self.cpu_function)(cpu, &self.operands);
+                cpu.observe_locked_cycle(address);
+                result
+            }
+            (true, _) => {
+                cpu.raise_exception(CpuException::InvalidOpcode);
+                Ok(())
+            }
+        };
+        // An access which failed without latching a more specific fault (e.g. one which is not
+        // permitted by the memory's protection) raises #GP.
+        if result.is_err() {
+            cpu.latch_fault(CpuException::GeneralProtection);
+        }
+        cpu.instruction_eip = None;
+        if let Some(fault) = cpu.fault.take() {
+            cpu.registers = registers;
+            cpu.registers.set_eip(eip);
+            // A stack overflow stops the machine, rather than being delivered.
+            if cpu.stack_overflow.is_none() {
+                cpu.raise_exception(fault);
+            }
+        } else if cpu.data_breakpoint_hits.get() != 0 {
+            cpu.raise_breakpoint(cpu.data_breakpoint_hits.take());
+        }
+        cpu.update_paging_entries();
+        cpu.repeat_prefix = None;
+        cpu.segment_override = None;
+        cpu.elapse(self.cycles as u64);
+    }
+
+    /// The approximate number of cycles which the instruction takes to execute.
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Writes the instruction in NASM syntax, e.g. `lock add dword [eax], byte -1`. The size of a
+    /// memory operand is left out where it is implied by a register operand, as it is when the
+    /// instruction is parsed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lock_prefix {
+            f.write_str("lock ")?;
+        }
+        match self.repeat_prefix {
+            Some(RepeatPrefix::Rep) => f.write_str("rep ")?,
+            Some(RepeatPrefix::Repe) => f.write_str("repe ")?,
+            Some(RepeatPrefix::Repne) => f.write_str("repne ")?,
+            None => (),
+        }
+        f.write_str(&self.mnemonic.to_lowercase())?;
+
+        let registers: Vec<_> = self
+            .operands
+            .0
+            .iter()
+            .filter_map(|operand| match &operand.operand_type {
+                OperandType::Register(register) => Some(register),
+                _ => None,
+            })
+            .collect();
+        for (i, operand) in self.operands.0.iter().enumerate() {
+            f.write_str(if i == 0 { " " } else { ", " })?;
+            let is_implied = |size: Size| {
+                registers
+                    .iter()
+                    .any(|register| !register.is_general_purpose() || register.size() == size)
+            };
+            match (&operand.operand_type, operand.size_directive) {
+                (OperandType::Memory(_), Some(size)) if is_implied(size) => {
+                    write!(f, "{}", Operand::new(operand.operand_type.clone(), None))?
+                }
+                _ => write!(f, "{operand}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A prefix which causes a string instruction to be repeated, using ECX as the counter. `CMPS` and
+/// `SCAS` may additionally stop repeating early depending on the ZF flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatPrefix {
+    /// `REP` (0xF3), which repeats the instruction until ECX is 0. It shares its encoding with
+    /// `REPE`, and therefore behaves as `REPE` for `CMPS` and `SCAS`.
+    Rep,
+    /// `REPE`/`REPZ` (0xF3), which also stops repeating `CMPS` and `SCAS` once ZF is clear.
+    Repe,
+    /// `REPNE`/`REPNZ` (0xF2), which also stops repeating `CMPS` and `SCAS` once ZF is set.
+    Repne,
+}
+
+impl TryFrom<&NasmStr<'_>> for RepeatPrefix {
+    type Error = Error;
+
+    fn try_from(value: &NasmStr<'_>) -> Result<Self, Self::Error> {
+        match value.0.to_uppercase().as_str() {
+            "REP" => Ok(Self::Rep),
+            "REPE" | "REPZ" => Ok(Self::Repe),
+            "REPNE" | "REPNZ" => Ok(Self::Repne),
+            value => Err(Error::CannotParseInstruction(format!(
+                "cannot convert {} into a valid repeat prefix",
+                value
+            ))),
+        }
+    }
 }
 
+#[derive(Clone, Debug)]
 pub struct Operands(pub Vec<Operand>);
 
 impl Operands {
+    /// Infers the operand size from the general-purpose register operands and any memory operands
+    /// with a size directive, which is the size that any operands without a size directive take on.
+    /// A size can only be inferred if there is at least one such operand and they are all of the
+    /// same size, e.g. nothing is inferred for `out dx, al`, but `dword` is inferred for
+    /// `add dword [eax], 4`. Other registers do not imply the size of the other operands, e.g. in
+    /// `movd mm0, [eax]` the memory operand is a dword.
+    pub(crate) fn infer_size(&self) -> Option<Size> {
+        let mut sizes = self
+            .0
+            .iter()
+            .filter_map(|operand| match &operand.operand_type {
+                OperandType::Register(register) if register.is_general_purpose() => {
+                    Some(register.size())
+                }
+                OperandType::Memory(_) => operand.size_directive,
+                _ => None,
+            });
+
+        let size = sizes.next()?;
+        if sizes.all(|other| other == size) {
+            Some(size)
+        } else {
+            None
+        }
+    }
+
     /// Unwrap the operand at the given index as an `Immediate`, otherwise panic.
     pub(crate) fn unwrap_immediate(&self, index: usize) -> &Immediate {
         &self.0.get(index).unwrap().operand_type.unwrap_immediate()
@@ -1572,29 +3421,398 @@ impl<'a> TryFrom<&NasmStr<'a>> for Instruction {
     type Error = Error;
 
     fn try_from(instruction: &NasmStr) -> Result<Self, Self::Error> {
-        let (mnemonic, remainder) =
-            instruction
-                .0
-                .split_once(" ")
-                .ok_or(Error::CannotParseInstruction(
-                    "no mnemonic available".into(),
-                ))?;
-
-        let operands: Vec<_> = remainder
-            .trim()
-            .split(",")
-            .map(|o| Operand::try_from(&NasmStr(o.trim())))
-            .collect::<Result<_, _>>()?;
-        let operands = Operands(operands);
-
-        let cpu_function =
-            InstructionDescriptor::lookup_using_mnemonic_and_operands(mnemonic, &operands)?;
+        Self::parse(
+            instruction.0,
+            Syntax::Nasm,
+            &SymbolTable::default(),
+            &mut Vec::new(),
+        )
+    }
+}
 
-        Ok(Self {
-            mnemonic: mnemonic.into(),
+impl<'a> TryFrom<&AttStr<'a>> for Instruction {
+    type Error = Error;
+
+    fn try_from(instruction: &AttStr) -> Result<Self, Self::Error> {
+        Self::parse(
+            instruction.0,
+            Syntax::Att,
+            &SymbolTable::default(),
+            &mut Vec::new(),
+        )
+    }
+}
+
+impl<'a> TryFrom<&MasmStr<'a>> for Instruction {
+    type Error = Error;
+
+    fn try_from(instruction: &MasmStr) -> Result<Self, Self::Error> {
+        Self::parse(
+            instruction.0,
+            Syntax::Masm,
+            &SymbolTable::default(),
+            &mut Vec::new(),
+        )
+    }
+}
+
+impl Instruction {
+    /// Parses an instruction in the given syntax, in which any symbols (e.g. labels) that are used
+    /// as operands are replaced by their values. Anything which is valid but likely to be a mistake
+    /// is added to `warnings`.
+    pub(crate) fn parse(
+        instruction: &str,
+        syntax: Syntax,
+        symbols: &SymbolTable,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Self, Error> {
+        // The `LOCK` prefix (e.g. `lock add [eax], 1`) and repeat prefixes (e.g. `rep movsb`) are
+        // written as separate words before the mnemonic, in any order, and each at most once.
+        let mut instruction = instruction.trim();
+        let mut lock_prefix = false;
+        let mut repeat_prefix = None;
+        while let Some((word, remainder)) = instruction.split_once(char::is_whitespace) {
+            if !lock_prefix && word.eq_ignore_ascii_case("lock") {
+                lock_prefix = true;
+            } else if let (None, Ok(prefix)) =
+                (repeat_prefix, RepeatPrefix::try_from(&NasmStr(word)))
+            {
+                repeat_prefix = Some(prefix);
+            } else {
+                break;
+            }
+            instruction = remainder.trim_start();
+        }
+
+        // Instructions such as `int3` have no operands, and therefore consist only of a mnemonic.
+        let (mnemonic, remainder) = instruction
+            .split_once(char::is_whitespace)
+            .unwrap_or((instruction, ""));
+        if mnemonic.is_empty() {
+            return Err(Error::CannotParseInstruction(
+                "no mnemonic available".into(),
+            ));
+        }
+
+        let remainder = symbols.substitute(remainder.trim());
+        let (mnemonic, operands) = match syntax {
+            Syntax::Nasm => {
+                let operands: Vec<_> = if remainder.is_empty() {
+                    Vec::new()
+                } else {
+                    remainder
+                        .split(',')
+                        .map(|o| Operand::parse_nasm(&NasmStr(o.trim()), warnings))
+                        .collect::<Result<_, _>>()?
+                };
+                (mnemonic.to_string(), Operands(operands))
+            }
+            Syntax::Att => Self::parse_att_operands(mnemonic, &remainder)?,
+            Syntax::Masm => {
+                let operands: Vec<_> = if remainder.is_empty() {
+                    Vec::new()
+                } else {
+                    remainder
+                        .split(',')
+                        .map(|o| Operand::parse_masm(&MasmStr(o.trim()), warnings))
+                        .collect::<Result<_, _>>()?
+                };
+                (mnemonic.to_string(), Operands(operands))
+            }
+        };
+
+        let (descriptor, map) = InstructionDescriptor::lookup(&mnemonic, &operands)?;
+        for &(operand, size, sign_extended) in map.instruction_operand_format.immediates() {
+            let immediate = operands.unwrap_immediate(operand);
+            if !immediate.fits(size, sign_extended) {
+                warnings.push(Warning::ImmediateOutOfRange {
+                    operand,
+                    value: immediate.0,
+                    size,
+                    sign_extended,
+                });
+            }
+        }
+
+        Ok(Self::new(
+            mnemonic,
+            descriptor,
+            map,
             operands,
-            cpu_function,
-        })
+            repeat_prefix,
+            lock_prefix,
+        ))
+    }
+
+    /// Creates an instruction which performs the given map of the descriptor on the operands.
+    pub(crate) fn new(
+        mnemonic: String,
+        descriptor: &InstructionDescriptor,
+        map: &OperandFunctionMap,
+        operands: Operands,
+        repeat_prefix: Option<RepeatPrefix>,
+        lock_prefix: bool,
+    ) -> Self {
+        Self {
+            mnemonic,
+            lockable: descriptor.is_lockable(&operands),
+            cycles: descriptor.cycles(&operands),
+            operands,
+            cpu_function: map.cpu_function,
+            repeat_prefix,
+            lock_prefix,
+        }
+    }
+
+    /// Parses the operands of an instruction in AT&T syntax into the order that they are given in
+    /// NASM syntax, along with the mnemonic without any size suffix. The size given by the suffix
+    /// is applied to each operand which is not a register.
+    fn parse_att_operands(mnemonic: &str, operands: &str) -> Result<(String, Operands), Error> {
+        // Commas also separate the base, index, and scale of memory operands.
+        let mut split = Vec::new();
+        let (mut depth, mut start) = (0, 0);
+        for (i, c) in operands.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    split.push(operands[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+        if !operands.is_empty() {
+            split.push(operands[start..].trim());
+        }
+
+        // The targets of branches are written without a `$`, despite being immediates.
+        let is_branch = {
+            let mnemonic = mnemonic.to_lowercase();
+            mnemonic.starts_with('j') || mnemonic.starts_with("loop") || mnemonic == "call"
+        };
+        let mut parsed = split
+            .into_iter()
+            .rev()
+            .map(|operand| match Immediate::try_from(&NasmStr(operand)) {
+                Ok(immediate) if is_branch => {
+                    Ok(Operand::new(OperandType::Immediate(immediate), None))
+                }
+                _ => Operand::try_from(&AttStr(operand)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The suffix is only removed if it is not part of the mnemonic itself (e.g. `movsb`). For
+        // string instructions, the suffix `l` corresponds to the `d` of the NASM mnemonic (e.g.
+        // `movsl` is `movsd`).
+        let exists = |mnemonic: &str| !lookup_instructions_by_mnemonic(mnemonic).is_empty();
+        if exists(mnemonic) {
+            return Ok((mnemonic.into(), Operands(parsed)));
+        }
+
+        let Some((stem, suffix)) = mnemonic
+            .char_indices()
+            .last()
+            .map(|(i, _)| mnemonic.split_at(i))
+        else {
+            return Ok((mnemonic.into(), Operands(parsed)));
+        };
+        let size = match suffix.to_lowercase().as_str() {
+            "b" => Size::Byte,
+            "w" => Size::Word,
+            "l" => Size::Dword,
+            _ => return Ok((mnemonic.into(), Operands(parsed))),
+        };
+
+        if size == Size::Dword && parsed.is_empty() && exists(&format!("{stem}d")) {
+            return Ok((format!("{stem}d"), Operands(parsed)));
+        }
+
+        if !exists(stem) {
+            return Err(Error::NoMatchingInstruction(format!(
+                "\"{mnemonic}\" does not correspond to any instruction"
+            )));
+        }
+        for operand in &mut parsed {
+            if !matches!(operand.operand_type, OperandType::Register(_)) {
+                operand.size_directive = Some(size);
+            }
+        }
+        Ok((stem.into(), Operands(parsed)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum MmxRegisterOrMemory64<'a> {
+    Register(&'a MmxRegister),
+    Memory(&'a EffectiveAddress),
+}
+
+impl MmxRegisterOrMemory64<'_> {
+    /// Reads the operand. A memory operand is read as a little-endian QWORD.
+    pub fn read(&self, cpu: &mut Cpu) -> Result<u64, Error> {
+        match self {
+            Self::Register(register) => Ok(cpu.fpu.read_mmx(register.index())),
+            Self::Memory(effective_address) => cpu.memory.read64(effective_address.translate(
+                cpu,
+                Size::Qword,
+                AccessKind::Read,
+            )?),
+        }
+    }
+
+    /// Writes the operand. A memory operand is written as a little-endian QWORD.
+    pub fn write(&self, cpu: &mut Cpu, value: u64) -> Result<(), Error> {
+        match self {
+            Self::Register(register) => {
+                cpu.fpu.write_mmx(register.index(), value);
+                Ok(())
+            }
+            Self::Memory(effective_address) => {
+                let address = effective_address.translate(cpu, Size::Qword, AccessKind::Write)?;
+                cpu.memory.write64(address, value)
+            }
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for MmxRegisterOrMemory64<'a> {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        match operand_type {
+            OperandType::Immediate(_) => Err(Error::CannotCovertType(
+                "cannot convert an immediate value into a MmxRegisterOrMemory64".into(),
+            )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "cannot convert a far pointer into a MmxRegisterOrMemory64".into(),
+            )),
+            OperandType::Memory(effective_address) => Ok(Self::Memory(effective_address)),
+            OperandType::Register(register) => {
+                Ok(Self::Register(<&MmxRegister>::try_from(register)?))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum XmmRegisterOrMemory128<'a> {
+    Register(&'a XmmRegister),
+    Memory(&'a EffectiveAddress),
+}
+
+impl XmmRegisterOrMemory128<'_> {
+    /// Whether the operand is a register, or a memory operand whose address is aligned to 16
+    /// bytes. Unless stated otherwise, SSE instructions require this of 128-bit memory operands.
+    pub fn is_aligned(&self, cpu: &Cpu) -> bool {
+        match self {
+            Self::Register(_) => true,
+            Self::Memory(effective_address) => effective_address.resolve(cpu) % 16 == 0,
+        }
+    }
+
+    /// Reads the operand. A memory operand is read as two little-endian QWORDs.
+    pub fn read(&self, cpu: &Cpu) -> Result<u128, Error> {
+        match self {
+            Self::Register(register) => Ok(cpu.sse.read_xmm(register.index())),
+            Self::Memory(effective_address) => {
+                let address = effective_address.translate(cpu, Size::Oword, AccessKind::Read)?;
+                let low = cpu.memory.read64(address)?;
+                let high = cpu.memory.read64(address.offset(8))?;
+                Ok((high as u128) << 64 | low as u128)
+            }
+        }
+    }
+
+    /// Writes the operand. A memory operand is written as two little-endian QWORDs.
+    pub fn write(&self, cpu: &mut Cpu, value: u128) -> Result<(), Error> {
+        match self {
+            Self::Register(register) => {
+                cpu.sse.write_xmm(register.index(), value);
+                Ok(())
+            }
+            Self::Memory(effective_address) => {
+                let address = effective_address.translate(cpu, Size::Oword, AccessKind::Write)?;
+                cpu.memory.write64(address, value as u64)?;
+                cpu.memory.write64(address.offset(8), (value >> 64) as u64)
+            }
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for XmmRegisterOrMemory128<'a> {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        match operand_type {
+            OperandType::Immediate(_) => Err(Error::CannotCovertType(
+                "cannot convert an immediate value into a XmmRegisterOrMemory128".into(),
+            )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "cannot convert a far pointer into a XmmRegisterOrMemory128".into(),
+            )),
+            OperandType::Memory(effective_address) => Ok(Self::Memory(effective_address)),
+            OperandType::Register(register) => {
+                Ok(Self::Register(<&XmmRegister>::try_from(register)?))
+            }
+        }
+    }
+}
+
+/// The low QWORD of an XMM register, or a 64-bit memory operand, as used by the scalar
+/// double-precision instructions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum XmmRegisterOrMemory64<'a> {
+    Register(&'a XmmRegister),
+    Memory(&'a EffectiveAddress),
+}
+
+impl XmmRegisterOrMemory64<'_> {
+    /// Reads the operand. A memory operand is read as a little-endian QWORD.
+    pub fn read(&self, cpu: &Cpu) -> Result<u64, Error> {
+        match self {
+            Self::Register(register) => Ok(cpu.sse.read_xmm(register.index()) as u64),
+            Self::Memory(effective_address) => cpu.memory.read64(effective_address.translate(
+                cpu,
+                Size::Qword,
+                AccessKind::Read,
+            )?),
+        }
+    }
+
+    /// Writes the operand. Only the low QWORD of a register is written, and the high QWORD is left
+    /// unchanged. A memory operand is written as a little-endian QWORD.
+    pub fn write(&self, cpu: &mut Cpu, value: u64) -> Result<(), Error> {
+        match self {
+            Self::Register(register) => {
+                let high = cpu.sse.read_xmm(register.index()) & !(u64::MAX as u128);
+                cpu.sse.write_xmm(register.index(), high | value as u128);
+                Ok(())
+            }
+            Self::Memory(effective_address) => {
+                let address = effective_address.translate(cpu, Size::Qword, AccessKind::Write)?;
+                cpu.memory.write64(address, value)
+            }
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for XmmRegisterOrMemory64<'a> {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        match operand_type {
+            OperandType::Immediate(_) => Err(Error::CannotCovertType(
+                "cannot convert an immediate value into a XmmRegisterOrMemory64".into(),
+            )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "cannot convert a far pointer into a XmmRegisterOrMemory64".into(),
+            )),
+            OperandType::Memory(effective_address) => Ok(Self::Memory(effective_address)),
+            OperandType::Register(register) => {
+                Ok(Self::Register(<&XmmRegister>::try_from(register)?))
+            }
+        }
     }
 }
 
@@ -1609,7 +3827,11 @@ impl RegisterOrMemory32<'_> {
     pub fn read(&self, cpu: &Cpu) -> Result<u32, Error> {
         match self {
             Self::Register(register) => Ok(cpu.registers.read32(register)),
-            Self::Memory(effective_address) => cpu.memory.read32(effective_address.resolve(cpu)),
+            Self::Memory(effective_address) => cpu.memory.read32(effective_address.translate(
+                cpu,
+                Size::Dword,
+                AccessKind::Read,
+            )?),
         }
     }
 
@@ -1617,7 +3839,8 @@ impl RegisterOrMemory32<'_> {
         match self {
             Self::Register(register) => Ok(cpu.registers.write32(register, value)),
             Self::Memory(effective_address) => {
-                cpu.memory.write32(effective_address.resolve(cpu), value)
+                let address = effective_address.translate(cpu, Size::Dword, AccessKind::Write)?;
+                cpu.memory.write32(address, value)
             }
         }
     }
@@ -1643,6 +3866,9 @@ impl<'a> TryFrom<&'a OperandType> for RegisterOrMemory32<'a> {
             OperandType::Immediate(_) => Err(Error::CannotCovertType(
                 "cannot convert an immediate value into a RegisterOrMemory32".into(),
             )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "cannot convert a far pointer into a RegisterOrMemory32".into(),
+            )),
             OperandType::Memory(effective_address) => Ok(Self::Memory(effective_address)),
             OperandType::Register(register) => {
                 Ok(Self::Register(<&Register32>::try_from(register)?))
@@ -1662,7 +3888,10 @@ impl RegisterOrMemory16<'_> {
     pub fn read(&self, cpu: &Cpu) -> Result<u16, Error> {
         match self {
             Self::Register(register) => Ok(cpu.registers.read16(register)),
-            Self::Memory(effective_address) => cpu.memory.read16(effective_address.resolve(cpu)),
+            Self::Memory(effective_address) => {
+                cpu.memory
+                    .read16(effective_address.translate(cpu, Size::Word, AccessKind::Read)?)
+            }
         }
     }
 
@@ -1670,7 +3899,8 @@ impl RegisterOrMemory16<'_> {
         match self {
             Self::Register(register) => Ok(cpu.registers.write16(register, value)),
             Self::Memory(effective_address) => {
-                cpu.memory.write16(effective_address.resolve(cpu), value)
+                let address = effective_address.translate(cpu, Size::Word, AccessKind::Write)?;
+                cpu.memory.write16(address, value)
             }
         }
     }
@@ -1696,6 +3926,9 @@ impl<'a> TryFrom<&'a OperandType> for RegisterOrMemory16<'a> {
             OperandType::Immediate(_) => Err(Error::CannotCovertType(
                 "cannot convert an immediate value into a RegisterOrMemory16".into(),
             )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "cannot convert a far pointer into a RegisterOrMemory16".into(),
+            )),
             OperandType::Memory(effective_address) => Ok(Self::Memory(effective_address)),
             OperandType::Register(register) => {
                 Ok(Self::Register(<&Register16>::try_from(register)?))
@@ -1715,7 +3948,10 @@ impl RegisterOrMemory8<'_> {
     pub fn read(&self, cpu: &Cpu) -> Result<u8, Error> {
         match self {
             Self::Register(register) => Ok(cpu.registers.read8(register)),
-            Self::Memory(effective_address) => cpu.memory.read8(effective_address.resolve(cpu)),
+            Self::Memory(effective_address) => {
+                cpu.memory
+                    .read8(effective_address.translate(cpu, Size::Byte, AccessKind::Read)?)
+            }
         }
     }
 
@@ -1723,7 +3959,8 @@ impl RegisterOrMemory8<'_> {
         match self {
             Self::Register(register) => Ok(cpu.registers.write8(register, value)),
             Self::Memory(effective_address) => {
-                cpu.memory.write8(effective_address.resolve(cpu), value)
+                let address = effective_address.translate(cpu, Size::Byte, AccessKind::Write)?;
+                cpu.memory.write8(address, value)
             }
         }
     }
@@ -1749,6 +3986,9 @@ impl<'a> TryFrom<&'a OperandType> for RegisterOrMemory8<'a> {
             OperandType::Immediate(_) => Err(Error::CannotCovertType(
                 "cannot convert an immediate value into a RegisterOrMemory8".into(),
             )),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "cannot convert a far pointer into a RegisterOrMemory8".into(),
+            )),
             OperandType::Memory(effective_address) => Ok(Self::Memory(effective_address)),
             OperandType::Register(register) => {
                 Ok(Self::Register(<&Register8>::try_from(register)?))
@@ -1760,6 +4000,7 @@ impl<'a> TryFrom<&'a OperandType> for RegisterOrMemory8<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::register::{DescriptorTableRegister, Segment};
 
     #[test]
     fn instruction_operand_format_matches() {
@@ -1782,6 +4023,12 @@ mod tests {
         assert!(F::Const3.matches(&vec![Operand::try_from(&NasmStr("3")).unwrap()].into()));
         assert!(F::Const3.matches(&vec![Operand::try_from(&NasmStr("WORD 3")).unwrap()].into()));
         assert!(!F::Const3.matches(&vec![Operand::try_from(&NasmStr("4")).unwrap()].into()));
+        assert!(
+            !F::Const3.matches(&vec![Operand::try_from(&NasmStr("strict byte 3")).unwrap()].into())
+        );
+        assert!(
+            F::Imm8.matches(&vec![Operand::try_from(&NasmStr("strict byte 3")).unwrap()].into())
+        );
         assert!(F::Imm8.matches(&vec![Operand::try_from(&NasmStr("0")).unwrap()].into()));
         assert!(F::Imm8.matches(&vec![Operand::try_from(&NasmStr("1")).unwrap()].into()));
         assert!(F::Imm8.matches(&vec![Operand::try_from(&NasmStr("byte 1")).unwrap()].into()));
@@ -1809,7 +4056,10 @@ mod tests {
         // F::Reg32Imm32,
         // F::Rel8,
         // F::Rel16,
-        // F::Rel32,
+        assert!(F::Rel32.matches(&vec![Operand::try_from(&NasmStr("0x100")).unwrap()].into()));
+        assert!(F::Rel32.matches(&vec![Operand::try_from(&NasmStr("dword 1")).unwrap()].into()));
+        assert!(!F::Rel32.matches(&vec![Operand::try_from(&NasmStr("byte 1")).unwrap()].into()));
+        assert!(!F::Rel32.matches(&vec![Operand::try_from(&NasmStr("[eax]")).unwrap()].into()));
         // F::Rm8,
         // F::Rm16,
         // F::Rm32,
@@ -1838,7 +4088,8 @@ mod tests {
         // F::Rm16Const1,
         // F::Rm32Const1,
         // F::Far16,
-        // F::Far32,
+        assert!(F::Far32.matches(&vec![Operand::try_from(&NasmStr("0x08:0x1000")).unwrap()].into()));
+        assert!(!F::Far32.matches(&vec![Operand::try_from(&NasmStr("0x1000")).unwrap()].into()));
         // F::Rm8Cl,
         // F::Rm16Cl,
         // F::Rm32Cl,
@@ -1986,6 +4237,13 @@ mod tests {
         assert_ea_err!("[ax]");
         assert_ea_err!("[eax-ebx]");
         assert_ea_err!("[eax*10]");
+        assert_ea_err!("[eax*3]");
+        assert_ea_err!("[eax*9]");
+        assert_ea_err!("[eax*0]");
+        assert_ea_err!("[eax*2+ebx*4]");
+        assert_ea_err!("[esp*2]");
+        assert_ea_err!("[eax+esp*2]");
+        assert_ea_err!("[esp+esp]");
         assert_ea_err!("[eax/10]");
         assert_ea_err!("[eflags]");
         assert_ea_err!("[eip]");
@@ -2033,18 +4291,14 @@ mod tests {
         assert_eq!(ea!("[ eax   +  4 ]"), expected);
 
         let expected = EffectiveAddress {
-            raw: vec![(Add, eao!(reg "eax")), (Subtract, eao!(imm "10"))],
+            raw: vec![(Add, eao!(reg "eax")), (Add, eao!(imm "-10"))],
             num_registers: 1,
             register_size: Some(Size::Dword),
         };
         assert_eq!(ea!("[eax-10]"), expected);
 
         let expected = EffectiveAddress {
-            raw: vec![
-                (Add, eao!(imm "8")),
-                (Multiply, eao!(imm "4")),
-                (Add, eao!(reg "ebx")),
-            ],
+            raw: vec![(Add, eao!(reg "ebx")), (Add, eao!(imm "32"))],
             num_registers: 1,
             register_size: Some(Size::Dword),
         };
@@ -2052,16 +4306,10 @@ mod tests {
 
         let expected = EffectiveAddress {
             raw: vec![
+                (Add, eao!(reg "ebx")),
                 (Add, eao!(reg "eax")),
                 (Multiply, eao!(imm "2")),
-                (Add, eao!(imm "4000q")),
-                (Add, eao!(imm "2000h")),
-                (Multiply, eao!(imm "8")),
-                (Add, eao!(imm "0x8000")),
-                (Add, eao!(imm "10d")),
-                (Add, eao!(imm "020d")),
-                (Add, eao!(reg "ebx")),
-                (Multiply, eao!(imm "0b1")),
+                (Add, eao!(imm "100382")),
             ],
             num_registers: 2,
             register_size: Some(Size::Dword),
@@ -2070,6 +4318,32 @@ mod tests {
             ea!("[eax*2+4000q+2000h*8+0x8000+10d+020d+ebx*0b1]"),
             expected
         );
+
+        let expected = EffectiveAddress {
+            raw: vec![(Add, eao!(reg "esp")), (Add, eao!(reg "eax"))],
+            num_registers: 2,
+            register_size: Some(Size::Dword),
+        };
+        assert_eq!(ea!("[eax+esp]"), expected);
+
+        let expected = EffectiveAddress {
+            raw: vec![
+                (Add, eao!(reg "esp")),
+                (Add, eao!(reg "eax")),
+                (Multiply, eao!(imm "8")),
+                (Add, eao!(imm "8")),
+            ],
+            num_registers: 2,
+            register_size: Some(Size::Dword),
+        };
+        assert_eq!(ea!("[4+eax*2*4+esp+4]"), expected);
+
+        let expected = EffectiveAddress {
+            raw: vec![(Add, eao!(imm "0"))],
+            num_registers: 0,
+            register_size: None,
+        };
+        assert_eq!(ea!("[4-2*2]"), expected);
     }
 
     #[test]
@@ -2078,6 +4352,62 @@ mod tests {
         assert!(Immediate::try_from(&NasmStr("c0h")).is_err());
         assert!(Immediate::try_from(&NasmStr(" 1 ")).is_err());
         assert!(Immediate::try_from(&NasmStr("0q200h")).is_err());
+        assert!(Immediate::try_from(&NasmStr("1+")).is_err());
+        assert!(Immediate::try_from(&NasmStr("0x200h")).is_err());
+        assert!(Immediate::try_from(&NasmStr("0b101y")).is_err());
+        assert!(Immediate::try_from(&NasmStr("0o8")).is_err());
+        assert!(Immediate::try_from(&NasmStr("0x")).is_err());
+        assert!(Immediate::try_from(&NasmStr("4294967296")).is_err());
+        assert!(Immediate::parse_literal("-2147483649").is_err());
+        assert_eq!(
+            Immediate::try_from(&NasmStr("-2147483648")).unwrap(),
+            Immediate(1 << 31)
+        );
+        assert_eq!(
+            Immediate::parse_literal("0b102").unwrap_err().to_string(),
+            "instruction could not be parsed: \"0b102\" is not a valid number, as '2' is not a \
+             binary digit"
+        );
+        assert_eq!(
+            Immediate::parse_literal("0q17q").unwrap_err().to_string(),
+            "instruction could not be parsed: \"0q17q\" is not a valid number, as its radix is \
+             given by both a prefix and a suffix"
+        );
+        for (to_parse, expected) in [
+            ("0o310", 200),
+            ("0O310", 200),
+            ("310o", 200),
+            ("0y1100_1000", 200),
+            ("11001000y", 200),
+            ("11001000Y", 200),
+            ("0t200", 200),
+            ("200t", 200),
+            ("0C8H", 200),
+            ("0x1b", 0x1b),
+            ("0bh", 0xb),
+            ("-0x1", u32::MAX),
+        ] {
+            assert_eq!(
+                Immediate::try_from(&NasmStr(to_parse)).unwrap(),
+                Immediate(expected)
+            );
+        }
+        assert_eq!(
+            Immediate::try_from(&NasmStr("4*2+1")).unwrap(),
+            Immediate(9)
+        );
+        assert_eq!(
+            Immediate::try_from(&NasmStr("(3+4)*8")).unwrap(),
+            Immediate(56)
+        );
+        assert_eq!(
+            Immediate::try_from(&NasmStr("1 << 12")).unwrap(),
+            Immediate(0x1000)
+        );
+        assert_eq!(
+            Immediate::try_from(&NasmStr("~0xff & 0xfff")).unwrap(),
+            Immediate(0xf00)
+        );
 
         let to_parse = "0x200";
         let expected_parsed = 512;
@@ -2177,6 +4507,15 @@ mod tests {
         };
     }
 
+    macro_rules! assert_lookup {
+        ($mnemonic:literal, [$($operand:literal),*], $expected:path) => {
+            let operands = Operands(vec![$(o!($operand)),*]);
+            let (_, map) = InstructionDescriptor::lookup($mnemonic, &operands).unwrap();
+            let cpu_function = map.cpu_function;
+            assert_eq!(cpu_function as *const () as usize, $expected as *const () as usize);
+        };
+    }
+
     #[test]
     fn operand_try_from_nasm_str() {
         assert_o_err!("WORDEBX");
@@ -2193,6 +4532,360 @@ mod tests {
 
         let expected = Operand::new(ot!(reg "eax"), None);
         assert_eq!(o!("byte EAX"), expected);
+
+        let operand = o!("STRICT byte 3");
+        assert_eq!(operand.size_directive, Some(Size::Byte));
+        assert!(operand.strict);
+        assert!(!o!("strict byte eax").strict);
+        assert_o_err!("strict 3");
+        assert_o_err!("strict byte");
+        assert_o_err!("byte strict 3");
+    }
+
+    #[test]
+    fn operands_infer_size() {
+        assert_eq!(
+            Operands(vec![o!("eax"), o!("[ebx]")]).infer_size(),
+            Some(Size::Dword)
+        );
+        assert_eq!(
+            Operands(vec![o!("[ebx]"), o!("al")]).infer_size(),
+            Some(Size::Byte)
+        );
+        assert_eq!(
+            Operands(vec![o!("ax"), o!("1")]).infer_size(),
+            Some(Size::Word)
+        );
+        assert_eq!(Operands(vec![o!("dx"), o!("al")]).infer_size(), None);
+        assert_eq!(Operands(vec![o!("[ebx]"), o!("1")]).infer_size(), None);
+        assert_eq!(
+            Operands(vec![o!("dword [ebx]"), o!("1")]).infer_size(),
+            Some(Size::Dword)
+        );
+        assert_eq!(
+            Operands(vec![o!("word [ebx]"), o!("ax")]).infer_size(),
+            Some(Size::Word)
+        );
+        assert_eq!(
+            Operands(vec![o!("eax"), o!("byte [ebx]")]).infer_size(),
+            None
+        );
+        assert_eq!(
+            Operands(vec![o!("ebx"), o!("byte 2")]).infer_size(),
+            Some(Size::Dword)
+        );
+        assert_eq!(Operands(vec![]).infer_size(), None);
+    }
+
+    #[test]
+    fn instruction_operand_format_matches_inferred_size() {
+        use InstructionOperandFormat as F;
+
+        let operands = Operands(vec![o!("eax"), o!("[ebx]")]);
+        assert!(F::Reg32Rm32.matches(&operands));
+        assert!(!F::Reg16Rm16.matches(&operands));
+        assert!(!F::Reg32Rm8.matches(&operands));
+        assert!(F::Reg32Mem.matches(&operands));
+
+        let operands = Operands(vec![o!("[ebx]"), o!("al")]);
+        assert!(F::Rm8Reg8.matches(&operands));
+        assert!(!F::Rm16Reg16.matches(&operands));
+
+        let operands = Operands(vec![o!("eax"), o!("1")]);
+        assert!(F::Rm32Imm32.matches(&operands));
+        assert!(F::EaxImm32.matches(&operands));
+        assert!(!F::Rm32Imm8.matches(&operands));
+        assert!(F::Rm32Imm8.matches(&Operands(vec![o!("eax"), o!("byte 1")])));
+
+        let operands = Operands(vec![o!("al"), o!("1")]);
+        assert!(F::Rm8Imm8.matches(&operands));
+        assert!(!F::Rm16Imm16.matches(&operands));
+
+        // Nothing can be inferred without a register operand.
+        let operands = Operands(vec![o!("[ebx]"), o!("1")]);
+        assert!(F::Rm8Imm8.matches(&operands));
+        assert!(F::Rm16Imm16.matches(&operands));
+        assert!(F::Rm32Imm32.matches(&operands));
+
+        // A size directive takes precedence over the inferred size.
+        assert!(!F::Reg32Rm32.matches(&Operands(vec![o!("eax"), o!("byte [ebx]")])));
+        assert!(F::Reg32Rm8.matches(&Operands(vec![o!("eax"), o!("byte [ebx]")])));
+
+        // Port numbers are not sized by the register they are used with.
+        assert!(F::Imm8Eax.matches(&Operands(vec![o!("0x60"), o!("eax")])));
+        assert!(F::EaxImm8.matches(&Operands(vec![o!("eax"), o!("0x60")])));
+    }
+
+    #[test]
+    fn lookup_inferred_size() {
+        let operands = Operands(vec![o!("eax"), o!("[ebx]")]);
+        let (_, map) = InstructionDescriptor::lookup("add", &operands).unwrap();
+        let cpu_function = map.cpu_function;
+        assert_eq!(
+            cpu_function as *const () as usize,
+            Cpu::add_reg32_rm32 as *const () as usize
+        );
+
+        let operands = Operands(vec![o!("[ebx]"), o!("al")]);
+        let (_, map) = InstructionDescriptor::lookup("mov", &operands).unwrap();
+        let cpu_function = map.cpu_function;
+        assert_eq!(
+            cpu_function as *const () as usize,
+            Cpu::mov_rm8_reg8 as *const () as usize
+        );
+
+        let operands = Operands(vec![o!("[ebx]"), o!("eax")]);
+        let (_, map) = InstructionDescriptor::lookup("mov", &operands).unwrap();
+        let cpu_function = map.cpu_function;
+        assert_eq!(
+            cpu_function as *const () as usize,
+            Cpu::mov_rm32_reg32 as *const () as usize
+        );
+    }
+
+    #[test]
+    fn lookup_immediate_group() {
+        assert_lookup!("add", ["dword [eax]", "4"], Cpu::add_rm32_imm32);
+        assert_lookup!("add", ["word [eax]", "4"], Cpu::add_rm16_imm16);
+        assert_lookup!("add", ["byte [eax]", "4"], Cpu::add_rm8_imm8);
+        assert_lookup!("sub", ["ebx", "byte 2"], Cpu::sub_rm32_imm8);
+        assert_lookup!("sub", ["ebx", "2"], Cpu::sub_rm32_imm32);
+        assert_lookup!("sub", ["ebx", "strict byte 2"], Cpu::sub_rm32_imm8);
+        assert_lookup!("sub", ["ebx", "strict dword 2"], Cpu::sub_rm32_imm32);
+        assert_lookup!("or", ["bx", "byte 2"], Cpu::or_rm16_imm8);
+        assert_lookup!("adc", ["bl", "2"], Cpu::adc_rm8_imm8);
+        assert_lookup!("sbb", ["word [ebx]", "byte 2"], Cpu::sbb_rm16_imm8);
+        assert_lookup!("and", ["dword [ebx]", "byte 2"], Cpu::and_rm32_imm8);
+        assert_lookup!("xor", ["ecx", "0x12345678"], Cpu::xor_rm32_imm32);
+        assert_lookup!("cmp", ["cx", "0x1234"], Cpu::cmp_rm16_imm16);
+
+        // The accumulator forms are preferred over the overlapping r/m forms.
+        assert_lookup!("add", ["al", "4"], Cpu::add_al_imm8);
+        assert_lookup!("and", ["ax", "4"], Cpu::and_ax_imm16);
+        assert_lookup!("sub", ["eax", "4"], Cpu::sub_eax_imm32);
+        assert_lookup!("sub", ["eax", "byte 4"], Cpu::sub_rm32_imm8);
+    }
+
+    #[test]
+    fn lookup_moffs() {
+        assert_lookup!("mov", ["al", "[0x1234]"], Cpu::mov_al_moffs8);
+        assert_lookup!("mov", ["ax", "[0x1234]"], Cpu::mov_ax_moffs16);
+        assert_lookup!("mov", ["eax", "dword [0x1234]"], Cpu::mov_eax_moffs32);
+        assert_lookup!("mov", ["byte [0x1234]", "al"], Cpu::mov_moffs8_al);
+        assert_lookup!("mov", ["[0x1234]", "ax"], Cpu::mov_moffs16_ax);
+        assert_lookup!("mov", ["[0x1234]", "eax"], Cpu::mov_moffs32_eax);
+
+        // Effective addresses with registers, or other registers, use the r/m forms.
+        assert_lookup!("mov", ["al", "[ebx]"], Cpu::mov_reg8_rm8);
+        assert_lookup!("mov", ["al", "[ebx+0x1234]"], Cpu::mov_reg8_rm8);
+        assert_lookup!("mov", ["[0x1234]", "ebx"], Cpu::mov_rm32_reg32);
+    }
+
+    #[test]
+    fn lookup_system_registers() {
+        assert_lookup!("mov", ["eax", "cr0"], Cpu::mov_reg32_cr);
+        assert_lookup!("mov", ["ebx", "cr3"], Cpu::mov_reg32_cr);
+        assert_lookup!("mov", ["cr0", "eax"], Cpu::mov_cr_reg32);
+        assert_lookup!("mov", ["cr4", "edi"], Cpu::mov_cr_reg32);
+        assert_lookup!("mov", ["eax", "dr0"], Cpu::mov_reg32_dr);
+        assert_lookup!("mov", ["dr7", "eax"], Cpu::mov_dr_reg32);
+        assert_lookup!("lgdt", ["[eax]"], Cpu::lgdt_mem);
+        assert_lookup!("lldt", ["ax"], Cpu::lldt_rm16);
+        assert_lookup!("invlpg", ["[eax]"], Cpu::invlpg_mem);
+        assert_lookup!("sldt", ["[eax]"], Cpu::sldt_rm16);
+        assert_lookup!("lidt", ["[0x1000]"], Cpu::lidt_mem);
+        assert_lookup!("sgdt", ["[eax+4]"], Cpu::sgdt_mem);
+        assert_lookup!("sidt", ["[ebx]"], Cpu::sidt_mem);
+        assert_lookup!("smsw", ["ax"], Cpu::smsw_rm16);
+        assert_lookup!("smsw", ["[eax]"], Cpu::smsw_rm16);
+        assert_lookup!("smsw", ["eax"], Cpu::smsw_reg32);
+        assert_lookup!("lmsw", ["ax"], Cpu::lmsw_rm16);
+        assert_lookup!("lmsw", ["word [eax]"], Cpu::lmsw_rm16);
+        assert_lookup!("clts", [], Cpu::clts);
+        assert_lookup!("rdmsr", [], Cpu::rdmsr);
+        assert_lookup!("wrmsr", [], Cpu::wrmsr);
+        assert_lookup!("sysenter", [], Cpu::sysenter);
+        assert_lookup!("sysexit", [], Cpu::sysexit);
+        assert_lookup!("ud2", [], Cpu::ud2);
+
+        // Control and debug registers can only be moved to and from 32-bit general-purpose
+        // registers.
+        for operands in [
+            ["ax", "cr0"],
+            ["cr0", "[eax]"],
+            ["cr0", "cr2"],
+            ["cr0", "5"],
+            ["dr0", "dx"],
+            ["[eax]", "dr0"],
+            ["cr0", "dr0"],
+        ] {
+            let operands = Operands(
+                operands
+                    .iter()
+                    .map(|operand| Operand::try_from(&NasmStr(operand)).unwrap())
+                    .collect(),
+            );
+            assert!(InstructionDescriptor::lookup("mov", &operands).is_err());
+        }
+
+        // The descriptor table instructions only accept memory operands.
+        for mnemonic in ["lgdt", "lidt", "sgdt", "sidt"] {
+            let operands = Operands(vec![o!("eax")]);
+            assert!(InstructionDescriptor::lookup(mnemonic, &operands).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup_fpu_control() {
+        assert_lookup!("finit", [], Cpu::fninit);
+        assert_lookup!("fninit", [], Cpu::fninit);
+        assert_lookup!("fldcw", ["[eax]"], Cpu::fldcw_mem16);
+        assert_lookup!("fldcw", ["word [eax]"], Cpu::fldcw_mem16);
+        assert_lookup!("fstcw", ["[eax]"], Cpu::fnstcw_mem16);
+        assert_lookup!("fnstcw", ["[eax]"], Cpu::fnstcw_mem16);
+        assert_lookup!("fstsw", ["ax"], Cpu::fnstsw_ax);
+        assert_lookup!("fnstsw", ["ax"], Cpu::fnstsw_ax);
+        assert_lookup!("fstsw", ["[eax]"], Cpu::fnstsw_mem16);
+        assert_lookup!("fnstsw", ["[eax]"], Cpu::fnstsw_mem16);
+
+        for (mnemonic, operand) in [
+            ("fldcw", "dword [eax]"),
+            ("fldcw", "ax"),
+            ("fstsw", "eax"),
+            ("fstsw", "bx"),
+        ] {
+            let operands = Operands(vec![Operand::try_from(&NasmStr(operand)).unwrap()]);
+            assert!(InstructionDescriptor::lookup(mnemonic, &operands).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup_mmx() {
+        assert_lookup!("movd", ["mm0", "eax"], Cpu::movd_mm_rm32);
+        assert_lookup!("movd", ["mm0", "[eax]"], Cpu::movd_mm_rm32);
+        assert_lookup!("movd", ["dword [eax]", "mm7"], Cpu::movd_rm32_mm);
+        assert_lookup!("movq", ["mm1", "mm2"], Cpu::movq_mm_mm64);
+        assert_lookup!("movq", ["mm1", "qword [eax]"], Cpu::movq_mm_mm64);
+        assert_lookup!("movq", ["[eax]", "mm2"], Cpu::movq_mm64_mm);
+        assert_lookup!("paddusw", ["mm3", "[ebx+4]"], Cpu::paddusw_mm_mm64);
+        assert_lookup!("pxor", ["mm0", "mm0"], Cpu::pxor_mm_mm64);
+        assert_lookup!("emms", [], Cpu::emms);
+
+        for (mnemonic, operands) in [
+            ("movd", ["mm0", "ax"]),
+            ("movd", ["mm0", "qword [eax]"]),
+            ("movq", ["mm0", "eax"]),
+            ("movq", ["mm0", "dword [eax]"]),
+            ("paddb", ["eax", "mm0"]),
+        ] {
+            let operands = Operands(
+                operands
+                    .iter()
+                    .map(|operand| Operand::try_from(&NasmStr(operand)).unwrap())
+                    .collect(),
+            );
+            assert!(InstructionDescriptor::lookup(mnemonic, &operands).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup_sse() {
+        assert_lookup!("movaps", ["xmm0", "xmm7"], Cpu::movaps_xmm_xmm128);
+        assert_lookup!("movaps", ["xmm0", "[eax]"], Cpu::movaps_xmm_xmm128);
+        assert_lookup!("movaps", ["oword [eax]", "xmm1"], Cpu::movaps_xmm128_xmm);
+        assert_lookup!("movups", ["xmm2", "oword [eax]"], Cpu::movups_xmm_xmm128);
+        assert_lookup!("movups", ["[eax]", "xmm3"], Cpu::movups_xmm128_xmm);
+        assert_lookup!("addps", ["xmm0", "xmm1"], Cpu::addps_xmm_xmm128);
+        assert_lookup!("subps", ["xmm0", "[ebx]"], Cpu::subps_xmm_xmm128);
+        assert_lookup!("mulps", ["xmm4", "xmm5"], Cpu::mulps_xmm_xmm128);
+        assert_lookup!("divps", ["xmm6", "xmm7"], Cpu::divps_xmm_xmm128);
+        assert_lookup!("cmpps", ["xmm0", "xmm1", "4"], Cpu::cmpps_xmm_xmm128_imm8);
+
+        for (mnemonic, operands) in [
+            ("movaps", ["xmm0", "qword [eax]"]),
+            ("movaps", ["xmm0", "mm0"]),
+            ("addps", ["mm0", "mm1"]),
+            ("addps", ["eax", "xmm1"]),
+        ] {
+            let operands = Operands(
+                operands
+                    .iter()
+                    .map(|operand| Operand::try_from(&NasmStr(operand)).unwrap())
+                    .collect(),
+            );
+            assert!(InstructionDescriptor::lookup(mnemonic, &operands).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup_sse2() {
+        assert_lookup!("movapd", ["xmm0", "[eax]"], Cpu::movapd_xmm_xmm128);
+        assert_lookup!("movapd", ["[eax]", "xmm0"], Cpu::movapd_xmm128_xmm);
+        assert_lookup!("movdqa", ["xmm0", "xmm1"], Cpu::movdqa_xmm_xmm128);
+        assert_lookup!("movdqu", ["oword [eax]", "xmm1"], Cpu::movdqu_xmm128_xmm);
+        assert_lookup!("movsd", [], Cpu::movsd);
+        assert_lookup!("movsd", ["xmm0", "xmm1"], Cpu::movsd_xmm_xmm64);
+        assert_lookup!("movsd", ["xmm0", "qword [eax]"], Cpu::movsd_xmm_xmm64);
+        assert_lookup!("movsd", ["[eax]", "xmm1"], Cpu::movsd_xmm64_xmm);
+        assert_lookup!("addpd", ["xmm0", "xmm1"], Cpu::addpd_xmm_xmm128);
+        assert_lookup!("addsd", ["xmm0", "[eax]"], Cpu::addsd_xmm_xmm64);
+        assert_lookup!("divsd", ["xmm0", "xmm1"], Cpu::divsd_xmm_xmm64);
+        assert_lookup!("lfence", [], Cpu::lfence);
+        assert_lookup!("mfence", [], Cpu::mfence);
+        assert_lookup!("sfence", [], Cpu::sfence);
+        assert_lookup!("pause", [], Cpu::pause);
+
+        for (mnemonic, operands) in [
+            ("movsd", ["xmm0", "oword [eax]"]),
+            ("addsd", ["xmm0", "dword [eax]"]),
+            ("addpd", ["xmm0", "qword [eax]"]),
+        ] {
+            let operands = Operands(
+                operands
+                    .iter()
+                    .map(|operand| Operand::try_from(&NasmStr(operand)).unwrap())
+                    .collect(),
+            );
+            assert!(InstructionDescriptor::lookup(mnemonic, &operands).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup_aliases() {
+        assert_lookup!("jz", ["0x10"], Cpu::je_rel32);
+        assert_lookup!("JNZ", ["0x10"], Cpu::jne_rel32);
+        assert_lookup!("jc", ["0x10"], Cpu::jb_rel32);
+        assert_lookup!("jnae", ["0x10"], Cpu::jb_rel32);
+        assert_lookup!("jnbe", ["0x10"], Cpu::ja_rel32);
+        assert_lookup!("jpe", ["0x10"], Cpu::jp_rel32);
+        assert_lookup!("jnle", ["0x10"], Cpu::jg_rel32);
+
+        assert_eq!(canonical_mnemonic("sal"), "SHL");
+        assert_eq!(canonical_mnemonic("setnae"), "SETB");
+        assert_eq!(canonical_mnemonic("cmovz"), "CMOVE");
+        assert_eq!(canonical_mnemonic("jmp"), "JMP");
+        assert_eq!(canonical_mnemonic("jecxz"), "JECXZ");
+        assert_eq!(canonical_mnemonic("setalc"), "SETALC");
+        assert!(lookup_instructions_by_mnemonic("jnx").is_empty());
+    }
+
+    #[test]
+    fn lookup_in_out() {
+        assert_lookup!("in", ["al", "0x60"], Cpu::in_al_imm8);
+        assert_lookup!("in", ["ax", "0x60"], Cpu::in_ax_imm8);
+        assert_lookup!("in", ["eax", "0x60"], Cpu::in_eax_imm8);
+        assert_lookup!("in", ["al", "dx"], Cpu::in_al_dx);
+        assert_lookup!("in", ["ax", "dx"], Cpu::in_ax_dx);
+        assert_lookup!("in", ["eax", "dx"], Cpu::in_eax_dx);
+        assert_lookup!("out", ["0x60", "al"], Cpu::out_imm8_al);
+        assert_lookup!("out", ["0x60", "ax"], Cpu::out_imm8_ax);
+        assert_lookup!("out", ["0x60", "eax"], Cpu::out_imm8_eax);
+        assert_lookup!("out", ["dx", "al"], Cpu::out_dx_al);
+        assert_lookup!("out", ["dx", "ax"], Cpu::out_dx_ax);
+        assert_lookup!("out", ["dx", "eax"], Cpu::out_dx_eax);
+
+        let operands = Operands(vec![o!("al"), o!("cx")]);
+        assert!(InstructionDescriptor::lookup("in", &operands).is_err());
     }
 
     macro_rules! assert_size_err {
@@ -2216,11 +4909,522 @@ mod tests {
         assert_eq!(size!("bYtE"), Size::Byte);
         assert_eq!(size!("WORD"), Size::Word);
         assert_eq!(size!("dword"), Size::Dword);
+        assert_eq!(size!("qword"), Size::Qword);
+        assert_eq!(size!("Oword"), Size::Oword);
     }
 
     #[test]
     fn instruction_try_from_nasm_str() {
-        // TODO
+        assert!(Instruction::try_from(&NasmStr("")).is_err());
+        assert!(Instruction::try_from(&NasmStr("   ")).is_err());
+        assert!(Instruction::try_from(&NasmStr("int3 1")).is_err());
+
+        let instruction = Instruction::try_from(&NasmStr("int3")).unwrap();
+        assert_eq!(instruction.mnemonic, "int3");
+        assert!(instruction.operands.0.is_empty());
+
+        let instruction = Instruction::try_from(&NasmStr("  into  ")).unwrap();
+        assert_eq!(instruction.mnemonic, "into");
+        assert!(instruction.operands.0.is_empty());
+
+        let instruction = Instruction::try_from(&NasmStr("int 0x21")).unwrap();
+        assert_eq!(instruction.mnemonic, "int");
+        assert_eq!(instruction.operands.0, vec![o!("0x21")]);
+        assert_eq!(instruction.repeat_prefix, None);
+
+        let instruction = Instruction::try_from(&NasmStr("int (1 << 5) | 1")).unwrap();
+        assert_eq!(instruction.operands.0, vec![o!("0x21")]);
+
+        let instruction = Instruction::try_from(&NasmStr("REP movsd")).unwrap();
+        assert_eq!(instruction.mnemonic, "movsd");
+        assert_eq!(instruction.repeat_prefix, Some(RepeatPrefix::Rep));
+
+        let instruction = Instruction::try_from(&NasmStr("rep  insb")).unwrap();
+        assert_eq!(instruction.mnemonic, "insb");
+        assert!(instruction.operands.0.is_empty());
+        assert_eq!(instruction.repeat_prefix, Some(RepeatPrefix::Rep));
+        assert!(Instruction::try_from(&NasmStr("rep")).is_err());
+        assert!(Instruction::try_from(&NasmStr("rep rep insb")).is_err());
+
+        let instruction = Instruction::try_from(&NasmStr("LOCK add dword [eax], 1")).unwrap();
+        assert_eq!(instruction.mnemonic, "add");
+        assert!(instruction.lock_prefix);
+        assert!(instruction.lockable);
+        assert!(
+            !Instruction::try_from(&NasmStr("add dword [eax], 1"))
+                .unwrap()
+                .lock_prefix
+        );
+        assert!(
+            !Instruction::try_from(&NasmStr("lock add eax, 1"))
+                .unwrap()
+                .lockable
+        );
+        assert!(
+            !Instruction::try_from(&NasmStr("lock mov [eax], ebx"))
+                .unwrap()
+                .lockable
+        );
+        assert!(
+            !Instruction::try_from(&NasmStr("lock cmp dword [eax], 1"))
+                .unwrap()
+                .lockable
+        );
+        assert!(Instruction::try_from(&NasmStr("lock")).is_err());
+        assert!(Instruction::try_from(&NasmStr("lock lock add dword [eax], 1")).is_err());
+
+        let instruction = Instruction::try_from(&NasmStr("repnz\tscasb")).unwrap();
+        assert_eq!(instruction.mnemonic, "scasb");
+        assert_eq!(instruction.repeat_prefix, Some(RepeatPrefix::Repne));
+        let instruction = Instruction::try_from(&NasmStr("repe lock add dword [eax], 1")).unwrap();
+        assert_eq!(instruction.mnemonic, "add");
+        assert_eq!(instruction.repeat_prefix, Some(RepeatPrefix::Repe));
+        assert!(instruction.lock_prefix);
+        assert!(Instruction::try_from(&NasmStr("rep lock rep movsb")).is_err());
+    }
+
+    #[test]
+    fn effective_address_try_from_att_str() {
+        for (att, nasm) in [
+            ("(%eax)", "[eax]"),
+            ("-4(%ebp)", "[ebp-4]"),
+            ("0x10(%ebx,%ecx,2)", "[ebx+ecx*2+0x10]"),
+            ("(%ebx, %ecx)", "[ebx+ecx]"),
+            ("(,%ecx,4)", "[ecx*4]"),
+            ("0x100", "[0x100]"),
+            ("8(%eax,%esp)", "[esp+eax+8]"),
+        ] {
+            assert_eq!(
+                EffectiveAddress::try_from(&AttStr(att)).unwrap(),
+                EffectiveAddress::try_from(&NasmStr(nasm)).unwrap()
+            );
+        }
+
+        assert!(EffectiveAddress::try_from(&AttStr("()")).is_err());
+        assert!(EffectiveAddress::try_from(&AttStr("(%eax")).is_err());
+        assert!(EffectiveAddress::try_from(&AttStr("(eax)")).is_err());
+        assert!(EffectiveAddress::try_from(&AttStr("(%eax,,2)")).is_err());
+        assert!(EffectiveAddress::try_from(&AttStr("(%eax,%ebx,3)")).is_err());
+        assert!(EffectiveAddress::try_from(&AttStr("(%eax,%ebx,2,1)")).is_err());
+    }
+
+    #[test]
+    fn instruction_try_from_att_str() {
+        let instruction = Instruction::try_from(&AttStr("movl %eax, 4(%ebx,%ecx,2)")).unwrap();
+        assert_eq!(instruction.mnemonic, "mov");
+        assert_eq!(
+            instruction.operands.0,
+            vec![o!("dword [ebx+ecx*2+4]"), o!("eax")]
+        );
+
+        let instruction = Instruction::try_from(&AttStr("addb $1, (%eax)")).unwrap();
+        assert_eq!(instruction.mnemonic, "add");
+        assert_eq!(instruction.operands.0, vec![o!("byte [eax]"), o!("byte 1")]);
+
+        let instruction = Instruction::try_from(&AttStr("sub %ecx, (%eax)")).unwrap();
+        assert_eq!(instruction.operands.0, vec![o!("[eax]"), o!("ecx")]);
+
+        let instruction = Instruction::try_from(&AttStr("rep movsl")).unwrap();
+        assert_eq!(instruction.mnemonic, "movsd");
+        assert_eq!(instruction.repeat_prefix, Some(RepeatPrefix::Rep));
+
+        let instruction = Instruction::try_from(&AttStr("movsb")).unwrap();
+        assert_eq!(instruction.mnemonic, "movsb");
+
+        let instruction = Instruction::try_from(&AttStr("jmp 0x10")).unwrap();
+        assert_eq!(instruction.operands.0, vec![o!("0x10")]);
+
+        let instruction = Instruction::try_from(&AttStr("int $0x21")).unwrap();
+        assert_eq!(instruction.operands.0, vec![o!("0x21")]);
+
+        assert!(Instruction::try_from(&AttStr("movl eax, ebx")).is_err());
+        assert!(Instruction::try_from(&AttStr("movz %eax, %ebx")).is_err());
+        assert!(Instruction::try_from(&AttStr("int 0x21")).is_err());
+        assert!(Instruction::try_from(&AttStr("jmp *%eax")).is_err());
+    }
+
+    #[test]
+    fn instruction_execute_att() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(0x1234);
+        cpu.registers.set_ebx(0x100);
+        cpu.registers.set_ecx(2);
+        for line in [
+            "movl %eax, 4(%ebx,%ecx,2)",
+            "addl $1, 8(%ebx)",
+            "movl 8(%ebx), %edx",
+        ] {
+            Instruction::try_from(&AttStr(line))
+                .unwrap()
+                .execute(&mut cpu);
+        }
+        assert_eq!(cpu.memory.read32(0x108).unwrap(), 0x1235);
+        assert_eq!(cpu.registers.get_edx(), 0x1235);
+    }
+
+    #[test]
+    fn instruction_execute_fault() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ecx(5);
+        cpu.registers.set_eip(0x11);
+        let instruction = Instruction::try_from(&NasmStr("mov ecx, [0xfffffffe]")).unwrap();
+        instruction.execute(&mut cpu);
+        // The registers are left as they were, and the fault is left for the embedder as there is
+        // nothing to deliver it to.
+        assert_eq!(cpu.registers.get_ecx(), 5);
+        assert_eq!(cpu.registers.get_eip(), 0x11);
+        assert_eq!(
+            cpu.unreported_exception,
+            Some(CpuException::GeneralProtection)
+        );
+        assert_eq!(cpu.fault.get(), None);
+
+        // With an interrupt service routine, the fault is delivered to it instead.
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 0x100;
+        cpu.memory.write16(13 * 4, 0x1234).unwrap();
+        instruction.execute(&mut cpu);
+        assert_eq!(cpu.registers.get_eip(), 0x1234);
+        assert_eq!(cpu.unreported_exception, None);
+
+        // If it cannot be delivered, e.g. as the stack is inaccessible, then it is a double fault.
+        cpu.registers
+            .set_segment_base(SegmentRegister::Ss, 0xffff_fff0);
+        cpu.registers.esp = 0x11;
+        instruction.execute(&mut cpu);
+        assert_eq!(cpu.unreported_exception, Some(CpuException::DoubleFault));
+    }
+
+    #[test]
+    fn instruction_execute_segmented() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(0x100);
+        cpu.registers.esp = 0x100;
+        for instruction in ["push ax", "pop ds"] {
+            Instruction::try_from(&NasmStr(instruction))
+                .unwrap()
+                .execute(&mut cpu);
+        }
+        assert_eq!(cpu.registers.get_segment_base(SegmentRegister::Ds), 0x1000);
+        Instruction::try_from(&NasmStr("mov [0x10], eax"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.memory.read32(0x1010).unwrap(), 0x100);
+
+        // An access which extends beyond the limit of its segment raises #GP, or #SS for the stack
+        // segment.
+        cpu.registers.set_segment(
+            SegmentRegister::Ds,
+            Segment {
+                base: 0x1000,
+                limit: 0xff,
+                ..Segment::default()
+            },
+        );
+        Instruction::try_from(&NasmStr("mov ecx, [0xfe]"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(
+            cpu.unreported_exception.take(),
+            Some(CpuException::GeneralProtection)
+        );
+        cpu.registers.set_segment(
+            SegmentRegister::Ss,
+            Segment {
+                base: 0,
+                limit: 0xff,
+                ..Segment::default()
+            },
+        );
+        Instruction::try_from(&NasmStr("mov ecx, [ebp+0x100]"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(
+            cpu.unreported_exception.take(),
+            Some(CpuException::StackFault)
+        );
+        assert_eq!(cpu.registers.get_ecx(), 0);
+    }
+
+    #[test]
+    fn instruction_execute_paged() {
+        let mut cpu = Cpu::default();
+        // The first 4 MiB are identity mapped by the page table at 0x2000, except for the page at
+        // 0x5000, which is not present.
+        cpu.memory.write32(0x1000, 0x2000 | 0b111).unwrap();
+        for page in 0..1024 {
+            if page != 5 {
+                cpu.memory
+                    .write32(0x2000 + page * 4, page << 12 | 0b111)
+                    .unwrap();
+            }
+        }
+        cpu.registers.control_registers.set_cr3(0x1000);
+        cpu.registers.control_registers.set_protection_enable(true);
+        cpu.registers.control_registers.set_paging(true);
+        cpu.registers.esp = 0x100;
+
+        cpu.registers.set_eax(0x1234);
+        Instruction::try_from(&NasmStr("mov [ebx+0x4000], eax"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.memory.read32(0x4000).unwrap(), 0x1234);
+        assert_eq!(cpu.memory.read32(0x2010).unwrap(), 0x4000 | 0b1100111);
+        assert_eq!(cpu.memory.read32(0x1000).unwrap(), 0x2000 | 0b0100111);
+
+        // An access to the page which is not present is delivered to the #PF handler through its
+        // gate in the IDT, with the address in CR2 and the error code pushed after the return
+        // address.
+        cpu.registers.gdtr = DescriptorTableRegister {
+            base: 0x3000,
+            limit: 0xf,
+        };
+        cpu.memory.write64(0x3008, 0x00cf_9a00_0000_ffff).unwrap();
+        cpu.load_segment(SegmentRegister::Cs, 0x08);
+        cpu.set_interrupt_vector(14, 0x08, 0x300).unwrap();
+        cpu.registers.set_eip(0x200);
+        Instruction::try_from(&NasmStr("mov [ebx+0x5004], eax"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.registers.get_eip(), 0x300);
+        assert_eq!(cpu.registers.control_registers.get_cr2(), 0x5004);
+        assert_eq!(cpu.registers.esp, 0x100 - 16);
+        assert_eq!(cpu.memory.read32(0x100 - 16).unwrap(), 0b010);
+        assert_eq!(cpu.memory.read32(0x100 - 12).unwrap(), 0x200);
+        assert_eq!(cpu.memory.read32(0x100 - 8).unwrap(), 0x08);
+
+        // Once the handler has popped the error code and mapped the page, IRETD returns to the
+        // faulting instruction, which then completes.
+        cpu.registers.esp += 4;
+        cpu.memory.write32(0x2000 + 5 * 4, 0x5000 | 0b111).unwrap();
+        Instruction::try_from(&NasmStr("iretd"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.registers.get_eip(), 0x200);
+        assert_eq!(cpu.registers.esp, 0x100);
+        Instruction::try_from(&NasmStr("mov [ebx+0x5004], eax"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.fault.get(), None);
+        assert_eq!(cpu.memory.read32(0x5004).unwrap(), 0x1234);
+
+        // An access which spans the page at 0x6000, mapped to the frame at 0x8000, and the page
+        // after it, is split between the two frames.
+        cpu.memory.write32(0x2000 + 6 * 4, 0x8000 | 0b111).unwrap();
+        cpu.registers.set_eax(0x1234_5678);
+        Instruction::try_from(&NasmStr("mov [ebx+0x6ffe], eax"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.fault.get(), None);
+        assert_eq!(cpu.memory.read16(0x8ffe).unwrap(), 0x5678);
+        assert_eq!(cpu.memory.read16(0x7000).unwrap(), 0x1234);
+        Instruction::try_from(&NasmStr("mov ecx, [ebx+0x6ffe]"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.registers.get_ecx(), 0x1234_5678);
+    }
+
+    #[test]
+    fn operand_try_from_masm_str() {
+        for (masm, nasm) in [
+            ("dword ptr [ebx]", "dword [ebx]"),
+            ("BYTE PTR [eax+0Ah]", "byte [eax+0ah]"),
+            ("word ptr[eax]", "word [eax]"),
+            ("0C8H", "0c8h"),
+            ("0FFh", "0xff"),
+            ("17o", "15"),
+            ("101B", "5"),
+            ("eax", "eax"),
+        ] {
+            assert_eq!(
+                Operand::try_from(&MasmStr(masm)).unwrap(),
+                Operand::try_from(&NasmStr(nasm)).unwrap()
+            );
+        }
+
+        assert!(Operand::try_from(&MasmStr("ptr [ebx]")).is_err());
+        assert!(Operand::try_from(&MasmStr("eax ptr")).is_err());
+        assert!(Operand::try_from(&MasmStr("C8h")).is_err());
+    }
+
+    #[test]
+    fn instruction_try_from_masm_str() {
+        let instruction = Instruction::try_from(&MasmStr("add dword ptr [ebx], 0C8h")).unwrap();
+        assert_eq!(instruction.mnemonic, "add");
+        assert_eq!(instruction.operands.0, vec![o!("dword [ebx]"), o!("0xc8")]);
+
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ebx(0x100);
+        instruction.execute(&mut cpu);
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 0xc8);
+    }
+
+    #[test]
+    fn repeat_prefix_try_from_nasm_str() {
+        assert!(RepeatPrefix::try_from(&NasmStr("")).is_err());
+        assert!(RepeatPrefix::try_from(&NasmStr("re p")).is_err());
+        assert_eq!(
+            RepeatPrefix::try_from(&NasmStr("rep")).unwrap(),
+            RepeatPrefix::Rep
+        );
+        assert_eq!(
+            RepeatPrefix::try_from(&NasmStr("REP")).unwrap(),
+            RepeatPrefix::Rep
+        );
+        assert_eq!(
+            RepeatPrefix::try_from(&NasmStr("repe")).unwrap(),
+            RepeatPrefix::Repe
+        );
+        assert_eq!(
+            RepeatPrefix::try_from(&NasmStr("repz")).unwrap(),
+            RepeatPrefix::Repe
+        );
+        assert_eq!(
+            RepeatPrefix::try_from(&NasmStr("repne")).unwrap(),
+            RepeatPrefix::Repne
+        );
+        assert_eq!(
+            RepeatPrefix::try_from(&NasmStr("repnz")).unwrap(),
+            RepeatPrefix::Repne
+        );
+    }
+
+    #[test]
+    fn instruction_execute() {
+        let mut cpu = Cpu::default();
+        cpu.set_cycles_per_instruction(3);
+        for line in ["rdtsc", "lea ebx, [eax]", "rdtsc"] {
+            Instruction::try_from(&NasmStr(line))
+                .unwrap()
+                .execute(&mut cpu);
+        }
+        assert_eq!(cpu.registers.get_ebx(), 0);
+        assert_eq!(cpu.registers.get_eax(), 6);
+        assert_eq!(cpu.time_stamp_counter.get(), 9);
+    }
+
+    #[test]
+    fn instruction_execute_no_op() {
+        let mut cpu = Cpu::default();
+        for line in ["lfence", "sfence", "mfence", "pause", "rdtsc"] {
+            Instruction::try_from(&NasmStr(line))
+                .unwrap()
+                .execute(&mut cpu);
+        }
+        // The fences take 3 cycles each and PAUSE 10, and RDTSC reads the counter before its own
+        // 11 cycles are added.
+        assert_eq!(cpu.registers.get_eax(), 19);
+        assert_eq!(cpu.time_stamp_counter.get(), 30);
+        assert_eq!(cpu.cycles(), 30);
+    }
+
+    #[test]
+    fn instruction_cycles() {
+        for (line, expected) in [
+            ("sub ecx, 1", 1),
+            ("sub [0x20], ecx", 2),
+            ("rdtsc", 11),
+            ("int 0x21", 30),
+            // The string MOVSD is costed separately from the SSE2 move which shares its mnemonic.
+            ("movsd", 7),
+            ("movsd xmm0, xmm1", 1),
+        ] {
+            let instruction = Instruction::try_from(&NasmStr(line)).unwrap();
+            assert_eq!(instruction.cycles(), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn effective_address_default_segment() {
+        for (effective_address, expected) in [
+            ("[0x100]", SegmentRegister::Ds),
+            ("[eax]", SegmentRegister::Ds),
+            ("[ebp]", SegmentRegister::Ss),
+            ("[esp+4]", SegmentRegister::Ss),
+            ("[ebp+eax*2]", SegmentRegister::Ss),
+            ("[eax+ebp*2]", SegmentRegister::Ds),
+        ] {
+            let effective_address =
+                EffectiveAddress::try_from(&NasmStr(effective_address)).unwrap();
+            assert_eq!(effective_address.default_segment(), expected);
+        }
+    }
+
+    #[test]
+    fn effective_address_offset() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(2);
+        cpu.registers.set_ebx(0x100);
+        for (effective_address, expected) in [
+            ("[0x100]", 0x100),
+            ("[0x100+eax*4]", 0x108),
+            ("[eax*4+0x100]", 0x108),
+            ("[ebx+eax*4+8]", 0x110),
+            ("[ebx-4+eax*2*2]", 0x104),
+            ("[eax-4]", 0xffff_fffe),
+        ] {
+            let effective_address =
+                EffectiveAddress::try_from(&NasmStr(effective_address)).unwrap();
+            assert_eq!(effective_address.offset(&cpu), expected);
+        }
+    }
+
+    #[test]
+    fn instruction_execute_segment_override() {
+        let mut cpu = Cpu::default();
+        cpu.registers.set_segment_base(SegmentRegister::Fs, 0x1000);
+        cpu.memory.write32(0x18, 0x1111_1111).unwrap();
+        cpu.memory.write32(0x1018, 0x2222_2222).unwrap();
+        for line in [
+            "fs",
+            "mov eax, [0x18]",
+            "mov ebx, [0x18]",
+            "fs",
+            "lea ecx, [0x18]",
+        ] {
+            Instruction::try_from(&NasmStr(line))
+                .unwrap()
+                .execute(&mut cpu);
+        }
+        assert_eq!(cpu.registers.get_eax(), 0x2222_2222);
+        assert_eq!(cpu.registers.get_ebx(), 0x1111_1111);
+        assert_eq!(cpu.registers.get_ecx(), 0x18);
+        assert_eq!(cpu.segment_override, None);
+        assert_eq!(cpu.pending_segment_override, None);
+    }
+
+    #[test]
+    fn instruction_execute_lock() {
+        fn record_address_in_edx(cpu: &mut Cpu, address: u32) {
+            cpu.registers.set_edx(address);
+        }
+
+        fn set_ecx_to_0x6(cpu: &mut Cpu) {
+            cpu.registers.set_ecx(6);
+        }
+
+        let mut cpu = Cpu::default();
+        cpu.set_locked_cycle_observer(record_address_in_edx);
+        cpu.register_interrupt_handler(CpuException::InvalidOpcode.vector(), set_ecx_to_0x6);
+        cpu.registers.set_eax(3);
+        cpu.registers.set_ebx(0x100);
+        cpu.memory.write32(0x100, 5).unwrap();
+
+        Instruction::try_from(&NasmStr("lock xadd [ebx], eax"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.memory.read32(0x100).unwrap(), 8);
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.get_edx(), 0x100);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+
+        cpu.registers.set_edx(0);
+        Instruction::try_from(&NasmStr("lock add eax, 1"))
+            .unwrap()
+            .execute(&mut cpu);
+        assert_eq!(cpu.registers.get_eax(), 5);
+        assert_eq!(cpu.registers.get_edx(), 0);
+        assert_eq!(cpu.registers.get_ecx(), 6);
     }
 
     #[test]
@@ -2232,4 +5436,55 @@ mod tests {
         assert_eq!(Immediate(u16::MAX as u32 + 1).infer_size(), Size::Dword);
         assert_eq!(Immediate(u32::MAX).infer_size(), Size::Dword);
     }
+
+    #[test]
+    fn immediate_fits() {
+        assert!(Immediate(255).fits(Size::Byte, false));
+        assert!(Immediate(-128i32 as u32).fits(Size::Byte, false));
+        assert!(!Immediate(256).fits(Size::Byte, false));
+        assert!(!Immediate(-129i32 as u32).fits(Size::Byte, false));
+        assert!(Immediate(127).fits(Size::Byte, true));
+        assert!(Immediate(-1i32 as u32).fits(Size::Byte, true));
+        assert!(!Immediate(128).fits(Size::Byte, true));
+        assert!(Immediate(65535).fits(Size::Word, false));
+        assert!(!Immediate(-32769i32 as u32).fits(Size::Word, false));
+        assert!(Immediate(u32::MAX).fits(Size::Dword, false));
+    }
+
+    #[test]
+    fn instruction_parse_immediate_out_of_range() {
+        let parse = |instruction| {
+            let mut warnings = Vec::new();
+            Instruction::parse(
+                instruction,
+                Syntax::Nasm,
+                &SymbolTable::default(),
+                &mut warnings,
+            )
+            .unwrap();
+            warnings
+        };
+
+        assert_eq!(
+            parse("add al, 500"),
+            vec![Warning::ImmediateOutOfRange {
+                operand: 1,
+                value: 500,
+                size: Size::Byte,
+                sign_extended: false,
+            }]
+        );
+        assert_eq!(
+            parse("sub ebx, byte 200")[0].to_string(),
+            "200 does not fit in a signed byte, and is truncated"
+        );
+        assert_eq!(
+            parse("cmp word [eax], -32769")[0].to_string(),
+            "-32769 does not fit in a word, and is truncated"
+        );
+        assert!(parse("add al, -128").is_empty());
+        assert!(parse("add al, 0xff").is_empty());
+        assert!(parse("sub ebx, byte -1").is_empty());
+        assert!(parse("add eax, 0xffffffff").is_empty());
+    }
 }
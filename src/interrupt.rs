@@ -0,0 +1,244 @@
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::fmt;
+
+use crate::cpu::Cpu;
+
+/// Exceptions raised by the CPU, each of which is delivered through its fixed interrupt vector.
+/// Instructions raise these rather than panicking, such that the fault can be handled by the guest
+/// (through its interrupt vector table) or by the embedder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuException {
+    /// #DE, raised when dividing by 0.
+    DivideError = 0,
+    /// #DB, raised by debug conditions such as accessing a debug register while DR7.GD is set.
+    Debug = 1,
+    /// #BP, raised by the `INT3` instruction, conventionally used by debuggers to plant
+    /// breakpoints.
+    Breakpoint = 3,
+    /// #OF, raised by the `INTO` instruction when the overflow flag is set.
+    Overflow = 4,
+    /// #BR, raised by the `BOUND` instruction when an index is outside of the bounds.
+    BoundRangeExceeded = 5,
+    /// #UD, raised by the `UD2` instruction, when an instruction has no valid encoding, or when it
+    /// references DR4 or DR5 while debugging extensions are enabled.
+    InvalidOpcode = 6,
+    /// #NM, raised by an SSE instruction while CR0.TS is set, so that the operating system can
+    /// save and restore SIMD state lazily.
+    DeviceNotAvailable = 7,
+    /// #DF, raised when an exception cannot be delivered, e.g. because the stack which it would be
+    /// delivered on is inaccessible.
+    DoubleFault = 8,
+    /// #TS, raised when switching to a task whose task-state segment is invalid.
+    InvalidTss = 10,
+    /// #NP, raised when loading a segment whose descriptor is marked as not present.
+    SegmentNotPresent = 11,
+    /// #SS, raised when a stack access exceeds the limit of the stack segment.
+    StackFault = 12,
+    /// #GP, raised when a protection check is violated, e.g. when a privileged instruction is
+    /// executed outside of CPL 0, or when memory outside of the address space is accessed.
+    GeneralProtection = 13,
+    /// #PF, raised when accessing a page which is not present, or whose protection forbids the
+    /// access.
+    PageFault = 14,
+    /// #MF, raised by an x87 FPU instruction which detects a pending unmasked floating-point
+    /// exception.
+    X87FloatingPoint = 16,
+    /// #AC, raised by an unaligned memory access while alignment checking is enabled (CR0.AM and
+    /// EFLAGS.AC) at CPL 3.
+    AlignmentCheck = 17,
+    /// #XM, raised by an SSE instruction which detects an unmasked SIMD floating-point exception,
+    /// provided that CR4.OSXMMEXCPT is set. Otherwise, #UD is raised instead.
+    SimdFloatingPoint = 19,
+}
+
+impl CpuException {
+    /// The interrupt vector through which the exception is delivered.
+    pub fn vector(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Whether the exception is a trap, which is raised once the instruction which caused it has
+    /// completed, such that the return address is that of the instruction after it. Otherwise, it
+    /// is a fault, which is raised with EIP (and the return address) still referring to the
+    /// instruction, such that it is restarted once the fault has been handled. #DB is a fault when
+    /// it is raised before an instruction (e.g. by an instruction breakpoint), and a trap when it
+    /// is raised once the instruction has completed (e.g. by a data breakpoint).
+    pub fn is_trap(&self) -> bool {
+        matches!(self, CpuException::Breakpoint | CpuException::Overflow)
+    }
+
+    /// Whether the exception pushes an error code when it is delivered through the IDT in
+    /// protected mode. For #PF, this describes the access which faulted (see `paging::PageFault`), and for
+    /// the others it is 0.
+    pub fn has_error_code(&self) -> bool {
+        use CpuException::*;
+        matches!(
+            self,
+            DoubleFault
+                | InvalidTss
+                | SegmentNotPresent
+                | StackFault
+                | GeneralProtection
+                | PageFault
+                | AlignmentCheck
+        )
+    }
+
+    /// The mnemonic by which the exception is known, e.g. `#GP`.
+    pub fn mnemonic(&self) -> &'static str {
+        use CpuException::*;
+        match self {
+            DivideError => "#DE",
+            Debug => "#DB",
+            Breakpoint => "#BP",
+            Overflow => "#OF",
+            BoundRangeExceeded => "#BR",
+            InvalidOpcode => "#UD",
+            DeviceNotAvailable => "#NM",
+            DoubleFault => "#DF",
+            InvalidTss => "#TS",
+            SegmentNotPresent => "#NP",
+            StackFault => "#SS",
+            GeneralProtection => "#GP",
+            PageFault => "#PF",
+            X87FloatingPoint => "#MF",
+            AlignmentCheck => "#AC",
+            SimdFloatingPoint => "#XM",
+        }
+    }
+}
+
+impl fmt::Display for CpuException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (vector {})", self.mnemonic(), self.vector())
+    }
+}
+
+/// A host (Rust) closure which services an interrupt in place of guest code. This allows programs
+/// to request services (e.g. `int 0x21`) without any interrupt service routines being present in
+/// memory.
+pub type InterruptHandler = Box<dyn FnMut(&mut Cpu)>;
+
+/// What services an interrupt vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptVector {
+    /// A host handler, which takes precedence over the interrupt vector table.
+    Host,
+    /// The interrupt service routine at the given far pointer, as read from the vector's entry in
+    /// the interrupt vector table, or from its gate in the IDT in protected mode. For a task gate,
+    /// the segment is the selector of the TSS.
+    Guest { segment: u16, offset: u32 },
+    /// Nothing, as there is no host handler and the vector's entry is null (or in protected mode,
+    /// beyond the limit of the IDT).
+    Unset,
+}
+
+/// How an interrupt was raised, which determines how it is delivered through the IDT in protected
+/// mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum InterruptKind {
+    /// By `INT n`, `INT3`, or `INTO`, which may only use a gate whose DPL is at least the CPL.
+    Software,
+    /// By an exception, with the error code which it pushes (if any).
+    Exception(Option<u32>),
+    /// By a device, through the interrupt controller.
+    External,
+}
+
+/// Host interrupt handlers, keyed by interrupt vector. A vector may have at most one handler.
+#[derive(Default)]
+pub struct InterruptHandlers(BTreeMap<u8, InterruptHandler>);
+
+impl fmt::Debug for InterruptHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut vectors: Vec<_> = self.0.keys().collect();
+        vectors.sort();
+        f.debug_tuple("InterruptHandlers").field(&vectors).finish()
+    }
+}
+
+impl InterruptHandlers {
+    /// Registers a handler for the given vector, returning the handler it replaced (if any).
+    pub fn register(&mut self, vector: u8, handler: InterruptHandler) -> Option<InterruptHandler> {
+        self.0.insert(vector, handler)
+    }
+
+    /// Removes the handler for the given vector, returning it (if any).
+    pub fn unregister(&mut self, vector: u8) -> Option<InterruptHandler> {
+        self.0.remove(&vector)
+    }
+
+    /// Whether a handler is registered for the given vector.
+    pub fn contains(&self, vector: u8) -> bool {
+        self.0.contains_key(&vector)
+    }
+
+    /// Calls the handler for the given vector (if any), returning whether there was one. The
+    /// handler is removed from `cpu` while it is called, so that it can be given the CPU, and is
+    /// then put back unless it has been replaced in the meantime.
+    pub(crate) fn call(cpu: &mut Cpu, vector: u8) -> bool {
+        let Some(mut handler) = cpu.interrupt_handlers.unregister(vector) else {
+            return false;
+        };
+        handler(cpu);
+        cpu.interrupt_handlers.0.entry(vector).or_insert(handler);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler_a(cpu: &mut Cpu) {
+        cpu.registers.set_eax(1);
+    }
+
+    fn handler_b(cpu: &mut Cpu) {
+        cpu.registers.set_eax(2);
+    }
+
+    #[test]
+    fn cpu_exception_display() {
+        assert_eq!(CpuException::PageFault.to_string(), "#PF (vector 14)");
+        assert_eq!(CpuException::StackFault.mnemonic(), "#SS");
+        assert_eq!(CpuException::AlignmentCheck.vector(), 17);
+    }
+
+    #[test]
+    fn register_and_unregister() {
+        let mut handlers = InterruptHandlers::default();
+        assert!(!handlers.contains(0x21));
+
+        assert!(handlers.register(0x21, Box::new(handler_a)).is_none());
+        assert!(handlers.contains(0x21));
+        assert!(!handlers.contains(0x20));
+
+        // Registering a second handler replaces the first.
+        assert!(handlers.register(0x21, Box::new(handler_b)).is_some());
+        let mut cpu = Cpu::default();
+        cpu.interrupt_handlers = handlers;
+        assert!(InterruptHandlers::call(&mut cpu, 0x21));
+        assert_eq!(cpu.registers.get_eax(), 2);
+        assert!(!InterruptHandlers::call(&mut cpu, 0x20));
+
+        let mut handlers = core::mem::take(&mut cpu.interrupt_handlers);
+        assert!(handlers.unregister(0x21).is_some());
+        assert!(!handlers.contains(0x21));
+        assert!(handlers.unregister(0x21).is_none());
+    }
+
+    #[test]
+    fn closure_handler() {
+        let mut cpu = Cpu::default();
+        let mut calls = 0;
+        cpu.register_interrupt_handler(0x21, move |cpu| {
+            calls += 1;
+            cpu.registers.set_eax(calls);
+        });
+        assert!(InterruptHandlers::call(&mut cpu, 0x21));
+        assert!(InterruptHandlers::call(&mut cpu, 0x21));
+        assert_eq!(cpu.registers.get_eax(), 2);
+        assert_eq!(cpu.interrupt_vector(0x21), InterruptVector::Host);
+    }
+}
@@ -0,0 +1,167 @@
+use alloc::{format, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    error::Error,
+    instruction::{NasmStr, Size},
+    program::{is_symbol_char, is_symbol_start},
+};
+
+/// The punctuation which may appear within an operand, with the longest first such that e.g. `<<`
+/// is never mistaken for `<`.
+const PUNCTUATION: [&str; 15] = [
+    "<<", ">>", "|", "^", "&", "+", "-", "*", "/", "%", "~", "(", ")", ",", ":",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind<'a> {
+    /// A name, such as that of a register or a symbol.
+    Identifier(&'a str),
+    /// A numeric literal, which is left as text as its format depends on the syntax (e.g. MASM
+    /// allows `0C8H`, whereas NASM only allows `0c8h`).
+    Number(&'a str),
+    Punctuation(&'static str),
+    OpenBracket,
+    CloseBracket,
+    /// A size keyword, such as `dword`.
+    Size(Size),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    /// The range of bytes within the text which the token was read from.
+    pub span: Range<usize>,
+}
+
+/// Splits text (e.g. an operand such as `dword [ebx+4*ecx]`) into tokens, ignoring whitespace.
+/// Returns an `Err` if it contains a character which cannot begin a token.
+pub fn tokenize(text: &str) -> Result<Vec<Token<'_>>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let (kind, end) = if c.is_ascii_digit() || is_symbol_start(c) {
+            // Numbers may contain letters for their radix (e.g. `0xff` or `11b`) and underscores as
+            // separators, whereas names may contain any of the characters allowed in a symbol.
+            let is_word_char: fn(char) -> bool = if c.is_ascii_digit() {
+                |c| c.is_ascii_alphanumeric() || c == '_'
+            } else {
+                is_symbol_char
+            };
+            let mut end = start + c.len_utf8();
+            while let Some((i, c)) = chars.next_if(|&(_, c)| is_word_char(c)) {
+                end = i + c.len_utf8();
+            }
+
+            let word = &text[start..end];
+            let kind = if c.is_ascii_digit() {
+                TokenKind::Number(word)
+            } else if let Ok(size) = Size::try_from(&NasmStr(word)) {
+                TokenKind::Size(size)
+            } else {
+                TokenKind::Identifier(word)
+            };
+            (kind, end)
+        } else if let Some(punctuation) = PUNCTUATION
+            .iter()
+            .find(|punctuation| text[start..].starts_with(*punctuation))
+        {
+            // Skip the remainder of multi-character punctuation.
+            for _ in 1..punctuation.len() {
+                chars.next();
+            }
+            (
+                TokenKind::Punctuation(punctuation),
+                start + punctuation.len(),
+            )
+        } else {
+            let kind = match c {
+                '[' => TokenKind::OpenBracket,
+                ']' => TokenKind::CloseBracket,
+                _ => {
+                    return Err(Error::CannotParseInstruction(format!(
+                        "unexpected '{c}' in \"{text}\""
+                    )))
+                }
+            };
+            (kind, start + 1)
+        };
+
+        tokens.push(Token {
+            kind,
+            span: start..end,
+        });
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<TokenKind<'_>> {
+        tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_operands() {
+        use TokenKind::*;
+
+        assert_eq!(
+            kinds("dword [ebx+4*ecx]"),
+            vec![
+                Size(crate::instruction::Size::Dword),
+                OpenBracket,
+                Identifier("ebx"),
+                Punctuation("+"),
+                Number("4"),
+                Punctuation("*"),
+                Identifier("ecx"),
+                CloseBracket,
+            ]
+        );
+        assert_eq!(
+            kinds("(1<<0x1_0)|~11b"),
+            vec![
+                Punctuation("("),
+                Number("1"),
+                Punctuation("<<"),
+                Number("0x1_0"),
+                Punctuation(")"),
+                Punctuation("|"),
+                Punctuation("~"),
+                Number("11b"),
+            ]
+        );
+        assert_eq!(
+            kinds("es:.loop ..@1.x a$b"),
+            vec![
+                Identifier("es"),
+                Punctuation(":"),
+                Identifier(".loop"),
+                Identifier("..@1.x"),
+                Identifier("a$b"),
+            ]
+        );
+        assert_eq!(kinds("  "), vec![]);
+
+        let spans: Vec<_> = tokenize(" [ eax ] <<1")
+            .unwrap()
+            .into_iter()
+            .map(|token| token.span)
+            .collect();
+        assert_eq!(spans, vec![1..2, 3..6, 7..8, 9..11, 11..12]);
+
+        assert!(tokenize("$").is_err());
+        assert!(tokenize("eax < 1").is_err());
+        assert!(tokenize("'a'").is_err());
+    }
+}
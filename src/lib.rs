@@ -1,26 +1,95 @@
-mod arguments;
-mod cpu;
-mod encodedinstruction;
-mod error;
-mod instruction;
-mod memory;
-mod modrm;
-mod register;
-mod sib;
-mod traits;
+//! An emulator of a 32-bit x86 processor, which assembles programs written in NASM, AT&T, or
+//! MASM syntax and runs them. Along with the `peanut` command, it can be embedded in other crates:
+//! a [`Program`] is assembled, and then run by a [`Machine`] on a [`Cpu`], whose [`Registers`] and
+//! [`Memory`] can be inspected and changed between runs. For example:
+//!
+//! ```
+//! use peanut::{
+//!     instruction::Syntax,
+//!     machine::StopReason,
+//!     program::Layout,
+//!     register::Register32,
+//!     Cpu, Machine, Program,
+//! };
+//!
+//! let source = "sub ecx, 1\nmov [0x100], ecx\nhlt";
+//! let program = Program::assemble(source, Layout::default(), Syntax::Nasm)?;
+//! let mut cpu = Cpu::default();
+//! cpu.registers_mut().write32(&Register32::Ecx, 5);
+//! let mut machine = Machine::new(cpu, program)?;
+//! assert_eq!(machine.run()?, StopReason::Halted);
+//!
+//! let cpu = machine.cpu();
+//! assert_eq!(cpu.registers().read32(&Register32::Ecx), 4);
+//! assert_eq!(cpu.memory().read::<u32>(0x100)?, 4);
+//! # Ok::<(), peanut::Error>(())
+//! ```
+//!
+//! Faults caused by the program (e.g. accessing memory which is protected, or executing an invalid
+//! instruction) never panic. As on a real processor, they raise an exception, which is delivered
+//! to the program's handler for it (see [`Machine::set_interrupt_vector`]), or to a host handler
+//! (see [`Machine::set_interrupt_handler`]). An exception which is not handled stops the machine
+//! with [`StopReason::Exception`](machine::StopReason::Exception), after which its state can be
+//! inspected, changed, and the machine run again. Only problems with the run itself (e.g. a
+//! timeout, a stack overflow, or a failed assertion) are returned as an [`Error`].
+//!
+//! With the `serde` feature, the state of a machine (its registers, and the contents of its memory)
+//! can be serialized, e.g. to compare it with a golden file, and deserialized as a
+//! [`CpuState`](cpu::CpuState) to be restored later.
+//!
+//! The `std` feature, which is enabled by default, provides the `peanut` command (see `run`), the
+//! devices and DOS personality which talk to the standard streams, the tracers which write to an
+//! I/O sink, and the timeout of a run. Without it, the crate is `no_std` (although it needs
+//! `alloc`), such that the processor, its memory, and the assembler can run inside a kernel, a
+//! fuzzer, or an embedded host.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::fs;
+extern crate alloc;
 
-use clap::Parser;
-use cpu::Cpu;
-use instruction::{Instruction, NasmStr};
+#[cfg(feature = "std")]
+mod arguments;
+pub mod assertion;
+pub mod breakpoint;
+pub mod builder;
+#[cfg(feature = "std")]
+mod cli;
+pub mod coverage;
+pub mod cpu;
+pub mod descriptor;
+pub mod devices;
+pub mod diagnostic;
+#[cfg(feature = "std")]
+pub mod dos;
+pub mod encoding;
+pub mod error;
+mod expression;
+pub mod fpu;
+pub mod instruction;
+pub mod interrupt;
+mod lexer;
+pub mod machine;
+pub mod memory;
+mod msr;
+mod paging;
+mod parser;
+mod preprocessor;
+pub mod profile;
+pub mod program;
+pub mod random;
+pub mod register;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sse;
+pub mod stats;
+pub mod trace;
+mod traits;
 
-pub fn run() {
-    let arguments = arguments::Arguments::parse();
-    let file_contents = fs::read_to_string(&arguments.file_path).expect("failed to read file");
-    let mut cpu = Cpu::default();
-    for line in file_contents.lines() {
-        let instruction = Instruction::try_from(&NasmStr(&line)).unwrap();
-        (instruction.cpu_function)(&mut cpu, &instruction.operands);
-    }
-}
+#[cfg(feature = "std")]
+pub use cli::run;
+pub use cpu::Cpu;
+pub use error::Error;
+pub use machine::Machine;
+pub use memory::Memory;
+pub use program::Program;
+pub use register::Registers;
@@ -0,0 +1,1222 @@
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use crate::{
+    breakpoint::{Breakpoint, Breakpoints},
+    coverage::Coverage,
+    cpu::{Cpu, CpuState},
+    devices::{
+        pic::{Pic, MASTER_PORTS, SLAVE_PORTS},
+        PortDevice,
+    },
+    error::Error,
+    instruction::Instruction,
+    interrupt::{CpuException, InterruptHandler, InterruptKind, InterruptVector},
+    memory::{HookId, HookTrigger, MemoryAccess, MemoryHook, Permissions},
+    profile::Profile,
+    program::{Program, SectionName},
+    register::{BreakpointCondition, Register},
+    stats::Stats,
+    trace::Tracer,
+};
+
+/// Why [`Machine::run`] stopped executing instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// `HLT` was executed, and EIP refers to the instruction after it.
+    Halted,
+    /// EIP reached a breakpoint (see [`Breakpoint`]) at the given address. The instruction there
+    /// has not yet been executed.
+    Breakpoint(u32),
+    /// The hardware breakpoint with the given index (0-3) in the debug registers was hit, and the
+    /// #DB exception which it raised could not be delivered. For an instruction breakpoint, the
+    /// instruction at EIP has not yet been executed (and the RF flag is set, such that it is
+    /// executed once the machine is resumed). For a data breakpoint, the access was made by the
+    /// instruction before EIP.
+    HardwareBreakpoint(usize),
+    /// An exception was raised which could not be delivered, as there was neither a host handler
+    /// nor an interrupt service routine (i.e. its entry in the interrupt vector table is null) for
+    /// it. Exceptions which are delivered do not stop the machine. EIP refers to the instruction
+    /// which raised the exception if it is a fault, or to the instruction after it if it is a trap
+    /// (see [`CpuException::is_trap`]).
+    Exception(CpuException),
+    /// The limit on the number of instructions executed by a single run was reached.
+    InstructionLimit,
+    /// EIP refers to the given address, which is neither within the program's `.text` nor within
+    /// an image loaded by [`Machine::load_image`] (e.g. because the last instruction has been
+    /// executed).
+    OutOfBounds(u32),
+}
+
+/// How many instructions are executed between each check of whether a run has timed out, as
+/// checking the time is comparatively slow.
+#[cfg(feature = "std")]
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// What a single instruction did, as reported by [`Machine::step`].
+#[derive(Clone, Debug)]
+pub struct StepReport {
+    /// The address of the instruction.
+    pub address: u32,
+    /// The instruction which was executed, or `None` if there was no instruction to execute. This
+    /// is the case when EIP is out-of-bounds, when an interrupt was delivered instead, or when the
+    /// instruction could not be fetched (e.g. as its page is not present).
+    pub instruction: Option<Instruction>,
+    /// The machine code of the instruction, which is empty if there was no instruction to execute.
+    pub bytes: Vec<u8>,
+    /// The general-purpose and segment registers which were changed by the instruction. EIP is
+    /// left out, as almost every instruction changes it.
+    pub registers: Vec<Register>,
+    /// Whether EFLAGS was changed by the instruction.
+    pub eflags_changed: bool,
+    /// The accesses to memory which were made by the instruction, in the order in which they were
+    /// made.
+    pub memory_accesses: Vec<MemoryAccess>,
+    /// Why the machine stopped, if the instruction caused it to. A breakpoint or instruction limit
+    /// never stops a single step.
+    pub stop_reason: Option<StopReason>,
+}
+
+/// The checkpoints which a machine can be rewound to, while its history is being recorded (see
+/// [`Machine::record_history`]).
+struct History {
+    /// The number of instructions which are executed between checkpoints.
+    interval: u64,
+    /// The number of instructions which have been executed since recording began.
+    position: u64,
+    /// The state of the processor before the instruction at each position which is a multiple of
+    /// the interval, in order of position. The first is always at position 0.
+    checkpoints: Vec<(u64, CpuState)>,
+}
+
+/// A processor which runs a program, and which reports why it stopped.
+pub struct Machine {
+    cpu: Cpu,
+    program: Program,
+    /// The ranges of memory which images were loaded into (see [`Machine::load_image`]).
+    images: Vec<RangeInclusive<u32>>,
+    breakpoints: Breakpoints,
+    instruction_limit: Option<u64>,
+    #[cfg(feature = "std")]
+    timeout: Option<Duration>,
+    pic: Option<Pic>,
+    tracer: Option<Box<dyn Tracer>>,
+    stats: Option<Stats>,
+    coverage: Option<Coverage>,
+    history: Option<History>,
+    profile: Option<Profile>,
+}
+
+impl Machine {
+    /// Loads the program into the processor's memory (see [`Program::load`]), such that it is
+    /// ready to be run. Returns an `Err` if the program does not fit in memory.
+    pub fn new(mut cpu: Cpu, program: Program) -> Result<Self, Error> {
+        program.load(&mut cpu)?;
+        Ok(Self {
+            cpu,
+            program,
+            images: Vec::new(),
+            breakpoints: Breakpoints::default(),
+            instruction_limit: None,
+            #[cfg(feature = "std")]
+            timeout: None,
+            pic: None,
+            tracer: None,
+            stats: None,
+            coverage: None,
+            history: None,
+            profile: None,
+        })
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Loads an image (e.g. a ROM or a boot sector) into memory at `address`, overwriting what was
+    /// there. Like the program's `.text`, the code in the image may then be executed. Returns an
+    /// `Err` if the image does not fit in memory.
+    pub fn load_image(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        self.cpu.memory.load(address, bytes)?;
+        if let Some(last) = (bytes.len() as u32).checked_sub(1) {
+            self.images.push(address..=address + last);
+        }
+        Ok(())
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    /// Sets a breakpoint at `address`, returning `false` if there already was one.
+    pub fn add_breakpoint(&mut self, address: u32) -> bool {
+        self.breakpoints.add_address(address)
+    }
+
+    /// Removes the breakpoint at `address`, returning `false` if there was none.
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        self.breakpoints.remove_address(address)
+    }
+
+    /// Sets a breakpoint on an address, a label of the program, or a mnemonic, returning `false`
+    /// if it was already set. Returns an `Error::InvalidSymbol` if the label is not defined.
+    pub fn set_breakpoint(&mut self, breakpoint: &Breakpoint) -> Result<bool, Error> {
+        self.breakpoints.add(breakpoint, self.program.symbols())
+    }
+
+    /// Removes a breakpoint which was set by [`Machine::set_breakpoint`], returning `false` if it
+    /// was not set.
+    pub fn clear_breakpoint(&mut self, breakpoint: &Breakpoint) -> Result<bool, Error> {
+        self.breakpoints.remove(breakpoint, self.program.symbols())
+    }
+
+    /// Adds a hook which is called with each access made to the given range of memory, of the kind
+    /// given by the trigger (see [`Memory::add_hook`](crate::memory::Memory::add_hook)).
+    pub fn add_memory_hook(
+        &mut self,
+        range: RangeInclusive<u32>,
+        trigger: HookTrigger,
+        hook: impl FnMut(&MemoryAccess) + 'static,
+    ) -> HookId {
+        self.cpu.memory.add_hook(range, trigger, hook)
+    }
+
+    /// Removes a memory hook, returning it, or `None` if it had already been removed.
+    pub fn remove_memory_hook(&mut self, id: HookId) -> Option<MemoryHook> {
+        self.cpu.memory.remove_hook(id)
+    }
+
+    /// Protects the memory occupied by the program's sections (see
+    /// [`Memory::protect`](crate::memory::Memory::protect)), such that `.text` may only be read and
+    /// executed, and `.data` and `.bss` may only be read and written. Writing to code, or executing
+    /// data, then raises a #GP exception rather than silently corrupting the program.
+    pub fn protect_sections(&mut self) {
+        let sections = [
+            (SectionName::Text, Permissions::READ_EXECUTE),
+            (SectionName::Data, Permissions::READ_WRITE),
+            (SectionName::Bss, Permissions::READ_WRITE),
+        ]
+        .map(|(name, permissions)| {
+            let section = self.program.section(name);
+            (section.base, section.size, permissions)
+        });
+        for (base, len, permissions) in sections {
+            if len > 0 {
+                let last = base.saturating_add(len - 1);
+                self.cpu.memory.protect(base..=last, permissions);
+            }
+        }
+    }
+
+    /// Attaches a device to the I/O port bus, such that `IN` and `OUT` instructions targeting the
+    /// given range of ports are serviced by it. Returns an `Err` if any of the ports are already
+    /// used by another device.
+    pub fn attach_device(
+        &mut self,
+        ports: RangeInclusive<u16>,
+        device: impl PortDevice + 'static,
+    ) -> Result<(), Error> {
+        self.cpu.attach_io_device(ports, Box::new(device))
+    }
+
+    /// Attaches a pair of cascaded 8259 PICs to their ports (0x20 and 0xa0), and delivers the IRQs
+    /// which they are requested to the processor between instructions, while interrupts are
+    /// enabled (i.e. IF is set). The returned handle is used to connect devices to IRQ lines.
+    /// Returns an `Err` if any of the ports are already used by another device.
+    pub fn attach_pic(&mut self) -> Result<Pic, Error> {
+        let pic = Pic::default();
+        self.install_pic(pic.clone())?;
+        Ok(pic)
+    }
+
+    /// Attaches a PIC which devices may already be connected to. See [`Machine::attach_pic`].
+    pub fn install_pic(&mut self, pic: Pic) -> Result<(), Error> {
+        let (master, slave) = pic.ports();
+        self.attach_device(MASTER_PORTS, master)?;
+        self.attach_device(SLAVE_PORTS, slave)?;
+        self.pic = Some(pic);
+        Ok(())
+    }
+
+    /// Services the interrupt `vector` with a host closure, which is given the processor in place
+    /// of any interrupt service routine in guest code (e.g. to implement `int 0x21` services).
+    /// Returns the handler it replaced (if any).
+    pub fn set_interrupt_handler(
+        &mut self,
+        vector: u8,
+        handler: impl FnMut(&mut Cpu) + 'static,
+    ) -> Option<InterruptHandler> {
+        self.cpu.register_interrupt_handler(vector, handler)
+    }
+
+    /// Services the interrupt `vector` with the guest code at `address` (e.g. that of a label, see
+    /// [`Program::symbols`]), by pointing its entry in the interrupt vector table at it, or in
+    /// protected mode, its gate in the IDT at the code segment in CS. Any host handler for the
+    /// vector is removed. Returns an `Err` if the address cannot be reached by a 16-bit offset in
+    /// real mode, or if the entry is not in memory.
+    pub fn set_interrupt_vector(&mut self, vector: u8, address: u32) -> Result<(), Error> {
+        let cpu = &mut self.cpu;
+        if cpu.registers.control_registers.get_protection_enable() {
+            return cpu.set_interrupt_vector(vector, cpu.registers.cs, address);
+        }
+        let offset = u16::try_from(address).map_err(|_| {
+            Error::InaccessibleAddress(format!(
+                "interrupt {vector:#04x} cannot be serviced at {address:#x}, which is not within \
+                 the first 64 KiB"
+            ))
+        })?;
+        cpu.set_interrupt_vector(vector, 0, offset.into())
+    }
+
+    /// What services the interrupt `vector`.
+    pub fn interrupt_vector(&self, vector: u8) -> InterruptVector {
+        self.cpu.interrupt_vector(vector)
+    }
+
+    /// Sets the hardware breakpoint `index` (0-3) in the debug registers, such that accesses of the
+    /// given kind to the `length` bytes at the linear address `address` raise a #DB exception (see
+    /// `DebugRegisters::set_breakpoint`). Unless the guest services #DB, this stops the machine
+    /// with `StopReason::HardwareBreakpoint`. Panics if the index is not 0-3, or the length is not
+    /// 1, 2, 4, or 8 bytes.
+    pub fn set_hardware_breakpoint(
+        &mut self,
+        index: usize,
+        address: u32,
+        condition: BreakpointCondition,
+        length: u32,
+    ) {
+        self.cpu
+            .registers
+            .debug_registers
+            .set_breakpoint(index, address, condition, length);
+    }
+
+    /// Disables the hardware breakpoint `index` (0-3).
+    pub fn remove_hardware_breakpoint(&mut self, index: usize) {
+        self.cpu.registers.debug_registers.clear_breakpoint(index);
+    }
+
+    /// Sets the maximum number of instructions which a single call to [`Machine::run`] executes,
+    /// or removes the limit if `None`.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+
+    /// Sets the lowest address which the stack may grow down to, or removes the limit if `None`
+    /// (see [`Cpu::set_stack_limit`]).
+    pub fn set_stack_limit(&mut self, limit: Option<u32>) {
+        self.cpu.set_stack_limit(limit);
+    }
+
+    /// Sets the maximum (wall-clock) time which a single call to [`Machine::run`] may take, or
+    /// removes the limit if `None`. Unlike the instruction limit, this is a watchdog for programs
+    /// which never stop (e.g. due to an infinite loop), and so exceeding it is an error. This needs
+    /// a clock, and so is only available with the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Installs a tracer, which is told about each instruction that is executed (by both
+    /// [`Machine::run`] and [`Machine::step`]), or removes it if `None`. Returns the tracer which
+    /// was replaced (if any).
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn Tracer>>) -> Option<Box<dyn Tracer>> {
+        core::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// Starts collecting statistics about the instructions which are executed, discarding any
+    /// which were collected before, or stops collecting them (and discards them).
+    pub fn collect_stats(&mut self, collect: bool) {
+        self.stats = collect.then(Stats::default);
+    }
+
+    /// The statistics which have been collected since [`Machine::collect_stats`] enabled them.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// Starts counting how many times each instruction is executed, discarding any counts which
+    /// were collected before, or stops counting them (and discards them).
+    pub fn collect_coverage(&mut self, collect: bool) {
+        self.coverage = collect.then(Coverage::default);
+    }
+
+    /// The coverage which has been collected since [`Machine::collect_coverage`] enabled it.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts profiling where the cycles of the instructions which are executed are spent,
+    /// discarding any profile which was collected before, or stops profiling (and discards it).
+    pub fn collect_profile(&mut self, collect: bool) {
+        self.profile = collect.then(Profile::default);
+    }
+
+    /// The profile which has been collected since [`Machine::collect_profile`] enabled it.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Starts recording the machine's history, such that it can be rewound by
+    /// [`Machine::reverse_step`] and [`Machine::reverse_continue`], discarding any which was
+    /// recorded before, or stops recording it (if `None`). The processor's state is saved every
+    /// `interval` instructions, and rewinding restores the last state saved before the target
+    /// and replays the instructions in between, so a shorter interval rewinds faster but uses
+    /// more memory.
+    ///
+    /// Replaying is only faithful to the original run if its instructions behave the same way
+    /// again, as the state of devices, host interrupt handlers, memory hooks, and the entropy
+    /// source of `RDRAND` is not rewound.
+    pub fn record_history(&mut self, interval: Option<u64>) {
+        self.history = interval.map(|interval| History {
+            interval: interval.max(1),
+            position: 0,
+            checkpoints: vec![(0, self.cpu.save_state())],
+        });
+    }
+
+    /// The number of instructions which have been executed since history started being recorded,
+    /// less those which have been rewound.
+    pub fn history_position(&self) -> Option<u64> {
+        self.history.as_ref().map(|history| history.position)
+    }
+
+    /// Rewinds the machine by a single instruction, to the state it had before the instruction was
+    /// executed. Returns `false` without doing anything if history is not being recorded, or if no
+    /// instruction has been executed since it started being.
+    pub fn reverse_step(&mut self) -> bool {
+        match self.history_position() {
+            Some(position) if position > 0 => {
+                self.rewind(position - 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Rewinds the machine to the last time that EIP reached a breakpoint (see
+    /// [`Machine::set_breakpoint`]) before the instruction which it is at, returning the address
+    /// of the breakpoint. If none was reached since history started being recorded, then the
+    /// machine is rewound to the start of its history instead, and `None` is returned. Nothing is
+    /// done if history is not being recorded.
+    pub fn reverse_continue(&mut self) -> Option<u32> {
+        let position = self.history_position()?;
+        let starts: Vec<_> = self
+            .history
+            .as_ref()?
+            .checkpoints
+            .iter()
+            .map(|(start, _)| *start)
+            .filter(|start| *start < position)
+            .collect();
+        // The intervals between checkpoints are searched from the latest to the earliest, as the
+        // last breakpoint reached is wanted.
+        let mut end = position;
+        for start in starts.into_iter().rev() {
+            self.rewind(start);
+            let mut hit = None;
+            for position in start..end {
+                if self.breakpoint_hit(self.cpu.registers.get_eip()) {
+                    hit = Some(position);
+                }
+                self.replay(1);
+            }
+            if let Some(hit) = hit {
+                self.rewind(hit);
+                return Some(self.cpu.registers.get_eip());
+            }
+            end = start;
+        }
+        self.rewind(0);
+        None
+    }
+
+    /// Rewinds the machine to the state it had once `target` instructions of its history had been
+    /// executed, by restoring the last checkpoint before it and replaying the instructions after
+    /// that. The checkpoints after the target are discarded, as the machine may take a different
+    /// course from there (e.g. if its registers are changed).
+    fn rewind(&mut self, target: u64) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        history
+            .checkpoints
+            .retain(|(position, _)| *position <= target);
+        let (position, state) = history
+            .checkpoints
+            .last()
+            .expect("the first checkpoint is at position 0");
+        self.cpu.restore_state(state);
+        history.position = *position;
+        let count = target - *position;
+        self.replay(count);
+    }
+
+    /// Executes `count` instructions again, as [`Machine::run`] would have, but without
+    /// breakpoints, tracing, statistics, or coverage.
+    fn replay(&mut self, count: u64) {
+        for _ in 0..count {
+            self.advance_history();
+            let eip = self.cpu.registers.get_eip();
+            let _ = self
+                .deliver_interrupt()
+                .or_else(|| self.execute_instruction());
+            if self.cpu.stack_overflow.take().is_some() {
+                self.cpu.registers.set_eip(eip);
+            }
+        }
+    }
+
+    /// Counts the instruction at EIP, which is about to be executed, in the history (if it is being
+    /// recorded), saving a checkpoint before it if one is due.
+    fn advance_history(&mut self) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let position = history.position;
+        let saved = history
+            .checkpoints
+            .last()
+            .is_some_and(|(last, _)| *last >= position);
+        if position % history.interval == 0 && !saved {
+            history.checkpoints.push((position, self.cpu.save_state()));
+        }
+        history.position += 1;
+    }
+
+    /// Runs the program, starting with the instruction at EIP, until one of the conditions in
+    /// [`StopReason`] is met. A halted processor is resumed, and the breakpoint (if any) at the
+    /// first instruction is ignored, such that a stopped machine can always be run again.
+    ///
+    /// Returns an `Error::Timeout` if the run takes longer than the timeout (if any), in which case
+    /// the machine may also be run again. Returns an `Error::StackOverflow` if an instruction
+    /// overflows the stack (see [`Cpu::set_stack_limit`]), in which case it has not been executed
+    /// and EIP still refers to it. Returns an `Error::AssertionFailed` if one of the program's
+    /// assertions (see [`Assertion`](crate::assertion::Assertion)) does not hold before the
+    /// instruction which it applies to, which has likewise not been executed.
+    pub fn run(&mut self) -> Result<StopReason, Error> {
+        self.resume();
+        #[cfg(feature = "std")]
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut executed = 0;
+        loop {
+            #[cfg(feature = "std")]
+            if executed % TIMEOUT_CHECK_INTERVAL == 0
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(Error::Timeout(format!(
+                    "the program ran for longer than {:?}",
+                    self.timeout.unwrap_or_default()
+                )));
+            }
+            let eip = self.cpu.registers.get_eip();
+            if executed > 0 && self.breakpoint_hit(eip) {
+                return Ok(StopReason::Breakpoint(eip));
+            }
+            if self
+                .instruction_limit
+                .is_some_and(|limit| executed >= limit)
+            {
+                return Ok(StopReason::InstructionLimit);
+            }
+            self.program
+                .check_assertions(self.cpu.instruction_address(), &self.cpu)?;
+            let stop_reason = self.execute();
+            if let Some(esp) = self.cpu.stack_overflow.take() {
+                self.cpu.registers.set_eip(eip);
+                return Err(Error::StackOverflow(format!(
+                    "the instruction at {eip:#x} pushed past the end of the stack, with ESP at \
+                     {esp:#x}"
+                )));
+            }
+            if let Some(stop_reason) = stop_reason {
+                return Ok(stop_reason);
+            }
+            executed += 1;
+        }
+    }
+
+    /// Executes exactly one instruction, the one at EIP, and reports what it did. As with
+    /// [`Machine::run`], a halted processor is resumed first.
+    pub fn step(&mut self) -> StepReport {
+        self.resume();
+        self.advance_history();
+        self.execute_and_report()
+    }
+
+    /// Whether a breakpoint stops the machine at the instruction at EIP, which is `eip`.
+    fn breakpoint_hit(&self, eip: u32) -> bool {
+        // The instruction is only decoded if there are breakpoints, as that is comparatively slow.
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+        let instruction = self.cpu.peek_instruction();
+        self.breakpoints.hit(
+            eip,
+            instruction.as_ref().map(|(instruction, _)| instruction),
+        )
+    }
+
+    /// Whether the linear address `address` is within the program's `.text`, or within an image
+    /// which was loaded into memory, such that the code there may be executed.
+    fn in_bounds(&self, address: u32) -> bool {
+        self.program.in_text(address) || self.images.iter().any(|image| image.contains(&address))
+    }
+
+    /// Clears the conditions which stopped the machine, such that it can continue.
+    fn resume(&mut self) {
+        self.cpu.halted = false;
+        self.cpu.unreported_exception = None;
+        self.cpu.stack_overflow = None;
+        self.cpu.unreported_breakpoint = None;
+    }
+
+    /// Why the machine must stop because of the exception which could not be delivered (if any).
+    fn unreported_exception(&mut self) -> Option<StopReason> {
+        let exception = self.cpu.unreported_exception.take()?;
+        match self.cpu.unreported_breakpoint.take() {
+            Some(breakpoint) if exception == CpuException::Debug => {
+                Some(StopReason::HardwareBreakpoint(breakpoint))
+            }
+            _ => Some(StopReason::Exception(exception)),
+        }
+    }
+
+    /// Executes the instruction at EIP, returning why the machine must stop (if it must). Each
+    /// instruction is reported to the tracer (if there is one), and counted in the statistics,
+    /// coverage, and profile (if they are being collected).
+    fn execute(&mut self) -> Option<StopReason> {
+        self.advance_history();
+        if self.tracer.is_some()
+            || self.stats.is_some()
+            || self.coverage.is_some()
+            || self.profile.is_some()
+        {
+            return self.execute_and_report().stop_reason;
+        }
+        self.deliver_interrupt()
+            .or_else(|| self.execute_instruction())
+    }
+
+    /// Executes the instruction at EIP, and reports what it did (including to the tracer, the
+    /// statistics, the coverage, and the profile, if there are any).
+    fn execute_and_report(&mut self) -> StepReport {
+        let interrupt_stop_reason = self.deliver_interrupt();
+        let address = self.cpu.registers.get_eip();
+        let instruction_address = self.cpu.instruction_address();
+        let fetched = match interrupt_stop_reason {
+            None if self.in_bounds(instruction_address) => self.cpu.peek_instruction(),
+            _ => None,
+        };
+        let (instruction, bytes) = fetched.unzip();
+        if let Some(tracer) = &mut self.tracer {
+            tracer.before(address, instruction.as_ref(), &self.cpu);
+        }
+        let registers = self.cpu.registers.clone();
+        let cycles = self.cpu.cycles();
+
+        self.cpu.memory.start_recording();
+        let stop_reason = interrupt_stop_reason.or_else(|| self.execute_instruction());
+        let memory_accesses = self.cpu.memory.stop_recording();
+
+        let report = StepReport {
+            address,
+            instruction,
+            bytes: bytes.unwrap_or_default(),
+            registers: self.cpu.registers.changed_since(&registers),
+            eflags_changed: self.cpu.registers.eflags.get_value() != registers.eflags.get_value(),
+            memory_accesses,
+            stop_reason,
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer.after(&report, &self.cpu);
+        }
+        let cycles = self.cpu.cycles() - cycles;
+        if let Some(stats) = &mut self.stats {
+            stats.record(&report, self.cpu.registers.get_eip(), cycles);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            if report.instruction.is_some() {
+                coverage.record(instruction_address);
+            }
+        }
+        if let Some(profile) = &mut self.profile {
+            let next = self.cpu.instruction_address();
+            profile.record(&report, instruction_address, next, cycles);
+        }
+        report
+    }
+
+    /// Delivers a pending hardware interrupt (if any), such that the next instruction executed is
+    /// the first of its interrupt service routine. Returns why the machine must stop, if the
+    /// interrupt could not be delivered.
+    fn deliver_interrupt(&mut self) -> Option<StopReason> {
+        self.cpu.io.tick(self.cpu.cycles());
+        let vector = self.acknowledge_interrupt()?;
+        if self.cpu.interrupt(vector, InterruptKind::External).is_err()
+            || self.cpu.fault.get().is_some()
+        {
+            let fault = self.cpu.fault.take();
+            self.cpu
+                .raise_exception(fault.unwrap_or(CpuException::GeneralProtection));
+        }
+        self.unreported_exception()
+    }
+
+    /// Executes the instruction at EIP, returning why the machine must stop (if it must).
+    fn execute_instruction(&mut self) -> Option<StopReason> {
+        let eip = self.cpu.registers.get_eip();
+        if !self
+            .cpu
+            .memory
+            .permissions(self.cpu.instruction_address())
+            .execute
+        {
+            self.cpu.raise_exception(CpuException::GeneralProtection);
+            return self.unreported_exception();
+        }
+        if !self.in_bounds(self.cpu.instruction_address()) {
+            return Some(StopReason::OutOfBounds(eip));
+        }
+        self.cpu.step();
+        if let Some(stop_reason) = self.unreported_exception() {
+            return Some(stop_reason);
+        }
+        if !self.cpu.halted {
+            return None;
+        }
+
+        // A hardware interrupt wakes a halted processor, which then continues after the HLT once
+        // the interrupt has been serviced.
+        self.cpu.io.tick(self.cpu.cycles());
+        if self.interrupts_enabled() && self.pic.as_ref().is_some_and(Pic::pending) {
+            self.cpu.halted = false;
+            return None;
+        }
+        Some(StopReason::Halted)
+    }
+
+    fn interrupts_enabled(&self) -> bool {
+        self.cpu.registers.eflags.get_interrupt_enable_flag()
+    }
+
+    /// Acknowledges the hardware interrupt which is pending (if any, and if interrupts are
+    /// enabled), returning its vector.
+    fn acknowledge_interrupt(&mut self) -> Option<u8> {
+        let shadowed = core::mem::take(&mut self.cpu.interrupt_shadow);
+        if shadowed || !self.interrupts_enabled() {
+            return None;
+        }
+        self.pic.as_ref()?.acknowledge()
+    }
+}
+
+/// A machine is serialized as the state of its processor (see [`CpuState`]), as the program, the
+/// devices, and the host handlers and hooks cannot be. Hence, there is no `Deserialize` for it:
+/// the state is deserialized instead, and restored into a machine which runs the same program (see
+/// [`Cpu::restore_state`]).
+#[cfg(feature = "serde")]
+impl serde::Serialize for Machine {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.cpu.save_state().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        devices::tests::Latches,
+        instruction::{NasmStr, Size, Syntax},
+        memory::AccessKind,
+        program::Layout,
+        register::Register32,
+    };
+
+    /// A machine which runs the given NASM source, with a stack at 0x1000.
+    pub(crate) fn load(source: &str) -> Machine {
+        let program = Program::try_from(&NasmStr(source)).unwrap();
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 0x1000;
+        Machine::new(cpu, program).unwrap()
+    }
+
+    #[test]
+    fn run_until_out_of_bounds() {
+        let mut machine = load("mov ecx, [0x800]\nsub ecx, 1");
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x100c));
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x100c));
+    }
+
+    #[test]
+    fn run_until_halted() {
+        let mut machine = load("hlt\nsub ecx, 1\nhlt");
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1001);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_ffff);
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x1008));
+    }
+
+    #[test]
+    fn run_code_fetched_from_memory() {
+        // The code in an image is executed as that of the program is.
+        let mut machine = load("call 0x2000\nhlt");
+        // add eax, 5; ret
+        machine
+            .load_image(0x2000, &[0x05, 5, 0, 0, 0, 0xc3])
+            .unwrap();
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_eax(), 5);
+
+        // Instructions are fetched as they are executed, so a program may modify its own code.
+        let mut machine = load("add byte [patch + 1], 6\npatch: add eax, 1");
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x100c));
+        assert_eq!(machine.cpu().registers.get_eax(), 7);
+
+        let mut machine = load("jmp 0x2000");
+        machine.load_image(0x2000, &[0x0f, 0x04]).unwrap();
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::InvalidOpcode)
+        );
+        assert_eq!(machine.cpu().registers.get_eip(), 0x2000);
+    }
+
+    #[test]
+    fn run_until_breakpoint() {
+        let mut machine = load("l: sub ecx, 1\njmp l");
+        assert!(machine.add_breakpoint(0x1000));
+        assert!(!machine.add_breakpoint(0x1000));
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1000));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_ffff);
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1000));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffe);
+
+        assert!(machine.remove_breakpoint(0x1000));
+        assert!(!machine.remove_breakpoint(0x1000));
+        machine.set_instruction_limit(Some(5));
+        assert_eq!(machine.run().unwrap(), StopReason::InstructionLimit);
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffb);
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1006);
+    }
+
+    #[test]
+    fn run_until_label_or_mnemonic_breakpoint() {
+        let mut machine = load("l: sub ecx, 1\ncall f\njmp l\nf: ret");
+        assert!(matches!(
+            machine.set_breakpoint(&Breakpoint::Label("g".into())),
+            Err(Error::InvalidSymbol(_))
+        ));
+        assert!(machine
+            .set_breakpoint(&Breakpoint::Label("f".into()))
+            .unwrap());
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1010));
+        assert!(machine
+            .clear_breakpoint(&Breakpoint::Label("f".into()))
+            .unwrap());
+
+        // The `CALL` which the machine is stopped at is skipped, so the next one is reached.
+        let call = Breakpoint::mnemonic("call").unwrap();
+        assert!(machine.set_breakpoint(&call).unwrap());
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1006));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffe);
+        assert!(!machine.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn reverse_step_and_continue() {
+        let mut machine = load("l: sub ecx, 1\nmov [0x800], ecx\njmp l");
+        assert!(!machine.reverse_step());
+        machine.record_history(Some(4));
+        assert!(!machine.reverse_step());
+        machine.set_instruction_limit(Some(10));
+        assert_eq!(machine.run().unwrap(), StopReason::InstructionLimit);
+        assert_eq!(machine.history_position(), Some(10));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffc);
+
+        // Rewinding undoes the changes to both the registers and memory.
+        assert!(machine.reverse_step());
+        assert_eq!(machine.history_position(), Some(9));
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1000);
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffd);
+        assert_eq!(machine.cpu().memory.read32(0x800).unwrap(), 0xffff_fffd);
+
+        assert!(machine.add_breakpoint(0x1006));
+        assert_eq!(machine.reverse_continue(), Some(0x1006));
+        assert_eq!(machine.history_position(), Some(7));
+        assert_eq!(machine.cpu().memory.read32(0x800).unwrap(), 0xffff_fffe);
+        assert_eq!(machine.reverse_continue(), Some(0x1006));
+        assert_eq!(machine.history_position(), Some(4));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffe);
+        assert_eq!(machine.cpu().memory.read32(0x800).unwrap(), 0xffff_ffff);
+
+        // The machine can be run forwards again from where it was rewound to.
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1006));
+        assert_eq!(machine.history_position(), Some(7));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_fffd);
+
+        assert_eq!(machine.reverse_continue(), Some(0x1006));
+        assert_eq!(machine.reverse_continue(), Some(0x1006));
+        assert_eq!(machine.history_position(), Some(1));
+        assert_eq!(machine.reverse_continue(), None);
+        assert_eq!(machine.history_position(), Some(0));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0);
+        assert_eq!(machine.cpu().memory.read32(0x800).unwrap(), 0);
+    }
+
+    #[test]
+    fn run_until_hardware_breakpoint() {
+        let mut machine = load("mov ecx, [0x800]\nadd [0x820], ecx\nsub ecx, 1");
+        machine.set_hardware_breakpoint(0, 0x100c, BreakpointCondition::Execute, 1);
+        machine.set_hardware_breakpoint(1, 0x822, BreakpointCondition::Write, 2);
+
+        // A data breakpoint stops the machine after the instruction which hit it.
+        assert_eq!(machine.run().unwrap(), StopReason::HardwareBreakpoint(1));
+        assert_eq!(machine.cpu().registers.get_eip(), 0x100c);
+        assert_eq!(
+            machine.cpu().registers.debug_registers.get_dr6() & 0xf,
+            0b0010
+        );
+
+        // An instruction breakpoint stops the machine before the instruction, which is then
+        // executed when the machine is resumed.
+        assert_eq!(machine.run().unwrap(), StopReason::HardwareBreakpoint(0));
+        assert_eq!(machine.cpu().registers.get_eip(), 0x100c);
+        assert_eq!(machine.cpu().registers.get_ecx(), 0);
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x1012));
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_ffff);
+
+        machine.remove_hardware_breakpoint(0);
+        machine.remove_hardware_breakpoint(1);
+        machine.set_hardware_breakpoint(2, 0x800, BreakpointCondition::ReadWrite, 4);
+        machine.cpu_mut().registers.set_eip(0x1000);
+        assert_eq!(machine.run().unwrap(), StopReason::HardwareBreakpoint(2));
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1006);
+    }
+
+    #[test]
+    fn run_until_exception() {
+        let mut machine = load("int3");
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::Breakpoint)
+        );
+
+        fn skip(_: &mut Cpu) {}
+        let mut machine = load("int3\nhlt");
+        machine
+            .cpu_mut()
+            .register_interrupt_handler(CpuException::Breakpoint.vector(), skip);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+
+        // The entry for #BP in the interrupt vector table refers to the instruction at 0x1002.
+        let mut machine = load("int3\nhlt\nsub eax, 1\nhlt");
+        machine.cpu_mut().memory.write16(3 * 4, 0x1002).unwrap();
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_eax(), 0xffff_ffff);
+
+        let mut machine = load("mov eax, [0xfffffffe]");
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::GeneralProtection)
+        );
+
+        // A trap leaves EIP after the instruction which raised it, whereas a fault leaves it at
+        // the instruction, as it is pushed as the return address of the handler.
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1000);
+        let mut machine = load("int3\nbound eax, [0x800]");
+        machine.cpu_mut().memory.write32(0x800, 1).unwrap();
+        machine.cpu_mut().memory.write32(0x804, 2).unwrap();
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::Breakpoint)
+        );
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1001);
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::BoundRangeExceeded)
+        );
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1001);
+        let mut machine = load("rdmsr");
+        machine.cpu_mut().registers.set_ecx(0x1234);
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::GeneralProtection)
+        );
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1000);
+
+        // The entry for #BR in the interrupt vector table refers to the instruction at 0x1007.
+        let mut machine = load("bound eax, [0x800]\nhlt\nhlt");
+        machine.cpu_mut().memory.write32(0x800, 1).unwrap();
+        machine.cpu_mut().memory.write32(0x804, 2).unwrap();
+        machine.cpu_mut().memory.write16(5 * 4, 0x1007).unwrap();
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1008);
+        assert_eq!(machine.cpu().memory.read16(0x1000 - 6).unwrap(), 0x1000);
+    }
+
+    #[test]
+    fn memory_hooks() {
+        let mut machine = load("sub ecx, 1\nmov [0x800], ecx\nmov eax, [0x800]\nmov [0x802], cx");
+        let writes = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&writes);
+        let watch = machine.add_memory_hook(0x800..=0x803, HookTrigger::Write, move |access| {
+            assert_eq!(access.kind, AccessKind::Write);
+            counter.set(counter.get() + 1);
+        });
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x1018));
+        assert_eq!(writes.get(), 2);
+
+        assert!(machine.remove_memory_hook(watch).is_some());
+        machine.cpu_mut().registers.set_eip(0x1006);
+        machine.run().unwrap();
+        assert_eq!(writes.get(), 2);
+    }
+
+    #[test]
+    fn protect_sections() {
+        // The code writes to itself, and then jumps into its data.
+        let layout = Layout {
+            data: 0x200,
+            ..Layout::default()
+        };
+        let source = "sub ecx, 1\n\
+                      mov [0x201], ecx\n\
+                      mov [0x1001], ecx\n\
+                      jmp 0x200\n\
+                      section .data\n\
+                      dd 0, 0";
+        let program = Program::assemble_modules(&[source], layout, Syntax::Nasm).unwrap();
+        let mut cpu = Cpu::default();
+        cpu.registers.esp = 0x1000;
+        let mut machine = Machine::new(cpu, program).unwrap();
+        machine.protect_sections();
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::GeneralProtection)
+        );
+        // As with other faults, EIP is left at the instruction which wrote to the code, which is
+        // executed again (and faults again) if the machine is run again.
+        assert_eq!(machine.cpu().registers.get_eip(), 0x100c);
+        assert_eq!(machine.cpu().memory.read32(0x201).unwrap(), 0xffff_ffff);
+        assert_eq!(machine.cpu().memory.read8(0x1001).unwrap(), 0xe9);
+
+        machine.cpu_mut().registers.set_eip(0x1012);
+        assert_eq!(
+            machine.run().unwrap(),
+            StopReason::Exception(CpuException::GeneralProtection)
+        );
+        // Jumping into the data faults, rather than stopping as it is not an instruction.
+        assert_eq!(machine.cpu().registers.get_eip(), 0x200);
+    }
+
+    #[test]
+    fn interrupt_vectors() {
+        let mut machine = load("int 0x21\nint 0x22\nhlt\nisr: sub ecx, 1\niret");
+        assert_eq!(machine.interrupt_vector(0x21), InterruptVector::Unset);
+
+        let isr = machine.program().symbols().get("isr").unwrap();
+        machine.set_interrupt_vector(0x21, isr).unwrap();
+        assert_eq!(
+            machine.interrupt_vector(0x21),
+            InterruptVector::Guest {
+                segment: 0,
+                offset: 0x1005
+            }
+        );
+        assert!(machine.set_interrupt_vector(0x21, 0x10000).is_err());
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        assert!(machine
+            .set_interrupt_handler(0x22, move |cpu| {
+                counter.set(counter.get() + 1);
+                cpu.registers.set_eax(cpu.registers.get_ecx());
+            })
+            .is_none());
+        assert_eq!(machine.interrupt_vector(0x22), InterruptVector::Host);
+
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(machine.cpu().registers.get_eax(), 0xffff_ffff);
+        assert_eq!(machine.cpu().registers.esp, 0x1000);
+
+        // Pointing a vector at guest code removes its host handler.
+        machine.set_interrupt_vector(0x22, isr).unwrap();
+        assert!(matches!(
+            machine.interrupt_vector(0x22),
+            InterruptVector::Guest { .. }
+        ));
+    }
+
+    #[test]
+    fn attach_device() {
+        let mut machine = load("in al, 0x10\nout 0x11, al");
+        let latches = Latches {
+            base: 0x10,
+            values: vec![0x42, 0],
+        };
+        machine.attach_device(0x10..=0x11, latches).unwrap();
+        let conflict = Latches {
+            base: 0x11,
+            values: vec![0],
+        };
+        assert!(machine.attach_device(0x11..=0x11, conflict).is_err());
+
+        assert_eq!(machine.run().unwrap(), StopReason::OutOfBounds(0x1004));
+        assert_eq!(machine.cpu().registers.get_al(), 0x42);
+        assert_eq!(machine.cpu_mut().io.read16(0x10), 0x4242);
+    }
+
+    #[test]
+    fn hardware_interrupts() {
+        let mut machine = load(
+            "int 0x30\n\
+             sti\n\
+             hlt\n\
+             sub eax, 1\n\
+             hlt\n\
+             isr: sub ecx, 1\n\
+             out 0x20, al\n\
+             iret",
+        );
+        let pic = machine.attach_pic().unwrap();
+        assert!(machine.attach_pic().is_err());
+        let isr = machine.program().symbols().get("isr").unwrap();
+        machine.set_interrupt_vector(0x09, isr).unwrap();
+        let keyboard = pic.irq_line(1);
+        machine.set_interrupt_handler(0x30, move |_| keyboard.raise());
+        // A non-specific end of interrupt, which the ISR sends to the master.
+        machine.cpu_mut().registers.set_al(0x20);
+
+        // The IRQ is raised while interrupts are disabled, and is only delivered once the
+        // instruction after STI has halted the processor, which it then wakes.
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_ffff);
+        assert_eq!(machine.cpu().registers.get_eax(), 0x1f);
+        assert_eq!(machine.cpu().registers.get_eip(), 0x100a);
+        assert!(!pic.pending());
+
+        // An interrupt is not delivered while IF is clear.
+        pic.irq_line(1).lower();
+        pic.irq_line(1).raise();
+        machine
+            .cpu_mut()
+            .registers
+            .eflags
+            .set_interrupt_enable_flag(false);
+        machine.cpu_mut().registers.set_eip(0x1004);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.cpu().registers.get_ecx(), 0xffff_ffff);
+        assert!(pic.pending());
+    }
+
+    #[test]
+    fn step() {
+        let mut machine = load("mov [0x800], ecx\nmov eax, [0x800]\nsub eax, 0x1234\nhlt");
+        machine.cpu_mut().registers.set_ecx(0x1234);
+        // A breakpoint does not stop a single step.
+        machine.add_breakpoint(0x1006);
+
+        let report = machine.step();
+        assert_eq!(report.address, 0x1000);
+        assert_eq!(report.instruction.unwrap().to_string(), "mov [0x800], ecx");
+        assert_eq!(report.registers, []);
+        assert!(!report.eflags_changed);
+        assert_eq!(
+            report.memory_accesses,
+            [MemoryAccess {
+                kind: AccessKind::Write,
+                address: 0x800,
+                size: Size::Dword,
+                value: 0x1234,
+            }]
+        );
+        assert_eq!(report.stop_reason, None);
+
+        let report = machine.step();
+        assert_eq!(report.registers, [Register32::Eax.into()]);
+        assert_eq!(report.memory_accesses[0].kind, AccessKind::Read);
+
+        let report = machine.step();
+        assert_eq!(report.registers, [Register32::Eax.into()]);
+        assert!(report.eflags_changed);
+        assert!(report.memory_accesses.is_empty());
+        assert_eq!(machine.cpu().registers.get_eax(), 0);
+
+        assert_eq!(machine.step().stop_reason, Some(StopReason::Halted));
+        let report = machine.step();
+        assert!(report.instruction.is_none());
+        assert_eq!(report.stop_reason, Some(StopReason::OutOfBounds(0x1011)));
+    }
+
+    #[test]
+    fn run_until_timeout() {
+        let mut machine = load("l: sub ecx, 1\njmp l");
+        machine.set_timeout(Some(Duration::from_millis(10)));
+        assert!(matches!(machine.run(), Err(Error::Timeout(_))));
+
+        // The instruction limit is a stop reason rather than an error, even with a timeout.
+        machine.set_instruction_limit(Some(3));
+        assert_eq!(machine.run().unwrap(), StopReason::InstructionLimit);
+    }
+
+    #[test]
+    fn run_until_stack_overflow() {
+        let mut machine = load("f: push ax\ncall f");
+        machine.set_stack_limit(Some(0xff0));
+        assert!(matches!(machine.run(), Err(Error::StackOverflow(_))));
+        // Each iteration pushes 6 bytes, so the third call would have gone below the limit, and was
+        // not performed.
+        assert_eq!(machine.cpu().registers.esp, 0xff2);
+        assert_eq!(machine.cpu().registers.get_eip(), 0x1002);
+        assert!(matches!(machine.run(), Err(Error::StackOverflow(_))));
+
+        // Without a limit, wrapping ESP past zero is still an overflow.
+        machine.set_stack_limit(None);
+        machine.cpu_mut().registers.esp = 2;
+        assert!(matches!(machine.run(), Err(Error::StackOverflow(_))));
+        assert_eq!(machine.cpu().registers.esp, 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn machine_serde() {
+        let source = "sub ecx, 1\nmov [0x800], ecx\ncall f\nf: hlt";
+        let mut machine = load(source);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+
+        let value = serde_json::to_value(&machine).unwrap();
+        let registers = &value["registers"];
+        assert_eq!(registers["ecx"], 0xffff_ffff_u32);
+        assert_eq!(registers["esp"], 0xffc);
+        assert_eq!(registers["eip"], 0x1012);
+        assert_eq!(
+            registers["eflags"],
+            machine.cpu().registers.eflags.get_value()
+        );
+        assert_eq!(value["cycles"], machine.cpu().cycles());
+        assert_eq!(value["halted"], true);
+
+        // The state can be restored into another machine running the same program.
+        let state: CpuState = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(state.memory().read::<u32>(0x800).unwrap(), 0xffff_ffff);
+        let mut restored = load(source);
+        restored.cpu_mut().restore_state(&state);
+        assert_eq!(serde_json::to_value(&restored).unwrap(), value);
+        assert_eq!(restored.cpu().memory.read::<u32>(0xffc).unwrap(), 0x1011);
+    }
+}
@@ -1,121 +1,925 @@
-use crate::{error::Error, instruction::OperandType};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    fmt, iter,
+    ops::{Range, RangeInclusive},
+};
 
-// u32 rather than usize as we are emulating 32-bit x86. In other words, in the context of
-// operating within the emulator, u32 is usize.
-const MEMORY_SIZE_BYTES: u32 = 1024 * 1024;
+use crate::{
+    error::Error,
+    instruction::{Instruction, Size},
+};
 
-// Placed on the heap as the stack will otherwise overflow. Uses a `Box`ed array rather than a `Vec`
-// because it better encapsulates the idea that this is an exact, fixed amount of memory.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Memory(Box<[u8; MEMORY_SIZE_BYTES as usize]>);
+/// The size of each of the pages in which memory is allocated.
+const PAGE_SIZE: usize = 4096;
 
-impl Memory {
-    /// Reads a byte from memory at the provided index. If the index is out-of-bounds, then an
-    /// `Err` is returned.
-    pub fn read8(&self, index: u32) -> Result<u8, Error> {
-        let index = index as usize;
-        match self.0.get(index) {
-            Some(n) => Ok(*n),
-            None => Err(Error::InaccessibleAddress(format!("{index}"))),
+/// The number of entries in the page directory and in each page table, such that they cover the
+/// whole 32-bit address space.
+const TABLE_ENTRIES: usize = 1024;
+
+type Page = Box<[u8; PAGE_SIZE]>;
+
+type PageTable = Box<[Option<Page>]>;
+
+/// Plain old data, i.e. a type which can be read from memory as its little-endian bytes (see
+/// `Memory::read`).
+pub trait Pod: Sized {
+    const SIZE: usize;
+
+    /// Converts `Self::SIZE` little-endian bytes into a value.
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_pod {
+    ($($type:ty),*) => {
+        $(
+            impl Pod for $type {
+                const SIZE: usize = core::mem::size_of::<$type>();
+
+                fn from_le_bytes(bytes: &[u8]) -> Self {
+                    <$type>::from_le_bytes(bytes.try_into().expect("the bytes are the wrong size"))
+                }
+            }
+        )*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+/// The number of bytes on each line of a hexdump.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Whether an access read from or wrote to memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A single successful read from or write to memory, of a byte, word, dword, or qword.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub address: u32,
+    pub size: Size,
+    /// The value which was read or written.
+    pub value: u64,
+}
+
+/// The physical address of an access. The linear address of an access which spans two pages is
+/// translated into two frames, which need not be consecutive, so such an access is split between
+/// the end of the first frame and the start of the second (see `Cpu::physical_address`). Any
+/// other access is to consecutive addresses, as is one made from a `u32` address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhysicalAddress {
+    /// The address of the first byte.
+    pub start: u32,
+    /// If the access is split, the number of bytes before the split, and the address of the frame
+    /// in which the rest of the bytes are.
+    pub split: Option<(u32, u32)>,
+}
+
+impl PhysicalAddress {
+    /// The address of the byte which is `offset` bytes into the access.
+    pub fn byte(self, offset: u32) -> u32 {
+        match self.split {
+            Some((length, frame)) if offset >= length => frame.wrapping_add(offset - length),
+            _ => self.start.wrapping_add(offset),
         }
     }
 
-    /// Reads 2 bytes from memory starting from the provided index, in little-endian format. If an
-    /// out-of-bounds area of memory is being read, then an `Err` is returned.
-    pub fn read16(&self, index: u32) -> Result<u16, Error> {
-        let index = index as usize;
-        let mut result = 0;
+    /// The address of an access which starts `offset` bytes into this one (e.g. the upper half of
+    /// a bound, or of an XMM register).
+    pub fn offset(self, offset: u32) -> Self {
+        match self.split {
+            Some((length, frame)) if offset < length => Self {
+                start: self.start.wrapping_add(offset),
+                split: Some((length - offset, frame)),
+            },
+            _ => Self::from(self.byte(offset)),
+        }
+    }
+
+    /// The runs of consecutive addresses which the first `count` bytes of the access are in, as
+    /// the address of each run along with its length.
+    fn runs(self, count: u32) -> impl Iterator<Item = (u32, u32)> {
+        let (first, rest) = match self.split {
+            Some((length, frame)) if count > length => (length, Some((frame, count - length))),
+            _ => (count, None),
+        };
+        iter::once((self.start, first)).chain(rest)
+    }
+}
+
+impl From<u32> for PhysicalAddress {
+    fn from(start: u32) -> Self {
+        Self { start, split: None }
+    }
+}
+
+/// What may be done with a region of memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    /// The permissions of memory which has not been protected.
+    pub const ALL: Self = Self {
+        read: true,
+        write: true,
+        execute: true,
+    };
+    /// The permissions of memory which cannot be accessed at all.
+    pub const NONE: Self = Self {
+        read: false,
+        write: false,
+        execute: false,
+    };
+    pub const READ_ONLY: Self = Self {
+        read: true,
+        write: false,
+        execute: false,
+    };
+    pub const READ_WRITE: Self = Self {
+        read: true,
+        write: true,
+        execute: false,
+    };
+    pub const READ_EXECUTE: Self = Self {
+        read: true,
+        write: false,
+        execute: true,
+    };
+
+    fn permits(self, kind: AccessKind) -> bool {
+        match kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+        }
+    }
+}
+
+impl fmt::Display for Permissions {
+    /// Formats the permissions as in `ls -l` (e.g. `r-x`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |permitted, c| if permitted { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.execute, 'x')
+        )
+    }
+}
+
+/// Which accesses a memory hook is called for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookTrigger {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl HookTrigger {
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            Self::Read => kind == AccessKind::Read,
+            Self::Write => kind == AccessKind::Write,
+            Self::ReadWrite => true,
+        }
+    }
+}
+
+/// A callback which is given each access to a range of memory, once it has been made.
+pub type MemoryHook = Box<dyn FnMut(&MemoryAccess)>;
+
+/// Identifies a hook which has been added to memory, such that it can be removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+/// The hooks which have been added to memory, in the order in which they were added.
+#[derive(Default)]
+struct MemoryHooks {
+    hooks: Vec<(HookId, RangeInclusive<u32>, HookTrigger, MemoryHook)>,
+    next_id: u64,
+}
+
+impl fmt::Debug for MemoryHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hooks: Vec<_> = self
+            .hooks
+            .iter()
+            .map(|(_, range, trigger, _)| (range, trigger))
+            .collect();
+        f.debug_tuple("MemoryHooks").field(&hooks).finish()
+    }
+}
+
+impl Clone for MemoryHooks {
+    /// A hook observes a single memory, so a copy of the memory has none.
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryHooks {
+    /// Calls each hook whose range overlaps the bytes which were accessed, and whose trigger
+    /// matches the kind of access.
+    fn call(&mut self, access: &MemoryAccess) {
+        let last = access.address.saturating_add(access.size as u32 / 8 - 1);
+        for (_, range, trigger, hook) in &mut self.hooks {
+            if trigger.matches(access.kind)
+                && access.address <= *range.end()
+                && last >= *range.start()
+            {
+                hook(access);
+            }
+        }
+    }
+}
+
+/// The whole 32-bit address space, which is allocated sparsely. As with x86 paging, an address is
+/// split into an index into the page directory, an index into a page table, and an offset into a
+/// 4 KiB page. Page tables and pages are only allocated once they are written to, and the memory
+/// which has not been reads as zero, such that a program can use addresses anywhere (e.g. a stack
+/// near 0xc0000000) without 4 GiB being allocated.
+#[derive(Clone, Debug)]
+pub struct Memory {
+    directory: Box<[Option<PageTable>]>,
+    /// The accesses which have been made since recording was started, if it has been. Reads only
+    /// borrow the memory immutably, so they are recorded through a `RefCell`.
+    accesses: RefCell<Option<Vec<MemoryAccess>>>,
+    /// The hooks which are called on accesses, through a `RefCell` for the same reason.
+    hooks: RefCell<MemoryHooks>,
+    /// The regions whose permissions have been restricted, keyed by their first address, along
+    /// with their last address. The regions do not overlap.
+    regions: BTreeMap<u32, (u32, Permissions)>,
+}
+
+impl PartialEq for Memory {
+    /// Compares the contents of the memory, regardless of the accesses which have been recorded,
+    /// the hooks which have been added, the permissions of its regions, or of which pages have been
+    /// allocated.
+    fn eq(&self, other: &Self) -> bool {
+        let zero = [0; PAGE_SIZE];
+        self.page_numbers()
+            .chain(other.page_numbers())
+            .all(|number| self.page(number).unwrap_or(&zero) == other.page(number).unwrap_or(&zero))
+    }
+}
+
+impl Eq for Memory {}
+
+impl Memory {
+    /// Reads a byte from memory at the provided index.
+    pub fn read8(&self, index: impl Into<PhysicalAddress>) -> Result<u8, Error> {
+        let index = index.into();
+        self.check_access(index, 1, AccessKind::Read)?;
+        let n = self.get(index.start);
+        self.record(AccessKind::Read, index.start, Size::Byte, n as u64);
+        Ok(n)
+    }
 
+    /// Reads 2 bytes from memory starting from the provided index, in little-endian format. If the
+    /// read would go past the end of the address space, then an `Err` is returned.
+    pub fn read16(&self, index: impl Into<PhysicalAddress>) -> Result<u16, Error> {
+        let index = index.into();
+        self.check_access(index, 2, AccessKind::Read)?;
+        let mut result = 0;
         for i in 0..2 {
-            let Some(n) = self.0.get(index + i) else {
-                return Err(Error::InaccessibleAddress(format!("reading 4 bytes went out-of-bounds at {}", index + i)));
-            };
-            result |= (*n as u16) << 8 * i;
+            result |= (self.get(index.byte(i)) as u16) << (8 * i);
         }
 
+        self.record(AccessKind::Read, index.start, Size::Word, result as u64);
         Ok(result)
     }
 
-    /// Reads 4 bytes from memory starting from the provided index, in little-endian format. If an
-    /// out-of-bounds area of memory is being read, an error is returned.
-    pub fn read32(&self, index: u32) -> Result<u32, Error> {
-        let index = index as usize;
+    /// Reads 4 bytes from memory starting from the provided index, in little-endian format. If the
+    /// read would go past the end of the address space, an error is returned.
+    pub fn read32(&self, index: impl Into<PhysicalAddress>) -> Result<u32, Error> {
+        let index = index.into();
+        self.check_access(index, 4, AccessKind::Read)?;
         let mut result = 0;
-
         for i in 0..4 {
-            let Some(n) = self.0.get(index + i) else {
-                return Err(Error::InaccessibleAddress(format!("reading 4 bytes went out-of-bounds at {}", index + i)));
-            };
-            result |= (*n as u32) << 8 * i;
+            result |= (self.get(index.byte(i)) as u32) << (8 * i);
         }
 
+        self.record(AccessKind::Read, index.start, Size::Dword, result as u64);
         Ok(result)
     }
 
-    /// Writes a byte into memory at the provided index. If the index is out-of-bounds, then an
-    /// `Err` is returned.
-    pub fn write8(&mut self, index: u32, value: u8) -> Result<(), Error> {
-        if index >= MEMORY_SIZE_BYTES {
-            return Err(Error::InaccessibleAddress(format!(
-                "{index} is out-of-bounds"
-            )));
+    /// Reads 8 bytes from memory starting from the provided index, in little-endian format. If the
+    /// read would go past the end of the address space, an error is returned.
+    pub fn read64(&self, index: impl Into<PhysicalAddress>) -> Result<u64, Error> {
+        let index = index.into();
+        self.check_access(index, 8, AccessKind::Read)?;
+        let mut result = 0;
+        for i in 0..8 {
+            result |= (self.get(index.byte(i)) as u64) << (8 * i);
         }
 
-        let index = index as usize;
-        self.0[index] = value;
+        self.record(AccessKind::Read, index.start, Size::Qword, result);
+        Ok(result)
+    }
+
+    /// Writes a byte into memory at the provided index.
+    pub fn write8(&mut self, index: impl Into<PhysicalAddress>, value: u8) -> Result<(), Error> {
+        let index = index.into();
+        self.check_access(index, 1, AccessKind::Write)?;
+        self.record(AccessKind::Write, index.start, Size::Byte, value as u64);
+        self.set(index.start, value);
 
         Ok(())
     }
 
-    /// Writes 2 bytes into memory starting at the provided index, in little-endian format. If an
-    /// out-of-bounds area of memory is accessed, then an `Err` is returned.
-    pub fn write16(&mut self, index: u32, value: u16) -> Result<(), Error> {
-        if index + 1 >= MEMORY_SIZE_BYTES {
-            return Err(Error::InaccessibleAddress(format!(
-                "writing 2 bytes starting at {index} would go out-of-bounds"
-            )));
-        }
+    /// Writes 2 bytes into memory starting at the provided index, in little-endian format. If the
+    /// write would go past the end of the address space, then an `Err` is returned.
+    pub fn write16(&mut self, index: impl Into<PhysicalAddress>, value: u16) -> Result<(), Error> {
+        let index = index.into();
+        self.check_access(index, 2, AccessKind::Write)?;
 
-        let index = index as usize;
+        self.record(AccessKind::Write, index.start, Size::Word, value as u64);
         for i in 0..2 {
-            self.0[index + i] = (value >> 8 * i) as u8;
+            self.set(index.byte(i), (value >> (8 * i)) as u8);
         }
 
         Ok(())
     }
 
-    /// Writes 4 bytes into memory starting at the provided index, in little-endian format. If an
-    /// out-of-bounds area of memory is accessed, then an `Err` is returned.
-    pub fn write32(&mut self, index: u32, value: u32) -> Result<(), Error> {
-        if index + 3 >= MEMORY_SIZE_BYTES {
-            return Err(Error::InaccessibleAddress(format!(
-                "writing 4 bytes starting at {index} would go out-of-bounds"
-            )));
-        }
+    /// Writes 4 bytes into memory starting at the provided index, in little-endian format. If the
+    /// write would go past the end of the address space, then an `Err` is returned.
+    pub fn write32(&mut self, index: impl Into<PhysicalAddress>, value: u32) -> Result<(), Error> {
+        let index = index.into();
+        self.check_access(index, 4, AccessKind::Write)?;
 
-        let index = index as usize;
+        self.record(AccessKind::Write, index.start, Size::Dword, value as u64);
         for i in 0..4 {
-            self.0[index + i] = (value >> 8 * i) as u8;
+            self.set(index.byte(i), (value >> (8 * i)) as u8);
+        }
+
+        Ok(())
+    }
+
+    /// Writes 8 bytes into memory starting at the provided index, in little-endian format. If the
+    /// write would go past the end of the address space, then an `Err` is returned.
+    pub fn write64(&mut self, index: impl Into<PhysicalAddress>, value: u64) -> Result<(), Error> {
+        let index = index.into();
+        self.check_access(index, 8, AccessKind::Write)?;
+
+        self.record(AccessKind::Write, index.start, Size::Qword, value);
+        for i in 0..8 {
+            self.set(index.byte(i), (value >> (8 * i)) as u8);
+        }
+
+        Ok(())
+    }
+
+    /// Reads `count` bytes from memory starting from the provided index. If the read would go past
+    /// the end of the address space, then an `Err` is returned. Each byte is recorded as a separate
+    /// access.
+    pub fn read_bytes(
+        &self,
+        index: impl Into<PhysicalAddress>,
+        count: u32,
+    ) -> Result<Vec<u8>, Error> {
+        let index = index.into();
+        self.check_access(index, count, AccessKind::Read)?;
+        let bytes: Vec<u8> = index
+            .runs(count)
+            .flat_map(|(address, length)| self.copy_to_vec(address, length))
+            .collect();
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.record(
+                AccessKind::Read,
+                index.byte(i as u32),
+                Size::Byte,
+                *byte as u64,
+            );
+        }
+        Ok(bytes)
+    }
+
+    /// Writes the bytes into memory starting at the provided index. If the write would go past the
+    /// end of the address space, then an `Err` is returned, and nothing is written. Each byte is
+    /// recorded as a separate access.
+    pub fn write_bytes(
+        &mut self,
+        index: impl Into<PhysicalAddress>,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        let index = index.into();
+        let count = u32::try_from(bytes.len()).map_err(|_| {
+            Error::InaccessibleAddress(format!("cannot write {} bytes", bytes.len()))
+        })?;
+        self.check_access(index, count, AccessKind::Write)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.record(
+                AccessKind::Write,
+                index.byte(i as u32),
+                Size::Byte,
+                *byte as u64,
+            );
+        }
+        let mut bytes = bytes;
+        for (address, length) in index.runs(count) {
+            let (run, rest) = bytes.split_at(length as usize);
+            self.copy_from(address, run);
+            bytes = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a value of any plain old data type (e.g. `u16` or `f64`) from memory starting at the
+    /// provided address, in little-endian format. This is for inspecting memory, so unlike
+    /// `read16` and the like, it is not an access made by the program: it is neither recorded nor
+    /// restricted by the permissions of the memory. If the read would go past the end of the
+    /// address space, then an `Err` is returned.
+    pub fn read<T: Pod>(&self, address: u32) -> Result<T, Error> {
+        check_bounds(address, T::SIZE as u32, "reading")?;
+        Ok(T::from_le_bytes(&self.copy_to_vec(address, T::SIZE as u32)))
+    }
+
+    /// Copies the `count` bytes of an instruction which is being fetched. The fetch is not a data
+    /// access, so it is neither recorded nor restricted by the permissions of the memory (whether
+    /// the instruction may be executed is checked by the machine). The bytes must not go past the
+    /// end of the address space.
+    pub(crate) fn fetch(&self, index: PhysicalAddress, count: u32) -> Vec<u8> {
+        index
+            .runs(count)
+            .flat_map(|(address, length)| self.copy_to_vec(address, length))
+            .collect()
+    }
+
+    /// Formats the given range of memory as a canonical hexdump, with the address, the bytes in
+    /// hexadecimal, and the bytes as ASCII (or `.` where they are not printable) on each line of 16
+    /// bytes. As with `read`, this is not an access made by the program.
+    pub fn hexdump(&self, range: Range<u32>) -> String {
+        let bytes = self.copy_to_vec(range.start, range.len() as u32);
+        let mut hexdump = String::new();
+        for (i, line) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+            let address = range.start as usize + i * HEXDUMP_WIDTH;
+            let mut hex = String::new();
+            for (j, byte) in line.iter().enumerate() {
+                if j == HEXDUMP_WIDTH / 2 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{byte:02x} "));
+            }
+            let ascii: String = line
+                .iter()
+                .map(|&byte| match byte {
+                    b' '..=b'~' => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            hexdump.push_str(&format!("{address:08x}  {hex:<49} |{ascii}|\n"));
+        }
+        hexdump
+    }
+
+    /// Takes a snapshot of the contents of memory, to be compared with later (see `Memory::diff`).
+    /// Only the contents are copied, so the snapshot has no recorded accesses, hooks, or
+    /// protected regions.
+    pub fn snapshot(&self) -> Memory {
+        Memory {
+            directory: self.directory.clone(),
+            ..Memory::default()
+        }
+    }
+
+    /// Replaces the contents of memory with those of a snapshot (see `Memory::snapshot`), keeping
+    /// its hooks and protected regions. This is not an access made by the program, so it is
+    /// neither recorded nor hooked.
+    pub fn restore(&mut self, snapshot: &Memory) {
+        self.directory = snapshot.directory.clone();
+    }
+
+    /// The ranges of addresses whose contents differ between this memory and `other` (e.g. a
+    /// snapshot taken before an instruction was executed), in ascending order, with adjacent
+    /// differing bytes merged into a single range. As with equality, memory which has not been
+    /// allocated is compared as zero.
+    pub fn diff(&self, other: &Memory) -> Vec<RangeInclusive<u32>> {
+        let zero = [0; PAGE_SIZE];
+        let numbers: BTreeSet<_> = self.page_numbers().chain(other.page_numbers()).collect();
+        let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+        for number in numbers {
+            let page = self.page(number).unwrap_or(&zero);
+            let other_page = other.page(number).unwrap_or(&zero);
+            if page == other_page {
+                continue;
+            }
+            let differences = page
+                .iter()
+                .zip(other_page.iter())
+                .enumerate()
+                .filter(|(_, (byte, other_byte))| byte != other_byte);
+            for (offset, _) in differences {
+                let address = (number * PAGE_SIZE + offset) as u32;
+                match ranges.last_mut() {
+                    Some(range) if range.end().checked_add(1) == Some(address) => {
+                        *range = *range.start()..=address;
+                    }
+                    _ => ranges.push(address..=address),
+                }
+            }
+        }
+        ranges
+    }
+
+    /// Places an image (e.g. a ROM, a boot sector, or a program's section) into memory starting at
+    /// the provided address. Unlike `write_bytes`, this is not an access made by the program, so
+    /// it is not recorded. If the image would go past the end of the address space, then an `Err`
+    /// is returned, and nothing is loaded.
+    pub fn load(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        let count = u32::try_from(bytes.len()).map_err(|_| {
+            Error::InaccessibleAddress(format!("cannot load {} bytes", bytes.len()))
+        })?;
+        check_bounds(address, count, "loading")?;
+        self.copy_from(address, bytes);
+
+        Ok(())
+    }
+
+    /// Zeroes `count` bytes of memory starting at the provided address (e.g. a program's `.bss`),
+    /// as `load` would with an image of zeros. Pages which have not been allocated already read as
+    /// zero, so they are left unallocated until they are written to. Like `load`, this is not
+    /// recorded, and nothing is zeroed if the range would go past the end of the address space.
+    pub fn zero(&mut self, address: u32, count: u32) -> Result<(), Error> {
+        check_bounds(address, count, "zeroing")?;
+        let mut address = address as usize;
+        let end = address + count as usize;
+        while address < end {
+            let offset = address % PAGE_SIZE;
+            let length = (PAGE_SIZE - offset).min(end - address);
+            let table = self.directory[address / PAGE_SIZE / TABLE_ENTRIES].as_mut();
+            if let Some(page) =
+                table.and_then(|table| table[address / PAGE_SIZE % TABLE_ENTRIES].as_mut())
+            {
+                page[offset..offset + length].fill(0);
+            }
+            address += length;
+        }
+        Ok(())
+    }
+}
+
+/// Returns an `Err` if accessing `count` bytes starting at `index` would go past the end of the
+/// address space.
+fn check_bounds(index: u32, count: u32, verb: &str) -> Result<(), Error> {
+    match index.checked_add(count.saturating_sub(1)) {
+        Some(_) => Ok(()),
+        None => Err(Error::InaccessibleAddress(format!(
+            "{verb} {count} bytes starting at {index:#x} would go past the end of the address space"
+        ))),
+    }
+}
+
+impl Memory {
+    /// The page which contains the given page number (i.e. address divided by the page size), if
+    /// it has been allocated.
+    fn page(&self, number: usize) -> Option<&[u8; PAGE_SIZE]> {
+        let table = self.directory[number / TABLE_ENTRIES].as_ref()?;
+        table[number % TABLE_ENTRIES].as_deref()
+    }
+
+    /// The page with the given number, which is allocated (along with its page table) if it has
+    /// not been already.
+    fn page_mut(&mut self, number: usize) -> &mut [u8; PAGE_SIZE] {
+        let table = self.directory[number / TABLE_ENTRIES]
+            .get_or_insert_with(|| vec![None; TABLE_ENTRIES].into_boxed_slice());
+        table[number % TABLE_ENTRIES].get_or_insert_with(|| Box::new([0; PAGE_SIZE]))
+    }
+
+    /// The numbers of the pages which have been allocated, in ascending order.
+    fn page_numbers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.directory
+            .iter()
+            .enumerate()
+            .filter_map(|(i, table)| Some((i, table.as_ref()?)))
+            .flat_map(|(i, table)| {
+                table
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, page)| page.is_some())
+                    .map(move |(j, _)| i * TABLE_ENTRIES + j)
+            })
+    }
+
+    /// Copies `count` bytes out of memory starting at the provided index, reading pages which have
+    /// not been allocated as zero. The bytes must not go past the end of the address space.
+    fn copy_to_vec(&self, index: u32, count: u32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(count as usize);
+        let mut address = index as usize;
+        let end = address + count as usize;
+        while address < end {
+            let offset = address % PAGE_SIZE;
+            let length = (PAGE_SIZE - offset).min(end - address);
+            match self.page(address / PAGE_SIZE) {
+                Some(page) => bytes.extend_from_slice(&page[offset..offset + length]),
+                None => bytes.resize(bytes.len() + length, 0),
+            }
+            address += length;
+        }
+        bytes
+    }
+
+    /// Copies the bytes into memory starting at the provided index, allocating pages as needed.
+    /// The bytes must not go past the end of the address space.
+    fn copy_from(&mut self, index: u32, bytes: &[u8]) {
+        let mut address = index as usize;
+        for chunk in bytes.chunks(PAGE_SIZE) {
+            // A chunk may still straddle two pages, unless the index is aligned to a page.
+            let offset = address % PAGE_SIZE;
+            let (first, second) = chunk.split_at((PAGE_SIZE - offset).min(chunk.len()));
+            let page = self.page_mut(address / PAGE_SIZE);
+            page[offset..offset + first.len()].copy_from_slice(first);
+            if !second.is_empty() {
+                let page = self.page_mut(address / PAGE_SIZE + 1);
+                page[..second.len()].copy_from_slice(second);
+            }
+            address += chunk.len();
         }
+    }
 
+    fn get(&self, address: u32) -> u8 {
+        let address = address as usize;
+        self.page(address / PAGE_SIZE)
+            .map_or(0, |page| page[address % PAGE_SIZE])
+    }
+
+    fn set(&mut self, address: u32, value: u8) {
+        let address = address as usize;
+        self.page_mut(address / PAGE_SIZE)[address % PAGE_SIZE] = value;
+    }
+}
+
+impl Memory {
+    /// Starts recording the accesses made to memory, discarding any which were recorded before.
+    pub(crate) fn start_recording(&self) {
+        *self.accesses.borrow_mut() = Some(Vec::new());
+    }
+
+    /// Stops recording the accesses made to memory, returning those which were recorded, in the
+    /// order in which they were made.
+    pub(crate) fn stop_recording(&self) -> Vec<MemoryAccess> {
+        self.accesses.borrow_mut().take().unwrap_or_default()
+    }
+
+    /// Restricts what may be done with the given range of memory, replacing the permissions of any
+    /// region which it overlaps. Reads and writes which are not permitted fail, as do instructions
+    /// executed from memory which is not executable (see `Machine::run`). Memory which has not
+    /// been protected permits everything, and `Permissions::ALL` lifts the restrictions again.
+    pub fn protect(&mut self, range: RangeInclusive<u32>, permissions: Permissions) {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end {
+            return;
+        }
+
+        let overlapping: Vec<_> = self
+            .regions
+            .range(..=end)
+            .rev()
+            .take_while(|(_, (last, _))| *last >= start)
+            .map(|(first, region)| (*first, *region))
+            .collect();
+        for (first, (last, region_permissions)) in overlapping {
+            self.regions.remove(&first);
+            if first < start {
+                self.regions.insert(first, (start - 1, region_permissions));
+            }
+            if last > end {
+                self.regions.insert(end + 1, (last, region_permissions));
+            }
+        }
+        if permissions != Permissions::ALL {
+            self.regions.insert(start, (end, permissions));
+        }
+    }
+
+    /// The permissions of the byte at the given address.
+    pub fn permissions(&self, address: u32) -> Permissions {
+        match self.regions.range(..=address).next_back() {
+            Some((_, (last, permissions))) if *last >= address => *permissions,
+            _ => Permissions::ALL,
+        }
+    }
+
+    /// Returns an `Err` if any of the `count` bytes starting at `index` do not permit the kind of
+    /// access.
+    fn check_permissions(&self, index: u32, count: u32, kind: AccessKind) -> Result<(), Error> {
+        let last = index.saturating_add(count.saturating_sub(1));
+        let denied = self
+            .regions
+            .range(..=last)
+            .rev()
+            .take_while(|(_, (region_last, _))| *region_last >= index)
+            .find(|(_, (_, permissions))| !permissions.permits(kind));
+        match denied {
+            Some((first, (region_last, permissions))) if count > 0 => {
+                Err(Error::ProtectionViolation(format!(
+                    "{} {count} bytes starting at {index:#x} is not permitted by the region \
+                     {first:#x}..={region_last:#x} ({permissions})",
+                    match kind {
+                        AccessKind::Read => "reading",
+                        AccessKind::Write => "writing",
+                    }
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an `Err` if any of the runs of the `count` bytes of the access would go past the
+    /// end of the address space, or do not permit the kind of access.
+    fn check_access(
+        &self,
+        index: PhysicalAddress,
+        count: u32,
+        kind: AccessKind,
+    ) -> Result<(), Error> {
+        let verb = match kind {
+            AccessKind::Read => "reading",
+            AccessKind::Write => "writing",
+        };
+        for (address, length) in index.runs(count) {
+            check_bounds(address, length, verb)?;
+            self.check_permissions(address, length, kind)?;
+        }
         Ok(())
     }
+
+    /// Adds a hook which is called with each access made to the given range of addresses, of the
+    /// kind given by the trigger (e.g. as a watchpoint, or to instrument a program). An access is
+    /// to the range if any of its bytes are. The hook is called once the access has been made,
+    /// and is not called for accesses which fail, nor for images which are loaded.
+    pub fn add_hook(
+        &mut self,
+        range: RangeInclusive<u32>,
+        trigger: HookTrigger,
+        hook: impl FnMut(&MemoryAccess) + 'static,
+    ) -> HookId {
+        let hooks = self.hooks.get_mut();
+        let id = HookId(hooks.next_id);
+        hooks.next_id += 1;
+        hooks.hooks.push((id, range, trigger, Box::new(hook)));
+        id
+    }
+
+    /// Removes a hook, returning it, or `None` if it had already been removed.
+    pub fn remove_hook(&mut self, id: HookId) -> Option<MemoryHook> {
+        let hooks = &mut self.hooks.get_mut().hooks;
+        let index = hooks.iter().position(|(hook_id, ..)| *hook_id == id)?;
+        Some(hooks.remove(index).3)
+    }
+
+    /// Records an access which has been made (if recording), and calls the hooks for it.
+    fn record(&self, kind: AccessKind, address: u32, size: Size, value: u64) {
+        let access = MemoryAccess {
+            kind,
+            address,
+            size,
+            value,
+        };
+        if let Some(accesses) = self.accesses.borrow_mut().as_mut() {
+            accesses.push(access);
+        }
+        self.hooks.borrow_mut().call(&access);
+    }
+
+    /// Decodes the instructions within the given range of addresses, along with the address of each
+    /// of them. The instructions must fill the range exactly, otherwise an `Err` is returned. Like
+    /// `hexdump`, this does not count as an access, so it is not recorded or checked.
+    pub fn disassemble(&self, range: Range<u32>) -> Result<Vec<(u32, Instruction)>, Error> {
+        check_bounds(range.start, range.len() as u32, "disassembling")?;
+        let bytes = self.copy_to_vec(range.start, range.len() as u32);
+
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let address = range.start + offset as u32;
+            let (instruction, length) = Instruction::decode(&bytes[offset..], address)?;
+            instructions.push((address, instruction));
+            offset += length;
+        }
+        Ok(instructions)
+    }
 }
 
 impl Default for Memory {
     fn default() -> Self {
-        Self(Box::new([0; MEMORY_SIZE_BYTES as usize]))
+        Self {
+            directory: vec![None; TABLE_ENTRIES].into_boxed_slice(),
+            accesses: RefCell::new(None),
+            hooks: RefCell::default(),
+            regions: BTreeMap::new(),
+        }
+    }
+}
+
+/// The form in which memory is serialized. Only the pages which have been allocated and hold
+/// something other than zeros are included, along with the regions whose permissions have been
+/// restricted, such that the state of a program which uses little of the address space stays
+/// small. The recorded accesses and the hooks are not serialized.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedMemory {
+    pages: Vec<SerializedPage>,
+    regions: Vec<SerializedRegion>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedPage {
+    address: u32,
+    #[serde(with = "crate::serialize::bytes")]
+    bytes: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedRegion {
+    start: u32,
+    end: u32,
+    permissions: Permissions,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Memory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pages = self
+            .page_numbers()
+            .filter_map(|number| {
+                let page = self.page(number)?;
+                page.iter().any(|byte| *byte != 0).then(|| SerializedPage {
+                    address: (number * PAGE_SIZE) as u32,
+                    bytes: page.to_vec(),
+                })
+            })
+            .collect();
+        let regions = self
+            .regions
+            .iter()
+            .map(|(start, (end, permissions))| SerializedRegion {
+                start: *start,
+                end: *end,
+                permissions: *permissions,
+            })
+            .collect();
+        SerializedMemory { pages, regions }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Memory {
+    /// Pages may be of any size and at any address, as long as they fit within the address space.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let serialized = SerializedMemory::deserialize(deserializer)?;
+        let mut memory = Memory::default();
+        for page in serialized.pages {
+            memory
+                .load(page.address, &page.bytes)
+                .map_err(D::Error::custom)?;
+        }
+        for region in serialized.regions {
+            if region.start > region.end {
+                return Err(D::Error::custom(format!(
+                    "the region from {:#x} to {:#x} is empty",
+                    region.start, region.end
+                )));
+            }
+            memory.protect(region.start..=region.end, region.permissions);
+        }
+        Ok(memory)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::rc::Rc;
+
     use super::*;
 
     fn set_up_memory() -> Memory {
         let mut memory = Memory::default();
         for i in 0..10 {
-            memory.0[i] = i as u8;
+            memory.write8(i, i as u8).unwrap();
         }
         memory
     }
@@ -126,7 +930,7 @@ mod tests {
         assert_eq!(memory.read8(0).unwrap(), 0);
         assert_eq!(memory.read8(1).unwrap(), 1);
         assert_eq!(memory.read8(11).unwrap(), 0);
-        assert!(memory.read8(MEMORY_SIZE_BYTES + 1).is_err());
+        assert_eq!(memory.read8(u32::MAX).unwrap(), 0);
     }
 
     #[test]
@@ -135,8 +939,8 @@ mod tests {
         assert_eq!(memory.read16(0).unwrap(), 0x100);
         assert_eq!(memory.read16(1).unwrap(), 0x201);
         assert_eq!(memory.read16(11).unwrap(), 0);
-        assert!(memory.read16(MEMORY_SIZE_BYTES).is_err());
-        assert!(memory.read16(MEMORY_SIZE_BYTES + 1).is_err());
+        assert_eq!(memory.read16(u32::MAX - 1).unwrap(), 0);
+        assert!(memory.read16(u32::MAX).is_err());
     }
 
     #[test]
@@ -145,45 +949,441 @@ mod tests {
         assert_eq!(memory.read32(0).unwrap(), 0x3020100);
         assert_eq!(memory.read32(1).unwrap(), 0x4030201);
         assert_eq!(memory.read32(11).unwrap(), 0);
-        assert!(memory.read32(MEMORY_SIZE_BYTES - 1).is_err());
-        assert!(memory.read32(MEMORY_SIZE_BYTES).is_err());
-        assert!(memory.read32(MEMORY_SIZE_BYTES + 1).is_err());
+        assert_eq!(memory.read32(u32::MAX - 3).unwrap(), 0);
+        assert!(memory.read32(u32::MAX - 2).is_err());
+        assert!(memory.read32(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn read64() {
+        let memory = set_up_memory();
+        assert_eq!(memory.read64(0).unwrap(), 0x0706_0504_0302_0100);
+        assert_eq!(memory.read64(3).unwrap(), 0x0009_0807_0605_0403);
+        assert_eq!(memory.read64(u32::MAX - 7).unwrap(), 0);
+        assert!(memory.read64(u32::MAX - 6).is_err());
+    }
+
+    #[test]
+    fn split_access() {
+        let mut memory = set_up_memory();
+        // The first 2 bytes are at 8 and 9, and the rest are at 0.
+        let address = PhysicalAddress {
+            start: 8,
+            split: Some((2, 0)),
+        };
+        assert_eq!(memory.read32(address).unwrap(), 0x0100_0908);
+        assert_eq!(memory.read16(address.offset(1)).unwrap(), 0x0009);
+        assert_eq!(memory.read8(address.offset(3)).unwrap(), 1);
+        assert_eq!(memory.read_bytes(address, 3).unwrap(), [8, 9, 0]);
+
+        memory.write32(address, 0xaabb_ccdd).unwrap();
+        assert_eq!(memory.read16(8).unwrap(), 0xccdd);
+        assert_eq!(memory.read16(0).unwrap(), 0xaabb);
+
+        memory.protect(0..=0, Permissions::READ_ONLY);
+        assert!(memory.write_bytes(address, &[1, 2, 3]).is_err());
+        assert_eq!(memory.read8(9).unwrap(), 0xcc);
+    }
+
+    #[test]
+    fn disassemble() {
+        let source = [
+            "lock add dword [eax], byte -1",
+            "add [ebx+ecx*4-8], eax",
+            "mov al, [0x1234]",
+            "sub ecx, byte 4",
+            "add dword [0x100], 0x12345678",
+            "rep movsb",
+            "movsw",
+            "int 0x80",
+            "mov cr0, eax",
+            "movdqu xmm0, [esp+0x20]",
+            "je 0x100",
+            "call 0x200",
+            "ret",
+        ];
+        let mut memory = Memory::default();
+        let mut address = 0x100;
+        for line in source {
+            let instruction = Instruction::try_from(&crate::instruction::NasmStr(line)).unwrap();
+            for byte in instruction.encode(address).unwrap().to_bytes() {
+                memory.write8(address, byte).unwrap();
+                address += 1;
+            }
+        }
+
+        memory.start_recording();
+        memory.protect(0x100..=address - 1, Permissions::NONE);
+        let disassembly: Vec<_> = memory
+            .disassemble(0x100..address)
+            .unwrap()
+            .into_iter()
+            .map(|(_, instruction)| instruction.to_string())
+            .collect();
+        assert_eq!(disassembly, source);
+        assert!(memory.stop_recording().is_empty());
+        assert!(memory.disassemble(0x100..address - 2).is_err());
+        assert!(memory.disassemble(u32::MAX - 1..u32::MAX).is_err());
     }
 
     #[test]
     fn write8() {
         let mut memory = Memory::default();
         assert!(memory.write8(1, 1).is_ok());
-        assert_eq!(memory.0[0], 0);
-        assert_eq!(memory.0[1], 1);
-        assert_eq!(memory.0[2], 0);
-        assert!(memory.write8(MEMORY_SIZE_BYTES, 0).is_err());
+        assert_eq!(memory.get(0), 0);
+        assert_eq!(memory.get(1), 1);
+        assert_eq!(memory.get(2), 0);
+        assert!(memory.write8(u32::MAX, 0xff).is_ok());
+        assert_eq!(memory.get(u32::MAX), 0xff);
     }
 
     #[test]
     fn write16() {
         let mut memory = Memory::default();
         assert!(memory.write16(1, 0x201).is_ok());
-        assert_eq!(memory.0[0], 0);
-        assert_eq!(memory.0[1], 1);
-        assert_eq!(memory.0[2], 2);
-        assert_eq!(memory.0[3], 0);
-        assert!(memory.write16(MEMORY_SIZE_BYTES - 1, 0).is_err());
-        assert!(memory.write16(MEMORY_SIZE_BYTES, 0).is_err());
+        assert_eq!(memory.get(0), 0);
+        assert_eq!(memory.get(1), 1);
+        assert_eq!(memory.get(2), 2);
+        assert_eq!(memory.get(3), 0);
+        assert!(memory.write16(u32::MAX - 1, 0).is_ok());
+        assert!(memory.write16(u32::MAX, 0).is_err());
     }
 
     #[test]
     fn write32() {
         let mut memory = Memory::default();
         assert!(memory.write32(1, 0x4030201).is_ok());
-        assert_eq!(memory.0[0], 0);
-        assert_eq!(memory.0[1], 1);
-        assert_eq!(memory.0[2], 2);
-        assert_eq!(memory.0[3], 3);
-        assert_eq!(memory.0[4], 4);
-        assert_eq!(memory.0[5], 0);
-        assert!(memory.write32(MEMORY_SIZE_BYTES - 2, 0).is_err());
-        assert!(memory.write32(MEMORY_SIZE_BYTES - 1, 0).is_err());
-        assert!(memory.write32(MEMORY_SIZE_BYTES, 0).is_err());
+        assert_eq!(memory.get(0), 0);
+        assert_eq!(memory.get(1), 1);
+        assert_eq!(memory.get(2), 2);
+        assert_eq!(memory.get(3), 3);
+        assert_eq!(memory.get(4), 4);
+        assert_eq!(memory.get(5), 0);
+        assert!(memory.write32(u32::MAX - 3, 0).is_ok());
+        assert!(memory.write32(u32::MAX - 2, 0).is_err());
+        assert!(memory.write32(u32::MAX, 0).is_err());
+    }
+
+    #[test]
+    fn write64() {
+        let mut memory = Memory::default();
+        assert!(memory.write64(1, 0x0807_0605_0403_0201).is_ok());
+        assert_eq!(memory.get(0), 0);
+        for i in 1..9 {
+            assert_eq!(memory.get(i), i as u8);
+        }
+        assert_eq!(memory.get(9), 0);
+        assert_eq!(memory.read32(5).unwrap(), 0x8070605);
+        assert!(memory.write64(u32::MAX - 7, 0).is_ok());
+        assert!(memory.write64(u32::MAX - 6, 0).is_err());
+    }
+
+    #[test]
+    fn bytes() {
+        let mut memory = Memory::default();
+        let bytes: Vec<u8> = (0..=255).cycle().take(3 * PAGE_SIZE).collect();
+        // The bytes straddle four pages, the first of which is already allocated.
+        memory.write8(0x1000, 0xff).unwrap();
+        memory.write_bytes(0x1ffe, &bytes).unwrap();
+        assert_eq!(memory.read8(0x1ffd).unwrap(), 0);
+        assert_eq!(memory.read16(0x1ffe).unwrap(), 0x100);
+        assert_eq!(memory.read8(0x1ffe + 3 * PAGE_SIZE as u32).unwrap(), 0);
+        assert_eq!(
+            memory.read_bytes(0x1ffe, bytes.len() as u32).unwrap(),
+            bytes
+        );
+        assert_eq!(memory.read_bytes(0xfff, 2).unwrap(), [0, 0xff]);
+        assert_eq!(memory.read_bytes(0x10_0000, 3).unwrap(), [0, 0, 0]);
+
+        assert!(memory.read_bytes(u32::MAX, 0).unwrap().is_empty());
+        assert!(memory.read_bytes(u32::MAX - 1, 3).is_err());
+        assert!(memory.write_bytes(u32::MAX - 1, &[1, 2]).is_ok());
+        assert!(memory.write_bytes(u32::MAX - 1, &[1, 2, 3]).is_err());
+        assert_eq!(memory.read8(u32::MAX).unwrap(), 2);
+    }
+
+    #[test]
+    fn read() {
+        let mut memory = set_up_memory();
+        memory.protect(0..=u32::MAX, Permissions::READ_EXECUTE);
+        memory.start_recording();
+        assert_eq!(memory.read::<u8>(9).unwrap(), 9);
+        assert_eq!(memory.read::<u32>(1).unwrap(), 0x4030201);
+        assert_eq!(memory.read::<i16>(0x100).unwrap(), 0);
+        assert_eq!(memory.read::<u64>(u32::MAX - 7).unwrap(), 0);
+        assert!(memory.read::<u64>(u32::MAX - 6).is_err());
+        memory.protect(0..=u32::MAX, Permissions::ALL);
+        memory.load(0x200, &1.5f64.to_le_bytes()).unwrap();
+        assert_eq!(memory.read::<f64>(0x200).unwrap(), 1.5);
+        assert!(memory.stop_recording().is_empty());
+    }
+
+    #[test]
+    fn hexdump() {
+        let mut memory = Memory::default();
+        memory.load(0x1000, b"Hello, world!\n\0\x7fpeanut").unwrap();
+        assert_eq!(
+            memory.hexdump(0x1000..0x1017),
+            "00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 7f  |Hello, world!...|\n\
+             00001010  70 65 61 6e 75 74 00                              |peanut.|\n"
+        );
+        assert_eq!(memory.hexdump(0x1000..0x1000), "");
+    }
+
+    #[test]
+    fn load() {
+        let mut memory = Memory::default();
+        memory.start_recording();
+        memory.load(0x7c00, &[0xeb, 0xfe]).unwrap();
+        assert!(memory.load(u32::MAX, &[0, 0]).is_err());
+        assert!(memory.stop_recording().is_empty());
+        assert_eq!(memory.read16(0x7c00).unwrap(), 0xfeeb);
+        assert_eq!(memory.read8(u32::MAX).unwrap(), 0);
+    }
+
+    #[test]
+    fn zero() {
+        let mut memory = Memory::default();
+        memory.load(0x1ffe, &[1, 2, 3, 4]).unwrap();
+        // Only the two pages which were loaded into are allocated, however many are zeroed.
+        memory.zero(0x1fff, 0x1000_0000).unwrap();
+        assert_eq!(memory.copy_to_vec(0x1ffe, 4), [1, 0, 0, 0]);
+        assert_eq!(memory.page_numbers().count(), 2);
+        assert!(memory.zero(u32::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn sparse_allocation() {
+        let mut memory = Memory::default();
+        assert_eq!(memory.page_numbers().count(), 0);
+        assert_eq!(memory.read32(0xc000_0000).unwrap(), 0);
+        assert_eq!(memory.page_numbers().count(), 0);
+
+        // A write which straddles two pages allocates both of them, and only them.
+        memory.write32(0xbfff_fffe, 0x4030201).unwrap();
+        assert_eq!(
+            memory.page_numbers().collect::<Vec<_>>(),
+            [0xbffff, 0xc0000]
+        );
+        assert_eq!(memory.read16(0xc000_0000).unwrap(), 0x403);
+
+        // Pages which have been allocated but are still zeroed equal those which have not.
+        let mut other = memory.clone();
+        other.write8(0x1234, 0).unwrap();
+        assert_eq!(memory, other);
+        other.write8(0x1234, 1).unwrap();
+        assert_ne!(memory, other);
+        assert_ne!(other, memory);
+    }
+
+    #[test]
+    fn protection() {
+        let mut memory = Memory::default();
+        memory.write32(0x100, 0x4030201).unwrap();
+        memory.protect(0x100..=0x1ff, Permissions::READ_EXECUTE);
+        memory.protect(0x180..=0x27f, Permissions::READ_WRITE);
+        memory.protect(0x300..=0x300, Permissions::READ_ONLY);
+        assert_eq!(memory.permissions(0xff), Permissions::ALL);
+        assert_eq!(memory.permissions(0x17f), Permissions::READ_EXECUTE);
+        assert_eq!(memory.permissions(0x180), Permissions::READ_WRITE);
+        assert_eq!(memory.permissions(0x280), Permissions::ALL);
+
+        assert_eq!(memory.read32(0x100).unwrap(), 0x4030201);
+        assert!(matches!(
+            memory.write8(0x100, 0),
+            Err(Error::ProtectionViolation(_))
+        ));
+        assert!(memory.write32(0xfe, 0).is_err());
+        assert!(memory.write_bytes(0x17f, &[0, 0]).is_err());
+        assert_eq!(memory.read32(0x100).unwrap(), 0x4030201);
+        memory.write64(0x180, u64::MAX).unwrap();
+        assert!(memory.write16(0x2ff, 0x200).is_err());
+        assert!(memory.write8(0x2ff, 0).is_ok());
+
+        // A read-only region within another region splits it.
+        memory.protect(0x1c0..=0x1c0, Permissions::READ_ONLY);
+        assert!(memory.write8(0x1bf, 0).is_ok());
+        assert!(memory.write8(0x1c0, 0).is_err());
+        assert!(memory.write8(0x1c1, 0).is_ok());
+
+        // Restrictions can be lifted, and images are loaded regardless of them.
+        memory.protect(0x100..=0x1ff, Permissions::ALL);
+        assert!(memory.write8(0x100, 0).is_ok());
+        assert!(memory.write8(0x1c0, 0).is_ok());
+        assert!(memory.write8(0x200, 0).is_ok());
+        memory.load(0x300, &[1]).unwrap();
+        assert_eq!(memory.read8(0x300).unwrap(), 1);
+
+        memory.protect(
+            0x400..=0x400,
+            Permissions {
+                read: false,
+                ..Permissions::ALL
+            },
+        );
+        assert!(memory.read8(0x400).is_err());
+        assert!(memory.read_bytes(0x3ff, 2).is_err());
+        assert_eq!(memory.permissions(0x400).to_string(), "-wx");
+    }
+
+    #[test]
+    fn hooks() {
+        let mut memory = Memory::default();
+        let accesses = Rc::new(RefCell::new(Vec::new()));
+        let writes = Rc::clone(&accesses);
+        let watch = memory.add_hook(0x100..=0x103, HookTrigger::Write, move |access| {
+            writes.borrow_mut().push(*access)
+        });
+        let reads = Rc::new(RefCell::new(0));
+        let counter = Rc::clone(&reads);
+        memory.add_hook(0x104..=0x104, HookTrigger::ReadWrite, move |_| {
+            *counter.borrow_mut() += 1
+        });
+
+        memory.write32(0xfe, 0x4030201).unwrap();
+        memory.write8(0x103, 5).unwrap();
+        memory.write32(0x104, 0).unwrap();
+        assert!(memory.write32(u32::MAX, 0).is_err());
+        memory.read16(0x102).unwrap();
+        memory.load(0x100, &[0; 8]).unwrap();
+        assert_eq!(
+            *accesses.borrow(),
+            [
+                MemoryAccess {
+                    kind: AccessKind::Write,
+                    address: 0xfe,
+                    size: Size::Dword,
+                    value: 0x4030201
+                },
+                MemoryAccess {
+                    kind: AccessKind::Write,
+                    address: 0x103,
+                    size: Size::Byte,
+                    value: 5
+                },
+            ]
+        );
+        assert_eq!(*reads.borrow(), 1);
+        memory.read_bytes(0x100, 8).unwrap();
+        assert_eq!(*reads.borrow(), 2);
+
+        assert!(memory.remove_hook(watch).is_some());
+        assert!(memory.remove_hook(watch).is_none());
+        memory.write8(0x100, 0).unwrap();
+        assert_eq!(accesses.borrow().len(), 2);
+
+        // A copy of the memory has no hooks.
+        memory.clone().read8(0x104).unwrap();
+        assert_eq!(*reads.borrow(), 2);
+    }
+
+    #[test]
+    fn diff() {
+        let mut memory = set_up_memory();
+        memory.protect(0..=0xff, Permissions::READ_ONLY);
+        let snapshot = memory.snapshot();
+        assert_eq!(snapshot, memory);
+        assert_eq!(snapshot.permissions(0), Permissions::ALL);
+        assert!(memory.diff(&snapshot).is_empty());
+
+        // Writing a value which is already there changes nothing. Changes either side of a page
+        // boundary are a single range, and unallocated memory compares as zero.
+        memory.write32(0x100, 0x1234).unwrap();
+        memory.write8(0x103, 0).unwrap();
+        memory.write16(0x1fff, 0xffff).unwrap();
+        memory.write8(u32::MAX, 1).unwrap();
+        memory.write8(0x2000_0000, 0).unwrap();
+        assert_eq!(
+            memory.diff(&snapshot),
+            [0x100..=0x101, 0x1fff..=0x2000, u32::MAX..=u32::MAX]
+        );
+        assert_eq!(snapshot.diff(&memory), memory.diff(&snapshot));
+        memory.restore(&snapshot);
+        assert!(memory.diff(&snapshot).is_empty());
+        assert_eq!(memory.permissions(0), Permissions::READ_ONLY);
+    }
+
+    #[test]
+    fn record_accesses() {
+        let mut memory = Memory::default();
+        memory.write8(0, 1).unwrap();
+        memory.start_recording();
+        memory.write16(2, 0x302).unwrap();
+        assert!(memory.write32(u32::MAX, 0).is_err());
+        assert_eq!(memory.read32(0).unwrap(), 0x3020001);
+        let accesses = memory.stop_recording();
+        assert_eq!(
+            accesses,
+            [
+                MemoryAccess {
+                    kind: AccessKind::Write,
+                    address: 2,
+                    size: Size::Word,
+                    value: 0x302
+                },
+                MemoryAccess {
+                    kind: AccessKind::Read,
+                    address: 0,
+                    size: Size::Dword,
+                    value: 0x3020001
+                },
+            ]
+        );
+        memory.read8(0).unwrap();
+        assert!(memory.stop_recording().is_empty());
+
+        // The accesses recorded do not affect whether two memories are equal.
+        memory.start_recording();
+        memory.read8(0).unwrap();
+        let mut other = Memory::default();
+        other.write32(0, 0x3020001).unwrap();
+        assert_eq!(memory, other);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn memory_serde() {
+        let mut memory = Memory::default();
+        memory.write16(0x1fff, 0x1234).unwrap();
+        // A page which has been allocated, but only holds zeros, is not serialized.
+        memory.write8(0x5000, 0).unwrap();
+        memory.protect(0x8000..=0x8fff, Permissions::READ_ONLY);
+
+        let value = serde_json::to_value(&memory).unwrap();
+        let pages = value["pages"].as_array().unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0]["address"], 0x1000);
+        let bytes = pages[0]["bytes"].as_str().unwrap();
+        assert_eq!(bytes.len(), 2 * PAGE_SIZE);
+        assert!(bytes.starts_with("0000") && bytes.ends_with("0034"));
+        assert_eq!(pages[1]["address"], 0x2000);
+        assert!(pages[1]["bytes"].as_str().unwrap().starts_with("1200"));
+        assert_eq!(
+            value["regions"],
+            serde_json::json!([{
+                "start": 0x8000,
+                "end": 0x8fff,
+                "permissions": { "read": true, "write": false, "execute": false }
+            }])
+        );
+
+        let deserialized: Memory = serde_json::from_value(value).unwrap();
+        assert_eq!(deserialized, memory);
+        assert_eq!(deserialized.read16(0x1fff).unwrap(), 0x1234);
+        assert_eq!(deserialized.permissions(0x8000), Permissions::READ_ONLY);
+
+        // Pages need not be aligned, but must fit within the address space.
+        let memory: Memory = serde_json::from_str(
+            r#"{ "pages": [{ "address": 4094, "bytes": "aabbcc" }], "regions": [] }"#,
+        )
+        .unwrap();
+        assert_eq!(memory.read32(0xffe).unwrap(), 0x00cc_bbaa);
+        for json in [
+            r#"{ "pages": [{ "address": 4294967295, "bytes": "aabb" }], "regions": [] }"#,
+            r#"{ "pages": [{ "address": 0, "bytes": "abc" }], "regions": [] }"#,
+            r#"{ "pages": [{ "address": 0, "bytes": "zz" }], "regions": [] }"#,
+        ] {
+            assert!(serde_json::from_str::<Memory>(json).is_err());
+        }
     }
 }
@@ -0,0 +1,100 @@
+use alloc::collections::BTreeMap;
+
+/// Intel manual volume 4, chapter 2 "MODEL-SPECIFIC REGISTERS (MSRS)". The addresses of the
+/// architectural MSRs which are implemented.
+pub const IA32_TIME_STAMP_COUNTER: u32 = 0x10;
+pub const IA32_SYSENTER_CS: u32 = 0x174;
+pub const IA32_SYSENTER_ESP: u32 = 0x175;
+pub const IA32_SYSENTER_EIP: u32 = 0x176;
+
+/// The model-specific registers, which are 64 bits wide and indexed by a 32-bit address (as held in
+/// ECX by `RDMSR` and `WRMSR`). Only MSRs which have been defined exist, and accessing any other
+/// address is an error, unless the bank is permissive. In that case, unknown MSRs read as 0 and
+/// writes to them are discarded.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModelSpecificRegisters {
+    /// Ordered by address, such that the state of the processor is always serialized the same way.
+    values: BTreeMap<u32, u64>,
+    permissive: bool,
+}
+
+impl Default for ModelSpecificRegisters {
+    fn default() -> Self {
+        let mut model_specific_registers = Self {
+            values: BTreeMap::new(),
+            permissive: false,
+        };
+        // The time-stamp counter is not held here, as it is kept by the CPU itself.
+        for address in [IA32_SYSENTER_CS, IA32_SYSENTER_ESP, IA32_SYSENTER_EIP] {
+            model_specific_registers.define(address, 0);
+        }
+        model_specific_registers
+    }
+}
+
+impl ModelSpecificRegisters {
+    /// Defines an MSR at `address` with the given initial value, replacing it if it already
+    /// exists.
+    pub fn define(&mut self, address: u32, value: u64) {
+        self.values.insert(address, value);
+    }
+
+    /// Sets whether accesses to undefined MSRs are permitted.
+    pub fn set_permissive(&mut self, permissive: bool) {
+        self.permissive = permissive;
+    }
+
+    /// Reads the MSR at `address`. If it has not been defined, then `None` is returned, unless the
+    /// bank is permissive, in which case it reads as 0.
+    pub fn read(&self, address: u32) -> Option<u64> {
+        match self.values.get(&address) {
+            Some(value) => Some(*value),
+            None if self.permissive => Some(0),
+            None => None,
+        }
+    }
+
+    /// Writes the MSR at `address`, returning whether the write was accepted. Writes to undefined
+    /// MSRs are rejected, unless the bank is permissive, in which case they are discarded.
+    pub fn write(&mut self, address: u32, value: u64) -> bool {
+        match self.values.get_mut(&address) {
+            Some(existing) => {
+                *existing = value;
+                true
+            }
+            None => self.permissive,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_write() {
+        let mut model_specific_registers = ModelSpecificRegisters::default();
+        assert_eq!(model_specific_registers.read(IA32_SYSENTER_CS), Some(0));
+        assert!(model_specific_registers.write(IA32_SYSENTER_CS, 0x8));
+        assert_eq!(model_specific_registers.read(IA32_SYSENTER_CS), Some(0x8));
+
+        assert_eq!(model_specific_registers.read(0x1234), None);
+        assert!(!model_specific_registers.write(0x1234, 1));
+
+        model_specific_registers.define(0x1234, 0xdead_beef_0000_0001);
+        assert_eq!(
+            model_specific_registers.read(0x1234),
+            Some(0xdead_beef_0000_0001)
+        );
+    }
+
+    #[test]
+    fn permissive() {
+        let mut model_specific_registers = ModelSpecificRegisters::default();
+        model_specific_registers.set_permissive(true);
+        assert_eq!(model_specific_registers.read(0x1234), Some(0));
+        assert!(model_specific_registers.write(0x1234, 1));
+        assert_eq!(model_specific_registers.read(0x1234), Some(0));
+    }
+}
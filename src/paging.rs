@@ -0,0 +1,290 @@
+use crate::memory::{AccessKind, Memory};
+use alloc::{vec, vec::Vec};
+
+/// The size in bytes of a page, and the alignment of the frames which pages are mapped to.
+pub const PAGE_SIZE: u32 = 0x1000;
+
+/// Intel manual section 4.3 "32-BIT PAGING", table 4-5 and table 4-6. The flags of the page-
+/// directory and page-table entries which are used.
+pub const PRESENT: u32 = 1 << 0;
+pub const WRITABLE: u32 = 1 << 1;
+pub const USER: u32 = 1 << 2;
+pub const ACCESSED: u32 = 1 << 5;
+pub const DIRTY: u32 = 1 << 6;
+/// Set in a page-directory entry which maps a 4 MiB page (when CR4.PSE is set), rather than
+/// referencing a page table.
+pub const PAGE_SIZE_EXTENSION: u32 = 1 << 7;
+
+/// Intel manual section 4.7 "PAGE-FAULT EXCEPTIONS". The flags of the error code of a #PF
+/// exception.
+pub const ERROR_PROTECTION: u32 = 1 << 0;
+pub const ERROR_WRITE: u32 = 1 << 1;
+pub const ERROR_USER: u32 = 1 << 2;
+
+/// A page fault which has been raised by an access, but which is yet to be delivered. CR2 is loaded
+/// with the address when it is delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageFault {
+    /// The linear address which could not be accessed.
+    pub address: u32,
+    pub error_code: u32,
+}
+
+/// An access to translate, and the paging state which it is translated with.
+#[derive(Clone, Copy, Debug)]
+pub struct Access {
+    /// The physical address of the page directory, from CR3.
+    pub directory: u32,
+    pub kind: AccessKind,
+    /// Whether the access is made at CPL 3.
+    pub user: bool,
+    /// Whether supervisor writes to read-only pages fault (CR0.WP).
+    pub write_protect: bool,
+    /// Whether page-directory entries may map 4 MiB pages (CR4.PSE).
+    pub large_pages: bool,
+}
+
+/// The translation of a single page, as cached by the TLB.
+#[derive(Clone, Copy, Debug)]
+struct Translation {
+    /// The linear address of the page.
+    page: u32,
+    /// The physical address of the frame which the page is mapped to.
+    frame: u32,
+    writable: bool,
+    user: bool,
+    /// Whether the dirty flag has been set in the page's entry, which must be done by the first
+    /// write to it.
+    dirty: bool,
+}
+
+/// The number of translations which the TLB holds.
+const TLB_ENTRIES: usize = 64;
+
+/// Intel manual section 4.10 "CACHING TRANSLATION INFORMATION". A small, direct-mapped translation
+/// lookaside buffer, which caches the translations of recently accessed pages. It is flushed
+/// whenever the page directory changes, and by `INVLPG`.
+#[derive(Clone, Debug)]
+pub struct Tlb {
+    directory: u32,
+    entries: [Option<Translation>; TLB_ENTRIES],
+}
+
+impl Default for Tlb {
+    fn default() -> Self {
+        Self {
+            directory: 0,
+            entries: [None; TLB_ENTRIES],
+        }
+    }
+}
+
+impl Tlb {
+    pub fn flush(&mut self) {
+        self.entries = [None; TLB_ENTRIES];
+    }
+
+    /// Invalidates the translation of the page containing `address`, if it is cached.
+    pub fn invalidate(&mut self, address: u32) {
+        let slot = &mut self.entries[Self::slot(address)];
+        if slot.is_some_and(|translation| translation.page == address & !(PAGE_SIZE - 1)) {
+            *slot = None;
+        }
+    }
+
+    /// Translates the linear address of an access into a physical address, using the cached
+    /// translation if there is one which permits the access, and otherwise walking the page tables
+    /// in `memory`. The accessed and dirty flags which must be set by the walk are returned as
+    /// `(entry address, flags)` pairs, as `memory` can only be read here.
+    pub fn translate(
+        &mut self,
+        memory: &Memory,
+        address: u32,
+        access: Access,
+    ) -> Result<(u32, Vec<(u32, u32)>), PageFault> {
+        if self.directory != access.directory {
+            self.flush();
+            self.directory = access.directory;
+        }
+
+        let page = address & !(PAGE_SIZE - 1);
+        let slot = Self::slot(address);
+        if let Some(translation) = self.entries[slot] {
+            if translation.page == page
+                && permitted(translation.writable, translation.user, access)
+                && (access.kind == AccessKind::Read || translation.dirty)
+            {
+                return Ok((translation.frame | address & (PAGE_SIZE - 1), Vec::new()));
+            }
+        }
+
+        self.entries[slot] = None;
+        let (translation, updates) = walk(memory, address, access)?;
+        self.entries[slot] = Some(translation);
+        Ok((translation.frame | address & (PAGE_SIZE - 1), updates))
+    }
+
+    fn slot(address: u32) -> usize {
+        (address / PAGE_SIZE) as usize % TLB_ENTRIES
+    }
+}
+
+/// Whether an access is permitted to a page with the given (combined) writable and user flags.
+/// Intel manual section 4.6 "ACCESS RIGHTS".
+fn permitted(writable: bool, user: bool, access: Access) -> bool {
+    let write = access.kind == AccessKind::Write;
+    if access.user {
+        user && (writable || !write)
+    } else {
+        writable || !write || !access.write_protect
+    }
+}
+
+/// Walks the two-level page tables to translate the page containing `address`, returning its
+/// translation along with the accessed and dirty flags to set.
+fn walk(
+    memory: &Memory,
+    address: u32,
+    access: Access,
+) -> Result<(Translation, Vec<(u32, u32)>), PageFault> {
+    let fault = |flags: u32| {
+        let mut error_code = flags;
+        if access.kind == AccessKind::Write {
+            error_code |= ERROR_WRITE;
+        }
+        if access.user {
+            error_code |= ERROR_USER;
+        }
+        PageFault {
+            address,
+            error_code,
+        }
+    };
+    let dirty = if access.kind == AccessKind::Write {
+        DIRTY
+    } else {
+        0
+    };
+
+    let directory_entry_address = access.directory & !(PAGE_SIZE - 1) | (address >> 22) << 2;
+    let directory_entry = memory.read32(directory_entry_address).unwrap_or(0);
+    if directory_entry & PRESENT == 0 {
+        return Err(fault(0));
+    }
+
+    let (frame, writable, user, updates) = if access.large_pages
+        && directory_entry & PAGE_SIZE_EXTENSION != 0
+    {
+        (
+            directory_entry & 0xffc0_0000 | address & 0x003f_f000,
+            directory_entry & WRITABLE != 0,
+            directory_entry & USER != 0,
+            vec![(directory_entry_address, ACCESSED | dirty)],
+        )
+    } else {
+        let table_entry_address = directory_entry & !(PAGE_SIZE - 1) | (address >> 12 & 0x3ff) << 2;
+        let table_entry = memory.read32(table_entry_address).unwrap_or(0);
+        if table_entry & PRESENT == 0 {
+            return Err(fault(0));
+        }
+        (
+            table_entry & !(PAGE_SIZE - 1),
+            directory_entry & table_entry & WRITABLE != 0,
+            directory_entry & table_entry & USER != 0,
+            vec![
+                (directory_entry_address, ACCESSED),
+                (table_entry_address, ACCESSED | dirty),
+            ],
+        )
+    };
+    if !permitted(writable, user, access) {
+        return Err(fault(ERROR_PROTECTION));
+    }
+
+    let translation = Translation {
+        page: address & !(PAGE_SIZE - 1),
+        frame,
+        writable,
+        user,
+        dirty: dirty != 0,
+    };
+    Ok((translation, updates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(kind: AccessKind, user: bool) -> Access {
+        Access {
+            directory: 0x1000,
+            kind,
+            user,
+            write_protect: false,
+            large_pages: true,
+        }
+    }
+
+    #[test]
+    fn translate() {
+        let mut memory = Memory::default();
+        // 0x0040_0000 is mapped to 0x5000 by the page table at 0x2000. 0x0080_0000 is a 4 MiB page
+        // mapped to 0x0100_0000, which is read-only and only accessible to the supervisor.
+        memory
+            .write32(0x1004, 0x2000 | USER | WRITABLE | PRESENT)
+            .unwrap();
+        memory
+            .write32(0x1008, 0x0100_0000 | PAGE_SIZE_EXTENSION | PRESENT)
+            .unwrap();
+        memory.write32(0x2000, 0x5000 | USER | PRESENT).unwrap();
+
+        let mut tlb = Tlb::default();
+        let (physical, updates) = tlb
+            .translate(&memory, 0x0040_0123, access(AccessKind::Read, true))
+            .unwrap();
+        assert_eq!(physical, 0x5123);
+        assert_eq!(updates, vec![(0x1004, ACCESSED), (0x2000, ACCESSED)]);
+
+        // The translation is cached, so unmapping the page has no effect until it is invalidated.
+        memory.write32(0x2000, 0).unwrap();
+        let (physical, updates) = tlb
+            .translate(&memory, 0x0040_0456, access(AccessKind::Read, true))
+            .unwrap();
+        assert_eq!(physical, 0x5456);
+        assert!(updates.is_empty());
+        tlb.invalidate(0x0040_0000);
+        assert_eq!(
+            tlb.translate(&memory, 0x0040_0456, access(AccessKind::Read, true)),
+            Err(PageFault {
+                address: 0x0040_0456,
+                error_code: ERROR_USER,
+            })
+        );
+
+        let (physical, updates) = tlb
+            .translate(&memory, 0x0080_1234, access(AccessKind::Write, false))
+            .unwrap();
+        assert_eq!(physical, 0x0100_1234);
+        assert_eq!(updates, vec![(0x1008, ACCESSED | DIRTY)]);
+        assert_eq!(
+            tlb.translate(&memory, 0x0080_1234, access(AccessKind::Read, true)),
+            Err(PageFault {
+                address: 0x0080_1234,
+                error_code: ERROR_USER | ERROR_PROTECTION,
+            })
+        );
+
+        // With CR0.WP set, the supervisor cannot write to read-only pages either.
+        let write_protected = Access {
+            write_protect: true,
+            ..access(AccessKind::Write, false)
+        };
+        assert_eq!(
+            tlb.translate(&memory, 0x0080_1234, write_protected),
+            Err(PageFault {
+                address: 0x0080_1234,
+                error_code: ERROR_WRITE | ERROR_PROTECTION,
+            })
+        );
+    }
+}
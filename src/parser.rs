@@ -0,0 +1,485 @@
+use crate::{
+    diagnostic::on_line,
+    error::Error,
+    expression::{self, substitute_location, Location},
+    instruction::{NasmStr, Syntax},
+    lexer,
+    preprocessor::Line,
+    program::{is_symbol_char, SectionName},
+};
+use alloc::{format, vec::Vec};
+
+/// An item of a data definition (e.g. `db "hello", 10`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataItem<'a> {
+    /// The contents of a quoted string, which are stored as their bytes.
+    String(&'a str),
+    /// An expression, which is stored as a number once the values of symbols are known.
+    Expression(&'a str),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatementKind<'a> {
+    /// `name:`, which defines a label as the address of the statement which follows it.
+    Label(&'a str),
+    /// `name equ expression`, which defines a constant.
+    Constant { name: &'a str, expression: &'a str },
+    /// `section name` (or `segment name`), which places the statements which follow in a section.
+    Section(SectionName),
+    /// `org address`, which gives the base address of `.text`.
+    Origin(&'a str),
+    /// `global names`, which makes the symbols defined by the module visible to other modules.
+    Global(Vec<&'a str>),
+    /// `extern names`, which allows the module to use symbols which other modules make global.
+    Extern(Vec<&'a str>),
+    /// `resb count` (or `resw`, `resd`, or `resq`), which reserves `count` items of `size` bytes.
+    Reservation { size: u32, count: &'a str },
+    /// `db items` (or `dw` or `dd`), which defines data in which each number is `size` bytes.
+    Data {
+        size: usize,
+        items: Vec<DataItem<'a>>,
+    },
+    /// An instruction, which is parsed once the values of the symbols that it uses are known.
+    Instruction(&'a str),
+    /// `%pragma peanut assert condition`, which asserts that the condition holds whenever the
+    /// instruction which follows is about to be executed (see [`Assertion`]).
+    ///
+    /// [`Assertion`]: crate::assertion::Assertion
+    Assertion(&'a str),
+}
+
+/// A statement of a program. A line of source may contain two statements, as a label may be
+/// followed by another statement on the same line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement<'a> {
+    /// The index of the line in the original source which the statement came from.
+    pub index: usize,
+    /// The text of the statement, without any label, comment, or `TIMES` prefix.
+    pub text: &'a str,
+    /// The count given by a `TIMES` prefix (e.g. `times 510-($-$$) db 0`), which is an expression
+    /// that gives the number of times that the statement is repeated.
+    pub times: Option<&'a str>,
+    pub kind: StatementKind<'a>,
+}
+
+/// Parses the lines of a program's preprocessed source into statements, without evaluating any
+/// expressions, as the values of symbols are not yet known. Errors refer to the lines of the
+/// `original` source, before it was preprocessed.
+pub fn parse<'a>(
+    source: &'a [Line],
+    original: &[&str],
+    syntax: Syntax,
+) -> Result<Vec<Statement<'a>>, Error> {
+    let mut statements = Vec::new();
+    for (index, line) in source {
+        let index = *index;
+        let line = strip_comment(line, syntax).trim();
+
+        if let Some((name, expression)) = split_equ(line) {
+            statements.push(Statement {
+                index,
+                text: line,
+                times: None,
+                kind: StatementKind::Constant { name, expression },
+            });
+            continue;
+        }
+
+        let text = match split_label(line) {
+            Some((label, text)) => {
+                statements.push(Statement {
+                    index,
+                    text: label,
+                    times: None,
+                    kind: StatementKind::Label(label),
+                });
+                text
+            }
+            None => line,
+        };
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (times, text) =
+            split_times(text).map_err(|error| on_line(original, index, text, error))?;
+        let kind = parse_statement(text).map_err(|(token, error)| {
+            on_line(
+                original,
+                index,
+                if token.is_empty() { text } else { token },
+                error,
+            )
+        })?;
+        if times.is_some()
+            && !matches!(
+                kind,
+                StatementKind::Reservation { .. }
+                    | StatementKind::Data { .. }
+                    | StatementKind::Instruction(_)
+            )
+        {
+            return Err(on_line(
+                original,
+                index,
+                text,
+                Error::CannotParseInstruction(format!(
+                    "TIMES cannot be used with \"{text}\", only with instructions, data, and \
+                     reservations"
+                )),
+            ));
+        }
+
+        statements.push(Statement {
+            index,
+            text,
+            times,
+            kind,
+        });
+    }
+    Ok(statements)
+}
+
+/// Parses a statement which is not a label or a constant. Returns the token which caused an error
+/// alongside it, which is empty if the whole statement did.
+fn parse_statement(statement: &str) -> Result<StatementKind<'_>, (&str, Error)> {
+    let (keyword, remainder) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    let remainder = remainder.trim();
+    match keyword.to_lowercase().as_str() {
+        "section" | "segment" => SectionName::try_from(&NasmStr(remainder))
+            .map(StatementKind::Section)
+            .map_err(|error| (remainder, error)),
+        "org" => Ok(StatementKind::Origin(remainder)),
+        "%pragma" => parse_pragma(remainder),
+        directive @ ("global" | "extern") => {
+            let names: Vec<_> = remainder.split(',').map(str::trim).collect();
+            if remainder.is_empty() || names.iter().any(|name| name.is_empty()) {
+                return Err((
+                    keyword,
+                    Error::CannotParseInstruction(format!(
+                        "{directive} requires a comma-separated list of symbols"
+                    )),
+                ));
+            }
+
+            Ok(if directive == "global" {
+                StatementKind::Global(names)
+            } else {
+                StatementKind::Extern(names)
+            })
+        }
+        directive @ ("resb" | "resw" | "resd" | "resq") => {
+            if remainder.is_empty() {
+                return Err((
+                    keyword,
+                    Error::CannotParseInstruction(format!("{directive} requires a count")),
+                ));
+            }
+
+            let size = match directive {
+                "resb" => 1,
+                "resw" => 2,
+                "resd" => 4,
+                _ => 8,
+            };
+            Ok(StatementKind::Reservation {
+                size,
+                count: remainder,
+            })
+        }
+        directive @ ("db" | "dw" | "dd" | "dq") => {
+            let items = split_data_items(remainder);
+            if items.is_empty() {
+                return Err((
+                    keyword,
+                    Error::CannotParseInstruction(format!("{directive} requires data")),
+                ));
+            }
+
+            let size = match directive {
+                "db" => 1,
+                "dw" => 2,
+                "dd" => 4,
+                _ => 8,
+            };
+            let items = items
+                .into_iter()
+                .map(|item| match parse_string(item) {
+                    Some(string) => DataItem::String(string),
+                    None => DataItem::Expression(item),
+                })
+                .collect();
+            Ok(StatementKind::Data { size, items })
+        }
+        _ => Ok(StatementKind::Instruction(statement)),
+    }
+}
+
+/// Parses a pragma (e.g. `peanut assert eax == 5`), which the preprocessor only passes on if it
+/// is for this assembler.
+fn parse_pragma(pragma: &str) -> Result<StatementKind<'_>, (&str, Error)> {
+    let (_, remainder) = pragma
+        .split_once(char::is_whitespace)
+        .unwrap_or((pragma, ""));
+    let remainder = remainder.trim();
+    let (directive, condition) = remainder
+        .split_once(char::is_whitespace)
+        .unwrap_or((remainder, ""));
+    if !directive.eq_ignore_ascii_case("assert") {
+        return Err((
+            directive,
+            Error::CannotParseInstruction(format!(
+                "unknown pragma \"{directive}\", expected \"assert\""
+            )),
+        ));
+    }
+    if condition.trim().is_empty() {
+        return Err((
+            directive,
+            Error::CannotParseInstruction("assert requires a condition".into()),
+        ));
+    }
+    Ok(StatementKind::Assertion(condition.trim()))
+}
+
+/// Removes any comment from the end of a line. A comment character within a string or character
+/// literal (e.g. `db "a;b"`) does not start a comment.
+pub(crate) fn strip_comment(line: &str, syntax: Syntax) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, ';') => return &line[..i],
+            (None, '#') if syntax == Syntax::Att => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits a label definition (e.g. `loop: dec ecx`) into the label, and the statement which
+/// follows it on the same line. Returns `None` if the line does not begin with a label.
+pub(crate) fn split_label(line: &str) -> Option<(&str, &str)> {
+    let (label, statement) = line.split_once(':')?;
+    if label.is_empty() || !label.chars().all(is_symbol_char) {
+        return None;
+    }
+    Some((label, statement.trim()))
+}
+
+/// Splits a `TIMES` prefix (e.g. `times 4 db 0`) from a statement, returning the count and the
+/// statement. As the count may contain spaces, it is the longest sequence of words that forms an
+/// expression, which is followed by at least one more word.
+fn split_times(statement: &str) -> Result<(Option<&str>, &str), Error> {
+    let Some((keyword, remainder)) = statement.split_once(char::is_whitespace) else {
+        return Ok((None, statement));
+    };
+    if !keyword.eq_ignore_ascii_case("times") {
+        return Ok((None, statement));
+    }
+
+    let remainder = remainder.trim_start();
+    remainder
+        .match_indices(char::is_whitespace)
+        .rev()
+        .map(|(i, _)| (remainder[..i].trim(), remainder[i..].trim()))
+        .find(|(count, statement)| !statement.is_empty() && is_expression(count))
+        .map(|(count, statement)| (Some(count), statement))
+        .ok_or_else(|| {
+            Error::CannotParseInstruction(
+                "TIMES must be followed by a count, and then an instruction or data".into(),
+            )
+        })
+}
+
+/// Whether the text forms an expression, which may use symbols and the location counter.
+fn is_expression(text: &str) -> bool {
+    let text = substitute_location(text, Location { here: 0, start: 0 });
+    lexer::tokenize(&text).is_ok_and(|tokens| expression::is_well_formed(&tokens))
+}
+
+/// Splits a constant definition (e.g. `SIZE equ 4*4`) into the name of the constant, and the
+/// expression which gives its value. As with labels, the name may be followed by a colon. Returns
+/// `None` if the line does not define a constant.
+fn split_equ(line: &str) -> Option<(&str, &str)> {
+    let (name, remainder) = line.split_once(char::is_whitespace)?;
+    let (keyword, expression) = remainder
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .unwrap_or((remainder.trim_start(), ""));
+    if !keyword.eq_ignore_ascii_case("equ") {
+        return None;
+    }
+    Some((name.strip_suffix(':').unwrap_or(name), expression.trim()))
+}
+
+/// Splits the comma-separated items of a data definition, ignoring any commas within strings.
+fn split_data_items(items: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in items.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, ',') => {
+                result.push(items[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if !items.trim().is_empty() {
+        result.push(items[start..].trim());
+    }
+    result
+}
+
+/// Returns the contents of the item if it is a quoted string.
+fn parse_string(item: &str) -> Option<&str> {
+    let quote = item.chars().next()?;
+    if !matches!(quote, '\'' | '"' | '`') || item.len() < 2 || !item.ends_with(quote) {
+        return None;
+    }
+    Some(&item[1..item.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(source: &str) -> Vec<Line> {
+        source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index, line.to_string()))
+            .collect()
+    }
+
+    fn parse_nasm(lines: &[Line]) -> Result<Vec<(usize, Option<&str>, StatementKind<'_>)>, Error> {
+        let original: Vec<_> = lines.iter().map(|(_, line)| line.as_str()).collect();
+        Ok(parse(lines, &original, Syntax::Nasm)?
+            .into_iter()
+            .map(|statement| (statement.index, statement.times, statement.kind))
+            .collect())
+    }
+
+    #[test]
+    fn parse_statements() {
+        use StatementKind::*;
+
+        assert_eq!(
+            parse_nasm(&lines(
+                "org 0x100 ; start\n\
+                 \n\
+                 start: add eax, 1\n\
+                 SIZE: equ 4*4\n\
+                 section .data\n\
+                 message: db \"a, b\", 10, SIZE\n\
+                 times 510-($-$$) dw 0\n\
+                 section .bss\n\
+                 .buffer: times SIZE / 2 resd 1"
+            ))
+            .unwrap(),
+            vec![
+                (0, None, Origin("0x100")),
+                (2, None, Label("start")),
+                (2, None, Instruction("add eax, 1")),
+                (
+                    3,
+                    None,
+                    Constant {
+                        name: "SIZE",
+                        expression: "4*4"
+                    }
+                ),
+                (4, None, Section(SectionName::Data)),
+                (5, None, Label("message")),
+                (
+                    5,
+                    None,
+                    Data {
+                        size: 1,
+                        items: vec![
+                            DataItem::String("a, b"),
+                            DataItem::Expression("10"),
+                            DataItem::Expression("SIZE"),
+                        ]
+                    }
+                ),
+                (
+                    6,
+                    Some("510-($-$$)"),
+                    Data {
+                        size: 2,
+                        items: vec![DataItem::Expression("0")]
+                    }
+                ),
+                (7, None, Section(SectionName::Bss)),
+                (8, None, Label(".buffer")),
+                (
+                    8,
+                    Some("SIZE / 2"),
+                    Reservation {
+                        size: 4,
+                        count: "1"
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            parse_nasm(&lines("global start, main\nEXTERN print")).unwrap(),
+            vec![
+                (0, None, Global(vec!["start", "main"])),
+                (1, None, Extern(vec!["print"])),
+            ]
+        );
+        assert_eq!(
+            parse_nasm(&lines("db \"a;b\", ';', '\"' ; a comment")).unwrap(),
+            vec![(
+                0,
+                None,
+                Data {
+                    size: 1,
+                    items: vec![
+                        DataItem::String("a;b"),
+                        DataItem::String(";"),
+                        DataItem::String("\""),
+                    ]
+                }
+            )]
+        );
+        assert_eq!(
+            parse_nasm(&lines("times (1 + 2) * 3 rep movsb")).unwrap(),
+            vec![(0, Some("(1 + 2) * 3"), Instruction("rep movsb"))]
+        );
+        assert_eq!(
+            parse_nasm(&lines("l: %pragma peanut assert mem dword [l] == 1")).unwrap(),
+            vec![
+                (0, None, Label("l")),
+                (0, None, Assertion("mem dword [l] == 1")),
+            ]
+        );
+
+        for source in [
+            "section .rodata",
+            "db",
+            "resb",
+            "times 2",
+            "times + nop",
+            "times 2 section .data",
+            "global",
+            "extern a,",
+            "times 2 global a",
+            "%pragma peanut assert",
+            "%pragma peanut check eax == 1",
+        ] {
+            assert!(parse_nasm(&lines(source)).is_err());
+        }
+    }
+}
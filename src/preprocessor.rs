@@ -0,0 +1,605 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    diagnostic::on_line,
+    error::Error,
+    instruction::Syntax,
+    parser::{split_label, strip_comment},
+    program::{is_symbol_char, is_symbol_start},
+};
+
+/// The maximum depth to which macros may expand into other macros, which catches macros that are
+/// (indirectly) defined in terms of themselves.
+const MAXIMUM_EXPANSION_DEPTH: usize = 64;
+
+/// The namespace of the pragmas which are for this assembler (e.g. `%pragma peanut assert ...`).
+pub(crate) const PRAGMA_NAMESPACE: &str = "peanut";
+
+/// A single-line macro, which is defined with `%define`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Define {
+    /// The names of the parameters if the macro takes arguments (e.g. `%define ADD(a, b) a+b`),
+    /// or `None` if it is replaced without any arguments (e.g. `%define SIZE 4`).
+    parameters: Option<Vec<String>>,
+    body: String,
+}
+
+/// A multi-line macro, which is defined with `%macro` and `%endmacro`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Macro {
+    /// The number of arguments which the macro takes.
+    parameters: usize,
+    body: Vec<String>,
+}
+
+/// A line of preprocessed source, along with the index of the line in the original source which it
+/// came from. All of the lines of a multi-line macro's expansion come from the line which used it.
+pub type Line = (usize, String);
+
+/// Performs the textual substitution of macros in a program's source, before it is assembled.
+#[derive(Clone, Debug, Default)]
+pub struct Preprocessor {
+    defines: BTreeMap<String, Define>,
+    macros: BTreeMap<String, Macro>,
+    /// The number of multi-line macros which have been expanded so far, which is used to give the
+    /// local labels (`%%name`) of each expansion a unique name.
+    expansions: usize,
+}
+
+impl Preprocessor {
+    /// Processes each line of the source in turn, such that a macro can only be used after it has
+    /// been defined. The directives are:
+    ///
+    /// - `%define NAME body`, which replaces `NAME` with `body`.
+    /// - `%define NAME(a, b) body`, which replaces `NAME(x, y)` with `body`, within which each
+    ///   parameter is replaced with its argument.
+    /// - `%undef NAME`, which removes a macro.
+    /// - `%macro NAME N`, followed by lines up until `%endmacro`, which replaces a line using
+    ///   `NAME` (e.g. `NAME eax, 4`) with those lines. Within them, `%1` to `%N` are replaced with
+    ///   the arguments, `%0` with the number of arguments, and `%%label` with a label which is
+    ///   unique to each use of the macro.
+    /// - `%pragma peanut ...`, which is passed on to the assembler with any macros expanded (see
+    ///   [`parser::parse`](crate::parser::parse)). Pragmas for other tools are ignored.
+    ///
+    /// As in NASM, macro names are case-sensitive, and macros within the body of a macro are
+    /// expanded when it is used, rather than when it is defined. Text within quotes is never
+    /// replaced.
+    pub fn process(&mut self, source: &str) -> Result<Vec<Line>, Error> {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index, line.to_string()));
+        let source: Vec<_> = source.lines().collect();
+        let mut output = Vec::new();
+        self.process_lines(&source, &mut lines, &mut output, 0)?;
+        Ok(output)
+    }
+
+    /// Processes lines until there are none left, where `depth` is the number of multi-line macros
+    /// which are being expanded. Errors refer to the lines of the original `source`.
+    fn process_lines(
+        &mut self,
+        source: &[&str],
+        lines: &mut dyn Iterator<Item = Line>,
+        output: &mut Vec<Line>,
+        depth: usize,
+    ) -> Result<(), Error> {
+        if depth > MAXIMUM_EXPANSION_DEPTH {
+            return Err(Error::CannotParseInstruction(format!(
+                "macros were expanded more than {MAXIMUM_EXPANSION_DEPTH} levels deep"
+            )));
+        }
+
+        while let Some((index, line)) = lines.next() {
+            let directive = line.trim_start();
+            if directive.starts_with('%') {
+                let token = strip_comment(directive, Syntax::Nasm);
+                let pragma = self
+                    .process_directive(directive, lines)
+                    .map_err(|error| on_line(source, index, token.trim(), error))?;
+                output.extend(pragma.map(|pragma| (index, pragma)));
+                continue;
+            }
+
+            // Any label before the use of a multi-line macro is kept on its own line, such that it
+            // refers to the start of the expansion.
+            let (label, statement) = match split_label(&line) {
+                Some((label, statement)) => (Some(label), statement),
+                None => (None, line.as_str()),
+            };
+            let (name, arguments) = statement
+                .split_once(char::is_whitespace)
+                .unwrap_or((statement, ""));
+            let Some(r#macro) = self.macros.get(name).cloned() else {
+                let line = self
+                    .expand(&line, &mut Vec::new())
+                    .map_err(|error| on_line(source, index, "", error))?;
+                output.push((index, line));
+                continue;
+            };
+
+            if let Some(label) = label {
+                output.push((index, format!("{label}:")));
+            }
+            self.expansions += 1;
+            let arguments = strip_comment(arguments, Syntax::Nasm);
+            let body = r#macro
+                .expand(name, &split_top_level(arguments), self.expansions)
+                .map_err(|error| on_line(source, index, name, error))?;
+            self.process_lines(
+                source,
+                &mut body.into_iter().map(|line| (index, line)),
+                output,
+                depth + 1,
+            )
+            .map_err(|error| on_line(source, index, name, error))?;
+        }
+        Ok(())
+    }
+
+    /// Processes a directive, taking the body of a multi-line macro from `lines`. Returns the
+    /// pragmas for the assembler, which are passed on to it.
+    fn process_directive(
+        &mut self,
+        line: &str,
+        lines: &mut dyn Iterator<Item = Line>,
+    ) -> Result<Option<String>, Error> {
+        let line = strip_comment(line, Syntax::Nasm).trim();
+        let (directive, remainder) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match directive.to_lowercase().as_str() {
+            "%define" => self.process_define(remainder.trim()).map(|_| None),
+            "%undef" => {
+                self.defines.remove(remainder.trim());
+                Ok(None)
+            }
+            "%macro" => self.process_macro(remainder.trim(), lines).map(|_| None),
+            // As in NASM, pragmas for other tools are ignored.
+            "%pragma" => match remainder.split_whitespace().next() {
+                Some(namespace) if namespace.eq_ignore_ascii_case(PRAGMA_NAMESPACE) => {
+                    self.expand(line, &mut Vec::new()).map(Some)
+                }
+                _ => Ok(None),
+            },
+            "%endmacro" => Err(Error::CannotParseInstruction(
+                "%endmacro must follow %macro".into(),
+            )),
+            _ => Err(Error::CannotParseInstruction(format!(
+                "unknown preprocessor directive \"{directive}\""
+            ))),
+        }
+    }
+
+    fn process_define(&mut self, definition: &str) -> Result<(), Error> {
+        let name_length = definition
+            .find(|c| !is_symbol_char(c))
+            .unwrap_or(definition.len());
+        let (name, remainder) = definition.split_at(name_length);
+        if !name.starts_with(is_symbol_start) {
+            return Err(Error::InvalidSymbol(format!(
+                "\"{name}\" is not a valid macro name"
+            )));
+        }
+
+        // Parameters must immediately follow the name, as otherwise the parentheses are part of
+        // the body (e.g. `%define SIZE (4*4)`).
+        let (parameters, body) = match remainder.strip_prefix('(') {
+            Some(remainder) => {
+                let (parameters, body) = remainder.split_once(')').ok_or_else(|| {
+                    Error::CannotParseInstruction(format!(
+                        "expected \")\" after the parameters of \"{name}\""
+                    ))
+                })?;
+                let parameters: Vec<_> = parameters
+                    .split(',')
+                    .map(|parameter| parameter.trim().to_string())
+                    .filter(|parameter| !parameter.is_empty())
+                    .collect();
+                if let Some(parameter) = parameters.iter().find(|parameter| {
+                    !parameter.starts_with(is_symbol_start)
+                        || !parameter.chars().all(is_symbol_char)
+                }) {
+                    return Err(Error::InvalidSymbol(format!(
+                        "\"{parameter}\" is not a valid parameter name"
+                    )));
+                }
+                (Some(parameters), body)
+            }
+            None => (None, remainder),
+        };
+
+        self.defines.insert(
+            name.into(),
+            Define {
+                parameters,
+                body: body.trim().into(),
+            },
+        );
+        Ok(())
+    }
+
+    fn process_macro(
+        &mut self,
+        definition: &str,
+        lines: &mut dyn Iterator<Item = Line>,
+    ) -> Result<(), Error> {
+        let (name, parameters) = definition
+            .split_once(char::is_whitespace)
+            .unwrap_or((definition, ""));
+        let mut chars = name.chars();
+        if !chars.next().is_some_and(is_symbol_start) || !chars.all(is_symbol_char) {
+            return Err(Error::InvalidSymbol(format!(
+                "\"{name}\" is not a valid macro name"
+            )));
+        }
+
+        let parameters = parameters.trim().parse().map_err(|_| {
+            Error::CannotParseInstruction(format!(
+                "%macro {name} must be followed by its number of parameters"
+            ))
+        })?;
+
+        let mut body = Vec::new();
+        loop {
+            let Some((_, line)) = lines.next() else {
+                return Err(Error::CannotParseInstruction(format!(
+                    "%macro {name} is missing %endmacro"
+                )));
+            };
+
+            let directive = line.trim_start().to_lowercase();
+            if directive.starts_with("%endmacro") {
+                break;
+            }
+
+            if directive.starts_with("%macro") {
+                return Err(Error::CannotParseInstruction(format!(
+                    "%macro cannot be used within %macro {name}"
+                )));
+            }
+            body.push(line);
+        }
+
+        self.macros.insert(name.into(), Macro { parameters, body });
+        Ok(())
+    }
+
+    /// Expands each macro within `text`. Macros which are currently being expanded are listed in
+    /// `active`, and are not expanded again within their own body.
+    fn expand(&self, text: &str, active: &mut Vec<String>) -> Result<String, Error> {
+        if active.len() > MAXIMUM_EXPANSION_DEPTH {
+            return Err(Error::CannotParseInstruction(format!(
+                "macros were expanded more than {MAXIMUM_EXPANSION_DEPTH} levels deep"
+            )));
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut remainder = text;
+        while let Some(c) = remainder.chars().next() {
+            if matches!(c, '\'' | '"' | '`') {
+                let end = remainder[1..]
+                    .find(c)
+                    .map_or(remainder.len(), |end| end + 2);
+                result.push_str(&remainder[..end]);
+                remainder = &remainder[end..];
+                continue;
+            }
+
+            // Consume the whole word, so that the end of a number (e.g. `0xff`) or of a longer
+            // name is never mistaken for a macro in its own right.
+            let length = remainder
+                .find(|c| !is_symbol_char(c))
+                .unwrap_or(remainder.len())
+                .max(c.len_utf8());
+            let (word, after) = remainder.split_at(length);
+            remainder = after;
+
+            let define = match self.defines.get(word) {
+                Some(define) if is_symbol_start(c) && !active.iter().any(|name| name == word) => {
+                    define
+                }
+                _ => {
+                    result.push_str(word);
+                    continue;
+                }
+            };
+
+            let body = match &define.parameters {
+                None => define.body.clone(),
+                Some(parameters) => match split_arguments(remainder) {
+                    Some((arguments, after)) => {
+                        remainder = after;
+                        substitute_parameters(word, define, parameters, &arguments)?
+                    }
+                    // Without arguments, the name is not a use of the macro.
+                    None => {
+                        result.push_str(word);
+                        continue;
+                    }
+                },
+            };
+
+            active.push(word.into());
+            let expansion = self.expand(&body, active);
+            active.pop();
+            result.push_str(&expansion?);
+        }
+        Ok(result)
+    }
+}
+
+impl Macro {
+    /// Replaces the parameters within the body of the macro with the arguments. Local labels are
+    /// given the prefix `..@N.`, where `N` is unique to this expansion of the macro.
+    fn expand(
+        &self,
+        name: &str,
+        arguments: &[&str],
+        expansion: usize,
+    ) -> Result<Vec<String>, Error> {
+        if self.parameters != arguments.len() {
+            return Err(Error::CannotParseInstruction(format!(
+                "\"{name}\" takes {} argument(s), but {} were given",
+                self.parameters,
+                arguments.len()
+            )));
+        }
+
+        let mut body = Vec::with_capacity(self.body.len());
+        for line in &self.body {
+            let mut result = String::with_capacity(line.len());
+            let mut remainder = line.as_str();
+            while let Some((start, after)) = remainder.split_once('%') {
+                result.push_str(start);
+                if let Some(after) = after.strip_prefix('%') {
+                    result.push_str(&format!("..@{expansion}."));
+                    remainder = after;
+                    continue;
+                }
+
+                let digits = after
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(after.len());
+                remainder = &after[digits..];
+                match after[..digits].parse::<usize>() {
+                    Ok(0) => result.push_str(&arguments.len().to_string()),
+                    Ok(parameter) if parameter <= arguments.len() => {
+                        result.push_str(arguments[parameter - 1])
+                    }
+                    Ok(parameter) => {
+                        return Err(Error::CannotParseInstruction(format!(
+                            "\"{name}\" does not have parameter %{parameter}"
+                        )))
+                    }
+                    // Not a parameter, but e.g. the modulo operator.
+                    Err(_) => result.push('%'),
+                }
+            }
+            result.push_str(remainder);
+            body.push(result);
+        }
+        Ok(body)
+    }
+}
+
+/// Splits the comma-separated arguments of a multi-line macro. Commas within quotes, parentheses,
+/// or brackets do not separate arguments.
+pub(crate) fn split_top_level(text: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                arguments.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    if !text.trim().is_empty() {
+        arguments.push(text[start..].trim());
+    }
+    arguments
+}
+
+/// Splits the parenthesised arguments of a macro from the text which follows them (e.g.
+/// `(eax, [ebx+4]) ; comment`). Commas within nested parentheses or brackets do not separate
+/// arguments. Returns `None` if `text` does not begin with arguments.
+fn split_arguments(text: &str) -> Option<(Vec<&str>, &str)> {
+    let text = text.trim_start().strip_prefix('(')?;
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' if depth == 0 => {
+                if !text[start..i].trim().is_empty() || !arguments.is_empty() {
+                    arguments.push(text[start..i].trim());
+                }
+                return Some((arguments, &text[i + 1..]));
+            }
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Replaces each parameter within the body of a macro with its argument.
+fn substitute_parameters(
+    name: &str,
+    define: &Define,
+    parameters: &[String],
+    arguments: &[&str],
+) -> Result<String, Error> {
+    if parameters.len() != arguments.len() {
+        return Err(Error::CannotParseInstruction(format!(
+            "\"{name}\" takes {} argument(s), but {} were given",
+            parameters.len(),
+            arguments.len()
+        )));
+    }
+
+    let mut result = String::with_capacity(define.body.len());
+    let mut remainder = define.body.as_str();
+    while let Some(c) = remainder.chars().next() {
+        let length = remainder
+            .find(|c| !is_symbol_char(c))
+            .unwrap_or(remainder.len())
+            .max(c.len_utf8());
+        let (word, after) = remainder.split_at(length);
+        remainder = after;
+        match parameters.iter().position(|parameter| parameter == word) {
+            Some(index) if is_symbol_start(c) => result.push_str(arguments[index]),
+            _ => result.push_str(word),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Joins the lines which were output by the preprocessor.
+    fn join(lines: Vec<Line>) -> Vec<String> {
+        lines.into_iter().map(|(_, line)| line).collect()
+    }
+
+    #[test]
+    fn preprocessor_define() {
+        let mut preprocessor = Preprocessor::default();
+        let output = preprocessor
+            .process(
+                "%define SIZE 4\n\
+                 %define DOUBLE(x) ((x)*2)\n\
+                 %define ADD(a, b) lea a, [a+b]\n\
+                 mov eax, SIZE ; SIZE\n\
+                 mov eax, DOUBLE(SIZE) + SIZES + 0xSIZE\n\
+                 ADD(eax, ecx)\n\
+                 db 'SIZE', DOUBLE\n\
+                 %undef SIZE\n\
+                 mov eax, SIZE",
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            [
+                (3, "mov eax, 4 ; 4".into()),
+                (4, "mov eax, ((4)*2) + SIZES + 0xSIZE".into()),
+                (5, "lea eax, [eax+ecx]".into()),
+                (6, "db 'SIZE', DOUBLE".into()),
+                (8, "mov eax, SIZE".into()),
+            ]
+        );
+
+        let mut preprocessor = Preprocessor::default();
+        assert_eq!(
+            join(
+                preprocessor
+                    .process("%define A B + 1\n%define B A\nA")
+                    .unwrap()
+            ),
+            ["A + 1"]
+        );
+
+        let mut preprocessor = Preprocessor::default();
+        assert!(preprocessor.process("%define F(a) a\nF(1, 2)").is_err());
+        assert!(preprocessor.process("%define 1 2").is_err());
+        assert!(preprocessor.process("%define F(a b").is_err());
+        assert!(preprocessor.process("%unknown").is_err());
+    }
+
+    #[test]
+    fn preprocessor_pragma() {
+        let mut preprocessor = Preprocessor::default();
+        let output = preprocessor
+            .process(
+                "%define SIZE 4\n\
+                 %pragma peanut assert eax == SIZE ; a comment\n\
+                 %pragma nasm warning\n\
+                 %define SEPARATOR ';' ; a comment\n\
+                 db SEPARATOR\n\
+                 %PRAGMA Peanut assert ecx != 0",
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            [
+                (1, "%pragma peanut assert eax == 4".into()),
+                (4, "db ';'".into()),
+                (5, "%PRAGMA Peanut assert ecx != 0".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn preprocessor_macro() {
+        let mut preprocessor = Preprocessor::default();
+        let output = preprocessor
+            .process(
+                "%define ONE 1\n\
+                 %macro COUNT_DOWN 2\n\
+                 %%top: sub %1, ONE ; %0 arguments\n\
+                 jne %%top\n\
+                 mov %2, %1\n\
+                 %endmacro\n\
+                 %macro CLEAR 1\n\
+                 and %1, 10 % 3\n\
+                 %endmacro\n\
+                 %macro CLEAR_BOTH 2\n\
+                 CLEAR %1\n\
+                 CLEAR %2\n\
+                 %endmacro\n\
+                 start: COUNT_DOWN eax, [ebx+4]\n\
+                 COUNT_DOWN ecx, edx ; a comment\n\
+                 CLEAR_BOTH eax, ecx",
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            [
+                (13, "start:".into()),
+                (13, "..@1.top: sub eax, 1 ; 2 arguments".into()),
+                (13, "jne ..@1.top".into()),
+                (13, "mov [ebx+4], eax".into()),
+                (14, "..@2.top: sub ecx, 1 ; 2 arguments".into()),
+                (14, "jne ..@2.top".into()),
+                (14, "mov edx, ecx".into()),
+                (15, "and eax, 10 % 3".into()),
+                (15, "and ecx, 10 % 3".into()),
+            ]
+        );
+
+        let mut preprocessor = Preprocessor::default();
+        assert!(preprocessor
+            .process("%macro M 1\nnop\n%endmacro\nM")
+            .is_err());
+        assert!(preprocessor
+            .process("%macro M 0\n%2\n%endmacro\nM")
+            .is_err());
+        assert!(preprocessor.process("%macro M 1\nnop").is_err());
+        assert!(preprocessor.process("%macro M\n%endmacro").is_err());
+        assert!(preprocessor.process("%endmacro").is_err());
+        assert!(preprocessor
+            .process("%macro M 0\n%macro N 0\n%endmacro\n%endmacro")
+            .is_err());
+        assert!(preprocessor.process("%macro R 0\nR\n%endmacro\nR").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::{cmp::Reverse, fmt::Write};
+
+use crate::{instruction::canonical_mnemonic, machine::StepReport, program::Program};
+
+/// How often an instruction was executed, and the cycles which it took in total.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sample {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+/// Where the cycles of a run were spent, which a [`Machine`](crate::machine::Machine) collects
+/// once it is enabled with [`Machine::collect_profile`](crate::machine::Machine::collect_profile).
+///
+/// Along with the cycles taken by each instruction, the cycles are attributed to the stack of
+/// functions which was active, as given by the targets of the `CALL` instructions which have not
+/// yet returned. The cycles are those estimated for each instruction (see
+/// [`Cpu::cycles`](crate::cpu::Cpu::cycles)).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The samples of the instructions which have been executed, by address.
+    samples: BTreeMap<u32, Sample>,
+    /// The cycles spent in each stack of functions, which are given by the addresses of their
+    /// first instructions, from the outermost to the innermost.
+    stacks: BTreeMap<Vec<u32>, u64>,
+    /// The functions which are active. The outermost is the function which the first instruction
+    /// profiled belongs to, and is never returned from.
+    stack: Vec<u32>,
+}
+
+impl Profile {
+    /// Counts an instruction which was executed at `address`, and took `cycles`, after which the
+    /// next instruction is at `next`.
+    pub(crate) fn record(&mut self, report: &StepReport, address: u32, next: u32, cycles: u64) {
+        let Some(instruction) = &report.instruction else {
+            return;
+        };
+        let sample = self.samples.entry(address).or_default();
+        sample.count += 1;
+        sample.cycles += cycles;
+        if self.stack.is_empty() {
+            self.stack.push(address);
+        }
+        *self.stacks.entry(self.stack.clone()).or_default() += cycles;
+
+        if report.stop_reason.is_some() {
+            return;
+        }
+        match canonical_mnemonic(&instruction.mnemonic).as_str() {
+            "CALL" => self.stack.push(next),
+            "RET" | "RETF" if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            _ => (),
+        }
+    }
+
+    /// The sample of the instruction at `address`.
+    pub fn sample(&self, address: u32) -> Sample {
+        self.samples.get(&address).copied().unwrap_or_default()
+    }
+
+    /// The total number of cycles which have been profiled.
+    pub fn cycles(&self) -> u64 {
+        self.samples.values().map(|sample| sample.cycles).sum()
+    }
+
+    /// The instructions which have been executed, from those which took the most cycles to those
+    /// which took the fewest, with ties in order of address.
+    pub fn hotspots(&self) -> Vec<(u32, Sample)> {
+        let mut hotspots: Vec<_> = self
+            .samples
+            .iter()
+            .map(|(address, sample)| (*address, *sample))
+            .collect();
+        hotspots.sort_by_key(|(_, sample)| Reverse(sample.cycles));
+        hotspots
+    }
+
+    /// A table of (at most `limit`) hotspots, which locates each by the labels of `program` (see
+    /// [`Program::locate`]). For example:
+    ///
+    /// ```text
+    ///   cycles       %  count  address   location
+    ///        6   20.0%      3  00000001  l
+    ///        6   20.0%      3  00000002  l+0x1
+    /// ```
+    pub fn report(&self, program: &Program, limit: usize) -> String {
+        let total = self.cycles();
+        let mut report = format!(
+            "{:>8}  {:>6}  {:>5}  {:<8}  location\n",
+            "cycles", "%", "count", "address"
+        );
+        for (address, sample) in self.hotspots().into_iter().take(limit) {
+            let share = sample.cycles as f64 * 100.0 / total as f64;
+            let _ = writeln!(
+                report,
+                "{:>8}  {share:>5.1}%  {:>5}  {address:08x}  {}",
+                sample.cycles,
+                sample.count,
+                program.locate(address)
+            );
+        }
+        report
+    }
+
+    /// The cycles spent in each stack of functions, in the folded format which `flamegraph.pl` and
+    /// `inferno` take: a line per stack, with its functions (located by the labels of `program`)
+    /// separated by `;`, followed by the cycles spent in it. For example:
+    ///
+    /// ```text
+    /// main 18
+    /// main;f 12
+    /// ```
+    pub fn folded(&self, program: &Program) -> String {
+        let mut folded = String::new();
+        for (stack, cycles) in &self.stacks {
+            let functions: Vec<_> = stack
+                .iter()
+                .map(|address| program.locate(*address))
+                .collect();
+            let _ = writeln!(folded, "{} {cycles}", functions.join(";"));
+        }
+        folded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{tests::load, StopReason};
+
+    #[test]
+    fn profile() {
+        let source =
+            "mov [0x20], ecx\nl: sub ecx, 1\ncall f\njmp l\nf: sub eax, 1\nsub eax, 1\nret";
+        let mut machine = load(source);
+        machine.cpu_mut().set_cycles_per_instruction(2);
+        assert_eq!(machine.profile(), None);
+        machine.collect_profile(true);
+        machine.set_instruction_limit(Some(15));
+        assert_eq!(machine.run().unwrap(), StopReason::InstructionLimit);
+
+        let profile = machine.profile().unwrap();
+        let program = machine.program();
+        assert_eq!(profile.cycles(), 30);
+        assert_eq!(
+            profile.sample(0x1016),
+            Sample {
+                count: 2,
+                cycles: 4
+            }
+        );
+        assert_eq!(profile.hotspots()[0].0, 0x1006);
+        assert_eq!(program.locate(0x101b), "f+0x5");
+        assert_eq!(program.locate(0), "0x00000000");
+        assert_eq!(
+            profile.report(program, 3),
+            "  cycles       %  count  address   location\n       \
+             6   20.0%      3  00001006  l\n       \
+             6   20.0%      3  0000100c  l+0x6\n       \
+             4   13.3%      2  00001011  l+0xb\n"
+        );
+        // The first instruction is outside of any function, so it is located by its address.
+        assert_eq!(profile.folded(program), "0x00001000 18\n0x00001000;f 12\n");
+    }
+}
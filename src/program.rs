@@ -0,0 +1,1696 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::ops::Range;
+
+use crate::{
+    assertion::Assertion,
+    cpu::Cpu,
+    diagnostic::{in_module, on_line, Diagnostic},
+    error::{Error, Warning},
+    expression::{self, substitute_location, Location},
+    instruction::{
+        lookup_instructions_by_mnemonic, AttStr, Immediate, Instruction, MasmStr, NasmStr, Operand,
+        OperandType, RepeatPrefix, Size, Syntax,
+    },
+    parser::{self, DataItem, Statement, StatementKind},
+    preprocessor::{split_top_level, Preprocessor},
+    register::Register,
+    traits::AsSigned,
+};
+
+/// The symbols (e.g. labels) defined by a program, and the values that they stand for. Symbol
+/// names are case-sensitive, as they are in NASM.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable(BTreeMap<String, u32>);
+
+impl SymbolTable {
+    /// Defines a symbol with the given value. Returns an `Err` if the name is not a valid symbol
+    /// name, or if the symbol has already been defined.
+    pub fn define(&mut self, name: &str, value: u32) -> Result<(), Error> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(is_symbol_start) && chars.all(is_symbol_char);
+        if !valid {
+            return Err(Error::InvalidSymbol(format!(
+                "\"{name}\" is not a valid symbol name"
+            )));
+        }
+
+        if Register::try_from(&NasmStr(name)).is_ok() || Size::try_from(&NasmStr(name)).is_ok() {
+            return Err(Error::InvalidSymbol(format!(
+                "\"{name}\" is a reserved word and cannot be used as a symbol name"
+            )));
+        }
+
+        if self.0.contains_key(name) {
+            return Err(Error::InvalidSymbol(format!(
+                "\"{name}\" has already been defined"
+            )));
+        }
+
+        self.0.insert(name.into(), value);
+        Ok(())
+    }
+
+    /// Gets the value of a symbol, if it has been defined.
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.0.get(name).copied()
+    }
+
+    /// The symbol with the highest value in `range` which is no higher than `value`, along with
+    /// its value. Of several symbols with the same value, the first in alphabetical order is
+    /// chosen, such that the result does not depend on the order in which they were defined.
+    pub fn nearest(&self, value: u32, range: Range<u32>) -> Option<(&str, u32)> {
+        self.0
+            .iter()
+            .filter(|(_, symbol)| range.contains(symbol) && **symbol <= value)
+            .map(|(name, symbol)| (name.as_str(), *symbol))
+            .max_by(|(lhs_name, lhs), (rhs_name, rhs)| lhs.cmp(rhs).then(rhs_name.cmp(lhs_name)))
+    }
+
+    /// Replaces each defined symbol which appears as a word in `text` with its value, such that
+    /// it can be parsed like any other immediate or displacement. Words which are not defined
+    /// symbols (e.g. registers and size directives) are left untouched, as are numbers.
+    pub fn substitute(&self, text: &str) -> String {
+        // Values are written in decimal, as hexadecimal values ending in `b` or `d` (e.g. `0xd`)
+        // would be mistaken for binary or decimal literals with a suffix.
+        replace_words(text, |word| self.get(word).map(|value| value.to_string()))
+    }
+}
+
+/// Replaces each word in `text` which could be a symbol with the result of `replace`, if it returns
+/// `Some`. Whole words are always considered, so that the end of a number (e.g. `0xff`) or of a
+/// longer symbol is never mistaken for a symbol in its own right.
+fn replace_words<F>(text: &str, mut replace: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !is_symbol_char(c) {
+            result.push(c);
+            continue;
+        }
+
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            if !is_symbol_char(c) {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+
+        let word = &text[start..end];
+        match replace(word) {
+            Some(replacement) if is_symbol_start(c) => result.push_str(&replacement),
+            _ => result.push_str(word),
+        }
+    }
+    result
+}
+
+/// Whether the name is that of a local label (e.g. `.loop`), which belongs to the most recently
+/// defined global label. Names beginning with `..` (e.g. the labels within macros, `..@1.top`)
+/// are not local.
+fn is_local_label(name: &str) -> bool {
+    name.starts_with('.') && !name.starts_with("..")
+}
+
+/// Replaces each local label in `text` with its full name, which is the name of the global label
+/// that it belongs to followed by the local label (e.g. `.loop` becomes `strlen.loop`). Local
+/// labels are left as they are if there is no global label.
+fn qualify_local_labels(text: &str, scope: Option<&str>) -> String {
+    let Some(scope) = scope else {
+        return text.into();
+    };
+    replace_words(text, |word| {
+        is_local_label(word).then(|| format!("{scope}{word}"))
+    })
+}
+
+/// Whether a symbol name may begin with the character. As in NASM, this excludes digits, such that
+/// symbols cannot be confused with numbers.
+pub(crate) fn is_symbol_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '_' | '.' | '?')
+}
+
+/// Whether a symbol name may contain the character after its first character.
+pub(crate) fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '?' | '$' | '#' | '@' | '~')
+}
+
+/// The sections which a program may be divided into with the `section` (or `segment`) directive.
+/// Statements belong to `.text` until a section is given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionName {
+    /// The instructions.
+    Text,
+    /// Initialised data, which is defined with `db`, `dw`, and `dd`.
+    Data,
+    /// Uninitialised data, which is reserved with `resb`, `resw`, `resd`, and `resq`, and is
+    /// zeroed when the program is loaded.
+    Bss,
+}
+
+impl TryFrom<&NasmStr<'_>> for SectionName {
+    type Error = Error;
+
+    fn try_from(value: &NasmStr<'_>) -> Result<Self, Self::Error> {
+        match value.0 {
+            ".text" => Ok(Self::Text),
+            ".data" => Ok(Self::Data),
+            ".bss" => Ok(Self::Bss),
+            name => Err(Error::CannotParseInstruction(format!(
+                "\"{name}\" is not a supported section (expected .text, .data, or .bss)"
+            ))),
+        }
+    }
+}
+
+/// The base address of each section, i.e. where it is loaded into memory. These determine the
+/// values of the labels defined within each section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub text: u32,
+    pub data: u32,
+    pub bss: u32,
+}
+
+impl Default for Layout {
+    /// `.text` begins after the interrupt vector table, which occupies the first 0x400 bytes.
+    fn default() -> Self {
+        Self {
+            text: 0x1000,
+            data: 0x1_0000,
+            bss: 0x2_0000,
+        }
+    }
+}
+
+/// A section of a program, and the bytes which are loaded into memory at its base address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub base: u32,
+    pub image: Vec<u8>,
+    /// The number of bytes which the section occupies. This is more than the length of its image
+    /// if the section is uninitialised (as `.bss` is), in which case the rest is zeroed when the
+    /// program is loaded.
+    pub size: u32,
+}
+
+/// A program written in assembly, which has been assembled into its sections. The image of `.text`
+/// is the machine code of its instructions.
+pub struct Program {
+    text: Section,
+    data: Section,
+    bss: Section,
+    /// The line which each instruction was written on, indexed by the address of the instruction.
+    lines: BTreeMap<u32, SourceLine>,
+    /// The assertions which are checked before the instruction at each address is executed, along
+    /// with the lines which they were written on.
+    assertions: BTreeMap<u32, Vec<(SourceLine, Assertion)>>,
+    symbols: SymbolTable,
+    warnings: Vec<Diagnostic>,
+}
+
+/// The line of source which an instruction was written on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLine {
+    /// The index of the module (see [`Program::assemble_modules`]).
+    pub module: usize,
+    /// The (1-based) number of the line, as in a [`Diagnostic`].
+    pub line: usize,
+}
+
+impl Program {
+    /// Assembles a program, with its sections placed at the addresses given by `layout`.
+    ///
+    /// The source is first preprocessed, such that any macros are expanded, and then parsed into
+    /// statements (see `parser::parse`). Assembly is then done in two passes. The first collects
+    /// the label definitions (written as `name:`, optionally followed by a statement on the same
+    /// line), and the second assembles each statement, with any labels used as operands replaced
+    /// by their addresses. This allows labels to be used before they are defined (e.g. to jump
+    /// forwards). Comments begin with `;`.
+    ///
+    /// Instructions are encoded into the image of `.text`, so the address of a label depends on
+    /// the lengths of the instructions before it, which may in turn depend on the values of labels
+    /// (e.g. whether a displacement fits in a byte). The first pass is therefore repeated, with
+    /// the lengths of instructions found from the labels of the pass before, until the labels no
+    /// longer change.
+    ///
+    /// Constants are defined with `name equ expression`, and are evaluated during the first pass.
+    /// Their expressions may therefore only use symbols which have already been defined.
+    ///
+    /// A label beginning with a single `.` (e.g. `.loop`) is local to the most recently defined
+    /// global label (e.g. `strlen`), and is defined with both names joined together (e.g.
+    /// `strlen.loop`). It can be referred to by its full name anywhere, or by just its local name
+    /// until the next global label. Constants do not change which global label is current.
+    ///
+    /// The base address of `.text` may also be given by the program with `org address`, which
+    /// takes precedence over `layout`. As in NASM, this applies to the whole program, regardless
+    /// of where it is written.
+    ///
+    /// An instruction or data definition may be repeated with a `TIMES` prefix (e.g.
+    /// `times 510-($-$$) db 0`), where `$` is the address of the current line, and `$$` is the
+    /// address of the start of the current section. The count is also evaluated during the first
+    /// pass.
+    ///
+    /// Instructions are written in the given syntax. Everything else (e.g. labels and directives)
+    /// is written as in NASM, except that comments in AT&T syntax may also begin with `#`.
+    ///
+    /// An assertion about the state of the processor may be written before an instruction with
+    /// `%pragma peanut assert condition` (see [`Assertion`]), which is checked whenever the
+    /// instruction is about to be executed.
+    pub fn assemble(source: &str, layout: Layout, syntax: Syntax) -> Result<Self, Error> {
+        Self::assemble_modules(&[source], layout, syntax)
+    }
+
+    /// Assembles a program from several modules (e.g. one for each source file), each of which is
+    /// assembled as by [`Program::assemble`]. The sections of each module are placed directly
+    /// after those of the module before it, such that the program begins with the first
+    /// instruction of the first module.
+    ///
+    /// The symbols defined by a module are private to it, unless it declares them with
+    /// `global name`. Other modules may then use them by declaring them with `extern name`. These
+    /// are resolved once the first pass has been done for every module, such that any module may
+    /// use the symbols of any other. Errors and warnings refer to the module which caused them by
+    /// its index within `sources`.
+    pub fn assemble_modules(
+        sources: &[&str],
+        layout: Layout,
+        syntax: Syntax,
+    ) -> Result<Self, Error> {
+        let originals: Vec<Vec<_>> = sources
+            .iter()
+            .map(|source| source.lines().collect())
+            .collect();
+        let preprocessed = sources
+            .iter()
+            .enumerate()
+            .map(|(module, source)| {
+                Preprocessor::default()
+                    .process(source)
+                    .map_err(|error| in_module(module, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let statements = preprocessed
+            .iter()
+            .zip(&originals)
+            .enumerate()
+            .map(|(module, (source, original))| {
+                parser::parse(source, original, syntax).map_err(|error| in_module(module, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut origin = None;
+        for (module, (statements, original)) in statements.iter().zip(&originals).enumerate() {
+            find_origin(original, statements, &mut origin)
+                .map_err(|error| in_module(module, error))?;
+        }
+        let layout = Layout {
+            text: origin.unwrap_or(layout.text),
+            ..layout
+        };
+
+        let mut estimates = vec![SymbolTable::default(); sources.len()];
+        let mut passes = 0;
+        let (modules, base) = loop {
+            let mut modules = Vec::with_capacity(sources.len());
+            let mut base = layout;
+            for (index, ((statements, original), estimates)) in statements
+                .iter()
+                .zip(&originals)
+                .zip(&estimates)
+                .enumerate()
+            {
+                let module = Module::define_symbols(statements, original, base, syntax, estimates)
+                    .map_err(|error| in_module(index, error))?;
+                base = module.end();
+                modules.push(module);
+            }
+
+            link(&mut modules, &originals)?;
+
+            if modules
+                .iter()
+                .zip(&estimates)
+                .all(|(module, estimates)| module.symbols == *estimates)
+            {
+                break (modules, base);
+            }
+            passes += 1;
+            if passes == MAX_PASSES {
+                return Err(Error::CannotParseInstruction(format!(
+                    "the addresses of labels did not settle after {MAX_PASSES} passes, as the \
+                     lengths of instructions depend on them"
+                )));
+            }
+            estimates = modules
+                .iter()
+                .map(|module| module.symbols.clone())
+                .collect();
+        };
+
+        let mut program = Self {
+            text: Section {
+                base: layout.text,
+                image: Vec::new(),
+                size: 0,
+            },
+            data: Section {
+                base: layout.data,
+                image: Vec::new(),
+                size: 0,
+            },
+            bss: Section {
+                base: layout.bss,
+                image: Vec::new(),
+                size: base.bss.wrapping_sub(layout.bss),
+            },
+            lines: BTreeMap::new(),
+            assertions: BTreeMap::new(),
+            symbols: SymbolTable::default(),
+            warnings: Vec::new(),
+        };
+        for (index, (module, original)) in modules.iter().zip(&originals).enumerate() {
+            module
+                .assemble(index, original, syntax, &mut program)
+                .map_err(|error| in_module(index, error))?;
+        }
+        for section in [&mut program.text, &mut program.data] {
+            section.size = section.image.len() as u32;
+        }
+        program.symbols = modules
+            .into_iter()
+            .next()
+            .map(|module| module.symbols)
+            .unwrap_or_default();
+        Ok(program)
+    }
+
+    /// The symbols which can be used by the program's first module, i.e. those that it defines and
+    /// those that it declares as `extern`.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// The warnings about the source of the program, which did not prevent it from being
+    /// assembled.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn section(&self, name: SectionName) -> &Section {
+        match name {
+            SectionName::Text => &self.text,
+            SectionName::Data => &self.data,
+            SectionName::Bss => &self.bss,
+        }
+    }
+
+    /// The line of source which the instruction at `address` was written on, or `None` if the
+    /// address is not that of an instruction within the program.
+    pub fn source_line(&self, address: u32) -> Option<SourceLine> {
+        self.lines.get(&address).copied()
+    }
+
+    /// The addresses of the program's instructions, along with the lines which they were written
+    /// on, in the order of their addresses.
+    pub fn source_lines(&self) -> impl Iterator<Item = (u32, SourceLine)> + '_ {
+        self.lines.iter().map(|(&address, &line)| (address, line))
+    }
+
+    /// Describes the address of an instruction by the label it is at, or the nearest label before
+    /// it within `.text` and the offset from it (e.g. `f+0x2`), or just the address if there is no
+    /// such label.
+    pub fn locate(&self, address: u32) -> String {
+        let text = self.text.base..self.text.base.wrapping_add(self.text.size);
+        match self.symbols.nearest(address, text) {
+            Some((label, value)) if value == address => label.to_string(),
+            Some((label, value)) => format!("{label}+{:#x}", address - value),
+            None => format!("{address:#010x}"),
+        }
+    }
+
+    /// The assertions which are checked before the instruction at `address` is executed, along
+    /// with the lines which they were written on.
+    pub fn assertions(&self, address: u32) -> &[(SourceLine, Assertion)] {
+        self.assertions.get(&address).map_or(&[], Vec::as_slice)
+    }
+
+    /// Checks the assertions about the instruction at `address` (see [`Program::assertions`]),
+    /// returning an `Error::AssertionFailed` for the first which does not hold.
+    pub(crate) fn check_assertions(&self, address: u32, cpu: &Cpu) -> Result<(), Error> {
+        for (line, assertion) in self.assertions(address) {
+            assertion.check(cpu).map_err(|failure| {
+                let module = match line.module {
+                    0 => String::new(),
+                    module => format!(" of module {module}"),
+                };
+                Error::AssertionFailed(format!("{failure}, on line {}{module}", line.line))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The number of instructions in the program.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Loads the image of each section into memory at its base address (zeroing the rest of the
+    /// section, e.g. the whole of `.bss`), and sets EIP to the start of `.text`, such that the
+    /// program is ready to be run. Returns an `Err` if a section does not fit in memory.
+    pub fn load(&self, cpu: &mut Cpu) -> Result<(), Error> {
+        for section in [&self.text, &self.data, &self.bss] {
+            cpu.memory.load(section.base, &section.image)?;
+            let image_end = section.base.wrapping_add(section.image.len() as u32);
+            cpu.memory.zero(
+                image_end,
+                section.size.saturating_sub(section.image.len() as u32),
+            )?;
+        }
+        cpu.registers.set_eip(self.text.base);
+        Ok(())
+    }
+
+    /// Runs the program, starting with the instruction at EIP, until EIP is no longer within
+    /// `.text` (e.g. after the last instruction has been executed), or until `HLT` is executed.
+    /// See `Program::step`.
+    pub fn run(&self, cpu: &mut Cpu) {
+        while !cpu.halted && self.step(cpu) {}
+    }
+
+    /// Whether the linear address `address` is within `.text`.
+    pub(crate) fn in_text(&self, address: u32) -> bool {
+        address.wrapping_sub(self.text.base) < self.text.size
+    }
+
+    /// Executes the instruction at CS:EIP, which is fetched from memory (see `Cpu::step`),
+    /// returning `false` without doing anything if it is not within `.text`.
+    fn step(&self, cpu: &mut Cpu) -> bool {
+        if !self.in_text(cpu.instruction_address()) {
+            return false;
+        }
+        cpu.step();
+        true
+    }
+}
+
+/// The number of times which the first pass of assembly is done before giving up on the addresses
+/// of labels settling (see [`Program::assemble`]).
+const MAX_PASSES: usize = 16;
+
+/// A module of a program (e.g. a single source file), whose symbols have been defined by the first
+/// pass of assembly, but whose instructions and data are yet to be assembled.
+struct Module<'a> {
+    /// The base address of each of the module's sections.
+    base: Layout,
+    text_size: u32,
+    data_size: u32,
+    bss_size: u32,
+    symbols: SymbolTable,
+    /// The instructions and data to be assembled by the second pass, along with the global label
+    /// which local labels belong to at each, and the section which each is in.
+    pending: Vec<(Option<&'a str>, SectionName, &'a Statement<'a>)>,
+    /// The symbols declared as `global`, along with the index of the line which declared them.
+    globals: Vec<(&'a str, usize)>,
+    /// The symbols declared as `extern`, along with the index of the line which declared them.
+    externs: Vec<(&'a str, usize)>,
+}
+
+impl<'a> Module<'a> {
+    /// Does the first pass of assembly, which defines the symbols of the module, given the base
+    /// address of each of its sections. The length of each instruction is found by encoding it
+    /// with the symbols defined so far, and with `estimates` (the symbols defined by the pass
+    /// before) for the rest. Errors refer to the lines of the `original` source.
+    fn define_symbols(
+        statements: &'a [Statement<'a>],
+        original: &[&str],
+        base: Layout,
+        syntax: Syntax,
+        estimates: &SymbolTable,
+    ) -> Result<Self, Error> {
+        let mut module = Self {
+            base,
+            text_size: 0,
+            data_size: 0,
+            bss_size: 0,
+            symbols: SymbolTable::default(),
+            pending: Vec::new(),
+            globals: Vec::new(),
+            externs: Vec::new(),
+        };
+        let mut estimates = estimates.clone();
+        let mut section = SectionName::Text;
+        // The global label which local labels currently belong to.
+        let mut scope: Option<&str> = None;
+        for statement in statements {
+            let index = statement.index;
+            let location = match section {
+                SectionName::Text => Location {
+                    here: base.text.wrapping_add(module.text_size),
+                    start: base.text,
+                },
+                SectionName::Data => Location {
+                    here: base.data.wrapping_add(module.data_size),
+                    start: base.data,
+                },
+                SectionName::Bss => Location {
+                    here: base.bss.wrapping_add(module.bss_size),
+                    start: base.bss,
+                },
+            };
+
+            let count = match statement.times {
+                Some(times) => evaluate_times(times, &module.symbols, scope, location)
+                    .map_err(|error| on_line(original, index, times, error))?,
+                None => 1,
+            };
+
+            let expected_section = match &statement.kind {
+                StatementKind::Label(label) => {
+                    if !label.starts_with('.') {
+                        scope = Some(label);
+                    }
+                    let qualified_name = qualify_local_labels(label, scope);
+                    module
+                        .symbols
+                        .define(&qualified_name, location.here)
+                        .map_err(|error| on_line(original, index, label, error))?;
+                    estimates.0.insert(qualified_name, location.here);
+                    continue;
+                }
+                StatementKind::Constant { name, expression } => {
+                    let qualified_name = qualify_local_labels(name, scope);
+                    let value = qualify_local_labels(expression, scope);
+                    let value = substitute_location(&module.symbols.substitute(&value), location);
+                    let value = Immediate::try_from(&NasmStr(&value))
+                        .map_err(|error| on_line(original, index, expression, error))?;
+                    module
+                        .symbols
+                        .define(&qualified_name, value.0)
+                        .map_err(|error| on_line(original, index, name, error))?;
+                    estimates.0.insert(qualified_name, value.0);
+                    continue;
+                }
+                StatementKind::Section(name) => {
+                    section = *name;
+                    continue;
+                }
+                StatementKind::Global(names) => {
+                    module
+                        .globals
+                        .extend(names.iter().map(|name| (*name, index)));
+                    continue;
+                }
+                StatementKind::Extern(names) => {
+                    for name in names {
+                        // Declaring the same symbol more than once is harmless.
+                        if !module.externs.iter().any(|(other, _)| other == name) {
+                            module.externs.push((name, index));
+                        }
+                    }
+                    continue;
+                }
+                // The origin has already been found.
+                StatementKind::Origin(_) => continue,
+                StatementKind::Reservation { .. } => SectionName::Bss,
+                // Data may also be written amongst the instructions (e.g. the signature at the end
+                // of a boot sector).
+                StatementKind::Data { .. } if section == SectionName::Text => SectionName::Text,
+                StatementKind::Data { .. } => SectionName::Data,
+                StatementKind::Instruction(_) | StatementKind::Assertion(_) => SectionName::Text,
+            };
+
+            if section != expected_section {
+                return Err(on_line(
+                    original,
+                    index,
+                    statement.text,
+                    Error::CannotParseInstruction(format!(
+                        "\"{}\" cannot be used in the {section:?} section, only in the \
+                         {expected_section:?} section",
+                        statement.text
+                    )),
+                ));
+            }
+
+            match &statement.kind {
+                StatementKind::Reservation {
+                    size,
+                    count: reserved,
+                } => {
+                    let reserved = qualify_local_labels(reserved, scope);
+                    let reserved =
+                        substitute_location(&module.symbols.substitute(&reserved), location);
+                    module.bss_size = reserve(&reserved, *size, count, module.bss_size)
+                        .map_err(|error| on_line(original, index, statement.text, error))?;
+                    continue;
+                }
+                StatementKind::Data { size, items } => {
+                    let item_size = items
+                        .iter()
+                        .map(|item| match item {
+                            DataItem::String(string) => string.len().next_multiple_of(*size),
+                            DataItem::Expression(_) => *size,
+                        })
+                        .sum::<usize>() as u32;
+                    let section_size = match section {
+                        SectionName::Text => &mut module.text_size,
+                        _ => &mut module.data_size,
+                    };
+                    *section_size = section_size.wrapping_add(item_size.wrapping_mul(count));
+                }
+                // An assertion applies to the instruction which follows it, so takes no space.
+                StatementKind::Assertion(_) => {}
+                StatementKind::Instruction(text) => {
+                    for _ in 0..count {
+                        let location = Location {
+                            here: base.text.wrapping_add(module.text_size),
+                            start: base.text,
+                        };
+                        module.text_size = module.text_size.wrapping_add(instruction_length(
+                            text, syntax, scope, &estimates, location,
+                        ));
+                    }
+                }
+                _ => unreachable!("only instructions, data, and reservations take space"),
+            }
+
+            for _ in 0..count {
+                module.pending.push((scope, section, statement));
+            }
+        }
+        Ok(module)
+    }
+
+    /// The address of the end of each of the module's sections, which is where the sections of the
+    /// module after it begin.
+    fn end(&self) -> Layout {
+        Layout {
+            text: self.base.text.wrapping_add(self.text_size),
+            data: self.base.data.wrapping_add(self.data_size),
+            bss: self.base.bss.wrapping_add(self.bss_size),
+        }
+    }
+
+    /// Does the second pass of assembly, which adds the module (whose index is `module`) to the
+    /// `program` assembled from the modules before it: the machine code of its instructions and
+    /// its data are appended to the images of their sections, and its instructions, their lines,
+    /// its assertions, and its warnings are added to those of the program. Errors and warnings
+    /// refer to the lines of the `original` source.
+    fn assemble(
+        &self,
+        module: usize,
+        original: &[&str],
+        syntax: Syntax,
+        program: &mut Program,
+    ) -> Result<(), Error> {
+        let (text_start, data_start) = (program.text.image.len(), program.data.image.len());
+        let source_line = |index: usize| SourceLine {
+            module,
+            line: index + 1,
+        };
+        for &(scope, section, statement) in &self.pending {
+            let index = statement.index;
+            match &statement.kind {
+                StatementKind::Instruction(source) => {
+                    let location = Location {
+                        here: self
+                            .base
+                            .text
+                            .wrapping_add((program.text.image.len() - text_start) as u32),
+                        start: self.base.text,
+                    };
+                    let qualified = qualify_local_labels(source, scope);
+                    let qualified = substitute_location(&qualified, location);
+                    let mut instruction_warnings = Vec::new();
+                    match Instruction::parse(
+                        &qualified,
+                        syntax,
+                        &self.symbols,
+                        &mut instruction_warnings,
+                    ) {
+                        Ok(instruction) => {
+                            let encoded = instruction
+                                .encode(location.here)
+                                .map_err(|error| on_line(original, index, source, error))?
+                                .to_bytes();
+                            program.text.image.extend_from_slice(&encoded);
+                        }
+                        Err(error @ Error::NoMatchingInstruction(_)) => {
+                            // An unknown mnemonic (e.g. a typo) is pointed out by itself, whereas
+                            // operands which it does not take are pointed out with it.
+                            let (mnemonic, _) = split_mnemonic(source);
+                            let token = if lookup_instructions_by_mnemonic(mnemonic).is_empty() {
+                                mnemonic
+                            } else {
+                                source
+                            };
+                            return Err(on_line(original, index, token, error));
+                        }
+                        Err(error) => {
+                            let token = find_invalid_operand(source, syntax, &self.symbols);
+                            return Err(on_line(original, index, token, error));
+                        }
+                    }
+                    program.lines.insert(location.here, source_line(index));
+
+                    let line = original.get(index).copied().unwrap_or_default();
+                    for warning in instruction_warnings {
+                        let token = match &warning {
+                            Warning::IgnoredSizeDirective { size, .. } => size.to_string(),
+                            Warning::ImmediateOutOfRange { operand, .. } => {
+                                let mut operands = split_operands(source);
+                                if syntax == Syntax::Att {
+                                    operands.reverse();
+                                }
+                                operands
+                                    .get(*operand)
+                                    .copied()
+                                    .unwrap_or(source)
+                                    .to_string()
+                            }
+                        };
+                        let mut warning = Diagnostic::warning(index, line, &token, &warning);
+                        warning.module = module;
+                        // An instruction which is repeated by `TIMES` is only warned about once.
+                        if !program.warnings.contains(&warning) {
+                            program.warnings.push(warning);
+                        }
+                    }
+                }
+                StatementKind::Data { size, items } => {
+                    let (image, base, start) = match section {
+                        SectionName::Text => (&mut program.text.image, self.base.text, text_start),
+                        _ => (&mut program.data.image, self.base.data, data_start),
+                    };
+                    let location = Location {
+                        here: base.wrapping_add((image.len() - start) as u32),
+                        start: base,
+                    };
+                    let line = original.get(index).copied().unwrap_or_default();
+                    for (item_index, item) in items.iter().enumerate() {
+                        let token = match item {
+                            DataItem::String(string) | DataItem::Expression(string) => string,
+                        };
+                        let warning = assemble_data_item(
+                            image,
+                            *size,
+                            item_index,
+                            *item,
+                            scope,
+                            &self.symbols,
+                            location,
+                        )
+                        .map_err(|error| on_line(original, index, token, error))?;
+                        if let Some(warning) = warning {
+                            let mut warning = Diagnostic::warning(index, line, token, &warning);
+                            warning.module = module;
+                            if !program.warnings.contains(&warning) {
+                                program.warnings.push(warning);
+                            }
+                        }
+                    }
+                }
+                StatementKind::Assertion(condition) => {
+                    let location = Location {
+                        here: self
+                            .base
+                            .text
+                            .wrapping_add((program.text.image.len() - text_start) as u32),
+                        start: self.base.text,
+                    };
+                    let substituted = qualify_local_labels(condition, scope);
+                    let substituted =
+                        substitute_location(&self.symbols.substitute(&substituted), location);
+                    let assertion = Assertion::parse(condition, &substituted)
+                        .map_err(|error| on_line(original, index, condition, error))?;
+                    program
+                        .assertions
+                        .entry(location.here)
+                        .or_default()
+                        .push((source_line(index), assertion));
+                }
+                _ => unreachable!(
+                    "only instructions, data, and assertions are assembled in the second pass"
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolves the symbols which each module declares as `extern` to the values of the symbols which
+/// other modules declare as `global`, by defining them within the symbols of the module. Errors
+/// refer to the lines of the `originals`, the source of each module.
+fn link(modules: &mut [Module], originals: &[Vec<&str>]) -> Result<(), Error> {
+    let locate = |module: usize, line: usize, name: &str, error: Error| {
+        in_module(module, on_line(&originals[module], line, name, error))
+    };
+
+    // The value of each global symbol, along with the module which declared it.
+    let mut globals: BTreeMap<&str, (usize, u32)> = BTreeMap::new();
+    for (index, module) in modules.iter().enumerate() {
+        for &(name, line) in &module.globals {
+            let Some(value) = module.symbols.get(name) else {
+                return Err(locate(
+                    index,
+                    line,
+                    name,
+                    Error::InvalidSymbol(format!(
+                        "\"{name}\" is declared as global, but is not defined"
+                    )),
+                ));
+            };
+            match globals.insert(name, (index, value)) {
+                Some((other, _)) if other != index => {
+                    return Err(locate(
+                        index,
+                        line,
+                        name,
+                        Error::InvalidSymbol(format!(
+                            "\"{name}\" is declared as global by more than one module"
+                        )),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (index, module) in modules.iter_mut().enumerate() {
+        for &(name, line) in &module.externs {
+            let Some(&(_, value)) = globals.get(name) else {
+                return Err(locate(
+                    index,
+                    line,
+                    name,
+                    Error::InvalidSymbol(format!(
+                        "\"{name}\" is declared as extern, but no module declares it as global"
+                    )),
+                ));
+            };
+            module
+                .symbols
+                .define(name, value)
+                .map_err(|error| locate(index, line, name, error))?;
+        }
+    }
+    Ok(())
+}
+
+/// Assembles a program with the default layout.
+impl TryFrom<&NasmStr<'_>> for Program {
+    type Error = Error;
+
+    fn try_from(source: &NasmStr<'_>) -> Result<Self, Self::Error> {
+        Self::assemble(source.0, Layout::default(), Syntax::Nasm)
+    }
+}
+
+/// Assembles a program with the default layout.
+impl TryFrom<&AttStr<'_>> for Program {
+    type Error = Error;
+
+    fn try_from(source: &AttStr<'_>) -> Result<Self, Self::Error> {
+        Self::assemble(source.0, Layout::default(), Syntax::Att)
+    }
+}
+
+/// Assembles a program with the default layout.
+impl TryFrom<&MasmStr<'_>> for Program {
+    type Error = Error;
+
+    fn try_from(source: &MasmStr<'_>) -> Result<Self, Self::Error> {
+        Self::assemble(source.0, Layout::default(), Syntax::Masm)
+    }
+}
+
+/// Finds the origin of the program, as given by its `ORG` directive, if it has one. The address
+/// must be a constant expression without any symbols, as it is needed before any labels can be
+/// defined. As a program may only have one origin, `origin` holds any that has been found in the
+/// modules before this one. Errors refer to the lines of the `original` source.
+fn find_origin(
+    original: &[&str],
+    statements: &[Statement],
+    origin: &mut Option<u32>,
+) -> Result<(), Error> {
+    for statement in statements {
+        let StatementKind::Origin(address) = statement.kind else {
+            continue;
+        };
+
+        if origin.is_some() {
+            let keyword = statement.text.split_whitespace().next().unwrap_or_default();
+            return Err(on_line(
+                original,
+                statement.index,
+                keyword,
+                Error::CannotParseInstruction("ORG can only be used once".into()),
+            ));
+        }
+        *origin = Some(
+            expression::evaluate(address)
+                .map_err(|error| on_line(original, statement.index, address, error))?,
+        );
+    }
+    Ok(())
+}
+
+/// The number of bytes which an instruction is encoded in at `location`, with any symbols that it
+/// uses replaced by the values in `estimates`. An instruction which cannot be encoded (e.g. as it
+/// uses a symbol which has not been estimated yet) is taken to be empty, and is reported by the
+/// second pass if it still cannot be encoded once every symbol is known.
+fn instruction_length(
+    instruction: &str,
+    syntax: Syntax,
+    scope: Option<&str>,
+    estimates: &SymbolTable,
+    location: Location,
+) -> u32 {
+    let qualified = qualify_local_labels(instruction, scope);
+    let qualified = substitute_location(&qualified, location);
+    Instruction::parse(&qualified, syntax, estimates, &mut Vec::new())
+        .and_then(|instruction| instruction.encode(location.here))
+        .map_or(0, |encoded| encoded.len() as u32)
+}
+
+/// Evaluates the count of a `TIMES` prefix, which cannot be negative.
+fn evaluate_times(
+    times: &str,
+    symbols: &SymbolTable,
+    scope: Option<&str>,
+    location: Location,
+) -> Result<u32, Error> {
+    let count = qualify_local_labels(times, scope);
+    let count = substitute_location(&symbols.substitute(&count), location);
+    let count = expression::evaluate(&count)?;
+    if count.as_signed() < 0 {
+        return Err(Error::CannotParseInstruction(format!(
+            "the count of a TIMES prefix cannot be negative (was {})",
+            count.as_signed()
+        )));
+    }
+    Ok(count)
+}
+
+/// The size of `.bss` once `count` more reservations of `reserved` units, each of `size` bytes,
+/// have been made in it. Returns an `Err` if the number of units is negative, or if `.bss` would
+/// no longer fit in the address space.
+fn reserve(reserved: &str, size: u32, count: u32, bss_size: u32) -> Result<u32, Error> {
+    let reserved = expression::evaluate64(reserved)?;
+    if (reserved as i64) < 0 {
+        return Err(Error::CannotParseInstruction(format!(
+            "the number of units to reserve cannot be negative (was {})",
+            reserved as i64
+        )));
+    }
+    reserved
+        .checked_mul(size.into())
+        .and_then(|bytes| bytes.checked_mul(count.into()))
+        .and_then(|bytes| u32::try_from(bytes).ok())
+        .and_then(|bytes| bss_size.checked_add(bytes))
+        .ok_or_else(|| {
+            Error::CannotParseInstruction(format!(
+                "reserving {reserved} units of {size} bytes would not fit in the address space"
+            ))
+        })
+}
+
+/// Appends an item of a data definition, each unit of which is `size` bytes in little-endian
+/// format. A string is stored as its bytes, padded with zeros to a multiple of `size`. Anything
+/// else must be an immediate, which may use symbols (including local labels within `scope`). An
+/// immediate which does not fit in `size` bytes is truncated, and a warning about it (as the
+/// operand at `index`) is returned.
+fn assemble_data_item(
+    data: &mut Vec<u8>,
+    size: usize,
+    index: usize,
+    item: DataItem,
+    scope: Option<&str>,
+    symbols: &SymbolTable,
+    location: Location,
+) -> Result<Option<Warning>, Error> {
+    let item = match item {
+        DataItem::String(string) => {
+            data.extend_from_slice(string.as_bytes());
+            data.resize(data.len() + (size - string.len() % size) % size, 0);
+            return Ok(None);
+        }
+        DataItem::Expression(item) => item,
+    };
+
+    let item = qualify_local_labels(item, scope);
+    let item = substitute_location(&symbols.substitute(&item), location);
+    let invalid = || Error::CannotParseInstruction(format!("\"{item}\" is not a valid data item"));
+    // The operand is parsed first, such that the reason that it is invalid (e.g. a malformed
+    // number) is the same as it would be in an instruction.
+    let value = match OperandType::try_from(&NasmStr(&item))? {
+        OperandType::Immediate(_) => expression::evaluate64(&item).map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+
+    // Either the unsigned or the signed range of the size may be used, as in NASM.
+    let bits = size as u32 * 8;
+    let fits = bits == 64 || value >> bits == 0 || (value as i64) >> (bits - 1) == -1;
+    let warning = (!fits).then_some(Warning::ImmediateOutOfRange {
+        operand: index,
+        value: value as u32,
+        size: match size {
+            1 => Size::Byte,
+            2 => Size::Word,
+            _ => Size::Dword,
+        },
+        sign_extended: false,
+    });
+    data.extend_from_slice(&value.to_le_bytes()[..size]);
+    Ok(warning)
+}
+
+/// Finds the part of an instruction which could not be parsed, which is the first operand that is
+/// invalid on its own, or otherwise the whole instruction (e.g. if its operands are valid, but
+/// cannot be used together).
+fn find_invalid_operand<'a>(statement: &'a str, syntax: Syntax, symbols: &SymbolTable) -> &'a str {
+    split_operands(statement)
+        .into_iter()
+        .find(|operand| {
+            let substituted = symbols.substitute(operand);
+            match syntax {
+                Syntax::Nasm => Operand::try_from(&NasmStr(&substituted)).is_err(),
+                Syntax::Att => Operand::try_from(&AttStr(&substituted)).is_err(),
+                Syntax::Masm => Operand::try_from(&MasmStr(&substituted)).is_err(),
+            }
+        })
+        .unwrap_or(statement)
+}
+
+/// Splits the operands of an instruction, skipping its mnemonic and any prefixes before it. They
+/// are given in the order that they are written, so AT&T operands are in the reverse order to
+/// their NASM equivalents.
+fn split_operands(statement: &str) -> Vec<&str> {
+    let (_, operands) = split_mnemonic(statement);
+    split_top_level(operands)
+}
+
+/// Splits the mnemonic of an instruction from its operands, skipping any prefixes before it.
+fn split_mnemonic(statement: &str) -> (&str, &str) {
+    let mut remainder = statement.trim();
+    while let Some((word, operands)) = remainder.split_once(char::is_whitespace) {
+        remainder = operands.trim_start();
+        if !word.eq_ignore_ascii_case("lock") && RepeatPrefix::try_from(&NasmStr(word)).is_err() {
+            return (word, remainder);
+        }
+    }
+    (remainder, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+
+    #[test]
+    fn symbol_table_define() {
+        let mut symbols = SymbolTable::default();
+        symbols.define("start", 0).unwrap();
+        symbols.define("_loop.inner?2", 3).unwrap();
+        assert_eq!(symbols.get("start"), Some(0));
+        assert_eq!(symbols.get("_loop.inner?2"), Some(3));
+        assert_eq!(symbols.get("Start"), None);
+
+        assert!(symbols.define("start", 1).is_err());
+        assert!(symbols.define("1st", 1).is_err());
+        assert!(symbols.define("a b", 1).is_err());
+        assert!(symbols.define("", 1).is_err());
+        assert!(symbols.define("eax", 1).is_err());
+        assert!(symbols.define("dword", 1).is_err());
+    }
+
+    #[test]
+    fn symbol_table_substitute() {
+        let mut symbols = SymbolTable::default();
+        symbols.define("x", 0x10).unwrap();
+        symbols.define("buffer", 0x200).unwrap();
+        symbols.define("y", 0xd).unwrap();
+        assert_eq!(symbols.substitute("x"), "16");
+        assert_eq!(symbols.substitute("y"), "13");
+        assert_eq!(
+            symbols.substitute("dword [buffer+eax*4]"),
+            "dword [512+eax*4]"
+        );
+        assert_eq!(symbols.substitute("[ebx+0x1x], xx"), "[ebx+0x1x], xx");
+        assert_eq!(symbols.substitute("eax, 0x0"), "eax, 0x0");
+    }
+
+    #[test]
+    fn program_try_from_nasm_str() {
+        let program = Program::try_from(&NasmStr(
+            "start: add eax, 3 ; the counter\n\
+             \n\
+             top:\n\
+             sub eax, 1\n\
+             jne top\n\
+             jmp end\n\
+             end:",
+        ))
+        .unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(program.symbols().get("start"), Some(0x1000));
+        assert_eq!(program.symbols().get("top"), Some(0x1005));
+        assert_eq!(program.symbols().get("end"), Some(0x1015));
+        // Labels are placed after the machine code of the instructions before them.
+        assert_eq!(
+            program.section(SectionName::Text).image,
+            [
+                0x05, 3, 0, 0, 0, // add eax, 3
+                0x2d, 1, 0, 0, 0, // sub eax, 1
+                0x0f, 0x85, 0xf5, 0xff, 0xff, 0xff, // jne top
+                0xe9, 0, 0, 0, 0, // jmp end
+            ]
+        );
+
+        // The length of an instruction may depend on a label after it, which depends on its
+        // length in turn (here, whether the displacement fits in a byte).
+        let program = Program::try_from(&NasmStr("mov eax, [ebx+end-$$]\nend:")).unwrap();
+        assert_eq!(program.symbols().get("end"), Some(0x1003));
+        assert_eq!(program.section(SectionName::Text).image, [0x8b, 0x43, 3]);
+
+        assert!(Program::try_from(&NasmStr("a:\na:")).is_err());
+        assert!(Program::try_from(&NasmStr("jmp nowhere")).is_err());
+
+        let program = Program::try_from(&NasmStr("%define TOP top\ntop: jmp TOP")).unwrap();
+        assert_eq!(program.symbols().get("top"), Some(0x1000));
+        assert!(Program::try_from(&NasmStr("%unknown")).is_err());
+
+        let program = Program::try_from(&NasmStr(
+            "%macro COUNT_DOWN 1\n\
+             %%top: sub %1, 1\n\
+             jne %%top\n\
+             %endmacro\n\
+             COUNT_DOWN eax\n\
+             COUNT_DOWN ecx",
+        ))
+        .unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(program.symbols().get("..@2.top"), Some(0x100b));
+        assert!(Program::try_from(&NasmStr("hlt\n%macro M 0"))
+            .is_err_and(|error| error.to_string().contains("line 2")));
+    }
+
+    #[test]
+    fn program_diagnostics() {
+        let diagnostic = |source| match Program::try_from(&NasmStr(source)) {
+            Err(Error::Diagnostic(diagnostic)) => (diagnostic.line, diagnostic.token),
+            Err(error) => panic!("expected a diagnostic, found {error:?}"),
+            Ok(_) => panic!("expected a diagnostic"),
+        };
+        assert_eq!(diagnostic("hlt\n  jmp nowhere"), (2, "nowhere".into()));
+        assert_eq!(diagnostic("a:\na: hlt"), (2, "a".into()));
+        assert_eq!(diagnostic("add eax, [ebx*3]"), (1, "[ebx*3]".into()));
+        assert_eq!(diagnostic("lock add eax, [ebx*3]"), (1, "[ebx*3]".into()));
+        assert_eq!(diagnostic("section .rodata"), (1, ".rodata".into()));
+        assert_eq!(diagnostic("org 1\norg 2"), (2, "org".into()));
+        assert_eq!(
+            diagnostic("section .data\ndb 1, ebx ; comment"),
+            (2, "ebx".into())
+        );
+        assert_eq!(diagnostic("%unknown 1 ; comment"), (1, "%unknown 1".into()));
+        // A mnemonic which no instruction has is pointed out by itself, and operands which the
+        // instruction does not take along with it.
+        assert_eq!(diagnostic("hlt\nmvo [eax], ecx"), (2, "mvo".into()));
+        assert_eq!(diagnostic("sub ecx ; comment"), (1, "sub ecx".into()));
+        // Errors within the expansion of a macro refer to the line which used it.
+        assert_eq!(
+            diagnostic("%macro M 0\njmp nowhere\n%endmacro\nhlt\nM"),
+            (5, "M".into())
+        );
+
+        // A malformed number is explained, rather than reported as an invalid operand.
+        let message = |source| match Program::try_from(&NasmStr(source)) {
+            Err(Error::Diagnostic(diagnostic)) => diagnostic.message,
+            Err(error) => panic!("expected a diagnostic, found {error:?}"),
+            Ok(_) => panic!("expected a diagnostic"),
+        };
+        assert_eq!(
+            message("add eax, 0x1h"),
+            "instruction could not be parsed: \"0x1h\" is not a valid number, as its radix is \
+             given by both a prefix and a suffix"
+        );
+        assert_eq!(
+            message("add eax, 0b102"),
+            "instruction could not be parsed: \"0b102\" is not a valid number, as '2' is not a \
+             binary digit"
+        );
+        assert_eq!(
+            message("add eax, 5000000000"),
+            "instruction could not be parsed: \"5000000000\" is not a valid number, as it does \
+             not fit in 32 bits"
+        );
+    }
+
+    #[test]
+    fn program_warnings() {
+        let program = Program::try_from(&NasmStr(
+            "sub dword eax, 1\n\
+             times 2 sub byte eax, 1\n\
+             add dword [eax], 1\n\
+             lock add byte [eax], 256\n\
+             section .data\n\
+             db 255, -128, 256, -129\n\
+             dw 0xffff, -1\n\
+             dq -1",
+        ))
+        .unwrap();
+        let warnings: Vec<_> = program
+            .warnings()
+            .iter()
+            .map(|warning| (warning.line, warning.token.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![(2, "byte"), (4, "256"), (6, "256"), (6, "-129")]
+        );
+        assert_eq!(
+            program.warnings()[3].message,
+            "-129 does not fit in a byte, and is truncated"
+        );
+        assert_eq!(
+            program.section(SectionName::Data).image,
+            [
+                0xff, 0x80, 0x00, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff
+            ]
+        );
+        assert_eq!(program.warnings()[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn program_run() {
+        let program = Program::try_from(&NasmStr(
+            "and eax, 0\n\
+             loop: call add_ecx\n\
+             sub ecx, 1\n\
+             jne loop\n\
+             jmp done\n\
+             add_ecx: lea eax, [eax+ecx]\n\
+             ret\n\
+             done: mov ebx, [data]\n\
+             data:",
+        ))
+        .unwrap();
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(0xffff);
+        cpu.registers.set_ecx(4);
+        cpu.registers.esp = 0x1000;
+        cpu.memory.write32(0x1025, 0x1234).unwrap();
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_eax(), 10);
+        assert_eq!(cpu.registers.get_ebx(), 0x1234);
+        assert_eq!(cpu.registers.get_eip(), 0x1025);
+        assert_eq!(cpu.registers.esp, 0x1000);
+    }
+
+    #[test]
+    fn program_assemble_modules() {
+        let main = "global start\n\
+                    extern add_ecx, value, end\n\
+                    start: call add_ecx\n\
+                    mov ebx, [value]\n\
+                    jmp end";
+        let library = "global add_ecx, value, end\n\
+                       start: ; Labels which are not global are private to their module.\n\
+                       add_ecx: lea eax, [eax+ecx]\n\
+                       ret\n\
+                       end:\n\
+                       section .data\n\
+                       value: dd 0x1234";
+        let program =
+            Program::assemble_modules(&[main, library], Layout::default(), Syntax::Nasm).unwrap();
+        assert_eq!(program.len(), 5);
+        assert_eq!(program.symbols().get("start"), Some(0x1000));
+        assert_eq!(program.symbols().get("add_ecx"), Some(0x1010));
+        assert_eq!(program.symbols().get("value"), Some(0x1_0000));
+
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(1);
+        cpu.registers.set_ecx(2);
+        cpu.registers.esp = 0x1000;
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_eax(), 3);
+        assert_eq!(cpu.registers.get_ebx(), 0x1234);
+        assert_eq!(cpu.registers.get_eip(), 0x1014);
+
+        let diagnostic = |sources: &[&str]| match Program::assemble_modules(
+            sources,
+            Layout::default(),
+            Syntax::Nasm,
+        ) {
+            Err(Error::Diagnostic(diagnostic)) => {
+                (diagnostic.module, diagnostic.line, diagnostic.token)
+            }
+            Err(error) => panic!("expected a diagnostic, found {error:?}"),
+            Ok(_) => panic!("expected a diagnostic"),
+        };
+        assert_eq!(
+            diagnostic(&["hlt", "extern missing"]),
+            (1, 1, "missing".into())
+        );
+        assert_eq!(diagnostic(&["a: hlt", "hlt\nglobal a"]), (1, 2, "a".into()));
+        assert_eq!(
+            diagnostic(&["global a\na: hlt", "global a\na: hlt"]),
+            (1, 1, "a".into())
+        );
+        assert_eq!(
+            diagnostic(&["extern a\na: hlt", "global a\na: hlt"]),
+            (0, 1, "a".into())
+        );
+        assert_eq!(diagnostic(&["hlt", "jmp a"]), (1, 1, "a".into()));
+        assert_eq!(diagnostic(&["org 1", "org 2"]), (1, 1, "org".into()));
+    }
+
+    #[test]
+    fn program_sections() {
+        let layout = Layout {
+            text: 0x100,
+            data: 0x2000,
+            bss: 0x3000,
+        };
+        let program = Program::assemble(
+            "section .data\n\
+             message: db 'hi', 0\n\
+             words: dw 0x1234, -1\n\
+             pointer: dd words\n\
+             section .bss\n\
+             buffer:\n\
+             section .text\n\
+             start: mov ebx, [pointer]\n\
+             done:",
+            layout,
+            Syntax::Nasm,
+        )
+        .unwrap();
+        assert_eq!(program.symbols().get("message"), Some(0x2000));
+        assert_eq!(program.symbols().get("words"), Some(0x2003));
+        assert_eq!(program.symbols().get("pointer"), Some(0x2007));
+        assert_eq!(program.symbols().get("buffer"), Some(0x3000));
+        assert_eq!(program.symbols().get("start"), Some(0x100));
+        assert_eq!(program.symbols().get("done"), Some(0x106));
+        assert_eq!(
+            program.section(SectionName::Data),
+            &Section {
+                base: 0x2000,
+                image: vec![b'h', b'i', 0, 0x34, 0x12, 0xff, 0xff, 0x03, 0x20, 0, 0],
+                size: 11,
+            }
+        );
+
+        let mut cpu = Cpu::default();
+        program.load(&mut cpu).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x100);
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_ebx(), 0x2003);
+        assert_eq!(cpu.registers.get_eip(), 0x106);
+
+        assert!(Program::try_from(&NasmStr("section .data\nret")).is_err());
+        assert!(Program::try_from(&NasmStr("section .rodata")).is_err());
+        assert!(Program::try_from(&NasmStr("section .data\ndb")).is_err());
+    }
+
+    #[test]
+    fn program_equ() {
+        let program = Program::try_from(&NasmStr(
+            "COUNT equ 3\n\
+             SIZE: EQU COUNT*4 + 1 ; a comment\n\
+             section .data\n\
+             table: dd SIZE, COUNT-4\n\
+             END equ table+8\n\
+             section .text\n\
+             mov ebx, [table+SIZE-13]\n\
+             lea ecx, [SIZE*2+ebx]\n\
+             sub ecx, COUNT",
+        ))
+        .unwrap();
+        assert_eq!(program.symbols().get("COUNT"), Some(3));
+        assert_eq!(program.symbols().get("SIZE"), Some(13));
+        assert_eq!(program.symbols().get("END"), Some(0x1_0008));
+        assert_eq!(
+            program.section(SectionName::Data).image,
+            [13, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]
+        );
+
+        let mut cpu = Cpu::default();
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_ebx(), 13);
+        assert_eq!(cpu.registers.get_ecx(), 36);
+
+        assert!(Program::try_from(&NasmStr("A equ B\nB equ 1")).is_err());
+        assert!(Program::try_from(&NasmStr("A equ 1\nA equ 2")).is_err());
+        assert!(Program::try_from(&NasmStr("eax equ 1")).is_err());
+        assert!(Program::try_from(&NasmStr("A equ")).is_err());
+    }
+
+    #[test]
+    fn program_times() {
+        let program = Program::try_from(&NasmStr(
+            "section .data\n\
+             header: db 1, 2\n\
+             times 6-($-$$) db 0xaa\n\
+             addresses: times 2 dd $\n\
+             section .text\n\
+             start: times 1 + 2 sub eax, 1\n\
+             here: lea ebx, [$]\n\
+             times 0 sub eax, 1",
+        ))
+        .unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(program.symbols().get("addresses"), Some(0x1_0006));
+        assert_eq!(program.symbols().get("here"), Some(0x100f));
+        assert_eq!(
+            program.section(SectionName::Data).image,
+            [1, 2, 0xaa, 0xaa, 0xaa, 0xaa, 6, 0, 1, 0, 0xa, 0, 1, 0]
+        );
+
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(5);
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_eax(), 2);
+        assert_eq!(cpu.registers.get_ebx(), 0x100f);
+
+        assert!(Program::try_from(&NasmStr("times 0-1 sub eax, 1")).is_err());
+        assert!(Program::try_from(&NasmStr("times sub eax, 1")).is_err());
+        assert!(Program::try_from(&NasmStr("times 2")).is_err());
+    }
+
+    #[test]
+    fn program_local_labels() {
+        let program = Program::try_from(&NasmStr(
+            ".orphan:\n\
+             first: and eax, 0\n\
+             .loop: sub ecx, 1\n\
+             jne .loop\n\
+             .count equ .loop + 1\n\
+             second:\n\
+             .loop: sub edx, 1\n\
+             jne .loop\n\
+             lea ebx, [first.loop]\n\
+             section .data\n\
+             table: dd .entry, table.entry\n\
+             .entry: db '.entry'",
+        ))
+        .unwrap();
+        assert_eq!(program.symbols().get(".orphan"), Some(0x1000));
+        assert_eq!(program.symbols().get("first.loop"), Some(0x1005));
+        assert_eq!(program.symbols().get("first.count"), Some(0x1006));
+        assert_eq!(program.symbols().get("second.loop"), Some(0x1011));
+        assert_eq!(program.symbols().get("table.entry"), Some(0x1_0008));
+        assert_eq!(
+            program.section(SectionName::Data).image[..8],
+            [8, 0, 1, 0, 8, 0, 1, 0]
+        );
+        assert_eq!(&program.section(SectionName::Data).image[8..], b".entry");
+
+        let mut cpu = Cpu::default();
+        cpu.registers.set_ecx(2);
+        cpu.registers.set_edx(3);
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.registers.get_edx(), 0);
+        assert_eq!(cpu.registers.get_ebx(), 0x1005);
+
+        assert!(Program::try_from(&NasmStr("a:\n.x:\nb:\n.x:\na.x:")).is_err());
+    }
+
+    #[test]
+    fn program_org() {
+        let program = Program::assemble(
+            "start: jmp end\n\
+             section .data\n\
+             value: dd end\n\
+             section .text\n\
+             org 0x7c00 ; a boot sector\n\
+             end:",
+            Layout::default(),
+            Syntax::Nasm,
+        )
+        .unwrap();
+        assert_eq!(program.section(SectionName::Text).base, 0x7c00);
+        assert_eq!(program.symbols().get("start"), Some(0x7c00));
+        assert_eq!(program.symbols().get("end"), Some(0x7c05));
+        assert_eq!(program.symbols().get("value"), Some(0x1_0000));
+        assert_eq!(program.section(SectionName::Data).image, [0x05, 0x7c, 0, 0]);
+
+        let mut cpu = Cpu::default();
+        program.load(&mut cpu).unwrap();
+        assert_eq!(cpu.registers.get_eip(), 0x7c00);
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_eip(), 0x7c05);
+
+        let program = Program::try_from(&NasmStr(
+            "org 0x7c00\n\
+             start: jmp start\n\
+             times 510-($-$$) db 0\n\
+             dw 0xaa55",
+        ))
+        .unwrap();
+        let text = program.section(SectionName::Text);
+        assert_eq!(text.size, 512);
+        assert_eq!(text.image[..5], [0xe9, 0xfb, 0xff, 0xff, 0xff]);
+        assert!(text.image[5..510].iter().all(|&byte| byte == 0));
+        assert_eq!(text.image[510..], [0x55, 0xaa]);
+        assert!(program.section(SectionName::Data).image.is_empty());
+
+        assert!(Program::try_from(&NasmStr("org 1\norg 2")).is_err());
+        assert!(Program::try_from(&NasmStr("org")).is_err());
+        assert!(Program::try_from(&NasmStr("start:\norg start")).is_err());
+    }
+
+    #[test]
+    fn program_label_effective_addresses() {
+        let program = Program::try_from(&NasmStr(
+            "section .data\n\
+             myvar: dd 0x11, 0x22\n\
+             table: dd 0x100, 0x200, 0x300\n\
+             section .text\n\
+             mov ebx, [myvar]\n\
+             mov ecx, [myvar+4]\n\
+             mov edx, [table+eax*4]\n\
+             lea esi, [eax*4+table]\n\
+             mov dword [table+eax*4+4], esi",
+        ))
+        .unwrap();
+        let mut cpu = Cpu::default();
+        cpu.registers.set_eax(2);
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_ebx(), 0x11);
+        assert_eq!(cpu.registers.get_ecx(), 0x22);
+        assert_eq!(cpu.registers.get_edx(), 0x300);
+        assert_eq!(cpu.registers.esi, 0x1_0010);
+        assert_eq!(cpu.memory.read32(0x1_0014).unwrap(), 0x1_0010);
+    }
+
+    #[test]
+    fn program_reservations() {
+        let program = Program::try_from(&NasmStr(
+            "section .bss\n\
+             buffer: resb 3\n\
+             words: resw 2\n\
+             SIZE equ $ - buffer\n\
+             dwords: times 2 resd 1\n\
+             quads: resq 1\n\
+             end:\n\
+             section .text\n\
+             mov dword [words], ebx",
+        ))
+        .unwrap();
+        assert_eq!(program.symbols().get("buffer"), Some(0x2_0000));
+        assert_eq!(program.symbols().get("words"), Some(0x2_0003));
+        assert_eq!(program.symbols().get("SIZE"), Some(7));
+        assert_eq!(program.symbols().get("dwords"), Some(0x2_0007));
+        assert_eq!(program.symbols().get("quads"), Some(0x2_000f));
+        assert_eq!(program.symbols().get("end"), Some(0x2_0017));
+        assert_eq!(
+            program.section(SectionName::Bss),
+            &Section {
+                base: 0x2_0000,
+                image: Vec::new(),
+                size: 0x17,
+            }
+        );
+
+        let mut cpu = Cpu::default();
+        cpu.memory.write32(0x2_0003, 0xffff_ffff).unwrap();
+        cpu.registers.set_ebx(0x1234);
+        program.load(&mut cpu).unwrap();
+        assert_eq!(cpu.memory.read32(0x2_0003).unwrap(), 0);
+        program.run(&mut cpu);
+        assert_eq!(cpu.memory.read32(0x2_0003).unwrap(), 0x1234);
+
+        assert!(Program::try_from(&NasmStr("resb 1")).is_err());
+        assert!(Program::try_from(&NasmStr("section .data\nresd 1")).is_err());
+        assert!(Program::try_from(&NasmStr("section .bss\nresb")).is_err());
+        assert!(Program::try_from(&NasmStr("section .bss\ndb 1")).is_err());
+
+        // A reservation which is negative, or too large for the address space, is rejected
+        // rather than wrapping around.
+        let message = |source| match Program::try_from(&NasmStr(source)) {
+            Err(Error::Diagnostic(diagnostic)) => diagnostic.message,
+            Err(error) => panic!("expected a diagnostic, found {error:?}"),
+            Ok(_) => panic!("expected a diagnostic"),
+        };
+        assert_eq!(
+            message("section .bss\nresb -1"),
+            "instruction could not be parsed: the number of units to reserve cannot be negative \
+             (was -1)"
+        );
+        assert_eq!(
+            message("section .bss\nresq 0x20000000"),
+            "instruction could not be parsed: reserving 536870912 units of 8 bytes would not fit \
+             in the address space"
+        );
+        assert!(Program::try_from(&NasmStr("section .bss\ntimes 2 resd 0x40000000")).is_err());
+        assert!(Program::try_from(&NasmStr("section .bss\nresb 0xffffffff\nresb 1")).is_err());
+
+        // A large reservation is only mapped when it is written to (see `Memory::zero`).
+        let program = Program::try_from(&NasmStr("section .bss\nresb 0x8000_0000")).unwrap();
+        let mut cpu = Cpu::default();
+        program.load(&mut cpu).unwrap();
+        assert_eq!(cpu.memory.read8(0xa000_0000).unwrap(), 0);
+    }
+
+    #[test]
+    fn program_try_from_att_str() {
+        let program = Program::try_from(&AttStr(
+            "section .data\n\
+             value: dd 5\n\
+             section .text\n\
+             movl value, %ecx # a comment\n\
+             top: subl $1, %ecx ; also a comment\n\
+             jne top\n\
+             movl %ecx, value",
+        ))
+        .unwrap();
+        let mut cpu = Cpu::default();
+        program.load(&mut cpu).unwrap();
+        program.run(&mut cpu);
+        assert_eq!(cpu.registers.get_ecx(), 0);
+        assert_eq!(cpu.memory.read32(0x1_0000).unwrap(), 0);
+    }
+}
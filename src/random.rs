@@ -0,0 +1,80 @@
+use alloc::boxed::Box;
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// A source of random numbers for `RDRAND`. Returning `None` indicates that no random number was
+/// available (e.g. because the entropy pool has been exhausted), in which case `RDRAND` fails by
+/// clearing the CF flag.
+pub trait EntropySource: Debug {
+    /// Returns the next 32-bit random number, if one is available.
+    fn next_u32(&mut self) -> Option<u32>;
+}
+
+/// The default entropy source, which is seeded differently on each run and never fails. This is a
+/// xorshift64* generator, so it is not suitable for cryptographic purposes. Without the `std`
+/// feature there is nothing to seed it from, so it produces the same numbers on every run, and an
+/// embedder which needs them to differ should provide an entropy source of its own (see
+/// [`RandomNumberGenerator::set_source`]).
+#[derive(Debug)]
+pub struct DefaultEntropySource {
+    state: u64,
+}
+
+impl Default for DefaultEntropySource {
+    fn default() -> Self {
+        // `RandomState` is randomly keyed, so hashing anything with it produces a random seed. The
+        // state of a xorshift generator must never be 0.
+        #[cfg(feature = "std")]
+        let seed = RandomState::new().build_hasher().finish();
+        #[cfg(not(feature = "std"))]
+        let seed = 0x853c_49e6_748f_ea9b;
+        Self { state: seed | 1 }
+    }
+}
+
+impl EntropySource for DefaultEntropySource {
+    fn next_u32(&mut self) -> Option<u32> {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        Some((self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32)
+    }
+}
+
+/// The random number generator used by `RDRAND`, which draws from a replaceable entropy source.
+#[derive(Debug)]
+pub struct RandomNumberGenerator(Box<dyn EntropySource>);
+
+impl Default for RandomNumberGenerator {
+    fn default() -> Self {
+        Self(Box::new(DefaultEntropySource::default()))
+    }
+}
+
+impl RandomNumberGenerator {
+    /// Replaces the entropy source, such that all future random numbers are drawn from it.
+    pub fn set_source(&mut self, source: Box<dyn EntropySource>) {
+        self.0 = source;
+    }
+
+    /// Returns the next 32-bit random number, or `None` if the entropy source has none available.
+    pub fn next_u32(&mut self) -> Option<u32> {
+        self.0.next_u32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_entropy_source() {
+        let mut source = DefaultEntropySource::default();
+        let values: Vec<_> = (0..8).map(|_| source.next_u32().unwrap()).collect();
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
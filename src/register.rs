@@ -1,4 +1,5 @@
-use std::{fmt::Display, u32};
+use alloc::{format, vec::Vec};
+use core::fmt::Display;
 
 use bitmaps::Bitmap;
 use num_traits::{CheckedAdd, FromPrimitive, PrimInt, Zero};
@@ -6,11 +7,14 @@ use paste::paste;
 
 use crate::{
     cpu::Operation,
+    descriptor::AccessRights,
     error::Error,
     instruction::{NasmStr, OperandType, Size},
+    memory::AccessKind,
     traits::{AsUnsigned, BitIndex, HighLowBytes32, MostSignificantBit, RegisterReadWrite, Signed},
 };
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CurrentPrivilegeLevel {
     CPL0,
     CPL1,
@@ -18,6 +22,20 @@ pub enum CurrentPrivilegeLevel {
     CPL3,
 }
 
+/// Intel manual section 2.2 "MODES OF OPERATION".
+/// The mode which the processor operates in, as selected by CR0.PE and EFLAGS.VM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperatingMode {
+    /// CR0.PE is clear. Each segment is based at 16 times its selector, and every instruction runs
+    /// at CPL 0.
+    Real,
+    /// CR0.PE is set. Segments are loaded from the descriptors which their selectors refer to.
+    Protected,
+    /// CR0.PE and EFLAGS.VM are set. Segments are loaded as in real mode, but instructions run at
+    /// CPL 3.
+    Virtual8086,
+}
+
 pub enum WithCarry {
     True,
     False,
@@ -111,7 +129,11 @@ pub enum WithCarry {
 /// The ability of a program to set or clear this flag indicates support for the CPUID
 /// instruction.
 #[derive(Clone, Debug)]
-pub struct Eflags(Bitmap<32>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Eflags(
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))] Bitmap<32>,
+);
 
 macro_rules! eflags_accessors {
     ($field_name:ident, $bit:literal) => {
@@ -230,6 +252,19 @@ impl Eflags {
         self.set_sign_flag(result.most_significant_bit());
     }
 
+    /// Gets the raw value of the register, e.g. for pushing it onto the stack.
+    pub fn get_value(&self) -> u32 {
+        *self.0.as_value()
+    }
+
+    /// Sets the raw value of the register, e.g. when popping it off the stack. Reserved bits are
+    /// forced to their fixed values regardless of `value`.
+    pub fn set_value(&mut self, value: u32) {
+        const RESERVED_SET: u32 = 1 << 1;
+        const RESERVED_CLEAR: u32 = 1 << 3 | 1 << 5 | 1 << 15 | 0xffc0_0000;
+        self.0 = Bitmap::from_value(value & !RESERVED_CLEAR | RESERVED_SET);
+    }
+
     pub fn get_iopl(&self) -> CurrentPrivilegeLevel {
         let first_bit = self.0.get(12);
         let second_bit = self.0.get(13);
@@ -264,6 +299,416 @@ impl Default for Eflags {
     }
 }
 
+/// The abbreviations of the flags in EFLAGS, along with their bits, in the order in which
+/// [`Eflags`] displays them. The 2-bit IOPL field is left out.
+const EFLAGS_NAMES: [(usize, &str); 16] = [
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+    (14, "NT"),
+    (16, "RF"),
+    (17, "VM"),
+    (18, "AC"),
+    (19, "VIF"),
+    (20, "VIP"),
+    (21, "ID"),
+];
+
+/// Lists the flags which are set, e.g. `[ CF PF ZF ]`, or `[ ]` if none are.
+impl Display for Eflags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "[ ")?;
+        for (bit, name) in EFLAGS_NAMES {
+            if self.0.get(bit) {
+                write!(f, "{name} ")?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// Intel manual section 2.5 "CONTROL REGISTERS".
+/// - CR0 contains system control flags that control the operating mode and states of the
+///   processor.
+/// - CR1 is reserved.
+/// - CR2 contains the page-fault linear address (the linear address that caused a page fault).
+/// - CR3 contains the physical address of the base of the paging-structure hierarchy, along with
+///   the PCD and PWT flags.
+/// - CR4 contains a group of flags that enable several architectural extensions.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlRegisters {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    cr0: Bitmap<32>,
+    cr2: u32,
+    cr3: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    cr4: Bitmap<32>,
+}
+
+macro_rules! control_register_accessors {
+    ($register:ident, $field_name:ident, $bit:literal) => {
+        paste! {
+            pub fn [<get_ $field_name>](&self) -> bool {
+                self.$register.get($bit)
+            }
+
+            pub fn [<set_ $field_name>](&mut self, value: bool) {
+                self.$register.set($bit, value);
+            }
+        }
+    };
+}
+
+impl ControlRegisters {
+    control_register_accessors!(cr0, protection_enable, 0);
+    control_register_accessors!(cr0, monitor_coprocessor, 1);
+    control_register_accessors!(cr0, emulation, 2);
+    control_register_accessors!(cr0, task_switched, 3);
+    control_register_accessors!(cr0, extension_type, 4);
+    control_register_accessors!(cr0, numeric_error, 5);
+    control_register_accessors!(cr0, write_protect, 16);
+    control_register_accessors!(cr0, alignment_mask, 18);
+    control_register_accessors!(cr0, not_write_through, 29);
+    control_register_accessors!(cr0, cache_disable, 30);
+    control_register_accessors!(cr0, paging, 31);
+
+    control_register_accessors!(cr4, virtual_8086_mode_extensions, 0);
+    control_register_accessors!(cr4, protected_mode_virtual_interrupts, 1);
+    control_register_accessors!(cr4, time_stamp_disable, 2);
+    control_register_accessors!(cr4, debugging_extensions, 3);
+    control_register_accessors!(cr4, page_size_extensions, 4);
+    control_register_accessors!(cr4, physical_address_extension, 5);
+    control_register_accessors!(cr4, machine_check_enable, 6);
+    control_register_accessors!(cr4, page_global_enable, 7);
+    control_register_accessors!(cr4, performance_monitoring_counter_enable, 8);
+    control_register_accessors!(cr4, osfxsr, 9);
+    control_register_accessors!(cr4, osxmmexcpt, 10);
+
+    pub fn get_cr0(&self) -> u32 {
+        *self.cr0.as_value()
+    }
+
+    /// Sets the raw value of CR0. The ET flag is hardwired to 1, and is set regardless of `value`.
+    pub fn set_cr0(&mut self, value: u32) {
+        self.cr0 = Bitmap::from_value(value);
+        self.set_extension_type(true);
+    }
+
+    pub fn get_cr2(&self) -> u32 {
+        self.cr2
+    }
+
+    pub fn set_cr2(&mut self, value: u32) {
+        self.cr2 = value;
+    }
+
+    pub fn get_cr3(&self) -> u32 {
+        self.cr3
+    }
+
+    pub fn set_cr3(&mut self, value: u32) {
+        self.cr3 = value;
+    }
+
+    pub fn get_cr4(&self) -> u32 {
+        *self.cr4.as_value()
+    }
+
+    pub fn set_cr4(&mut self, value: u32) {
+        self.cr4 = Bitmap::from_value(value);
+    }
+
+    pub fn read(&self, register: &ControlRegister) -> u32 {
+        use ControlRegister::*;
+        match register {
+            Cr0 => self.get_cr0(),
+            Cr2 => self.get_cr2(),
+            Cr3 => self.get_cr3(),
+            Cr4 => self.get_cr4(),
+        }
+    }
+
+    pub fn write(&mut self, register: &ControlRegister, value: u32) {
+        use ControlRegister::*;
+        match register {
+            Cr0 => self.set_cr0(value),
+            Cr2 => self.set_cr2(value),
+            Cr3 => self.set_cr3(value),
+            Cr4 => self.set_cr4(value),
+        }
+    }
+}
+
+impl Default for ControlRegisters {
+    /// Intel manual section 10.1.1 "Processor State After Reset". CR0 is 0x60000010, i.e. with CD,
+    /// NW, and ET set, and the remaining control registers are clear.
+    fn default() -> Self {
+        let mut control_registers = Self {
+            cr0: Bitmap::new(),
+            cr2: 0,
+            cr3: 0,
+            cr4: Bitmap::new(),
+        };
+        control_registers.set_cr0(0x6000_0010);
+        control_registers
+    }
+}
+
+/// Intel manual section 18.2.4 "Debug Control Register (DR7)".
+/// The accesses which a breakpoint is hit by, as selected by its R/W field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakpointCondition {
+    /// The execution of the instruction at the address.
+    Execute = 0b00,
+    Write = 0b01,
+    /// Reads and writes of the I/O port at the address, while debugging extensions (CR4.DE) are
+    /// enabled. These are not supported, and the breakpoint is never hit.
+    Io = 0b10,
+    ReadWrite = 0b11,
+}
+
+/// Intel manual section 18.2 "DEBUG REGISTERS".
+/// - DR0-DR3 each hold the linear address of a breakpoint.
+/// - DR4 and DR5 are reserved. While debugging extensions (CR4.DE) are disabled they alias DR6 and
+///   DR7 respectively, and otherwise referencing them raises #UD.
+/// - DR6 (debug status) reports the conditions which were sampled at the time the last debug
+///   exception was generated.
+/// - DR7 (debug control) enables or disables breakpoints and sets breakpoint conditions.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DebugRegisters {
+    breakpoint_addresses: [u32; 4],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    dr6: Bitmap<32>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    dr7: Bitmap<32>,
+}
+
+macro_rules! debug_register_accessors {
+    ($register:ident, $field_name:ident, $bit:literal) => {
+        paste! {
+            pub fn [<get_ $field_name>](&self) -> bool {
+                self.$register.get($bit)
+            }
+
+            pub fn [<set_ $field_name>](&mut self, value: bool) {
+                self.$register.set($bit, value);
+            }
+        }
+    };
+}
+
+impl DebugRegisters {
+    debug_register_accessors!(dr6, breakpoint_0_detected, 0);
+    debug_register_accessors!(dr6, breakpoint_1_detected, 1);
+    debug_register_accessors!(dr6, breakpoint_2_detected, 2);
+    debug_register_accessors!(dr6, breakpoint_3_detected, 3);
+    debug_register_accessors!(dr6, debug_register_access_detected, 13);
+    debug_register_accessors!(dr6, single_step, 14);
+    debug_register_accessors!(dr6, task_switch, 15);
+
+    debug_register_accessors!(dr7, local_exact_breakpoint_enable, 8);
+    debug_register_accessors!(dr7, global_exact_breakpoint_enable, 9);
+    debug_register_accessors!(dr7, general_detect_enable, 13);
+
+    /// Gets the linear address of the given breakpoint (0-3).
+    pub fn get_breakpoint_address(&self, breakpoint: usize) -> u32 {
+        self.breakpoint_addresses[breakpoint]
+    }
+
+    /// Whether the given breakpoint (0-3) is enabled for the current task (the L0-L3 flags).
+    pub fn get_local_breakpoint_enable(&self, breakpoint: usize) -> bool {
+        self.dr7.get(2 * breakpoint)
+    }
+
+    /// Whether the given breakpoint (0-3) is enabled for all tasks (the G0-G3 flags).
+    pub fn get_global_breakpoint_enable(&self, breakpoint: usize) -> bool {
+        self.dr7.get(2 * breakpoint + 1)
+    }
+
+    /// Gets the R/W field of the given breakpoint (0-3), which selects the breakpoint condition:
+    /// 0b00 for instruction execution, 0b01 for data writes, 0b10 for I/O reads or writes, and
+    /// 0b11 for data reads or writes.
+    pub fn get_breakpoint_condition(&self, breakpoint: usize) -> u8 {
+        (self.get_dr7() >> (16 + 4 * breakpoint) & 0b11) as u8
+    }
+
+    /// Gets the LEN field of the given breakpoint (0-3), which selects the size of the monitored
+    /// memory location: 0b00 for 1 byte, 0b01 for 2 bytes, 0b10 for 8 bytes, and 0b11 for 4
+    /// bytes.
+    pub fn get_breakpoint_length(&self, breakpoint: usize) -> u8 {
+        (self.get_dr7() >> (18 + 4 * breakpoint) & 0b11) as u8
+    }
+
+    /// Whether the given breakpoint (0-3) is enabled, either locally or globally.
+    pub fn get_breakpoint_enable(&self, breakpoint: usize) -> bool {
+        self.get_local_breakpoint_enable(breakpoint)
+            || self.get_global_breakpoint_enable(breakpoint)
+    }
+
+    /// Sets the given breakpoint (0-3) to be hit by accesses of the given kind to the `length`
+    /// bytes at the linear address `address`, and enables it globally. Panics if the length is not
+    /// 1, 2, 4, or 8 bytes.
+    pub fn set_breakpoint(
+        &mut self,
+        breakpoint: usize,
+        address: u32,
+        condition: BreakpointCondition,
+        length: u32,
+    ) {
+        let length = match length {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            4 => 0b11,
+            _ => panic!("a breakpoint cannot monitor {length} bytes"),
+        };
+        self.breakpoint_addresses[breakpoint] = address;
+        let shift = 16 + 4 * breakpoint;
+        let dr7 = self.get_dr7() & !(0b1111 << shift) | (length << 2 | condition as u32) << shift;
+        self.set_dr7(dr7 | 1 << (2 * breakpoint + 1));
+    }
+
+    /// Disables the given breakpoint (0-3), both locally and globally.
+    pub fn clear_breakpoint(&mut self, breakpoint: usize) {
+        self.set_dr7(self.get_dr7() & !(0b11 << (2 * breakpoint)));
+    }
+
+    /// The enabled instruction breakpoints at the linear address of an instruction, as a mask of
+    /// bits 0-3.
+    pub fn instruction_breakpoints(&self, address: u32) -> u8 {
+        self.breakpoints(|breakpoint, condition| {
+            condition == BreakpointCondition::Execute as u8
+                && self.breakpoint_addresses[breakpoint] == address
+        })
+    }
+
+    /// The enabled data breakpoints which are hit by an access of the given kind to the `length`
+    /// bytes at a linear address, as a mask of bits 0-3. The monitored location is aligned to its
+    /// length, as the processor ignores the low bits of its address.
+    pub fn data_breakpoints(&self, address: u32, length: u32, kind: AccessKind) -> u8 {
+        let last = address.saturating_add(length.saturating_sub(1));
+        self.breakpoints(|breakpoint, condition| {
+            let hit = condition == BreakpointCondition::ReadWrite as u8
+                || condition == BreakpointCondition::Write as u8 && kind == AccessKind::Write;
+            let monitored_length = match self.get_breakpoint_length(breakpoint) {
+                0b00 => 1,
+                0b01 => 2,
+                0b10 => 8,
+                _ => 4,
+            };
+            let start = self.breakpoint_addresses[breakpoint] & !(monitored_length - 1);
+            hit && start <= last && address <= start + (monitored_length - 1)
+        })
+    }
+
+    /// The enabled breakpoints for which `hit` returns `true`, given each breakpoint and its
+    /// condition (R/W field), as a mask of bits 0-3.
+    fn breakpoints(&self, hit: impl Fn(usize, u8) -> bool) -> u8 {
+        (0..4)
+            .filter(|&breakpoint| {
+                self.get_breakpoint_enable(breakpoint)
+                    && hit(breakpoint, self.get_breakpoint_condition(breakpoint))
+            })
+            .fold(0, |mask, breakpoint| mask | 1 << breakpoint)
+    }
+
+    pub fn get_dr6(&self) -> u32 {
+        *self.dr6.as_value()
+    }
+
+    /// Sets the raw value of DR6. Reserved bits are forced to their fixed values regardless of
+    /// `value`.
+    pub fn set_dr6(&mut self, value: u32) {
+        const RESERVED_SET: u32 = 0xffff_0ff0;
+        const RESERVED_CLEAR: u32 = 1 << 12;
+        self.dr6 = Bitmap::from_value(value & !RESERVED_CLEAR | RESERVED_SET);
+    }
+
+    pub fn get_dr7(&self) -> u32 {
+        *self.dr7.as_value()
+    }
+
+    /// Sets the raw value of DR7. Reserved bits are forced to their fixed values regardless of
+    /// `value`.
+    pub fn set_dr7(&mut self, value: u32) {
+        const RESERVED_SET: u32 = 1 << 10;
+        const RESERVED_CLEAR: u32 = 1 << 11 | 1 << 12 | 1 << 14 | 1 << 15;
+        self.dr7 = Bitmap::from_value(value & !RESERVED_CLEAR | RESERVED_SET);
+    }
+
+    /// Reads the given debug register. DR4 and DR5 are aliases of DR6 and DR7.
+    pub fn read(&self, register: &DebugRegister) -> u32 {
+        use DebugRegister::*;
+        match register {
+            Dr0 => self.breakpoint_addresses[0],
+            Dr1 => self.breakpoint_addresses[1],
+            Dr2 => self.breakpoint_addresses[2],
+            Dr3 => self.breakpoint_addresses[3],
+            Dr4 | Dr6 => self.get_dr6(),
+            Dr5 | Dr7 => self.get_dr7(),
+        }
+    }
+
+    /// Writes the given debug register. DR4 and DR5 are aliases of DR6 and DR7.
+    pub fn write(&mut self, register: &DebugRegister, value: u32) {
+        use DebugRegister::*;
+        match register {
+            Dr0 => self.breakpoint_addresses[0] = value,
+            Dr1 => self.breakpoint_addresses[1] = value,
+            Dr2 => self.breakpoint_addresses[2] = value,
+            Dr3 => self.breakpoint_addresses[3] = value,
+            Dr4 | Dr6 => self.set_dr6(value),
+            Dr5 | Dr7 => self.set_dr7(value),
+        }
+    }
+}
+
+impl Default for DebugRegisters {
+    /// Intel manual section 10.1.1 "Processor State After Reset". DR6 is 0xffff0ff0 and DR7 is
+    /// 0x00000400, i.e. only the reserved bits which are fixed to 1 are set.
+    fn default() -> Self {
+        let mut debug_registers = Self {
+            breakpoint_addresses: [0; 4],
+            dr6: Bitmap::new(),
+            dr7: Bitmap::new(),
+        };
+        debug_registers.set_dr6(0);
+        debug_registers.set_dr7(0);
+        debug_registers
+    }
+}
+
+/// Intel manual section 2.4 "MEMORY-MANAGEMENT REGISTERS".
+/// GDTR and IDTR each hold the linear base address and limit of their descriptor table (the GDT
+/// and IDT respectively). The limit is the number of bytes in the table, minus 1. They are loaded
+/// and stored by `LGDT`/`LIDT` and `SGDT`/`SIDT`, using a 6-byte pseudo-descriptor in memory: the
+/// limit in the low 2 bytes, followed by the base.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DescriptorTableRegister {
+    pub(crate) base: u32,
+    pub(crate) limit: u16,
+}
+
+impl Default for DescriptorTableRegister {
+    /// Intel manual section 10.1.1 "Processor State After Reset". The base is 0 and the limit is
+    /// 0xffff, which in real mode places the interrupt vector table at address 0.
+    fn default() -> Self {
+        Self {
+            base: 0,
+            limit: 0xffff,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Register32 {
     Eax,
@@ -289,7 +734,7 @@ impl RegisterReadWrite for Register32 {
 }
 
 impl Display for Register32 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use Register32::*;
         let register = match self {
             Eax => "EAX",
@@ -383,7 +828,7 @@ pub enum Register16 {
 }
 
 impl Display for Register16 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use Register16::*;
         let register = match self {
             Ax => "AX",
@@ -495,7 +940,7 @@ pub enum Register8 {
 }
 
 impl Display for Register8 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use Register8::*;
         let register = match self {
             Ah => "AH",
@@ -560,11 +1005,227 @@ impl<'a> TryFrom<&'a OperandType> for &'a Register8 {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ControlRegister {
+    Cr0,
+    Cr2,
+    Cr3,
+    Cr4,
+}
+
+impl Display for ControlRegister {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use ControlRegister::*;
+        let register = match self {
+            Cr0 => "CR0",
+            Cr2 => "CR2",
+            Cr3 => "CR3",
+            Cr4 => "CR4",
+        };
+
+        write!(f, "{register}")
+    }
+}
+
+impl<'a> TryFrom<&'a Register> for &'a ControlRegister {
+    type Error = Error;
+
+    fn try_from(register: &'a Register) -> Result<Self, Self::Error> {
+        match register {
+            Register::ControlRegister(register) => Ok(register),
+            _ => Err(Error::CannotCovertType(format!(
+                "{} is not a control register",
+                register
+            ))),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for &'a ControlRegister {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        <&Register>::try_from(operand_type)?.try_into()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DebugRegister {
+    Dr0,
+    Dr1,
+    Dr2,
+    Dr3,
+    Dr4,
+    Dr5,
+    Dr6,
+    Dr7,
+}
+
+impl Display for DebugRegister {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use DebugRegister::*;
+        let register = match self {
+            Dr0 => "DR0",
+            Dr1 => "DR1",
+            Dr2 => "DR2",
+            Dr3 => "DR3",
+            Dr4 => "DR4",
+            Dr5 => "DR5",
+            Dr6 => "DR6",
+            Dr7 => "DR7",
+        };
+
+        write!(f, "{register}")
+    }
+}
+
+impl<'a> TryFrom<&'a Register> for &'a DebugRegister {
+    type Error = Error;
+
+    fn try_from(register: &'a Register) -> Result<Self, Self::Error> {
+        match register {
+            Register::DebugRegister(register) => Ok(register),
+            _ => Err(Error::CannotCovertType(format!(
+                "{} is not a debug register",
+                register
+            ))),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for &'a DebugRegister {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        <&Register>::try_from(operand_type)?.try_into()
+    }
+}
+
+/// The MMX registers, which are aliased onto the significands of the x87 FPU data registers, such
+/// that MMn is physical register Rn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MmxRegister {
+    Mm0,
+    Mm1,
+    Mm2,
+    Mm3,
+    Mm4,
+    Mm5,
+    Mm6,
+    Mm7,
+}
+
+impl MmxRegister {
+    /// The index of the x87 FPU data register which this register is aliased onto.
+    pub fn index(&self) -> usize {
+        use MmxRegister::*;
+        match self {
+            Mm0 => 0,
+            Mm1 => 1,
+            Mm2 => 2,
+            Mm3 => 3,
+            Mm4 => 4,
+            Mm5 => 5,
+            Mm6 => 6,
+            Mm7 => 7,
+        }
+    }
+}
+
+impl Display for MmxRegister {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "MM{}", self.index())
+    }
+}
+
+impl<'a> TryFrom<&'a Register> for &'a MmxRegister {
+    type Error = Error;
+
+    fn try_from(register: &'a Register) -> Result<Self, Self::Error> {
+        match register {
+            Register::MmxRegister(register) => Ok(register),
+            _ => Err(Error::CannotCovertType(format!(
+                "{} is not an MMX register",
+                register
+            ))),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for &'a MmxRegister {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        <&Register>::try_from(operand_type)?.try_into()
+    }
+}
+
+/// The SSE registers, which are 128 bits wide and independent of the x87 FPU data registers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum XmmRegister {
+    Xmm0,
+    Xmm1,
+    Xmm2,
+    Xmm3,
+    Xmm4,
+    Xmm5,
+    Xmm6,
+    Xmm7,
+}
+
+impl XmmRegister {
+    pub fn index(&self) -> usize {
+        use XmmRegister::*;
+        match self {
+            Xmm0 => 0,
+            Xmm1 => 1,
+            Xmm2 => 2,
+            Xmm3 => 3,
+            Xmm4 => 4,
+            Xmm5 => 5,
+            Xmm6 => 6,
+            Xmm7 => 7,
+        }
+    }
+}
+
+impl Display for XmmRegister {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "XMM{}", self.index())
+    }
+}
+
+impl<'a> TryFrom<&'a Register> for &'a XmmRegister {
+    type Error = Error;
+
+    fn try_from(register: &'a Register) -> Result<Self, Self::Error> {
+        match register {
+            Register::XmmRegister(register) => Ok(register),
+            _ => Err(Error::CannotCovertType(format!(
+                "{} is not an XMM register",
+                register
+            ))),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a OperandType> for &'a XmmRegister {
+    type Error = Error;
+
+    fn try_from(operand_type: &'a OperandType) -> Result<Self, Self::Error> {
+        <&Register>::try_from(operand_type)?.try_into()
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Register {
     Register32(Register32),
     Register16(Register16),
     Register8(Register8),
+    ControlRegister(ControlRegister),
+    DebugRegister(DebugRegister),
+    MmxRegister(MmxRegister),
+    XmmRegister(XmmRegister),
 }
 
 impl Register {
@@ -575,17 +1236,86 @@ impl Register {
             Register32(_) => Dword,
             Register16(_) => Word,
             Register8(_) => Byte,
+            ControlRegister(_) | DebugRegister(_) => Dword,
+            MmxRegister(_) => Qword,
+            XmmRegister(_) => Oword,
+        }
+    }
+
+    /// The number which identifies the register within the ModR/M and SIB bytes, e.g. 0b011 for
+    /// EBX, BX, BL, MM3, and XMM3. Segment registers are numbered as in the sreg field of `MOV`.
+    pub fn code(&self) -> u8 {
+        match self {
+            Register::Register32(register) => match register {
+                Register32::Eax => 0,
+                Register32::Ecx => 1,
+                Register32::Edx => 2,
+                Register32::Ebx => 3,
+                Register32::Esp => 4,
+                Register32::Ebp => 5,
+                Register32::Esi => 6,
+                Register32::Edi => 7,
+            },
+            Register::Register16(register) => match register {
+                Register16::Ax | Register16::Es => 0,
+                Register16::Cx | Register16::Cs => 1,
+                Register16::Dx | Register16::Ss => 2,
+                Register16::Bx | Register16::Ds => 3,
+                Register16::Sp | Register16::Fs => 4,
+                Register16::Bp | Register16::Gs => 5,
+                Register16::Si => 6,
+                Register16::Di => 7,
+            },
+            Register::Register8(register) => match register {
+                Register8::Al => 0,
+                Register8::Cl => 1,
+                Register8::Dl => 2,
+                Register8::Bl => 3,
+                Register8::Ah => 4,
+                Register8::Ch => 5,
+                Register8::Dh => 6,
+                Register8::Bh => 7,
+            },
+            Register::ControlRegister(register) => match register {
+                ControlRegister::Cr0 => 0,
+                ControlRegister::Cr2 => 2,
+                ControlRegister::Cr3 => 3,
+                ControlRegister::Cr4 => 4,
+            },
+            Register::DebugRegister(register) => match register {
+                DebugRegister::Dr0 => 0,
+                DebugRegister::Dr1 => 1,
+                DebugRegister::Dr2 => 2,
+                DebugRegister::Dr3 => 3,
+                DebugRegister::Dr4 => 4,
+                DebugRegister::Dr5 => 5,
+                DebugRegister::Dr6 => 6,
+                DebugRegister::Dr7 => 7,
+            },
+            Register::MmxRegister(register) => register.index() as u8,
+            Register::XmmRegister(register) => register.index() as u8,
         }
     }
+
+    /// Whether this is one of the general-purpose (or segment) registers, as opposed to a register
+    /// which is only accessible through dedicated instructions (e.g. a control register).
+    pub fn is_general_purpose(&self) -> bool {
+        use Register::*;
+        matches!(self, Register32(_) | Register16(_) | Register8(_))
+    }
 }
 
 impl Display for Register {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use Register::*;
         match self {
             Register32(r) => r.fmt(f),
             Register16(r) => r.fmt(f),
             Register8(r) => r.fmt(f),
+            ControlRegister(r) => r.fmt(f),
+            DebugRegister(r) => r.fmt(f),
+            MmxRegister(r) => r.fmt(f),
+            XmmRegister(r) => r.fmt(f),
         }
     }
 }
@@ -608,6 +1338,30 @@ impl From<Register8> for Register {
     }
 }
 
+impl From<ControlRegister> for Register {
+    fn from(register: ControlRegister) -> Self {
+        Self::ControlRegister(register)
+    }
+}
+
+impl From<DebugRegister> for Register {
+    fn from(register: DebugRegister) -> Self {
+        Self::DebugRegister(register)
+    }
+}
+
+impl From<MmxRegister> for Register {
+    fn from(register: MmxRegister) -> Self {
+        Self::MmxRegister(register)
+    }
+}
+
+impl From<XmmRegister> for Register {
+    fn from(register: XmmRegister) -> Self {
+        Self::XmmRegister(register)
+    }
+}
+
 impl TryFrom<&NasmStr<'_>> for Register {
     type Error = Error;
 
@@ -652,6 +1406,38 @@ impl TryFrom<&NasmStr<'_>> for Register {
             "FS" => Ok(Register16::Fs.into()),
             "GS" => Ok(Register16::Gs.into()),
 
+            "CR0" => Ok(ControlRegister::Cr0.into()),
+            "CR2" => Ok(ControlRegister::Cr2.into()),
+            "CR3" => Ok(ControlRegister::Cr3.into()),
+            "CR4" => Ok(ControlRegister::Cr4.into()),
+
+            "DR0" => Ok(DebugRegister::Dr0.into()),
+            "DR1" => Ok(DebugRegister::Dr1.into()),
+            "DR2" => Ok(DebugRegister::Dr2.into()),
+            "DR3" => Ok(DebugRegister::Dr3.into()),
+            "DR4" => Ok(DebugRegister::Dr4.into()),
+            "DR5" => Ok(DebugRegister::Dr5.into()),
+            "DR6" => Ok(DebugRegister::Dr6.into()),
+            "DR7" => Ok(DebugRegister::Dr7.into()),
+
+            "MM0" => Ok(MmxRegister::Mm0.into()),
+            "MM1" => Ok(MmxRegister::Mm1.into()),
+            "MM2" => Ok(MmxRegister::Mm2.into()),
+            "MM3" => Ok(MmxRegister::Mm3.into()),
+            "MM4" => Ok(MmxRegister::Mm4.into()),
+            "MM5" => Ok(MmxRegister::Mm5.into()),
+            "MM6" => Ok(MmxRegister::Mm6.into()),
+            "MM7" => Ok(MmxRegister::Mm7.into()),
+
+            "XMM0" => Ok(XmmRegister::Xmm0.into()),
+            "XMM1" => Ok(XmmRegister::Xmm1.into()),
+            "XMM2" => Ok(XmmRegister::Xmm2.into()),
+            "XMM3" => Ok(XmmRegister::Xmm3.into()),
+            "XMM4" => Ok(XmmRegister::Xmm4.into()),
+            "XMM5" => Ok(XmmRegister::Xmm5.into()),
+            "XMM6" => Ok(XmmRegister::Xmm6.into()),
+            "XMM7" => Ok(XmmRegister::Xmm7.into()),
+
             _ => Err(Error::CannotParseInstruction(format!(
                 "{} is not a valid register",
                 value.0
@@ -672,11 +1458,119 @@ impl<'a> TryFrom<&'a OperandType> for &'a Register {
                 "a memory reference was provided when a register was expected".into(),
             )),
             OperandType::Register(register) => Ok(register),
+            OperandType::FarPointer(_) => Err(Error::CannotCovertType(
+                "a far pointer was provided when a register was expected".into(),
+            )),
+        }
+    }
+}
+
+/// The segment registers, in the order in which they are encoded in the REG field of a ModR/M
+/// byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentRegister {
+    Es,
+    Cs,
+    Ss,
+    Ds,
+    Fs,
+    Gs,
+}
+
+impl SegmentRegister {
+    pub const ALL: [SegmentRegister; 6] = [
+        SegmentRegister::Es,
+        SegmentRegister::Cs,
+        SegmentRegister::Ss,
+        SegmentRegister::Ds,
+        SegmentRegister::Fs,
+        SegmentRegister::Gs,
+    ];
+
+    pub fn index(&self) -> usize {
+        use SegmentRegister::*;
+        match self {
+            Es => 0,
+            Cs => 1,
+            Ss => 2,
+            Ds => 3,
+            Fs => 4,
+            Gs => 5,
+        }
+    }
+}
+
+/// Intel manual section 3.4.3 "Segment Registers".
+/// The hidden part of a segment register, i.e. the base address and limit of the segment which its
+/// selector refers to. Every memory access made through the segment adds its offset to the base,
+/// and faults if the offset is beyond the limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    pub base: u32,
+    /// The highest offset within the segment.
+    pub limit: u32,
+    /// The access rights of the descriptor which the segment was loaded from, or `None` if it was
+    /// not loaded from a descriptor (e.g. in real mode).
+    pub rights: Option<AccessRights>,
+    /// Whether the segment can be used to access memory, which it cannot once the null selector
+    /// has been loaded in protected mode.
+    pub usable: bool,
+    /// The D/B flag. A code segment with it set defaults to 32-bit operands and addresses, rather
+    /// than 16-bit ones, and a stack segment with it set is accessed through ESP rather than SP.
+    pub big: bool,
+}
+
+impl Default for Segment {
+    /// A flat segment, which spans the whole address space. Programs are assembled for a flat
+    /// address space, so every segment starts out like this.
+    fn default() -> Self {
+        Self {
+            base: 0,
+            limit: u32::MAX,
+            rights: None,
+            usable: true,
+            big: true,
         }
     }
 }
 
+impl Segment {
+    /// A 64 KiB, 16-bit segment based at 16 times `selector`, as segments are after a reset in
+    /// real mode, and always are in virtual-8086 mode.
+    pub fn real_mode(selector: u16) -> Self {
+        Self {
+            base: (selector as u32) << 4,
+            limit: 0xffff,
+            rights: None,
+            usable: true,
+            big: false,
+        }
+    }
+}
+
+impl Display for SegmentRegister {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use SegmentRegister::*;
+        let register = match self {
+            Es => "ES",
+            Cs => "CS",
+            Ss => "SS",
+            Ds => "DS",
+            Fs => "FS",
+            Gs => "GS",
+        };
+
+        write!(f, "{register}")
+    }
+}
+
+/// The registers of the processor. The general-purpose and segment registers are read and written
+/// by their type, with [`Registers::read32`], [`Registers::read16`], and [`Registers::read8`] (and
+/// the corresponding `write` methods), while EIP, EFLAGS, and the control and debug registers have
+/// accessors of their own.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers {
     pub(crate) eax: u32,
     pub(crate) ecx: u32,
@@ -693,6 +1587,25 @@ pub struct Registers {
     pub(crate) gs: u16,
     pub(crate) ss: u16,
     pub(crate) eflags: Eflags,
+    pub(crate) control_registers: ControlRegisters,
+    pub(crate) debug_registers: DebugRegisters,
+    pub(crate) gdtr: DescriptorTableRegister,
+    pub(crate) idtr: DescriptorTableRegister,
+    /// Intel manual section 2.4.2 "Local Descriptor Table Register (LDTR)".
+    /// The selector of the LDT's descriptor in the GDT, and the base and limit which `LLDT` caches
+    /// from it. The LDT is unusable until it has been loaded.
+    pub(crate) ldtr: u16,
+    pub(crate) ldt: Segment,
+    /// Intel manual section 2.4.4 "Task Register (TR)".
+    /// The selector of the running task's TSS descriptor in the GDT, and the base and limit which
+    /// are cached from it by `LTR` or a task switch. No task is running until it has been loaded.
+    pub(crate) tr: u16,
+    pub(crate) tss: Segment,
+
+    /// The hidden part of each segment register, indexed by `SegmentRegister::index`. These are
+    /// flat unless a selector has been loaded in real mode, or they have been set explicitly (e.g.
+    /// to point FS at thread-local storage).
+    segments: [Segment; 6],
 
     /// Intel manual section 3.5 "INSTRUCTION POINTER".
     /// Contains offset in current code segment for next instruction to be executed. Cannot be
@@ -777,12 +1690,168 @@ impl Registers {
         self.esp.set_low_16(value);
     }
 
+    /// The general-purpose and segment registers whose values differ from those in `before`.
+    pub(crate) fn changed_since(&self, before: &Registers) -> Vec<Register> {
+        let general_purpose = [
+            (Register32::Eax, self.eax, before.eax),
+            (Register32::Ecx, self.ecx, before.ecx),
+            (Register32::Edx, self.edx, before.edx),
+            (Register32::Ebx, self.ebx, before.ebx),
+            (Register32::Esp, self.esp, before.esp),
+            (Register32::Ebp, self.ebp, before.ebp),
+            (Register32::Esi, self.esi, before.esi),
+            (Register32::Edi, self.edi, before.edi),
+        ];
+        let segment = [
+            (Register16::Es, self.es, before.es),
+            (Register16::Cs, self.cs, before.cs),
+            (Register16::Ss, self.ss, before.ss),
+            (Register16::Ds, self.ds, before.ds),
+            (Register16::Fs, self.fs, before.fs),
+            (Register16::Gs, self.gs, before.gs),
+        ];
+        general_purpose
+            .into_iter()
+            .filter(|(_, after, before)| after != before)
+            .map(|(register, _, _)| register.into())
+            .chain(
+                segment
+                    .into_iter()
+                    .filter(|(_, after, before)| after != before)
+                    .map(|(register, _, _)| register.into()),
+            )
+            .collect()
+    }
+
+    pub fn get_segment(&self, segment: SegmentRegister) -> Segment {
+        self.segments[segment.index()]
+    }
+
+    pub fn set_segment(&mut self, segment: SegmentRegister, value: Segment) {
+        self.segments[segment.index()] = value;
+    }
+
+    pub fn get_segment_base(&self, segment: SegmentRegister) -> u32 {
+        self.segments[segment.index()].base
+    }
+
+    pub fn set_segment_base(&mut self, segment: SegmentRegister, base: u32) {
+        self.segments[segment.index()].base = base;
+    }
+
+    pub fn get_selector(&self, segment: SegmentRegister) -> u16 {
+        use SegmentRegister::*;
+        match segment {
+            Es => self.es,
+            Cs => self.cs,
+            Ss => self.ss,
+            Ds => self.ds,
+            Fs => self.fs,
+            Gs => self.gs,
+        }
+    }
+
+    /// Loads a selector into a segment register. In real mode (i.e. while CR0.PE is clear), the
+    /// base of the segment is the selector shifted left by 4, and as on real processors, the rest
+    /// of the segment is left as it was. In virtual-8086 mode, the whole segment is that of real
+    /// mode after a reset (see `Segment::real_mode`). In protected mode, only the selector is
+    /// loaded, as the descriptor it refers to can only be read from memory (see
+    /// `Cpu::load_segment`).
+    pub fn load_segment(&mut self, segment: SegmentRegister, selector: u16) {
+        use SegmentRegister::*;
+        match segment {
+            Es => self.es = selector,
+            Cs => self.cs = selector,
+            Ss => self.ss = selector,
+            Ds => self.ds = selector,
+            Fs => self.fs = selector,
+            Gs => self.gs = selector,
+        }
+        match self.get_operating_mode() {
+            OperatingMode::Real => self.set_segment_base(segment, (selector as u32) << 4),
+            OperatingMode::Virtual8086 => self.set_segment(segment, Segment::real_mode(selector)),
+            OperatingMode::Protected => (),
+        }
+    }
+
+    pub fn get_eip(&self) -> u32 {
+        self.eip
+    }
+
+    pub fn set_eip(&mut self, value: u32) {
+        self.eip = value;
+    }
+
+    /// Gets the current privilege level. In real mode (CR0.PE clear) the processor always runs at
+    /// CPL 0, and in virtual-8086 mode always at CPL 3. Otherwise, the CPL is held in the lowest 2
+    /// bits of the CS selector.
+    pub fn get_cpl(&self) -> CurrentPrivilegeLevel {
+        match self.get_operating_mode() {
+            OperatingMode::Real => return CurrentPrivilegeLevel::CPL0,
+            OperatingMode::Virtual8086 => return CurrentPrivilegeLevel::CPL3,
+            OperatingMode::Protected => (),
+        }
+
+        match self.cs & 0b11 {
+            0 => CurrentPrivilegeLevel::CPL0,
+            1 => CurrentPrivilegeLevel::CPL1,
+            2 => CurrentPrivilegeLevel::CPL2,
+            _ => CurrentPrivilegeLevel::CPL3,
+        }
+    }
+
+    /// The mode which the processor operates in. Virtual-8086 mode can only be entered from
+    /// protected mode, so EFLAGS.VM is ignored while CR0.PE is clear.
+    pub fn get_operating_mode(&self) -> OperatingMode {
+        if !self.control_registers.get_protection_enable() {
+            OperatingMode::Real
+        } else if self.eflags.get_virtual_8086_mode() {
+            OperatingMode::Virtual8086
+        } else {
+            OperatingMode::Protected
+        }
+    }
+
+    /// The default size of operands and addresses, which is given by the D flag of the code
+    /// segment. Instructions which do not name the size (e.g. `CALL` and `RET`) use it, as does an
+    /// effective address which has no registers to give its size.
+    pub fn get_default_size(&self) -> Size {
+        if self.get_segment(SegmentRegister::Cs).big {
+            Size::Dword
+        } else {
+            Size::Word
+        }
+    }
+
+    /// The stack pointer, which is SP (zero-extended) rather than ESP when the B flag of the stack
+    /// segment is clear.
+    pub fn get_stack_pointer(&self) -> u32 {
+        if self.get_segment(SegmentRegister::Ss).big {
+            self.esp
+        } else {
+            self.esp & 0xffff
+        }
+    }
+
+    /// Sets the stack pointer, which leaves the high WORD of ESP unchanged when the B flag of the
+    /// stack segment is clear.
+    pub fn set_stack_pointer(&mut self, value: u32) {
+        if self.get_segment(SegmentRegister::Ss).big {
+            self.esp = value;
+        } else {
+            self.esp = self.esp & 0xffff_0000 | value & 0xffff;
+        }
+    }
+
+    /// Moves the stack pointer down past a value of the given size, wrapping around the address
+    /// space (or the 64 KiB segment, for a 16-bit stack). Pushes check for overflow before the
+    /// stack is grown (see `Cpu::set_stack_limit`).
     pub fn grow_stack(&mut self, size: &Size) {
-        self.esp -= *size as u32 / 8;
+        self.set_stack_pointer(self.get_stack_pointer().wrapping_sub(*size as u32 / 8));
     }
 
     pub fn shrink_stack(&mut self, size: &Size) {
-        self.esp += *size as u32 / 8;
+        self.set_stack_pointer(self.get_stack_pointer().wrapping_add(*size as u32 / 8));
     }
 
     pub fn read32(&self, register: &Register32) -> u32 {
@@ -844,12 +1913,12 @@ impl Registers {
             Di => self.set_di(value),
             Bp => self.set_bp(value),
             Sp => self.set_bp(value),
-            Cs => self.cs = value,
-            Ds => self.ds = value,
-            Es => self.es = value,
-            Fs => self.fs = value,
-            Gs => self.gs = value,
-            Ss => self.ss = value,
+            Cs => self.load_segment(SegmentRegister::Cs, value),
+            Ds => self.load_segment(SegmentRegister::Ds, value),
+            Es => self.load_segment(SegmentRegister::Es, value),
+            Fs => self.load_segment(SegmentRegister::Fs, value),
+            Gs => self.load_segment(SegmentRegister::Gs, value),
+            Ss => self.load_segment(SegmentRegister::Ss, value),
         }
     }
 
@@ -882,6 +1951,40 @@ impl Registers {
     }
 }
 
+/// A dump of the general-purpose registers, the segment selectors, EIP, and EFLAGS, which are
+/// aligned in columns. For example:
+///
+/// ```text
+/// EAX=00000001  ECX=ffffffff  EDX=00000000  EBX=00000000
+/// ESP=00001000  EBP=00000000  ESI=00000000  EDI=00000000
+/// ES=0000  CS=0000  SS=0000  DS=0000  FS=0000  GS=0000
+/// EIP=00000002  EFLAGS=00000286 [ PF SF IF ]
+/// ```
+impl Display for Registers {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use Register32::*;
+        for row in [[Eax, Ecx, Edx, Ebx], [Esp, Ebp, Esi, Edi]] {
+            let row: Vec<_> = row
+                .iter()
+                .map(|register| format!("{register}={:08x}", self.read32(register)))
+                .collect();
+            writeln!(f, "{}", row.join("  "))?;
+        }
+        let segments: Vec<_> = SegmentRegister::ALL
+            .iter()
+            .map(|segment| format!("{segment}={:04x}", self.get_selector(*segment)))
+            .collect();
+        writeln!(f, "{}", segments.join("  "))?;
+        write!(
+            f,
+            "EIP={:08x}  EFLAGS={:08x} {}",
+            self.eip,
+            self.eflags.get_value(),
+            self.eflags
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -927,6 +2030,28 @@ mod tests {
         test_abcd_register_accessors!(b);
     }
 
+    #[test]
+    fn display() {
+        let mut registers = Registers::default();
+        assert_eq!(registers.eflags.to_string(), "[ ]");
+        registers.eflags.set_value(0x0000_0897);
+        assert_eq!(registers.eflags.to_string(), "[ CF PF AF SF OF ]");
+
+        registers.eflags.set_value(0x286);
+        registers.eax = 1;
+        registers.ecx = 0xffff_ffff;
+        registers.esp = 0x1000;
+        registers.eip = 2;
+        registers.cs = 0x1b;
+        assert_eq!(
+            registers.to_string(),
+            "EAX=00000001  ECX=ffffffff  EDX=00000000  EBX=00000000\n\
+             ESP=00001000  EBP=00000000  ESI=00000000  EDI=00000000\n\
+             ES=0000  CS=001b  SS=0000  DS=0000  FS=0000  GS=0000\n\
+             EIP=00000002  EFLAGS=00000286 [ PF SF IF ]"
+        );
+    }
+
     #[test]
     fn grow_and_shrink_stack() {
         let mut registers = Registers::default();
@@ -944,6 +2069,67 @@ mod tests {
         assert_eq!(registers.esp, 96);
         registers.shrink_stack(&Size::Dword);
         assert_eq!(registers.esp, 100);
+
+        registers.esp = 2;
+        registers.grow_stack(&Size::Dword);
+        assert_eq!(registers.esp, 0xffff_fffe);
+        registers.shrink_stack(&Size::Dword);
+        assert_eq!(registers.esp, 2);
+
+        // A 16-bit stack only moves SP, which wraps around within the segment.
+        registers.esp = 0x1234_0002;
+        registers.set_segment(SegmentRegister::Ss, Segment::real_mode(0));
+        registers.grow_stack(&Size::Dword);
+        assert_eq!(registers.esp, 0x1234_fffe);
+        assert_eq!(registers.get_stack_pointer(), 0xfffe);
+        registers.shrink_stack(&Size::Dword);
+        assert_eq!(registers.esp, 0x1234_0002);
+    }
+
+    #[test]
+    fn load_segment() {
+        let mut registers = Registers::default();
+        registers.load_segment(SegmentRegister::Es, 0xb800);
+        assert_eq!(registers.es, 0xb800);
+        assert_eq!(
+            registers.get_segment(SegmentRegister::Es),
+            Segment {
+                base: 0xb8000,
+                ..Segment::default()
+            }
+        );
+
+        // In protected mode, the descriptor is left for the CPU to load.
+        registers.control_registers.set_protection_enable(true);
+        registers.load_segment(SegmentRegister::Es, 0x10);
+        assert_eq!(registers.es, 0x10);
+        assert_eq!(registers.get_segment_base(SegmentRegister::Es), 0xb8000);
+
+        // In virtual-8086 mode, the whole segment is reloaded as a real-mode segment.
+        registers.eflags.set_virtual_8086_mode(true);
+        assert_eq!(registers.get_operating_mode(), OperatingMode::Virtual8086);
+        registers.load_segment(SegmentRegister::Es, 0x10);
+        assert_eq!(
+            registers.get_segment(SegmentRegister::Es),
+            Segment::real_mode(0x10)
+        );
+    }
+
+    #[test]
+    fn cpl() {
+        let mut registers = Registers::default();
+        registers.cs = 0x1b;
+        assert_eq!(registers.get_cpl(), CurrentPrivilegeLevel::CPL0);
+
+        registers.control_registers.set_protection_enable(true);
+        assert_eq!(registers.get_cpl(), CurrentPrivilegeLevel::CPL3);
+        registers.cs = 0x08;
+        assert_eq!(registers.get_cpl(), CurrentPrivilegeLevel::CPL0);
+        registers.cs = 0x11;
+        assert_eq!(registers.get_cpl(), CurrentPrivilegeLevel::CPL1);
+
+        registers.eflags.set_virtual_8086_mode(true);
+        assert_eq!(registers.get_cpl(), CurrentPrivilegeLevel::CPL3);
     }
 
     mod eflags {
@@ -1200,6 +2386,27 @@ mod tests {
             assert!(!eflags.get_auxiliary_carry_flag());
         }
 
+        #[test]
+        fn value() {
+            let mut eflags = Eflags::default();
+            assert_eq!(eflags.get_value(), 0b10);
+
+            eflags.set_carry_flag(true);
+            eflags.set_zero_flag(true);
+            assert_eq!(eflags.get_value(), 0b100_0011);
+
+            eflags.set_value(0b1000_0000_0010);
+            assert!(eflags.get_overflow_flag());
+            assert!(!eflags.get_carry_flag());
+            assert!(!eflags.get_zero_flag());
+
+            // Reserved bits cannot be changed.
+            eflags.set_value(0);
+            assert_eq!(eflags.get_value(), 0b10);
+            eflags.set_value(u32::MAX);
+            assert_eq!(eflags.get_value(), 0x003f_7fd7);
+        }
+
         #[test]
         fn zero_flag() {
             let mut eflags = Eflags::default();
@@ -1232,4 +2439,123 @@ mod tests {
             assert!(eflags.get_sign_flag());
         }
     }
+
+    mod control_registers {
+        use super::*;
+
+        #[test]
+        fn cr0() {
+            let mut control_registers = ControlRegisters::default();
+            assert_eq!(control_registers.get_cr0(), 0x6000_0010);
+            assert!(control_registers.get_cache_disable());
+            assert!(control_registers.get_not_write_through());
+            assert!(!control_registers.get_protection_enable());
+
+            control_registers.set_protection_enable(true);
+            control_registers.set_paging(true);
+            assert_eq!(control_registers.get_cr0(), 0xe000_0011);
+
+            // ET is hardwired to 1.
+            control_registers.set_cr0(0x8000_0001);
+            assert_eq!(control_registers.get_cr0(), 0x8000_0011);
+            assert!(control_registers.get_paging());
+            assert!(!control_registers.get_cache_disable());
+        }
+
+        #[test]
+        fn cr4() {
+            let mut control_registers = ControlRegisters::default();
+            assert_eq!(control_registers.get_cr4(), 0);
+
+            control_registers.set_page_size_extensions(true);
+            control_registers.set_osfxsr(true);
+            assert_eq!(control_registers.get_cr4(), 0x210);
+
+            control_registers.set_cr4(0x20);
+            assert!(control_registers.get_physical_address_extension());
+            assert!(!control_registers.get_page_size_extensions());
+        }
+
+        #[test]
+        fn read_and_write() {
+            let mut control_registers = ControlRegisters::default();
+            control_registers.write(&ControlRegister::Cr2, 0xdead_beef);
+            control_registers.write(&ControlRegister::Cr3, 0x0010_0000);
+            assert_eq!(control_registers.read(&ControlRegister::Cr2), 0xdead_beef);
+            assert_eq!(control_registers.read(&ControlRegister::Cr3), 0x0010_0000);
+            assert_eq!(control_registers.read(&ControlRegister::Cr0), 0x6000_0010);
+        }
+    }
+
+    mod debug_registers {
+        use super::*;
+
+        #[test]
+        fn reserved_bits() {
+            let mut debug_registers = DebugRegisters::default();
+            assert_eq!(debug_registers.get_dr6(), 0xffff_0ff0);
+            assert_eq!(debug_registers.get_dr7(), 0x0000_0400);
+
+            debug_registers.set_dr6(0);
+            assert_eq!(debug_registers.get_dr6(), 0xffff_0ff0);
+            debug_registers.set_dr6(u32::MAX);
+            assert_eq!(debug_registers.get_dr6(), 0xffff_efff);
+            debug_registers.set_dr7(u32::MAX);
+            assert_eq!(debug_registers.get_dr7(), 0xffff_27ff);
+        }
+
+        #[test]
+        fn dr7() {
+            let mut debug_registers = DebugRegisters::default();
+            debug_registers.write(&DebugRegister::Dr7, 0x1d20_0086);
+            assert!(!debug_registers.get_local_breakpoint_enable(0));
+            assert!(debug_registers.get_global_breakpoint_enable(0));
+            assert!(debug_registers.get_local_breakpoint_enable(1));
+            assert!(!debug_registers.get_global_breakpoint_enable(1));
+            assert!(!debug_registers.get_local_breakpoint_enable(3));
+            assert!(debug_registers.get_global_breakpoint_enable(3));
+            assert_eq!(debug_registers.get_breakpoint_condition(0), 0b00);
+            assert_eq!(debug_registers.get_breakpoint_length(0), 0b00);
+            assert_eq!(debug_registers.get_breakpoint_condition(1), 0b10);
+            assert_eq!(debug_registers.get_breakpoint_condition(2), 0b01);
+            assert_eq!(debug_registers.get_breakpoint_length(2), 0b11);
+            assert_eq!(debug_registers.get_breakpoint_condition(3), 0b01);
+            assert_eq!(debug_registers.get_breakpoint_length(3), 0b00);
+        }
+
+        #[test]
+        fn breakpoints() {
+            let mut debug_registers = DebugRegisters::default();
+            debug_registers.set_breakpoint(0, 0x1000, BreakpointCondition::Execute, 1);
+            debug_registers.set_breakpoint(2, 0x2006, BreakpointCondition::Write, 4);
+            debug_registers.set_breakpoint(3, 0x2010, BreakpointCondition::ReadWrite, 2);
+            assert_eq!(debug_registers.get_dr7(), 0x7d00_04a2);
+            assert_eq!(debug_registers.instruction_breakpoints(0x1000), 0b0001);
+            assert_eq!(debug_registers.instruction_breakpoints(0x1001), 0);
+
+            // The monitored location is aligned to its length, so 0x2004 to 0x2007 is monitored.
+            let write = |address, length| {
+                debug_registers.data_breakpoints(address, length, AccessKind::Write)
+            };
+            assert_eq!(write(0x2000, 4), 0);
+            assert_eq!(write(0x2002, 4), 0b0100);
+            assert_eq!(write(0x2007, 1), 0b0100);
+            assert_eq!(write(0x2008, 8), 0);
+            assert_eq!(write(0x200c, 8), 0b1000);
+            assert_eq!(
+                debug_registers.data_breakpoints(0x2004, 4, AccessKind::Read),
+                0
+            );
+            assert_eq!(
+                debug_registers.data_breakpoints(0x2011, 1, AccessKind::Read),
+                0b1000
+            );
+
+            debug_registers.clear_breakpoint(3);
+            assert_eq!(
+                debug_registers.data_breakpoints(0x200c, 8, AccessKind::Write),
+                0
+            );
+        }
+    }
 }
@@ -0,0 +1,98 @@
+/// A bitmap (e.g. EFLAGS or CR0), which is serialized as the integer which holds its bits.
+pub(crate) mod bitmap {
+    use bitmaps::{Bitmap, Bits, BitsImpl};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn serialize<const SIZE: usize, S>(
+        bitmap: &Bitmap<SIZE>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        BitsImpl<SIZE>: Bits,
+        <BitsImpl<SIZE> as Bits>::Store: Serialize,
+        S: Serializer,
+    {
+        bitmap.into_value().serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, const SIZE: usize, D>(
+        deserializer: D,
+    ) -> Result<Bitmap<SIZE>, D::Error>
+    where
+        BitsImpl<SIZE>: Bits,
+        <BitsImpl<SIZE> as Bits>::Store: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer).map(Bitmap::from_value)
+    }
+}
+
+/// A run of bytes, which is serialized as a string of hexadecimal digits by human-readable formats
+/// (e.g. JSON), such that golden files stay legible, and as bytes by the others.
+pub(crate) mod bytes {
+    use alloc::{format, string::String, vec::Vec};
+    use core::fmt;
+
+    use serde::{
+        de::{self, SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(bytes);
+        }
+        let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BytesVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("bytes, or a string of hexadecimal digits")
+        }
+
+        fn visit_str<E: de::Error>(self, hex: &str) -> Result<Self::Value, E> {
+            // Each byte is a pair of digits, so an odd digit at the end is invalid.
+            hex.as_bytes()
+                .chunks(2)
+                .map(|digits| {
+                    core::str::from_utf8(digits)
+                        .ok()
+                        .filter(|digits| digits.len() == 2)
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .ok_or_else(|| E::invalid_value(de::Unexpected::Str(hex), &self))
+                })
+                .collect()
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(bytes)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
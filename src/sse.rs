@@ -0,0 +1,596 @@
+use core::cmp::Ordering;
+
+use bitmaps::Bitmap;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+use paste::paste;
+
+use crate::fpu::RoundingMode;
+
+/// Intel manual section 11.5.2 "SIMD Floating-Point Exception Conditions". The SIMD floating-point
+/// exceptions, each represented by the same bit as its flag in MXCSR. The corresponding mask is
+/// the same bit shifted left by 7.
+pub const INVALID_OPERATION: u32 = 1 << 0;
+pub const DENORMAL_OPERAND: u32 = 1 << 1;
+pub const DIVIDE_BY_ZERO: u32 = 1 << 2;
+pub const OVERFLOW: u32 = 1 << 3;
+pub const UNDERFLOW: u32 = 1 << 4;
+pub const PRECISION: u32 = 1 << 5;
+
+/// The arithmetic operations which SSE performs on packed and scalar floating-point values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticOperation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl ArithmeticOperation {
+    /// Computes the operation in double precision, returning the result along with its error,
+    /// such that the infinitely precise result is the sum of the two. The error is exact, except
+    /// for division, where only its sign is guaranteed to be correct. Either way, this is enough to
+    /// round the result correctly in any rounding mode.
+    fn compute(&self, lhs: f64, rhs: f64) -> (f64, f64) {
+        // Knuth's TwoSum, which recovers the rounding error of an addition exactly.
+        let two_sum = |lhs: f64, rhs: f64| {
+            let sum = lhs + rhs;
+            let rhs_part = sum - lhs;
+            let error = (lhs - (sum - rhs_part)) + (rhs - rhs_part);
+            (sum, error)
+        };
+
+        match self {
+            ArithmeticOperation::Add => two_sum(lhs, rhs),
+            ArithmeticOperation::Subtract => two_sum(lhs, -rhs),
+            ArithmeticOperation::Multiply => {
+                let product = lhs * rhs;
+                (product, lhs.mul_add(rhs, -product))
+            }
+            ArithmeticOperation::Divide => {
+                let quotient = lhs / rhs;
+                let remainder = (-quotient).mul_add(rhs, lhs);
+                (quotient, remainder / rhs)
+            }
+        }
+    }
+}
+
+/// The floating-point formats which SSE operates on: single precision, and (with SSE2) double
+/// precision. The values are stored in the lanes of the XMM registers as their raw bits.
+pub trait SimdFloat: Copy + PartialOrd {
+    /// The width of the format in bits, which is also the width of a lane holding it.
+    const BITS: usize;
+    const ZERO: Self;
+    const MIN_POSITIVE: Self;
+    /// The QNaN floating-point indefinite, which is the result of an invalid operation when the
+    /// invalid operation exception is masked.
+    const DEFAULT_NAN: Self;
+
+    fn from_lane(lane: u128) -> Self;
+    fn to_lane(self) -> u128;
+    fn is_nan(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_finite(self) -> bool;
+    fn is_subnormal(self) -> bool;
+    fn abs(self) -> Self;
+    fn copysign(self, sign: Self) -> Self;
+    fn next_up(self) -> Self;
+    fn next_down(self) -> Self;
+
+    /// Computes `operation`, rounding the result to nearest. The ordering of the rounded result
+    /// relative to the infinitely precise result is also returned, which is `Equal` if it is exact.
+    fn compute(operation: ArithmeticOperation, lhs: Self, rhs: Self) -> (Self, Ordering);
+
+    /// The quiet bit, which is the most significant bit of the fraction.
+    fn quiet_bit() -> u128;
+
+    /// Whether the value is a signaling NaN, i.e. a NaN whose quiet bit is clear.
+    fn is_signaling(self) -> bool {
+        self.is_nan() && self.to_lane() & Self::quiet_bit() == 0
+    }
+
+    /// Converts an SNaN into a QNaN by setting its quiet bit.
+    fn quieted(self) -> Self {
+        Self::from_lane(self.to_lane() | Self::quiet_bit())
+    }
+}
+
+macro_rules! simd_float {
+    ($float:ident, $bits:ident, $default_nan:literal, $compute:ident) => {
+        impl SimdFloat for $float {
+            const BITS: usize = $bits::BITS as usize;
+            const ZERO: Self = 0.0;
+            const MIN_POSITIVE: Self = $float::MIN_POSITIVE;
+            const DEFAULT_NAN: Self = $float::from_bits($default_nan);
+
+            fn from_lane(lane: u128) -> Self {
+                $float::from_bits(lane as $bits)
+            }
+
+            fn to_lane(self) -> u128 {
+                self.to_bits() as u128
+            }
+
+            fn is_nan(self) -> bool {
+                $float::is_nan(self)
+            }
+
+            fn is_infinite(self) -> bool {
+                $float::is_infinite(self)
+            }
+
+            fn is_finite(self) -> bool {
+                $float::is_finite(self)
+            }
+
+            fn is_subnormal(self) -> bool {
+                $float::is_subnormal(self)
+            }
+
+            fn abs(self) -> Self {
+                $float::abs(self)
+            }
+
+            fn copysign(self, sign: Self) -> Self {
+                $float::copysign(self, sign)
+            }
+
+            fn next_up(self) -> Self {
+                $float::next_up(self)
+            }
+
+            fn next_down(self) -> Self {
+                $float::next_down(self)
+            }
+
+            fn compute(operation: ArithmeticOperation, lhs: Self, rhs: Self) -> (Self, Ordering) {
+                $compute(operation, lhs, rhs)
+            }
+
+            fn quiet_bit() -> u128 {
+                1 << ($float::MANTISSA_DIGITS - 2)
+            }
+        }
+    };
+}
+
+simd_float!(f32, u32, 0xffc0_0000, compute_single);
+simd_float!(f64, u64, 0xfff8_0000_0000_0000, compute_double);
+
+/// Computes an operation on single-precision values. The result is computed in double precision
+/// first, which (along with its error) is then rounded to single precision.
+fn compute_single(operation: ArithmeticOperation, lhs: f32, rhs: f32) -> (f32, Ordering) {
+    let (result, error) = operation.compute(lhs as f64, rhs as f64);
+    let nearest = result as f32;
+    let ordering = if !result.is_finite() {
+        // Double precision cannot overflow with single-precision operands, so the result is an
+        // exact infinity or a NaN.
+        Ordering::Equal
+    } else if nearest.is_infinite() {
+        // Overflow, in which case the result is rounded away from zero.
+        nearest.partial_cmp(&0.0).unwrap()
+    } else {
+        // The difference is exact, as `nearest` is within an ulp of `result`.
+        (nearest as f64 - result).partial_cmp(&error).unwrap()
+    };
+    (nearest, ordering)
+}
+
+/// Computes an operation on double-precision values, which is rounded to nearest by the host.
+fn compute_double(operation: ArithmeticOperation, lhs: f64, rhs: f64) -> (f64, Ordering) {
+    let (result, error) = operation.compute(lhs, rhs);
+    let exact_infinity = !lhs.is_finite()
+        || !rhs.is_finite()
+        || (operation == ArithmeticOperation::Divide && rhs == 0.0);
+    let ordering = if result.is_nan() || (result.is_infinite() && exact_infinity) {
+        Ordering::Equal
+    } else if result.is_infinite() {
+        // Overflow, in which case the result is rounded away from zero.
+        result.partial_cmp(&0.0).unwrap()
+    } else {
+        0.0.partial_cmp(&error).unwrap()
+    };
+    (result, ordering)
+}
+
+/// Intel manual section 10.2 "SSE PROGRAMMING ENVIRONMENT".
+/// The XMM registers and MXCSR.
+/// - XMM0-XMM7 are 128 bits wide, and hold packed single-precision floating-point values (or, with
+///   SSE2, packed double-precision floating-point values and packed integers).
+/// - MXCSR holds the SIMD floating-point exception flags (bits 0-5), the denormals are zeros flag
+///   (bit 6), the exception masks (bits 7-12), the rounding control field (bits 13 and 14), and the
+///   flush to zero flag (bit 15). Bits 16-31 are reserved.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sse {
+    registers: [u128; 8],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::bitmap"))]
+    mxcsr: Bitmap<32>,
+}
+
+macro_rules! mxcsr_flag {
+    ($field_name:ident, $bit:literal) => {
+        paste! {
+            pub fn [<get_ $field_name>](&self) -> bool {
+                self.mxcsr.get($bit)
+            }
+        }
+    };
+}
+
+impl Sse {
+    mxcsr_flag!(denormals_are_zeros, 6);
+    mxcsr_flag!(underflow_mask, 11);
+    mxcsr_flag!(flush_to_zero, 15);
+
+    pub fn read_xmm(&self, index: usize) -> u128 {
+        self.registers[index]
+    }
+
+    pub fn write_xmm(&mut self, index: usize, value: u128) {
+        self.registers[index] = value;
+    }
+
+    pub fn get_mxcsr(&self) -> u32 {
+        *self.mxcsr.as_value()
+    }
+
+    /// Sets the raw value of MXCSR. The reserved bits are cleared regardless of `value`.
+    pub fn set_mxcsr(&mut self, value: u32) {
+        self.mxcsr = Bitmap::from_value(value & 0xffff);
+    }
+
+    pub fn get_rounding_mode(&self) -> RoundingMode {
+        match (self.get_mxcsr() >> 13) & 0b11 {
+            0 => RoundingMode::Nearest,
+            1 => RoundingMode::Down,
+            2 => RoundingMode::Up,
+            _ => RoundingMode::TowardZero,
+        }
+    }
+
+    /// Records the given exceptions in the MXCSR flags, returning whether any of them are
+    /// unmasked. In that case, the instruction which detected them must not write its result, and
+    /// a SIMD floating-point exception must be raised instead.
+    pub fn signal_exceptions(&mut self, exceptions: u32) -> bool {
+        let mxcsr = self.get_mxcsr();
+        self.set_mxcsr(mxcsr | exceptions);
+        let masks = (mxcsr >> 7) & 0x3f;
+        exceptions & !masks != 0
+    }
+
+    /// Applies the denormals are zeros mode to an operand. A denormal operand is replaced with a
+    /// zero of the same sign if DAZ is set, and otherwise signals a denormal operand exception.
+    fn denormal_operand<T: SimdFloat>(&self, operand: T, exceptions: &mut u32) -> T {
+        if !operand.is_subnormal() {
+            return operand;
+        }
+
+        if self.get_denormals_are_zeros() {
+            return T::ZERO.copysign(operand);
+        }
+
+        *exceptions |= DENORMAL_OPERAND;
+        operand
+    }
+
+    /// Applies the current rounding mode to a result which was rounded to nearest, given the
+    /// ordering of that result relative to the infinitely precise result.
+    fn round<T: SimdFloat>(&self, nearest: T, ordering: Ordering) -> T {
+        match (ordering, self.get_rounding_mode()) {
+            (Ordering::Equal, _) | (_, RoundingMode::Nearest) => nearest,
+            (Ordering::Greater, RoundingMode::Down) => nearest.next_down(),
+            (Ordering::Greater, RoundingMode::TowardZero) if nearest > T::ZERO => {
+                nearest.next_down()
+            }
+            (Ordering::Less, RoundingMode::Up) => nearest.next_up(),
+            (Ordering::Less, RoundingMode::TowardZero) if nearest < T::ZERO => nearest.next_up(),
+            _ => nearest,
+        }
+    }
+
+    /// Intel manual section 11.5.2 "SIMD Floating-Point Exception Conditions". Performs an
+    /// arithmetic operation on two floating-point values as SSE does, returning the result along
+    /// with the exceptions which were detected. The MXCSR flags are not updated; see
+    /// `signal_exceptions`. The result assumes that every exception is masked.
+    pub fn arithmetic<T: SimdFloat>(
+        &self,
+        operation: ArithmeticOperation,
+        lhs: T,
+        rhs: T,
+    ) -> (T, u32) {
+        let mut exceptions = 0;
+        if lhs.is_nan() || rhs.is_nan() {
+            if lhs.is_signaling() || rhs.is_signaling() {
+                exceptions |= INVALID_OPERATION;
+            }
+            // The first source operand takes priority if both are NaNs.
+            let nan = if lhs.is_nan() { lhs } else { rhs };
+            return (nan.quieted(), exceptions);
+        }
+
+        let lhs = self.denormal_operand(lhs, &mut exceptions);
+        let rhs = self.denormal_operand(rhs, &mut exceptions);
+        let (nearest, ordering) = T::compute(operation, lhs, rhs);
+        if nearest.is_nan() {
+            // E.g. infinity minus infinity, or 0 divided by 0.
+            return (T::DEFAULT_NAN, exceptions | INVALID_OPERATION);
+        }
+
+        if nearest.is_infinite() && ordering == Ordering::Equal {
+            if operation == ArithmeticOperation::Divide && rhs == T::ZERO && lhs.is_finite() {
+                exceptions |= DIVIDE_BY_ZERO;
+            }
+            return (nearest, exceptions);
+        }
+
+        let inexact = ordering != Ordering::Equal;
+        let mut rounded = self.round(nearest, ordering);
+        if nearest.is_infinite() || rounded.is_infinite() {
+            exceptions |= OVERFLOW | PRECISION;
+        } else if nearest.abs() < T::MIN_POSITIVE && (nearest != T::ZERO || inexact) {
+            // Tininess is detected before rounding. When underflow is masked, it is only signaled
+            // if the result is also inexact, unless flush to zero replaces the result with 0.
+            if !self.get_underflow_mask() {
+                exceptions |= UNDERFLOW;
+            } else if self.get_flush_to_zero() {
+                rounded = T::ZERO.copysign(rounded);
+                exceptions |= UNDERFLOW | PRECISION;
+            } else if inexact {
+                exceptions |= UNDERFLOW;
+            }
+        }
+
+        if inexact {
+            exceptions |= PRECISION;
+        }
+        (rounded, exceptions)
+    }
+
+    /// Compares two floating-point values using one of the predicates encoded in the immediate
+    /// operand of `CMPPS` (only the low 3 bits are used), returning the result along with the
+    /// exceptions which were detected. An SNaN operand is an invalid operation, as is a QNaN
+    /// operand to any of the ordering predicates (LT, LE, NLT, and NLE).
+    ///
+    /// | Predicate | Name  | Description                 | Unordered result |
+    /// | --------- | ----- | --------------------------- | ---------------- |
+    /// | 0         | EQ    | Equal                       | false            |
+    /// | 1         | LT    | Less-than                   | false            |
+    /// | 2         | LE    | Less-than-or-equal          | false            |
+    /// | 3         | UNORD | Unordered                   | true             |
+    /// | 4         | NEQ   | Not-equal                   | true             |
+    /// | 5         | NLT   | Not-less-than               | true             |
+    /// | 6         | NLE   | Not-less-than-or-equal      | true             |
+    /// | 7         | ORD   | Ordered                     | false            |
+    pub fn compare<T: SimdFloat>(&self, predicate: u8, lhs: T, rhs: T) -> (bool, u32) {
+        let mut exceptions = 0;
+        let predicate = predicate & 0b111;
+        let unordered = lhs.is_nan() || rhs.is_nan();
+        if lhs.is_signaling()
+            || rhs.is_signaling()
+            || (unordered && matches!(predicate, 1 | 2 | 5 | 6))
+        {
+            exceptions |= INVALID_OPERATION;
+        }
+
+        let lhs = self.denormal_operand(lhs, &mut exceptions);
+        let rhs = self.denormal_operand(rhs, &mut exceptions);
+        let result = match predicate {
+            0 => lhs == rhs,
+            1 => lhs < rhs,
+            2 => lhs <= rhs,
+            3 => unordered,
+            4 => lhs != rhs,
+            5 => !matches!(lhs.partial_cmp(&rhs), Some(Ordering::Less)),
+            6 => !matches!(
+                lhs.partial_cmp(&rhs),
+                Some(Ordering::Less | Ordering::Equal)
+            ),
+            _ => !unordered,
+        };
+        (result, exceptions)
+    }
+}
+
+impl Default for Sse {
+    /// Intel manual section 10.2.3 "MXCSR Control and Status Register". MXCSR is 0x1f80 following
+    /// reset, such that every exception is masked and results are rounded to nearest.
+    fn default() -> Self {
+        let mut sse = Self {
+            registers: [0; 8],
+            mxcsr: Bitmap::new(),
+        };
+        sse.set_mxcsr(0x1f80);
+        sse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mxcsr() {
+        let mut sse = Sse::default();
+        assert_eq!(sse.get_mxcsr(), 0x1f80);
+        assert_eq!(sse.get_rounding_mode(), RoundingMode::Nearest);
+        assert_ne!(sse.get_mxcsr() & PRECISION << 7, 0);
+
+        sse.set_mxcsr(0xffff_ffff);
+        assert_eq!(sse.get_mxcsr(), 0xffff);
+        assert_eq!(sse.get_rounding_mode(), RoundingMode::TowardZero);
+
+        sse.set_mxcsr(0x1f80);
+        sse.set_mxcsr(0x1f80 | (RoundingMode::Up as u32) << 13);
+        assert_eq!(sse.get_mxcsr(), 0x5f80);
+    }
+
+    #[test]
+    fn signal_exceptions() {
+        let mut sse = Sse::default();
+        assert!(!sse.signal_exceptions(DIVIDE_BY_ZERO | PRECISION));
+        assert_eq!(sse.get_mxcsr(), 0x1f84 | 0x20);
+
+        sse.set_mxcsr(sse.get_mxcsr() & !(DIVIDE_BY_ZERO << 7));
+        assert!(!sse.signal_exceptions(PRECISION));
+        assert!(sse.signal_exceptions(DIVIDE_BY_ZERO));
+    }
+
+    #[test]
+    fn arithmetic_single() {
+        use ArithmeticOperation::*;
+        let mut sse = Sse::default();
+        assert_eq!(sse.arithmetic::<f32>(Add, 1.5, 2.25), (3.75, 0));
+        assert_eq!(sse.arithmetic::<f32>(Subtract, 1.0, 4.0), (-3.0, 0));
+        assert_eq!(sse.arithmetic::<f32>(Multiply, 3.0, -0.5), (-1.5, 0));
+        assert_eq!(
+            sse.arithmetic::<f32>(Divide, 1.0, 0.0),
+            (f32::INFINITY, DIVIDE_BY_ZERO)
+        );
+
+        let (result, exceptions) = sse.arithmetic::<f32>(Divide, 0.0, 0.0);
+        assert_eq!(result.to_bits(), <f32 as SimdFloat>::DEFAULT_NAN.to_bits());
+        assert_eq!(exceptions, INVALID_OPERATION);
+
+        // An SNaN is quieted, and takes priority as the first operand.
+        let signaling = f32::from_bits(0x7f80_0001);
+        let (result, exceptions) = sse.arithmetic::<f32>(Add, signaling, f32::NAN);
+        assert_eq!(result.to_bits(), 0x7fc0_0001);
+        assert_eq!(exceptions, INVALID_OPERATION);
+
+        assert_eq!(
+            sse.arithmetic::<f32>(Multiply, f32::MAX, 2.0),
+            (f32::INFINITY, OVERFLOW | PRECISION)
+        );
+        assert_eq!(
+            sse.arithmetic::<f32>(Multiply, f32::MIN_POSITIVE, 0.75),
+            (f32::MIN_POSITIVE * 0.75, 0)
+        );
+        assert_eq!(
+            sse.arithmetic::<f32>(Multiply, f32::MIN_POSITIVE, 1.0 / 3.0),
+            (f32::from_bits(0x002a_aaab), UNDERFLOW | PRECISION)
+        );
+        assert_eq!(
+            sse.arithmetic::<f32>(Add, f32::from_bits(1), 1.0),
+            (1.0, DENORMAL_OPERAND | PRECISION)
+        );
+
+        // DAZ and FTZ.
+        sse.set_mxcsr(sse.get_mxcsr() | 1 << 6 | 1 << 15);
+        assert_eq!(sse.arithmetic::<f32>(Add, f32::from_bits(1), 1.0), (1.0, 0));
+        assert_eq!(
+            sse.arithmetic::<f32>(Multiply, f32::MIN_POSITIVE, 0.75),
+            (0.0, UNDERFLOW | PRECISION)
+        );
+    }
+
+    #[test]
+    fn round_to_single() {
+        use ArithmeticOperation::*;
+        let mut sse = Sse::default();
+        let third = 1.0 / 3.0_f32;
+        for (rounding_mode, expected) in [
+            (RoundingMode::Nearest, [third, -third, 1.0, f32::INFINITY]),
+            // The nearest single-precision value to 1/3 is slightly greater than it.
+            (
+                RoundingMode::Down,
+                [third.next_down(), -third, 1.0, f32::MAX],
+            ),
+            (
+                RoundingMode::Up,
+                [third, (-third).next_up(), 1.0_f32.next_up(), f32::INFINITY],
+            ),
+            (
+                RoundingMode::TowardZero,
+                [third.next_down(), (-third).next_up(), 1.0, f32::MAX],
+            ),
+        ] {
+            sse.set_mxcsr(0x1f80 | (rounding_mode as u32) << 13);
+            let results = [
+                sse.arithmetic::<f32>(Divide, 1.0, 3.0).0,
+                sse.arithmetic::<f32>(Divide, -1.0, 3.0).0,
+                // 2^-100 is lost entirely when adding in double precision.
+                sse.arithmetic::<f32>(Add, 1.0, 2.0_f32.powi(-100)).0,
+                sse.arithmetic::<f32>(Multiply, f32::MAX, 2.0).0,
+            ];
+            assert_eq!(results, expected, "{rounding_mode:?}");
+        }
+    }
+
+    #[test]
+    fn arithmetic_double() {
+        use ArithmeticOperation::*;
+        let mut sse = Sse::default();
+        assert_eq!(sse.arithmetic(Add, 0.1, 0.2), (0.1 + 0.2, PRECISION));
+        assert_eq!(sse.arithmetic(Multiply, 1.5, -4.0), (-6.0, 0));
+        assert_eq!(
+            sse.arithmetic(Multiply, f64::MAX, 2.0),
+            (f64::INFINITY, OVERFLOW | PRECISION)
+        );
+        assert_eq!(
+            sse.arithmetic(Divide, -1.0, 0.0),
+            (f64::NEG_INFINITY, DIVIDE_BY_ZERO)
+        );
+        assert_eq!(
+            sse.arithmetic(Multiply, f64::MIN_POSITIVE, 0.5),
+            (f64::MIN_POSITIVE * 0.5, 0)
+        );
+
+        let signaling = f64::from_bits(0x7ff0_0000_0000_0001);
+        let (result, exceptions) = sse.arithmetic(Subtract, 1.0, signaling);
+        assert_eq!(result.to_bits(), 0x7ff8_0000_0000_0001);
+        assert_eq!(exceptions, INVALID_OPERATION);
+
+        let third = 1.0 / 3.0_f64;
+        for (rounding_mode, expected) in [
+            (RoundingMode::Nearest, [third, 1.0, f64::INFINITY]),
+            // The nearest double-precision value to 1/3 is slightly less than it.
+            (RoundingMode::Down, [third, 1.0, f64::MAX]),
+            (
+                RoundingMode::Up,
+                [third.next_up(), 1.0_f64.next_up(), f64::INFINITY],
+            ),
+            (RoundingMode::TowardZero, [third, 1.0, f64::MAX]),
+        ] {
+            sse.set_mxcsr(0x1f80 | (rounding_mode as u32) << 13);
+            let results = [
+                sse.arithmetic(Divide, 1.0, 3.0).0,
+                sse.arithmetic(Add, 1.0, 2.0_f64.powi(-100)).0,
+                sse.arithmetic(Multiply, f64::MAX, 2.0).0,
+            ];
+            assert_eq!(results, expected, "{rounding_mode:?}");
+        }
+    }
+
+    #[test]
+    fn compare_single() {
+        let sse = Sse::default();
+        let results = |lhs: f32, rhs: f32| {
+            (0..8)
+                .map(|predicate| sse.compare::<f32>(predicate, lhs, rhs))
+                .collect::<Vec<_>>()
+        };
+
+        let none = |result: bool| (result, 0);
+        assert_eq!(
+            results(1.0, 2.0),
+            [false, true, true, false, true, false, false, true].map(none)
+        );
+        assert_eq!(
+            results(2.0, 2.0),
+            [true, false, true, false, false, true, false, true].map(none)
+        );
+
+        let exceptions = results(f32::NAN, 2.0)
+            .into_iter()
+            .map(|(_, exceptions)| exceptions)
+            .collect::<Vec<_>>();
+        let invalid = INVALID_OPERATION;
+        assert_eq!(exceptions, [0, invalid, invalid, 0, 0, invalid, invalid, 0]);
+        assert_eq!(
+            sse.compare::<f32>(0, f32::from_bits(0x7f80_0001), 2.0),
+            (false, INVALID_OPERATION)
+        );
+    }
+}
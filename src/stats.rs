@@ -0,0 +1,191 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::{instruction::canonical_mnemonic, machine::StepReport, memory::AccessKind};
+
+/// Statistics about the instructions which a [`Machine`](crate::machine::Machine) has executed,
+/// which it collects once they are enabled with
+/// [`Machine::collect_stats`](crate::machine::Machine::collect_stats).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of instructions which were executed, including those which raised exceptions.
+    pub instructions: u64,
+    /// The number of cycles which the instructions took (see [`Cpu::cycles`]).
+    ///
+    /// [`Cpu::cycles`]: crate::cpu::Cpu::cycles
+    pub cycles: u64,
+    /// How many times the instructions with each mnemonic were executed. Aliases are counted
+    /// under the same mnemonic (see [`Stats::histogram`]).
+    pub mnemonics: BTreeMap<String, u64>,
+    /// The number of reads from memory, and the total number of bytes which they read.
+    pub reads: u64,
+    pub bytes_read: u64,
+    /// The number of writes to memory, and the total number of bytes which they wrote.
+    pub writes: u64,
+    pub bytes_written: u64,
+    /// The number of conditional branches (`Jcc`, `JECXZ`, and `LOOP`) which were executed, and
+    /// how many of them were taken.
+    pub branches: u64,
+    pub branches_taken: u64,
+}
+
+impl Stats {
+    /// Counts what an instruction did, where `eip` is EIP after it was executed, and `cycles` is
+    /// the number of cycles which it took.
+    pub(crate) fn record(&mut self, report: &StepReport, eip: u32, cycles: u64) {
+        self.cycles += cycles;
+        for access in &report.memory_accesses {
+            let bytes = access.size as u64 / 8;
+            match access.kind {
+                AccessKind::Write => {
+                    self.writes += 1;
+                    self.bytes_written += bytes;
+                }
+                _ => {
+                    self.reads += 1;
+                    self.bytes_read += bytes;
+                }
+            }
+        }
+
+        let Some(instruction) = &report.instruction else {
+            return;
+        };
+        self.instructions += 1;
+        let mnemonic = canonical_mnemonic(&instruction.mnemonic);
+        if is_conditional_branch(&mnemonic) {
+            self.branches += 1;
+            // An instruction which is not taken falls through to the next one.
+            if eip != report.address.wrapping_add(report.bytes.len() as u32) {
+                self.branches_taken += 1;
+            }
+        }
+        *self.mnemonics.entry(mnemonic).or_default() += 1;
+    }
+
+    /// The mnemonics which were executed, from the most to the least frequent, with ties in
+    /// alphabetical order.
+    pub fn histogram(&self) -> Vec<(&str, u64)> {
+        let mut histogram: Vec<_> = self
+            .mnemonics
+            .iter()
+            .map(|(mnemonic, count)| (mnemonic.as_str(), *count))
+            .collect();
+        histogram.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+        histogram
+    }
+}
+
+/// Whether the (canonical) mnemonic is that of a branch which may or may not be taken.
+fn is_conditional_branch(mnemonic: &str) -> bool {
+    mnemonic.starts_with('J') && mnemonic != "JMP" || mnemonic.starts_with("LOOP")
+}
+
+/// The report written by `--stats` once the program has stopped. For example:
+///
+/// ```text
+/// instructions executed: 11
+/// cycles: 21 (1.91 per instruction)
+/// memory reads: 0 (0 bytes)
+/// memory writes: 1 (4 bytes)
+/// conditional branches: 3 (2 taken)
+/// CMP  3  27.3%
+/// JNE  3  27.3%
+/// SUB  3  27.3%
+/// HLT  1   9.1%
+/// MOV  1   9.1%
+/// ```
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "instructions executed: {}", self.instructions)?;
+        let per_instruction = match self.instructions {
+            0 => 0.0,
+            instructions => self.cycles as f64 / instructions as f64,
+        };
+        writeln!(
+            f,
+            "cycles: {} ({per_instruction:.2} per instruction)",
+            self.cycles
+        )?;
+        writeln!(
+            f,
+            "memory reads: {} ({} bytes)",
+            self.reads, self.bytes_read
+        )?;
+        writeln!(
+            f,
+            "memory writes: {} ({} bytes)",
+            self.writes, self.bytes_written
+        )?;
+        writeln!(
+            f,
+            "conditional branches: {} ({} taken)",
+            self.branches, self.branches_taken
+        )?;
+        let histogram = self.histogram();
+        let width = histogram
+            .iter()
+            .map(|(mnemonic, _)| mnemonic.len())
+            .max()
+            .unwrap_or_default();
+        let count_width = histogram
+            .first()
+            .map(|(_, count)| count.to_string().len())
+            .unwrap_or_default();
+        for (mnemonic, count) in histogram {
+            let share = count as f64 * 100.0 / self.instructions as f64;
+            writeln!(f, "{mnemonic:width$}  {count:>count_width$} {share:>5.1}%")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{tests::load, StopReason};
+
+    #[test]
+    fn stats() {
+        let source = "mov [0x20], ecx\nl: sub ecx, 1\ncmp ecx, 0xfffffffd\njne l\nhlt";
+        let mut machine = load(source);
+        assert_eq!(machine.stats(), None);
+        machine.collect_stats(true);
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+
+        let stats = machine.stats().unwrap();
+        assert_eq!(stats.instructions, 11);
+        // MOV has a memory operand, JNE is costed as if it is taken, and HLT takes 4 cycles.
+        assert_eq!(stats.cycles, 21);
+        assert_eq!((stats.reads, stats.bytes_read), (0, 0));
+        assert_eq!((stats.writes, stats.bytes_written), (1, 4));
+        assert_eq!((stats.branches, stats.branches_taken), (3, 2));
+        assert_eq!(
+            stats.histogram(),
+            [("CMP", 3), ("JNE", 3), ("SUB", 3), ("HLT", 1), ("MOV", 1)]
+        );
+        assert_eq!(
+            stats.to_string(),
+            "instructions executed: 11\n\
+             cycles: 21 (1.91 per instruction)\n\
+             memory reads: 0 (0 bytes)\n\
+             memory writes: 1 (4 bytes)\n\
+             conditional branches: 3 (2 taken)\n\
+             CMP  3  27.3%\n\
+             JNE  3  27.3%\n\
+             SUB  3  27.3%\n\
+             HLT  1   9.1%\n\
+             MOV  1   9.1%\n"
+        );
+
+        // Enabling the statistics again starts them afresh, and disabling them discards them.
+        machine.collect_stats(true);
+        assert_eq!(machine.stats(), Some(&Stats::default()));
+        machine.collect_stats(false);
+        assert_eq!(machine.stats(), None);
+    }
+}
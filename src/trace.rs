@@ -0,0 +1,449 @@
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use crate::{cpu::Cpu, instruction::Instruction, machine::StepReport};
+#[cfg(feature = "std")]
+use crate::{
+    memory::AccessKind,
+    register::{Register, Registers},
+};
+
+/// Observes the instructions executed by a [`Machine`](crate::machine::Machine), on which it is
+/// installed with [`Machine::set_tracer`](crate::machine::Machine::set_tracer).
+pub trait Tracer {
+    /// Called before the instruction at `address` is executed. The instruction is `None` if there
+    /// is no instruction to execute (see [`StepReport::instruction`]).
+    fn before(&mut self, _address: u32, _instruction: Option<&Instruction>, _cpu: &Cpu) {}
+
+    /// Called after the instruction has been executed, with what it did.
+    fn after(&mut self, _report: &StepReport, _cpu: &Cpu) {}
+}
+
+#[cfg(feature = "std")]
+/// A tracer which writes a line of text for each instruction: its address, the instruction, and
+/// the new values of the registers and memory which it changed. For example:
+///
+/// ```text
+/// 00000002  sub ecx, 1  ECX=0xffffffff EFLAGS=0x00000097 [ CF PF AF SF ]
+/// 00000003  mov [0x20], ecx  [0x00000020]=0xffffffff
+/// 00000004  hlt  ; stopped: Halted
+/// ```
+///
+/// Errors writing to the sink are ignored, as they cannot be reported to the machine.
+pub struct TextTracer<W: Write> {
+    sink: W,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> TextTracer<W> {
+    pub fn new(sink: W) -> Self {
+        Self { sink }
+    }
+
+    /// Removes the tracer's sink.
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Tracer for TextTracer<W> {
+    fn after(&mut self, report: &StepReport, cpu: &Cpu) {
+        let mut changes: Vec<String> = report
+            .registers
+            .iter()
+            .filter_map(|register| {
+                let value = register_value(&cpu.registers, register)?;
+                Some(format!("{register}={value:#x}"))
+            })
+            .collect();
+        if report.eflags_changed {
+            let eflags = &cpu.registers.eflags;
+            changes.push(format!("EFLAGS={:#010x} {eflags}", eflags.get_value()));
+        }
+        changes.extend(
+            report
+                .memory_accesses
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| format!("[{:#010x}]={:#x}", access.address, access.value)),
+        );
+        if let Some(stop_reason) = &report.stop_reason {
+            changes.push(format!("; stopped: {stop_reason:?}"));
+        }
+
+        let instruction = match &report.instruction {
+            Some(instruction) => instruction.to_string(),
+            None => "(none)".into(),
+        };
+        let _ = if changes.is_empty() {
+            writeln!(self.sink, "{:08x}  {instruction}", report.address)
+        } else {
+            writeln!(
+                self.sink,
+                "{:08x}  {instruction}  {}",
+                report.address,
+                changes.join(" ")
+            )
+        };
+    }
+}
+
+#[cfg(feature = "std")]
+/// The formats in which `--trace` can write the executed instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum TraceFormat {
+    /// A human-readable line per instruction, giving its address, machine code and mnemonic, and
+    /// the registers and memory which it changed.
+    #[default]
+    Text,
+    /// Comma-separated values, with a header row.
+    Csv,
+    /// A JSON object per line.
+    JsonLines,
+}
+
+#[cfg(feature = "std")]
+impl TraceFormat {
+    /// A tracer which writes to `sink` in this format.
+    pub fn tracer<W: Write + 'static>(self, sink: W) -> Box<dyn Tracer> {
+        match self {
+            TraceFormat::Text => Box::new(TextTracer::new(sink)),
+            TraceFormat::Csv => Box::new(CsvTracer::new(sink)),
+            TraceFormat::JsonLines => Box::new(JsonLinesTracer::new(sink)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// What an instruction did, as written by the structured tracers, such that external tools can
+/// analyze a run.
+struct Record {
+    address: u32,
+    /// The machine code of the instruction, which is empty if it has none (e.g. when there was no
+    /// instruction to execute).
+    bytes: Vec<u8>,
+    mnemonic: String,
+    instruction: String,
+    /// The names and new values of the registers which were changed, including EFLAGS.
+    registers: Vec<(String, u32)>,
+    /// The address, size in bytes, and value of each write to memory.
+    writes: Vec<(u32, u32, u64)>,
+    stop_reason: Option<String>,
+}
+
+#[cfg(feature = "std")]
+impl Record {
+    fn new(report: &StepReport, cpu: &Cpu) -> Self {
+        let mut registers: Vec<_> = report
+            .registers
+            .iter()
+            .filter_map(|register| {
+                let value = register_value(&cpu.registers, register)?;
+                Some((register.to_string(), value))
+            })
+            .collect();
+        if report.eflags_changed {
+            registers.push(("EFLAGS".into(), cpu.registers.eflags.get_value()));
+        }
+        let instruction = report.instruction.as_ref();
+        Self {
+            address: report.address,
+            bytes: report.bytes.clone(),
+            mnemonic: instruction
+                .map(|instruction| instruction.mnemonic.to_uppercase())
+                .unwrap_or_default(),
+            instruction: instruction.map(ToString::to_string).unwrap_or_default(),
+            registers,
+            writes: report
+                .memory_accesses
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| (access.address, access.size as u32 / 8, access.value))
+                .collect(),
+            stop_reason: report
+                .stop_reason
+                .as_ref()
+                .map(|stop_reason| format!("{stop_reason:?}")),
+        }
+    }
+
+    fn hex_bytes(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+/// A tracer which writes comma-separated values, starting with a header. The changed registers
+/// and the writes to memory are each given as a single field of space-separated `NAME=VALUE`
+/// pairs. For example:
+///
+/// ```text
+/// address,bytes,mnemonic,instruction,registers,writes,stop_reason
+/// 0x00000000,81e901000000,SUB,"sub ecx, 1",ECX=0xffffffff EFLAGS=0x00000097,,
+/// 0x00000001,890d20000000,MOV,"mov [0x20], ecx",,[0x00000020]=0xffffffff,
+/// 0x00000002,f4,HLT,hlt,,,Halted
+/// ```
+///
+/// Errors writing to the sink are ignored, as they are by [`TextTracer`].
+pub struct CsvTracer<W: Write> {
+    sink: W,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> CsvTracer<W> {
+    pub fn new(mut sink: W) -> Self {
+        let _ = writeln!(
+            sink,
+            "address,bytes,mnemonic,instruction,registers,writes,stop_reason"
+        );
+        Self { sink }
+    }
+
+    /// Removes the tracer's sink.
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Tracer for CsvTracer<W> {
+    fn after(&mut self, report: &StepReport, cpu: &Cpu) {
+        let record = Record::new(report, cpu);
+        let registers: Vec<_> = record
+            .registers
+            .iter()
+            .map(|(name, value)| format!("{name}={value:#010x}"))
+            .collect();
+        let writes: Vec<_> = record
+            .writes
+            .iter()
+            .map(|(address, _, value)| format!("[{address:#010x}]={value:#x}"))
+            .collect();
+        let fields = [
+            format!("{:#010x}", record.address),
+            record.hex_bytes(),
+            record.mnemonic.clone(),
+            record.instruction.clone(),
+            registers.join(" "),
+            writes.join(" "),
+            record.stop_reason.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+        let _ = writeln!(self.sink, "{}", fields.join(","));
+    }
+}
+
+#[cfg(feature = "std")]
+/// Quotes a CSV field if it contains a comma, a quote, or a line break, doubling its quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+#[cfg(feature = "std")]
+/// A tracer which writes a JSON object per line, with numbers given as numbers (not strings),
+/// and the machine code as a string of hexadecimal digits. For example:
+///
+/// ```text
+/// {"address":0,"bytes":"81e901000000","mnemonic":"SUB","instruction":"sub ecx, 1","registers":{"ECX":4294967295,"EFLAGS":151},"writes":[],"stop_reason":null}
+/// ```
+///
+/// Errors writing to the sink are ignored, as they are by [`TextTracer`].
+pub struct JsonLinesTracer<W: Write> {
+    sink: W,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(sink: W) -> Self {
+        Self { sink }
+    }
+
+    /// Removes the tracer's sink.
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn after(&mut self, report: &StepReport, cpu: &Cpu) {
+        let record = Record::new(report, cpu);
+        let registers: Vec<_> = record
+            .registers
+            .iter()
+            .map(|(name, value)| format!("{}:{value}", json_string(name)))
+            .collect();
+        let writes: Vec<_> = record
+            .writes
+            .iter()
+            .map(|(address, size, value)| {
+                format!("{{\"address\":{address},\"size\":{size},\"value\":{value}}}")
+            })
+            .collect();
+        let stop_reason = match &record.stop_reason {
+            Some(stop_reason) => json_string(stop_reason),
+            None => "null".into(),
+        };
+        let _ = writeln!(
+            self.sink,
+            "{{\"address\":{},\"bytes\":\"{}\",\"mnemonic\":{},\"instruction\":{},\
+             \"registers\":{{{}}},\"writes\":[{}],\"stop_reason\":{stop_reason}}}",
+            record.address,
+            record.hex_bytes(),
+            json_string(&record.mnemonic),
+            json_string(&record.instruction),
+            registers.join(","),
+            writes.join(","),
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+/// A JSON string literal holding `text`.
+fn json_string(text: &str) -> String {
+    let mut string = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            c if c.is_control() => string.push_str(&format!("\\u{:04x}", c as u32)),
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+#[cfg(feature = "std")]
+/// The value of one of the registers which are reported as changed (see
+/// [`StepReport::registers`]).
+fn register_value(registers: &Registers, register: &Register) -> Option<u32> {
+    match register {
+        Register::Register32(register) => Some(registers.read32(register)),
+        Register::Register16(register) => Some(registers.read16(register) as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::machine::{tests::load, StopReason};
+
+    /// Records the addresses which it is told about, before (`true`) and after (`false`) each
+    /// instruction.
+    #[derive(Clone, Default)]
+    struct Addresses(Rc<RefCell<Vec<(bool, u32)>>>);
+
+    impl Tracer for Addresses {
+        fn before(&mut self, address: u32, instruction: Option<&Instruction>, _: &Cpu) {
+            assert!(instruction.is_some());
+            self.0.borrow_mut().push((true, address));
+        }
+
+        fn after(&mut self, report: &StepReport, _: &Cpu) {
+            self.0.borrow_mut().push((false, report.address));
+        }
+    }
+
+    /// A sink which can still be read once it has been given to the tracer.
+    #[derive(Clone, Default)]
+    struct SharedSink(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedSink {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn callbacks() {
+        let mut machine = load("l: sub ecx, 1\njmp l");
+        let addresses = Addresses::default();
+        machine.set_tracer(Some(Box::new(addresses.clone())));
+        machine.set_instruction_limit(Some(3));
+        assert_eq!(machine.run().unwrap(), StopReason::InstructionLimit);
+        machine.step();
+        assert!(machine.set_tracer(None).is_some());
+        machine.step();
+        assert_eq!(
+            *addresses.0.borrow(),
+            [
+                (true, 0x1000),
+                (false, 0x1000),
+                (true, 0x1006),
+                (false, 0x1006),
+                (true, 0x1000),
+                (false, 0x1000),
+                (true, 0x1006),
+                (false, 0x1006),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_trace() {
+        let mut machine = load("sub ecx, 1\nmov [0x20], ecx\nhlt");
+        let sink = SharedSink::default();
+        machine.set_tracer(Some(TraceFormat::Csv.tracer(sink.clone())));
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(
+            String::from_utf8(sink.0.take()).unwrap(),
+            "address,bytes,mnemonic,instruction,registers,writes,stop_reason\n\
+             0x00001000,81e901000000,SUB,\"sub ecx, 1\",ECX=0xffffffff EFLAGS=0x00000097,,\n\
+             0x00001006,890d20000000,MOV,\"mov [0x20], ecx\",,[0x00000020]=0xffffffff,\n\
+             0x0000100c,f4,HLT,hlt,,,Halted\n"
+        );
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn json_lines_trace() {
+        let mut machine = load("sub ecx, 1\nmov [0x20], ecx\nhlt");
+        let sink = SharedSink::default();
+        machine.set_tracer(Some(TraceFormat::JsonLines.tracer(sink.clone())));
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(
+            String::from_utf8(sink.0.take()).unwrap(),
+            "{\"address\":4096,\"bytes\":\"81e901000000\",\"mnemonic\":\"SUB\",\"instruction\":\"sub ecx, 1\",\
+             \"registers\":{\"ECX\":4294967295,\"EFLAGS\":151},\"writes\":[],\"stop_reason\":null}\n\
+             {\"address\":4102,\"bytes\":\"890d20000000\",\"mnemonic\":\"MOV\",\
+             \"instruction\":\"mov [0x20], ecx\",\"registers\":{},\
+             \"writes\":[{\"address\":32,\"size\":4,\"value\":4294967295}],\"stop_reason\":null}\n\
+             {\"address\":4108,\"bytes\":\"f4\",\"mnemonic\":\"HLT\",\"instruction\":\"hlt\",\
+             \"registers\":{},\"writes\":[],\"stop_reason\":\"Halted\"}\n"
+        );
+        assert_eq!(json_string("a\"b\\\n"), "\"a\\\"b\\\\\\n\"");
+    }
+
+    #[test]
+    fn text_trace() {
+        let mut machine = load("sub ecx, 1\nmov [0x20], ecx\nhlt");
+        let sink = SharedSink::default();
+        machine.set_tracer(Some(Box::new(TextTracer::new(sink.clone()))));
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(
+            String::from_utf8(sink.0.take()).unwrap(),
+            "00001000  sub ecx, 1  ECX=0xffffffff EFLAGS=0x00000097 [ CF PF AF SF ]\n\
+             00001006  mov [0x20], ecx  [0x00000020]=0xffffffff\n\
+             0000100c  hlt  ; stopped: Halted\n"
+        );
+    }
+}
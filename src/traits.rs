@@ -1,6 +1,6 @@
-use std::mem;
+use core::mem;
 
-use num_traits::{PrimInt, Unsigned, FromPrimitive};
+use num_traits::{FromPrimitive, PrimInt, Unsigned};
 
 use crate::register::Registers;
 
@@ -113,7 +113,7 @@ macro_rules! impl_as_unsigned {
                 self
             }
         }
-    }
+    };
 }
 
 impl_as_unsigned!(u8);
@@ -154,7 +154,7 @@ macro_rules! impl_as_signed {
                 self
             }
         }
-    }
+    };
 }
 
 impl_as_signed!(i8);
@@ -186,7 +186,6 @@ mod tests {
         };
     }
 
-
     #[test]
     fn as_signed() {
         test_as_signed!(i8, i8);